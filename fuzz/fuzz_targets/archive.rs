@@ -0,0 +1,16 @@
+//! Sniffing, listing and unpacking gzip and zip files
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate farore;
+
+use farore::archive;
+
+
+fuzz_target!(|data: &[u8]| {
+    archive::sniff(data);
+    let _ = archive::list(data);
+    let _ = archive::unpack(data, None);
+    let _ = archive::unpack(data, Some("game.gb"));
+});
@@ -0,0 +1,20 @@
+//! The cartridge header parser, and the repairs that rewrite it
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate farore;
+
+use std::io;
+
+use farore::cart::{self, GameboyProgramMeta, Repairs};
+
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(meta) = GameboyProgramMeta::new(data) {
+        meta.fields();
+        meta.to_json();
+        meta.print_debug(&mut io::sink());
+    }
+    let _ = cart::repair(&mut data.to_vec(), Repairs::ALL);
+});
@@ -0,0 +1,17 @@
+//! Game Genie and GameShark codes
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate farore;
+
+use std::str;
+
+use farore::cheat::Cheat;
+
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(code) = str::from_utf8(data) {
+        let _ = Cheat::parse(code);
+    }
+});
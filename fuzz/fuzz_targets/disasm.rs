@@ -0,0 +1,17 @@
+//! Disassembling a ROM, with the first byte picking the bank
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate farore;
+
+use farore::disasm::{BankView, Listing};
+
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&bank, rom)) = data.split_first() {
+        let view = BankView::new(rom, bank as usize);
+        Listing::linear(&view, 0x0000..0x8000).to_string();
+        Listing::follow(&view, 0x0100, 1000).to_string();
+    }
+});
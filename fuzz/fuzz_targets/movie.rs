@@ -0,0 +1,13 @@
+//! Input movie files
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate farore;
+
+use farore::movie::InputMovie;
+
+
+fuzz_target!(|data: &[u8]| {
+    let _ = InputMovie::read(&mut &data[..]);
+});
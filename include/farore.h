@@ -0,0 +1,93 @@
+/* The farore C API.  Build the library with:
+ *   cargo rustc --release --features capi --crate-type cdylib
+ * or --crate-type staticlib, and see src/capi.rs for the ownership rules. */
+
+#ifndef FARORE_H
+#define FARORE_H
+
+/* Generated by cbindgen from src/capi.rs, don't edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * `FaroreOptions.model`: go by the cartridge's CGB flag.
+ */
+#define FARORE_MODEL_AUTO 0
+
+/**
+ * `FaroreOptions.model`: the original Game Boy.
+ */
+#define FARORE_MODEL_DMG 1
+
+/**
+ * `FaroreOptions.model`: the Game Boy Color.
+ */
+#define FARORE_MODEL_CGB 2
+
+/**
+ * `FaroreOptions.palette`: plain grays.
+ */
+#define FARORE_PALETTE_GRAYSCALE 0
+
+/**
+ * `FaroreOptions.palette`: the original model's green.
+ */
+#define FARORE_PALETTE_DMG_GREEN 1
+
+/**
+ * `FaroreOptions.palette`: the Game Boy Pocket's gray-green.
+ */
+#define FARORE_PALETTE_POCKET 2
+
+/**
+ * A machine, opaque to C.
+ */
+typedef struct FaroreMachine FaroreMachine;
+
+/**
+ * How a machine is set up.  Passing null for the options is the same as passing all zeros.
+ */
+typedef struct FaroreOptions {
+  /**
+   * One of the `FARORE_MODEL_` constants.
+   */
+  uint32_t model;
+  /**
+   * One of the `FARORE_PALETTE_` constants, for DMG games.
+   */
+  uint32_t palette;
+} FaroreOptions;
+
+/**
+ * Creates a machine running the `rom_len` bytes at `rom`.  Returns null on failure, with the
+ * reason in `farore_last_error(NULL)`.
+ *
+ * # Safety
+ *
+ * `rom` must point to `rom_len` readable bytes, and `options` must be null or point to a
+ * `FaroreOptions`.
+ */
+FaroreMachine *farore_machine_new(const uint8_t *rom, size_t rom_len, const FaroreOptions *options);
+
+/**
+ * Frees a machine.  Null is ignored.
+ *
+ * # Safety
+ *
+ * `machine` must be null or come from `farore_machine_new`, and not have been freed.
+ */
+void farore_machine_free(FaroreMachine *machine);
+
+/**
+ * Why the last call on `machine` failed, or with null, why the last `farore_machine_new` on
+ * this thread did.  Null when it didn't fail.
+ *
+ * # Safety
+ *
+ * `machine` must be null or a machine that hasn't been freed.
+ */
+const char *farore_last_error(const FaroreMachine *machine);
+
+#endif /* FARORE_H */
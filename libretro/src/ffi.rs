@@ -0,0 +1,111 @@
+//! The libretro entry points
+//!
+//! Only moves values between the frontend and the functions in the crate root.  The structs
+//! mirror libretro.h, which is the authority on their layout.
+
+use std::os::raw::{c_char, c_uint};
+
+use farore::ppu::{DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+
+/// The libretro API version this core is written against, `RETRO_API_VERSION`.
+pub const API_VERSION: c_uint = 1;
+
+/// The rate audio is resampled to before it's handed over.
+pub const SAMPLE_RATE: f64 = 48_000.0;
+
+// T-cycles per second, whatever the speed mode: double speed runs twice the cycles per frame.
+const CLOCK_RATE: f64 = 4_194_304.0;
+
+/// `struct retro_system_info`
+#[repr(C)]
+#[derive(Debug)]
+pub struct SystemInfo {
+    /// The core's name.
+    pub library_name: *const c_char,
+    /// Its version.
+    pub library_version: *const c_char,
+    /// The ROM extensions it loads, separated by `|`.
+    pub valid_extensions: *const c_char,
+    /// Whether the core wants a path rather than the ROM's data.
+    pub need_fullpath: bool,
+    /// Whether the frontend should leave archives alone.
+    pub block_extract: bool,
+}
+
+/// `struct retro_game_geometry`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct GameGeometry {
+    /// The screen's width in pixels.
+    pub base_width: c_uint,
+    /// Its height.
+    pub base_height: c_uint,
+    /// The largest width the core will ever use.
+    pub max_width: c_uint,
+    /// The largest height.
+    pub max_height: c_uint,
+    /// The display's aspect ratio, or 0 for square pixels.
+    pub aspect_ratio: f32,
+}
+
+/// `struct retro_system_timing`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SystemTiming {
+    /// Frames per second.
+    pub fps: f64,
+    /// Audio samples per second.
+    pub sample_rate: f64,
+}
+
+/// `struct retro_system_av_info`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SystemAvInfo {
+    /// The screen.
+    pub geometry: GameGeometry,
+    /// The frame and sample rates.
+    pub timing: SystemTiming,
+}
+
+/// `retro_api_version`
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+/// `retro_get_system_info`
+///
+/// # Safety
+///
+/// `info` must be null or point to a `SystemInfo` the core may write.  The strings it's given
+/// are static.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    if let Some(info) = info.as_mut() {
+        *info = SystemInfo {
+            library_name: b"farore\0".as_ptr() as *const c_char,
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: b"gb|gbc|dmg|cgb\0".as_ptr() as *const c_char,
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+/// `retro_get_system_av_info`
+///
+/// # Safety
+///
+/// `info` must be null or point to a `SystemAvInfo` the core may write.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    if let Some(info) = info.as_mut() {
+        let (width, height) = (SCREEN_WIDTH as c_uint, SCREEN_HEIGHT as c_uint);
+        *info = SystemAvInfo {
+            geometry: GameGeometry { base_width: width, base_height: height, max_width: width, max_height: height, aspect_ratio: 0.0 },
+            timing: SystemTiming { fps: CLOCK_RATE / DOTS_PER_FRAME as f64, sample_rate: SAMPLE_RATE },
+        };
+    }
+}
@@ -0,0 +1,154 @@
+//! Farore as a libretro core.
+//!
+//! The frontend hands over the ROM, calls `retro_run` once a frame and takes video, audio and
+//! input through callbacks.  This crate keeps the glue thin: the conversions between farore's
+//! types and libretro's are plain functions here, tested without a frontend, and the `ffi`
+//! module only moves values across the boundary.
+//!
+//! There is no CPU yet, so only the entry points that don't need a machine are exported.
+
+#![deny(missing_docs)]
+
+extern crate farore;
+
+use farore::cart::GameboyProgramMeta;
+use farore::error::FaroreError;
+use farore::joypad::{Button, InputState};
+use farore::model::HardwareModel;
+use farore::palette::DisplayPalette;
+use farore::savestate::{self, SaveState};
+
+pub mod ffi;
+
+
+/// Which `RETRO_DEVICE_ID_JOYPAD` button each Game Boy button is read from.  The retropad's B
+/// and A are the bottom and right face buttons, where the Game Boy's sit.
+pub const JOYPAD_MAP: [(u32, Button); 8] = [
+    (0, Button::B),
+    (2, Button::Select),
+    (3, Button::Start),
+    (4, Button::Up),
+    (5, Button::Down),
+    (6, Button::Left),
+    (7, Button::Right),
+    (8, Button::A),
+];
+
+/// The buffer `retro_serialize` is given.  libretro wants the same size for every state of a
+/// game, and a whole CGB machine with 128KiB of cartridge RAM compresses to well under this
+/// even when the RAM is noise.
+pub const SERIALIZE_SIZE: usize = 384 * 1024;
+
+/// Polls the buttons through `pressed`, which answers for a `RETRO_DEVICE_ID_JOYPAD` id on
+/// port 0.
+pub fn joypad<F: FnMut(u32) -> bool>(mut pressed: F) -> InputState {
+    let mut state = InputState::new();
+    for &(id, button) in &JOYPAD_MAP {
+        state.set(button, pressed(id));
+    }
+    state
+}
+
+/// Converts RGBA pixels, as `Frame::write_rgba` writes them, into `RETRO_PIXEL_FORMAT_RGB565`.
+/// Fails with `InvalidArgument` unless `out` has a pixel for every 4 bytes of `rgba`.
+pub fn rgba_to_rgb565(rgba: &[u8], out: &mut [u16]) -> Result<(), FaroreError> {
+    if rgba.len() != out.len() * 4 {
+        return Err(FaroreError::InvalidArgument(format!(
+            "{} bytes of RGBA don't fill {} pixels", rgba.len(), out.len())));
+    }
+    for (pixel, rgba) in out.iter_mut().zip(rgba.chunks_exact(4)) {
+        let (r, g, b) = (rgba[0] as u16, rgba[1] as u16, rgba[2] as u16);
+        *pixel = (r >> 3) << 11 | (g >> 2) << 5 | b >> 3;
+    }
+    Ok(())
+}
+
+/// Appends stereo samples to `out` as the interleaved 16 bit frames `retro_audio_sample_batch`
+/// takes, clamping each to -1.0 to 1.0.
+pub fn samples_to_pcm(samples: &[(f32, f32)], out: &mut Vec<i16>) {
+    let pcm = |sample: f32| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+    for &(left, right) in samples {
+        out.push(pcm(left));
+        out.push(pcm(right));
+    }
+}
+
+/// How much of the cartridge RAM `RETRO_MEMORY_SAVE_RAM` exposes for the frontend's .srm file:
+/// all of it on a cartridge with a battery, none without one, as nothing would have kept it.
+pub fn save_ram_size(cart: &GameboyProgramMeta) -> usize {
+    if cart.cart_type().has_battery() {
+        cart.declared_ram_size().bytes()
+    } else {
+        0
+    }
+}
+
+/// Saves `state` into the start of `out` and zeroes the rest, so every state fills the same
+/// `SERIALIZE_SIZE` buffer.  Fails with `InvalidArgument` if the state doesn't fit.
+pub fn pad_state(state: &SaveState, out: &mut [u8]) -> Result<(), FaroreError> {
+    let mut file = Vec::new();
+    state.write(&mut file).map_err(|e| FaroreError::InvalidArgument(e.to_string()))?;
+    if file.len() > out.len() {
+        return Err(FaroreError::InvalidArgument(format!(
+            "the save state is {} bytes, the buffer is {}", file.len(), out.len())));
+    }
+    out[..file.len()].copy_from_slice(&file);
+    for byte in &mut out[file.len()..] {
+        *byte = 0;
+    }
+    Ok(())
+}
+
+/// Reads back a state saved by `pad_state`, ignoring the padding.
+pub fn unpad_state(data: &[u8]) -> Result<SaveState, FaroreError> {
+    match savestate::stored_size(data) {
+        Some(size) if size <= data.len() => SaveState::parse(&data[..size]),
+        _ => SaveState::parse(data),
+    }
+}
+
+/// The core options, as `RETRO_ENVIRONMENT_SET_VARIABLES` takes them: the key, then the
+/// description and the values, the first being the default.
+pub const OPTIONS: [(&str, &str); 2] = [
+    ("farore_model", "Model; auto|dmg|cgb"),
+    ("farore_palette", "DMG palette; grayscale|dmg-green|pocket"),
+];
+
+/// The core options' current values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CoreOptions {
+    /// The model to emulate, or `None` to go by the cartridge.
+    pub model: Option<HardwareModel>,
+    /// What DMG shades are shown as.
+    pub palette: DisplayPalette,
+}
+
+impl CoreOptions {
+    /// Sets an option from the value the frontend reports for `key`.  Fails with
+    /// `InvalidArgument` on a key or value that isn't in `OPTIONS`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), FaroreError> {
+        let invalid = || FaroreError::InvalidArgument(format!("{} isn't a value of {}", value, key));
+        match key {
+            "farore_model" => {
+                self.model = match value {
+                    "auto" => None,
+                    "dmg" => Some(HardwareModel::Dmg),
+                    "cgb" => Some(HardwareModel::Cgb),
+                    _ => return Err(invalid()),
+                };
+            },
+            "farore_palette" => self.palette = DisplayPalette::named(value).ok_or_else(invalid)?,
+            _ => return Err(FaroreError::InvalidArgument(format!("unknown core option {}", key))),
+        }
+        Ok(())
+    }
+
+    /// The model to run `cart` on: the chosen one, or a CGB for games that support it.
+    pub fn model_for(&self, cart: &GameboyProgramMeta) -> HardwareModel {
+        match self.model {
+            Some(model) => model,
+            None if cart.supports_cgb() => HardwareModel::Cgb,
+            None => HardwareModel::Dmg,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The conversions between farore and libretro, checked without a frontend.
+
+extern crate farore;
+extern crate farore_libretro;
+
+use farore::cart::{self, GameboyProgramMeta, Repairs};
+use farore::error::FaroreError;
+use farore::joypad::{Button, InputState};
+use farore::model::HardwareModel;
+use farore::palette::DisplayPalette;
+use farore::savestate::{self, SaveState};
+use farore_libretro::{CoreOptions, OPTIONS, SERIALIZE_SIZE};
+
+
+fn rom(cart_type: u8, ram_size: u8, cgb_flag: u8) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x013B].copy_from_slice(b"RETRO\0\0");
+    rom[0x0143] = cgb_flag;
+    rom[0x0147] = cart_type;
+    rom[0x0149] = ram_size;
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+fn reason<T: std::fmt::Debug>(result: Result<T, FaroreError>) -> String {
+    match result {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn pixels_are_packed_into_rgb565() {
+    let rgba = [
+        0xFF, 0xFF, 0xFF, 0xFF,
+        0x00, 0x00, 0x00, 0xFF,
+        0xFF, 0x00, 0x00, 0xFF,
+        0x00, 0xFF, 0x00, 0xFF,
+        0x00, 0x00, 0xFF, 0xFF,
+        0x9B, 0xBC, 0x0F, 0x00, // Alpha is dropped
+    ];
+    let mut out = [0u16; 6];
+    farore_libretro::rgba_to_rgb565(&rgba, &mut out).unwrap();
+    assert_eq!(out, [0xFFFF, 0x0000, 0xF800, 0x07E0, 0x001F, 0x9DE1]);
+
+    assert_eq!(reason(farore_libretro::rgba_to_rgb565(&rgba, &mut [0; 5])), "24 bytes of RGBA don't fill 5 pixels");
+}
+
+#[test]
+fn buttons_are_read_from_the_retropad() {
+    assert_eq!(farore_libretro::joypad(|_| false), InputState::new());
+    let mut all = InputState::new();
+    for &button in &Button::ALL {
+        all.set(button, true);
+    }
+    assert_eq!(farore_libretro::joypad(|_| true), all);
+
+    for &(id, button) in &farore_libretro::JOYPAD_MAP {
+        let state = farore_libretro::joypad(|polled| polled == id);
+        assert!(state.is_pressed(button), "{:?}", button);
+        assert_eq!(state.bits().count_ones(), 1, "{:?}", button);
+    }
+    // Y and X, ids 1 and 9, aren't on a Game Boy
+    assert_eq!(farore_libretro::joypad(|id| id == 1 || id == 9), InputState::new());
+}
+
+#[test]
+fn samples_become_interleaved_pcm() {
+    let mut out = vec![7];
+    farore_libretro::samples_to_pcm(&[(0.0, 1.0), (-1.0, 0.5), (2.0, -3.0)], &mut out);
+    assert_eq!(out, [7, 0, 32767, -32767, 16383, 32767, -32767]);
+}
+
+#[test]
+fn states_fill_a_buffer_of_one_size() {
+    let mut state = SaveState::new(0x1234_5678, "RETRO", HardwareModel::Cgb, 600);
+    state.set_section(savestate::MAPPER, (0..128 * 1024).map(|i: u32| i.wrapping_mul(2_654_435_761) as u8).collect());
+    state.set_section(savestate::CPU, vec![1; 12]);
+    let mut buffer = vec![0xAA; SERIALIZE_SIZE];
+    farore_libretro::pad_state(&state, &mut buffer).unwrap();
+    assert_eq!(buffer[SERIALIZE_SIZE - 1], 0);
+    assert_eq!(farore_libretro::unpad_state(&buffer).unwrap(), state);
+
+    // A smaller state later on still fills the whole buffer
+    let small = SaveState::new(0x1234_5678, "RETRO", HardwareModel::Cgb, 601);
+    farore_libretro::pad_state(&small, &mut buffer).unwrap();
+    assert!(buffer[0x2C..].iter().skip(64).all(|&byte| byte == 0));
+    assert_eq!(farore_libretro::unpad_state(&buffer).unwrap(), small);
+
+    assert!(reason(farore_libretro::pad_state(&state, &mut [0; 64])).starts_with("the save state is "));
+    assert_eq!(reason(farore_libretro::unpad_state(&[0; 64])), "invalid save state: not a save state");
+}
+
+#[test]
+fn save_ram_needs_a_battery() {
+    for &(cart_type, ram_size, expected) in &[
+        (0x00, 0x00, 0),         // ROM ONLY
+        (0x02, 0x02, 0),         // MBC1+RAM, lost at power off
+        (0x03, 0x02, 8 * 1024),  // MBC1+RAM+BATTERY
+        (0x03, 0x03, 32 * 1024),
+        (0x06, 0x00, 512),       // MBC2+BATTERY
+        (0x10, 0x04, 128 * 1024), // MBC3+TIMER+RAM+BATTERY
+    ] {
+        let rom = rom(cart_type, ram_size, 0x00);
+        let cart = GameboyProgramMeta::new(&rom).unwrap();
+        assert_eq!(farore_libretro::save_ram_size(&cart), expected, "type {:02X}", cart_type);
+    }
+}
+
+#[test]
+fn options_pick_the_model_and_palette() {
+    let (dmg, cgb) = (rom(0x00, 0x00, 0x00), rom(0x00, 0x00, 0x80));
+    let (dmg, cgb) = (GameboyProgramMeta::new(&dmg).unwrap(), GameboyProgramMeta::new(&cgb).unwrap());
+    let mut options = CoreOptions::default();
+    assert_eq!(options.model_for(&dmg), HardwareModel::Dmg);
+    assert_eq!(options.model_for(&cgb), HardwareModel::Cgb);
+    options.set("farore_model", "dmg").unwrap();
+    assert_eq!(options.model_for(&cgb), HardwareModel::Dmg);
+    options.set("farore_model", "cgb").unwrap();
+    assert_eq!(options.model_for(&dmg), HardwareModel::Cgb);
+
+    assert_eq!(options.palette, DisplayPalette::GRAYSCALE);
+    options.set("farore_palette", "pocket").unwrap();
+    assert_eq!(options.palette, DisplayPalette::POCKET);
+
+    assert_eq!(reason(options.set("farore_model", "sgb")), "sgb isn't a value of farore_model");
+    assert_eq!(reason(options.set("farore_speed", "2")), "unknown core option farore_speed");
+}
+
+#[test]
+fn every_listed_value_is_accepted() {
+    for &(key, description) in &OPTIONS {
+        let values = description.split("; ").nth(1).unwrap();
+        let mut options = CoreOptions::default();
+        for value in values.split('|') {
+            options.set(key, value).unwrap_or_else(|e| panic!("{} {}: {}", key, value, e));
+        }
+        // The first value is the default
+        let mut first = CoreOptions::default();
+        first.set(key, values.split('|').next().unwrap()).unwrap();
+        assert_eq!(first, CoreOptions::default(), "{}", key);
+    }
+}
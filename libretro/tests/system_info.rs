@@ -0,0 +1,46 @@
+//! The entry points a frontend calls before loading a game, called through their C signatures.
+
+extern crate farore_libretro;
+
+use std::ffi::CStr;
+use std::ptr;
+
+use farore_libretro::ffi::{self, SystemAvInfo, SystemInfo};
+
+
+#[test]
+fn the_core_describes_itself() {
+    assert_eq!(ffi::retro_api_version(), 1);
+
+    let mut info = SystemInfo {
+        library_name: ptr::null(),
+        library_version: ptr::null(),
+        valid_extensions: ptr::null(),
+        need_fullpath: true,
+        block_extract: true,
+    };
+    let text = |s| unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+    unsafe { ffi::retro_get_system_info(&mut info) };
+    assert_eq!(text(info.library_name), "farore");
+    assert_eq!(text(info.library_version), "0.1.0");
+    assert_eq!(text(info.valid_extensions), "gb|gbc|dmg|cgb");
+    assert!(!info.need_fullpath && !info.block_extract);
+}
+
+#[test]
+fn timing_follows_the_hardware() {
+    let mut info = SystemAvInfo::default();
+    unsafe { ffi::retro_get_system_av_info(&mut info) };
+    assert_eq!((info.geometry.base_width, info.geometry.base_height), (160, 144));
+    assert_eq!((info.geometry.max_width, info.geometry.max_height), (160, 144));
+    assert!((info.timing.fps - 59.7275).abs() < 0.0001, "{}", info.timing.fps);
+    assert_eq!(info.timing.sample_rate, 48_000.0);
+}
+
+#[test]
+fn null_pointers_are_ignored() {
+    unsafe {
+        ffi::retro_get_system_info(ptr::null_mut());
+        ffi::retro_get_system_av_info(ptr::null_mut());
+    }
+}
@@ -0,0 +1,82 @@
+//! Volume envelope, shared by the pulse and noise channels
+
+/// NRx2 - Volume envelope
+///   Bit 7-4   Initial volume
+///   Bit 3     Direction, 1 = louder
+///   Bit 2-0   Period in 64Hz steps, 0 = off
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope::default()
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// The channel's DAC is powered by the upper 5 bits of NRx2.  With all of them clear the
+    /// channel is switched off.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// "Zombie mode": on DMG, writing NRx2 while the channel plays nudges the current volume
+    /// instead of leaving it alone.  This covers the commonly agreed behaviour, which is what
+    /// games like Prehistorik Man rely on:
+    ///   - an old period of 0 with the direction unchanged adds 1
+    ///   - changing direction sets the volume to 16 - volume
+    ///
+    /// The result wraps to 4 bits.  Call before `write`.
+    pub fn zombie_write(&mut self, value: u8) {
+        let direction_changed = (self.register ^ value) & 0x08 != 0;
+        let mut volume = self.volume;
+        if self.period() == 0 && !direction_changed {
+            volume += 1;
+        }
+        if direction_changed {
+            volume = 16 - volume;
+        }
+        self.volume = volume & 0x0F;
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn clock(&mut self) {
+        let period = self.period();
+        if period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = period;
+            if self.register & 0x08 != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if self.register & 0x08 == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+}
@@ -0,0 +1,76 @@
+//! Length counter
+//!
+//! Counts down at 256Hz while enabled and switches its channel off when it reaches zero.  The
+//! pulse and noise channels count from 64, the wave channel from 256.
+//!
+//! The frame sequencer clocks length on every other step.  In the first half of a length
+//! period (right after a length clock) enabling the counter or triggering the channel clocks
+//! it once extra.
+
+
+#[derive(Debug, Copy, Clone)]
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+    first_half: bool, // The frame sequencer's next step doesn't clock length
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> Self {
+        LengthCounter { max, counter: 0, enabled: false, first_half: false }
+    }
+
+    /// Loads the counter from the length bits of NRx1, which count up towards the maximum.
+    pub fn load(&mut self, length: u16) {
+        self.counter = self.max - length;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set by the frame sequencer after each step.
+    pub fn set_first_half(&mut self, first_half: bool) {
+        self.first_half = first_half;
+    }
+
+    /// Returns true when the extra clock from enabling the counter makes it run out.
+    pub fn set_enabled(&mut self, enabled: bool) -> bool {
+        let extra_clock = !self.enabled && enabled && self.first_half;
+        self.enabled = enabled;
+        if extra_clock && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    /// A trigger reloads an expired counter with the maximum length, less the extra clock.
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && self.first_half {
+                self.counter -= 1;
+            }
+        }
+    }
+
+    /// Powering the APU down disables the counter.  DMG keeps the count, CGB clears it.
+    pub fn power_off(&mut self, keep_counter: bool) {
+        self.enabled = false;
+        self.first_half = false;
+        if !keep_counter {
+            self.counter = 0;
+        }
+    }
+
+    /// Returns true when the counter runs out, meaning the channel should be switched off.
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+}
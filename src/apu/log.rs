@@ -0,0 +1,120 @@
+//! APU register write log, for ripping music
+
+use std::io::{self, Write};
+
+
+const CLOCK_RATE: u64 = 4_194_304;
+
+// VGM timestamps count samples at 44.1kHz
+const VGM_RATE: u64 = 44_100;
+const VGM_HEADER_SIZE: usize = 0x100;
+
+/// The formats a write log can be exported as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriteLogFormat {
+    /// "cycle,register,value" rows, with the register as a hex address.
+    Csv,
+    /// VGM 1.61, which has Game Boy DMG commands most players understand.
+    Vgm,
+}
+
+/// A single register write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoggedWrite {
+    /// T-cycles since the APU was created.
+    pub cycle: u64,
+    /// The register, FF10-FF3F.
+    pub address: u16,
+    /// The value written.
+    pub value: u8,
+}
+
+/// Every write to FF10-FF3F since logging was enabled.  It starts with the register state at
+/// that moment, so playing it back from an empty APU recreates the sound.
+pub struct WriteLog {
+    /// The writes in the order they happened.
+    pub writes: Vec<LoggedWrite>,
+}
+
+impl WriteLog {
+    /// Starts a log from the last values written to FF10-FF3F.  NR52 goes first so the
+    /// APU is powered before the rest arrives, and trigger bits are left out.
+    pub fn new(cycle: u64, registers: &[u8; 0x30]) -> Self {
+        let mut writes = vec![LoggedWrite { cycle, address: 0xFF26, value: registers[0x16] }];
+        for (offset, &value) in registers.iter().enumerate() {
+            let address = 0xFF10 + offset as u16;
+            let value = match address {
+                0xFF26 => continue,
+                0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => value & 0x7F,
+                _ => value,
+            };
+            writes.push(LoggedWrite { cycle, address, value });
+        }
+        WriteLog { writes }
+    }
+
+    /// Records a write.
+    pub fn push(&mut self, cycle: u64, address: u16, value: u8) {
+        self.writes.push(LoggedWrite { cycle, address, value });
+    }
+
+    /// Writes the log out in the given format.
+    pub fn export(&self, format: WriteLogFormat, writer: &mut dyn Write) -> io::Result<()> {
+        match format {
+            WriteLogFormat::Csv => self.export_csv(writer),
+            WriteLogFormat::Vgm => self.export_vgm(writer),
+        }
+    }
+
+    fn export_csv(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(writer, "cycle,register,value")?;
+        for write in &self.writes {
+            writeln!(writer, "{},{:04X},{:02X}", write.cycle, write.address, write.value)?;
+        }
+        Ok(())
+    }
+
+    fn export_vgm(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let start = self.writes.first().map_or(0, |write| write.cycle);
+        let mut data = Vec::new();
+        let mut samples = 0u64;
+        for write in &self.writes {
+            let target = (write.cycle - start) * VGM_RATE / CLOCK_RATE;
+            push_vgm_wait(&mut data, target - samples);
+            samples = target;
+            data.extend_from_slice(&[0xB3, (write.address - 0xFF10) as u8, write.value]);
+        }
+        data.push(0x66); // End of sound data
+
+        let mut header = [0u8; VGM_HEADER_SIZE];
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+        let eof_offset = (VGM_HEADER_SIZE + data.len() - 0x04) as u32;
+        header[0x04..0x08].copy_from_slice(&eof_offset.to_le_bytes());
+        header[0x08..0x0C].copy_from_slice(&0x161u32.to_le_bytes());
+        header[0x18..0x1C].copy_from_slice(&(samples as u32).to_le_bytes());
+        let data_offset = (VGM_HEADER_SIZE - 0x34) as u32;
+        header[0x34..0x38].copy_from_slice(&data_offset.to_le_bytes());
+        header[0x80..0x84].copy_from_slice(&(CLOCK_RATE as u32).to_le_bytes());
+
+        writer.write_all(&header)?;
+        writer.write_all(&data)
+    }
+}
+
+fn push_vgm_wait(data: &mut Vec<u8>, samples: u64) {
+    let mut samples = samples;
+    while samples > 0 {
+        match samples {
+            1..=16 => {
+                data.push(0x70 + (samples - 1) as u8);
+                samples = 0;
+            },
+            _ => {
+                let wait = samples.min(0xFFFF);
+                data.push(0x61);
+                data.extend_from_slice(&(wait as u16).to_le_bytes());
+                samples -= wait;
+            },
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Mixing and resampling
+//!
+//! Every M-cycle each channel's 4 bit DAC input is converted to an analog level, panned by
+//! NR51 and scaled by the NR50 master volume.  The ~1MHz stereo stream is then averaged down
+//! to the rate the consumer asked for, and optionally run through a high-pass filter.
+
+use model::HardwareModel;
+
+
+/// The rate the mixer produces samples at, one per M-cycle.
+pub const NATIVE_RATE: u32 = 4_194_304 / 4;
+
+/// Samples are handed to the callback in chunks of this many frames.
+pub const CHUNK_FRAMES: usize = 512;
+
+/// Called with each chunk of stereo samples.
+pub type SampleCallback = Box<dyn FnMut(&[(f32, f32)])>;
+
+/// The four sound channels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Channel {
+    /// Pulse channel 1, the one with the sweep.
+    Pulse1,
+    /// Pulse channel 2.
+    Pulse2,
+    /// Wave channel 3.
+    Wave,
+    /// Noise channel 4.
+    Noise,
+}
+
+impl Channel {
+    fn bit(self) -> u8 {
+        match self {
+            Channel::Pulse1 => 0x1,
+            Channel::Pulse2 => 0x2,
+            Channel::Wave => 0x4,
+            Channel::Noise => 0x8,
+        }
+    }
+}
+
+/// Which channels reach the mixer.  Muting is purely an output thing, the channels keep
+/// running and the game sees no difference.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChannelMask(u8);
+
+impl ChannelMask {
+    /// Every channel enabled.
+    pub fn all() -> Self {
+        ChannelMask(0xF)
+    }
+
+    /// Only one channel enabled.
+    pub fn solo(channel: Channel) -> Self {
+        ChannelMask(channel.bit())
+    }
+
+    /// Parses channel numbers 1-4 separated by commas, like "1,4".
+    pub fn parse_channels(s: &str) -> Result<Self, String> {
+        let mut mask = ChannelMask(0);
+        for part in s.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+            match part {
+                "1" => mask.0 |= 0x1,
+                "2" => mask.0 |= 0x2,
+                "3" => mask.0 |= 0x4,
+                "4" => mask.0 |= 0x8,
+                _ => return Err(format!("\"{}\" is not a channel number (1-4)", part)),
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Whether a channel reaches the mixer.
+    pub fn is_enabled(&self, channel: Channel) -> bool {
+        self.0 & channel.bit() != 0
+    }
+
+    /// Enables or disables a channel.
+    pub fn set(&mut self, channel: Channel, enabled: bool) {
+        if enabled {
+            self.0 |= channel.bit();
+        } else {
+            self.0 &= !channel.bit();
+        }
+    }
+
+    /// Flips whether a channel is enabled.
+    pub fn toggle(&mut self, channel: Channel) {
+        self.0 ^= channel.bit();
+    }
+
+    /// The mask with the selected channels switched off, for turning a list of channels
+    /// to mute into a mask.
+    pub fn inverted(self) -> Self {
+        ChannelMask(!self.0 & 0xF)
+    }
+}
+
+impl Default for ChannelMask {
+    fn default() -> Self {
+        ChannelMask::all()
+    }
+}
+
+/// Converts a DAC input to an analog level.  0-15 map linearly onto 1.0 to -1.0, the way the
+/// hardware inverts it.  A channel whose DAC is off contributes nothing.
+pub fn dac_output(dac_enabled: bool, input: u8) -> f32 {
+    if !dac_enabled {
+        return 0.0;
+    }
+    1.0 - input as f32 / 7.5
+}
+
+/// Mixes four channel levels into a stereo frame in the range -1.0 to 1.0.  Everything that
+/// produces audio goes through here, so sample output and any visualization agree.
+///   NR50   Bit 7 Vin to the left, bit 6-4 left volume, bit 3 Vin to the right, bit 2-0
+///          right volume.  Volumes 0-7 scale by 1/8 to 8/8, so 0 is quiet but not silent.
+///          No cartridge drives Vin, so it mixes in as silence.
+///   NR51   Bit 7-4 channel 4-1 to the left, bit 3-0 channel 4-1 to the right
+pub fn mix(levels: [f32; 4], nr50: u8, nr51: u8) -> (f32, f32) {
+    let (mut left, mut right) = (0.0, 0.0);
+    for (channel, level) in levels.iter().enumerate() {
+        if nr51 & (0x10 << channel) != 0 {
+            left += level;
+        }
+        if nr51 & (0x01 << channel) != 0 {
+            right += level;
+        }
+    }
+    let left_volume = (((nr50 >> 4) & 0x7) + 1) as f32 / 8.0;
+    let right_volume = ((nr50 & 0x7) + 1) as f32 / 8.0;
+    (left / 4.0 * left_volume, right / 4.0 * right_volume)
+}
+
+/// The DC blocking capacitor on the hardware's output.  Every DAC that's switched on adds an
+/// offset, which would otherwise pop whenever one turns on or off.  Per T-cycle the capacitor
+/// keeps 0.999958 of its charge on DMG and 0.998943 on CGB.
+pub(crate) struct HighPass {
+    charge_factor: f32,
+    capacitor: (f32, f32),
+}
+
+impl HighPass {
+    pub fn new(model: HardwareModel, rate: u32) -> Self {
+        let per_cycle: f64 = if model.is_cgb() { 0.998943 } else { 0.999958 };
+        let cycles_per_sample = 4_194_304.0 / rate as f64;
+        HighPass {
+            charge_factor: per_cycle.powf(cycles_per_sample) as f32,
+            capacitor: (0.0, 0.0),
+        }
+    }
+
+    pub fn apply(&mut self, frame: (f32, f32)) -> (f32, f32) {
+        let left = frame.0 - self.capacitor.0;
+        let right = frame.1 - self.capacitor.1;
+        self.capacitor.0 = frame.0 - left * self.charge_factor;
+        self.capacitor.1 = frame.1 - right * self.charge_factor;
+        (left, right)
+    }
+}
+
+/// Averages native rate frames down to an output rate and buffers them into chunks.
+pub(crate) struct SampleOutput {
+    rate: u32,
+    callback: SampleCallback,
+    high_pass: Option<HighPass>,
+    phase: u32,
+    sum: (f32, f32),
+    count: u32,
+    buffer: Vec<(f32, f32)>,
+}
+
+impl SampleOutput {
+    pub fn new(rate: u32, callback: SampleCallback, high_pass: Option<HighPass>) -> Self {
+        SampleOutput {
+            rate: rate.clamp(1, NATIVE_RATE),
+            callback,
+            high_pass,
+            phase: 0,
+            sum: (0.0, 0.0),
+            count: 0,
+            buffer: Vec::with_capacity(CHUNK_FRAMES),
+        }
+    }
+
+    pub fn push(&mut self, frame: (f32, f32)) {
+        self.sum.0 += frame.0;
+        self.sum.1 += frame.1;
+        self.count += 1;
+
+        self.phase += self.rate;
+        if self.phase < NATIVE_RATE {
+            return;
+        }
+        self.phase -= NATIVE_RATE;
+
+        let count = self.count as f32;
+        let mut frame = (self.sum.0 / count, self.sum.1 / count);
+        if let Some(filter) = self.high_pass.as_mut() {
+            frame = filter.apply(frame);
+        }
+        self.buffer.push(frame);
+        self.sum = (0.0, 0.0);
+        self.count = 0;
+        if self.buffer.len() == CHUNK_FRAMES {
+            self.flush();
+        }
+    }
+
+    /// Hands over whatever is buffered, even if it's less than a chunk.
+    pub fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            (self.callback)(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Audio processing unit
+//!
+//! Each channel is ticked in T-cycles (4194304Hz) and produces a 4 bit DAC input.  The length,
+//! sweep and envelope units are clocked separately by the frame sequencer.
+
+mod envelope;
+mod length;
+pub mod log;
+pub mod mixer;
+mod noise;
+mod pulse;
+mod sequencer;
+mod wave;
+
+use std::io::{self as stdio, Write};
+
+use io::IoPeripheral;
+use model::HardwareModel;
+use timer::DividerListener;
+use self::log::{WriteLog, WriteLogFormat};
+use self::mixer::{Channel, ChannelMask, HighPass, SampleCallback, SampleOutput};
+use self::noise::Noise;
+use self::pulse::Pulse;
+use self::sequencer::FrameSequencer;
+use self::wave::Wave;
+
+
+/// Audio output settings that aren't visible to the game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AudioConfig {
+    /// Remove the DC offset like the hardware's output capacitor.
+    pub high_pass: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { high_pass: true }
+    }
+}
+
+/// Sound Registers.  Unused bits and write only registers read back as 1.
+///   FF10-FF14   NR10-NR14 - Pulse channel 1
+///   FF16-FF19   NR21-NR24 - Pulse channel 2
+///   FF1A-FF1E   NR30-NR34 - Wave channel 3
+///   FF20-FF23   NR41-NR44 - Noise channel 4
+///   FF24        NR50 - Master volume and VIN panning
+///   FF25        NR51 - Channel panning
+///   FF26        NR52 - Bit 7 power, bit 3-0 channel status (read only)
+///   FF30-FF3F   Wave RAM
+pub struct Apu {
+    model: HardwareModel,
+    config: AudioConfig,
+    powered: bool,
+    double_speed: bool,
+
+    pulse1: Pulse,
+    pulse2: Pulse,
+    wave: Wave,
+    noise: Noise,
+
+    nr50: u8,
+    nr51: u8,
+    channel_mask: ChannelMask,
+
+    sequencer: FrameSequencer,
+
+    // T-cycles left over from the last tick, short of a full M-cycle
+    cycle_remainder: u32,
+    output: Option<SampleOutput>,
+
+    // T-cycles ticked so far, timestamps for the write log
+    cycles: u64,
+    // The last value written to each of FF10-FF3F since power-off, the starting point of a
+    // write log.  Wave RAM survives power-off, so its entries do too.
+    written: [u8; 0x30],
+    write_log: Option<WriteLog>,
+}
+
+impl Apu {
+    /// A powered off APU with the default output settings.
+    pub fn new(model: HardwareModel) -> Self {
+        Apu::with_config(model, AudioConfig::default())
+    }
+
+    /// A powered off APU.
+    pub fn with_config(model: HardwareModel, config: AudioConfig) -> Self {
+        Apu {
+            model,
+            config,
+            powered: false,
+            double_speed: false,
+            pulse1: Pulse::with_sweep(),
+            pulse2: Pulse::new(),
+            wave: Wave::new(model),
+            noise: Noise::new(),
+            nr50: 0,
+            nr51: 0,
+            channel_mask: ChannelMask::all(),
+            sequencer: FrameSequencer::new(),
+            cycle_remainder: 0,
+            output: None,
+            cycles: 0,
+            written: [0; 0x30],
+            write_log: None,
+        }
+    }
+
+    /// Follows the CGB speed switch, which halves how often the frame sequencer steps.
+    pub fn set_double_speed(&mut self, double_speed: bool) {
+        self.double_speed = double_speed;
+    }
+
+    /// Silences channels at the mixer.  NR52 and everything else the game can see carry on
+    /// as normal.
+    pub fn set_channel_mask(&mut self, mask: ChannelMask) {
+        self.channel_mask = mask;
+    }
+
+    /// Which channels reach the mixer.
+    pub fn channel_mask(&self) -> ChannelMask {
+        self.channel_mask
+    }
+
+    /// Silences every channel but one at the mixer.
+    pub fn solo(&mut self, channel: Channel) {
+        self.channel_mask = ChannelMask::solo(channel);
+    }
+
+    /// See `Wave::set_trigger_corruption`.
+    pub fn set_wave_trigger_corruption(&mut self, enabled: bool) {
+        self.wave.set_trigger_corruption(enabled);
+    }
+
+    /// Registers a callback receiving stereo samples at the given rate, in chunks of
+    /// `mixer::CHUNK_FRAMES`.
+    pub fn set_sample_callback(&mut self, rate: u32, callback: SampleCallback) {
+        let high_pass = if self.config.high_pass { Some(HighPass::new(self.model, rate)) } else { None };
+        self.output = Some(SampleOutput::new(rate, callback, high_pass));
+    }
+
+    /// Delivers buffered samples that don't fill a whole chunk yet.
+    pub fn flush_samples(&mut self) {
+        if let Some(output) = self.output.as_mut() {
+            output.flush();
+        }
+    }
+
+    /// Starts recording every register write, beginning with the current register state.
+    pub fn enable_write_log(&mut self) {
+        self.write_log = Some(WriteLog::new(self.cycles, &self.written));
+    }
+
+    /// Stops recording and hands over what was recorded.
+    pub fn take_write_log(&mut self) -> Option<WriteLog> {
+        self.write_log.take()
+    }
+
+    /// Writes out what the write log has recorded so far.  Fails if it isn't enabled.
+    pub fn export_write_log(&self, format: WriteLogFormat, writer: &mut dyn Write) -> stdio::Result<()> {
+        match self.write_log {
+            Some(ref log) => log.export(format, writer),
+            None => Err(stdio::Error::other("APU write logging isn't enabled")),
+        }
+    }
+
+    /// Advances the channels by a number of T-cycles, mixing a sample every M-cycle.
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        if self.output.is_none() {
+            self.tick_channels(cycles);
+            return;
+        }
+
+        let mut cycles = cycles + self.cycle_remainder;
+        while cycles >= 4 {
+            self.tick_channels(4);
+            let frame = self.mix();
+            if let Some(output) = self.output.as_mut() {
+                output.push(frame);
+            }
+            cycles -= 4;
+        }
+        self.cycle_remainder = cycles;
+    }
+
+    fn tick_channels(&mut self, cycles: u32) {
+        if !self.powered {
+            return;
+        }
+        self.pulse1.tick(cycles);
+        self.pulse2.tick(cycles);
+        self.wave.tick(cycles);
+        self.noise.tick(cycles);
+    }
+
+    /// The stereo level being output right now.  NR50 and NR51 are applied as they currently
+    /// are, so panning changes take effect mid-note.
+    pub fn mix(&self) -> (f32, f32) {
+        let mask = self.channel_mask;
+        let level = |channel, dac_enabled, output| {
+            if mask.is_enabled(channel) { mixer::dac_output(dac_enabled, output) } else { 0.0 }
+        };
+        let levels = [
+            level(Channel::Pulse1, self.pulse1.dac_enabled(), self.pulse1.output()),
+            level(Channel::Pulse2, self.pulse2.dac_enabled(), self.pulse2.output()),
+            level(Channel::Wave, self.wave.dac_enabled(), self.wave.output()),
+            level(Channel::Noise, self.noise.dac_enabled(), self.noise.output()),
+        ];
+        mixer::mix(levels, self.nr50, self.nr51)
+    }
+
+    /// Called with the 16 bit internal divider whenever it changes, including when a write
+    /// to DIV resets it.  The frame sequencer runs off one of its bits.
+    pub fn update_divider(&mut self, divider: u16) {
+        let step = match self.sequencer.update(divider, self.double_speed) {
+            Some(step) if self.powered => step,
+            _ => return,
+        };
+
+        if sequencer::clocks_length(step) {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if sequencer::clocks_sweep(step) {
+            self.pulse1.clock_sweep();
+        }
+        if sequencer::clocks_envelope(step) {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.update_length_phase();
+    }
+
+    fn update_length_phase(&mut self) {
+        let first_half = self.sequencer.length_first_half();
+        self.pulse1.set_length_first_half(first_half);
+        self.pulse2.set_length_first_half(first_half);
+        self.wave.set_length_first_half(first_half);
+        self.noise.set_length_first_half(first_half);
+    }
+
+    fn read_nr52(&self) -> u8 {
+        let mut value = 0x70;
+        if self.powered {
+            value |= 0x80;
+        }
+        for (bit, enabled) in [
+            self.pulse1.is_enabled(),
+            self.pulse2.is_enabled(),
+            self.wave.is_enabled(),
+            self.noise.is_enabled(),
+        ].iter().enumerate() {
+            if *enabled {
+                value |= 1 << bit;
+            }
+        }
+        value
+    }
+
+    // Powering down clears every register but wave RAM, and on DMG the length counters.
+    // Powering up restarts the frame sequencer so its next step is 0.
+    fn write_nr52(&mut self, value: u8) {
+        let powered = value & 0x80 != 0;
+        if self.powered && !powered {
+            let keep_length = !self.model.is_cgb();
+            self.pulse1.power_off(keep_length);
+            self.pulse2.power_off(keep_length);
+            self.wave.power_off(keep_length);
+            self.noise.power_off(keep_length);
+            self.nr50 = 0;
+            self.nr51 = 0;
+            for value in self.written[..0x20].iter_mut() {
+                *value = 0;
+            }
+        } else if !self.powered && powered {
+            self.sequencer.reset();
+            self.update_length_phase();
+        }
+        self.powered = powered;
+    }
+
+    // Keeps track of a write that made it past the power gate, for the write log.
+    fn record_write(&mut self, address: u16, value: u8) {
+        self.written[(address - 0xFF10) as usize] = value;
+        if let Some(log) = self.write_log.as_mut() {
+            log.push(self.cycles, address, value);
+        }
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new(HardwareModel::Dmg)
+    }
+}
+
+impl IoPeripheral for Apu {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF10..=0xFF14 => self.pulse1.read((address - 0xFF10) as u8),
+            0xFF15..=0xFF19 => self.pulse2.read((address - 0xFF15) as u8),
+            0xFF1A..=0xFF1E => self.wave.read((address - 0xFF1A) as u8),
+            0xFF20..=0xFF23 => self.noise.read((address - 0xFF20) as u8),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => self.read_nr52(),
+            0xFF30..=0xFF3F => self.wave.read_ram(address),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        // Only NR52 and wave RAM can be written while the APU is off, plus the length
+        // registers on DMG.
+        if !self.powered && address < 0xFF26 {
+            if !self.model.is_cgb() {
+                match address {
+                    0xFF11 => self.pulse1.write_length(value),
+                    0xFF16 => self.pulse2.write_length(value),
+                    0xFF1B => self.wave.write_length(value),
+                    0xFF20 => self.noise.write_length(value),
+                    _ => return,
+                }
+                self.record_write(address, value);
+            }
+            return;
+        }
+        if !self.model.is_cgb() {
+            match address {
+                0xFF12 => self.pulse1.zombie_envelope(value),
+                0xFF17 => self.pulse2.zombie_envelope(value),
+                0xFF21 => self.noise.zombie_envelope(value),
+                _ => {},
+            }
+        }
+
+        match address {
+            0xFF10..=0xFF14 => self.pulse1.write((address - 0xFF10) as u8, value),
+            0xFF15..=0xFF19 => self.pulse2.write((address - 0xFF15) as u8, value),
+            0xFF1A..=0xFF1E => self.wave.write((address - 0xFF1A) as u8, value),
+            0xFF20..=0xFF23 => self.noise.write((address - 0xFF20) as u8, value),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            0xFF26 => self.write_nr52(value),
+            0xFF30..=0xFF3F => self.wave.write_ram(address, value),
+            _ => return,
+        }
+        self.record_write(address, value);
+    }
+}
+
+impl DividerListener for Apu {
+    fn divider_changed(&mut self, divider: u16) {
+        self.update_divider(divider);
+    }
+}
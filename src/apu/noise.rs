@@ -0,0 +1,171 @@
+//! Noise channel 4
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+
+/// Plays pseudo-random noise from a 15 bit linear feedback shift register.  Each clock XORs
+/// the lowest two bits, shifts right and feeds the result back in at bit 14, and also at bit 6
+/// in 7 bit mode.  The output is high while bit 0 is clear.
+///
+/// Registers
+///   FF20   NR41 - Bit 5-0 length (write only)
+///   FF21   NR42 - Volume envelope
+///   FF22   NR43 - Bit 7-4 clock shift, bit 3 width (1 = 7 bit), bit 2-0 divisor code
+///   FF23   NR44 - Bit 7 trigger, bit 6 length enable
+pub struct Noise {
+    enabled: bool,
+    polynomial: u8, // NR43
+    lfsr: u16,
+    timer: u32,
+
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            enabled: false,
+            polynomial: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+            envelope: Envelope::new(),
+            length: LengthCounter::new(64),
+        }
+    }
+
+    /// Resets every register, keeping the length counter on DMG.
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off(keep_length);
+        *self = Noise { length, ..Noise::new() };
+    }
+
+    /// Writes NR41.  On DMG this works even while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load((value & 0x3F) as u16);
+    }
+
+    /// Applies the DMG envelope zombie mode for an NRx2 write, if the channel is playing.
+    pub fn zombie_envelope(&mut self, value: u8) {
+        if self.enabled {
+            self.envelope.zombie_write(value);
+        }
+    }
+
+    /// Whether the channel is playing, as reported in NR52.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// The current DAC input, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || !self.envelope.dac_enabled() || self.lfsr & 0x1 != 0 {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+
+    /// Advances the LFSR by a number of T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        // Shifts of 14 and 15 stop the LFSR entirely
+        if self.polynomial >> 4 >= 14 {
+            return;
+        }
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.clock_lfsr();
+        }
+    }
+
+    pub fn set_length_first_half(&mut self, first_half: bool) {
+        self.length.set_first_half(first_half);
+    }
+
+    /// 256Hz, from the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// 64Hz, from the frame sequencer.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0 => 0xFF,
+            1 => self.envelope.read(),
+            2 => self.polynomial,
+            3 => 0xBF | if self.length.is_enabled() { 0x40 } else { 0 },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => self.write_length(value),
+            1 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            2 => self.polynomial = value,
+            3 => {
+                let expired = self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                } else if expired {
+                    self.enabled = false;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub fn clock_lfsr(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x1;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.polynomial & 0x08 != 0 {
+            self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+        }
+    }
+
+    // T-cycles between LFSR clocks: a divisor of 16 times the code, where code 0 counts as
+    // half (8), shifted left by the clock shift.
+    fn period(&self) -> u32 {
+        let divisor = match self.polynomial & 0x7 {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+        divisor << (self.polynomial >> 4)
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+        self.length.trigger();
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise::new()
+    }
+}
@@ -0,0 +1,276 @@
+//! Pulse (square wave) channels 1 and 2
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+
+// Duty cycle waveforms, one bit per step, played from bit 0
+const DUTY_PATTERNS: [u8; 4] = [
+    0b1000_0000, // 12.5%
+    0b1000_0001, // 25%
+    0b1110_0001, // 50%
+    0b0111_1110, // 75%
+];
+
+/// NR10 - Frequency sweep
+///   Bit 6-4   Period in 128Hz steps, 0 = no sweeping
+///   Bit 3     Direction, 1 = downwards
+///   Bit 2-0   Shift
+#[derive(Debug, Copy, Clone, Default)]
+struct Sweep {
+    register: u8,
+    shadow: u16,
+    timer: u8,
+    enabled: bool,
+    negated: bool, // A downwards calculation happened since the last trigger
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x7
+    }
+
+    fn negate(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x7
+    }
+
+    // A period of 0 reloads the timer with 8
+    fn reload_timer(&mut self) {
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+
+    // Returns the next frequency, or None if it overflows 11 bits.
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift();
+        let frequency = if self.negate() {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        if frequency > 0x7FF { None } else { Some(frequency) }
+    }
+}
+
+/// Channel 1 and 2 are the same apart from channel 2 lacking the sweep unit, and NR20 being
+/// unused.
+///
+/// Registers (channel 2 at FF15-FF19)
+///   FF10   NR10 - Sweep
+///   FF11   NR11 - Bit 7-6 duty, bit 5-0 length (write only)
+///   FF12   NR12 - Volume envelope
+///   FF13   NR13 - Frequency low bits (write only)
+///   FF14   NR14 - Bit 7 trigger, bit 6 length enable, bit 2-0 frequency high bits
+pub struct Pulse {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16, // 11 bits, the period is (2048 - frequency) * 4 T-cycles per duty step
+    timer: u32,
+
+    sweep: Option<Sweep>,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Pulse {
+    /// Channel 2
+    pub fn new() -> Self {
+        Pulse {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            sweep: None,
+            envelope: Envelope::new(),
+            length: LengthCounter::new(64),
+        }
+    }
+
+    /// Channel 1
+    pub fn with_sweep() -> Self {
+        Pulse { sweep: Some(Sweep::default()), ..Pulse::new() }
+    }
+
+    /// Resets every register, keeping the length counter on DMG.
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off(keep_length);
+        let sweep = self.sweep.map(|_| Sweep::default());
+        *self = Pulse { sweep, length, ..Pulse::new() };
+    }
+
+    /// Writes the length bits of NRx1.  On DMG this works even while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load((value & 0x3F) as u16);
+    }
+
+    /// Applies the DMG envelope zombie mode for an NRx2 write, if the channel is playing.
+    pub fn zombie_envelope(&mut self, value: u8) {
+        if self.enabled {
+            self.envelope.zombie_write(value);
+        }
+    }
+
+    /// Whether the channel is playing, as reported in NR52.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// The current DAC input, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || !self.envelope.dac_enabled() {
+            return 0;
+        }
+        let high = (DUTY_PATTERNS[self.duty as usize] >> self.duty_step) & 0x1;
+        high * self.envelope.volume()
+    }
+
+    /// Advances the waveform by a number of T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    /// 128Hz, from the frame sequencer.
+    pub fn clock_sweep(&mut self) {
+        let sweep = match self.sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        match sweep.calculate() {
+            Some(frequency) if sweep.shift() != 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for overflow once more, without being used
+                if sweep.calculate().is_none() {
+                    self.enabled = false;
+                }
+            },
+            Some(_) => {},
+            None => self.enabled = false,
+        }
+    }
+
+    pub fn set_length_first_half(&mut self, first_half: bool) {
+        self.length.set_first_half(first_half);
+    }
+
+    /// 256Hz, from the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// 64Hz, from the frame sequencer.
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0 => match self.sweep {
+                Some(ref sweep) => 0x80 | sweep.register,
+                None => 0xFF,
+            },
+            1 => 0x3F | (self.duty << 6),
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            4 => 0xBF | if self.length.is_enabled() { 0x40 } else { 0 },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => if let Some(ref mut sweep) = self.sweep {
+                // Leaving downwards mode after it was used for a calculation kills the channel
+                if sweep.negated && sweep.negate() && value & 0x08 == 0 {
+                    self.enabled = false;
+                }
+                sweep.register = value & 0x7F;
+            },
+            1 => {
+                self.duty = value >> 6;
+                self.write_length(value);
+            },
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            },
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x7) << 8);
+                let expired = self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                } else if expired {
+                    self.enabled = false;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    // The duty step is left alone, so retriggering a playing channel keeps its phase.  Only
+    // the time until the next step restarts.
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.length.trigger();
+
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.negated = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            if sweep.shift() != 0 && sweep.calculate().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+}
+
+impl Default for Pulse {
+    fn default() -> Self {
+        Pulse::new()
+    }
+}
@@ -0,0 +1,64 @@
+//! Frame sequencer
+//!
+//! Steps at 512Hz on the falling edge of a DIV bit (bit 4 of FF04, bit 5 in double speed mode),
+//! so resetting DIV can step it early.
+//!   Step   Length   Sweep   Envelope
+//!   0      x
+//!   1
+//!   2      x        x
+//!   3
+//!   4      x
+//!   5
+//!   6      x        x
+//!   7                       x
+
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameSequencer {
+    step: u8, // The next step to run
+    div_bit: bool,
+}
+
+impl FrameSequencer {
+    pub fn new() -> Self {
+        FrameSequencer::default()
+    }
+
+    /// Called with the 16 bit internal divider whenever it changes.  Returns the step to run,
+    /// if the watched bit fell.
+    pub fn update(&mut self, divider: u16, double_speed: bool) -> Option<u8> {
+        let bit = if double_speed { 13 } else { 12 };
+        let div_bit = divider & (1 << bit) != 0;
+        let fell = self.div_bit && !div_bit;
+        self.div_bit = div_bit;
+        if !fell {
+            return None;
+        }
+
+        let step = self.step;
+        self.step = (self.step + 1) % 8;
+        Some(step)
+    }
+
+    /// Whether the next step skips the length counters, making this the first half of a
+    /// length period.
+    pub fn length_first_half(&self) -> bool {
+        self.step & 1 == 1
+    }
+
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+}
+
+pub fn clocks_length(step: u8) -> bool {
+    step & 1 == 0
+}
+
+pub fn clocks_sweep(step: u8) -> bool {
+    step == 2 || step == 6
+}
+
+pub fn clocks_envelope(step: u8) -> bool {
+    step == 7
+}
@@ -0,0 +1,228 @@
+//! Wave channel 3
+
+use model::HardwareModel;
+use super::length::LengthCounter;
+
+
+// On DMG, wave RAM can only be reached while the channel plays within this many T-cycles of
+// the channel itself reading it.
+const DMG_ACCESS_WINDOW: u32 = 4;
+
+// Retriggering a playing DMG channel this close to its next wave RAM read corrupts wave RAM.
+const DMG_CORRUPTION_WINDOW: u32 = 2;
+
+/// Plays 32 4 bit samples from wave RAM, high nibble first.
+///
+/// Registers
+///   FF1A        NR30 - Bit 7 DAC enable
+///   FF1B        NR31 - Length (write only)
+///   FF1C        NR32 - Bit 6-5 volume: 0 = mute, 1 = 100%, 2 = 50%, 3 = 25%
+///   FF1D        NR33 - Frequency low bits (write only)
+///   FF1E        NR34 - Bit 7 trigger, bit 6 length enable, bit 2-0 frequency high bits
+///   FF30-FF3F   Wave RAM
+pub struct Wave {
+    model: HardwareModel,
+
+    enabled: bool,
+    dac_enabled: bool,
+    volume: u8,
+    frequency: u16, // 11 bits, the period is (2048 - frequency) * 2 T-cycles per sample
+    timer: u32,
+
+    ram: [u8; 16],
+    position: u8,    // Sample index, 0-31
+    sample: u8,      // The last sample read, which keeps playing until the next one
+    since_read: u32, // T-cycles since the channel last read wave RAM
+
+    trigger_corruption: bool,
+
+    length: LengthCounter,
+}
+
+impl Wave {
+    pub fn new(model: HardwareModel) -> Self {
+        Wave {
+            model,
+            enabled: false,
+            dac_enabled: false,
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            ram: [0; 16],
+            position: 0,
+            sample: 0,
+            since_read: u32::MAX,
+            trigger_corruption: false,
+            length: LengthCounter::new(256),
+        }
+    }
+
+    /// Resets everything but wave RAM, and the length counter on DMG.
+    pub fn power_off(&mut self, keep_length: bool) {
+        let mut length = self.length;
+        length.power_off(keep_length);
+        *self = Wave {
+            ram: self.ram,
+            length,
+            trigger_corruption: self.trigger_corruption,
+            ..Wave::new(self.model)
+        };
+    }
+
+    /// Opts in to the DMG wave RAM corruption on retrigger.  Off by default, only a few test
+    /// ROMs care.
+    pub fn set_trigger_corruption(&mut self, enabled: bool) {
+        self.trigger_corruption = enabled;
+    }
+
+    /// Writes NR31.  On DMG this works even while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value as u16);
+    }
+
+    /// Whether the channel is playing, as reported in NR52.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// The current DAC input, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || !self.dac_enabled {
+            return 0;
+        }
+        match self.volume {
+            0 => 0,
+            shift => self.sample >> (shift - 1),
+        }
+    }
+
+    /// Advances the sample position by a number of T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                self.since_read = self.since_read.saturating_add(cycles);
+                return;
+            }
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+            self.since_read = 0;
+        }
+    }
+
+    pub fn set_length_first_half(&mut self, first_half: bool) {
+        self.length.set_first_half(first_half);
+    }
+
+    /// 256Hz, from the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0 => 0x7F | if self.dac_enabled { 0x80 } else { 0 },
+            1 => 0xFF,
+            2 => 0x9F | (self.volume << 5),
+            3 => 0xFF,
+            4 => 0xBF | if self.length.is_enabled() { 0x40 } else { 0 },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            },
+            1 => self.write_length(value),
+            2 => self.volume = (value >> 5) & 0x3,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x7) << 8);
+                let expired = self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                } else if expired {
+                    self.enabled = false;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // While the channel plays, the CPU can only reach the byte the channel is playing.  CGB
+    // always gets it.  DMG only gets it in the same moment the channel reads it, and sees 0xFF
+    // otherwise.
+    pub fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_index(address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = self.ram_index(address) {
+            self.ram[index] = value;
+        }
+    }
+
+    // The byte a CPU access ends up at, if any.
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.enabled {
+            Some((address & 0xF) as usize)
+        } else if self.model.is_cgb() || self.since_read < DMG_ACCESS_WINDOW {
+            Some(self.position as usize / 2)
+        } else {
+            None
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    // The byte about to be read gets copied over the start of wave RAM.  In the first 4 bytes
+    // that's just byte 0, otherwise the whole aligned 4 byte block it sits in.
+    fn corrupt_ram(&mut self) {
+        let index = ((self.position as usize + 1) % 32) / 2;
+        if index < 4 {
+            self.ram[0] = self.ram[index];
+        } else {
+            let block = index & !0x3;
+            for i in 0..4 {
+                self.ram[i] = self.ram[block + i];
+            }
+        }
+    }
+
+    // The position goes back to the start, but the sample buffer isn't refilled.  The old
+    // sample plays for one more period before sample 1 is read.
+    fn trigger(&mut self) {
+        if self.trigger_corruption && !self.model.is_cgb() && self.enabled
+            && self.timer <= DMG_CORRUPTION_WINDOW {
+            self.corrupt_ram();
+        }
+
+        self.enabled = self.dac_enabled;
+        self.timer = self.period();
+        self.position = 0;
+        self.length.trigger();
+    }
+}
@@ -0,0 +1,611 @@
+//! Loading ROMs out of gzip and zip archives, and the DEFLATE they use
+//!
+//! Only what ROM archives use is supported: zip entries stored or deflated, without
+//! encryption or zip64, and single-member gzip files.  The compressor is for save states.
+
+use std::error::Error;
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crc::crc32;
+use error::FaroreError;
+
+
+/// Nothing is inflated past this, so a zip bomb can't eat all the memory.
+pub const MAX_UNPACKED_SIZE: usize = 16 * 1024 * 1024;
+
+const ROM_EXTENSIONS: [&str; 3] = [".gb", ".gbc", ".sgb"];
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4B50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4B50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4B50;
+
+/// A malformed, unsupported or ambiguous archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveError(String);
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ArchiveError {}
+
+fn error<T>(message: &str) -> Result<T, ArchiveError> {
+    Err(ArchiveError(message.to_string()))
+}
+
+/// The archive formats ROMs are commonly distributed in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// A single gzip compressed file.
+    Gzip,
+    /// A zip file, stored or deflated.
+    Zip,
+}
+
+/// Recognizes an archive by its first bytes.
+pub fn sniff(data: &[u8]) -> Option<ArchiveKind> {
+    if data.starts_with(&GZIP_MAGIC) {
+        Some(ArchiveKind::Gzip)
+    } else if data.len() >= 4 && LittleEndian::read_u32(data) == ZIP_LOCAL_HEADER {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// A file in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// The path inside the archive.
+    pub name: String,
+    /// The uncompressed size.
+    pub size: usize,
+    method: u16,
+    compressed_size: usize,
+    crc32: u32,
+    header_offset: usize,
+}
+
+impl ArchiveEntry {
+    /// Whether the name has a ROM extension, .gb, .gbc or .sgb.
+    pub fn is_rom(&self) -> bool {
+        let name = self.name.to_ascii_lowercase();
+        ROM_EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+    }
+}
+
+/// A ROM taken out of an archive, with its name inside the archive if it has one.
+pub struct Unpacked {
+    /// The name it had inside the archive, if the archive stored one.
+    pub name: Option<String>,
+    /// The ROM itself.
+    pub data: Vec<u8>,
+}
+
+/// Lists the files in a zip, or the single member of a gzip file.
+pub fn list(data: &[u8]) -> Result<Vec<ArchiveEntry>, FaroreError> {
+    match sniff(data) {
+        Some(ArchiveKind::Zip) => Ok(list_zip(data)?),
+        Some(ArchiveKind::Gzip) => {
+            let (name, _) = parse_gzip_header(data)?;
+            let size = if data.len() >= 4 { LittleEndian::read_u32(&data[data.len() - 4..]) as usize } else { 0 };
+            Ok(vec![ArchiveEntry {
+                name: name.unwrap_or_default(),
+                size,
+                method: 8,
+                compressed_size: data.len(),
+                crc32: 0,
+                header_offset: 0,
+            }])
+        },
+        None => Ok(error("not an archive")?),
+    }
+}
+
+/// Unpacks the ROM from an archive.  In a zip, `entry` picks a file by name.  Without it the
+/// zip must hold exactly one ROM, going by the file extensions, or only one file.
+pub fn unpack(data: &[u8], entry: Option<&str>) -> Result<Unpacked, FaroreError> {
+    match sniff(data) {
+        Some(ArchiveKind::Gzip) => Ok(gunzip(data)?),
+        Some(ArchiveKind::Zip) => {
+            let entries = list_zip(data)?;
+            let chosen = match entry {
+                Some(name) => match entries.iter().find(|entry| entry.name == name) {
+                    Some(entry) => entry,
+                    None => return Err(ArchiveError(format!("no entry named {} in the archive", name)).into()),
+                },
+                None => choose_rom(&entries)?,
+            };
+            let rom = extract_zip(data, chosen)?;
+            Ok(Unpacked { name: Some(chosen.name.clone()), data: rom })
+        },
+        None => Ok(error("not an archive")?),
+    }
+}
+
+fn choose_rom(entries: &[ArchiveEntry]) -> Result<&ArchiveEntry, ArchiveError> {
+    let roms: Vec<&ArchiveEntry> = entries.iter().filter(|entry| entry.is_rom()).collect();
+    match (roms.len(), entries.len()) {
+        (1, _) => Ok(roms[0]),
+        (0, 1) => Ok(&entries[0]),
+        (0, _) => error("the archive has no .gb or .gbc files, pick one with --entry"),
+        _ => {
+            let names: Vec<&str> = roms.iter().map(|entry| entry.name.as_str()).collect();
+            Err(ArchiveError(format!("the archive has several roms, pick one with --entry: {}", names.join(", "))))
+        },
+    }
+}
+
+fn list_zip(data: &[u8]) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    // The end of central directory record is 22 bytes plus a comment of up to 64KiB
+    let search_start = data.len().saturating_sub(22 + 0xFFFF);
+    let end = match (search_start..data.len().saturating_sub(21)).rev()
+        .find(|&offset| LittleEndian::read_u32(&data[offset..]) == ZIP_END_OF_DIRECTORY) {
+        Some(end) => end,
+        None => return error("the zip has no central directory"),
+    };
+    let count = LittleEndian::read_u16(&data[end + 10..]) as usize;
+    let mut offset = LittleEndian::read_u32(&data[end + 16..]) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = match data.get(offset..offset + 46) {
+            Some(header) if LittleEndian::read_u32(header) == ZIP_CENTRAL_HEADER => header,
+            _ => return error("the zip's central directory is damaged"),
+        };
+        let name_len = LittleEndian::read_u16(&header[28..]) as usize;
+        let extra_len = LittleEndian::read_u16(&header[30..]) as usize;
+        let comment_len = LittleEndian::read_u16(&header[32..]) as usize;
+        let name = match data.get(offset + 46..offset + 46 + name_len) {
+            Some(name) => String::from_utf8_lossy(name).into_owned(),
+            None => return error("the zip's central directory is damaged"),
+        };
+        entries.push(ArchiveEntry {
+            name,
+            size: LittleEndian::read_u32(&header[24..]) as usize,
+            method: LittleEndian::read_u16(&header[10..]),
+            compressed_size: LittleEndian::read_u32(&header[20..]) as usize,
+            crc32: LittleEndian::read_u32(&header[16..]),
+            header_offset: LittleEndian::read_u32(&header[42..]) as usize,
+        });
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+    // Directories aren't files
+    entries.retain(|entry| !entry.name.ends_with('/'));
+    Ok(entries)
+}
+
+fn extract_zip(data: &[u8], entry: &ArchiveEntry) -> Result<Vec<u8>, ArchiveError> {
+    let offset = entry.header_offset;
+    let header = match data.get(offset..offset + 30) {
+        Some(header) if LittleEndian::read_u32(header) == ZIP_LOCAL_HEADER => header,
+        _ => return Err(ArchiveError(format!("the zip entry {} is damaged", entry.name))),
+    };
+    if LittleEndian::read_u16(&header[6..]) & 0x0001 != 0 {
+        return Err(ArchiveError(format!("{} is encrypted", entry.name)));
+    }
+    let start = offset + 30 + LittleEndian::read_u16(&header[26..]) as usize + LittleEndian::read_u16(&header[28..]) as usize;
+    let compressed = match data.get(start..start + entry.compressed_size) {
+        Some(compressed) => compressed,
+        None => return Err(ArchiveError(format!("the zip entry {} is truncated", entry.name))),
+    };
+
+    let contents = match entry.method {
+        0 if entry.size > MAX_UNPACKED_SIZE => return error("the rom is too large"),
+        0 => compressed.to_vec(),
+        8 => inflate(compressed, MAX_UNPACKED_SIZE)?.0,
+        method => return Err(ArchiveError(format!("{} uses unsupported compression method {}", entry.name, method))),
+    };
+    if crc32(&contents) != entry.crc32 {
+        return Err(ArchiveError(format!("{} fails its CRC check", entry.name)));
+    }
+    Ok(contents)
+}
+
+// Returns the stored file name and where the compressed data starts.
+fn parse_gzip_header(data: &[u8]) -> Result<(Option<String>, usize), ArchiveError> {
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+    const FHCRC: u8 = 0x02;
+
+    if data.len() < 18 || data[2] != 8 {
+        return error("not a deflate gzip file");
+    }
+    let flags = data[3];
+    let mut offset = 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(offset..offset + 2).map(LittleEndian::read_u16).unwrap_or(0) as usize;
+        offset += 2 + len;
+    }
+    let read_string = |offset: &mut usize| -> Result<String, ArchiveError> {
+        let rest = data.get(*offset..).unwrap_or(&[]);
+        match rest.iter().position(|&byte| byte == 0) {
+            Some(len) => {
+                *offset += len + 1;
+                Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+            },
+            None => error("the gzip header is truncated"),
+        }
+    };
+    let name = if flags & FNAME != 0 { Some(read_string(&mut offset)?) } else { None };
+    if flags & FCOMMENT != 0 {
+        read_string(&mut offset)?;
+    }
+    if flags & FHCRC != 0 {
+        offset += 2;
+    }
+    if offset > data.len() {
+        return error("the gzip header is truncated");
+    }
+    Ok((name, offset))
+}
+
+fn gunzip(data: &[u8]) -> Result<Unpacked, ArchiveError> {
+    let (name, start) = parse_gzip_header(data)?;
+    let (contents, used) = inflate(&data[start..], MAX_UNPACKED_SIZE)?;
+    let trailer = match data.get(start + used..start + used + 8) {
+        Some(trailer) => trailer,
+        None => return error("the gzip file is truncated"),
+    };
+    if LittleEndian::read_u32(trailer) != crc32(&contents) {
+        return error("the gzip file fails its CRC check");
+    }
+    Ok(Unpacked { name, data: contents })
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // The next byte to load
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, ArchiveError> {
+        while self.count < n {
+            let byte = match self.data.get(self.position) {
+                Some(&byte) => byte,
+                None => return error("the compressed data is truncated"),
+            };
+            self.buffer |= (byte as u32) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer = self.buffer.checked_shr(n).unwrap_or(0);
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ArchiveError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        error("the compressed data has an invalid code")
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses raw DEFLATE data, failing once the output would pass `limit`.  Returns the data
+/// and how many input bytes it took.
+pub fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), ArchiveError> {
+    let mut reader = BitReader { data, position: 0, buffer: 0, count: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = match data.get(reader.position..reader.position + 4) {
+                    Some(header) => header,
+                    None => return error("the compressed data is truncated"),
+                };
+                let len = LittleEndian::read_u16(header) as usize;
+                if LittleEndian::read_u16(&header[2..]) != !(len as u16) {
+                    return error("the compressed data has a damaged stored block");
+                }
+                let start = reader.position + 4;
+                match data.get(start..start + len) {
+                    Some(block) => output.extend_from_slice(block),
+                    None => return error("the compressed data is truncated"),
+                }
+                reader.position = start + len;
+            },
+            1 => {
+                let mut lengths = [0u8; 288];
+                for (symbol, length) in lengths.iter_mut().enumerate() {
+                    *length = match symbol {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            },
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            },
+            _ => return error("the compressed data has an invalid block type"),
+        }
+        if output.len() > limit {
+            return Err(ArchiveError(format!("the rom unpacks to more than {} bytes", limit)));
+        }
+        if last {
+            return Ok((output, reader.position));
+        }
+    }
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), ArchiveError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let (value, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 if index > 0 => (lengths[index - 1], 3 + reader.bits(2)? as usize),
+            16 => return error("the compressed data repeats a missing code length"),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return error("the compressed data has too many code lengths");
+        }
+        for length in &mut lengths[index..index + repeat] {
+            *length = value;
+        }
+        index += repeat;
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman,
+                 limit: usize) -> Result<(), ArchiveError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return error("the compressed data has an invalid length");
+            }
+            let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code] as u32)? as usize;
+            let code = distances.decode(reader)? as usize;
+            if code >= DISTANCE_BASE.len() {
+                return error("the compressed data has an invalid distance");
+            }
+            let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+            if distance > output.len() {
+                return error("the compressed data refers back past its start");
+            }
+            let start = output.len() - distance;
+            for i in 0..length {
+                let byte = output[start + i];
+                output.push(byte);
+            }
+        }
+        if output.len() > limit {
+            return Err(ArchiveError(format!("the rom unpacks to more than {} bytes", limit)));
+        }
+    }
+}
+
+// Matches are looked for this far back, the most DEFLATE allows.
+const WINDOW_SIZE: usize = 32 * 1024;
+// And no longer than this.
+const MAX_MATCH: usize = 258;
+// Candidates tried per position before settling for the best so far.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Compresses data as raw DEFLATE, in a single block of the fixed Huffman codes.  That leaves
+/// out building code tables, and still shrinks the zero runs and repeats machine state is full
+/// of.  `inflate` reads it back.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { output: Vec::new(), buffer: 0, count: 0 };
+    writer.bits(1, 1); // The last block
+    writer.bits(1, 2); // Fixed codes
+
+    let mut matches = MatchFinder { head: vec![usize::MAX; 1 << HASH_BITS], previous: vec![usize::MAX; data.len()] };
+    let mut position = 0;
+    while position < data.len() {
+        let step = match matches.longest(data, position) {
+            (length, distance) if length >= 3 => {
+                writer.length(length);
+                writer.distance(distance);
+                length
+            },
+            _ => {
+                writer.literal(data[position] as usize);
+                1
+            },
+        };
+        for at in position..position + step {
+            matches.insert(data, at);
+        }
+        position += step;
+    }
+    writer.literal(256);
+    writer.finish()
+}
+
+// Chains together the positions that start with the same 3 bytes, going by their hash.
+struct MatchFinder {
+    head: Vec<usize>,     // The most recent position of each hash
+    previous: Vec<usize>, // The one before each position with the same hash
+}
+
+impl MatchFinder {
+    fn hash(data: &[u8], at: usize) -> usize {
+        let prefix = (data[at] as u32) << 16 | (data[at + 1] as u32) << 8 | data[at + 2] as u32;
+        (prefix.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, data: &[u8], at: usize) {
+        if at + 3 <= data.len() {
+            let key = MatchFinder::hash(data, at);
+            self.previous[at] = self.head[key];
+            self.head[key] = at;
+        }
+    }
+
+    // The longest earlier match for the data at `position` and how far back it is, or a length
+    // of 0.
+    fn longest(&self, data: &[u8], position: usize) -> (usize, usize) {
+        if position + 3 > data.len() {
+            return (0, 0);
+        }
+        let (mut best_length, mut best_distance) = (0, 0);
+        let max_length = MAX_MATCH.min(data.len() - position);
+        let mut candidate = self.head[MatchFinder::hash(data, position)];
+        let mut tries = 0;
+        while candidate != usize::MAX && position - candidate <= WINDOW_SIZE && tries < MAX_CHAIN {
+            let length = data[candidate..].iter().zip(&data[position..position + max_length])
+                .take_while(|&(a, b)| a == b)
+                .count();
+            if length > best_length {
+                best_length = length;
+                best_distance = position - candidate;
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate];
+            tries += 1;
+        }
+        (best_length, best_distance)
+    }
+}
+
+// Packs bits least significant first, as DEFLATE does everything but its Huffman codes.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go in most significant bit first.
+    fn code(&mut self, code: u32, n: u32) {
+        self.bits(code.reverse_bits() >> (32 - n), n);
+    }
+
+    // A literal byte, the end of block or a length, in the fixed literal/length code.
+    fn literal(&mut self, symbol: usize) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+        self.literal(257 + code);
+        self.bits((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+        self.code(code as u32, 5);
+        self.bits((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
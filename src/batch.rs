@@ -0,0 +1,264 @@
+//! Validating a whole directory of ROMs
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use farore::cart::GameboyProgramMeta;
+use farore::json::Json;
+
+use cli::Failure;
+
+
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+/// How one file fared.
+#[derive(Debug, Clone)]
+pub enum BatchResult {
+    Checked {
+        title: String,
+        cart_type: u8,
+        size_ok: bool,
+        header_ok: bool,
+        logo_ok: bool,
+    },
+    Error(String), // Unreadable, or the header couldn't be parsed
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchRow {
+    pub path: PathBuf, // Relative to the directory being checked
+    pub result: BatchResult,
+}
+
+impl BatchRow {
+    /// Whether the ROM would boot.  The size check only warns.
+    pub fn passed(&self) -> bool {
+        match self.result {
+            BatchResult::Checked { header_ok, logo_ok, .. } => header_ok && logo_ok,
+            BatchResult::Error(_) => false,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let path = ("path", Json::String(self.path.display().to_string()));
+        match self.result {
+            BatchResult::Checked { ref title, cart_type, size_ok, header_ok, logo_ok } => Json::object(vec![
+                path,
+                ("title", Json::string(title)),
+                ("cart_type", Json::Number(cart_type as i64)),
+                ("size_ok", Json::Bool(size_ok)),
+                ("header_ok", Json::Bool(header_ok)),
+                ("logo_ok", Json::Bool(logo_ok)),
+                ("passed", Json::Bool(self.passed())),
+            ]),
+            BatchResult::Error(ref error) => Json::object(vec![path, ("error", Json::string(error))]),
+        }
+    }
+}
+
+/// Every ROM under a directory, sorted by path.
+pub struct BatchReport {
+    pub rows: Vec<BatchRow>,
+    pub failed_only: bool, // Leave out the ROMs that passed when printing
+}
+
+impl BatchReport {
+    /// Walks `dir` for .gb and .gbc files and checks each one.  Errors on single files or
+    /// subdirectories become rows instead of stopping the walk.  Symlinks are not followed,
+    /// so link loops can't trap it.
+    pub fn check_dir(dir: &Path) -> Self {
+        let mut rows = Vec::new();
+        walk(dir, dir, &mut rows);
+        rows.sort_by(|a, b| a.path.cmp(&b.path));
+        BatchReport { rows, failed_only: false }
+    }
+
+    /// The shown rows as a JSON array.
+    pub fn to_json(&self) -> Json {
+        Json::Array(self.shown_rows().map(BatchRow::to_json).collect())
+    }
+
+    fn shown_rows(&self) -> impl Iterator<Item = &BatchRow> {
+        let failed_only = self.failed_only;
+        self.rows.iter().filter(move |row| !failed_only || !row.passed())
+    }
+
+    pub fn failures(&self) -> usize {
+        self.rows.iter().filter(|row| !row.passed()).count()
+    }
+
+    /// The run's outcome, which picks the exit code: failed ROMs first, then with `strict`
+    /// ROMs that don't match their declared size.
+    pub fn verdict(&self, strict: bool) -> Result<(), Failure> {
+        if self.failures() > 0 {
+            return Err(Failure::Invalid(format!("{} roms failed", self.failures())));
+        }
+        let warnings = self.rows.iter().filter(|row| match row.result {
+            BatchResult::Checked { size_ok, .. } => !size_ok,
+            BatchResult::Error(_) => false,
+        }).count();
+        if strict && warnings > 0 {
+            return Err(Failure::Warnings(format!("{} roms don't match their declared size", warnings)));
+        }
+        Ok(())
+    }
+}
+
+fn walk(root: &Path, dir: &Path, rows: &mut Vec<BatchRow>) {
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            rows.push(BatchRow { path: relative(dir), result: BatchResult::Error(e.to_string()) });
+            return;
+        },
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                rows.push(BatchRow { path: relative(dir), result: BatchResult::Error(e.to_string()) });
+                continue;
+            },
+        };
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                rows.push(BatchRow { path: relative(&path), result: BatchResult::Error(e.to_string()) });
+                continue;
+            },
+        };
+        if file_type.is_dir() {
+            walk(root, &path, rows);
+        } else if file_type.is_symlink() {
+            debug!("Not following symlink {}", path.display());
+        } else if file_type.is_file() && has_rom_extension(&path) {
+            debug!("Checking {}", path.display());
+            rows.push(BatchRow { path: relative(&path), result: check_file(&path) });
+        }
+    }
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => ROM_EXTENSIONS.iter().any(|rom| extension.eq_ignore_ascii_case(rom)),
+        None => false,
+    }
+}
+
+fn check_file(path: &Path) -> BatchResult {
+    let mut rom = Vec::new();
+    if let Err(e) = fs::File::open(path).and_then(|mut file| file.read_to_end(&mut rom)) {
+        return BatchResult::Error(e.to_string());
+    }
+    match GameboyProgramMeta::new(&rom) {
+        Ok(meta) => BatchResult::Checked {
+            title: meta.name.to_string(),
+            cart_type: meta.cart_type().byte(),
+            size_ok: meta.is_valid_size(),
+            header_ok: meta.is_valid_header(),
+            logo_ok: meta.is_valid_logo(),
+        },
+        Err(e) => BatchResult::Error(e.to_string()),
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let test = |x| if x { "OK" } else { "FAILED" };
+        let width = self.rows.iter().map(|row| row.path.display().to_string().len()).max().unwrap_or(0).max(4);
+
+        writeln!(f, "{:<width$}  {:<16}  type  size    header  logo    verdict", "file", "title", width = width)?;
+        for row in self.shown_rows() {
+            let path = row.path.display().to_string();
+            let line = match row.result {
+                BatchResult::Checked { ref title, cart_type, size_ok, header_ok, logo_ok } => {
+                    format!("{:<width$}  {:<16}  {:02X}    {:<6}  {:<6}  {:<6}  {}",
+                        path, title, cart_type, test(size_ok), test(header_ok), test(logo_ok),
+                        test(row.passed()), width = width)
+                },
+                BatchResult::Error(ref error) => format!("{:<width$}  error: {}", path, error, width = width),
+            };
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "{} roms, {} passed, {} failed", self.rows.len(), self.rows.len() - self.failures(), self.failures())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use farore::cart::{self, Repairs};
+
+    use farore::error::FaroreError;
+
+    use cli::EXIT_INVALID;
+
+    use super::*;
+
+
+    // A fresh directory under the temp dir, for the test called `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("farore-batch-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A 32KiB ROM that passes every check.
+    fn good_rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        cart::repair(&mut rom, Repairs::ALL).unwrap();
+        rom
+    }
+
+    #[test]
+    fn a_tree_of_good_and_broken_files() {
+        let dir = scratch("tree");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("good.gb"), good_rom(b"GOOD")).unwrap();
+        fs::write(dir.join("sub").join("short.GBC"), &good_rom(b"SHORT")[..0x100]).unwrap();
+        fs::write(dir.join("notes.txt"), "not a rom").unwrap();
+
+        let mut report = BatchReport::check_dir(&dir);
+        let table = report.to_string();
+        report.failed_only = true;
+        let failed = report.to_string();
+        let verdict = report.verdict(false);
+        let _ = fs::remove_dir_all(&dir);
+
+        let short = format!("{}  error: {}", Path::new("sub").join("short.GBC").display(), FaroreError::RomTooShort(0x100));
+        assert_eq!(table, [
+            "file           title             type  size    header  logo    verdict",
+            "good.gb        GOOD              00    OK      OK      OK      OK",
+            &short,
+            "2 roms, 1 passed, 1 failed\n",
+        ].join("\n"));
+        assert_eq!(failed, [
+            "file           title             type  size    header  logo    verdict",
+            &short,
+            "2 roms, 1 passed, 1 failed\n",
+        ].join("\n"));
+        assert_eq!(verdict.unwrap_err().exit_code(), EXIT_INVALID);
+    }
+
+    #[test]
+    fn an_all_good_tree_passes() {
+        let dir = scratch("good");
+        fs::write(dir.join("a.gb"), good_rom(b"A")).unwrap();
+        fs::write(dir.join("b.gbc"), good_rom(b"B")).unwrap();
+        let report = BatchReport::check_dir(&dir);
+        let _ = fs::remove_dir_all(&dir);
+
+        let paths: Vec<String> = report.rows.iter().map(|row| row.path.display().to_string()).collect();
+        assert_eq!(paths, ["a.gb", "b.gbc"]);
+        assert!(report.verdict(true).is_ok());
+    }
+}
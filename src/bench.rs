@@ -0,0 +1,155 @@
+//! Measuring emulation speed
+//!
+//! Like the frame pacer, a benchmark only does arithmetic on the timestamps handed to it, so
+//! the report comes out the same against a made up clock.  The first frames of a run warm up
+//! caches and are left out of the measurement.
+
+use std::fmt;
+use std::time::Duration;
+
+use json::Json;
+use pacing::FRAME_TIME;
+
+
+/// Frames left out of the measurement unless asked otherwise, one second of emulated time.
+pub const DEFAULT_WARMUP_FRAMES: u64 = 60;
+
+/// Times a run of frames, leaving out the warm-up.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    warmup_frames: u64,
+    frames: u64,                       // Including the warm-up
+    start: Option<(Duration, u64)>,    // Host time and cycle count when the warm-up ended
+    last: (Duration, u64),
+    components: Vec<(&'static str, Duration)>,
+}
+
+impl Benchmark {
+    /// Starts a benchmark at host time `now`, measuring after `warmup_frames` frames.
+    pub fn new(warmup_frames: u64, now: Duration) -> Self {
+        let start = if warmup_frames == 0 { Some((now, 0)) } else { None };
+        Benchmark { warmup_frames, frames: 0, start, last: (now, 0), components: Vec::new() }
+    }
+
+    /// Whether frames are still being left out.
+    pub fn is_warming_up(&self) -> bool {
+        self.start.is_none()
+    }
+
+    /// Records a finished frame at host time `now`, `cycles` being the clock cycles run since
+    /// the start, warm-up included.
+    pub fn frame(&mut self, now: Duration, cycles: u64) {
+        self.frames += 1;
+        self.last = (now, cycles);
+        if self.frames == self.warmup_frames {
+            self.start = Some(self.last);
+        }
+    }
+
+    /// Adds time spent in one part of the machine, such as "cpu" or "ppu".  Time during the
+    /// warm-up is ignored.
+    pub fn add_component_time(&mut self, name: &'static str, time: Duration) {
+        if self.is_warming_up() {
+            return;
+        }
+        match self.components.iter_mut().find(|component| component.0 == name) {
+            Some(component) => component.1 += time,
+            None => self.components.push((name, time)),
+        }
+    }
+
+    /// What was measured so far.  All zeros while warming up.
+    pub fn report(&self) -> BenchReport {
+        let (start, frames) = match self.start {
+            Some(start) => (start, self.frames - self.warmup_frames),
+            None => (self.last, 0),
+        };
+        BenchReport {
+            frames,
+            warmup_frames: self.warmup_frames,
+            cycles: self.last.1 - start.1,
+            wall_time: self.last.0.saturating_sub(start.0),
+            components: self.components.clone(),
+        }
+    }
+}
+
+/// The result of a benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Frames emulated after the warm-up.
+    pub frames: u64,
+    /// Frames left out before measuring.
+    pub warmup_frames: u64,
+    /// Clock cycles run after the warm-up.
+    pub cycles: u64,
+    /// Host time the measured frames took.
+    pub wall_time: Duration,
+    /// Time spent in each part of the machine, in the order first seen.  Empty when the
+    /// scheduler doesn't count it.
+    pub components: Vec<(&'static str, Duration)>,
+}
+
+impl BenchReport {
+    /// How long the measured frames take on the hardware.
+    pub fn emulated_time(&self) -> Duration {
+        Duration::from_nanos(FRAME_TIME.as_nanos() as u64 * self.frames)
+    }
+
+    /// How many times faster than the hardware, or None if no time passed.
+    pub fn speed(&self) -> Option<f64> {
+        if self.wall_time.is_zero() {
+            return None;
+        }
+        Some(self.emulated_time().as_secs_f64() / self.wall_time.as_secs_f64())
+    }
+
+    /// Clock cycles run per host second, or None if no time passed.
+    pub fn cycles_per_second(&self) -> Option<u64> {
+        if self.wall_time.is_zero() {
+            return None;
+        }
+        Some((self.cycles as f64 / self.wall_time.as_secs_f64()) as u64)
+    }
+
+    /// The report as JSON, times in microseconds and the speed in percent of the hardware's.
+    pub fn to_json(&self) -> Json {
+        let micros = |time: Duration| Json::Number(time.as_micros() as i64);
+        let components = self.components.iter()
+            .map(|&(name, time)| Json::object(vec![("name", Json::string(name)), ("time_us", micros(time))]))
+            .collect();
+        Json::object(vec![
+            ("frames", Json::Number(self.frames as i64)),
+            ("warmup_frames", Json::Number(self.warmup_frames as i64)),
+            ("cycles", Json::Number(self.cycles as i64)),
+            ("wall_time_us", micros(self.wall_time)),
+            ("emulated_time_us", micros(self.emulated_time())),
+            ("speed_percent", self.speed().map_or(Json::Null, |speed| Json::Number((speed * 100.0).round() as i64))),
+            ("cycles_per_second", self.cycles_per_second().map_or(Json::Null, |cycles| Json::Number(cycles as i64))),
+            ("components", Json::Array(components)),
+        ])
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "frames:  {} after {} to warm up, {:.2}s of emulated time",
+            self.frames, self.warmup_frames, self.emulated_time().as_secs_f64())?;
+        write!(f, "time:    {:.2}s", self.wall_time.as_secs_f64())?;
+        match self.speed() {
+            Some(speed) => writeln!(f, ", {:.2}x the hardware", speed)?,
+            None => writeln!(f)?,
+        }
+        write!(f, "cycles:  {}", self.cycles)?;
+        match self.cycles_per_second() {
+            Some(cycles) => writeln!(f, ", {:.1}M per second", cycles as f64 / 1e6)?,
+            None => writeln!(f)?,
+        }
+        let total: Duration = self.components.iter().map(|component| component.1).sum();
+        for &(name, time) in &self.components {
+            let share = if total.is_zero() { 0.0 } else { time.as_secs_f64() / total.as_secs_f64() * 100.0 };
+            writeln!(f, "{:<8} {:.2}s  {:.0}%", name, time.as_secs_f64(), share)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//! Result reporting of blargg's test ROMs
+//!
+//! The newer test ROMs (dmg_sound, cgb_sound, mem_timing, ...) report through cartridge RAM:
+//!   A000        Status: 0x80 while running, then the result code, 0 = passed
+//!   A001-A003   Signature DE B0 61, once the protocol is active
+//!   A004-       Zero terminated text, the same as shown on screen
+
+
+const STATUS_ADDRESS: u16 = 0xA000;
+const SIGNATURE_ADDRESS: u16 = 0xA001;
+const TEXT_ADDRESS: u16 = 0xA004;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RUNNING: u8 = 0x80;
+
+// Longer text than this means the ROM never terminated it
+const MAX_TEXT_LEN: u16 = 0x1000;
+
+/// How far a test ROM has got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlarggStatus {
+    /// Not started yet, or the ROM doesn't use the protocol.
+    NoSignature,
+    /// The test is still going.
+    Running,
+    /// The test is done.
+    Finished(SoundTestResult),
+}
+
+/// The outcome of a finished test ROM.  Multi-test ROMs list their sub-tests in the text as
+/// "01:ok 02:01 ...", where anything other than "ok" is the sub-test's failure code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoundTestResult {
+    /// The result code, 0 for a pass.
+    pub code: u8,
+    /// The text the ROM printed.
+    pub text: String,
+    /// Each sub-test's number and whether it passed.
+    pub subtests: Vec<(u8, bool)>,
+}
+
+impl SoundTestResult {
+    /// Whether the whole ROM passed.
+    pub fn passed(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// Checks the result area, reading memory through `read`.
+pub fn read_status(read: &dyn Fn(u16) -> u8) -> BlarggStatus {
+    let signature = [
+        read(SIGNATURE_ADDRESS),
+        read(SIGNATURE_ADDRESS + 1),
+        read(SIGNATURE_ADDRESS + 2),
+    ];
+    if signature != SIGNATURE {
+        return BlarggStatus::NoSignature;
+    }
+
+    let code = read(STATUS_ADDRESS);
+    if code == RUNNING {
+        return BlarggStatus::Running;
+    }
+
+    let mut bytes = Vec::new();
+    for offset in 0..MAX_TEXT_LEN {
+        match read(TEXT_ADDRESS + offset) {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    let subtests = parse_subtests(&text);
+
+    BlarggStatus::Finished(SoundTestResult { code, text, subtests })
+}
+
+fn parse_subtests(text: &str) -> Vec<(u8, bool)> {
+    text.split_whitespace()
+        .filter_map(|token| {
+            let (number, result) = token.split_once(':')?;
+            if number.len() != 2 || result.is_empty() {
+                return None;
+            }
+            Some((number.parse().ok()?, result == "ok"))
+        })
+        .collect()
+}
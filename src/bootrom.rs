@@ -0,0 +1,182 @@
+//! Boot ROMs
+//!
+//! A boot ROM is optional.  Without one the machine starts from the state the boot ROM would
+//! have left it in, given by `post_boot_registers` and `post_boot_io`.  Dumps are recognized
+//! by SHA-1, but an unrecognized one still loads, as people patch them to change the logo or
+//! skip the animation.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha1;
+
+use error::FaroreError;
+use model::HardwareModel;
+use trace::Registers;
+
+
+const DMG_SIZE: usize = 0x100;
+const CGB_SIZE: usize = 0x900;
+
+const DMG_POST_BOOT_IO: [(u16, u8); 41] = [
+    (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E), (0xFF04, 0xAB), (0xFF05, 0x00), (0xFF06, 0x00),
+    (0xFF07, 0xF8), (0xFF0F, 0xE1), (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+    (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF), (0xFF1A, 0x7F),
+    (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00),
+    (0xFF22, 0x00), (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91),
+    (0xFF41, 0x85), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF44, 0x00), (0xFF45, 0x00), (0xFF46, 0xFF),
+    (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFF50, 0xFF), (0xFFFF, 0x00),
+];
+const CGB_POST_BOOT_IO: [(u16, u8); 47] = [
+    (0xFF00, 0xC7), (0xFF01, 0x00), (0xFF02, 0x7F), (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8),
+    (0xFF0F, 0xE1), (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF),
+    (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1B, 0xFF),
+    (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00),
+    (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91), (0xFF42, 0x00),
+    (0xFF43, 0x00), (0xFF45, 0x00), (0xFF46, 0x00), (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00),
+    (0xFF4D, 0x7E), (0xFF4F, 0xFE), (0xFF50, 0xFF), (0xFF51, 0xFF), (0xFF52, 0xFF), (0xFF53, 0xFF),
+    (0xFF54, 0xFF), (0xFF55, 0xFF), (0xFF6C, 0xFE), (0xFF70, 0xF8), (0xFFFF, 0x00),
+];
+
+/// The boot ROM dumps in circulation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BootRomKind {
+    /// The early Japanese DMG revision.
+    Dmg0,
+    /// The DMG.
+    Dmg,
+    /// The Game Boy Pocket.
+    Mgb,
+    /// The Super Game Boy.
+    Sgb,
+    /// The Super Game Boy 2.
+    Sgb2,
+    /// The early CGB revision.
+    Cgb0,
+    /// The CGB.
+    Cgb,
+    /// The Game Boy Advance's CGB mode.
+    Agb,
+}
+
+impl BootRomKind {
+    /// Whether it needs the CGB hardware.
+    pub fn is_cgb(self) -> bool {
+        matches!(self, BootRomKind::Cgb0 | BootRomKind::Cgb | BootRomKind::Agb)
+    }
+}
+
+/// The SHA-1 of each known dump.
+pub const KNOWN_DIGESTS: [(BootRomKind, &str); 8] = [
+    (BootRomKind::Dmg0, "8bd501e31921e9601788316dbd3ce9833a97bcbc"),
+    (BootRomKind::Dmg, "4ed31ec6b0b175bb109c0eb5fd3d193da823339f"),
+    (BootRomKind::Mgb, "4e68f9da03c310e84c523654b9026e51f26ce7f0"),
+    (BootRomKind::Sgb, "aa2f50a77dfb4823da96ba99309085a3c6278515"),
+    (BootRomKind::Sgb2, "93407ea10d2f30ab96a314d8eca44fe160aea734"),
+    (BootRomKind::Cgb0, "df5a0d2d49de38fbd31cc2aab8e62c8550e655c0"),
+    (BootRomKind::Cgb, "1293d68bf9643bc4f36954c1e80e38f39864528d"),
+    (BootRomKind::Agb, "fa5287e24b0fa533b3b5ef2b28a81245346c1a0f"),
+];
+
+/// A boot ROM that fits the model it's going to run on.
+#[derive(Debug, Clone)]
+pub struct BootRom {
+    /// The ROM, 256 bytes for the DMG or 2304 for the CGB.
+    pub data: Vec<u8>,
+    /// The SHA-1, in lowercase hex.
+    pub sha1: String,
+    /// Which dump it is, or `None` if it's patched or unknown.
+    pub kind: Option<BootRomKind>,
+}
+
+impl BootRom {
+    /// Checks `data` against `model` and the known dumps.
+    pub fn new(data: Vec<u8>, model: HardwareModel) -> Result<Self, FaroreError> {
+        BootRom::with_digests(data, model, &KNOWN_DIGESTS)
+    }
+
+    /// Like `new`, recognizing dumps by `digests` instead.
+    pub fn with_digests(data: Vec<u8>, model: HardwareModel, digests: &[(BootRomKind, &str)]) -> Result<Self, FaroreError> {
+        let sha1 = sha1::Sha1::from(&data[..]).digest().to_string();
+        let kind = digests.iter().find(|&&(_, digest)| digest.eq_ignore_ascii_case(&sha1)).map(|&(kind, _)| kind);
+        let is_cgb = match (data.len(), kind) {
+            (_, Some(kind)) => kind.is_cgb(),
+            (DMG_SIZE, None) => false,
+            (CGB_SIZE, None) => true,
+            (size, None) => {
+                return Err(FaroreError::InvalidArgument(
+                    format!("a boot rom is {} bytes for the DMG or {} for the CGB, not {}", DMG_SIZE, CGB_SIZE, size)));
+            },
+        };
+        if is_cgb != model.is_cgb() {
+            let (rom, machine) = if is_cgb { ("CGB", "DMG") } else { ("DMG", "CGB") };
+            return Err(FaroreError::InvalidArgument(format!("a {} boot rom can't boot the {}", rom, machine)));
+        }
+        match kind {
+            Some(kind) => info!("Boot ROM: {:?}", kind),
+            None => warn!("Unrecognized boot ROM with SHA-1 {}, using it anyway", sha1),
+        }
+        Ok(BootRom { data, sha1, kind })
+    }
+
+    /// Reads and checks a boot ROM file.
+    pub fn load(path: &Path, model: HardwareModel) -> Result<Self, FaroreError> {
+        let data = fs::read(path).map_err(|e| FaroreError::io(path, e))?;
+        BootRom::new(data, model)
+    }
+}
+
+/// The CPU registers the boot ROM leaves behind, with PC at the cartridge entry point 0x0100.
+/// The DMG boot ROM ends by checking the header checksum, so `header_checksum`, the byte at
+/// 0x014D, decides whether H and C are set.  The CGB values are the ones for CGB carts.
+pub fn post_boot_registers(model: HardwareModel, header_checksum: u8) -> Registers {
+    if model.is_cgb() {
+        return Registers { a: 0x11, f: 0x80, b: 0x00, c: 0x00, d: 0xFF, e: 0x56, h: 0x00, l: 0x0D, sp: 0xFFFE, pc: 0x0100 };
+    }
+    let f = if header_checksum == 0 { 0x80 } else { 0xB0 };
+    Registers { a: 0x01, f, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, sp: 0xFFFE, pc: 0x0100 }
+}
+
+/// What the I/O registers read as when the boot ROM hands over, by address.  Registers that
+/// differ between units, or depend on exactly how long the boot took, are left out.
+pub fn post_boot_io(model: HardwareModel) -> &'static [(u16, u8)] {
+    if model.is_cgb() { &CGB_POST_BOOT_IO } else { &DMG_POST_BOOT_IO }
+}
+
+/// Where to look for a boot ROM, most specific first.
+#[derive(Debug, Clone, Default)]
+pub struct BootRomPaths {
+    /// A file given for this run, whatever the model.
+    pub explicit: Option<PathBuf>,
+    /// The file for the DMG.
+    pub dmg: Option<PathBuf>,
+    /// The file for the CGB.
+    pub cgb: Option<PathBuf>,
+    /// A directory to look in for the usual file names.
+    pub dir: Option<PathBuf>,
+}
+
+// The names dumps usually go by, in order of preference.
+const DMG_NAMES: [&str; 4] = ["dmg_boot.bin", "dmg.bin", "mgb_boot.bin", "dmg0_boot.bin"];
+const CGB_NAMES: [&str; 4] = ["cgb_boot.bin", "cgb.bin", "agb_boot.bin", "cgb0_boot.bin"];
+
+/// Picks and loads the boot ROM for `model`.  `None` means there's none configured, and the
+/// machine should skip straight to the cartridge.
+pub fn select(paths: &BootRomPaths, model: HardwareModel) -> Result<Option<BootRom>, FaroreError> {
+    let configured = if model.is_cgb() { &paths.cgb } else { &paths.dmg };
+    let names = if model.is_cgb() { &CGB_NAMES } else { &DMG_NAMES };
+    let found = paths.dir.as_ref().and_then(|dir| {
+        names.iter().map(|name| dir.join(name)).find(|path| path.is_file())
+    });
+    match paths.explicit.as_ref().or(configured.as_ref()).or(found.as_ref()) {
+        Some(path) => {
+            debug!("Loading the boot ROM from {}", path.display());
+            BootRom::load(path, model).map(Some)
+        },
+        None => {
+            let name = if model.is_cgb() { "CGB" } else { "DMG" };
+            info!("No boot ROM for the {}, starting from the state it leaves the machine in", name);
+            Ok(None)
+        },
+    }
+}
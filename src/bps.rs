@@ -0,0 +1,203 @@
+//! Applying BPS patches, which carry checksums of the ROM they expect and the ROM they make
+//!
+//! Layout:
+//!   "BPS1"
+//!   Number    Source size
+//!   Number    Target size
+//!   Number    Metadata size, then that much UTF-8 metadata
+//!   Actions until the footer, each a number: the low two bits pick the action, the rest
+//!   are the length minus one
+//!     0 SourceRead    Copy from the source at the same offset as the output
+//!     1 TargetRead    Copy the next bytes of the patch
+//!     2 SourceCopy    Then a signed number moving a source cursor, copy from there
+//!     3 TargetCopy    Then a signed number moving a target cursor, copy from the output
+//!                     made so far, which may overlap what's being written
+//!   Footer, little endian: CRC-32s of the source, the target and the patch before this field
+//! Numbers are 7 bits a byte, low bits first, with the top bit marking the last byte.  Signed
+//! numbers keep the sign in the low bit.
+
+use crc::crc32;
+use ips::PatchError;
+
+
+const MAGIC: &[u8] = b"BPS1";
+const FOOTER_SIZE: usize = 12;
+
+/// Patches can't make a ROM bigger than this, so a bogus size can't eat all the memory.
+pub const MAX_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+/// A parsed BPS patch, with its checksum verified.
+#[derive(Debug, Clone)]
+pub struct BpsPatch<'a> {
+    /// The size of the ROM it applies to.
+    pub source_size: usize,
+    /// The size of the ROM it makes.
+    pub target_size: usize,
+    /// Free-form text from the patch's author, often XML.  Empty if there is none.
+    pub metadata: String,
+    /// The CRC-32 of the ROM it applies to.
+    pub source_crc32: u32,
+    /// The CRC-32 of the ROM it makes.
+    pub target_crc32: u32,
+    actions: &'a [u8],
+}
+
+/// Applies `patch` to `source`, failing unless both the source and the result have the CRC-32s
+/// the patch expects.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    BpsPatch::parse(patch)?.apply(source, false)
+}
+
+impl<'a> BpsPatch<'a> {
+    /// Reads the header and footer, and checks the patch against its own CRC-32.
+    pub fn parse(patch: &'a [u8]) -> Result<Self, PatchError> {
+        if !patch.starts_with(MAGIC) || patch.len() < MAGIC.len() + FOOTER_SIZE {
+            return Err(PatchError("not a BPS patch".to_string()));
+        }
+        let footer = &patch[patch.len() - FOOTER_SIZE..];
+        let crc = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+        let patch_crc32 = crc32(&patch[..patch.len() - 4]);
+        if patch_crc32 != crc(8) {
+            return Err(PatchError(format!("the patch is corrupt, its CRC32 is {:08X} instead of {:08X}", patch_crc32, crc(8))));
+        }
+
+        let mut reader = Reader { data: &patch[..patch.len() - FOOTER_SIZE], at: MAGIC.len() };
+        let source_size = reader.number()?;
+        let target_size = reader.number()?;
+        if target_size > MAX_TARGET_SIZE {
+            return Err(PatchError(format!("the patch makes a {} byte rom, more than the {} allowed", target_size, MAX_TARGET_SIZE)));
+        }
+        let metadata_size = reader.number()?;
+        let metadata = String::from_utf8_lossy(reader.bytes(metadata_size)?).into_owned();
+        Ok(BpsPatch {
+            source_size,
+            target_size,
+            metadata,
+            source_crc32: crc(0),
+            target_crc32: crc(4),
+            actions: &reader.data[reader.at..],
+        })
+    }
+
+    /// Applies the patch to `source`.  With `force`, the source doesn't have to match and a
+    /// result with the wrong CRC-32 is returned anyway, which is only useful when the source is
+    /// known to differ from the expected one in ways the patch doesn't touch.
+    pub fn apply(&self, source: &[u8], force: bool) -> Result<Vec<u8>, PatchError> {
+        let source_crc32 = crc32(source);
+        if source_crc32 != self.source_crc32 {
+            let message = format!("the rom's CRC32 is {:08X}, the patch is for {:08X}", source_crc32, self.source_crc32);
+            if !force {
+                return Err(PatchError(message));
+            }
+            warn!("Applying anyway: {}", message);
+        }
+
+        let target = self.decode(source)?;
+        let target_crc32 = crc32(&target);
+        if target_crc32 != self.target_crc32 {
+            let message = format!("the patched rom's CRC32 is {:08X} instead of {:08X}", target_crc32, self.target_crc32);
+            if !force {
+                return Err(PatchError(message));
+            }
+            warn!("Keeping it anyway: {}", message);
+        }
+        Ok(target)
+    }
+
+    fn decode(&self, source: &[u8]) -> Result<Vec<u8>, PatchError> {
+        let out_of_range = |action: &str| PatchError(format!("a {} reaches outside the rom", action));
+        let mut reader = Reader { data: self.actions, at: 0 };
+        let mut target = Vec::with_capacity(self.target_size);
+        let (mut source_cursor, mut target_cursor) = (0usize, 0usize);
+
+        while !reader.is_done() {
+            let action = reader.number()?;
+            let length = (action >> 2) + 1;
+            if length > self.target_size - target.len() {
+                return Err(PatchError("the patch writes past the end of the target".to_string()));
+            }
+            match action & 3 {
+                0 => {
+                    let start = target.len();
+                    let bytes = source.get(start..start + length).ok_or_else(|| out_of_range("SourceRead"))?;
+                    target.extend_from_slice(bytes);
+                },
+                1 => target.extend_from_slice(reader.bytes(length)?),
+                2 => {
+                    source_cursor = reader.offset(source_cursor)?.ok_or_else(|| out_of_range("SourceCopy"))?;
+                    let end = source_cursor.checked_add(length).ok_or_else(|| out_of_range("SourceCopy"))?;
+                    let bytes = source.get(source_cursor..end).ok_or_else(|| out_of_range("SourceCopy"))?;
+                    target.extend_from_slice(bytes);
+                    source_cursor = end;
+                },
+                _ => {
+                    target_cursor = reader.offset(target_cursor)?.ok_or_else(|| out_of_range("TargetCopy"))?;
+                    if target_cursor >= target.len() {
+                        return Err(out_of_range("TargetCopy"));
+                    }
+                    // Byte by byte, as the copy can read what it has just written
+                    for _ in 0..length {
+                        let byte = target[target_cursor];
+                        target.push(byte);
+                        target_cursor += 1;
+                    }
+                },
+            }
+        }
+        if target.len() != self.target_size {
+            return Err(PatchError(format!("the patch made {} bytes instead of {}", target.len(), self.target_size)));
+        }
+        Ok(target)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.at >= self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        let byte = *self.data.get(self.at).ok_or_else(truncated)?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.at.checked_add(count).ok_or_else(truncated)?;
+        let bytes = self.data.get(self.at..end).ok_or_else(truncated)?;
+        self.at = end;
+        Ok(bytes)
+    }
+
+    // Each byte after the first also adds the value one more byte would start at, so there's
+    // only one way to write each number.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let overflow = || PatchError("a number in the patch is too large".to_string());
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.byte()?;
+            let digit = usize::from(byte & 0x7F).checked_mul(shift).ok_or_else(overflow)?;
+            value = value.checked_add(digit).ok_or_else(overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+            value = value.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    // A signed step from `cursor`, or `None` if it goes below zero.
+    fn offset(&mut self, cursor: usize) -> Result<Option<usize>, PatchError> {
+        let step = self.number()?;
+        Ok(if step & 1 == 1 { cursor.checked_sub(step >> 1) } else { cursor.checked_add(step >> 1) })
+    }
+}
+
+fn truncated() -> PatchError {
+    PatchError("the patch is truncated".to_string())
+}
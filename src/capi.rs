@@ -0,0 +1,145 @@
+//! The C API, built with the `capi` feature
+//!
+//! For embedding the core in C, C++ or anything else with a C FFI.  include/farore.h declares
+//! it and is generated from this file with cbindgen, using cbindgen.toml.
+//!
+//! Ownership rules:
+//!   - A machine from `farore_machine_new` belongs to the caller until it's passed to
+//!     `farore_machine_free`, once.
+//!   - The ROM and options passed in are only read during the call, and stay the caller's.
+//!   - The string from `farore_last_error` belongs to farore.  It lasts until the next call
+//!     on the same thread, or on the same machine when it's a machine's error.
+//!
+//! No entry point unwinds into the caller: a panic is caught and reported as an error.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use cart::{CartridgeType, GameboyProgramMeta};
+use error::FaroreError;
+
+
+/// `FaroreOptions.model`: go by the cartridge's CGB flag.
+pub const FARORE_MODEL_AUTO: u32 = 0;
+/// `FaroreOptions.model`: the original Game Boy.
+pub const FARORE_MODEL_DMG: u32 = 1;
+/// `FaroreOptions.model`: the Game Boy Color.
+pub const FARORE_MODEL_CGB: u32 = 2;
+
+/// `FaroreOptions.palette`: plain grays.
+pub const FARORE_PALETTE_GRAYSCALE: u32 = 0;
+/// `FaroreOptions.palette`: the original model's green.
+pub const FARORE_PALETTE_DMG_GREEN: u32 = 1;
+/// `FaroreOptions.palette`: the Game Boy Pocket's gray-green.
+pub const FARORE_PALETTE_POCKET: u32 = 2;
+
+/// How a machine is set up.  Passing null for the options is the same as passing all zeros.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FaroreOptions {
+    /// One of the `FARORE_MODEL_` constants.
+    pub model: u32,
+    /// One of the `FARORE_PALETTE_` constants, for DMG games.
+    pub palette: u32,
+}
+
+/// A machine, opaque to C.
+#[derive(Debug)]
+pub struct FaroreMachine {
+    error: Option<CString>,
+}
+
+thread_local! {
+    // The error from the last call on this thread that failed without a machine to keep it.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Creates a machine running the `rom_len` bytes at `rom`.  Returns null on failure, with the
+/// reason in `farore_last_error(NULL)`.
+///
+/// # Safety
+///
+/// `rom` must point to `rom_len` readable bytes, and `options` must be null or point to a
+/// `FaroreOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn farore_machine_new(rom: *const u8, rom_len: usize, options: *const FaroreOptions)
+    -> *mut FaroreMachine {
+    guard(ptr::null_mut(), || {
+        if rom.is_null() {
+            return Err(FaroreError::InvalidArgument("the rom is null".to_string()));
+        }
+        let options = options.as_ref().cloned().unwrap_or_default();
+        check_options(&options)?;
+        let rom = slice::from_raw_parts(rom, rom_len);
+        if let CartridgeType::Invalid(byte) = GameboyProgramMeta::new(rom)?.cart_type() {
+            return Err(FaroreError::UnsupportedMapper(byte));
+        }
+        Err(FaroreError::InvalidArgument("running roms isn't supported yet, there is no CPU".to_string()))
+    })
+}
+
+/// Frees a machine.  Null is ignored.
+///
+/// # Safety
+///
+/// `machine` must be null or come from `farore_machine_new`, and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn farore_machine_free(machine: *mut FaroreMachine) {
+    if !machine.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(machine))));
+    }
+}
+
+/// Why the last call on `machine` failed, or with null, why the last `farore_machine_new` on
+/// this thread did.  Null when it didn't fail.
+///
+/// # Safety
+///
+/// `machine` must be null or a machine that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn farore_last_error(machine: *const FaroreMachine) -> *const c_char {
+    let message = |error: &Option<CString>| error.as_ref().map_or(ptr::null(), |message| message.as_ptr());
+    match machine.as_ref() {
+        Some(machine) => message(&machine.error),
+        None => LAST_ERROR.with(|error| message(&error.borrow())),
+    }
+}
+
+fn check_options(options: &FaroreOptions) -> Result<(), FaroreError> {
+    if options.model > FARORE_MODEL_CGB {
+        return Err(FaroreError::InvalidArgument(format!("{} isn't a FARORE_MODEL_", options.model)));
+    }
+    if options.palette > FARORE_PALETTE_POCKET {
+        return Err(FaroreError::InvalidArgument(format!("{} isn't a FARORE_PALETTE_", options.palette)));
+    }
+    Ok(())
+}
+
+// Runs an entry point without a machine, recording its error or panic for farore_last_error
+// and returning `failed` in their place.
+fn guard<T, F: FnOnce() -> Result<T, FaroreError>>(failed: T, f: F) -> T {
+    let error = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => {
+            LAST_ERROR.with(|error| *error.borrow_mut() = None);
+            return value;
+        },
+        Ok(Err(e)) => e.to_string(),
+        Err(payload) => format!("farore panicked: {}", panic_message(&*payload)),
+    };
+    // A message can't hold a NUL for C, so cut it there
+    let error = CString::new(error.split('\0').next().unwrap_or("")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+    failed
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown", |message| message),
+    }
+}
@@ -1,20 +1,40 @@
+//! Cartridge header parsing and repair
+
+use std::borrow::Cow;
+use std::fmt;
 use std::num::Wrapping;
+use std::ops::Range;
 use std::io::Write;
-use std::error::Error;
+use std::slice::Chunks;
 
 use byteorder::{ByteOrder, BigEndian};
-use sha1;
 
+use error::FaroreError;
+use json::Json;
+use licensee::{Licensee, LicenseeCode};
+
+
+/// The header runs from 0x0100 to 0x014F, so a ROM needs at least this many bytes to hold one.
+pub const HEADER_END: usize = 0x0150;
+
+/// The size of a ROM bank, which ROMs are trimmed to and split into.
+pub const BANK_SIZE: usize = 0x4000;
 
-static LOGO_BITMAP_HASH: [u8; 20] = [
-    0x07, 0x45, 0xFD, 0xEF, 0x34, 0x13, 0x2D, 0x1B, 0x3D, 0x48,
-    0x8C, 0xFB, 0xDF, 0x03, 0x79, 0xA3, 0x9F, 0xD5, 0x4B, 0x4C,
+// No cartridge has less than two banks
+const MIN_ROM_SIZE: usize = 2 * BANK_SIZE;
+
+static NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
 ];
 
 #[derive(Debug, Copy, Clone)]
 enum GameboyRegionCode {
     Japan,    // 0x00
     NonJapan, // 0x01
+    #[allow(dead_code)] // Only shown through Debug
     Invalid(u8),
 }
 
@@ -33,6 +53,7 @@ enum GameboyColorFlag {
     Undefined,           // 0x00.  On older cartridges, this byte is part of the title.
     BackwardsCompatible, // 0x80
     GBCOnly,             // 0xC0
+    #[allow(dead_code)] // Only shown through Debug
     Invalid(u8),
 }
 
@@ -51,6 +72,7 @@ impl GameboyColorFlag {
 enum SuperGameboyFeatureFlag {
     Unsupported, // 0x00
     Supported,   // 0x03
+    #[allow(dead_code)] // Only shown through Debug
     Invalid(u8),
 }
 
@@ -64,14 +86,212 @@ impl SuperGameboyFeatureFlag {
     }
 }
 
+/// The memory bank controller, or other mapper, on a cartridge.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MbcKind {
+    /// No mapper, just 32KB of ROM and maybe 8KB of RAM.
+    None,
+    /// MBC1.
+    Mbc1,
+    /// MBC2, with 512 half-bytes of RAM built in.
+    Mbc2,
+    /// MMM01, for multicarts.
+    Mmm01,
+    /// MBC3, some with a clock.
+    Mbc3,
+    /// MBC5.
+    Mbc5,
+    /// MBC6, with flash memory.
+    Mbc6,
+    /// MBC7, with an accelerometer and EEPROM.
+    Mbc7,
+    /// The Game Boy Camera's mapper.
+    PocketCamera,
+    /// Bandai's TAMA5.
+    Tama5,
+    /// Hudson's HuC3, with a clock and infrared.
+    HuC3,
+    /// Hudson's HuC1, with infrared.
+    HuC1,
+}
+
+/// The cartridge type byte at 0x0147: the mapper and the hardware alongside it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CartridgeType {
+    /// 0x00, ROM ONLY.
+    RomOnly,
+    /// 0x01, MBC1.
+    Mbc1,
+    /// 0x02, MBC1+RAM.
+    Mbc1Ram,
+    /// 0x03, MBC1+RAM+BATTERY.
+    Mbc1RamBattery,
+    /// 0x05, MBC2.
+    Mbc2,
+    /// 0x06, MBC2+BATTERY.
+    Mbc2Battery,
+    /// 0x08, ROM+RAM.
+    RomRam,
+    /// 0x09, ROM+RAM+BATTERY.
+    RomRamBattery,
+    /// 0x0B, MMM01.
+    Mmm01,
+    /// 0x0C, MMM01+RAM.
+    Mmm01Ram,
+    /// 0x0D, MMM01+RAM+BATTERY.
+    Mmm01RamBattery,
+    /// 0x0F, MBC3+TIMER+BATTERY.
+    Mbc3TimerBattery,
+    /// 0x10, MBC3+TIMER+RAM+BATTERY.
+    Mbc3TimerRamBattery,
+    /// 0x11, MBC3.
+    Mbc3,
+    /// 0x12, MBC3+RAM.
+    Mbc3Ram,
+    /// 0x13, MBC3+RAM+BATTERY.
+    Mbc3RamBattery,
+    /// 0x19, MBC5.
+    Mbc5,
+    /// 0x1A, MBC5+RAM.
+    Mbc5Ram,
+    /// 0x1B, MBC5+RAM+BATTERY.
+    Mbc5RamBattery,
+    /// 0x1C, MBC5+RUMBLE.
+    Mbc5Rumble,
+    /// 0x1D, MBC5+RUMBLE+RAM.
+    Mbc5RumbleRam,
+    /// 0x1E, MBC5+RUMBLE+RAM+BATTERY.
+    Mbc5RumbleRamBattery,
+    /// 0x20, MBC6.
+    Mbc6,
+    /// 0x22, MBC7+SENSOR+RUMBLE+RAM+BATTERY.
+    Mbc7SensorRumbleRamBattery,
+    /// 0xFC, POCKET CAMERA.
+    PocketCamera,
+    /// 0xFD, BANDAI TAMA5.
+    BandaiTama5,
+    /// 0xFE, HuC3.
+    HuC3,
+    /// 0xFF, HuC1+RAM+BATTERY.
+    HuC1RamBattery,
+    /// A byte no cartridge uses.
+    Invalid(u8),
+}
+
+// Each type with its byte and the name the Pan Docs give it.
+const CARTRIDGE_TYPES: [(u8, CartridgeType, &str); 28] = [
+    (0x00, CartridgeType::RomOnly, "ROM ONLY"),
+    (0x01, CartridgeType::Mbc1, "MBC1"),
+    (0x02, CartridgeType::Mbc1Ram, "MBC1+RAM"),
+    (0x03, CartridgeType::Mbc1RamBattery, "MBC1+RAM+BATTERY"),
+    (0x05, CartridgeType::Mbc2, "MBC2"),
+    (0x06, CartridgeType::Mbc2Battery, "MBC2+BATTERY"),
+    (0x08, CartridgeType::RomRam, "ROM+RAM"),
+    (0x09, CartridgeType::RomRamBattery, "ROM+RAM+BATTERY"),
+    (0x0B, CartridgeType::Mmm01, "MMM01"),
+    (0x0C, CartridgeType::Mmm01Ram, "MMM01+RAM"),
+    (0x0D, CartridgeType::Mmm01RamBattery, "MMM01+RAM+BATTERY"),
+    (0x0F, CartridgeType::Mbc3TimerBattery, "MBC3+TIMER+BATTERY"),
+    (0x10, CartridgeType::Mbc3TimerRamBattery, "MBC3+TIMER+RAM+BATTERY"),
+    (0x11, CartridgeType::Mbc3, "MBC3"),
+    (0x12, CartridgeType::Mbc3Ram, "MBC3+RAM"),
+    (0x13, CartridgeType::Mbc3RamBattery, "MBC3+RAM+BATTERY"),
+    (0x19, CartridgeType::Mbc5, "MBC5"),
+    (0x1A, CartridgeType::Mbc5Ram, "MBC5+RAM"),
+    (0x1B, CartridgeType::Mbc5RamBattery, "MBC5+RAM+BATTERY"),
+    (0x1C, CartridgeType::Mbc5Rumble, "MBC5+RUMBLE"),
+    (0x1D, CartridgeType::Mbc5RumbleRam, "MBC5+RUMBLE+RAM"),
+    (0x1E, CartridgeType::Mbc5RumbleRamBattery, "MBC5+RUMBLE+RAM+BATTERY"),
+    (0x20, CartridgeType::Mbc6, "MBC6"),
+    (0x22, CartridgeType::Mbc7SensorRumbleRamBattery, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+    (0xFC, CartridgeType::PocketCamera, "POCKET CAMERA"),
+    (0xFD, CartridgeType::BandaiTama5, "BANDAI TAMA5"),
+    (0xFE, CartridgeType::HuC3, "HuC3"),
+    (0xFF, CartridgeType::HuC1RamBattery, "HuC1+RAM+BATTERY"),
+];
+
+impl CartridgeType {
+    /// Decodes the byte at 0x0147.
+    pub fn new(byte: u8) -> Self {
+        CARTRIDGE_TYPES.iter()
+            .find(|&&(code, _, _)| code == byte)
+            .map_or(CartridgeType::Invalid(byte), |&(_, cart_type, _)| cart_type)
+    }
+
+    /// The byte at 0x0147.
+    pub fn byte(self) -> u8 {
+        match self {
+            CartridgeType::Invalid(byte) => byte,
+            cart_type => CARTRIDGE_TYPES.iter().find(|&&(_, known, _)| known == cart_type).map_or(0, |&(code, _, _)| code),
+        }
+    }
+
+    /// Whether there's RAM for the game to use, counting the MBC2's built-in RAM.
+    pub fn has_ram(self) -> bool {
+        use self::CartridgeType::*;
+        matches!(self, Mbc1Ram | Mbc1RamBattery | Mbc2 | Mbc2Battery | RomRam | RomRamBattery | Mmm01Ram
+            | Mmm01RamBattery | Mbc3TimerRamBattery | Mbc3Ram | Mbc3RamBattery | Mbc5Ram | Mbc5RamBattery
+            | Mbc5RumbleRam | Mbc5RumbleRamBattery | Mbc7SensorRumbleRamBattery | PocketCamera | HuC3 | HuC1RamBattery)
+    }
+
+    /// Whether a battery keeps the RAM, or the clock, going with the power off.
+    pub fn has_battery(self) -> bool {
+        use self::CartridgeType::*;
+        matches!(self, Mbc1RamBattery | Mbc2Battery | RomRamBattery | Mmm01RamBattery | Mbc3TimerBattery
+            | Mbc3TimerRamBattery | Mbc3RamBattery | Mbc5RamBattery | Mbc5RumbleRamBattery
+            | Mbc7SensorRumbleRamBattery | PocketCamera | BandaiTama5 | HuC3 | HuC1RamBattery)
+    }
+
+    /// Whether there's a real time clock.
+    pub fn has_rtc(self) -> bool {
+        use self::CartridgeType::*;
+        matches!(self, Mbc3TimerBattery | Mbc3TimerRamBattery | BandaiTama5 | HuC3)
+    }
+
+    /// Whether there's a rumble motor.
+    pub fn has_rumble(self) -> bool {
+        use self::CartridgeType::*;
+        matches!(self, Mbc5Rumble | Mbc5RumbleRam | Mbc5RumbleRamBattery | Mbc7SensorRumbleRamBattery)
+    }
+
+    /// The mapper, or `None` for an invalid byte.
+    pub fn mbc_kind(self) -> Option<MbcKind> {
+        use self::CartridgeType::*;
+        Some(match self {
+            RomOnly | RomRam | RomRamBattery => MbcKind::None,
+            Mbc1 | Mbc1Ram | Mbc1RamBattery => MbcKind::Mbc1,
+            Mbc2 | Mbc2Battery => MbcKind::Mbc2,
+            Mmm01 | Mmm01Ram | Mmm01RamBattery => MbcKind::Mmm01,
+            Mbc3TimerBattery | Mbc3TimerRamBattery | Mbc3 | Mbc3Ram | Mbc3RamBattery => MbcKind::Mbc3,
+            Mbc5 | Mbc5Ram | Mbc5RamBattery | Mbc5Rumble | Mbc5RumbleRam | Mbc5RumbleRamBattery => MbcKind::Mbc5,
+            Mbc6 => MbcKind::Mbc6,
+            Mbc7SensorRumbleRamBattery => MbcKind::Mbc7,
+            PocketCamera => MbcKind::PocketCamera,
+            BandaiTama5 => MbcKind::Tama5,
+            HuC3 => MbcKind::HuC3,
+            HuC1RamBattery => MbcKind::HuC1,
+            Invalid(_) => return None,
+        })
+    }
+}
+
+impl fmt::Display for CartridgeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match CARTRIDGE_TYPES.iter().find(|&&(_, known, _)| known == *self) {
+            Some(&(_, _, name)) => write!(f, "{}", name),
+            None => write!(f, "unknown (0x{:02X})", self.byte()),
+        }
+    }
+}
+
 fn calculate_header_checksum(buf: &[u8]) -> u8 {
     // x=0:FOR i=0134h TO 014Ch:x=x-MEM[i]-1:NEXT
-    buf.into_iter().skip(0x0134).take(0x014C - 0x0134 + 1)
+    buf.iter().skip(0x0134).take(0x014C - 0x0134 + 1)
         .fold(Wrapping(0u8), |acc, &x| acc - Wrapping(x) - Wrapping(1u8)).0
 }
 
 fn calculate_global_checksum(buf: &[u8]) -> u16 {
-    let iter = buf.into_iter().enumerate().filter_map(|(i, &x)| {
+    let iter = buf.iter().enumerate().filter_map(|(i, &x)| {
         match i {
             0x014E => None,
             0x014F => None,
@@ -79,16 +299,215 @@ fn calculate_global_checksum(buf: &[u8]) -> u16 {
         }
     });
 
-    return iter.fold(Wrapping(0u16), |acc, x| acc + Wrapping(x as u16)).0;
+    iter.fold(Wrapping(0u16), |acc, x| acc + Wrapping(x as u16)).0
+}
+
+/// Which repairs `repair` applies.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Repairs {
+    /// Reinsert the Nintendo logo.
+    pub logo: bool,
+    /// Recompute the header and global checksums.
+    pub checksums: bool,
+    /// Pad with 0xFF up to the declared ROM size.
+    pub pad: bool,
+}
+
+impl Repairs {
+    /// Leave the ROM as it is.
+    pub const NONE: Repairs = Repairs { logo: false, checksums: false, pad: false };
+    /// Every repair there is.
+    pub const ALL: Repairs = Repairs { logo: true, checksums: true, pad: true };
+}
+
+/// Fixes up a ROM in place.  Padding comes before the checksums so the global checksum covers
+/// the padding.  Fails if the ROM is too short to hold a header.
+pub fn repair(rom: &mut Vec<u8>, repairs: Repairs) -> Result<(), FaroreError> {
+    if rom.len() < HEADER_END {
+        return Err(FaroreError::RomTooShort(rom.len()));
+    }
+    if repairs.logo {
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+    }
+    if repairs.pad {
+        let declared = GameboyProgramMeta::new(rom)?.declared_size();
+        if let Some(size) = declared {
+            if size > rom.len() {
+                rom.resize(size, 0xFF);
+            }
+        }
+    }
+    if repairs.checksums {
+        rom[0x014D] = calculate_header_checksum(rom);
+        let global = calculate_global_checksum(rom);
+        BigEndian::write_u16(&mut rom[0x014E..0x0150], global);
+    }
+    Ok(())
+}
+
+/// Pads the ROM with `fill` up to `size` bytes.  Fails if it's already bigger.
+pub fn pad(rom: &mut Vec<u8>, size: usize, fill: u8) -> Result<(), FaroreError> {
+    if size < rom.len() {
+        return Err(FaroreError::InvalidArgument(format!("the rom is already {} bytes, more than {}", rom.len(), size)));
+    }
+    rom.resize(size, fill);
+    Ok(())
+}
+
+/// The size the ROM would be without the run of identical bytes at its end, rounded up to a
+/// whole bank and never below two banks.
+pub fn trimmed_size(rom: &[u8]) -> usize {
+    let end = match rom.last() {
+        Some(&padding) => rom.iter().rposition(|&byte| byte != padding).map_or(0, |last| last + 1),
+        None => 0,
+    };
+    end.div_ceil(BANK_SIZE).saturating_mul(BANK_SIZE).max(MIN_ROM_SIZE).min(rom.len())
+}
+
+/// Cuts the trailing padding off, down to `trimmed_size`.  Unless `force` is set, fails rather
+/// than leave the ROM smaller than its header declares.  Returns the new size.
+pub fn trim(rom: &mut Vec<u8>, force: bool) -> Result<usize, FaroreError> {
+    let size = trimmed_size(rom);
+    if !force {
+        if let Some(declared) = GameboyProgramMeta::new(rom)?.declared_size() {
+            if size < declared {
+                return Err(FaroreError::InvalidArgument(
+                    format!("trimming to {} bytes would leave less than the declared {}", size, declared)));
+            }
+        }
+    }
+    rom.truncate(size);
+    Ok(size)
+}
+
+/// The ROM in pieces of `banks` banks each, the last one possibly shorter.
+pub fn split(rom: &[u8], banks: usize) -> Chunks<'_, u8> {
+    rom.chunks(banks.max(1).saturating_mul(BANK_SIZE))
+}
+
+/// One field of the header, and whether it passed validation if it's checked at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    /// A human readable name, like "header checksum".
+    pub name: &'static str,
+    /// Where the field sits in the ROM.
+    pub range: Range<usize>,
+    /// None for fields that aren't checked.
+    pub valid: Option<bool>,
+}
+
+/// How a ROM's length compares to the size its header declares.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeCheck {
+    /// Exactly the declared size.
+    Matches,
+    /// Longer than declared by this many bytes, as from a bad dump or leftover padding.
+    Overdump(usize),
+    /// Shorter than declared by this many bytes.
+    Truncated(usize),
+    /// The ROM size byte isn't one the table has.
+    UnknownIndicator(u8),
+}
+
+impl fmt::Display for SizeCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SizeCheck::Matches => write!(f, "OK"),
+            SizeCheck::Overdump(extra) => write!(f, "overdump, {} bytes past the declared size", extra),
+            SizeCheck::Truncated(missing) => write!(f, "truncated, {} bytes short of the declared size", missing),
+            SizeCheck::UnknownIndicator(byte) => write!(f, "unknown size indicator 0x{:02X}", byte),
+        }
+    }
+}
+
+/// The cartridge RAM a header declares at 0x0149.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RamSize {
+    /// No RAM.
+    None,
+    /// The 512 half-bytes built into an MBC2, which declares none.
+    Mbc2,
+    /// Banks of 8KiB RAM.
+    Banked {
+        /// The total size in bytes.
+        bytes: usize,
+        /// How many banks that is.
+        banks: usize,
+    },
+    /// A size byte that isn't in the table, including the unused 0x01.
+    Invalid(u8),
 }
 
+impl RamSize {
+    /// Decodes the size byte, for a cartridge of `cart_type`.
+    pub fn new(byte: u8, cart_type: CartridgeType) -> Self {
+        let banked = |bytes: usize| RamSize::Banked { bytes, banks: bytes / (8 * 1024) };
+        match byte {
+            0x00 if cart_type.mbc_kind() == Some(MbcKind::Mbc2) => RamSize::Mbc2,
+            0x00 => RamSize::None,
+            0x02 => banked(8 * 1024),
+            0x03 => banked(32 * 1024),
+            0x04 => banked(128 * 1024),
+            0x05 => banked(64 * 1024),
+            x => RamSize::Invalid(x),
+        }
+    }
+
+    /// The size in bytes.  The MBC2's RAM is 512 bytes, of which only the low halves work.
+    pub fn bytes(self) -> usize {
+        match self {
+            RamSize::Mbc2 => 512,
+            RamSize::Banked { bytes, .. } => bytes,
+            RamSize::None | RamSize::Invalid(_) => 0,
+        }
+    }
+
+    /// How many banks there are.
+    pub fn banks(self) -> usize {
+        match self {
+            RamSize::Mbc2 => 1,
+            RamSize::Banked { banks, .. } => banks,
+            RamSize::None | RamSize::Invalid(_) => 0,
+        }
+    }
+}
+
+impl fmt::Display for RamSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RamSize::None => write!(f, "none"),
+            RamSize::Mbc2 => write!(f, "512 half-bytes built into the MBC2"),
+            RamSize::Banked { bytes, banks } => {
+                let plural = if banks == 1 { "" } else { "s" };
+                write!(f, "{} ({} bank{} of {})", human_size(bytes), banks, plural, human_size(bytes / banks))
+            },
+            RamSize::Invalid(byte) => write!(f, "unknown size indicator 0x{:02X}", byte),
+        }
+    }
+}
+
+// Sizes in KiB, or MiB when they're whole.
+fn human_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 && bytes.is_multiple_of(1024 * 1024) {
+        format!("{} MiB", bytes / (1024 * 1024))
+    } else {
+        format!("{} KiB", bytes / 1024)
+    }
+}
+
+/// The cartridge header at 0x0100-0x014F, borrowed from the ROM it was parsed from or owned
+/// after `into_owned`.
 pub struct GameboyProgramMeta<'a> {
-    pub name: &'a str,  // On newer games the name is clamped to 9 chars.  Extra space is used for manufacturer code.
-    pub manufacturer_code: &'a [u8],
-    pub licensee_code: Vec<u8>,  // Newer games are 0x0144-0x0145.  Older games are 0x14B
+    /// On newer games the name is clamped to 9 chars.  Extra space is used for manufacturer code.
+    pub name: Cow<'a, str>,
+    /// 0x013F-0x0142, overlapping the end of the title.
+    pub manufacturer_code: Cow<'a, [u8]>,
+    /// Newer games are 0x0144-0x0145.  Older games are 0x14B.
+    pub licensee_code: Vec<u8>,
+    licensee: LicenseeCode,
     color_flag: GameboyColorFlag, // 0x80 = Backwards compatible with non-CGB, 0xC0 = CGB only.
     super_gameboy_flag: SuperGameboyFeatureFlag, // 0x00 = no SGB, 0x03 = SGB
-    cart_type: u8, // 0x0147, Cartridge Type.  Indicates extra hardware on cartridge.
+    cart_type: CartridgeType, // 0x0147.  Indicates extra hardware on cartridge.
     rom_size: u8,  // Rom size uses this through a translation table times 32k
     ram_size: u8,  // Again uses a translation table.  Size of cold storage on cartridge
     region_code: GameboyRegionCode, // 0x00 = japanese, 0x01 = non-japanese.
@@ -98,21 +517,26 @@ pub struct GameboyProgramMeta<'a> {
 
     header_checksum_calculated: u8,
     global_checksum_calculated: u16,
-    logo_bitmap: &'a [u8],
+    logo_bitmap: Cow<'a, [u8]>,
+    /// The length of the whole ROM in bytes.
     pub program_size: usize,
 }
 
-fn bufstr(buf: &[u8]) -> Result<&str, Box<Error>> {
-    let first_zero = buf.into_iter().enumerate().find(|(_idx, &x)| x == 0).map(|(idx, _)| idx);
+fn bufstr(buf: &[u8]) -> Result<&str, FaroreError> {
+    let first_zero = buf.iter().enumerate().find(|(_idx, &x)| x == 0).map(|(idx, _)| idx);
     let chars = match first_zero {
         Some(i) => &buf[0..i],
         None => buf,
     };
-    ::std::str::from_utf8(chars).map_err(|e| e.into())
+    ::std::str::from_utf8(chars).map_err(|e| FaroreError::InvalidHeaderField { field: "title", reason: e.to_string() })
 }
 
 impl<'a> GameboyProgramMeta<'a> {
-    pub fn new(rom: &[u8]) -> Result<GameboyProgramMeta, Box<Error>> {
+    /// Parses the header.  Fails if the ROM is too short to hold one or the title isn't UTF-8.
+    pub fn new(rom: &[u8]) -> Result<GameboyProgramMeta<'_>, FaroreError> {
+        if rom.len() < HEADER_END {
+            return Err(FaroreError::RomTooShort(rom.len()));
+        }
 
         // older carts have a licensee code at 0x014B, but newer carts reserve 2 bytes for it at
         // 0x0144 and set the old licensee code to 0x33 to indicate the newer licensee code form.
@@ -127,12 +551,13 @@ impl<'a> GameboyProgramMeta<'a> {
 
 
         Ok(GameboyProgramMeta {
-            name: bufstr(&rom[0x0134..0x0143])?,
-            manufacturer_code: &rom[0x13F..0x143],
+            name: Cow::Borrowed(bufstr(&rom[0x0134..0x0143])?),
+            manufacturer_code: Cow::Borrowed(&rom[0x13F..0x143]),
             licensee_code: l_code,
+            licensee: LicenseeCode::from_header(rom),
             color_flag: GameboyColorFlag::new(rom[0x0143]),
             super_gameboy_flag: SuperGameboyFeatureFlag::new(rom[0x0146]),
-            cart_type: rom[0x0147],
+            cart_type: CartridgeType::new(rom[0x0147]),
             rom_size: rom[0x0148],
             ram_size: rom[0x0149],
             region_code: GameboyRegionCode::new(rom[0x014A]),
@@ -140,26 +565,53 @@ impl<'a> GameboyProgramMeta<'a> {
             header_checksum: rom[0x014D],
             global_checksum: BigEndian::read_u16(&rom[0x14E..0x150]),
 
-            header_checksum_calculated: calculate_header_checksum(&rom),
-            global_checksum_calculated: calculate_global_checksum(&rom),
-            logo_bitmap: logo,
+            header_checksum_calculated: calculate_header_checksum(rom),
+            global_checksum_calculated: calculate_global_checksum(rom),
+            logo_bitmap: Cow::Borrowed(logo),
             program_size: rom.len(),
         })
     }
 
-    pub fn is_valid_logo(&self) -> bool {
-        if self.logo_bitmap.len() != 48 {
-            return false;
+    /// Parses the header of a ROM that's about to be dropped, keeping copies of the few
+    /// fields that borrow from it.
+    pub fn from_vec(rom: Vec<u8>) -> Result<GameboyProgramMeta<'static>, FaroreError> {
+        GameboyProgramMeta::new(&rom).map(GameboyProgramMeta::into_owned)
+    }
+
+    /// Copies the borrowed fields, so the header can outlive the ROM.
+    pub fn into_owned(self) -> GameboyProgramMeta<'static> {
+        GameboyProgramMeta {
+            name: Cow::Owned(self.name.into_owned()),
+            manufacturer_code: Cow::Owned(self.manufacturer_code.into_owned()),
+            logo_bitmap: Cow::Owned(self.logo_bitmap.into_owned()),
+            licensee_code: self.licensee_code,
+            licensee: self.licensee,
+            color_flag: self.color_flag,
+            super_gameboy_flag: self.super_gameboy_flag,
+            cart_type: self.cart_type,
+            rom_size: self.rom_size,
+            ram_size: self.ram_size,
+            region_code: self.region_code,
+            program_version_number: self.program_version_number,
+            header_checksum: self.header_checksum,
+            global_checksum: self.global_checksum,
+            header_checksum_calculated: self.header_checksum_calculated,
+            global_checksum_calculated: self.global_checksum_calculated,
+            program_size: self.program_size,
         }
+    }
 
-        let digest = sha1::Sha1::from(self.logo_bitmap).digest().bytes();
-        digest.iter().zip(LOGO_BITMAP_HASH.iter()).all(|(&a, &b)| a == b)
+    /// Whether the logo matches the one the boot ROM checks, byte for byte.
+    pub fn is_valid_logo(&self) -> bool {
+        self.logo_bitmap[..] == NINTENDO_LOGO[..]
     }
 
+    /// Whether the header checksum at 0x014D matches.
     pub fn is_valid_header(&self) -> bool {
         self.header_checksum == self.header_checksum_calculated
     }
 
+    /// Whether the global checksum at 0x014E-0x014F matches.
     pub fn is_valid_program(&self) -> bool {
         // This checks the global_checksum against the rest of the file.  A failure does not
         // mean that the program will not execute, just that it doesn't match the advertised
@@ -167,6 +619,7 @@ impl<'a> GameboyProgramMeta<'a> {
         self.global_checksum == self.global_checksum_calculated
     }
 
+    /// Whether real hardware would boot it.
     pub fn is_runable(&self) -> bool {
         // The gameboy has a place on the rom for a full program checksum, but does not
         // validate the checksum, instead opting to ignore it.  Thus a runnable rom only needs to
@@ -174,26 +627,96 @@ impl<'a> GameboyProgramMeta<'a> {
         self.is_valid_header() && self.is_valid_logo()
     }
 
-    // pub fn declared_size(&self) -> usize {
-    //     match self.cartridge_size_indicator {
-    //         0x00 => 32 * 1024,
-    //         0x01 => 64 * 1024,
-    //         0x0
-    //     }
-    // }
+    /// Whether the CGB flag asks for color, either exclusively or alongside DMG support.
+    pub fn supports_cgb(&self) -> bool {
+        matches!(self.color_flag, GameboyColorFlag::BackwardsCompatible | GameboyColorFlag::GBCOnly)
+    }
 
-    pub fn print_debug(&self, writer: &mut Write) {
+    /// Whether the CGB flag marks the game as CGB only.
+    pub fn is_cgb_only(&self) -> bool {
+        matches!(self.color_flag, GameboyColorFlag::GBCOnly)
+    }
+
+    /// The publisher, going by whichever licensee code the header uses.
+    pub fn licensee_name(&self) -> Licensee {
+        self.licensee.lookup()
+    }
+
+    /// The cartridge type at 0x0147, naming the MBC and extra hardware.
+    pub fn cart_type(&self) -> CartridgeType {
+        self.cart_type
+    }
+
+    /// The ROM size the header declares, if the size code is a known one.
+    pub fn declared_size(&self) -> Option<usize> {
+        match self.rom_size {
+            0x00..=0x08 => Some((32 * 1024) << self.rom_size),
+            0x52 => Some(72 * 16 * 1024),
+            0x53 => Some(80 * 16 * 1024),
+            0x54 => Some(96 * 16 * 1024),
+            _ => None,
+        }
+    }
+
+    /// The cartridge RAM the header declares, taking the MBC2's built-in RAM into account.
+    pub fn declared_ram_size(&self) -> RamSize {
+        RamSize::new(self.ram_size, self.cart_type)
+    }
+
+    /// Whether the RAM size agrees with the cartridge type: RAM is declared exactly when the
+    /// type has some.  The MBC7 keeps its EEPROM out of the header, so anything goes there.
+    pub fn is_valid_ram_size(&self) -> bool {
+        match (self.declared_ram_size(), self.cart_type.mbc_kind()) {
+            (RamSize::Invalid(_), _) => false,
+            (_, Some(MbcKind::Mbc7)) => true,
+            (RamSize::Mbc2, _) => true,
+            (RamSize::None, _) => !self.cart_type.has_ram(),
+            (RamSize::Banked { .. }, Some(MbcKind::Mbc2)) => false,
+            (RamSize::Banked { .. }, _) => self.cart_type.has_ram(),
+        }
+    }
+
+    /// How many 16KiB banks the header declares.
+    pub fn declared_rom_banks(&self) -> Option<usize> {
+        self.declared_size().map(|size| size / BANK_SIZE)
+    }
+
+    /// Whether the ROM is exactly the size the header declares.
+    pub fn is_valid_size(&self) -> bool {
+        self.size_check() == SizeCheck::Matches
+    }
+
+    /// Compares the ROM's length to the declared size.
+    pub fn size_check(&self) -> SizeCheck {
+        match self.declared_size() {
+            None => SizeCheck::UnknownIndicator(self.rom_size),
+            Some(declared) if self.program_size > declared => SizeCheck::Overdump(self.program_size - declared),
+            Some(declared) if self.program_size < declared => SizeCheck::Truncated(declared - self.program_size),
+            Some(_) => SizeCheck::Matches,
+        }
+    }
+
+    /// Writes every field and check as human readable lines.
+    pub fn print_debug(&self, writer: &mut dyn Write) {
         let test = |x| -> &str {if x {"OK"} else {"FAILED"}};
 
         writeln!(writer, "name: {}", self.name).ok();
         writeln!(writer, "size: {}", self.program_size).ok();
         writeln!(writer, "manufacturer code: {:?}", self.manufacturer_code).ok();
-        writeln!(writer, "licensee code: {:?}", self.licensee_code).ok();
+        match self.licensee_name() {
+            Licensee::Known(name) => writeln!(writer, "licensee: {} ({})", name, self.licensee).ok(),
+            unknown => writeln!(writer, "licensee: {}", unknown).ok(),
+        };
         writeln!(writer, "color flag: {:?}", self.color_flag).ok();
         writeln!(writer, "super flag: {:?}", self.super_gameboy_flag).ok();
-        writeln!(writer, "cart type: {:?}", self.cart_type).ok();
-        writeln!(writer, "ROM size indicator: {:?}", self.rom_size).ok();
-        writeln!(writer, "RAM size indiciator: {:?}", self.ram_size).ok();
+        writeln!(writer, "cart type: {}", self.cart_type).ok();
+        match (self.declared_size(), self.declared_rom_banks()) {
+            (Some(size), Some(banks)) => {
+                writeln!(writer, "ROM size: 0x{:02X}, {} ({} banks)", self.rom_size, human_size(size), banks).ok()
+            },
+            _ => writeln!(writer, "ROM size: 0x{:02X}, unknown", self.rom_size).ok(),
+        };
+        writeln!(writer, "RAM size: 0x{:02X}, {}", self.ram_size, self.declared_ram_size()).ok();
         writeln!(writer, "region code: {:?}", self.region_code).ok();
         writeln!(writer, "version number: {:?}", self.program_version_number).ok();
         writeln!(writer, "header checksum: Declared({0:?}) Calculated({1:?})", self.header_checksum, self.header_checksum_calculated).ok();
@@ -201,6 +724,72 @@ impl<'a> GameboyProgramMeta<'a> {
         writeln!(writer, "logo test: {}", test(self.is_valid_logo())).ok();
         writeln!(writer, "header test: {}", test(self.is_valid_header())).ok();
         writeln!(writer, "program test: {}", test(self.is_valid_program())).ok();
+        writeln!(writer, "size test: {}", self.size_check()).ok();
+        writeln!(writer, "RAM size test: {}", test(self.is_valid_ram_size())).ok();
         writeln!(writer, "runable test: {}", test(self.is_runable())).ok();
     }
+
+    /// Every header field from 0x0100 to 0x014F, in address order.
+    pub fn fields(&self) -> Vec<HeaderField> {
+        let field = |name, range, valid| HeaderField { name, range, valid };
+        vec![
+            field("entry point", 0x0100..0x0104, None),
+            field("logo", 0x0104..0x0134, Some(self.is_valid_logo())),
+            field("title", 0x0134..0x013F, None),
+            field("manufacturer code", 0x013F..0x0143, None),
+            field("CGB flag", 0x0143..0x0144, None),
+            field("new licensee code", 0x0144..0x0146, None),
+            field("SGB flag", 0x0146..0x0147, None),
+            field("cart type", 0x0147..0x0148, None),
+            field("ROM size", 0x0148..0x0149, Some(self.is_valid_size())),
+            field("RAM size", 0x0149..0x014A, Some(self.is_valid_ram_size())),
+            field("region", 0x014A..0x014B, None),
+            field("old licensee code", 0x014B..0x014C, None),
+            field("version", 0x014C..0x014D, None),
+            field("header checksum", 0x014D..0x014E, Some(self.is_valid_header())),
+            field("global checksum", 0x014E..0x0150, Some(self.is_valid_program())),
+        ]
+    }
+
+    /// Every field and check, the same as `print_debug` shows.
+    pub fn to_json(&self) -> Json {
+        let number_array = |bytes: &[u8]| Json::Array(bytes.iter().map(|&x| Json::Number(x as i64)).collect());
+        let checksum = |declared: u16, calculated: u16| Json::object(vec![
+            ("declared", Json::Number(declared as i64)),
+            ("calculated", Json::Number(calculated as i64)),
+        ]);
+
+        Json::object(vec![
+            ("name", Json::string(&self.name)),
+            ("size", Json::Number(self.program_size as i64)),
+            ("manufacturer_code", number_array(&self.manufacturer_code)),
+            ("licensee_code", number_array(&self.licensee_code)),
+            ("licensee", Json::String(self.licensee_name().to_string())),
+            ("color_flag", Json::String(format!("{:?}", self.color_flag))),
+            ("super_flag", Json::String(format!("{:?}", self.super_gameboy_flag))),
+            ("cart_type", Json::Number(self.cart_type.byte() as i64)),
+            ("cart_type_name", Json::String(self.cart_type.to_string())),
+            ("rom_size", Json::Number(self.rom_size as i64)),
+            ("declared_size", self.declared_size().map_or(Json::Null, |size| Json::Number(size as i64))),
+            ("ram_size", Json::Number(self.ram_size as i64)),
+            ("declared_ram_size", Json::Number(self.declared_ram_size().bytes() as i64)),
+            ("region_code", Json::String(format!("{:?}", self.region_code))),
+            ("version", Json::Number(self.program_version_number as i64)),
+            ("header_checksum", checksum(self.header_checksum as u16, self.header_checksum_calculated as u16)),
+            ("global_checksum", checksum(self.global_checksum, self.global_checksum_calculated)),
+            ("validation", self.validation_json()),
+        ])
+    }
+
+    /// Just the checks, keyed by name.
+    pub fn validation_json(&self) -> Json {
+        Json::object(vec![
+            ("logo", Json::Bool(self.is_valid_logo())),
+            ("header_checksum", Json::Bool(self.is_valid_header())),
+            ("global_checksum", Json::Bool(self.is_valid_program())),
+            ("size", Json::Bool(self.is_valid_size())),
+            ("ram_size", Json::Bool(self.is_valid_ram_size())),
+            ("runnable", Json::Bool(self.is_runable())),
+        ])
+    }
 }
@@ -0,0 +1,193 @@
+//! Cheat codes
+//!
+//! Game Genie codes patch ROM: ABC-DEF-GHI, where
+//!   AB      The new value
+//!   FCDE    The address, with F XORed by F
+//!   GI      The value to compare against, XORed by BA and rotated left by two
+//!   H       Unused
+//! Six digit codes leave off GHI and patch the address whatever it holds.  The patch applies
+//! to reads from the CPU's view of 0000-7FFF, so it hits whichever bank is mapped there.
+//!
+//! GameShark codes poke RAM once a frame: TTVVAAAA, where
+//!   TT      The code type: 01 for any bank, 8X for bank X only
+//!   VV      The value to write
+//!   AAAA    The address, low byte first, in A000-DFFF
+//! The bank is the external RAM bank for A000-BFFF, and the work RAM bank for C000-DFFF.
+
+use std::fmt;
+
+use error::FaroreError;
+
+
+/// What a cheat changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Patch {
+    /// Reads of a ROM address return `value`, if the ROM holds `compare` there or there's
+    /// nothing to compare.
+    Rom {
+        /// The CPU address, 0000-7FFF.
+        address: u16,
+        /// What reads return instead.
+        value: u8,
+        /// The byte the ROM must hold for the patch to apply.
+        compare: Option<u8>,
+    },
+    /// `value` is written to a RAM address every frame, while `bank` is mapped there.
+    Ram {
+        /// The RAM bank, or `None` for whichever is mapped.
+        bank: Option<u8>,
+        /// The CPU address, A000-DFFF.
+        address: u16,
+        /// What gets written.
+        value: u8,
+    },
+}
+
+/// A parsed cheat code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    code: String, // As given, upper cased
+    patch: Patch,
+}
+
+impl Cheat {
+    /// Parses a Game Genie code, with or without its dashes, or a GameShark code.  The error
+    /// says which digit is wrong.
+    pub fn parse(code: &str) -> Result<Self, FaroreError> {
+        let code = code.trim().to_ascii_uppercase();
+        let invalid = |reason: String| FaroreError::InvalidArgument(format!("invalid cheat {}: {}", code, reason));
+
+        let mut digits = Vec::with_capacity(9);
+        for c in code.chars().filter(|&c| c != '-') {
+            match c.to_digit(16) {
+                Some(digit) => digits.push(digit as u8),
+                None => return Err(invalid(format!("digit {} ('{}') isn't hex", digits.len() + 1, c))),
+            }
+        }
+        let patch = match digits.len() {
+            6 | 9 => game_genie(&digits),
+            8 if code.contains('-') => Err("GameShark codes don't have dashes".to_string()),
+            8 => game_shark(&digits),
+            count => Err(format!("Game Genie codes are 6 or 9 digits and GameShark codes 8, not {}", count)),
+        };
+        match patch {
+            Ok(patch) => Ok(Cheat { code, patch }),
+            Err(reason) => Err(invalid(reason)),
+        }
+    }
+
+    /// The code as it was given.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// What the code changes.
+    pub fn patch(&self) -> Patch {
+        self.patch
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+// ABC-DEF-GHI, without the dashes.
+fn game_genie(digits: &[u8]) -> Result<Patch, String> {
+    let value = digits[0] << 4 | digits[1];
+    let top = digits[5] ^ 0xF;
+    if top >= 0x8 {
+        return Err("digit 6 puts the address outside the ROM".to_string());
+    }
+    let address = u16::from(top) << 12 | u16::from(digits[2]) << 8 | u16::from(digits[3]) << 4 | u16::from(digits[4]);
+    let compare = if digits.len() == 9 {
+        Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA)
+    } else {
+        None
+    };
+    Ok(Patch::Rom { address, value, compare })
+}
+
+// TTVVAAAA.
+fn game_shark(digits: &[u8]) -> Result<Patch, String> {
+    let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+    let bank = match byte(0) {
+        0x01 => None,
+        kind @ 0x80..=0x8F => Some(kind & 0x0F),
+        kind => return Err(format!("digits 1-2, {:02X}, aren't a known code type", kind)),
+    };
+    let address = u16::from(byte(6)) << 8 | u16::from(byte(4));
+    if !(0xA000..=0xDFFF).contains(&address) {
+        return Err(format!("digits 5-8 put the address, {:04X}, outside cartridge and work RAM", address));
+    }
+    Ok(Patch::Ram { bank, address, value: byte(2) })
+}
+
+/// The cheats loaded into a machine, each of which can be switched off without removing it.
+#[derive(Debug, Clone, Default)]
+pub struct CheatList {
+    cheats: Vec<(Cheat, bool)>,
+}
+
+impl CheatList {
+    /// No cheats.
+    pub fn new() -> Self {
+        CheatList { cheats: Vec::new() }
+    }
+
+    /// Adds an enabled cheat, returning its index.
+    pub fn add(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push((cheat, true));
+        self.cheats.len() - 1
+    }
+
+    /// Removes the cheat at `index`.  Later cheats move down one.
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        if index < self.cheats.len() {
+            Some(self.cheats.remove(index).0)
+        } else {
+            None
+        }
+    }
+
+    /// Each cheat and whether it's enabled, in the order they were added.
+    pub fn list(&self) -> impl Iterator<Item = (&Cheat, bool)> {
+        self.cheats.iter().map(|(cheat, enabled)| (cheat, *enabled))
+    }
+
+    /// Switches the cheat at `index` on or off.  False if there's no such cheat.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.cheats.get_mut(index) {
+            Some(entry) => {
+                entry.1 = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// What the CPU sees when reading `original` from a ROM address.  The first enabled cheat
+    /// that matches wins.
+    pub fn patch_rom_read(&self, address: u16, original: u8) -> u8 {
+        for (cheat, enabled) in self.list() {
+            if !enabled {
+                continue;
+            }
+            if let Patch::Rom { address: target, value, compare } = cheat.patch {
+                if target == address && compare.is_none_or(|compare| compare == original) {
+                    return value;
+                }
+            }
+        }
+        original
+    }
+
+    /// The RAM writes the enabled GameShark codes make each frame, as (bank, address, value).
+    pub fn ram_writes(&self) -> impl Iterator<Item = (Option<u8>, u16, u8)> + '_ {
+        self.list().filter(|&(_, enabled)| enabled).filter_map(|(cheat, _)| match cheat.patch {
+            Patch::Ram { bank, address, value } => Some((bank, address, value)),
+            Patch::Rom { .. } => None,
+        })
+    }
+}
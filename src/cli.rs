@@ -0,0 +1,1254 @@
+//! Command line parsing
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use farore::apu::log::WriteLogFormat;
+use farore::apu::mixer::ChannelMask;
+use farore::bench::DEFAULT_WARMUP_FRAMES;
+use farore::cart::Repairs;
+use farore::cheat::Cheat;
+use farore::error::FaroreError;
+use farore::json::Json;
+use farore::logging::Level;
+use farore::palette::DisplayPalette;
+use farore::ppu::Renderer;
+
+
+pub const USAGE: &str = "\
+usage: farore <command> <rom> [options]
+       farore --help
+
+options for every command:
+  -q                                   Only print errors
+  -v, -vv                              Print more detail about what's going on
+  --entry NAME                         Pick the rom to load from a zip holding several
+  --config PATH                        Read settings from PATH instead of the default config
+
+commands:
+  info <rom> [--json] [--list]         Print the cartridge header, or list a zip's files
+  header <rom>                         Hexdump the header with each field labeled
+  validate <rom> [--strict] [--json]   Check the logo, header and program checksums
+  validate <dir> --recursive [--failed-only] [--strict] [--json]
+                                       Check every .gb and .gbc file under a directory
+  run <rom> [--frames N] [--headless [--print-hash] [--print-hash-every N] [--dump-oam]]
+      [--bootrom PATH | --skip-boot] [--cheat CODE]...
+      [--screenshot PATH [--at-frame N]] [--screenshot-every N --screenshot-dir DIR]
+      [--dump-tiles PATH] [--dump-tilemap PATH] [--renderer scanline|fifo]
+      [--palette NAME|COLORS] [--ghosting F] [--wav PATH]
+      [--mute-channels LIST] [--apu-log PATH] [--serial-stdout]
+      [--record-movie PATH | --play-movie PATH]
+      [--record PATH [--record-frames N] [--record-skip N]]
+      [--load-state PATH [--force]] [--save-state-at-frame N --state-file PATH]
+      [--script PATH]
+                                       Run the ROM, with Game Genie codes like 00A-17B-C49
+                                       or GameShark codes like 010238CD.  Headless runs can
+                                       print the frame hash every N frames and after the
+                                       last one, and the OAM table after the last one, which
+                                       need --frames.  --skip-boot starts
+                                       at the cartridge even when a boot rom is configured.
+                                       --screenshot saves frame N or the last frame as a
+                                       PNG, and --screenshot-every saves
+                                       every Nth frame into DIR.  --dump-tiles and
+                                       --dump-tilemap save the VRAM tile sheet and the
+                                       background map after the last frame.  The fifo
+                                       renderer is slower but gets mid-line effects right.
+                                       --palette is grayscale, dmg-green, pocket or four
+                                       #rrggbb colors, lightest first, for DMG output.
+                                       --ghosting keeps F (0 to 1) of each frame in the
+                                       next, like the DMG's slow LCD.  --wav records the
+                                       audio to a 16 bit stereo WAV file, up to the last frame.
+                                       --mute-channels silences channels like 1,4 in the
+                                       output without the game noticing.  --apu-log writes
+                                       every sound register write up to the last frame, as
+                                       VGM if PATH ends in .vgm and CSV otherwise.
+                                       --serial-stdout prints what the game sends over the
+                                       link cable, like blargg's test results.  Movies hold
+                                       the buttons of every frame, for replaying a run
+                                       exactly.  --record saves an animated GIF of every
+                                       Nth frame, up to --record-frames frames.
+                                       --load-state starts from a save state, refusing one
+                                       of another ROM without --force, and
+                                       --save-state-at-frame saves one after frame N.
+                                       --script runs the hooks in PATH between frames, and a
+                                       failed assert fails the run
+  play <rom> [--scale N] [--pause-on-focus-loss]
+                                       Play the ROM in a window scaled N times (default from
+                                       the config), with the keys from the config file
+  bench <rom> [--seconds N | --frames N] [--warmup N] [--json]
+                                       Run as fast as possible with no output for N seconds
+                                       (default 10) of host time or N frames, and report the
+                                       speed.  The first N frames (default 60) aren't measured
+  dump <rom> --range START..END        Hexdump part of the ROM, addresses in hex or decimal
+  fix <rom> (-o OUT | --in-place) [--logo] [--checksums] [--pad]
+                                       Write a repaired copy, applying every repair if none
+                                       are picked
+  patch <rom> <patch> -o OUT [--fix-checksums] [--force]
+                                       Apply an IPS or BPS patch, then repair the checksums if
+                                       asked, as most hacks break them.  --force applies a BPS
+                                       patch to a rom with the wrong CRC32
+  rom pad <rom> [--to declared|SIZE] [--fill BYTE] (-o OUT | --in-place) [--fix-checksums]
+                                       Pad to the declared size or SIZE, with 0xFF by default
+  rom trim <rom> [--force] (-o OUT | --in-place) [--fix-checksums]
+                                       Cut the trailing padding, down to a whole bank.  --force
+                                       allows going below the declared size
+  rom split <rom> [--banks N] [-o PREFIX] [--fix-checksums]
+                                       Write every N banks (default 1) to PREFIX-00.bin,
+                                       PREFIX-01.bin and so on, PREFIX being the rom's name
+  disasm <rom> [--bank N] [--range START..END] [--follow] [--budget N] [--sym FILE]
+         [--coverage FILE]
+                                       Disassemble 0000-7FFF with bank N (default 1) mapped
+                                       at 4000.  --follow traces the code reachable from the
+                                       range start, or from the 0100 entry point.  Labels come
+                                       from --sym or a .sym file next to the rom, and with
+                                       --coverage only code that ran is decoded
+  debug <rom> [--sym FILE]             Debug the ROM at a prompt, reading the commands from
+                                       stdin.  Labels come from --sym or a .sym file next to
+                                       the rom.  Type help for the commands
+  config [--print-default]             Show the settings from the config file, or print a
+                                       commented template to start one from
+
+<rom> can be - to read the ROM from stdin.  ROMs in gzip and zip files are unpacked.  With
+--json, reports and errors are written to stdout as JSON and any other messages go to stderr.";
+
+pub const EXIT_CODES: &str = "\
+exit codes:
+  0  Success, and for validate the ROM would boot
+  1  Usage error
+  2  The ROM couldn't be opened or read
+  3  The ROM header couldn't be parsed
+  4  Validation failed, the ROM wouldn't boot
+  5  Validation warnings, with --strict";
+
+pub const EXIT_USAGE: i32 = 1;
+pub const EXIT_IO: i32 = 2;
+pub const EXIT_HEADER: i32 = 3;
+pub const EXIT_INVALID: i32 = 4;
+pub const EXIT_WARNINGS: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Info { rom: String, json: bool, list: bool },
+    Header { rom: String },
+    Validate { rom: String, strict: bool, recursive: bool, failed_only: bool, json: bool },
+    Run { rom: String, options: Box<RunOptions> },
+    Play { rom: String, scale: Option<u32>, pause_on_focus_loss: bool },
+    Bench { rom: String, length: BenchLength, warmup: u64, json: bool },
+    Dump { rom: String, range: Range<usize> },
+    Fix { rom: String, output: Option<String>, repairs: Repairs },
+    Patch { rom: String, patch: String, output: String, fix_checksums: bool, force: bool },
+    Rom { rom: String, action: RomAction, output: Option<String>, fix_checksums: bool },
+    Disasm {
+        rom: String,
+        bank: Option<usize>,
+        range: Option<Range<usize>>,
+        follow: bool,
+        budget: usize,
+        sym: Option<String>,
+        coverage: Option<String>,
+    },
+    Debug { rom: String, sym: Option<String> },
+    Config { print_default: bool },
+}
+
+/// How long `bench` runs for, warm-up included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchLength {
+    Seconds(u32), // Of host time
+    Frames(u64),
+}
+
+/// What `rom` does to the ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomAction {
+    Pad { size: Option<usize>, fill: u8 }, // No size means the declared size
+    Trim { force: bool },
+    Split { banks: usize },
+}
+
+/// How `run` runs the ROM.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+    pub frames: Option<u32>,
+    pub headless: Option<Headless>,
+    pub bootrom: Option<String>,
+    pub skip_boot: bool, // Even when the config names a boot rom
+    pub cheats: Vec<Cheat>,
+    pub screenshot: Option<String>,
+    pub at_frame: Option<u32>, // Which frame the screenshot is of, rather than the last
+    pub screenshot_every: Option<(u32, String)>, // The interval and the directory
+    pub dump_tiles: Option<String>,
+    pub dump_tilemap: Option<String>,
+    pub renderer: Option<Renderer>, // None keeps the default
+    pub palette: Option<DisplayPalette>, // Overrides the config's
+    pub ghosting: Option<f32>,
+    pub wav: Option<String>,
+    pub channel_mask: ChannelMask, // Which channels are heard
+    pub apu_log: Option<(String, WriteLogFormat)>,
+    pub serial_stdout: bool,
+    pub record_movie: Option<String>,
+    pub play_movie: Option<String>,
+    pub record: Option<Recording>,
+    pub load_state: Option<String>,
+    pub force: bool, // Load a state taken with another ROM
+    pub save_state: Option<(u32, String)>, // The frame and the file
+    pub script: Option<String>,
+}
+
+/// What `run --record` puts in the GIF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub path: String,
+    pub frames: Option<u32>, // A limit on the frames in the GIF
+    pub skip: u64,           // Keep every Nth frame
+}
+
+/// What `run --headless` prints, for checking the emulation against known frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Headless {
+    pub print_hash: bool, // The last frame's hash
+    pub print_hash_every: Option<u32>,
+    pub dump_oam: bool, // After the last frame
+}
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub command: Command,
+    pub log_level: Level,
+    pub entry: Option<String>, // Which file to load from an archive
+    pub config: Option<String>, // Overrides the config file location
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    NoCommand,
+    UnknownCommand(String),
+    MissingRom(&'static str),
+    MissingValue(String),
+    BadValue(String, String),
+    UnexpectedArgument(String),
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::NoCommand => write!(f, "no command given"),
+            CliError::UnknownCommand(ref command) => write!(f, "unknown command \"{}\"", command),
+            CliError::MissingRom(command) => write!(f, "{} requires a rom", command),
+            CliError::MissingValue(ref option) => write!(f, "{} requires a value", option),
+            CliError::BadValue(ref option, ref value) => {
+                write!(f, "invalid value \"{}\" for {}", value, option)
+            },
+            CliError::UnexpectedArgument(ref arg) => write!(f, "unexpected argument \"{}\"", arg),
+            CliError::Invalid(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+/// Why a command failed, which decides the exit code.
+#[derive(Debug)]
+pub enum Failure {
+    Usage(String),
+    Io(String),
+    Header(String),
+    Invalid(String),
+    Warnings(String),
+}
+
+impl Failure {
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Failure::Usage(_) => "Usage",
+            Failure::Io(_) => "Io",
+            Failure::Header(_) => "Header",
+            Failure::Invalid(_) => "Invalid",
+            Failure::Warnings(_) => "Warnings",
+        }
+    }
+
+    /// The error as the JSON object printed with --json, like
+    /// `{"error": {"kind": "Io", "message": "..."}}`.
+    pub fn to_json(&self) -> Json {
+        let error = Json::object(vec![("kind", Json::string(self.kind())), ("message", Json::String(self.to_string()))]);
+        Json::object(vec![("error", error)])
+    }
+
+    /// Puts what was being done in front of the message, like "unable to unpack x.zip: ...".
+    pub fn context(self, context: &str) -> Self {
+        let wrap = |message| format!("{}: {}", context, message);
+        match self {
+            Failure::Usage(message) => Failure::Usage(wrap(message)),
+            Failure::Io(message) => Failure::Io(wrap(message)),
+            Failure::Header(message) => Failure::Header(wrap(message)),
+            Failure::Invalid(message) => Failure::Invalid(wrap(message)),
+            Failure::Warnings(message) => Failure::Warnings(wrap(message)),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Io(_) => EXIT_IO,
+            Failure::Header(_) => EXIT_HEADER,
+            Failure::Invalid(_) => EXIT_INVALID,
+            Failure::Warnings(_) => EXIT_WARNINGS,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Usage(ref message) |
+            Failure::Io(ref message) |
+            Failure::Header(ref message) |
+            Failure::Invalid(ref message) |
+            Failure::Warnings(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<FaroreError> for Failure {
+    fn from(error: FaroreError) -> Self {
+        let message = describe(&error);
+        match error {
+            FaroreError::Io { .. } | FaroreError::Archive(_) | FaroreError::Patch(_) => Failure::Io(message),
+            FaroreError::RamSizeMismatch { .. } | FaroreError::SaveStateVersion { .. } => Failure::Io(message),
+            FaroreError::RomTooShort(_) | FaroreError::InvalidHeaderField { .. } => Failure::Header(message),
+            FaroreError::UnsupportedMapper(_) => Failure::Header(message),
+            FaroreError::RomMismatch { .. } | FaroreError::InvalidArgument(_) => Failure::Usage(message),
+        }
+    }
+}
+
+/// An error followed by each of its causes, joined like "unable to access x: permission denied".
+pub fn describe(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Parses the arguments after the program name.  The options shared by every command can go
+/// anywhere.
+pub fn parse_args(args: &[String]) -> Result<Invocation, CliError> {
+    let mut log_level = Level::Warn;
+    let mut entry = None;
+    let mut config = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" => log_level = Level::Error,
+            "-v" => log_level = Level::Info,
+            "-vv" => log_level = Level::Debug,
+            "--entry" => entry = Some(args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?.clone()),
+            "--config" => config = Some(args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?.clone()),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let command = parse_command(&rest)?;
+    Ok(Invocation { command, log_level, entry, config })
+}
+
+fn parse_command(args: &[String]) -> Result<Command, CliError> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err(CliError::NoCommand),
+    };
+    let name = match command {
+        "--help" | "-h" => return Ok(Command::Help),
+        "info" => "info",
+        "header" => "header",
+        "validate" => "validate",
+        "run" => "run",
+        "play" => "play",
+        "bench" => "bench",
+        "dump" => "dump",
+        "fix" => "fix",
+        "patch" => "patch",
+        "disasm" => "disasm",
+        "debug" => "debug",
+        "config" => return parse_config_command(rest),
+        "rom" => return parse_rom_command(rest),
+        _ => return Err(CliError::UnknownCommand(command.to_string())),
+    };
+    let (rom, mut options) = match rest.split_first() {
+        Some((rom, options)) if rom == "-" || !rom.starts_with('-') => (rom.clone(), options.iter()),
+        _ => return Err(CliError::MissingRom(name)),
+    };
+
+    let mut run = RunOptions::default();
+    let mut headless = false;
+    let mut hashes = Headless::default();
+    let mut screenshot_every = None;
+    let mut screenshot_dir = None;
+    let mut record_frames = None;
+    let mut record_skip = None;
+    let mut save_state_at = None;
+    let mut state_file = None;
+    let mut bench_length = None;
+    let mut warmup = DEFAULT_WARMUP_FRAMES;
+    let mut scale = None;
+    let mut pause_on_focus_loss = false;
+    let mut strict = false;
+    let mut recursive = false;
+    let mut failed_only = false;
+    let mut json = false;
+    let mut list = false;
+    let mut range = None;
+    let mut output = None;
+    let mut in_place = false;
+    let mut repairs = Repairs::NONE;
+    let mut patch = None;
+    let mut fix_checksums = false;
+    let mut force = false;
+    let mut bank = None;
+    let mut follow = false;
+    let mut budget = 10000;
+    let mut sym = None;
+    let mut coverage = None;
+    while let Some(option) = options.next() {
+        match (name, option.as_str()) {
+            ("info", "--json") | ("validate", "--json") | ("bench", "--json") => json = true,
+            ("info", "--list") => list = true,
+            ("validate", "--strict") => strict = true,
+            ("validate", "--recursive") => recursive = true,
+            ("validate", "--failed-only") => failed_only = true,
+            ("run", "--headless") => headless = true,
+            ("run", "--print-hash") => hashes.print_hash = true,
+            ("run", "--dump-oam") => hashes.dump_oam = true,
+            ("run", "--print-hash-every") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse() {
+                    Ok(0) | Err(_) => return Err(CliError::BadValue(option.clone(), value.clone())),
+                    Ok(parsed) => hashes.print_hash_every = Some(parsed),
+                }
+            },
+            ("run", "--frames") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let parsed = value.parse().map_err(|_| CliError::BadValue(option.clone(), value.clone()))?;
+                run.frames = Some(parsed);
+            },
+            ("run", "--bootrom") => {
+                run.bootrom = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--skip-boot") => run.skip_boot = true,
+            ("run", "--serial-stdout") => run.serial_stdout = true,
+            ("run", "--cheat") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                run.cheats.push(Cheat::parse(value).map_err(|e| CliError::Invalid(e.to_string()))?);
+            },
+            ("run", "--screenshot") => {
+                run.screenshot = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--at-frame") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse() {
+                    Ok(0) | Err(_) => return Err(CliError::BadValue(option.clone(), value.clone())),
+                    Ok(parsed) => run.at_frame = Some(parsed),
+                }
+            },
+            ("run", "--screenshot-every") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse() {
+                    Ok(0) | Err(_) => return Err(CliError::BadValue(option.clone(), value.clone())),
+                    Ok(parsed) => screenshot_every = Some(parsed),
+                }
+            },
+            ("run", "--dump-tiles") => {
+                run.dump_tiles = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--dump-tilemap") => {
+                run.dump_tilemap = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--renderer") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                run.renderer = Some(match value.as_str() {
+                    "scanline" => Renderer::Scanline,
+                    "fifo" => Renderer::Fifo,
+                    _ => return Err(CliError::BadValue(option.clone(), value.clone())),
+                });
+            },
+            ("run", "--palette") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                run.palette = Some(value.parse::<DisplayPalette>().map_err(|e| CliError::Invalid(e.to_string()))?);
+            },
+            ("run", "--ghosting") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse::<f32>() {
+                    Ok(factor) if (0.0..=1.0).contains(&factor) => run.ghosting = Some(factor),
+                    _ => return Err(CliError::BadValue(option.clone(), value.clone())),
+                }
+            },
+            ("run", "--wav") => {
+                run.wav = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--mute-channels") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let muted = ChannelMask::parse_channels(value).map_err(|e| CliError::Invalid(format!("invalid channel list: {}", e)))?;
+                run.channel_mask = muted.inverted();
+            },
+            ("run", "--apu-log") => {
+                let path = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let format = if path.to_ascii_lowercase().ends_with(".vgm") { WriteLogFormat::Vgm } else { WriteLogFormat::Csv };
+                run.apu_log = Some((path.clone(), format));
+            },
+            ("run", "--record-movie") => {
+                run.record_movie = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--play-movie") => {
+                run.play_movie = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--record") => {
+                let path = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone();
+                run.record = Some(Recording { path, frames: None, skip: 1 });
+            },
+            ("run", "--record-frames") | ("run", "--record-skip") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse::<u32>() {
+                    Ok(0) | Err(_) => return Err(CliError::BadValue(option.clone(), value.clone())),
+                    Ok(parsed) if option == "--record-frames" => record_frames = Some(parsed),
+                    Ok(parsed) => record_skip = Some(parsed as u64),
+                }
+            },
+            ("run", "--load-state") => {
+                run.load_state = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--force") => run.force = true,
+            ("run", "--save-state-at-frame") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                save_state_at = Some(value.parse().map_err(|_| CliError::BadValue(option.clone(), value.clone()))?);
+            },
+            ("run", "--state-file") => {
+                state_file = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--script") => {
+                run.script = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("run", "--screenshot-dir") => {
+                screenshot_dir = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("play", "--scale") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                match value.parse() {
+                    Ok(parsed @ 1..=16) => scale = Some(parsed),
+                    _ => return Err(CliError::BadValue(option.clone(), value.clone())),
+                }
+            },
+            ("play", "--pause-on-focus-loss") => pause_on_focus_loss = true,
+            ("bench", "--seconds") | ("bench", "--frames") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                if bench_length.is_some() {
+                    return Err(CliError::Invalid("bench takes one of --seconds and --frames".to_string()));
+                }
+                bench_length = Some(match (option.as_str(), value.parse()) {
+                    (_, Ok(0)) | (_, Err(_)) => return Err(CliError::BadValue(option.clone(), value.clone())),
+                    ("--seconds", Ok(seconds)) => BenchLength::Seconds(seconds),
+                    (_, Ok(frames)) => BenchLength::Frames(frames as u64),
+                });
+            },
+            ("bench", "--warmup") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                warmup = value.parse().map_err(|_| CliError::BadValue(option.clone(), value.clone()))?;
+            },
+            ("dump", "--range") | ("disasm", "--range") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let parsed = parse_range(value).ok_or_else(|| CliError::BadValue(option.clone(), value.clone()))?;
+                range = Some(parsed);
+            },
+            ("disasm", "--bank") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                bank = Some(parse_number(value).ok_or_else(|| CliError::BadValue(option.clone(), value.clone()))?);
+            },
+            ("disasm", "--follow") => follow = true,
+            ("disasm", "--budget") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                budget = value.parse().map_err(|_| CliError::BadValue(option.clone(), value.clone()))?;
+            },
+            ("disasm", "--sym") | ("debug", "--sym") => {
+                sym = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("disasm", "--coverage") => {
+                coverage = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("fix", "-o") | ("patch", "-o") => {
+                output = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("fix", "--in-place") => in_place = true,
+            ("fix", "--logo") => repairs.logo = true,
+            ("fix", "--checksums") => repairs.checksums = true,
+            ("fix", "--pad") => repairs.pad = true,
+            ("patch", "--fix-checksums") => fix_checksums = true,
+            ("patch", "--force") => force = true,
+            ("patch", path) if patch.is_none() && !path.starts_with('-') => patch = Some(path.to_string()),
+            _ => return Err(CliError::UnexpectedArgument(option.clone())),
+        }
+    }
+
+    Ok(match name {
+        "info" => Command::Info { rom, json, list },
+        "header" => Command::Header { rom },
+        "validate" => Command::Validate { rom, strict, recursive, failed_only, json },
+        "run" => {
+            if !headless && hashes.dump_oam {
+                return Err(CliError::Invalid("--dump-oam is only printed with --headless".to_string()));
+            }
+            if hashes.dump_oam && run.frames.is_none() {
+                return Err(CliError::Invalid("--dump-oam needs --frames to know which frame is the last".to_string()));
+            }
+            if !headless && hashes != Headless::default() {
+                return Err(CliError::Invalid("frame hashes are only printed with --headless".to_string()));
+            }
+            if hashes.print_hash && run.frames.is_none() {
+                return Err(CliError::Invalid("--print-hash needs --frames to know which frame is the last".to_string()));
+            }
+            if run.skip_boot && run.bootrom.is_some() {
+                return Err(CliError::Invalid("--skip-boot and --bootrom can't be used together".to_string()));
+            }
+            if run.record_movie.is_some() && run.play_movie.is_some() {
+                return Err(CliError::Invalid("--record-movie and --play-movie can't be used together".to_string()));
+            }
+            if run.at_frame.is_some() && run.screenshot.is_none() {
+                return Err(CliError::Invalid("--at-frame picks the frame for --screenshot, which is missing".to_string()));
+            }
+            if run.screenshot.is_some() && run.frames.is_none() && run.at_frame.is_none() {
+                return Err(CliError::Invalid("--screenshot needs --frames or --at-frame to know which frame to save".to_string()));
+            }
+            if let (Some(at_frame), Some(frames)) = (run.at_frame, run.frames) {
+                if at_frame > frames {
+                    return Err(CliError::Invalid(format!("--at-frame {} is past the last frame, {}", at_frame, frames)));
+                }
+            }
+            if run.wav.is_some() && run.frames.is_none() {
+                return Err(CliError::Invalid("--wav needs --frames to know when the recording ends".to_string()));
+            }
+            if run.apu_log.is_some() && run.frames.is_none() {
+                return Err(CliError::Invalid("--apu-log needs --frames to know when the log ends".to_string()));
+            }
+            for &(flag, dump) in &[("--dump-tiles", &run.dump_tiles), ("--dump-tilemap", &run.dump_tilemap)] {
+                if dump.is_some() && run.frames.is_none() {
+                    return Err(CliError::Invalid(format!("{} needs --frames to know which frame is the last", flag)));
+                }
+            }
+            run.screenshot_every = match (screenshot_every, screenshot_dir) {
+                (Some(interval), Some(dir)) => Some((interval, dir)),
+                (None, None) => None,
+                _ => return Err(CliError::Invalid("--screenshot-every and --screenshot-dir go together".to_string())),
+            };
+            match run.record {
+                Some(ref mut recording) => {
+                    recording.frames = record_frames;
+                    recording.skip = record_skip.unwrap_or(1);
+                },
+                None if record_frames.is_some() || record_skip.is_some() => {
+                    return Err(CliError::Invalid("--record-frames and --record-skip need --record".to_string()));
+                },
+                None => {},
+            }
+            if run.force && run.load_state.is_none() {
+                return Err(CliError::Invalid("--force only applies to --load-state".to_string()));
+            }
+            run.save_state = match (save_state_at, state_file) {
+                (Some(frame), Some(path)) => Some((frame, path)),
+                (None, None) => None,
+                _ => return Err(CliError::Invalid("--save-state-at-frame and --state-file go together".to_string())),
+            };
+            if let (Some((at, _)), Some(frames)) = (&run.save_state, run.frames) {
+                if *at > frames {
+                    return Err(CliError::Invalid(format!("--save-state-at-frame {} is past the last frame, {}", at, frames)));
+                }
+            }
+            run.headless = if headless { Some(hashes) } else { None };
+            Command::Run { rom, options: Box::new(run) }
+        },
+        "play" => Command::Play { rom, scale, pause_on_focus_loss },
+        "bench" => {
+            let length = bench_length.unwrap_or(BenchLength::Seconds(10));
+            if let BenchLength::Frames(frames) = length {
+                if frames <= warmup {
+                    return Err(CliError::Invalid(format!("--frames {} leaves nothing to measure after the {} frame warm-up", frames, warmup)));
+                }
+            }
+            Command::Bench { rom, length, warmup, json }
+        },
+        "dump" => match range {
+            Some(range) => Command::Dump { rom, range },
+            None => return Err(CliError::MissingValue("--range".to_string())),
+        },
+        "disasm" => {
+            match range {
+                Some(ref range) if range.end > 0x8000 => {
+                    return Err(CliError::Invalid("disasm only covers the rom area, 0x0000..0x8000".to_string()));
+                },
+                None if !follow => return Err(CliError::MissingValue("--range".to_string())),
+                _ => {},
+            }
+            if follow && coverage.is_some() {
+                return Err(CliError::Invalid("--coverage already says where the code is, it can't be used with --follow".to_string()));
+            }
+            Command::Disasm { rom, bank, range, follow, budget, sym, coverage }
+        },
+        "debug" => {
+            if rom == "-" {
+                return Err(CliError::Invalid("debug reads its commands from stdin, so the rom can't come from there".to_string()));
+            }
+            Command::Debug { rom, sym }
+        },
+        "patch" => match (patch, output) {
+            (Some(patch), Some(output)) => Command::Patch { rom, patch, output, fix_checksums, force },
+            (None, _) => return Err(CliError::Invalid("patch needs a patch file to apply".to_string())),
+            (_, None) => return Err(CliError::MissingValue("-o".to_string())),
+        },
+        _ => {
+            if output.is_some() == in_place {
+                return Err(CliError::Invalid("fix needs either -o or --in-place".to_string()));
+            }
+            if in_place && rom == "-" {
+                return Err(CliError::Invalid("--in-place can't be used with stdin".to_string()));
+            }
+            if repairs == Repairs::NONE {
+                repairs = Repairs::ALL;
+            }
+            Command::Fix { rom, output, repairs }
+        },
+    })
+}
+
+// config takes no rom.
+fn parse_config_command(args: &[String]) -> Result<Command, CliError> {
+    let mut print_default = false;
+    for arg in args {
+        match arg.as_str() {
+            "--print-default" => print_default = true,
+            _ => return Err(CliError::UnexpectedArgument(arg.clone())),
+        }
+    }
+    Ok(Command::Config { print_default })
+}
+
+// rom takes an action before the rom.
+fn parse_rom_command(args: &[String]) -> Result<Command, CliError> {
+    let (action, rest) = match args.split_first() {
+        Some((action, rest)) => (action.as_str(), rest),
+        None => return Err(CliError::Invalid("rom needs an action: pad, trim or split".to_string())),
+    };
+    if !matches!(action, "pad" | "trim" | "split") {
+        return Err(CliError::UnknownCommand(format!("rom {}", action)));
+    }
+    let (rom, mut options) = match rest.split_first() {
+        Some((rom, options)) if rom == "-" || !rom.starts_with('-') => (rom.clone(), options.iter()),
+        _ => return Err(CliError::MissingRom("rom")),
+    };
+
+    let mut size = None;
+    let mut fill = 0xFF;
+    let mut force = false;
+    let mut banks = 1;
+    let mut output = None;
+    let mut in_place = false;
+    let mut fix_checksums = false;
+    while let Some(option) = options.next() {
+        match (action, option.as_str()) {
+            ("pad", "--to") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                size = match value.as_str() {
+                    "declared" => None,
+                    _ => Some(parse_number(value).ok_or_else(|| CliError::BadValue(option.clone(), value.clone()))?),
+                };
+            },
+            ("pad", "--fill") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let parsed = parse_number(value).filter(|&fill| fill <= 0xFF);
+                fill = parsed.ok_or_else(|| CliError::BadValue(option.clone(), value.clone()))? as u8;
+            },
+            ("trim", "--force") => force = true,
+            ("split", "--banks") => {
+                let value = options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let parsed = parse_number(value).filter(|&banks| banks > 0);
+                banks = parsed.ok_or_else(|| CliError::BadValue(option.clone(), value.clone()))?;
+            },
+            (_, "-o") => {
+                output = Some(options.next().ok_or_else(|| CliError::MissingValue(option.clone()))?.clone());
+            },
+            ("pad", "--in-place") | ("trim", "--in-place") => in_place = true,
+            (_, "--fix-checksums") => fix_checksums = true,
+            _ => return Err(CliError::UnexpectedArgument(option.clone())),
+        }
+    }
+
+    let action = match action {
+        "pad" => RomAction::Pad { size, fill },
+        "trim" => RomAction::Trim { force },
+        _ => {
+            if output.is_none() && rom == "-" {
+                return Err(CliError::Invalid("rom split needs -o when reading stdin".to_string()));
+            }
+            RomAction::Split { banks }
+        },
+    };
+    if !matches!(action, RomAction::Split { .. }) {
+        if output.is_some() == in_place {
+            return Err(CliError::Invalid("rom pad and rom trim need either -o or --in-place".to_string()));
+        }
+        if in_place && rom == "-" {
+            return Err(CliError::Invalid("--in-place can't be used with stdin".to_string()));
+        }
+    }
+    Ok(Command::Rom { rom, action, output, fix_checksums })
+}
+
+// START..END, each in hex with a 0x prefix or in decimal.
+fn parse_range(s: &str) -> Option<Range<usize>> {
+    let (start, end) = s.split_once("..")?;
+    let (start, end) = (parse_number(start)?, parse_number(end)?);
+    if start > end {
+        return None;
+    }
+    Some(start..end)
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use farore::apu::mixer::Channel;
+    use farore::archive;
+    use farore::ips;
+
+    use super::*;
+
+
+    fn parse(line: &str) -> Result<Command, CliError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_args(&args).map(|invocation| invocation.command)
+    }
+
+    #[test]
+    fn commands_need_a_rom() {
+        for &command in &["info", "header", "validate", "run", "dump", "fix", "patch", "disasm", "debug"] {
+            assert_eq!(parse(command), Err(CliError::MissingRom(command)));
+            assert_eq!(parse(&format!("{} --json", command)), Err(CliError::MissingRom(command)));
+        }
+        assert_eq!(parse("rom pad"), Err(CliError::MissingRom("rom")));
+        assert_eq!(parse(""), Err(CliError::NoCommand));
+    }
+
+    #[test]
+    fn fix_needs_exactly_one_output() {
+        let invalid = Err(CliError::Invalid("fix needs either -o or --in-place".to_string()));
+        assert_eq!(parse("fix game.gb"), invalid);
+        assert_eq!(parse("fix game.gb -o out.gb --in-place"), invalid);
+        assert_eq!(parse("fix game.gb --in-place"), Ok(Command::Fix {
+            rom: "game.gb".to_string(),
+            output: None,
+            repairs: Repairs::ALL,
+        }));
+    }
+
+    #[test]
+    fn fill_has_to_fit_a_byte() {
+        assert_eq!(parse("rom pad game.gb --fill 0x100 --in-place"),
+                   Err(CliError::BadValue("--fill".to_string(), "0x100".to_string())));
+        assert_eq!(parse("rom pad game.gb --fill x --in-place"),
+                   Err(CliError::BadValue("--fill".to_string(), "x".to_string())));
+        assert_eq!(parse("rom pad game.gb --fill 0xFF --in-place"), Ok(Command::Rom {
+            rom: "game.gb".to_string(),
+            action: RomAction::Pad { size: None, fill: 0xFF },
+            output: None,
+            fix_checksums: false,
+        }));
+    }
+
+    #[test]
+    fn disasm_stays_in_the_rom_area() {
+        let outside = Err(CliError::Invalid("disasm only covers the rom area, 0x0000..0x8000".to_string()));
+        assert_eq!(parse("disasm game.gb --range 0..0x9000"), outside);
+        assert_eq!(parse("disasm game.gb --range 0x4000..0x8001"), outside);
+        assert!(parse("disasm game.gb --range 0..0x8000").is_ok());
+        assert_eq!(parse("disasm game.gb --range 0x200..0x100"),
+                   Err(CliError::BadValue("--range".to_string(), "0x200..0x100".to_string())));
+    }
+
+    #[test]
+    fn frame_hashes_need_a_headless_run() {
+        assert_eq!(parse("run game.gb --headless --frames 300 --print-hash --print-hash-every 60"), Ok(Command::Run {
+            rom: "game.gb".to_string(),
+            options: Box::new(RunOptions {
+                frames: Some(300),
+                headless: Some(Headless { print_hash: true, print_hash_every: Some(60), dump_oam: false }),
+                ..RunOptions::default()
+            }),
+        }));
+        assert_eq!(parse("run game.gb --frames 300 --print-hash"),
+                   Err(CliError::Invalid("frame hashes are only printed with --headless".to_string())));
+        assert_eq!(parse("run game.gb --headless --print-hash"),
+                   Err(CliError::Invalid("--print-hash needs --frames to know which frame is the last".to_string())));
+        assert_eq!(parse("run game.gb --headless --print-hash-every 0"),
+                   Err(CliError::BadValue("--print-hash-every".to_string(), "0".to_string())));
+        assert_eq!(parse("run game.gb --headless --print-hash-every"),
+                   Err(CliError::MissingValue("--print-hash-every".to_string())));
+        match parse("run game.gb --headless") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.headless, Some(Headless::default())),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn oam_is_dumped_after_the_last_headless_frame() {
+        match parse("run game.gb --headless --frames 60 --dump-oam") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!(options.headless, Some(Headless { dump_oam: true, ..Headless::default() }));
+            },
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --frames 60 --dump-oam"),
+                   Err(CliError::Invalid("--dump-oam is only printed with --headless".to_string())));
+        assert_eq!(parse("run game.gb --headless --dump-oam"),
+                   Err(CliError::Invalid("--dump-oam needs --frames to know which frame is the last".to_string())));
+    }
+
+    #[test]
+    fn skip_boot_and_a_boot_rom_exclude_each_other() {
+        match parse("run game.gb --skip-boot") {
+            Ok(Command::Run { options, .. }) => assert!(options.skip_boot),
+            other => panic!("{:?}", other),
+        }
+        match parse("run game.gb") {
+            Ok(Command::Run { options, .. }) => assert!(!options.skip_boot),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --bootrom dmg.bin --skip-boot"),
+                   Err(CliError::Invalid("--skip-boot and --bootrom can't be used together".to_string())));
+    }
+
+    #[test]
+    fn screenshots_need_to_know_which_frames() {
+        match parse("run game.gb --frames 600 --screenshot out.png --screenshot-every 60 --screenshot-dir shots") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!(options.screenshot, Some("out.png".to_string()));
+                assert_eq!(options.screenshot_every, Some((60, "shots".to_string())));
+            },
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --screenshot out.png"),
+                   Err(CliError::Invalid("--screenshot needs --frames or --at-frame to know which frame to save".to_string())));
+        let apart = Err(CliError::Invalid("--screenshot-every and --screenshot-dir go together".to_string()));
+        assert_eq!(parse("run game.gb --screenshot-every 60"), apart);
+        assert_eq!(parse("run game.gb --screenshot-dir shots"), apart);
+        assert_eq!(parse("run game.gb --screenshot-every 0 --screenshot-dir shots"),
+                   Err(CliError::BadValue("--screenshot-every".to_string(), "0".to_string())));
+    }
+
+    #[test]
+    fn screenshots_can_be_of_a_given_frame() {
+        match parse("run game.gb --screenshot out.png --at-frame 300") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!((options.screenshot.as_deref(), options.at_frame, options.frames), (Some("out.png"), Some(300), None));
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(parse("run game.gb --frames 300 --screenshot out.png --at-frame 300").is_ok());
+        assert_eq!(parse("run game.gb --frames 299 --screenshot out.png --at-frame 300"),
+                   Err(CliError::Invalid("--at-frame 300 is past the last frame, 299".to_string())));
+        assert_eq!(parse("run game.gb --at-frame 300"),
+                   Err(CliError::Invalid("--at-frame picks the frame for --screenshot, which is missing".to_string())));
+        assert_eq!(parse("run game.gb --screenshot out.png --at-frame 0"),
+                   Err(CliError::BadValue("--at-frame".to_string(), "0".to_string())));
+    }
+
+    #[test]
+    fn tiles_are_dumped_after_the_last_frame() {
+        match parse("run game.gb --frames 60 --dump-tiles tiles.png --dump-tilemap map.png") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!(options.dump_tiles, Some("tiles.png".to_string()));
+                assert_eq!(options.dump_tilemap, Some("map.png".to_string()));
+            },
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --dump-tiles tiles.png"),
+                   Err(CliError::Invalid("--dump-tiles needs --frames to know which frame is the last".to_string())));
+        assert_eq!(parse("run game.gb --dump-tilemap map.png"),
+                   Err(CliError::Invalid("--dump-tilemap needs --frames to know which frame is the last".to_string())));
+        assert_eq!(parse("run game.gb --frames 60 --dump-tiles"), Err(CliError::MissingValue("--dump-tiles".to_string())));
+    }
+
+    #[test]
+    fn renderer_is_scanline_or_fifo() {
+        for &(name, renderer) in &[("scanline", Renderer::Scanline), ("fifo", Renderer::Fifo)] {
+            match parse(&format!("run game.gb --renderer {}", name)) {
+                Ok(Command::Run { options, .. }) => assert_eq!(options.renderer, Some(renderer)),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(parse("run game.gb --renderer pixel"),
+                   Err(CliError::BadValue("--renderer".to_string(), "pixel".to_string())));
+    }
+
+    #[test]
+    fn palettes_are_presets_or_hex_colors() {
+        let palette = |line: &str| match parse(line) {
+            Ok(Command::Run { options, .. }) => options.palette,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(palette("run game.gb --palette dmg-green"), Some(DisplayPalette::DMG_GREEN));
+        assert_eq!(palette("run game.gb --palette #e0f8d0,#88c070,#346856,#081820").map(|palette| palette.colors),
+                   Some([[0xE0, 0xF8, 0xD0], [0x88, 0xC0, 0x70], [0x34, 0x68, 0x56], [0x08, 0x18, 0x20]]));
+        assert_eq!(palette("run game.gb"), None);
+        let unknown = "invalid palette: unknown preset \"sepia\", expected grayscale, dmg-green, pocket or four hex colors";
+        assert_eq!(parse("run game.gb --palette sepia"), Err(CliError::Invalid(unknown.to_string())));
+        assert_eq!(parse("run game.gb --palette #000000,#ffffff"),
+                   Err(CliError::Invalid("invalid palette: expected 4 colors, got 2".to_string())));
+    }
+
+    #[test]
+    fn ghosting_is_a_factor_from_0_to_1() {
+        for &(value, factor) in &[("0", 0.0), ("0.5", 0.5), ("1", 1.0)] {
+            match parse(&format!("run game.gb --ghosting {}", value)) {
+                Ok(Command::Run { options, .. }) => assert_eq!(options.ghosting, Some(factor)),
+                other => panic!("{:?}", other),
+            }
+        }
+        for &value in &["1.5", "-0.1", "NaN", "half"] {
+            assert_eq!(parse(&format!("run game.gb --ghosting {}", value)),
+                       Err(CliError::BadValue("--ghosting".to_string(), value.to_string())));
+        }
+    }
+
+    #[test]
+    fn audio_is_recorded_up_to_the_last_frame() {
+        match parse("run game.gb --frames 600 --wav out.wav") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.wav, Some("out.wav".to_string())),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --wav out.wav"),
+                   Err(CliError::Invalid("--wav needs --frames to know when the recording ends".to_string())));
+        assert_eq!(parse("run game.gb --frames 600 --wav"), Err(CliError::MissingValue("--wav".to_string())));
+    }
+
+    #[test]
+    fn muted_channels_are_left_out_of_the_mask() {
+        match parse("run game.gb --mute-channels 1,4") {
+            Ok(Command::Run { options, .. }) => {
+                let heard: Vec<bool> = [Channel::Pulse1, Channel::Pulse2, Channel::Wave, Channel::Noise].iter()
+                    .map(|&channel| options.channel_mask.is_enabled(channel))
+                    .collect();
+                assert_eq!(heard, [false, true, true, false]);
+            },
+            other => panic!("{:?}", other),
+        }
+        match parse("run game.gb") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.channel_mask, ChannelMask::all()),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --mute-channels 1,5"),
+                   Err(CliError::Invalid("invalid channel list: \"5\" is not a channel number (1-4)".to_string())));
+    }
+
+    #[test]
+    fn the_apu_log_format_follows_the_extension() {
+        let log = |line: &str| match parse(line) {
+            Ok(Command::Run { options, .. }) => options.apu_log,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(log("run game.gb --frames 600 --apu-log song.csv"), Some(("song.csv".to_string(), WriteLogFormat::Csv)));
+        assert_eq!(log("run game.gb --frames 600 --apu-log song.VGM"), Some(("song.VGM".to_string(), WriteLogFormat::Vgm)));
+        assert_eq!(log("run game.gb --frames 600 --apu-log song"), Some(("song".to_string(), WriteLogFormat::Csv)));
+        assert_eq!(parse("run game.gb --apu-log song.csv"),
+                   Err(CliError::Invalid("--apu-log needs --frames to know when the log ends".to_string())));
+    }
+
+    #[test]
+    fn serial_output_can_go_to_stdout() {
+        for &(line, echoed) in &[("run game.gb --serial-stdout", true), ("run game.gb", false)] {
+            match parse(line) {
+                Ok(Command::Run { options, .. }) => assert_eq!(options.serial_stdout, echoed),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(parse("info game.gb --serial-stdout"), Err(CliError::UnexpectedArgument("--serial-stdout".to_string())));
+    }
+
+    #[test]
+    fn movies_are_recorded_or_played() {
+        match parse("run game.gb --record-movie run.fgbm") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!(options.record_movie, Some("run.fgbm".to_string()));
+                assert_eq!(options.play_movie, None);
+            },
+            other => panic!("{:?}", other),
+        }
+        match parse("run game.gb --play-movie run.fgbm") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.play_movie, Some("run.fgbm".to_string())),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --record-movie a.fgbm --play-movie b.fgbm"),
+                   Err(CliError::Invalid("--record-movie and --play-movie can't be used together".to_string())));
+        assert_eq!(parse("run game.gb --play-movie"), Err(CliError::MissingValue("--play-movie".to_string())));
+    }
+
+    #[test]
+    fn errors_are_json_objects_with_a_kind() {
+        let json = Failure::Header("the rom is 10 bytes".to_string()).to_json();
+        let parsed = Json::parse(&json.to_string()).unwrap();
+        let error = parsed.get("error").unwrap();
+        assert_eq!(error.get("kind").and_then(Json::as_str), Some("Header"));
+        assert_eq!(error.get("message").and_then(Json::as_str), Some("the rom is 10 bytes"));
+        assert_eq!(parsed, Json::object(vec![("error", error.clone())]));
+    }
+
+    #[test]
+    fn play_takes_a_scale_from_1_to_16() {
+        assert_eq!(parse("play game.gb --scale 4 --pause-on-focus-loss"),
+                   Ok(Command::Play { rom: "game.gb".to_string(), scale: Some(4), pause_on_focus_loss: true }));
+        assert_eq!(parse("play game.gb"),
+                   Ok(Command::Play { rom: "game.gb".to_string(), scale: None, pause_on_focus_loss: false }));
+        for &value in &["0", "17", "x"] {
+            assert_eq!(parse(&format!("play game.gb --scale {}", value)),
+                       Err(CliError::BadValue("--scale".to_string(), value.to_string())));
+        }
+        assert_eq!(parse("play game.gb --frames 10"), Err(CliError::UnexpectedArgument("--frames".to_string())));
+        assert_eq!(parse("play"), Err(CliError::MissingRom("play")));
+    }
+
+    #[test]
+    fn bench_runs_for_seconds_or_frames() {
+        let bench = |line: &str| match parse(line) {
+            Ok(Command::Bench { length, warmup, json, .. }) => (length, warmup, json),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(bench("bench game.gb"), (BenchLength::Seconds(10), 60, false));
+        assert_eq!(bench("bench game.gb --seconds 3 --json"), (BenchLength::Seconds(3), 60, true));
+        assert_eq!(bench("bench game.gb --frames 600 --warmup 0"), (BenchLength::Frames(600), 0, false));
+        assert_eq!(parse("bench game.gb --seconds 3 --frames 600"),
+                   Err(CliError::Invalid("bench takes one of --seconds and --frames".to_string())));
+        assert_eq!(parse("bench game.gb --seconds 0"), Err(CliError::BadValue("--seconds".to_string(), "0".to_string())));
+        assert_eq!(parse("bench game.gb --frames 60"),
+                   Err(CliError::Invalid("--frames 60 leaves nothing to measure after the 60 frame warm-up".to_string())));
+    }
+
+    #[test]
+    fn recordings_keep_every_nth_frame_up_to_a_limit() {
+        let record = |line: &str| match parse(line) {
+            Ok(Command::Run { options, .. }) => options.record,
+            other => panic!("{:?}", other),
+        };
+        let recording = |frames, skip| Some(Recording { path: "out.gif".to_string(), frames, skip });
+        assert_eq!(record("run game.gb --record out.gif"), recording(None, 1));
+        assert_eq!(record("run game.gb --record-frames 300 --record-skip 2 --record out.gif"), recording(Some(300), 2));
+        assert_eq!(record("run game.gb"), None);
+        assert_eq!(parse("run game.gb --record-frames 300"),
+                   Err(CliError::Invalid("--record-frames and --record-skip need --record".to_string())));
+        assert_eq!(parse("run game.gb --record out.gif --record-skip 0"),
+                   Err(CliError::BadValue("--record-skip".to_string(), "0".to_string())));
+    }
+
+    #[test]
+    fn states_are_loaded_and_saved() {
+        match parse("run game.gb --load-state game.state1 --force") {
+            Ok(Command::Run { options, .. }) => {
+                assert_eq!(options.load_state, Some("game.state1".to_string()));
+                assert!(options.force);
+            },
+            other => panic!("{:?}", other),
+        }
+        match parse("run game.gb --frames 150 --save-state-at-frame 100 --state-file at100.state") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.save_state, Some((100, "at100.state".to_string()))),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --load-state"), Err(CliError::MissingValue("--load-state".to_string())));
+        assert_eq!(parse("run game.gb --force"), Err(CliError::Invalid("--force only applies to --load-state".to_string())));
+        assert_eq!(parse("run game.gb --save-state-at-frame 100"),
+                   Err(CliError::Invalid("--save-state-at-frame and --state-file go together".to_string())));
+        assert_eq!(parse("run game.gb --frames 50 --save-state-at-frame 100 --state-file at100.state"),
+                   Err(CliError::Invalid("--save-state-at-frame 100 is past the last frame, 50".to_string())));
+        assert_eq!(parse("run game.gb --save-state-at-frame x --state-file s"),
+                   Err(CliError::BadValue("--save-state-at-frame".to_string(), "x".to_string())));
+    }
+
+    #[test]
+    fn scripts_are_passed_on() {
+        match parse("run game.gb --script test.farore") {
+            Ok(Command::Run { options, .. }) => assert_eq!(options.script, Some("test.farore".to_string())),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse("run game.gb --script"), Err(CliError::MissingValue("--script".to_string())));
+        assert_eq!(parse("info game.gb --script test.farore"), Err(CliError::UnexpectedArgument("--script".to_string())));
+    }
+
+    #[test]
+    fn debug_takes_symbols_but_not_stdin() {
+        assert_eq!(parse("debug game.gb"), Ok(Command::Debug { rom: "game.gb".to_string(), sym: None }));
+        assert_eq!(parse("debug game.gb --sym game.sym"),
+                   Ok(Command::Debug { rom: "game.gb".to_string(), sym: Some("game.sym".to_string()) }));
+        assert_eq!(parse("debug -"),
+                   Err(CliError::Invalid("debug reads its commands from stdin, so the rom can't come from there".to_string())));
+        assert_eq!(parse("debug game.gb --frames 10"), Err(CliError::UnexpectedArgument("--frames".to_string())));
+    }
+
+    #[test]
+    fn stdin_can_not_be_written_in_place() {
+        let invalid = Err(CliError::Invalid("--in-place can't be used with stdin".to_string()));
+        assert_eq!(parse("fix - --in-place"), invalid);
+        assert_eq!(parse("rom trim - --in-place"), invalid);
+        assert_eq!(parse("rom split -"), Err(CliError::Invalid("rom split needs -o when reading stdin".to_string())));
+        assert!(parse("fix - -o out.gb").is_ok());
+    }
+
+    #[test]
+    fn shared_options_go_anywhere() {
+        let invocation = parse_args(&["-q".to_string(), "info".to_string(), "game.gb".to_string(),
+                                      "--entry".to_string(), "b.gb".to_string()]).unwrap();
+        assert_eq!(invocation.log_level, Level::Error);
+        assert_eq!(invocation.entry, Some("b.gb".to_string()));
+        assert_eq!(parse("info game.gb --entry"), Err(CliError::MissingValue("--entry".to_string())));
+    }
+
+    #[test]
+    fn errors_map_to_exit_codes() {
+        let archive = archive::unpack(&[0x1F, 0x8B, 0x08], None).err().unwrap();
+        let patch = FaroreError::from(ips::apply(&mut vec![0; 16], b"PATCH").unwrap_err());
+        let cases = vec![
+            (FaroreError::io(Path::new("game.gb"), io::Error::new(io::ErrorKind::NotFound, "not found")), EXIT_IO),
+            (archive, EXIT_IO),
+            (patch, EXIT_IO),
+            (FaroreError::RamSizeMismatch { expected: 0x2000, found: 0x1000 }, EXIT_IO),
+            (FaroreError::SaveStateVersion { found: 2, supported: 1 }, EXIT_IO),
+            (FaroreError::RomTooShort(4), EXIT_HEADER),
+            (FaroreError::InvalidHeaderField { field: "title", reason: "not UTF-8".to_string() }, EXIT_HEADER),
+            (FaroreError::UnsupportedMapper(0xEE), EXIT_HEADER),
+            (FaroreError::RomMismatch { expected_crc32: 1, found_crc32: 2 }, EXIT_USAGE),
+            (FaroreError::InvalidArgument("bad".to_string()), EXIT_USAGE),
+        ];
+        for (error, code) in cases {
+            let name = format!("{:?}", error);
+            assert_eq!(Failure::from(error).exit_code(), code, "{}", name);
+        }
+    }
+
+    #[test]
+    fn failures_keep_the_whole_cause() {
+        let error = FaroreError::io(Path::new("game.gb"), io::Error::new(io::ErrorKind::NotFound, "not found"));
+        let failure = Failure::from(error).context("unable to open game.gb");
+        assert_eq!(failure.to_string(), "unable to open game.gb: unable to access game.gb: not found");
+        assert_eq!(failure.kind(), "Io");
+    }
+}
@@ -0,0 +1,620 @@
+//! Settings from the config file
+//!
+//! The file is a small subset of TOML: comments, [sections], and key = value pairs holding
+//! strings, integers, floats or booleans.  Unknown keys and sections only warn, so a config
+//! written for a newer version still loads.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use farore::model::HardwareModel;
+use farore::palette::DisplayPalette;
+
+
+pub const DEFAULT_TEMPLATE: &str = r##"# farore config file
+#
+# Every setting is optional.  Command line flags override what's set here.
+
+# Hardware to emulate: "dmg" or "cgb"
+model = "dmg"
+
+# DMG colors: "grayscale", "dmg-green", "pocket", or four "#rrggbb" colors, lightest first
+palette = "grayscale"
+
+# Window size as a multiple of 160x144
+scale = 3
+
+# Where battery saves go, next to the ROM when unset
+# save_dir = "/path/to/saves"
+
+[audio]
+enabled = true
+sample_rate = 48000
+# Percent, 0 to 100
+volume = 100
+
+# Without a boot rom, games start where the boot rom would have left them
+[boot_roms]
+# dmg = "/path/to/dmg_boot.bin"
+# cgb = "/path/to/cgb_boot.bin"
+# Searched for dmg_boot.bin, cgb_boot.bin and the like when the model's file isn't set
+# dir = "/path/to/boot_roms"
+
+# Keys for the play command.  Key names are letters, digits, f1-f12, up, down, left, right,
+# space, enter, escape, tab, backspace, shift, ctrl and alt.
+[keys]
+up = "up"
+down = "down"
+left = "left"
+right = "right"
+a = "x"
+b = "z"
+start = "enter"
+select = "backspace"
+turbo = "tab"
+pause = "p"
+screenshot = "f12"
+"##;
+
+// Unknown sections are warned about once, at their header
+const SECTIONS: [&str; 4] = ["", "audio", "boot_roms", "keys"];
+
+/// A keyboard key, as named in the config file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char), // A-Z or 0-9, uppercase
+    F(u8),      // F1-F12
+    Up,
+    Down,
+    Left,
+    Right,
+    Space,
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Shift,
+    Ctrl,
+    Alt,
+}
+
+const NAMED_KEYS: [(&str, Key); 13] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("space", Key::Space),
+    ("enter", Key::Enter),
+    ("escape", Key::Escape),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("shift", Key::Shift),
+    ("ctrl", Key::Ctrl),
+    ("alt", Key::Alt),
+    ("esc", Key::Escape),
+];
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                return Ok(Key::Char(c.to_ascii_uppercase()));
+            }
+        }
+        if let Some(number) = name.strip_prefix('f').and_then(|number| number.parse().ok()) {
+            if (1..=12).contains(&number) {
+                return Ok(Key::F(number));
+            }
+        }
+        match NAMED_KEYS.iter().find(|&&(key_name, _)| key_name == name) {
+            Some(&(_, key)) => Ok(key),
+            None => Err(format!("unknown key \"{}\", expected a letter, digit, f1-f12, up, down, left, right, \
+                space, enter, escape, tab, backspace, shift, ctrl or alt", s)),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Key::Char(c) => write!(f, "{}", c.to_ascii_lowercase()),
+            Key::F(number) => write!(f, "f{}", number),
+            key => {
+                let name = NAMED_KEYS.iter().find(|&&(_, named)| named == key).map(|&(name, _)| name);
+                write!(f, "{}", name.unwrap_or("?"))
+            },
+        }
+    }
+}
+
+/// Something a key can be bound to in the play command.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+    Turbo,
+    Pause,
+    Screenshot,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::Up, Action::Down, Action::Left, Action::Right, Action::A, Action::B, Action::Start,
+        Action::Select, Action::Turbo, Action::Pause, Action::Screenshot,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::A => "a",
+            Action::B => "b",
+            Action::Start => "start",
+            Action::Select => "select",
+            Action::Turbo => "turbo",
+            Action::Pause => "pause",
+            Action::Screenshot => "screenshot",
+        }
+    }
+
+    fn named(name: &str) -> Option<Action> {
+        Action::ALL.iter().cloned().find(|action| action.name() == name)
+    }
+
+    fn default_key(self) -> Key {
+        match self {
+            Action::Up => Key::Up,
+            Action::Down => Key::Down,
+            Action::Left => Key::Left,
+            Action::Right => Key::Right,
+            Action::A => Key::Char('X'),
+            Action::B => Key::Char('Z'),
+            Action::Start => Key::Enter,
+            Action::Select => Key::Backspace,
+            Action::Turbo => Key::Tab,
+            Action::Pause => Key::Char('P'),
+            Action::Screenshot => Key::F(12),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    pub sample_rate: u32,
+    pub volume: u8, // Percent
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { enabled: true, sample_rate: 48000, volume: 100 }
+    }
+}
+
+/// Everything the config file can set, starting from the built-in defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub model: HardwareModel,
+    pub palette: DisplayPalette,
+    pub scale: u32,
+    pub save_dir: Option<PathBuf>,
+    pub audio: AudioConfig,
+    pub dmg_boot_rom: Option<PathBuf>,
+    pub cgb_boot_rom: Option<PathBuf>,
+    pub boot_rom_dir: Option<PathBuf>,
+    keys: Vec<(Action, Key)>, // One per action, in `Action::ALL` order
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            model: HardwareModel::Dmg,
+            palette: DisplayPalette::GRAYSCALE,
+            scale: 3,
+            save_dir: None,
+            audio: AudioConfig::default(),
+            dmg_boot_rom: None,
+            cgb_boot_rom: None,
+            boot_rom_dir: None,
+            keys: Action::ALL.iter().map(|&action| (action, action.default_key())).collect(),
+        }
+    }
+}
+
+/// A line of the config file that couldn't be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Config {
+    /// Where the config file is looked for when none is given: the platform's per-user
+    /// config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let var = |name| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let dir = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library").join("Application Support"))
+        } else {
+            var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+        };
+        dir.map(|dir| dir.join("farore").join("config.toml"))
+    }
+
+    /// Reads settings on top of the defaults.  Unknown keys and sections are warned about and
+    /// skipped, bad values are errors.
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        let mut seen: Vec<String> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let fail = |message: String| ConfigError { line: line_number, message };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                section = match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                    Some(name) => name.trim().to_string(),
+                    None => return Err(fail(format!("malformed section header {}", line))),
+                };
+                if !SECTIONS.contains(&section.as_str()) {
+                    warn!("config line {}: unknown section [{}], ignoring it", line_number, section);
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(fail(format!("expected key = value, got {}", line))),
+            };
+            let path = if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) };
+            if seen.contains(&path) {
+                return Err(fail(format!("{} is set twice", path)));
+            }
+            seen.push(path.clone());
+            let value = parse_value(value).map_err(&fail)?;
+            let known = config.set(&section, key, value).map_err(|message| fail(format!("{}: {}", path, message)))?;
+            if !known && SECTIONS.contains(&section.as_str()) {
+                warn!("config line {}: unknown setting {}, ignoring it", line_number, path);
+            }
+        }
+        Ok(config)
+    }
+
+    // Returns false for settings it doesn't know.
+    fn set(&mut self, section: &str, key: &str, value: Value) -> Result<bool, String> {
+        match (section, key) {
+            ("", "model") => {
+                self.model = match value.as_str()? {
+                    "dmg" => HardwareModel::Dmg,
+                    "cgb" => HardwareModel::Cgb,
+                    model => return Err(format!("unknown model \"{}\", expected dmg or cgb", model)),
+                };
+            },
+            ("", "palette") => self.palette = value.as_str()?.parse().map_err(|e| format!("{}", e))?,
+            ("", "scale") => self.scale = value.as_integer(1, 16)? as u32,
+            ("", "save_dir") => self.save_dir = Some(PathBuf::from(value.as_str()?)),
+            ("audio", "enabled") => self.audio.enabled = value.as_bool()?,
+            ("audio", "sample_rate") => self.audio.sample_rate = value.as_integer(8000, 192000)? as u32,
+            ("audio", "volume") => self.audio.volume = value.as_integer(0, 100)? as u8,
+            ("boot_roms", "dmg") => self.dmg_boot_rom = Some(PathBuf::from(value.as_str()?)),
+            ("boot_roms", "cgb") => self.cgb_boot_rom = Some(PathBuf::from(value.as_str()?)),
+            ("boot_roms", "dir") => self.boot_rom_dir = Some(PathBuf::from(value.as_str()?)),
+            ("keys", action) => match Action::named(action) {
+                Some(action) => self.bind(action, value.as_str()?.parse()?),
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The key bound to an action.
+    pub fn key(&self, action: Action) -> Key {
+        self.keys.iter().find(|&&(bound, _)| bound == action).map(|&(_, key)| key).unwrap_or(action.default_key())
+    }
+
+    /// Binds a key to an action.  A key bound to two actions triggers both, which is allowed but
+    /// rarely wanted, so it's warned about.
+    pub fn bind(&mut self, action: Action, key: Key) {
+        for &(other, bound) in &self.keys {
+            if other != action && bound == key {
+                warn!("{} is bound to both {} and {}", key, other.name(), action.name());
+            }
+        }
+        for binding in &mut self.keys {
+            if binding.0 == action {
+                binding.1 = key;
+            }
+        }
+    }
+}
+
+impl Value {
+    fn as_str(&self) -> Result<&str, String> {
+        match *self {
+            Value::String(ref s) => Ok(s),
+            _ => Err("expected a string".to_string()),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match *self {
+            Value::Boolean(value) => Ok(value),
+            _ => Err("expected true or false".to_string()),
+        }
+    }
+
+    fn as_integer(&self, min: i64, max: i64) -> Result<i64, String> {
+        match *self {
+            Value::Integer(value) if (min..=max).contains(&value) => Ok(value),
+            Value::Integer(value) => Err(format!("{} is out of range, expected {} to {}", value, min, max)),
+            _ => Err(format!("expected an integer from {} to {}", min, max)),
+        }
+    }
+}
+
+// Cuts a trailing comment, leaving # inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(open), c) if c == open && !escaped => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {},
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if let Some(literal) = s.strip_prefix('\'') {
+        return match literal.strip_suffix('\'') {
+            Some(literal) if !literal.contains('\'') => Ok(Value::String(literal.to_string())),
+            _ => Err(format!("malformed string {}", s)),
+        };
+    }
+    if let Some(quoted) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if chars.as_str().is_empty() => return Ok(Value::String(value)),
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    _ => return Err(format!("unsupported escape in {}", s)),
+                },
+                c => value.push(c),
+            }
+        }
+        return Err(format!("malformed string {}", s));
+    }
+    match s {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        _ => {},
+    }
+    let number = s.replace('_', "");
+    if let Ok(value) = number.parse() {
+        return Ok(Value::Integer(value));
+    }
+    match number.parse() {
+        Ok(value) if number.contains('.') => Ok(Value::Float(value)),
+        _ => Err(format!("unsupported value {}, expected a string, number or boolean", s)),
+    }
+}
+
+// Writes the settings in config file form.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let string = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let path = |path: &Option<PathBuf>| path.as_ref().map(|path| string(&path.display().to_string()));
+
+        let model = if self.model.is_cgb() { "cgb" } else { "dmg" };
+        writeln!(f, "model = {}", string(model))?;
+        let preset = ["grayscale", "dmg-green", "pocket"].iter()
+            .find(|&&name| DisplayPalette::named(name) == Some(self.palette));
+        let palette = match preset {
+            Some(name) => name.to_string(),
+            None => {
+                let colors: Vec<String> = self.palette.colors.iter()
+                    .map(|color| format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]))
+                    .collect();
+                colors.join(",")
+            },
+        };
+        writeln!(f, "palette = {}", string(&palette))?;
+        writeln!(f, "scale = {}", self.scale)?;
+        if let Some(save_dir) = path(&self.save_dir) {
+            writeln!(f, "save_dir = {}", save_dir)?;
+        }
+
+        writeln!(f, "\n[audio]")?;
+        writeln!(f, "enabled = {}", self.audio.enabled)?;
+        writeln!(f, "sample_rate = {}", self.audio.sample_rate)?;
+        writeln!(f, "volume = {}", self.audio.volume)?;
+
+        writeln!(f, "\n[boot_roms]")?;
+        if let Some(dmg) = path(&self.dmg_boot_rom) {
+            writeln!(f, "dmg = {}", dmg)?;
+        }
+        if let Some(cgb) = path(&self.cgb_boot_rom) {
+            writeln!(f, "cgb = {}", cgb)?;
+        }
+        if let Some(dir) = path(&self.boot_rom_dir) {
+            writeln!(f, "dir = {}", dir)?;
+        }
+
+        writeln!(f, "\n[keys]")?;
+        for &(action, key) in &self.keys {
+            writeln!(f, "{} = {}", action.name(), string(&key.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn error(text: &str) -> ConfigError {
+        Config::parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn the_default_template_gives_the_defaults() {
+        assert_eq!(Config::parse(DEFAULT_TEMPLATE).unwrap(), Config::default());
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parses_a_sample_config() {
+        let config = Config::parse(r##"
+            model = "cgb"   # trailing comment
+            palette = "#e0f8d0, #88c070, #346856, #081820"
+            scale = 4
+            save_dir = 'C:\saves'
+
+            [audio]
+            enabled = false
+            sample_rate = 44_100
+            volume = 35
+
+            [boot_roms]
+            dmg = "/roms/dmg \"boot\".bin"
+            dir = "/roms"
+
+            [keys]
+            a = "K"
+            turbo = "f5"
+            screenshot = "esc"
+        "##).unwrap();
+
+        assert_eq!(config.model, HardwareModel::Cgb);
+        assert_eq!(config.palette.colors, [[0xE0, 0xF8, 0xD0], [0x88, 0xC0, 0x70], [0x34, 0x68, 0x56], [0x08, 0x18, 0x20]]);
+        assert_eq!(config.scale, 4);
+        assert_eq!(config.save_dir, Some(PathBuf::from("C:\\saves")));
+        assert_eq!(config.audio, AudioConfig { enabled: false, sample_rate: 44100, volume: 35 });
+        assert_eq!(config.dmg_boot_rom, Some(PathBuf::from("/roms/dmg \"boot\".bin")));
+        assert_eq!(config.cgb_boot_rom, None);
+        assert_eq!(config.boot_rom_dir, Some(PathBuf::from("/roms")));
+        assert_eq!(config.key(Action::A), Key::Char('K'));
+        assert_eq!(config.key(Action::Turbo), Key::F(5));
+        assert_eq!(config.key(Action::Screenshot), Key::Escape);
+        assert_eq!(config.key(Action::B), Key::Char('Z'));
+    }
+
+    #[test]
+    fn printing_a_config_reads_back_the_same() {
+        let mut config = Config::parse("palette = \"pocket\"\nsave_dir = \"/a \\\\ b\"\n[keys]\nup = \"w\"").unwrap();
+        assert_eq!(Config::parse(&config.to_string()).unwrap(), config);
+        config.palette = "#010203,#040506,#070809,#0a0b0c".parse().unwrap();
+        config.cgb_boot_rom = Some(PathBuf::from("/boot/cgb.bin"));
+        assert_eq!(Config::parse(&config.to_string()).unwrap(), config);
+    }
+
+    #[test]
+    fn unknown_keys_and_sections_are_skipped() {
+        let config = Config::parse("colour = \"red\"\n[video]\nshader = \"crt\"\n[keys]\njump = \"j\"\n[audio]\nvolume = 50").unwrap();
+        assert_eq!(config.audio.volume, 50);
+        assert_eq!(config.key(Action::A), Key::Char('X'));
+    }
+
+    #[test]
+    fn bad_values_say_where_and_why() {
+        assert_eq!(error("model = \"gba\"").to_string(), "line 1: model: unknown model \"gba\", expected dmg or cgb");
+        assert_eq!(error("\n\nscale = 0").to_string(), "line 3: scale: 0 is out of range, expected 1 to 16");
+        assert_eq!(error("scale = \"big\"").message, "scale: expected an integer from 1 to 16");
+        assert_eq!(error("scale = 2.5").message, "scale: expected an integer from 1 to 16");
+        assert_eq!(error("[audio]\nenabled = 1").message, "audio.enabled: expected true or false");
+        assert_eq!(error("[audio]\nsample_rate = 1000").line, 2);
+        assert_eq!(error("[boot_roms]\ndmg = 7").message, "boot_roms.dmg: expected a string");
+        assert!(error("palette = \"sepia\"").message.contains("unknown preset \"sepia\""));
+        assert!(error("palette = \"#000000,#ffffff\"").message.contains("expected 4 colors, got 2"));
+    }
+
+    #[test]
+    fn bad_syntax_is_an_error() {
+        assert_eq!(error("model").message, "expected key = value, got model");
+        assert_eq!(error("[audio").message, "malformed section header [audio");
+        assert_eq!(error("model = \"dmg").message, "malformed string \"dmg");
+        assert_eq!(error("model = 'dmg").message, "malformed string 'dmg");
+        assert_eq!(error("model = \"a\\qb\"").message, "unsupported escape in \"a\\qb\"");
+        assert_eq!(error("scale = three").message, "unsupported value three, expected a string, number or boolean");
+        assert_eq!(error("scale = 2\nscale = 3").to_string(), "line 2: scale is set twice");
+
+        // The same key in two sections is fine
+        assert!(Config::parse("[keys]\nup = \"w\"\n[other]\nup = 1").is_ok());
+    }
+
+    #[test]
+    fn key_names_are_checked() {
+        for &(name, key) in &[("a", Key::Char('A')), ("Z", Key::Char('Z')), ("7", Key::Char('7')), ("F1", Key::F(1)),
+                              ("f12", Key::F(12)), ("Space", Key::Space), ("esc", Key::Escape), ("ctrl", Key::Ctrl)] {
+            assert_eq!(name.parse::<Key>(), Ok(key), "{}", name);
+        }
+        for &name in &["", "f0", "f13", "ff", "-", "é", "leftshift", "up "] {
+            let message = name.parse::<Key>().unwrap_err();
+            assert!(message.starts_with(&format!("unknown key \"{}\", expected a letter", name)), "{}", message);
+        }
+        assert_eq!(error("[keys]\nstart = \"return\"").to_string(),
+                   "line 2: keys.start: unknown key \"return\", expected a letter, digit, f1-f12, up, down, left, right, \
+                    space, enter, escape, tab, backspace, shift, ctrl or alt");
+    }
+
+    #[test]
+    fn keys_print_as_they_parse() {
+        for key in Action::ALL.iter().map(|&action| action.default_key()).chain(vec![Key::Char('Q'), Key::F(3), Key::Alt]) {
+            assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+        }
+        assert_eq!(Key::Escape.to_string(), "escape");
+    }
+}
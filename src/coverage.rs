@@ -0,0 +1,157 @@
+//! Which parts of the ROM ran as code
+//!
+//! One bit per ROM byte, set for every byte of each instruction fetched, so a bank costs 2KB
+//! and recording an instruction is a few bit sets.  The report lists the covered ranges of each
+//! bank as the CPU addresses them, and reads back in so the disassembler can tell code from
+//! data.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use cart::BANK_SIZE;
+use error::FaroreError;
+
+
+/// The ROM bytes executed so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    bits: Vec<u64>,
+    banks: usize,
+}
+
+impl Coverage {
+    /// Nothing covered yet, for a ROM of `rom_size` bytes.
+    pub fn new(rom_size: usize) -> Self {
+        let banks = rom_size.div_ceil(BANK_SIZE).max(1);
+        Coverage { bits: vec![0; banks * BANK_SIZE / 64], banks }
+    }
+
+    /// How many banks are tracked.
+    pub fn banks(&self) -> usize {
+        self.banks
+    }
+
+    /// Marks the `length` bytes of an instruction fetched from `address`, with `bank` mapped
+    /// there.  Anything outside the ROM is ignored.
+    pub fn record(&mut self, bank: usize, address: u16, length: usize) {
+        for address in (address as usize..address as usize + length).take_while(|&address| address < 0x8000) {
+            if let Some(bit) = self.bit(bank, address as u16) {
+                self.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+    }
+
+    /// Whether the byte at `address`, with `bank` mapped there, has run.
+    pub fn contains(&self, bank: usize, address: u16) -> bool {
+        self.bit(bank, address).is_some_and(|bit| self.bits[bit / 64] & 1 << (bit % 64) != 0)
+    }
+
+    /// The covered runs of `bank`, as the CPU addresses them.
+    pub fn ranges(&self, bank: usize) -> Vec<RangeInclusive<u16>> {
+        let base = window(bank);
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for address in base..base + BANK_SIZE as u16 {
+            match (self.contains(bank, address), start) {
+                (true, None) => start = Some(address),
+                (false, Some(first)) => {
+                    ranges.push(first..=address - 1);
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+        if let Some(first) = start {
+            ranges.push(first..=base + (BANK_SIZE - 1) as u16);
+        }
+        ranges
+    }
+
+    /// How many bytes of `bank` have run.
+    pub fn covered_bytes(&self, bank: usize) -> usize {
+        let words = BANK_SIZE / 64;
+        self.bits.get(bank * words..(bank + 1) * words)
+            .map_or(0, |bits| bits.iter().map(|word| word.count_ones() as usize).sum())
+    }
+
+    /// The share of the whole ROM that has run, out of 100.
+    pub fn percentage(&self) -> f64 {
+        let covered: usize = (0..self.banks).map(|bank| self.covered_bytes(bank)).sum();
+        covered as f64 * 100.0 / (self.banks * BANK_SIZE) as f64
+    }
+
+    /// Reads a report back in.
+    pub fn parse(text: &str) -> Result<Self, FaroreError> {
+        let mut coverage = Coverage::new(0);
+        let mut bank = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let invalid = || FaroreError::InvalidArgument(format!("line {} of the coverage report is invalid: {}", number + 1, line));
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("bank ") {
+                let digits = rest.split_whitespace().next().ok_or_else(invalid)?;
+                let number = usize::from_str_radix(digits, 16).map_err(|_| invalid())?;
+                coverage.grow(number + 1).ok_or_else(invalid)?;
+                bank = Some(number);
+                continue;
+            }
+            let bank = bank.ok_or_else(invalid)?;
+            let (first, last) = line.split_once('-').unwrap_or((line, line));
+            let first = u16::from_str_radix(first, 16).map_err(|_| invalid())?;
+            let last = u16::from_str_radix(last, 16).map_err(|_| invalid())?;
+            let base = window(bank);
+            if first < base || first > last || last - base >= BANK_SIZE as u16 {
+                return Err(invalid());
+            }
+            coverage.record(bank, first, (last - first) as usize + 1);
+        }
+        Ok(coverage)
+    }
+
+    // Banks past the ROM's end only show up in reports for a bigger ROM, capped so a bogus bank
+    // number can't eat all the memory.
+    fn grow(&mut self, banks: usize) -> Option<()> {
+        if banks > 0x200 {
+            return None;
+        }
+        if banks > self.banks {
+            self.bits.resize(banks * BANK_SIZE / 64, 0);
+            self.banks = banks;
+        }
+        Some(())
+    }
+
+    // Bank 0 is always at 0000-3FFF, other banks at 4000-7FFF.
+    fn bit(&self, bank: usize, address: u16) -> Option<usize> {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF if bank > 0 => bank,
+            _ => return None,
+        };
+        if bank >= self.banks {
+            return None;
+        }
+        Some(bank * BANK_SIZE + (address as usize & (BANK_SIZE - 1)))
+    }
+}
+
+// Where the CPU sees `bank`.
+fn window(bank: usize) -> u16 {
+    if bank == 0 { 0x0000 } else { 0x4000 }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "; {:.1}% of {} bytes ran as code", self.percentage(), self.banks * BANK_SIZE)?;
+        for bank in 0..self.banks {
+            let percentage = self.covered_bytes(bank) as f64 * 100.0 / BANK_SIZE as f64;
+            writeln!(f, "bank {:02x}  {:.1}%", bank, percentage)?;
+            for range in self.ranges(bank) {
+                writeln!(f, "  {:04x}-{:04x}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
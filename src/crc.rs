@@ -0,0 +1,54 @@
+//! CRC-32 (ISO-HDLC, as used by PNG, zip and friends), and zlib's Adler-32
+
+
+/// An incremental CRC-32.
+#[derive(Debug, Copy, Clone)]
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    /// A CRC over no data yet.
+    pub fn new() -> Self {
+        Crc32 { value: 0xFFFF_FFFF }
+    }
+
+    /// Feeds in more data.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.value ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (self.value & 1).wrapping_neg();
+                self.value = (self.value >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    /// The CRC of everything fed in so far.
+    pub fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+/// The CRC-32 of a single buffer.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// The Adler-32 checksum that ends a zlib stream.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,374 @@
+//! Debugger commands
+//!
+//! One command per line, a name followed by arguments split on whitespace.  Numbers are hex
+//! with a 0x or $ prefix and decimal otherwise, and anywhere an address goes a label from the
+//! symbol file can go instead.  Parsing stays apart from running the commands, so a script of
+//! command lines can be checked without a terminal.
+
+use error::FaroreError;
+use ppu::dump::TileMapSelect;
+
+
+/// What the `help` command prints.
+pub const HELP: &str = "\
+s, step [N]                  Run N instructions, 1 by default
+n, next                      Run an instruction, running a whole CALL as one
+c, continue                  Run until a breakpoint or watchpoint is hit
+b, break LOC [if REG OP N]   Stop before running LOC, when REG compares true against N.
+                             OP is one of == != < <= > >=
+w, watch LOC [r|w|rw]        Stop when LOC is read, written or either, written by default
+d, delete [N]                Remove breakpoint or watchpoint N, or all of them
+r, regs                      Show the registers
+x LOC [LEN]                  Hexdump LEN bytes from LOC, 64 by default
+u LOC [COUNT]                Disassemble COUNT instructions from LOC, 10 by default
+u                            Disassemble from PC
+bt, backtrace                Show the return addresses of the CALLs being run
+oam                          Show the sprites
+tiles [PATH]                 Show the tile data, or save the tile sheet to PATH as a PNG
+map [bg|win|9800|9c00] [PATH]
+                             Save a tile map as a PNG with the screen and window outlined,
+                             the background's map by default, to map.png by default
+pal, palettes                Show the palettes
+banks                        Show the banks mapped in
+save PATH                    Save the machine state to PATH
+load PATH                    Load the machine state from PATH
+h, help                      Show this
+q, quit                      Leave the debugger
+
+LOC is an address, 0x150 or $150 in hex or 336 in decimal, or a label from the symbol file.";
+
+const DEFAULT_EXAMINE_LENGTH: u16 = 64;
+const DEFAULT_DISASSEMBLE_COUNT: u16 = 10;
+const DEFAULT_MAP_PATH: &str = "map.png";
+
+/// A place in memory, as typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// A CPU address.
+    Address(u16),
+    /// A label, looked up in the symbol table when the command runs.
+    Label(String),
+}
+
+/// A CPU register a breakpoint condition can test.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    /// The accumulator.
+    A,
+    /// The flags.
+    F,
+    /// B.
+    B,
+    /// C.
+    C,
+    /// D.
+    D,
+    /// E.
+    E,
+    /// H.
+    H,
+    /// L.
+    L,
+    /// A and the flags together.
+    AF,
+    /// B and C together.
+    BC,
+    /// D and E together.
+    DE,
+    /// H and L together.
+    HL,
+    /// The stack pointer.
+    SP,
+    /// The program counter.
+    PC,
+}
+
+impl Register {
+    /// Whether it holds 16 bits rather than 8.
+    pub fn is_pair(self) -> bool {
+        matches!(self, Register::AF | Register::BC | Register::DE | Register::HL | Register::SP | Register::PC)
+    }
+}
+
+/// How a condition compares a register against its value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Whether `left` compares true against `right`.
+    pub fn holds(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// A breakpoint's condition, such as `a == 0x03`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Condition {
+    /// The register tested.
+    pub register: Register,
+    /// How it's compared.
+    pub comparison: Comparison,
+    /// What it's compared against, no wider than the register.
+    pub value: u16,
+}
+
+/// What a watchpoint stops on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// Reads only, `r`.
+    Read,
+    /// Writes only, `w`.
+    Write,
+    /// Both, `rw`.
+    ReadWrite,
+}
+
+/// A parsed debugger command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// `s`: run this many instructions.
+    Step(u32),
+    /// `n`: run an instruction, running a CALL through to its return.
+    StepOver,
+    /// `c`: run until something stops it.
+    Continue,
+    /// `b`: stop before running the instruction at `location`, if `condition` holds.
+    Break {
+        /// Where to stop.
+        location: Location,
+        /// Only stop when this holds.
+        condition: Option<Condition>,
+    },
+    /// `w`: stop when `location` is accessed.
+    Watch {
+        /// The byte watched.
+        location: Location,
+        /// The accesses that stop.
+        access: Access,
+    },
+    /// `d`: remove a breakpoint or watchpoint by number, or all of them.
+    Delete(Option<usize>),
+    /// `r`: show the registers.
+    Registers,
+    /// `x`: hexdump memory.
+    Examine {
+        /// Where to start.
+        location: Location,
+        /// How many bytes to show.
+        length: u16,
+    },
+    /// `u`: disassemble.
+    Disassemble {
+        /// Where to start, or None for PC.
+        location: Option<Location>,
+        /// How many instructions to show.
+        count: u16,
+    },
+    /// `bt`: show the call stack.
+    Backtrace,
+    /// `oam`: show the sprites.
+    Oam,
+    /// `tiles`: show the tile data, or save the tile sheet as a PNG.
+    Tiles(Option<String>),
+    /// `map`: save a whole tile map as a PNG.
+    TileMap {
+        /// Which map.
+        which: TileMapSelect,
+        /// Where to save it.
+        path: String,
+    },
+    /// `pal`: show the palettes.
+    Palettes,
+    /// `banks`: show the banks mapped in.
+    Banks,
+    /// `save`: save the machine state to a file.
+    SaveState(String),
+    /// `load`: load the machine state from a file.
+    LoadState(String),
+    /// `help`: show the commands.
+    Help,
+    /// `q`: leave the debugger.
+    Quit,
+}
+
+impl DebugCommand {
+    /// Parses a command line.  Fails with `InvalidArgument` on an unknown command, missing or
+    /// extra arguments and numbers out of range.
+    pub fn parse(line: &str) -> Result<Self, FaroreError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Err(invalid("no command given".to_string())),
+        };
+        let arity = |min: usize, max: usize| {
+            if args.len() < min || args.len() > max {
+                let wanted = match (min, max) {
+                    (1, 1) => "1 argument".to_string(),
+                    (min, max) if min == max => format!("{} arguments", min),
+                    (min, max) => format!("{} to {} arguments", min, max),
+                };
+                return Err(invalid(format!("{} takes {}, not {}", name, wanted, args.len())));
+            }
+            Ok(())
+        };
+
+        Ok(match name {
+            "s" | "step" => {
+                arity(0, 1)?;
+                match args.first() {
+                    Some(count) => DebugCommand::Step(number(count, 1, u32::MAX as usize)? as u32),
+                    None => DebugCommand::Step(1),
+                }
+            },
+            "n" | "next" => arity(0, 0).map(|_| DebugCommand::StepOver)?,
+            "c" | "continue" => arity(0, 0).map(|_| DebugCommand::Continue)?,
+            "b" | "break" => {
+                let condition = match args.get(1) {
+                    None => None,
+                    Some(&"if") if args.len() == 5 => Some(condition(&args[2..])?),
+                    Some(_) => return Err(invalid(format!("expected {} LOC [if REG OP N]", name))),
+                };
+                arity(1, 5)?;
+                DebugCommand::Break { location: location(args[0])?, condition }
+            },
+            "w" | "watch" => {
+                arity(1, 2)?;
+                let access = match args.get(1).copied() {
+                    None | Some("w") => Access::Write,
+                    Some("r") => Access::Read,
+                    Some("rw") => Access::ReadWrite,
+                    Some(other) => return Err(invalid(format!("{} isn't r, w or rw", other))),
+                };
+                DebugCommand::Watch { location: location(args[0])?, access }
+            },
+            "d" | "delete" => {
+                arity(0, 1)?;
+                DebugCommand::Delete(args.first().map(|index| number(index, 0, usize::MAX)).transpose()?)
+            },
+            "r" | "regs" => arity(0, 0).map(|_| DebugCommand::Registers)?,
+            "x" => {
+                arity(1, 2)?;
+                let length = match args.get(1) {
+                    Some(length) => number(length, 1, u16::MAX as usize)? as u16,
+                    None => DEFAULT_EXAMINE_LENGTH,
+                };
+                DebugCommand::Examine { location: location(args[0])?, length }
+            },
+            "u" => {
+                arity(0, 2)?;
+                let count = match args.get(1) {
+                    Some(count) => number(count, 1, u16::MAX as usize)? as u16,
+                    None => DEFAULT_DISASSEMBLE_COUNT,
+                };
+                DebugCommand::Disassemble { location: args.first().map(|arg| location(arg)).transpose()?, count }
+            },
+            "bt" | "backtrace" => arity(0, 0).map(|_| DebugCommand::Backtrace)?,
+            "oam" => arity(0, 0).map(|_| DebugCommand::Oam)?,
+            "tiles" => arity(0, 1).map(|_| DebugCommand::Tiles(args.first().map(|path| path.to_string())))?,
+            "map" => {
+                arity(0, 2)?;
+                let which = match args.first().copied() {
+                    Some("bg") => Some(TileMapSelect::Background),
+                    Some("win") => Some(TileMapSelect::Window),
+                    Some("9800") => Some(TileMapSelect::Map9800),
+                    Some("9c00") | Some("9C00") => Some(TileMapSelect::Map9C00),
+                    _ => None,
+                };
+                let rest = if which.is_some() { &args[1..] } else { args };
+                if rest.len() > 1 {
+                    return Err(invalid(format!("{} isn't bg, win, 9800 or 9c00", args[0])));
+                }
+                DebugCommand::TileMap {
+                    which: which.unwrap_or(TileMapSelect::Background),
+                    path: rest.first().map_or(DEFAULT_MAP_PATH, |path| *path).to_string(),
+                }
+            },
+            "pal" | "palettes" => arity(0, 0).map(|_| DebugCommand::Palettes)?,
+            "banks" => arity(0, 0).map(|_| DebugCommand::Banks)?,
+            "save" => arity(1, 1).map(|_| DebugCommand::SaveState(args[0].to_string()))?,
+            "load" => arity(1, 1).map(|_| DebugCommand::LoadState(args[0].to_string()))?,
+            "h" | "help" => DebugCommand::Help,
+            "q" | "quit" => arity(0, 0).map(|_| DebugCommand::Quit)?,
+            _ => return Err(invalid(format!("unknown command {}, try help", name))),
+        })
+    }
+}
+
+fn invalid(reason: String) -> FaroreError {
+    FaroreError::InvalidArgument(reason)
+}
+
+// A number from `min` to `max`, in hex with a 0x or $ prefix or in decimal.
+pub(crate) fn number(s: &str, min: usize, max: usize) -> Result<usize, FaroreError> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).or_else(|| s.strip_prefix('$')) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    match parsed {
+        Some(n) if n >= min && n <= max => Ok(n),
+        Some(_) => Err(invalid(format!("{} isn't from {} to {}", s, min, max))),
+        None => Err(invalid(format!("{} isn't a number", s))),
+    }
+}
+
+// Labels can't start with a digit, so anything that does is an address.
+pub(crate) fn location(s: &str) -> Result<Location, FaroreError> {
+    if s.starts_with(|c: char| c.is_ascii_digit() || c == '$') {
+        return Ok(Location::Address(number(s, 0, 0xFFFF)? as u16));
+    }
+    Ok(Location::Label(s.to_string()))
+}
+
+fn condition(words: &[&str]) -> Result<Condition, FaroreError> {
+    let register = match words[0].to_ascii_lowercase().as_str() {
+        "a" => Register::A,
+        "f" => Register::F,
+        "b" => Register::B,
+        "c" => Register::C,
+        "d" => Register::D,
+        "e" => Register::E,
+        "h" => Register::H,
+        "l" => Register::L,
+        "af" => Register::AF,
+        "bc" => Register::BC,
+        "de" => Register::DE,
+        "hl" => Register::HL,
+        "sp" => Register::SP,
+        "pc" => Register::PC,
+        other => return Err(invalid(format!("{} isn't a register", other))),
+    };
+    let max = if register.is_pair() { 0xFFFF } else { 0xFF };
+    Ok(Condition { register, comparison: comparison(words[1])?, value: number(words[2], 0, max)? as u16 })
+}
+
+pub(crate) fn comparison(s: &str) -> Result<Comparison, FaroreError> {
+    Ok(match s {
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::Greater,
+        ">=" => Comparison::GreaterOrEqual,
+        other => return Err(invalid(format!("{} isn't one of == != < <= > >=", other))),
+    })
+}
@@ -0,0 +1,358 @@
+//! SM83 disassembler
+//!
+//! Opcodes are decoded from their octal fields (xx yyy zzz), which lays the instruction set out
+//! in a handful of regular groups.  The syntax follows RGBDS.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+use coverage::Coverage;
+use symbols::SymbolTable;
+
+
+const R: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const RP: [&str; 4] = ["bc", "de", "hl", "sp"];
+const RP2: [&str; 4] = ["bc", "de", "hl", "af"];
+const CC: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp"];
+const ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+/// Where execution can go after an instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flow {
+    /// Falls through to the next instruction.
+    Next,
+    /// Conditional jump or any call, so it may also fall through.
+    Branch(u16),
+    /// Unconditional jump, never falls through.
+    Jump(u16),
+    /// Return or JP HL, the target isn't known.
+    End,
+    /// Not an instruction, shown as a data byte.
+    Invalid,
+}
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// Where it starts.
+    pub address: u16,
+    /// Its opcode and operands.
+    pub bytes: Vec<u8>,
+    /// RGBDS syntax, with jump targets as $addresses.
+    pub text: String,
+    /// Where execution goes next.
+    pub flow: Flow,
+}
+
+impl Instruction {
+    /// The jump or call target, if it's known.
+    pub fn target(&self) -> Option<u16> {
+        match self.flow {
+            Flow::Branch(target) | Flow::Jump(target) => Some(target),
+            Flow::Next | Flow::End | Flow::Invalid => None,
+        }
+    }
+
+    /// Whether execution never falls through to the next address.
+    pub fn ends_block(&self) -> bool {
+        matches!(self.flow, Flow::Jump(_) | Flow::End | Flow::Invalid)
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which sits at `address`.  When `bytes`
+/// runs out partway through an instruction, its first byte comes back as data.
+pub fn decode(bytes: &[u8], address: u16) -> Instruction {
+    let opcode = match bytes.first() {
+        Some(&opcode) => opcode,
+        None => return data(&[], address),
+    };
+    let (x, y, z) = (opcode >> 6, ((opcode >> 3) & 7) as usize, (opcode & 7) as usize);
+    let (p, q) = (y >> 1, y & 1);
+
+    let d8 = bytes.get(1).cloned();
+    let d16 = match (bytes.get(1), bytes.get(2)) {
+        (Some(&low), Some(&high)) => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    };
+    let relative = d8.map(|offset| address.wrapping_add(2).wrapping_add(offset as i8 as u16));
+
+    // Builds the result once the operands' length is known, falling back to data if they're
+    // cut off
+    let done = |length: usize, text: String, flow: Flow| -> Instruction {
+        if bytes.len() < length {
+            return data(bytes, address);
+        }
+        Instruction { address, bytes: bytes[..length].to_vec(), text, flow }
+    };
+    let imm8 = || d8.map(|value| format!("${:02x}", value)).unwrap_or_default();
+    let imm16 = || d16.map(|value| format!("${:04x}", value)).unwrap_or_default();
+    let signed = || d8.map(|value| {
+        let value = value as i8;
+        if value < 0 { format!("-${:02x}", -(value as i16)) } else { format!("${:02x}", value) }
+    }).unwrap_or_default();
+    let to_rel = || format!("${:04x}", relative.unwrap_or(0));
+
+    match (x, z) {
+        (0, 0) => match y {
+            0 => done(1, "nop".to_string(), Flow::Next),
+            1 => done(3, format!("ld [{}], sp", imm16()), Flow::Next),
+            2 => done(2, "stop".to_string(), Flow::Next),
+            3 => done(2, format!("jr {}", to_rel()), Flow::Jump(relative.unwrap_or(0))),
+            _ => done(2, format!("jr {}, {}", CC[y - 4], to_rel()), Flow::Branch(relative.unwrap_or(0))),
+        },
+        (0, 1) if q == 0 => done(3, format!("ld {}, {}", RP[p], imm16()), Flow::Next),
+        (0, 1) => done(1, format!("add hl, {}", RP[p]), Flow::Next),
+        (0, 2) => {
+            let memory = ["[bc]", "[de]", "[hl+]", "[hl-]"][p];
+            let text = if q == 0 { format!("ld {}, a", memory) } else { format!("ld a, {}", memory) };
+            done(1, text, Flow::Next)
+        },
+        (0, 3) => done(1, format!("{} {}", if q == 0 { "inc" } else { "dec" }, RP[p]), Flow::Next),
+        (0, 4) => done(1, format!("inc {}", R[y]), Flow::Next),
+        (0, 5) => done(1, format!("dec {}", R[y]), Flow::Next),
+        (0, 6) => done(2, format!("ld {}, {}", R[y], imm8()), Flow::Next),
+        (0, _) => {
+            let text = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y];
+            done(1, text.to_string(), Flow::Next)
+        },
+        (1, _) if y == 6 && z == 6 => done(1, "halt".to_string(), Flow::Next),
+        (1, _) => done(1, format!("ld {}, {}", R[y], R[z]), Flow::Next),
+        (2, _) => done(1, format!("{} {}", ALU[y], R[z]), Flow::Next),
+        (_, 0) => match y {
+            0..=3 => done(1, format!("ret {}", CC[y]), Flow::Next),
+            4 => done(2, format!("ldh [$ff{:02x}], a", d8.unwrap_or(0)), Flow::Next),
+            5 => done(2, format!("add sp, {}", signed()), Flow::Next),
+            6 => done(2, format!("ldh a, [$ff{:02x}]", d8.unwrap_or(0)), Flow::Next),
+            _ => {
+                let offset = signed();
+                let text = match offset.strip_prefix('-') {
+                    Some(magnitude) => format!("ld hl, sp - {}", magnitude),
+                    None => format!("ld hl, sp + {}", offset),
+                };
+                done(2, text, Flow::Next)
+            },
+        },
+        (_, 1) if q == 0 => done(1, format!("pop {}", RP2[p]), Flow::Next),
+        (_, 1) => match p {
+            0 => done(1, "ret".to_string(), Flow::End),
+            1 => done(1, "reti".to_string(), Flow::End),
+            2 => done(1, "jp hl".to_string(), Flow::End),
+            _ => done(1, "ld sp, hl".to_string(), Flow::Next),
+        },
+        (_, 2) => match y {
+            0..=3 => done(3, format!("jp {}, {}", CC[y], imm16()), Flow::Branch(d16.unwrap_or(0))),
+            4 => done(1, "ldh [c], a".to_string(), Flow::Next),
+            5 => done(3, format!("ld [{}], a", imm16()), Flow::Next),
+            6 => done(1, "ldh a, [c]".to_string(), Flow::Next),
+            _ => done(3, format!("ld a, [{}]", imm16()), Flow::Next),
+        },
+        (_, 3) => match y {
+            0 => done(3, format!("jp {}", imm16()), Flow::Jump(d16.unwrap_or(0))),
+            1 => match d8 {
+                Some(cb) => done(2, decode_cb(cb), Flow::Next),
+                None => data(bytes, address),
+            },
+            6 => done(1, "di".to_string(), Flow::Next),
+            7 => done(1, "ei".to_string(), Flow::Next),
+            _ => data(bytes, address),
+        },
+        (_, 4) if y < 4 => done(3, format!("call {}, {}", CC[y], imm16()), Flow::Branch(d16.unwrap_or(0))),
+        (_, 5) if q == 0 => done(1, format!("push {}", RP2[p]), Flow::Next),
+        (_, 5) if p == 0 => done(3, format!("call {}", imm16()), Flow::Branch(d16.unwrap_or(0))),
+        (_, 6) => done(2, format!("{} {}", ALU[y], imm8()), Flow::Next),
+        (_, 7) => done(1, format!("rst ${:02x}", y * 8), Flow::Branch(y as u16 * 8)),
+        _ => data(bytes, address),
+    }
+}
+
+fn decode_cb(opcode: u8) -> String {
+    let (x, y, z) = (opcode >> 6, ((opcode >> 3) & 7) as usize, (opcode & 7) as usize);
+    match x {
+        0 => format!("{} {}", ROT[y], R[z]),
+        1 => format!("bit {}, {}", y, R[z]),
+        2 => format!("res {}, {}", y, R[z]),
+        _ => format!("set {}, {}", y, R[z]),
+    }
+}
+
+// A byte that isn't an instruction.
+fn data(bytes: &[u8], address: u16) -> Instruction {
+    let byte = bytes.first().cloned().unwrap_or(0);
+    Instruction { address, bytes: vec![byte], text: format!("db ${:02x}", byte), flow: Flow::Invalid }
+}
+
+/// The CPU's view of the ROM with one bank mapped at 4000-7FFF, as after a bank switch.
+pub struct BankView<'a> {
+    rom: &'a [u8],
+    bank: usize,
+}
+
+impl<'a> BankView<'a> {
+    /// Maps `bank` at 4000-7FFF.  Bank 0 is always at 0000-3FFF.
+    pub fn new(rom: &'a [u8], bank: usize) -> Self {
+        BankView { rom, bank }
+    }
+
+    /// The bytes from `address` up to the end of the ROM area, empty past the end of the ROM.
+    pub fn bytes_from(&self, address: u16) -> &'a [u8] {
+        // Saturating, so an absurd bank number just maps nothing
+        let base = self.bank.saturating_mul(0x4000);
+        let (offset, end) = match address {
+            0x0000..=0x3FFF => (address as usize, 0x4000),
+            0x4000..=0x7FFF => (base.saturating_add(address as usize - 0x4000), base.saturating_add(0x4000)),
+            _ => return &[],
+        };
+        let end = end.min(self.rom.len());
+        if offset >= end { &[] } else { &self.rom[offset..end] }
+    }
+
+    /// The bank behind `address`.
+    pub fn bank_at(&self, address: u16) -> usize {
+        if address < 0x4000 { 0 } else { self.bank }
+    }
+
+    /// Whether there's ROM behind `address`.
+    pub fn is_mapped(&self, address: u16) -> bool {
+        !self.bytes_from(address).is_empty()
+    }
+}
+
+/// Decoded instructions, ready to print with labels on the jump targets they contain.
+pub struct Listing {
+    instructions: BTreeMap<u16, Instruction>,
+    names: BTreeMap<u16, String>,
+}
+
+impl Listing {
+    /// Decodes one instruction after another through `range`.  After a jump or return the
+    /// next instruction starts a new block.
+    pub fn linear(view: &BankView, range: Range<u16>) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut address = range.start;
+        while address < range.end && view.is_mapped(address) {
+            let instruction = decode(view.bytes_from(address), address);
+            let next = address.wrapping_add(instruction.bytes.len() as u16);
+            instructions.insert(address, instruction);
+            if next <= address {
+                break;
+            }
+            address = next;
+        }
+        Listing { instructions, names: BTreeMap::new() }
+    }
+
+    /// Like `linear`, but only decodes where `coverage` saw code run.  Everything else is shown
+    /// as data.
+    pub fn covered(view: &BankView, range: Range<u16>, coverage: &Coverage) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut address = range.start;
+        while address < range.end && view.is_mapped(address) {
+            let bytes = view.bytes_from(address);
+            let instruction = if coverage.contains(view.bank_at(address), address) {
+                decode(bytes, address)
+            } else {
+                data(bytes, address)
+            };
+            let next = address.wrapping_add(instruction.bytes.len() as u16);
+            instructions.insert(address, instruction);
+            if next <= address {
+                break;
+            }
+            address = next;
+        }
+        Listing { instructions, names: BTreeMap::new() }
+    }
+
+    /// Traces the code reachable from `start`, following jumps and calls into mapped ROM,
+    /// until `budget` instructions have been decoded.
+    pub fn follow(view: &BankView, start: u16, budget: usize) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut pending = vec![start];
+        while let Some(mut address) = pending.pop() {
+            while instructions.len() < budget && view.is_mapped(address) && !instructions.contains_key(&address) {
+                let instruction = decode(view.bytes_from(address), address);
+                if let Some(target) = instruction.target() {
+                    pending.push(target);
+                }
+                let (next, ends_block) = (address.wrapping_add(instruction.bytes.len() as u16), instruction.ends_block());
+                instructions.insert(address, instruction);
+                if ends_block {
+                    break;
+                }
+                address = next;
+            }
+        }
+        Listing { instructions, names: BTreeMap::new() }
+    }
+
+    /// Labels instructions and jump targets with the names in `symbols`, instead of the
+    /// generated `.l_` ones.
+    pub fn name_from(&mut self, symbols: &SymbolTable, view: &BankView) {
+        let addresses = self.instructions.values()
+            .flat_map(|instruction| Some(instruction.address).into_iter().chain(instruction.target()));
+        let names: Vec<(u16, String)> = addresses
+            .filter_map(|address| symbols.name(view.bank_at(address), address).map(|name| (address, name.to_string())))
+            .collect();
+        self.names.extend(names);
+    }
+
+    /// How many instructions were decoded.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether nothing was decoded.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    fn labels(&self) -> BTreeSet<u16> {
+        self.instructions.values()
+            .filter_map(Instruction::target)
+            .filter(|target| self.instructions.contains_key(target))
+            .collect()
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let labels = self.labels();
+        let mut previous: Option<&Instruction> = None;
+        for instruction in self.instructions.values() {
+            let address = instruction.address;
+            // A gap in traced code, or the end of a block, starts a new paragraph.  Runs of data
+            // bytes stay together.
+            let contiguous = previous.is_some_and(|previous| {
+                let ends_block = previous.ends_block() && previous.flow != Flow::Invalid;
+                previous.address as usize + previous.bytes.len() == address as usize && !ends_block
+            });
+            if previous.is_some() && !contiguous {
+                writeln!(f)?;
+            }
+            if let Some(name) = self.names.get(&address) {
+                writeln!(f, "{}:", name)?;
+            } else if labels.contains(&address) {
+                writeln!(f, ".l_{:04x}:", address)?;
+            }
+
+            let mut text = instruction.text.clone();
+            if let Some(target) = instruction.target() {
+                let label = match self.names.get(&target) {
+                    Some(name) => Some(name.clone()),
+                    None if labels.contains(&target) => Some(format!(".l_{:04x}", target)),
+                    None => None,
+                };
+                let operand = format!("${:04x}", target);
+                if let (Some(label), Some(position)) = (label, text.rfind(&operand)) {
+                    text.replace_range(position.., &label);
+                }
+            }
+            let hex: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            writeln!(f, "    {:04x}  {:<8}  {}", address, hex.join(" "), text)?;
+            previous = Some(instruction);
+        }
+        Ok(())
+    }
+}
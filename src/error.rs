@@ -0,0 +1,120 @@
+//! The library's error type
+//!
+//! Anything that fails on untrusted input, a ROM, an archive or a path the user gave, reports
+//! it through `FaroreError` rather than panicking.  APIs that only write to a caller's `Write`
+//! stick to `io::Result` like the standard library does.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use archive::ArchiveError;
+use cart::HEADER_END;
+use ips::PatchError;
+
+
+/// Why an operation failed.
+#[derive(Debug)]
+pub enum FaroreError {
+    /// Reading or writing a file failed.
+    Io {
+        /// The file or directory involved.
+        path: PathBuf,
+        /// What went wrong.
+        source: io::Error,
+    },
+    /// The ROM is this many bytes, too short to hold a header.
+    RomTooShort(usize),
+    /// A header field that can't be decoded.
+    InvalidHeaderField {
+        /// The field's name, as in `cart::HeaderField`.
+        field: &'static str,
+        /// What's wrong with it.
+        reason: String,
+    },
+    /// A gzip or zip file that can't be unpacked.
+    Archive(ArchiveError),
+    /// An IPS patch that can't be applied.
+    Patch(PatchError),
+    /// Something was recorded against a different ROM, going by CRC-32.
+    RomMismatch {
+        /// The CRC-32 it was recorded with.
+        expected_crc32: u32,
+        /// The CRC-32 of the ROM in use.
+        found_crc32: u32,
+    },
+    /// An argument out of the range an API accepts.
+    InvalidArgument(String),
+    /// The cartridge type byte names a memory controller that isn't emulated.
+    UnsupportedMapper(u8),
+    /// A save file that isn't the size of the RAM the header declares.
+    RamSizeMismatch {
+        /// The declared RAM size in bytes.
+        expected: usize,
+        /// The save file's size in bytes.
+        found: usize,
+    },
+    /// A save state from a format version this build can't read.
+    SaveStateVersion {
+        /// The version the save state has.
+        found: u32,
+        /// The version this build writes.
+        supported: u32,
+    },
+}
+
+impl FaroreError {
+    /// Wraps an I/O error with the path it happened on.
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        FaroreError::Io { path: path.to_path_buf(), source }
+    }
+}
+
+impl fmt::Display for FaroreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaroreError::Io { ref path, .. } => write!(f, "unable to access {}", path.display()),
+            FaroreError::RomTooShort(size) => {
+                write!(f, "the rom is {} bytes, too short to hold a header, which needs at least {}", size, HEADER_END)
+            },
+            FaroreError::InvalidHeaderField { field, ref reason } => write!(f, "the {} is invalid: {}", field, reason),
+            FaroreError::Archive(_) => write!(f, "the archive can't be unpacked"),
+            FaroreError::Patch(_) => write!(f, "the patch can't be applied"),
+            FaroreError::RomMismatch { expected_crc32, found_crc32 } => {
+                write!(f, "recorded with ROM CRC32 {:08X}, this ROM is {:08X}", expected_crc32, found_crc32)
+            },
+            FaroreError::InvalidArgument(ref message) => write!(f, "{}", message),
+            FaroreError::UnsupportedMapper(byte) => write!(f, "cartridge type 0x{:02X} isn't supported", byte),
+            FaroreError::RamSizeMismatch { expected, found } => {
+                write!(f, "the save is {} bytes, the cartridge has {} bytes of RAM", found, expected)
+            },
+            FaroreError::SaveStateVersion { found, supported } => {
+                write!(f, "the save state is version {}, only version {} can be loaded", found, supported)
+            },
+        }
+    }
+}
+
+impl Error for FaroreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FaroreError::Io { ref source, .. } => Some(source),
+            FaroreError::Archive(ref error) => Some(error),
+            FaroreError::Patch(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArchiveError> for FaroreError {
+    fn from(error: ArchiveError) -> Self {
+        FaroreError::Archive(error)
+    }
+}
+
+impl From<PatchError> for FaroreError {
+    fn from(error: PatchError) -> Self {
+        FaroreError::Patch(error)
+    }
+}
@@ -0,0 +1,320 @@
+//! Animated GIF recording
+//!
+//! Frames are encoded and written as they arrive, so a long recording never sits in memory.
+//! Each frame gets its own color table: DMG frames only have 4 colors and most CGB frames fit
+//! in 256, the rest are mapped onto a fixed 6x7x6 color cube.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use ppu::{Frame, FrameCallback, DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+
+const CLOCK_RATE: f64 = 4_194_304.0;
+
+// GIF delays are in hundredths of a second
+const CENTISECONDS_PER_FRAME: f64 = 100.0 * DOTS_PER_FRAME as f64 / CLOCK_RATE;
+
+// Browsers play anything faster than this at 10 frames per second instead
+const MIN_DELAY: u16 = 2;
+
+const MAX_CODE: u16 = 4095;
+
+/// Writes an animated GIF that loops forever.  `finish` writes the trailer.  It's also called
+/// on drop, so the file is valid however the recording stops.
+pub struct GifEncoder<W: Write> {
+    writer: Option<W>,
+    width: u16,
+    height: u16,
+    frames: u32,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Writes the header for an image of the given size.
+    pub fn new(mut writer: W, width: u16, height: u16) -> io::Result<Self> {
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        writer.write_all(&[
+            0, // No global color table
+            0, // Background color
+            0, // Square pixels
+        ])?;
+        // Loop forever
+        writer.write_all(&[0x21, 0xFF, 0x0B])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+        Ok(GifEncoder { writer: Some(writer), width, height, frames: 0 })
+    }
+
+    /// Appends a frame of RGBA pixels, shown for `delay` hundredths of a second.
+    pub fn add_frame(&mut self, rgba: &[u8], delay: u16) -> io::Result<()> {
+        let (width, height) = (self.width, self.height);
+        if rgba.len() != width as usize * height as usize * 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel buffer does not match image size"));
+        }
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Err(io::Error::other("the gif is already finished")),
+        };
+        let (palette, indices) = quantize(rgba);
+
+        // Graphic control extension, for the delay
+        writer.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        writer.write_all(&delay.to_le_bytes())?;
+        writer.write_all(&[0x00, 0x00])?;
+
+        // The table size is a power of two, from 2 to 256 colors
+        let table_bits = (palette.len().max(2).next_power_of_two().trailing_zeros() as u8).max(1);
+        writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        writer.write_all(&[0x80 | (table_bits - 1)])?;
+        for i in 0..1usize << table_bits {
+            writer.write_all(&palette.get(i).cloned().unwrap_or([0; 3]))?;
+        }
+
+        let min_code_size = table_bits.max(2);
+        writer.write_all(&[min_code_size])?;
+        for block in lzw_encode(&indices, min_code_size).chunks(255) {
+            writer.write_all(&[block.len() as u8])?;
+            writer.write_all(block)?;
+        }
+        writer.write_all(&[0])?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes the trailer.  Calling it again does nothing.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(mut writer) => {
+                writer.write_all(&[0x3B])?;
+                writer.flush()
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// How many frames have been added.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+impl<W: Write> Drop for GifEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// Returns the frame's palette and a palette index for each pixel.
+fn quantize(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match lookup.get(&color) {
+            Some(&index) => index,
+            None if palette.len() < 256 => {
+                let index = palette.len() as u8;
+                palette.push(color);
+                lookup.insert(color, index);
+                index
+            },
+            None => return quantize_to_cube(rgba),
+        };
+        indices.push(index);
+    }
+    (palette, indices)
+}
+
+fn quantize_to_cube(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    const LEVELS: [u32; 3] = [6, 7, 6];
+    let scale = |value: u32, levels: u32| (value * (levels - 1) + 127) / 255;
+
+    let mut palette = Vec::with_capacity(252);
+    for r in 0..LEVELS[0] {
+        for g in 0..LEVELS[1] {
+            for b in 0..LEVELS[2] {
+                palette.push([
+                    (r * 255 / (LEVELS[0] - 1)) as u8,
+                    (g * 255 / (LEVELS[1] - 1)) as u8,
+                    (b * 255 / (LEVELS[2] - 1)) as u8,
+                ]);
+            }
+        }
+    }
+    let indices = rgba.chunks(4).map(|pixel| {
+        let r = scale(pixel[0] as u32, LEVELS[0]);
+        let g = scale(pixel[1] as u32, LEVELS[1]);
+        let b = scale(pixel[2] as u32, LEVELS[2]);
+        ((r * LEVELS[1] + g) * LEVELS[2] + b) as u8
+    }).collect();
+    (palette, indices)
+}
+
+// Packs variable width codes, least significant bit first.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += width as u32;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+// GIF flavored LZW.  The code width grows as the table fills and the table is cleared when it
+// runs out of 12 bit codes, in step with what a decoder expects.
+struct Lzw {
+    bits: BitWriter,
+    table: HashMap<(u16, u8), u16>,
+    min_code_size: u8,
+    width: u8,
+    highest: u16, // The last code handed out
+}
+
+impl Lzw {
+    fn clear_code(&self) -> u16 {
+        1 << self.min_code_size
+    }
+
+    fn reset(&mut self) {
+        self.table.clear();
+        self.width = self.min_code_size + 1;
+        self.highest = self.clear_code() + 1;
+    }
+
+    // Moves on to the next code, returning false when the table had to be cleared instead
+    fn next_code(&mut self) -> bool {
+        self.highest += 1;
+        if self.highest == 1 << self.width {
+            self.width += 1;
+        }
+        if self.highest == MAX_CODE {
+            let clear = self.clear_code();
+            self.bits.write(clear, self.width);
+            self.reset();
+            return false;
+        }
+        true
+    }
+}
+
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let bits = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+    let mut lzw = Lzw { bits, table: HashMap::new(), min_code_size, width: 0, highest: 0 };
+    lzw.reset();
+    let (clear, end) = (lzw.clear_code(), lzw.clear_code() + 1);
+
+    lzw.bits.write(clear, lzw.width);
+    let mut pixels = indices.iter();
+    if let Some(&first) = pixels.next() {
+        let mut code = first as u16;
+        for &pixel in pixels {
+            if let Some(&known) = lzw.table.get(&(code, pixel)) {
+                code = known;
+                continue;
+            }
+            lzw.bits.write(code, lzw.width);
+            if lzw.next_code() {
+                lzw.table.insert((code, pixel), lzw.highest);
+            }
+            code = pixel as u16;
+        }
+        lzw.bits.write(code, lzw.width);
+        lzw.next_code();
+    }
+    lzw.bits.write(end, lzw.width);
+    lzw.bits.finish()
+}
+
+/// Records frames from the PPU frame callback into a GIF.  Every `skip`th frame is kept, with
+/// delays as close to real time as GIF allows, and the file is finished once `limit` frames
+/// are in.  Clones share the same recording.
+#[derive(Clone)]
+pub struct GifRecorder {
+    state: Rc<RefCell<RecorderState>>,
+}
+
+struct RecorderState {
+    encoder: GifEncoder<Box<dyn Write>>,
+    skip: u64,
+    limit: Option<u32>,
+    elapsed: f64,  // Centiseconds of play covered by the recorded frames
+    written: u64,  // Centiseconds of delay written out so far
+    error: Option<io::Error>, // The first failed write, since the frame callback can't return it
+}
+
+impl GifRecorder {
+    /// Records every `skip`th frame, stopping after `limit` frames if there is one.
+    pub fn new(writer: Box<dyn Write>, skip: u64, limit: Option<u32>) -> io::Result<Self> {
+        let encoder = GifEncoder::new(writer, SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16)?;
+        let state = RecorderState { encoder, skip: skip.max(1), limit, elapsed: 0.0, written: 0, error: None };
+        Ok(GifRecorder { state: Rc::new(RefCell::new(state)) })
+    }
+
+    /// Adds the frame if it isn't skipped and the limit isn't reached.
+    pub fn capture(&self, frame: &Frame) {
+        let mut state = self.state.borrow_mut();
+        if state.error.is_some() || state.is_full() || !(frame.number - 1).is_multiple_of(state.skip) {
+            return;
+        }
+        state.elapsed += CENTISECONDS_PER_FRAME * state.skip as f64;
+        let delay = (state.elapsed.round() as u64).saturating_sub(state.written).max(MIN_DELAY as u64);
+        state.written += delay;
+
+        let mut result = state.encoder.add_frame(&frame.to_rgba(), delay as u16);
+        if result.is_ok() && state.is_full() {
+            result = state.encoder.finish();
+        }
+        if let Err(e) = result {
+            state.error = Some(e);
+        }
+    }
+
+    /// A callback for the PPU that feeds this recorder.
+    pub fn frame_callback(&self) -> FrameCallback {
+        let recorder = self.clone();
+        Box::new(move |frame: &Frame| recorder.capture(frame))
+    }
+
+    /// Writes the trailer, for stopping before the limit.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        match state.error.take() {
+            Some(e) => Err(e),
+            None => state.encoder.finish(),
+        }
+    }
+
+    /// How many frames have been recorded.
+    pub fn frames(&self) -> u32 {
+        self.state.borrow().encoder.frames()
+    }
+}
+
+impl RecorderState {
+    fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.encoder.frames() >= limit)
+    }
+}
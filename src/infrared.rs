@@ -0,0 +1,136 @@
+//! CGB infrared port
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use io::IoPeripheral;
+use model::HardwareModel;
+
+
+/// Whatever the IR sensor is pointed at.
+pub trait InfraredSource {
+    /// Whether the sensor sees light at a point in time, in T-cycles.
+    fn light_seen(&mut self, _cycle: u64) -> bool {
+        false
+    }
+
+    /// Called when the game turns its own LED on or off.
+    fn led_changed(&mut self, _on: bool, _cycle: u64) {}
+}
+
+/// Nothing in front of the sensor.
+pub struct NoLight;
+
+impl InfraredSource for NoLight {}
+
+/// A mirror in front of the port: the sensor sees the port's own LED.
+#[derive(Default)]
+pub struct Loopback {
+    led: bool,
+}
+
+impl InfraredSource for Loopback {
+    fn light_seen(&mut self, _cycle: u64) -> bool {
+        self.led
+    }
+
+    fn led_changed(&mut self, on: bool, _cycle: u64) {
+        self.led = on;
+    }
+}
+
+/// Two IR ports facing each other, each seeing the other's LED.
+pub struct InfraredLink;
+
+struct InfraredLinkEnd {
+    leds: Rc<RefCell<[bool; 2]>>,
+    side: usize,
+}
+
+impl InfraredLink {
+    /// Points two ports at each other, replacing their current sources.
+    pub fn connect(a: &mut Infrared, b: &mut Infrared) {
+        let leds = Rc::new(RefCell::new([false; 2]));
+        a.set_source(Box::new(InfraredLinkEnd { leds: leds.clone(), side: 0 }));
+        b.set_source(Box::new(InfraredLinkEnd { leds, side: 1 }));
+    }
+}
+
+impl InfraredSource for InfraredLinkEnd {
+    fn light_seen(&mut self, _cycle: u64) -> bool {
+        self.leds.borrow()[1 - self.side]
+    }
+
+    fn led_changed(&mut self, on: bool, _cycle: u64) {
+        self.leds.borrow_mut()[self.side] = on;
+    }
+}
+
+/// Registers
+///   FF56   RP - Bit 7-6 read enable (both set to read), bit 1 sensor (0 = light seen, read
+///               only), bit 0 LED on.  CGB only.
+///
+/// The sensor is sampled when the port ticks.  With reading disabled bit 1 reads as 1, and so
+/// do bits 5-2.
+pub struct Infrared {
+    model: HardwareModel,
+    rp: u8, // Bit 7-6 and 0
+    source: Box<dyn InfraredSource>,
+    cycle: u64,
+    light: bool,
+}
+
+impl Infrared {
+    /// A port seeing no light.
+    pub fn new(model: HardwareModel) -> Self {
+        Infrared {
+            model,
+            rp: 0,
+            source: Box::new(NoLight),
+            cycle: 0,
+            light: false,
+        }
+    }
+
+    /// Replaces what the sensor sees.  The new source is told the current LED state.
+    pub fn set_source(&mut self, source: Box<dyn InfraredSource>) {
+        self.source = source;
+        self.source.led_changed(self.led_on(), self.cycle);
+    }
+
+    /// Whether the game has the LED switched on.
+    pub fn led_on(&self) -> bool {
+        self.rp & 0x01 != 0
+    }
+
+    /// Advances by a number of T-cycles and samples the sensor.
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycle += cycles as u64;
+        self.light = self.source.light_seen(self.cycle);
+    }
+
+    fn reading_enabled(&self) -> bool {
+        self.rp & 0xC0 == 0xC0
+    }
+}
+
+impl IoPeripheral for Infrared {
+    fn read(&self, address: u16) -> u8 {
+        if address != 0xFF56 || !self.model.is_cgb() {
+            return 0xFF;
+        }
+        let sensor = if self.reading_enabled() && self.light { 0 } else { 0x02 };
+        0x3C | self.rp | sensor
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address != 0xFF56 || !self.model.is_cgb() {
+            return;
+        }
+        let led_was_on = self.led_on();
+        self.rp = value & 0xC1;
+        if self.led_on() != led_was_on {
+            self.source.led_changed(self.led_on(), self.cycle);
+        }
+    }
+}
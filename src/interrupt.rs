@@ -0,0 +1,285 @@
+//! Interrupt requests
+
+use std::fmt;
+
+use io::IoPeripheral;
+
+/// The five interrupt sources, in priority order.  The discriminant is the bit index in IF/IE.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Bit 0, INT 0x40.
+    VBlank,
+    /// Bit 1, INT 0x48.
+    LcdStat,
+    /// Bit 2, INT 0x50.
+    Timer,
+    /// Bit 3, INT 0x58.
+    Serial,
+    /// Bit 4, INT 0x60.
+    Joypad,
+}
+
+impl Interrupt {
+    /// Every source, highest priority first.
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The source's bit in IF and IE.
+    pub fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    /// The address the CPU calls to service it.
+    pub fn vector(self) -> u16 {
+        0x40 + 8 * (self as u16)
+    }
+}
+
+/// How often a source has fired, for the debugger and profiler.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct InterruptStats {
+    /// Requests made, including ones written to IF.
+    pub raised: u64,
+    /// Requests the CPU jumped to the vector for.
+    pub serviced: u64,
+    /// The cycle of the latest request.
+    pub last_raised: Option<u64>,
+}
+
+/// The spread of a number of cycles measured over and over.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    /// How many were measured.
+    pub count: u64,
+    /// The shortest.
+    pub min: u64,
+    /// The longest.
+    pub max: u64,
+    /// All of them added up.
+    pub total: u64,
+}
+
+impl CycleStats {
+    /// Adds a measurement.
+    pub fn add(&mut self, cycles: u64) {
+        self.min = if self.count == 0 { cycles } else { self.min.min(cycles) };
+        self.max = self.max.max(cycles);
+        self.total += cycles;
+        self.count += 1;
+    }
+
+    /// The average, or None before anything was measured.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.total as f64 / self.count as f64)
+    }
+}
+
+/// How long interrupts wait to be serviced, from the request to the dispatch, and how long
+/// their handlers run, from the dispatch to the RETI.  All in T-cycles.  Nested handlers are
+/// matched to their RETIs innermost first.
+#[derive(Debug, Clone, Default)]
+pub struct IrqStats {
+    latency: [CycleStats; 5],
+    handler: [CycleStats; 5],
+    running: Vec<(Interrupt, u64)>, // Handlers not returned from yet, and when they started
+}
+
+impl IrqStats {
+    /// Nothing measured yet.
+    pub fn new() -> Self {
+        IrqStats::default()
+    }
+
+    /// Records the CPU jumping to an interrupt's vector at `cycle`, having been requested at
+    /// `raised`.
+    pub fn dispatched(&mut self, interrupt: Interrupt, raised: u64, cycle: u64) {
+        self.latency[interrupt as usize].add(cycle.saturating_sub(raised));
+        self.running.push((interrupt, cycle));
+    }
+
+    /// Records a RETI at `cycle`, ending the innermost handler.  A RETI outside any handler
+    /// isn't counted.
+    pub fn returned(&mut self, cycle: u64) {
+        if let Some((interrupt, start)) = self.running.pop() {
+            self.handler[interrupt as usize].add(cycle.saturating_sub(start));
+        }
+    }
+
+    /// The cycles from a source's requests to their dispatch.
+    pub fn latency(&self, interrupt: Interrupt) -> CycleStats {
+        self.latency[interrupt as usize]
+    }
+
+    /// The cycles a source's handlers ran for.
+    pub fn handler_time(&self, interrupt: Interrupt) -> CycleStats {
+        self.handler[interrupt as usize]
+    }
+}
+
+// One row per source, the minimum, mean and maximum of each measurement.
+impl fmt::Display for IrqStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let spread = |stats: CycleStats| match stats.mean() {
+            Some(mean) => format!("{:>6} {:>8.1} {:>6}", stats.min, mean, stats.max),
+            None => format!("{:>6} {:>8} {:>6}", "-", "-", "-"),
+        };
+        writeln!(f, "source   serviced   latency min/mean/max    handler min/mean/max")?;
+        for &interrupt in &Interrupt::ALL {
+            let row = format!("{:<8} {:>8}   {}    {}",
+                format!("{:?}", interrupt),
+                self.latency(interrupt).count,
+                spread(self.latency(interrupt)),
+                spread(self.handler_time(interrupt)));
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// The IF register (0xFF0F) as seen by the peripherals.  Peripherals raise requests on the
+/// line as they tick, and whoever services interrupts clears them.
+#[derive(Debug, Default)]
+pub struct InterruptLine {
+    flags: u8,
+    cycle: u64,
+    stats: [InterruptStats; 5],
+}
+
+impl InterruptLine {
+    /// A line with nothing requested.
+    pub fn new() -> Self {
+        InterruptLine::default()
+    }
+
+    /// Sets a source's IF bit.
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flags |= interrupt.bit();
+        let stats = &mut self.stats[interrupt as usize];
+        stats.raised += 1;
+        stats.last_raised = Some(self.cycle);
+    }
+
+    /// Clears a source's IF bit.
+    pub fn clear(&mut self, interrupt: Interrupt) {
+        self.flags &= !interrupt.bit();
+    }
+
+    /// Whether a source's IF bit is set.
+    pub fn is_requested(&self, interrupt: Interrupt) -> bool {
+        self.flags & interrupt.bit() != 0
+    }
+
+    /// Reads IF.  Only the lower 5 bits are backed by hardware, the rest read back as 1.
+    pub fn read(&self) -> u8 {
+        self.flags | 0xE0
+    }
+
+    /// Writes IF.  Bits set this way count as requests, which some games rely on to trigger
+    /// an interrupt by hand.
+    pub fn write(&mut self, value: u8) {
+        for &interrupt in &Interrupt::ALL {
+            if value & interrupt.bit() != 0 && !self.is_requested(interrupt) {
+                self.request(interrupt);
+            }
+        }
+        self.flags = value & 0x1F;
+    }
+}
+
+/// Owns IF and IE.  Peripherals request interrupts through `line_mut()`, and the CPU picks the
+/// highest priority enabled request with `highest_pending()` and services it with
+/// `acknowledge()`.
+///
+/// Registers
+///   FF0F   IF - Bit 4-0 requested interrupts
+///   FFFF   IE - Bit 4-0 enabled interrupts, all 8 bits read back
+#[derive(Debug, Default)]
+pub struct InterruptController {
+    line: InterruptLine,
+    enabled: u8,
+}
+
+impl InterruptController {
+    /// A controller with nothing requested or enabled.
+    pub fn new() -> Self {
+        InterruptController::default()
+    }
+
+    /// The IF half, for reading.
+    pub fn line(&self) -> &InterruptLine {
+        &self.line
+    }
+
+    /// The IF half, for peripherals to request interrupts on.
+    pub fn line_mut(&mut self) -> &mut InterruptLine {
+        &mut self.line
+    }
+
+    /// Advances the clock used to timestamp requests, in T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        self.line.cycle += cycles as u64;
+    }
+
+    /// The enabled request with the highest priority, if any.
+    pub fn highest_pending(&self) -> Option<Interrupt> {
+        let pending = self.line.flags & self.enabled & 0x1F;
+        Interrupt::ALL.iter().cloned().find(|interrupt| pending & interrupt.bit() != 0)
+    }
+
+    /// Clears a request as the CPU jumps to its vector.
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.line.clear(interrupt);
+        self.line.stats[interrupt as usize].serviced += 1;
+    }
+
+    /// How often a source has fired so far.
+    pub fn stats(&self, interrupt: Interrupt) -> InterruptStats {
+        self.line.stats[interrupt as usize]
+    }
+}
+
+impl IoPeripheral for InterruptController {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF0F => self.line.read(),
+            0xFFFF => self.enabled,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF0F => self.line.write(value),
+            0xFFFF => self.enabled = value,
+            _ => {},
+        }
+    }
+}
+
+impl fmt::Display for InterruptController {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "IF {:02X}  IE {:02X}", self.line.read(), self.enabled)?;
+        writeln!(f, "source   IF IE    raised  serviced  last raised")?;
+        for &interrupt in &Interrupt::ALL {
+            let stats = self.stats(interrupt);
+            let last = match stats.last_raised {
+                Some(cycle) => cycle.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(f, "{:<8} {:>2} {:>2} {:>9} {:>9}  {}",
+                format!("{:?}", interrupt),
+                self.line.is_requested(interrupt) as u8,
+                (self.enabled & interrupt.bit() != 0) as u8,
+                stats.raised, stats.serviced, last)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+//! I/O register peripherals
+
+
+/// A device mapped into the I/O port range (0xFF00-0xFF7F).  Addresses are passed through
+/// unmodified, so each peripheral matches on the registers it owns.
+pub trait IoPeripheral {
+    /// Reads a register.  Unmapped bits read as 1.
+    fn read(&self, address: u16) -> u8;
+    /// Writes a register.  Read only bits are left alone.
+    fn write(&mut self, address: u16, value: u8);
+}
+
+/// Memory as a debugger sees it: reads without side effects, and without the blocking the CPU
+/// gets while the PPU is using VRAM or OAM.
+pub trait Peek {
+    /// Reads a byte.  Addresses the implementor doesn't map read 0xFF.
+    fn peek(&self, address: u16) -> u8;
+}
@@ -0,0 +1,122 @@
+//! Applying IPS patches, the format ROM hacks are shipped in
+//!
+//! Layout, big endian:
+//!   "PATCH"
+//!   Records until "EOF":
+//!     3 bytes   Offset into the ROM
+//!     2 bytes   Size, or 0 for a run
+//!     Size bytes to copy in, or for a run:
+//!       2 bytes   Run length
+//!       1 byte    The value to repeat
+//!   "EOF"
+//!   Optionally, 3 bytes: the size to truncate the ROM to
+//! Records past the end of the ROM grow it, filling any gap with zeros.
+
+use std::error::Error;
+use std::fmt;
+
+use byteorder::{BigEndian, ByteOrder};
+
+
+const MAGIC: &[u8] = b"PATCH";
+const END: &[u8] = b"EOF";
+
+/// A patch that is malformed or doesn't fit the ROM.  Nothing is applied when this is
+/// returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError(pub(crate) String);
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for PatchError {}
+
+/// What applying a patch did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpsSummary {
+    /// How many records were applied, runs included.
+    pub records: usize,
+    /// How many bytes differ from before, counting those added by growing the ROM.
+    pub bytes_changed: usize,
+    /// The size the patch truncated the ROM to, if it did.
+    pub truncated_to: Option<usize>,
+}
+
+// A record, with its data borrowed from the patch.
+enum Record<'a> {
+    Copy(usize, &'a [u8]),
+    Run(usize, usize, u8),
+}
+
+/// Applies `patch` to `rom`.  The whole patch is checked before anything is written, so a
+/// truncated or corrupt patch leaves the ROM alone.
+pub fn apply(rom: &mut Vec<u8>, patch: &[u8]) -> Result<IpsSummary, PatchError> {
+    let (records, truncate) = parse(patch)?;
+
+    let before = rom.clone();
+    for record in &records {
+        match *record {
+            Record::Copy(offset, data) => write(rom, offset, data.len()).copy_from_slice(data),
+            Record::Run(offset, len, value) => write(rom, offset, len).iter_mut().for_each(|byte| *byte = value),
+        }
+    }
+    let bytes_changed = rom.iter().enumerate().filter(|&(i, byte)| before.get(i) != Some(byte)).count();
+
+    let truncated_to = match truncate {
+        Some(size) if size < rom.len() => {
+            rom.truncate(size);
+            Some(size)
+        },
+        _ => None,
+    };
+    Ok(IpsSummary { records: records.len(), bytes_changed, truncated_to })
+}
+
+// The bytes a record covers, growing the ROM to fit them.
+fn write(rom: &mut Vec<u8>, offset: usize, len: usize) -> &mut [u8] {
+    if offset + len > rom.len() {
+        rom.resize(offset + len, 0);
+    }
+    &mut rom[offset..offset + len]
+}
+
+fn parse(patch: &[u8]) -> Result<(Vec<Record<'_>>, Option<usize>), PatchError> {
+    if !patch.starts_with(MAGIC) {
+        return Err(PatchError("not an IPS patch".to_string()));
+    }
+    let truncated = |at: usize| PatchError(format!("the patch is truncated in the record at {:#x}", at));
+
+    let mut records = Vec::new();
+    let mut at = MAGIC.len();
+    loop {
+        let rest = &patch[at..];
+        if rest.starts_with(END) {
+            at += END.len();
+            break;
+        }
+        if rest.len() < 5 {
+            return Err(if rest.is_empty() { PatchError("the patch has no EOF marker".to_string()) } else { truncated(at) });
+        }
+        let offset = BigEndian::read_u24(rest) as usize;
+        let size = BigEndian::read_u16(&rest[3..]) as usize;
+        if size > 0 {
+            let data = rest.get(5..5 + size).ok_or_else(|| truncated(at))?;
+            records.push(Record::Copy(offset, data));
+            at += 5 + size;
+        } else {
+            let run = rest.get(5..8).ok_or_else(|| truncated(at))?;
+            records.push(Record::Run(offset, BigEndian::read_u16(run) as usize, run[2]));
+            at += 8;
+        }
+    }
+
+    let truncate = match patch.len() - at {
+        0 => None,
+        3 => Some(BigEndian::read_u24(&patch[at..]) as usize),
+        extra => return Err(PatchError(format!("{} unexpected bytes after the EOF marker", extra))),
+    };
+    Ok((records, truncate))
+}
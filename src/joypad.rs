@@ -0,0 +1,281 @@
+//! Joypad input
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use interrupt::{Interrupt, InterruptLine};
+use io::IoPeripheral;
+use sgb::{SgbCallback, SgbDecoder, MLT_REQ};
+
+
+/// The eight inputs.  The discriminant is the bit in `InputState`, d-pad in the low nibble and
+/// buttons in the high one, matching the order of the FF00 input lines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Button {
+    /// D-pad right.
+    Right,
+    /// D-pad left.
+    Left,
+    /// D-pad up.
+    Up,
+    /// D-pad down.
+    Down,
+    /// The A button.
+    A,
+    /// The B button.
+    B,
+    /// Select.
+    Select,
+    /// Start.
+    Start,
+}
+
+impl Button {
+    /// All of them, in `InputState` bit order.
+    pub const ALL: [Button; 8] =
+        [Button::Right, Button::Left, Button::Up, Button::Down, Button::A, Button::B, Button::Select, Button::Start];
+
+    /// The lowercase name scripts and embedders use, like "start".
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::Right => "right",
+            Button::Left => "left",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::A => "a",
+            Button::B => "b",
+            Button::Select => "select",
+            Button::Start => "start",
+        }
+    }
+
+    /// Looks up a button by its name, in any case.
+    pub fn named(name: &str) -> Option<Button> {
+        Button::ALL.iter().cloned().find(|button| button.name().eq_ignore_ascii_case(name))
+    }
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// Which inputs are held down.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct InputState {
+    pressed: u8,
+}
+
+impl InputState {
+    /// Nothing held.
+    pub fn new() -> Self {
+        InputState { pressed: 0 }
+    }
+
+    /// Builds a state from one bit per button, in `Button` order.
+    pub fn from_bits(pressed: u8) -> Self {
+        InputState { pressed }
+    }
+
+    /// One bit per button, in `Button` order.
+    pub fn bits(&self) -> u8 {
+        self.pressed
+    }
+
+    /// Whether a button is held.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.bit() != 0
+    }
+
+    /// Presses or releases a button.
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.pressed |= button.bit();
+        } else {
+            self.pressed &= !button.bit();
+        }
+    }
+
+    // Releases both directions of a pair that are held together.
+    fn without_opposing(self) -> Self {
+        let mut pressed = self.pressed;
+        for &(a, b) in &[(Button::Left, Button::Right), (Button::Up, Button::Down)] {
+            let pair = a.bit() | b.bit();
+            if pressed & pair == pair {
+                pressed &= !pair;
+            }
+        }
+        InputState { pressed }
+    }
+}
+
+/// A handle to the joypad's input latch.  It can be cloned and sent to a frontend thread, and
+/// the joypad samples whatever was set last when it ticks or FF00 is read.
+#[derive(Debug, Clone, Default)]
+pub struct JoypadInput {
+    latch: Arc<AtomicU8>,
+}
+
+impl JoypadInput {
+    /// Presses or releases a button.
+    pub fn set_button(&self, button: Button, pressed: bool) {
+        if pressed {
+            self.latch.fetch_or(button.bit(), Ordering::Relaxed);
+        } else {
+            self.latch.fetch_and(!button.bit(), Ordering::Relaxed);
+        }
+    }
+
+    /// Replaces every input at once.
+    pub fn set_inputs(&self, inputs: InputState) {
+        self.latch.store(inputs.pressed, Ordering::Relaxed);
+    }
+
+    /// What's held right now.
+    pub fn inputs(&self) -> InputState {
+        InputState { pressed: self.latch.load(Ordering::Relaxed) }
+    }
+}
+
+/// Registers
+///   FF00   P1/JOYP - Bit 5 select buttons, bit 4 select d-pad (both active low),
+///                    bit 3-0 input lines (active low, read only)
+///
+/// The input lines read the pressed state of whichever groups are selected, and read 1 when
+/// nothing is selected.  The Joypad interrupt is requested when any line goes from high to low.
+///
+/// With SGB packet decoding on, the select line writes are also decoded as SGB commands.  If
+/// MLT_REQ is answered, reading with neither group selected returns 0xF minus the current
+/// controller, which advances each time P15 goes high.  Only controller 1 has inputs.
+pub struct Joypad {
+    select: u8, // Bit 5-4 of P1
+    input: JoypadInput,
+    filter_opposing: bool,
+    lines: u8, // The input lines as of the last tick
+
+    sgb: Option<(SgbDecoder, SgbCallback)>,
+    answer_mlt_req: bool,
+    controllers: u8,
+    controller: u8,
+}
+
+impl Joypad {
+    /// A joypad with nothing held and nothing selected.
+    pub fn new() -> Self {
+        Joypad {
+            select: 0x30,
+            input: JoypadInput::default(),
+            filter_opposing: false,
+            lines: 0x0F,
+            sgb: None,
+            answer_mlt_req: false,
+            controllers: 1,
+            controller: 0,
+        }
+    }
+
+    /// A handle for setting inputs from elsewhere, possibly another thread.
+    pub fn input(&self) -> JoypadInput {
+        self.input.clone()
+    }
+
+    /// Presses or releases a button, the same as through `input()`.
+    pub fn set_button(&self, button: Button, pressed: bool) {
+        self.input.set_button(button, pressed);
+    }
+
+    /// Replaces every input at once.
+    pub fn set_inputs(&self, inputs: InputState) {
+        self.input.set_inputs(inputs);
+    }
+
+    /// Releases Left+Right and Up+Down when they're held together, which the d-pad can't do
+    /// and some games don't expect.  Off by default.
+    pub fn set_opposing_filter(&mut self, filter: bool) {
+        self.filter_opposing = filter;
+    }
+
+    /// Decodes SGB command packets from P1 writes, for carts with the SGB flag or when forced.
+    /// With `answer_mlt_req` set, MLT_REQ switches on multi-controller mode like an SGB would.
+    pub fn enable_sgb(&mut self, callback: SgbCallback, answer_mlt_req: bool) {
+        self.sgb = Some((SgbDecoder::new(), callback));
+        self.answer_mlt_req = answer_mlt_req;
+    }
+
+    /// Samples the inputs and requests the Joypad interrupt on any falling input line.
+    pub fn tick(&mut self, irq: &mut InterruptLine) {
+        let lines = self.input_lines();
+        if self.lines & !lines != 0 {
+            irq.request(Interrupt::Joypad);
+        }
+        self.lines = lines;
+    }
+
+    // The low nibble of P1, 0 for each pressed input in a selected group.
+    fn input_lines(&self) -> u8 {
+        if self.controllers > 1 {
+            if self.select == 0x30 {
+                return 0x0F - self.controller;
+            }
+            if self.controller != 0 {
+                return 0x0F;
+            }
+        }
+        let mut inputs = self.input.inputs();
+        if self.filter_opposing {
+            inputs = inputs.without_opposing();
+        }
+        let mut pressed = 0;
+        if self.select & 0x10 == 0 {
+            pressed |= inputs.pressed & 0x0F;
+        }
+        if self.select & 0x20 == 0 {
+            pressed |= inputs.pressed >> 4;
+        }
+        !pressed & 0x0F
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+impl IoPeripheral for Joypad {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF00 => 0xC0 | self.select | self.input_lines(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address != 0xFF00 {
+            return;
+        }
+        let previous = self.select;
+        self.select = value & 0x30;
+        if self.controllers > 1 && previous & 0x20 == 0 && self.select & 0x20 != 0 {
+            self.controller = (self.controller + 1) % self.controllers;
+        }
+
+        let command = match self.sgb.as_mut() {
+            Some((decoder, callback)) => match decoder.write(self.select) {
+                Some(command) => {
+                    callback(&command);
+                    command
+                },
+                None => return,
+            },
+            None => return,
+        };
+        if command.command == MLT_REQ && self.answer_mlt_req {
+            self.controllers = match command.data[1] & 0x03 {
+                1 => 2,
+                3 => 4,
+                _ => 1,
+            };
+            self.controller = 0;
+        }
+    }
+}
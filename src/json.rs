@@ -0,0 +1,310 @@
+//! Minimal JSON for the CLI's machine-readable mode and the test suites read from JSON
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use error::FaroreError;
+
+
+/// A JSON value.  Objects keep their keys in insertion order so output is stable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// An integer, which is all the output and the test suites need.
+    Number(i64),
+    /// A string, escaped on output.
+    String(String),
+    /// An array.
+    Array(Vec<Json>),
+    /// An object, in key order as given.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from key/value pairs.
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Builds a string value.
+    pub fn string(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+
+    /// Parses a JSON document.  Numbers with a fraction or an exponent aren't supported.  Fails
+    /// with `InvalidArgument` saying where the text stopped making sense.
+    pub fn parse(text: &str) -> Result<Self, FaroreError> {
+        let mut parser = Parser { chars: text.chars().peekable(), offset: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.chars.peek().is_some() {
+            return Err(parser.error("expected the end of the text"));
+        }
+        Ok(value)
+    }
+
+    /// An object's value for `key`, None for a missing key or anything but an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|field| field.0 == key).map(|field| &field.1),
+            _ => None,
+        }
+    }
+
+    /// The value of a number.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The text of a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The values of an array.
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// A recursive descent parser, counting characters for the error messages.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &str) -> FaroreError {
+        FaroreError::InvalidArgument(format!("invalid JSON at character {}: {}", self.offset, reason))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c.is_some() {
+            self.offset += 1;
+        }
+        c
+    }
+
+    // Takes the next character if it's `c`.
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) {
+            self.next();
+            return true;
+        }
+        false
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.chars.peek() {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), FaroreError> {
+        for expected in word.chars() {
+            if !self.eat(expected) {
+                return Err(self.error(&format!("expected {}", word)));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, FaroreError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(&c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("the text ended early")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, FaroreError> {
+        let mut digits = String::new();
+        if self.eat('-') {
+            digits.push('-');
+        }
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '0'..='9' => digits.push(c),
+                '.' | 'e' | 'E' => return Err(self.error("only whole numbers are supported")),
+                _ => break,
+            }
+            self.next();
+        }
+        digits.parse().map(Json::Number).map_err(|_| self.error("expected a number that fits 64 bits"))
+    }
+
+    fn string(&mut self) -> Result<String, FaroreError> {
+        self.next();
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.chars.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.next();
+                            s.push(self.escaped_char()?);
+                            continue;
+                        },
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    self.next();
+                    s.push(c);
+                },
+                Some(c) if (c as u32) < 0x20 => return Err(self.error("control character in a string")),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // After \u.  Characters outside the BMP come as a surrogate pair of escapes.
+    fn escaped_char(&mut self) -> Result<char, FaroreError> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                self.expect("\\u")?;
+                match self.hex4()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => return Err(self.error("unpaired surrogate")),
+                }
+            },
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, FaroreError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.peek().and_then(|c| c.to_digit(16)).ok_or_else(|| self.error("expected 4 hex digits"))?;
+            self.next();
+            code = code << 4 | digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, FaroreError> {
+        self.next();
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Json::Array(values));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected , or ]"));
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, FaroreError> {
+        self.next();
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() != Some(&'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(':') {
+                return Err(self.error("expected :"));
+            }
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected , or }"));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
@@ -0,0 +1,63 @@
+//! A Game Boy and Game Boy Color emulator.
+//!
+//! The cartridge header parser in [`cart`] stands on its own, for tools that only need to
+//! inspect ROMs.  The rest are the pieces of the emulation core: the [`ppu`], [`apu`], [`timer`]
+//! and the other I/O peripherals, each driven by ticking it and reading or writing its registers
+//! through [`io::IoPeripheral`].  There is no CPU yet to tie them together into a machine.
+//!
+//! Farore builds on stable Rust 1.87 or later, the `rust-version` in Cargo.toml, and never
+//! on nightly-only features.
+
+#![deny(missing_docs)]
+#![forbid(unstable_features)]
+
+extern crate sha1;
+extern crate byteorder;
+
+#[macro_use]
+pub mod logging;
+
+pub mod apu;
+pub mod archive;
+pub mod bench;
+pub mod blargg;
+pub mod bootrom;
+pub mod bps;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cart;
+pub mod cheat;
+pub mod coverage;
+pub mod crc;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod gif;
+pub mod infrared;
+pub mod interrupt;
+pub mod io;
+pub mod ips;
+pub mod joypad;
+pub mod json;
+pub mod licensee;
+pub mod mbc;
+pub mod model;
+pub mod mooneye;
+pub mod movie;
+pub mod pacing;
+pub mod palette;
+pub mod ppu;
+pub mod printer;
+pub mod render;
+pub mod rewind;
+pub mod savestate;
+pub mod script;
+pub mod serial;
+pub mod sgb;
+pub mod singlestep;
+pub mod speed;
+pub mod symbols;
+pub mod timer;
+pub mod trace;
+pub mod visual;
+pub mod wav;
@@ -0,0 +1,66 @@
+//! Diagnostic messages on stderr
+//!
+//! stdout only carries the report a command was asked for, so everything else goes through
+//! these macros and is filtered by the verbosity set from the command line.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+
+/// How severe a message is, from most to least.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something failed and the command can't carry on as asked.
+    Error,
+    /// Something looks wrong but the command carries on.
+    Warn,
+    /// Progress worth knowing about.
+    Info,
+    /// Details for tracking down problems.
+    Debug,
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Shows messages up to and including `level`.
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are shown.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $prefix:expr, $($arg:tt)*) => {
+        if $crate::logging::enabled($level) {
+            eprintln!("{}{}", $prefix, format!($($arg)*));
+        }
+    };
+}
+
+/// Logs an error, with format arguments like `eprintln!`.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Error, "error: ", $($arg)*) };
+}
+
+/// Logs a warning.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Warn, "warning: ", $($arg)*) };
+}
+
+/// Logs progress information, without a prefix.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Info, "", $($arg)*) };
+}
+
+/// Logs debugging details.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Debug, "debug: ", $($arg)*) };
+}
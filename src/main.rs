@@ -1,32 +1,616 @@
-extern crate sha1;
-extern crate byteorder;
+#![forbid(unstable_features)]
 
-mod cart;
+#[macro_use]
+extern crate farore;
 
-use std::fs::File;
-use std::io::{BufReader, Read, stdout};
+mod batch;
+mod cli;
+mod config;
+mod play;
+mod repl;
 
+use std::fs::{self, File};
+use std::io::{stdin, stdout, BufRead, BufReader, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
 
-fn main() -> Result<(), Box<::std::error::Error>> {
-    let rom_path: String;
-    match std::env::args().nth(1) {
-        Some(x) => {
-            println!("Opening rom {}", x);
-            rom_path = x;
+use farore::bootrom::{self, BootRomPaths};
+use farore::coverage::Coverage;
+use farore::crc::crc32;
+use farore::json::Json;
+use farore::error::FaroreError;
+use farore::savestate::SaveState;
+use farore::script::Script;
+use farore::symbols::SymbolTable;
+use farore::{archive, bps, cart, disasm, ips, logging};
+
+use batch::BatchReport;
+use cli::{BenchLength, CliError, Command, Failure, RomAction, RunOptions};
+use config::Config;
+use play::{KeyMap, PlayPacer};
+use repl::{Reply, Session};
+
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, entry, config) = match cli::parse_args(&args) {
+        Ok(invocation) => {
+            logging::set_max_level(invocation.log_level);
+            (invocation.command, invocation.entry, invocation.config)
+        },
+        Err(CliError::NoCommand) => {
+            eprintln!("{}", cli::USAGE);
+            process::exit(cli::EXIT_USAGE);
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(cli::EXIT_USAGE);
+        },
+    };
+
+    let entry = entry.as_deref();
+    let (result, json) = match command {
+        Command::Help => {
+            println!("{}\n\n{}", cli::USAGE, cli::EXIT_CODES);
+            (Ok(()), false)
+        },
+        Command::Info { rom, json, list: false } => (info(&rom, entry, json), json),
+        Command::Info { rom, json, list: true } => (list(&rom, json), json),
+        Command::Header { rom } => (header(&rom, entry), false),
+        Command::Validate { rom, strict, recursive: false, json, .. } => {
+            (validate(&rom, entry, strict, json), json)
+        },
+        Command::Validate { rom, strict, recursive: true, failed_only, json } => {
+            (validate_tree(&rom, strict, failed_only, json), json)
+        },
+        Command::Run { rom, options } => (run(&rom, entry, config.as_deref(), &options), false),
+        Command::Play { rom, scale, pause_on_focus_loss } => {
+            (play(&rom, entry, config.as_deref(), scale, pause_on_focus_loss), false)
+        },
+        Command::Bench { rom, length, warmup, json } => (bench(&rom, entry, length, warmup), json),
+        Command::Dump { rom, range } => (dump(&rom, entry, range), false),
+        Command::Fix { rom, output, repairs } => (fix(&rom, entry, output.as_deref(), repairs), false),
+        Command::Patch { rom, patch, output, fix_checksums, force } => {
+            (apply_patch(&rom, entry, &patch, &output, fix_checksums, force), false)
+        },
+        Command::Rom { rom, action, output, fix_checksums } => {
+            (rom_surgery(&rom, entry, &action, output.as_deref(), fix_checksums), false)
+        },
+        Command::Disasm { rom, bank, range, follow, budget, sym, coverage } => {
+            let follow = if follow { Some(budget) } else { None };
+            (disassemble(&rom, entry, bank, range, follow, sym.as_deref(), coverage.as_deref()), false)
+        },
+        Command::Debug { rom, sym } => (debug(&rom, entry, sym.as_deref()), false),
+        Command::Config { print_default: true } => {
+            print!("{}", config::DEFAULT_TEMPLATE);
+            (Ok(()), false)
+        },
+        Command::Config { print_default: false } => (show_config(config.as_deref()), false),
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        // Failed validations are already in the JSON report
+        let reported = matches!(e, Failure::Invalid(_) | Failure::Warnings(_));
+        if json && !reported {
+            println!("{}", e.to_json());
+        }
+        process::exit(e.exit_code());
+    }
+}
+
+// A ROM as the CLI loaded it.  When it came out of an archive, `name` is the file inside it, so
+// messages follow the ROM rather than the archive.
+struct LoadedRom {
+    name: String,
+    data: Vec<u8>,
+    archived: bool,
+}
+
+// Reads a ROM from a path, or from stdin for "-", unpacking it if it's in a gzip or zip file.
+fn read_rom(path: &str, entry: Option<&str>) -> Result<LoadedRom, Failure> {
+    unpack_rom(path, read_file(path)?, entry)
+}
+
+// Unpacks what was read from `path` if it's an archive.
+fn unpack_rom(path: &str, data: Vec<u8>, entry: Option<&str>) -> Result<LoadedRom, Failure> {
+    if archive::sniff(&data).is_none() {
+        if entry.is_some() {
+            return Err(Failure::Usage(format!("--entry was given, but {} isn't an archive", path)));
+        }
+        return Ok(LoadedRom { name: path.to_string(), data, archived: false });
+    }
+
+    let unpacked = archive::unpack(&data, entry)
+        .map_err(|e| Failure::from(e).context(&format!("unable to unpack {}", path)))?;
+    // gzip files don't always store the name
+    let name = unpacked.name.unwrap_or_else(|| path.strip_suffix(".gz").unwrap_or(path).to_string());
+    info!("Unpacked {} from {}", name, path);
+    Ok(LoadedRom { name, data: unpacked.data, archived: true })
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, Failure> {
+    if path == "-" {
+        info!("Reading rom from stdin");
+        let stdin = stdin();
+        if stdin.is_terminal() {
+            return Err(Failure::Usage("expected a rom piped to stdin, but stdin is a terminal".to_string()));
+        }
+        return read_rom_from(&mut stdin.lock())
+            .map_err(|e| Failure::Io(format!("unable to read stdin: {}", e)));
+    }
+    info!("Opening rom {}", path);
+    let file = File::open(path).map_err(|e| Failure::Io(format!("unable to open {}: {}", path, e)))?;
+    read_rom_from(&mut BufReader::new(file)).map_err(|e| Failure::Io(format!("unable to read {}: {}", path, e)))
+}
+
+fn read_rom_from(reader: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+    let mut rom = Vec::new();
+    reader.read_to_end(&mut rom)?;
+    Ok(rom)
+}
+
+fn parse_header<'a>(path: &str, rom: &'a [u8]) -> Result<cart::GameboyProgramMeta<'a>, Failure> {
+    debug!("Read {} bytes", rom.len());
+    let meta = cart::GameboyProgramMeta::new(rom)
+        .map_err(|e| Failure::from(e).context("unable to parse the header"))?;
+    check_extension(path, &meta);
+    Ok(meta)
+}
+
+// Warns when the file extension disagrees with the header's CGB flag.
+fn check_extension(path: &str, meta: &cart::GameboyProgramMeta) {
+    let extension = Path::new(path).extension().and_then(|extension| extension.to_str());
+    match extension.map(|extension| extension.to_ascii_lowercase()) {
+        Some(ref extension) if extension == "gb" && meta.is_cgb_only() => {
+            warn!("{} has a .gb extension, but the header says it only runs on CGB", path);
+        },
+        Some(ref extension) if extension == "gbc" && !meta.supports_cgb() => {
+            warn!("{} has a .gbc extension, but the header doesn't declare CGB support", path);
         },
-        None => {
-            eprintln!("Requires a rom at parameter 1.");
+        _ => {},
+    }
+}
+
+fn info(path: &str, entry: Option<&str>, json: bool) -> Result<(), Failure> {
+    let rom = read_rom(path, entry)?;
+    let meta = parse_header(&rom.name, &rom.data)?;
+    if json {
+        println!("{}", meta.to_json());
+    } else {
+        meta.print_debug(&mut stdout());
+    }
+    Ok(())
+}
+
+// Lists the files in an archive with the title from each one's header.
+fn list(path: &str, json: bool) -> Result<(), Failure> {
+    let data = read_file(path)?;
+    let entries = archive::list(&data).map_err(|e| Failure::from(e).context(&format!("unable to list {}", path)))?;
+    let mut rows = Vec::new();
+    for entry in entries {
+        let title = archive::unpack(&data, Some(&entry.name))
+            .map_err(|e| e.to_string())
+            .and_then(|unpacked| {
+                cart::GameboyProgramMeta::new(&unpacked.data).map(|meta| meta.name.to_string()).map_err(|e| e.to_string())
+            });
+        rows.push((entry, title));
+    }
+
+    if json {
+        let rows = rows.iter().map(|(entry, title)| {
+            let title = match title {
+                Ok(title) => ("title", Json::string(title)),
+                Err(error) => ("error", Json::string(error)),
+            };
+            Json::object(vec![("name", Json::string(&entry.name)), ("size", Json::Number(entry.size as i64)), title])
+        });
+        println!("{}", Json::Array(rows.collect()));
+        return Ok(());
+    }
+    let width = rows.iter().map(|(entry, _)| entry.name.len()).max().unwrap_or(0).max(4);
+    println!("{:<width$}  {:>8}  title", "name", "size", width = width);
+    for (entry, title) in rows {
+        let title = title.unwrap_or_else(|e| format!("error: {}", e));
+        println!("{:<width$}  {:>8}  {}", entry.name, entry.size, title, width = width);
+    }
+    Ok(())
+}
+
+fn header(path: &str, entry: Option<&str>) -> Result<(), Failure> {
+    let LoadedRom { name, data: rom, .. } = read_rom(path, entry)?;
+    let meta = parse_header(&name, &rom)?;
+    for field in meta.fields() {
+        let mark = match field.valid {
+            Some(false) => "  FAILED",
+            _ => "",
+        };
+        for (row, bytes) in rom[field.range.clone()].chunks(16).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let label = if row == 0 { format!("{}{}", field.name, mark) } else { String::new() };
+            let line = format!("{:04X}  {:<47}  {}", field.range.start + row * 16, hex.join(" "), label);
+            println!("{}", line.trim_end());
+        }
+    }
+    Ok(())
+}
+
+fn validate(path: &str, entry: Option<&str>, strict: bool, json: bool) -> Result<(), Failure> {
+    let rom = read_rom(path, entry)?;
+    let meta = parse_header(&rom.name, &rom.data)?;
+    if json {
+        println!("{}", meta.validation_json());
+    } else {
+        let test = |x| if x { "OK" } else { "FAILED" };
+        println!("logo: {}", test(meta.is_valid_logo()));
+        println!("header checksum: {}", test(meta.is_valid_header()));
+        println!("global checksum: {}", test(meta.is_valid_program()));
+        println!("size: {}", meta.size_check());
+        println!("runable: {}", test(meta.is_runable()));
+    }
+    if !meta.is_runable() {
+        return Err(Failure::Invalid("the rom would not boot".to_string()));
+    }
+    if strict && !meta.is_valid_program() {
+        return Err(Failure::Warnings("the global checksum doesn't match".to_string()));
+    }
+    if strict && !meta.is_valid_size() {
+        return Err(Failure::Warnings(format!("the size doesn't match the header: {}", meta.size_check())));
+    }
+    Ok(())
+}
+
+fn validate_tree(dir: &str, strict: bool, failed_only: bool, json: bool) -> Result<(), Failure> {
+    let mut report = BatchReport::check_dir(Path::new(dir));
+    report.failed_only = failed_only;
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report);
+    }
+    report.verdict(strict)
+}
+
+fn run(path: &str, entry: Option<&str>, config: Option<&str>, options: &RunOptions) -> Result<(), Failure> {
+    let LoadedRom { name, data: rom, .. } = read_rom(path, entry)?;
+    if let cart::CartridgeType::Invalid(byte) = parse_header(&name, &rom)?.cart_type() {
+        return Err(FaroreError::UnsupportedMapper(byte).into());
+    }
+    let config = load_config(config)?;
+    if options.skip_boot {
+        info!("Skipping the boot rom, starting from the state it leaves the machine in");
+    } else {
+        let paths = BootRomPaths {
+            explicit: options.bootrom.as_ref().map(PathBuf::from),
+            dmg: config.dmg_boot_rom.clone(),
+            cgb: config.cgb_boot_rom.clone(),
+            dir: config.boot_rom_dir.clone(),
+        };
+        bootrom::select(&paths, config.model).map_err(|e| Failure::from(e).context("unable to load the boot rom"))?;
+    }
+    if let Some(ref state_path) = options.load_state {
+        let state = SaveState::load(Path::new(state_path))?;
+        let refused = |e| Failure::from(e).context(&format!("the save state is for {:?}, --force loads it anyway", state.rom_title));
+        state.check_rom(crc32(&rom), options.force).map_err(refused)?;
+        info!("Loading the state taken at frame {}", state.frame);
+    }
+    if let Some(ref script_path) = options.script {
+        let script = Script::load(Path::new(script_path))?;
+        info!("Running {} with {} hooks", script.name, script.hooks.len());
+    }
+    Err(Failure::Usage("running roms isn't supported yet, there is no CPU".to_string()))
+}
+
+fn play(path: &str, entry: Option<&str>, config: Option<&str>, scale: Option<u32>,
+        pause_on_focus_loss: bool) -> Result<(), Failure> {
+    let LoadedRom { name, data: rom, .. } = read_rom(path, entry)?;
+    if let cart::CartridgeType::Invalid(byte) = parse_header(&name, &rom)?.cart_type() {
+        return Err(FaroreError::UnsupportedMapper(byte).into());
+    }
+    let config = load_config(config)?;
+    let _keys = KeyMap::new(&config);
+    let _pacer = PlayPacer::new(pause_on_focus_loss);
+    info!("Playing at {}x", scale.unwrap_or(config.scale));
+    Err(Failure::Usage("playing roms isn't supported yet, there is no CPU".to_string()))
+}
+
+fn bench(path: &str, entry: Option<&str>, length: BenchLength, warmup: u64) -> Result<(), Failure> {
+    let LoadedRom { name, data: rom, .. } = read_rom(path, entry)?;
+    if let cart::CartridgeType::Invalid(byte) = parse_header(&name, &rom)?.cart_type() {
+        return Err(FaroreError::UnsupportedMapper(byte).into());
+    }
+    match length {
+        BenchLength::Seconds(seconds) => info!("Benchmarking for {}s after {} warm-up frames", seconds, warmup),
+        BenchLength::Frames(frames) => info!("Benchmarking {} frames, the first {} as a warm-up", frames, warmup),
+    }
+    Err(Failure::Usage("benchmarking isn't supported yet, there is no CPU".to_string()))
+}
+
+fn fix(path: &str, entry: Option<&str>, output: Option<&str>, repairs: cart::Repairs) -> Result<(), Failure> {
+    // No output means --in-place
+    if let Some(output) = output {
+        if is_same_file(path, output) {
+            return Err(Failure::Usage(format!("{} is the input rom, use --in-place to overwrite it", output)));
+        }
+    }
+    let output = output.unwrap_or(path);
+
+    let LoadedRom { name, data: mut rom, archived } = read_rom(path, entry)?;
+    if archived && output == path {
+        return Err(Failure::Usage(format!("{} is an archive, use -o to write the repaired rom", path)));
+    }
+    println!("before: {}", validation_summary(&parse_header(&name, &rom)?));
+    cart::repair(&mut rom, repairs).map_err(|e| Failure::from(e).context("unable to repair the rom"))?;
+    println!("after:  {}", validation_summary(&parse_header(output, &rom)?));
+    write_rom(output, &rom)
+}
+
+// IPS or BPS, going by the patch's magic bytes.
+fn apply_patch(path: &str, entry: Option<&str>, patch: &str, output: &str, fix_checksums: bool,
+               force: bool) -> Result<(), Failure> {
+    if is_same_file(path, output) {
+        return Err(Failure::Usage(format!("{} is the input rom, pick another output", output)));
+    }
+    let mut rom = read_rom(path, entry)?.data;
+    let patch_data = read_file(patch)?;
+    let failed = |e| Failure::from(FaroreError::from(e)).context(&format!("unable to apply {}", patch));
+    if patch_data.starts_with(b"BPS1") {
+        let bps = bps::BpsPatch::parse(&patch_data).map_err(failed)?;
+        if !bps.metadata.is_empty() {
+            println!("metadata: {}", bps.metadata);
+        }
+        rom = bps.apply(&rom, force).map_err(failed)?;
+        println!("made a {} byte rom", rom.len());
+    } else {
+        let summary = ips::apply(&mut rom, &patch_data).map_err(failed)?;
+        println!("applied {} records, {} bytes changed", summary.records, summary.bytes_changed);
+        if let Some(size) = summary.truncated_to {
+            println!("truncated to {} bytes", size);
+        }
+    }
+    if fix_checksums {
+        let checksums = cart::Repairs { logo: false, checksums: true, pad: false };
+        cart::repair(&mut rom, checksums).map_err(|e| Failure::from(e).context("unable to repair the checksums"))?;
+    }
+    match cart::GameboyProgramMeta::new(&rom) {
+        Ok(meta) => println!("{}", validation_summary(&meta)),
+        Err(e) => warn!("The patched rom has no valid header: {}", e),
+    }
+    write_rom(output, &rom)
+}
+
+fn rom_surgery(path: &str, entry: Option<&str>, action: &RomAction, output: Option<&str>,
+               fix_checksums: bool) -> Result<(), Failure> {
+    if let Some(output) = output {
+        if !matches!(*action, RomAction::Split { .. }) && is_same_file(path, output) {
+            return Err(Failure::Usage(format!("{} is the input rom, use --in-place to overwrite it", output)));
+        }
+    }
+    let LoadedRom { data: mut rom, archived, .. } = read_rom(path, entry)?;
+    let before = rom.len();
+    match *action {
+        RomAction::Pad { size, fill } => {
+            let size = match size {
+                Some(size) => size,
+                None => parse_header(path, &rom)?.declared_size().ok_or_else(|| {
+                    Failure::Header("the header's rom size code is unknown, give the size with --to".to_string())
+                })?,
+            };
+            cart::pad(&mut rom, size, fill).map_err(|e| Failure::from(e).context("unable to pad the rom"))?;
+        },
+        RomAction::Trim { force } => {
+            cart::trim(&mut rom, force).map_err(|e| match e {
+                FaroreError::InvalidArgument(message) => Failure::Usage(format!("{}, use --force to trim anyway", message)),
+                e => Failure::from(e).context("unable to trim the rom"),
+            })?;
+        },
+        RomAction::Split { .. } => {},
+    }
+    if fix_checksums {
+        let checksums = cart::Repairs { logo: false, checksums: true, pad: false };
+        cart::repair(&mut rom, checksums).map_err(|e| Failure::from(e).context("unable to repair the checksums"))?;
+    }
+    println!("before: {} bytes, after: {} bytes", before, rom.len());
+
+    if let RomAction::Split { banks } = *action {
+        let prefix = match output {
+            Some(prefix) => prefix.to_string(),
+            None => Path::new(path).with_extension("").display().to_string(),
+        };
+        let pieces = cart::split(&rom, banks);
+        let count = pieces.len();
+        for (i, piece) in pieces.enumerate() {
+            write_rom(&format!("{}-{:02}.bin", prefix, i), piece)?;
+        }
+        println!("wrote {} files of up to {} banks", count, banks);
+        return Ok(());
+    }
+    let output = output.unwrap_or(path);
+    if archived && output == path {
+        return Err(Failure::Usage(format!("{} is an archive, use -o to write the rom", path)));
+    }
+    write_rom(output, &rom)
+}
+
+fn is_same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Writes next to the destination and renames over it, so a failure never leaves half a rom.
+fn write_rom(output: &str, rom: &[u8]) -> Result<(), Failure> {
+    let temp = format!("{}.tmp", output);
+    let written = File::create(&temp)
+        .and_then(|mut file| file.write_all(rom).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&temp, output));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(Failure::Io(format!("unable to write {}: {}", output, e)));
+    }
+    info!("Wrote {}", output);
+    Ok(())
+}
+
+fn validation_summary(meta: &cart::GameboyProgramMeta) -> String {
+    let test = |x| if x { "OK" } else { "FAILED" };
+    format!("logo {}, header checksum {}, global checksum {}, size {}",
+        test(meta.is_valid_logo()), test(meta.is_valid_header()), test(meta.is_valid_program()),
+        test(meta.is_valid_size()))
+}
+
+fn dump(path: &str, entry: Option<&str>, range: Range<usize>) -> Result<(), Failure> {
+    let rom = read_rom(path, entry)?.data;
+    if range.end > rom.len() {
+        return Err(Failure::Usage(format!("range ends past the rom size of 0x{:X}", rom.len())));
+    }
+    let start = range.start;
+    for (row, bytes) in rom[range].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes.iter()
+            .map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' })
+            .collect();
+        println!("{:06X}  {:<47}  |{}|", start + row * 16, hex.join(" "), text);
+    }
+    Ok(())
+}
+
+// With `follow`, traces at most that many instructions.
+fn disassemble(path: &str, entry: Option<&str>, bank: Option<usize>, range: Option<Range<usize>>,
+               follow: Option<usize>, sym: Option<&str>, coverage: Option<&str>) -> Result<(), Failure> {
+    let rom = read_rom(path, entry)?.data;
+    let bank = bank.unwrap_or(1);
+    let banks = rom.len().div_ceil(0x4000);
+    if bank > 0 && bank >= banks {
+        return Err(Failure::Usage(format!("bank {} is past the end of the rom, which has {} banks", bank, banks)));
+    }
+    let coverage = match coverage {
+        Some(coverage) => {
+            let text = fs::read_to_string(coverage)
+                .map_err(|e| Failure::Io(format!("unable to read {}: {}", coverage, e)))?;
+            Some(Coverage::parse(&text).map_err(|e| Failure::from(e).context(&format!("unable to load {}", coverage)))?)
+        },
+        None => None,
+    };
+    let symbols = load_symbols(path, sym)?;
+
+    let view = disasm::BankView::new(&rom, bank);
+    let mut listing = match (range, follow, coverage) {
+        (range, Some(budget), _) => disasm::Listing::follow(&view, range.map_or(0x0100, |range| range.start as u16), budget),
+        (Some(range), None, Some(coverage)) => disasm::Listing::covered(&view, range.start as u16..range.end as u16, &coverage),
+        (Some(range), None, None) => disasm::Listing::linear(&view, range.start as u16..range.end as u16),
+        (None, None, _) => return Err(Failure::Usage("disasm needs --range or --follow".to_string())),
+    };
+    if let Some(symbols) = symbols {
+        listing.name_from(&symbols, &view);
+    }
+    print!("{}", listing);
+    Ok(())
+}
+
+// Loads the symbols given with --sym, or the .sym file next to the rom if there is one.  A
+// broken file that was only found, not asked for, is skipped with a warning.
+fn debug(path: &str, entry: Option<&str>, sym: Option<&str>) -> Result<(), Failure> {
+    let LoadedRom { name, data: rom, .. } = read_rom(path, entry)?;
+    if let cart::CartridgeType::Invalid(byte) = parse_header(&name, &rom)?.cart_type() {
+        return Err(FaroreError::UnsupportedMapper(byte).into());
+    }
+    let mut session = Session::new(load_symbols(path, sym)?);
+    let stdin = stdin();
+    let prompt = stdin.is_terminal();
+    let mut lines = stdin.lock();
+    let mut line = String::new();
+    loop {
+        if prompt {
+            print!("(farore) ");
+            let _ = stdout().flush();
+        }
+        line.clear();
+        if lines.read_line(&mut line).map_err(|e| Failure::Io(format!("unable to read stdin: {}", e)))? == 0 {
             return Ok(());
+        }
+        match session.respond(&line) {
+            Reply::Print(text) if text.is_empty() => {},
+            Reply::Print(text) => println!("{}", text),
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+fn load_symbols(rom: &str, sym: Option<&str>) -> Result<Option<SymbolTable>, Failure> {
+    if let Some(sym) = sym {
+        let symbols = SymbolTable::load(Path::new(sym))
+            .map_err(|e| Failure::from(e).context(&format!("unable to load the symbols from {}", sym)))?;
+        return Ok(Some(symbols));
+    }
+    let beside = Path::new(rom).with_extension("sym");
+    if rom == "-" || !beside.is_file() {
+        return Ok(None);
+    }
+    match SymbolTable::load(&beside) {
+        Ok(symbols) => {
+            info!("Read {} symbols from {}", symbols.len(), beside.display());
+            Ok(Some(symbols))
+        },
+        Err(e) => {
+            warn!("Ignoring {}: {}", beside.display(), e);
+            Ok(None)
         },
     }
+}
 
-    let mut rom_buf: Vec<u8> = Vec::new();
-    match File::open(rom_path.clone()) {
-        Ok(file) => BufReader::new(&file).read_to_end(&mut rom_buf).ok(),
-        Err(..) => panic!("Unable to open file {}", rom_path),
+// Loads the config file given with --config, or the default one if it exists.  Without either,
+// the built-in defaults are used.
+fn load_config(path: Option<&str>) -> Result<Config, Failure> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match Config::default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
     };
+    info!("Reading config {}", path.display());
+    let text = fs::read_to_string(&path)
+        .map_err(|e| Failure::Io(format!("unable to read {}: {}", path.display(), e)))?;
+    Config::parse(&text).map_err(|e| Failure::Usage(format!("{}: {}", path.display(), e)))
+}
 
-    let meta = cart::GameboyProgramMeta::new(&rom_buf)?;
-    meta.print_debug(&mut stdout());
+fn show_config(path: Option<&str>) -> Result<(), Failure> {
+    print!("{}", load_config(path)?);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn corpus(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join("archive").join(name)
+    }
+
+    // Loads `path` the way "-" would if it were piped in.
+    fn read_piped(path: &Path, entry: Option<&str>) -> Result<LoadedRom, Failure> {
+        let data = read_rom_from(&mut Cursor::new(fs::read(path).unwrap())).unwrap();
+        unpack_rom("-", data, entry)
+    }
+
+    #[test]
+    fn stdin_loads_the_same_rom_as_the_file() {
+        let plain = env::temp_dir().join(format!("farore-stdin-{}.gb", process::id()));
+        fs::write(&plain, (0..0x8000).map(|i| (i * 7) as u8).collect::<Vec<u8>>()).unwrap();
+        let paths = [plain.clone(), corpus("gzip"), corpus("zip-deflated")];
+        for path in &paths {
+            let file = read_rom(path.to_str().unwrap(), None).unwrap_or_else(|e| panic!("{}: {:?}", path.display(), e));
+            let piped = read_piped(path, None).unwrap();
+            assert_eq!((&piped.data, piped.archived), (&file.data, file.archived), "{}", path.display());
+        }
+        let _ = fs::remove_file(&plain);
+
+        assert!(read_piped(&corpus("gzip-truncated"), None).is_err());
+        assert!(read_piped(&corpus("zip-two-roms"), None).is_err());
+    }
+}
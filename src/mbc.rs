@@ -1,9 +1,26 @@
-// Memory controllers
+//! Memory controllers
+//!
+//! The cartridge's bank switching, and a skeleton of the bus that routes the CPU's address space
+//! to it.  Games write whatever they like to these registers, so nothing here panics on a value
+//! or address the hardware would ignore.
 
+use cheat::CheatList;
 
+
+/// A cartridge's view of 0000-7FFF and A000-BFFF.
 pub trait MemoryBankController {
+    /// Reads from ROM or external RAM.
     fn read(&self, address: u16) -> u8;
+    /// Writes to a banking register or external RAM.
     fn write(&mut self, address: u16, value: u8);
+
+    /// The ROM bank mapped at 4000, always less than the number of banks in the ROM.
+    fn current_rom_bank(&self) -> usize;
+
+    /// The external RAM bank mapped at A000, for cheats that only poke one bank.
+    fn ram_bank(&self) -> u8 {
+        0
+    }
 }
 
 /// Memory Map
@@ -21,7 +38,7 @@ pub trait MemoryBankController {
 ///   FFFF        Interrupt Enable Register
 struct GBMemory {
     // The memory bank controller on the current cart
-    mbc: Box<MemoryBankController>,
+    mbc: Box<dyn MemoryBankController>,
 
     // Video ram
     vram: [u8; 0x2000],
@@ -37,99 +54,132 @@ struct GBMemory {
 
     // High RAM (HRAM)
     hram: [u8; 0x80],
+
+    // Game Genie codes patch what the CPU reads from ROM, GameShark codes poke RAM each frame
+    cheats: CheatList,
 }
 
+#[allow(dead_code)] // Waiting on a CPU to drive it
 impl GBMemory {
-    fn new(mbc: Box<MemoryBankController>) -> Self {
+    fn new(mbc: Box<dyn MemoryBankController>) -> Self {
         GBMemory {
             mbc,
-            vram: Default::default(),
-            wram: Default::default(),
-            hram: Default::default(),
+            vram: [0; 0x2000],
+            wram: [0; 0x2000],
+            hram: [0; 0x80],
+            cheats: CheatList::new(),
         }
     }
 
     fn read(&self, address: u16) -> u8 {
         let addr = address as usize;
         match address {
-            0x0000..0x8000 => self.mbc.read(address),
-            0x8000..0xA000 => self.vram[addr-0x8000],
-            0xA000..0xC000 => self.mbc.read(address),
-            0xC000..0xE000 => self.wram[addr-0xC000],
-            0xE000..0xFE00 => self.wram[addr-0xE000],
-            0xFE00..0xFEA0 => unimplemented!(), // Sprite attribute table
-            0xFEA0..0xFF00 => unimplemented!(), // unusable
-            0xFF00..0xFF80 => unimplemented!(), // I/O ports
-            0xFF80..0xFFFE => self.hram[addr-0xFF80],
-            0xFFFF         => unimplemented!(), // Interrupt Enable Register
+            0x0000..=0x7FFF => self.cheats.patch_rom_read(address, self.mbc.read(address)),
+            0x8000..=0x9FFF => self.vram[addr-0x8000],
+            0xA000..=0xBFFF => self.mbc.read(address),
+            0xC000..=0xDFFF => self.wram[addr-0xC000],
+            0xE000..=0xFDFF => self.wram[addr-0xE000],
+            0xFE00..=0xFE9F => 0xFF, // Sprite attribute table, not wired in yet
+            0xFEA0..=0xFEFF => 0xFF, // unusable
+            0xFF00..=0xFF7F => 0xFF, // I/O ports, not wired in yet
+            0xFF80..=0xFFFE => self.hram[addr-0xFF80],
+            0xFFFF          => 0xFF, // Interrupt Enable Register, not wired in yet
         }
     }
 
-    fn write(&self, address: u16, value: u8) {
+    fn write(&mut self, address: u16, value: u8) {
         let addr = address as usize;
         match address {
-            0x0000..0x8000 => self.mbc.write(address, value),
-            0x8000..0xA000 => self.vram[addr-0x8000] = value,
-            0xA000..0xC000 => self.mbc.write(address, value),
-            0xC000..0xE000 => self.wram[addr-0xC000] = value,
-            0xE000..0xFE00 => self.wram[addr-0xE000] = value,
-            0xFE00..0xFEA0 => unimplemented!(), // Sprite attribute table
-            0xFEA0..0xFF00 => unimplemented!(), // unusable
-            0xFF00..0xFF80 => unimplemented!(), // I/O ports
-            0xFF80..0xFFFE => self.hram[addr-0xFF80] = value,
-            0xFFFF         => unimplemented!(), // Interrupt Enable Register
+            0x0000..=0x7FFF => self.mbc.write(address, value),
+            0x8000..=0x9FFF => self.vram[addr-0x8000] = value,
+            0xA000..=0xBFFF => self.mbc.write(address, value),
+            0xC000..=0xDFFF => self.wram[addr-0xC000] = value,
+            0xE000..=0xFDFF => self.wram[addr-0xE000] = value,
+            0xFE00..=0xFE9F => {}, // Sprite attribute table, not wired in yet
+            0xFEA0..=0xFEFF => {}, // unusable
+            0xFF00..=0xFF7F => {}, // I/O ports, not wired in yet
+            0xFF80..=0xFFFE => self.hram[addr-0xFF80] = value,
+            0xFFFF          => {}, // Interrupt Enable Register, not wired in yet
+        }
+    }
+
+    // Applies the GameShark codes, once a frame at VBlank.  Writes go through the bus like the
+    // real device's, so cartridge RAM that isn't enabled ignores them.
+    fn apply_ram_cheats(&mut self) {
+        let writes: Vec<_> = self.cheats.ram_writes().collect();
+        for (bank, address, value) in writes {
+            let mapped = match address {
+                0xA000..=0xBFFF => self.mbc.ram_bank(),
+                0xC000..=0xCFFF => 0,
+                _ => 1, // No CGB banking yet, D000 is always bank 1
+            };
+            if bank.is_none_or(|bank| bank == mapped) {
+                self.write(address, value);
+            }
         }
     }
 }
 
-trait Ram {
+/// External RAM on the cartridge, in one or more 8KB banks.
+pub trait Ram {
+    /// Reads a byte, `address` counting from A000.
     fn read(&self, bank: u8, address: u16) -> u8;
+    /// Writes a byte, `address` counting from A000.
     fn write(&mut self, bank: u8, address: u16, value: u8);
 
+    /// The contents, for a battery save.
     fn serialize(&self) -> Vec<u8>;
 }
 
-struct Ram2kb {
+/// A single 2KB bank, mapped to A000-A7FF.  Reads past the end or from another bank see an
+/// open bus, and writes there are dropped.
+pub struct Ram2kb {
     memory: [u8; 0x800]
 }
 
 impl Ram2kb {
-    fn new() -> Self {
+    /// Cleared RAM.
+    pub fn new() -> Self {
         Ram2kb {
             memory: [0; 0x800]
         }
     }
 
-    fn load(mem: &[u8]) -> Self {
-        unimplemented!()
+    /// RAM restored from a battery save.  A short save leaves the rest cleared, and anything
+    /// past 2KB is ignored.
+    pub fn load(mem: &[u8]) -> Self {
+        let mut ram = Ram2kb::new();
+        let len = mem.len().min(ram.memory.len());
+        ram.memory[..len].copy_from_slice(&mem[..len]);
+        ram
+    }
+}
+
+impl Default for Ram2kb {
+    fn default() -> Self {
+        Ram2kb::new()
     }
 }
 
 impl Ram for Ram2kb {
     fn read(&self, bank: u8, address: u16) -> u8 {
         let addr = address as usize;
-        if addr > self.memory.len() {
-            panic("Attempted to read memory outside of range of RAM bank");
-        }
-        if bank != 0 {
-            panic("Attempted to read memory from bank {} from bankless ram", bank);
+        if addr >= self.memory.len() || bank != 0 {
+            return 0xFF;
         }
         self.memory[addr]
     }
 
     fn write(&mut self, bank: u8, address: u16, value: u8) {
         let addr = address as usize;
-        if addr > self.memory.len() {
-            panic("Attempted to write memory outside of range of RAM bank");
-        }
-        if bank != 0 {
-            panic("Attempted to write memory to bank {} from bankless ram", bank);
+        if addr >= self.memory.len() || bank != 0 {
+            return;
         }
         self.memory[addr] = value;
     }
 
-    fn serialize(&self) -> _ {
-        unimplemented!()
+    fn serialize(&self) -> Vec<u8> {
+        self.memory.to_vec()
     }
 }
 
@@ -147,13 +197,15 @@ impl Ram for Ram2kb {
 //    }
 //}
 
+/// The MBC1 controller, for up to 2MB of ROM and 32KB of RAM.
 pub struct MBC1 {
+    // The whole ROM, in 16KB banks.
     // The first bank is always mapped to 0x0-0x3FFF
     // each subsequent bank may be mapped to 0x4000-0x7FFF
     // Note that banks 0x20, 0x40, and 0x60 cannot be used.  When attempting to map these
     // banks, switch to bank 0x21, 0x41, and 0x61 respectively.
     // Similarly, when attempting to map bank 0, map bank 1 instead.  Bank 0 is always mapped.
-    rom_banks: [[u8; 0x4000]; 0x80],
+    rom: Vec<u8>,
 
     // Writing to 0x2000-0x3FFF takes the lower 5 bits and uses them for bank selection
     // so in the range of 0x01-0x1F (inclusive).  Writing 0x00 also selects 0x01.
@@ -163,7 +215,7 @@ pub struct MBC1 {
     // if the cart has a 2kb bank, its mapped to 0xA000-0xA7FF
     // if the cart has an 8kb bank, its mapped to 0xA000-0xBFFF
     // if the cart has a 32kb bank, its split into 4 banks and mapped to 0xA000-0xBFFF
-    ram_bank: Box<Ram>,
+    ram_bank: Box<dyn Ram>,
     ram_bank_number: u8,
     ram_write_enabled: bool,
 
@@ -181,9 +233,10 @@ pub struct MBC1 {
 }
 
 impl MBC1 {
-    fn new(ram: Box<Ram>) -> Self {
+    /// A controller for `rom`, with `ram` on the cartridge.
+    pub fn new(rom: Vec<u8>, ram: Box<dyn Ram>) -> Self {
         MBC1 {
-            rom_banks: Default::default(),
+            rom,
             rom_bank_number: 1,  // Rom bank zero cannot be mapped twice, so default to 1
             ram_bank: ram,
             ram_bank_number: 0,
@@ -197,31 +250,48 @@ impl MBC1 {
             0x00 => 0x01,
             0x20 => 0x21,
             0x40 => 0x41,
+            0x60 => 0x61,
+            bank => bank,
         };
         self.rom_bank_number = real_bank;
     }
+
+    // A ROM shorter than a bank still counts as one, and reads past its end see an open bus.
+    fn read_rom(&self, bank: usize, offset: usize) -> u8 {
+        self.rom.get(bank * 0x4000 + offset).cloned().unwrap_or(0xFF)
+    }
 }
 
 impl MemoryBankController for MBC1 {
     fn read(&self, address: u16) -> u8 {
         let addr = address as usize;
         match address {
-            0x0000..0x4000 => self.rom_banks[0][addr],
-            0x4000..0x8000 => self.rom_banks[self.rom_bank_number as usize][addr - 0x4000],
-            0xA000..0xC000 => self.ram_bank.read(self.ram_bank_number, addr - 0xA000),
-            _ => unreachable!(),
+            0x0000..=0x3FFF => self.read_rom(0, addr),
+            0x4000..=0x7FFF => self.read_rom(self.current_rom_bank(), addr - 0x4000),
+            0xA000..=0xBFFF => self.ram_bank.read(self.ram_bank_number, address - 0xA000),
+            _ => 0xFF,
         }
     }
 
+    // Banks past the end of the ROM wrap around, as only the low bits of the bank number are
+    // wired to the chip.
+    fn current_rom_bank(&self) -> usize {
+        let banks = (self.rom.len() / 0x4000).max(1);
+        self.rom_bank_number as usize % banks
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank_number
+    }
+
     fn write(&mut self, address: u16, value: u8) {
-        let addr = address as usize;
         match address {
             // Mask lower 4 bits, looking for 0xA.  0xA enables writing, any other
             // value disables writing
-            0x0000..0x2000 => self.ram_write_enabled = value & 0xF == 0xA,
+            0x0000..=0x1FFF => self.ram_write_enabled = value & 0xF == 0xA,
 
             // Select the lower 5 bits and use them to begin selecting the rom bank
-            0x2000..0x4000 => {
+            0x2000..=0x3FFF => {
                 let oldnum = self.rom_bank_number & 0xE0;
                 let req = value & 0x1F;
                 self.set_rom_bank(oldnum | req);
@@ -231,10 +301,10 @@ impl MemoryBankController for MBC1 {
             // two bits of the rom bank number
             // If in ram banking mode, then this 2 bit register is used to select the current
             // ram bank in range 0x00-0x03
-            0x4000..0x6000 => {
+            0x4000..=0x5FFF => {
                 let mask = value & 0x3;
                 if self.is_rom_banking_mode  {
-                    self.oldval = self.rom_bank_number & 0x1F;
+                    let oldval = self.rom_bank_number & 0x1F;
                     self.set_rom_bank(oldval | (mask << 5));
                 } else {
                     self.ram_bank_number = mask;
@@ -244,9 +314,9 @@ impl MemoryBankController for MBC1 {
             // This 1 bit register controls the behavior of the above 2 bit register.
             // 0x00 => switch to rom banking mode (default)
             // 0x01 => switch to ram banking mode
-            0x6000..0x8000 => {
-                // insufficient documentation here
-                match value {
+            0x6000..=0x7FFF => {
+                // Only the low bit is wired up
+                match value & 0x01 {
                     0x00 => {
                         // rom banking mode
                         self.is_rom_banking_mode = true;
@@ -255,19 +325,17 @@ impl MemoryBankController for MBC1 {
                     0x01 => {
                         // ram banking mode
                         self.is_rom_banking_mode = false;
-                        self.rom_bank_number = self.rom_bank_number & 0x1F;
+                        self.rom_bank_number &= 0x1F;
                     },
                     _ => unreachable!(),
                 }
             },
 
-            0xA000..0xC000 => {
-                if !self.ram_write_enabled {
-                    panic!("Attempted to write ram while ram writing is disabled");
-                }
+            // The chip ignores writes until they're enabled
+            0xA000..=0xBFFF if self.ram_write_enabled => {
                 self.ram_bank.write(self.ram_bank_number, address - 0xA000, value);
             },
-            _ => unreachable!(),
+            _ => {},
         }
     }
-}
\ No newline at end of file
+}
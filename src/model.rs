@@ -0,0 +1,19 @@
+//! Hardware models
+
+
+/// The console being emulated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum HardwareModel {
+    #[default]
+    /// Original Game Boy.
+    Dmg,
+    /// Game Boy Color.
+    Cgb,
+}
+
+impl HardwareModel {
+    /// Whether the CGB-only hardware is present.
+    pub fn is_cgb(self) -> bool {
+        self == HardwareModel::Cgb
+    }
+}
@@ -0,0 +1,52 @@
+//! Result reporting of the mooneye-gb test ROMs
+//!
+//! The tests end by running LD B,B, the magic breakpoint, with the result in the registers:
+//!   Passed   B=3 C=5 D=8 E=13 H=21 L=34, the Fibonacci numbers
+//!   Failed   0x42 in all six
+
+use std::fmt;
+
+
+/// The opcode of LD B,B.
+pub const MAGIC_BREAKPOINT: u8 = 0x40;
+
+const PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAILED: [u8; 6] = [0x42; 6];
+
+/// How a test ROM ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MooneyeResult {
+    /// It reached the breakpoint with the Fibonacci numbers.
+    Pass,
+    /// It reached the breakpoint with 0x42 everywhere.
+    Fail,
+    /// It ran out of cycles before reaching the breakpoint.
+    Timeout,
+}
+
+impl MooneyeResult {
+    /// Reads the result from B, C, D, E, H and L, in that order, as they are when the magic
+    /// breakpoint runs.  Any other values aren't a result, so the ROM should keep running.
+    pub fn from_registers(registers: [u8; 6]) -> Option<Self> {
+        match registers {
+            PASSED => Some(MooneyeResult::Pass),
+            FAILED => Some(MooneyeResult::Fail),
+            _ => None,
+        }
+    }
+
+    /// Whether the ROM passed.
+    pub fn passed(self) -> bool {
+        self == MooneyeResult::Pass
+    }
+}
+
+impl fmt::Display for MooneyeResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MooneyeResult::Pass => write!(f, "pass"),
+            MooneyeResult::Fail => write!(f, "fail"),
+            MooneyeResult::Timeout => write!(f, "timeout"),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Input movies, for replaying a run exactly
+//!
+//! File layout, little endian:
+//!   00-03   Magic "FGBM"
+//!   04      Version, 1
+//!   05      Model: 0 DMG, 1 CGB
+//!   06      Flags: bit 0 recorded from reset
+//!   07      Reserved
+//!   08-0B   CRC-32 of the ROM
+//!   0C-0F   Frame count
+//!   10-     One byte of buttons per frame, in `Button` bit order
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use error::FaroreError;
+use joypad::{InputState, JoypadInput};
+use model::HardwareModel;
+use ppu::{Frame, FrameCallback};
+
+
+const MAGIC: [u8; 4] = *b"FGBM";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 0x10;
+
+/// A recorded run: the joypad state at every frame, and what it was recorded against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputMovie {
+    /// The ROM it was recorded with, to catch playing it back on another.
+    pub rom_crc32: u32,
+    /// The model it was recorded on.
+    pub model: HardwareModel,
+    /// Whether recording started from power on.
+    pub from_reset: bool,
+    /// The buttons held at each frame.
+    pub frames: Vec<InputState>,
+}
+
+impl InputMovie {
+    /// An empty movie.
+    pub fn new(rom_crc32: u32, model: HardwareModel, from_reset: bool) -> Self {
+        InputMovie { rom_crc32, model, from_reset, frames: Vec::new() }
+    }
+
+    /// Saves the movie in the file layout above.
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0x00..0x04].copy_from_slice(&MAGIC);
+        header[0x04] = VERSION;
+        header[0x05] = self.model.is_cgb() as u8;
+        header[0x06] = self.from_reset as u8;
+        header[0x08..0x0C].copy_from_slice(&self.rom_crc32.to_le_bytes());
+        header[0x0C..0x10].copy_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        writer.write_all(&header)?;
+
+        let frames: Vec<u8> = self.frames.iter().map(InputState::bits).collect();
+        writer.write_all(&frames)
+    }
+
+    /// Loads a movie.  Fails with `InvalidArgument` on anything but a whole version 1 movie
+    /// file.
+    pub fn read(reader: &mut dyn Read) -> Result<Self, FaroreError> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header).map_err(|e| read_error(e, "the header is truncated"))?;
+        if header[0x00..0x04] != MAGIC {
+            return Err(invalid("not an input movie"));
+        }
+        if header[0x04] != VERSION {
+            return Err(invalid(&format!("unsupported movie version {}", header[0x04])));
+        }
+        let model = match header[0x05] {
+            0 => HardwareModel::Dmg,
+            1 => HardwareModel::Cgb,
+            model => return Err(invalid(&format!("unknown model {}", model))),
+        };
+
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&header[0x08..0x0C]);
+        let rom_crc32 = u32::from_le_bytes(word);
+        word.copy_from_slice(&header[0x0C..0x10]);
+        let count = u32::from_le_bytes(word) as u64;
+        // Read rather than allocate up front, so a bogus frame count can't ask for gigabytes
+        let mut frames = Vec::new();
+        reader.take(count).read_to_end(&mut frames).map_err(|e| read_error(e, "the movie is truncated"))?;
+        if (frames.len() as u64) < count {
+            return Err(invalid(&format!("the movie has {} frames, the header says {}", frames.len(), count)));
+        }
+
+        Ok(InputMovie {
+            rom_crc32,
+            model,
+            from_reset: header[0x06] & 0x01 != 0,
+            frames: frames.into_iter().map(InputState::from_bits).collect(),
+        })
+    }
+
+    /// Loads a movie from a file.
+    pub fn load(path: &Path) -> Result<Self, FaroreError> {
+        InputMovie::read(&mut &fs::read(path).map_err(|e| FaroreError::io(path, e))?[..])
+    }
+}
+
+fn invalid(reason: &str) -> FaroreError {
+    FaroreError::InvalidArgument(format!("invalid input movie: {}", reason))
+}
+
+// Running out of data means the movie is cut short, anything else is the reader's own failure.
+fn read_error(e: io::Error, truncated: &str) -> FaroreError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid(truncated),
+        _ => FaroreError::InvalidArgument(format!("unable to read the input movie: {}", e)),
+    }
+}
+
+/// Records the joypad latch once per frame.  Clones share the same movie, so one can be turned
+/// into the PPU's frame callback while another collects the result.
+#[derive(Clone)]
+pub struct MovieRecorder {
+    movie: Rc<RefCell<InputMovie>>,
+    input: JoypadInput,
+}
+
+impl MovieRecorder {
+    /// Records onto the end of `movie`, reading the joypad through `input`.
+    pub fn new(movie: InputMovie, input: JoypadInput) -> Self {
+        MovieRecorder { movie: Rc::new(RefCell::new(movie)), input }
+    }
+
+    /// Appends the buttons currently held as the next frame.
+    pub fn record_frame(&self) {
+        self.movie.borrow_mut().frames.push(self.input.inputs());
+    }
+
+    /// A callback for the PPU that records a frame each time.
+    pub fn frame_callback(&self) -> FrameCallback {
+        let recorder = self.clone();
+        Box::new(move |_: &Frame| recorder.record_frame())
+    }
+
+    /// A copy of what's been recorded so far.
+    pub fn movie(&self) -> InputMovie {
+        self.movie.borrow().clone()
+    }
+}
+
+/// Plays a movie back by overriding the joypad latch at every frame.  After the last frame the
+/// inputs are left alone, so the frontend can take over.
+#[derive(Clone)]
+pub struct MoviePlayer {
+    state: Rc<RefCell<PlayerState>>,
+}
+
+struct PlayerState {
+    movie: InputMovie,
+    input: JoypadInput,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    /// Starts playback, applying the first frame right away.  Fails if the ROM differs from
+    /// the recording, which would desync it.
+    pub fn new(movie: InputMovie, input: JoypadInput, rom_crc32: u32) -> Result<Self, FaroreError> {
+        if movie.rom_crc32 != rom_crc32 {
+            return Err(FaroreError::RomMismatch { expected_crc32: movie.rom_crc32, found_crc32: rom_crc32 });
+        }
+        if let Some(&first) = movie.frames.first() {
+            input.set_inputs(first);
+        }
+        let state = PlayerState { movie, input, frame: 0 };
+        Ok(MoviePlayer { state: Rc::new(RefCell::new(state)) })
+    }
+
+    /// Moves on to the next frame's buttons.
+    pub fn advance_frame(&self) {
+        let mut state = self.state.borrow_mut();
+        state.frame += 1;
+        if let Some(&inputs) = state.movie.frames.get(state.frame) {
+            state.input.set_inputs(inputs);
+        }
+    }
+
+    /// A callback for the PPU that advances a frame each time.
+    pub fn frame_callback(&self) -> FrameCallback {
+        let player = self.clone();
+        Box::new(move |_: &Frame| player.advance_frame())
+    }
+
+    /// The frame being played.
+    pub fn frame(&self) -> usize {
+        self.state.borrow().frame
+    }
+
+    /// Whether every recorded frame has been played.
+    pub fn is_finished(&self) -> bool {
+        let state = self.state.borrow();
+        state.frame >= state.movie.frames.len()
+    }
+}
@@ -0,0 +1,115 @@
+//! Frame pacing for the frontend
+//!
+//! The pacer only does arithmetic on timestamps handed to it, so it runs the same against a
+//! real clock and a made up one.  Frames are scheduled from an anchor time rather than from
+//! the previous frame, so rounding never adds up to drift.
+
+use std::time::Duration;
+
+use ppu::DOTS_PER_FRAME;
+
+
+const CLOCK_RATE: u64 = 4_194_304;
+
+/// How long the hardware takes to draw a frame, about 16.74ms.
+pub const FRAME_TIME: Duration = Duration::from_nanos(DOTS_PER_FRAME as u64 * 1_000_000_000 / CLOCK_RATE);
+
+// Falling further behind than this drops the missed frames instead of racing to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// What to do until the next call to `FramePacer::pace`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pacing {
+    /// How many frames to emulate now.
+    pub frames: u32,
+    /// How long to wait afterwards.
+    pub sleep: Duration,
+}
+
+/// Decides how many frames to emulate for a host timestamp, at a target speed.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    speed: Option<f64>, // A multiple of real time, or None to run uncapped
+    paused: bool,
+    anchor: Option<Duration>, // When frame 0 of the current run of frames was due
+    frames: u64,              // Frames handed out since the anchor
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        FramePacer::new()
+    }
+}
+
+impl FramePacer {
+    /// A pacer running at real time.
+    pub fn new() -> Self {
+        FramePacer { speed: Some(1.0), paused: false, anchor: None, frames: 0 }
+    }
+
+    /// Sets the speed as a multiple of real time, like 4.0 for turbo or 0.5 for slow motion.
+    /// None runs as fast as the host allows.
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        self.speed = speed.filter(|&speed| speed > 0.0);
+        self.anchor = None;
+    }
+
+    /// The speed as a multiple of real time, or None when uncapped.
+    pub fn speed(&self) -> Option<f64> {
+        self.speed
+    }
+
+    /// While paused no frames are emulated, but `pace` keeps asking to be called back every
+    /// frame time so the frontend can stay responsive and present the last frame.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.anchor = None;
+    }
+
+    /// Whether the pacer is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Says how many frames are due at host time `now` and how long to sleep before calling
+    /// again.  The times only need to be measured from a fixed starting point.
+    pub fn pace(&mut self, now: Duration) -> Pacing {
+        if self.paused {
+            return Pacing { frames: 0, sleep: FRAME_TIME };
+        }
+        let period = match self.speed {
+            Some(speed) => FRAME_TIME.as_nanos() as f64 / speed,
+            None => return Pacing { frames: 1, sleep: Duration::ZERO },
+        };
+
+        let mut anchor = match self.anchor {
+            Some(anchor) if now >= anchor => anchor,
+            _ => self.restart(now),
+        };
+        let due = |anchor: Duration, frame: u64| {
+            anchor + Duration::from_nanos((frame as f64 * period).round() as u64)
+        };
+        if now > due(anchor, self.frames) + MAX_LAG {
+            anchor = self.restart(now);
+        }
+
+        // Count every frame due by now, going by `due` itself so a wakeup at exactly the
+        // promised time always finds its frame
+        let mut last_due = ((now - anchor).as_nanos() as f64 / period) as u64;
+        while due(anchor, last_due + 1) <= now {
+            last_due += 1;
+        }
+        while last_due > 0 && due(anchor, last_due) > now {
+            last_due -= 1;
+        }
+        let frames = (last_due + 1).saturating_sub(self.frames);
+        self.frames += frames;
+        Pacing { frames: frames as u32, sleep: due(anchor, self.frames) - now }
+    }
+
+    fn restart(&mut self, now: Duration) -> Duration {
+        self.anchor = Some(now);
+        self.frames = 0;
+        now
+    }
+}
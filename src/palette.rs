@@ -0,0 +1,112 @@
+//! DMG display palettes
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// A color as red, green and blue bytes.
+pub type Rgb = [u8; 3];
+
+/// The colors the four DMG shades are shown as, lightest first.  Only the RGBA conversion
+/// uses it, the index buffers and frame hashes don't depend on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DisplayPalette {
+    /// Shades 0-3.
+    pub colors: [Rgb; 4],
+}
+
+impl DisplayPalette {
+    /// Plain grays, the default.
+    pub const GRAYSCALE: DisplayPalette = DisplayPalette {
+        colors: [[0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55], [0x00, 0x00, 0x00]],
+    };
+
+    /// The pea soup screen of the original model.
+    pub const DMG_GREEN: DisplayPalette = DisplayPalette {
+        colors: [[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]],
+    };
+
+    /// The gray-green screen of the Game Boy Pocket.
+    pub const POCKET: DisplayPalette = DisplayPalette {
+        colors: [[0xC4, 0xCF, 0xA1], [0x8B, 0x95, 0x6D], [0x4D, 0x53, 0x3C], [0x1F, 0x1F, 0x1F]],
+    };
+
+    /// Looks up a built-in palette by its command line name.
+    pub fn named(name: &str) -> Option<DisplayPalette> {
+        match name {
+            "grayscale" | "greyscale" => Some(DisplayPalette::GRAYSCALE),
+            "dmg-green" => Some(DisplayPalette::DMG_GREEN),
+            "pocket" => Some(DisplayPalette::POCKET),
+            _ => None,
+        }
+    }
+
+    /// The color of a shade.  Only the low 2 bits are used.
+    pub fn color(&self, shade: u8) -> Rgb {
+        self.colors[(shade & 0x3) as usize]
+    }
+
+    /// Converts a buffer of shades (0-3) into RGBA, 4 bytes per pixel.
+    pub fn shades_to_rgba(&self, shades: &[u8]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(shades.len() * 4);
+        for &shade in shades {
+            let [r, g, b] = self.color(shade);
+            rgba.extend_from_slice(&[r, g, b, 0xFF]);
+        }
+        rgba
+    }
+}
+
+impl Default for DisplayPalette {
+    fn default() -> Self {
+        DisplayPalette::GRAYSCALE
+    }
+}
+
+/// A palette that's neither a known name nor four hex colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePaletteError(String);
+
+impl fmt::Display for ParsePaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid palette: {}", self.0)
+    }
+}
+
+impl Error for ParsePaletteError {}
+
+// Either a preset name or four "#rrggbb" colors separated by commas, lightest first.
+impl FromStr for DisplayPalette {
+    type Err = ParsePaletteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = DisplayPalette::named(s) {
+            return Ok(palette);
+        }
+        if !s.contains(',') && !s.starts_with('#') {
+            return Err(ParsePaletteError(format!(
+                "unknown preset \"{}\", expected grayscale, dmg-green, pocket or four hex colors", s)));
+        }
+
+        let parts: Vec<&str> = s.split(',').map(|part| part.trim()).collect();
+        if parts.len() != 4 {
+            return Err(ParsePaletteError(format!("expected 4 colors, got {}", parts.len())));
+        }
+
+        let mut colors = [[0; 3]; 4];
+        for (color, part) in colors.iter_mut().zip(parts) {
+            *color = parse_hex_color(part)?;
+        }
+        Ok(DisplayPalette { colors })
+    }
+}
+
+fn parse_hex_color(s: &str) -> Result<Rgb, ParsePaletteError> {
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ParsePaletteError(format!("\"{}\" is not a #rrggbb color", s)));
+    }
+    let value = u32::from_str_radix(hex, 16).unwrap();
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
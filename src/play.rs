@@ -0,0 +1,147 @@
+//! The play command's input and pacing
+//!
+//! None of this touches a window, so it's tested on its own.  The frontend reads the held keys
+//! and the window focus each time round its loop, gets the joypad state and how many frames to
+//! emulate from here, and sleeps for as long as it's told.
+
+use std::time::Duration;
+
+use farore::joypad::{Button, InputState};
+use farore::pacing::{FramePacer, Pacing};
+
+use config::{Action, Config, Key};
+
+
+// Holding the turbo key runs at this multiple of real time
+const TURBO_SPEED: f64 = 4.0;
+
+// The actions that are joypad buttons, the rest are for the frontend
+const BUTTONS: [(Action, Button); 8] = [
+    (Action::Up, Button::Up),
+    (Action::Down, Button::Down),
+    (Action::Left, Button::Left),
+    (Action::Right, Button::Right),
+    (Action::A, Button::A),
+    (Action::B, Button::B),
+    (Action::Start, Button::Start),
+    (Action::Select, Button::Select),
+];
+
+/// The key bound to each action, taken from the config.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl KeyMap {
+    pub fn new(config: &Config) -> Self {
+        KeyMap { bindings: Action::ALL.iter().map(|&action| (config.key(action), action)).collect() }
+    }
+
+    /// Whether any held key triggers `action`.
+    pub fn is_active(&self, action: Action, held: &[Key]) -> bool {
+        self.bindings.iter().any(|&(key, bound)| bound == action && held.contains(&key))
+    }
+
+    /// The joypad state the held keys make.
+    #[allow(dead_code)] // For the windowed frontend, which doesn't exist yet
+    pub fn joypad(&self, held: &[Key]) -> InputState {
+        let mut state = InputState::new();
+        for &(action, button) in &BUTTONS {
+            state.set(button, self.is_active(action, held));
+        }
+        state
+    }
+}
+
+/// The frame pacer, driven by the pause and turbo keys and the window focus.
+#[derive(Debug, Clone)]
+pub struct PlayPacer {
+    pacer: FramePacer,
+    pause_on_focus_loss: bool,
+    paused: bool,     // With the pause key
+    pause_held: bool, // So holding the key only toggles once
+}
+
+impl PlayPacer {
+    pub fn new(pause_on_focus_loss: bool) -> Self {
+        PlayPacer { pacer: FramePacer::new(), pause_on_focus_loss, paused: false, pause_held: false }
+    }
+
+    /// Says how many frames to emulate at host time `now` and how long to sleep afterwards.
+    #[allow(dead_code)] // For the windowed frontend, which doesn't exist yet
+    pub fn pace(&mut self, now: Duration, keys: &KeyMap, held: &[Key], focused: bool) -> Pacing {
+        let pause = keys.is_active(Action::Pause, held);
+        if pause && !self.pause_held {
+            self.paused = !self.paused;
+        }
+        self.pause_held = pause;
+
+        let paused = self.paused || (self.pause_on_focus_loss && !focused);
+        if paused != self.pacer.is_paused() {
+            self.pacer.set_paused(paused);
+        }
+        let speed = if keys.is_active(Action::Turbo, held) { TURBO_SPEED } else { 1.0 };
+        if self.pacer.speed() != Some(speed) {
+            self.pacer.set_speed(Some(speed));
+        }
+        self.pacer.pace(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use farore::pacing::FRAME_TIME;
+
+    use super::*;
+
+
+    #[test]
+    fn default_keys_press_their_buttons() {
+        let keys = KeyMap::new(&Config::default());
+        let state = keys.joypad(&[Key::Up, Key::Char('X'), Key::Enter, Key::Tab]);
+        let pressed: Vec<Button> = BUTTONS.iter().map(|&(_, button)| button).filter(|&button| state.is_pressed(button)).collect();
+        assert_eq!(pressed, [Button::Up, Button::A, Button::Start]);
+        assert_eq!(keys.joypad(&[]), InputState::new());
+    }
+
+    #[test]
+    fn rebound_keys_follow_the_config() {
+        let mut config = Config::default();
+        config.bind(Action::A, Key::Char('K'));
+        let keys = KeyMap::new(&config);
+        assert!(keys.joypad(&[Key::Char('K')]).is_pressed(Button::A));
+        assert!(!keys.joypad(&[Key::Char('X')]).is_pressed(Button::A));
+    }
+
+    #[test]
+    fn the_pause_key_toggles_once_per_press() {
+        let keys = KeyMap::new(&Config::default());
+        let mut pacer = PlayPacer::new(false);
+        let p = [Key::Char('P')];
+        assert_eq!(pacer.pace(Duration::ZERO, &keys, &[], true).frames, 1);
+        assert_eq!(pacer.pace(FRAME_TIME, &keys, &p, true).frames, 0);
+        assert_eq!(pacer.pace(FRAME_TIME * 2, &keys, &p, true).frames, 0);
+        assert_eq!(pacer.pace(FRAME_TIME * 3, &keys, &[], true).frames, 0);
+        assert_eq!(pacer.pace(FRAME_TIME * 4, &keys, &p, true).frames, 1);
+    }
+
+    #[test]
+    fn focus_loss_only_pauses_when_asked() {
+        let keys = KeyMap::new(&Config::default());
+        for &(pause_on_focus_loss, frames) in &[(true, 0), (false, 1)] {
+            let mut pacer = PlayPacer::new(pause_on_focus_loss);
+            assert_eq!(pacer.pace(Duration::ZERO, &keys, &[], false).frames, frames);
+        }
+    }
+
+    #[test]
+    fn turbo_runs_four_times_as_fast() {
+        let keys = KeyMap::new(&Config::default());
+        let mut pacer = PlayPacer::new(false);
+        let tab = [Key::Tab];
+        pacer.pace(Duration::ZERO, &keys, &tab, true);
+        assert_eq!(pacer.pace(FRAME_TIME, &keys, &tab, true).frames, 4);
+        assert_eq!(pacer.pace(FRAME_TIME * 2, &keys, &[], true).frames, 1);
+    }
+}
@@ -0,0 +1,402 @@
+//! Debug views of PPU memory
+
+use std::fmt;
+use std::path::Path;
+
+use error::FaroreError;
+use io::Peek;
+use palette::DisplayPalette;
+use render::write_png_rgba;
+use super::{rgb555_to_rgb, PaletteRam, Ppu, Sprite, MAX_SPRITES_PER_LINE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+
+const TILES_PER_BANK: usize = 384;
+const SHEET_COLUMNS: usize = 16;
+
+// Size of one color cell in the palette image, and the gap between the BG and OBJ sections
+const SWATCH_SIZE: usize = 16;
+const SWATCH_GAP: usize = 8;
+
+/// An image of every tile in a VRAM bank, 16 tiles wide.  Pixels are raw 2 bit color indices.
+pub struct TileSheet {
+    /// Always 128.
+    pub width: usize,
+    /// 192, for 24 rows of tiles.
+    pub height: usize,
+    /// One color index per pixel, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl TileSheet {
+    /// Converts to RGBA with a fixed grayscale palette, color 0 as white.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        DisplayPalette::GRAYSCALE.shades_to_rgba(&self.pixels)
+    }
+
+    /// Saves the sheet as a grayscale PNG.
+    pub fn write_png(&self, path: &Path) -> Result<(), FaroreError> {
+        write_png_rgba(path, self.width as u32, self.height as u32, &self.to_rgba())
+    }
+}
+
+/// Which 32x32 tile map to dump.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileMapSelect {
+    /// The map at 9800-9BFF.
+    Map9800,
+    /// The map at 9C00-9FFF.
+    Map9C00,
+    /// Whichever map LCDC bit 3 selects.
+    Background,
+    /// Whichever map LCDC bit 6 selects.
+    Window,
+}
+
+/// A rectangle in tile map coordinates.  It may extend past 256 and wrap around.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MapRect {
+    /// Left edge.
+    pub x: usize,
+    /// Top edge.
+    pub y: usize,
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+}
+
+/// A full 256x256 tile map rendered through the current palettes, along with where the
+/// screen and the window currently sit on it.
+pub struct TileMapImage {
+    /// Always 256.
+    pub width: usize,
+    /// Always 256.
+    pub height: usize,
+    /// 4 bytes per pixel, row by row.
+    pub rgba: Vec<u8>,
+    /// The SCX/SCY screen area.
+    pub viewport: MapRect,
+    /// The part of the screen covered by the window, if shown.
+    pub window: Option<MapRect>,
+}
+
+impl TileMapImage {
+    /// Outlines the viewport (red) and window (blue) rectangles on the image.
+    pub fn draw_overlays(&mut self) {
+        let viewport = self.viewport;
+        self.outline(viewport, [0xFF, 0x00, 0x00, 0xFF]);
+        if let Some(window) = self.window {
+            self.outline(window, [0x00, 0x00, 0xFF, 0xFF]);
+        }
+    }
+
+    fn outline(&mut self, rect: MapRect, color: [u8; 4]) {
+        let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        for x in rect.x..=right {
+            self.plot(x, rect.y, color);
+            self.plot(x, bottom, color);
+        }
+        for y in rect.y..=bottom {
+            self.plot(rect.x, y, color);
+            self.plot(right, y, color);
+        }
+    }
+
+    fn plot(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let i = ((y % self.height) * self.width + x % self.width) * 4;
+        self.rgba[i..i + 4].copy_from_slice(&color);
+    }
+
+    /// Saves the image as a PNG.
+    pub fn write_png(&self, path: &Path) -> Result<(), FaroreError> {
+        write_png_rgba(path, self.width as u32, self.height as u32, &self.rgba)
+    }
+}
+
+/// One palette color.  `raw` is the RGB555 value on CGB and the shade (0-3) on DMG.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Swatch {
+    /// RGB555 on CGB, a shade on DMG.
+    pub raw: u16,
+    /// The color it's shown as.
+    pub rgb: [u8; 3],
+}
+
+/// Every palette the PPU can currently draw with, 4 colors each.  On CGB that is the 8 BG and
+/// 8 OBJ palettes from palette RAM.  On DMG it is BGP, and OBP0 and OBP1 shown through the
+/// display palette.
+pub struct PaletteSwatches {
+    /// Whether these are CGB palettes.
+    pub cgb: bool,
+    /// BGP, OBP0, OBP1.
+    pub dmg_registers: [u8; 3],
+    /// The background palettes.
+    pub bg: Vec<[Swatch; 4]>,
+    /// The sprite palettes.
+    pub obj: Vec<[Swatch; 4]>,
+}
+
+impl PaletteSwatches {
+    /// Lays the palettes out as a grid, one row per palette and one cell per color, with the
+    /// BG palettes on the left and the OBJ palettes on the right.
+    pub fn to_rgba(&self) -> (usize, usize, Vec<u8>) {
+        let section = 4 * SWATCH_SIZE;
+        let width = section * 2 + SWATCH_GAP;
+        let height = self.bg.len().max(self.obj.len()) * SWATCH_SIZE;
+        let mut rgba = vec![0; width * height * 4];
+
+        for (left, palettes) in [(0, &self.bg), (section + SWATCH_GAP, &self.obj)].iter() {
+            for (row, palette) in palettes.iter().enumerate() {
+                for (col, swatch) in palette.iter().enumerate() {
+                    let [r, g, b] = swatch.rgb;
+                    for y in row * SWATCH_SIZE..(row + 1) * SWATCH_SIZE {
+                        for x in 0..SWATCH_SIZE {
+                            let i = (y * width + left + col * SWATCH_SIZE + x) * 4;
+                            rgba[i..i + 4].copy_from_slice(&[r, g, b, 0xFF]);
+                        }
+                    }
+                }
+            }
+        }
+
+        (width, height, rgba)
+    }
+
+    /// Saves the grid from `to_rgba` as a PNG.
+    pub fn write_png(&self, path: &Path) -> Result<(), FaroreError> {
+        let (width, height, rgba) = self.to_rgba();
+        write_png_rgba(path, width as u32, height as u32, &rgba)
+    }
+}
+
+// CGB palettes as RGB555 hex, DMG palettes as the register value and the shade each color
+// index maps to.
+impl fmt::Display for PaletteSwatches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.cgb {
+            for (name, palettes) in &[("BG", &self.bg), ("OBJ", &self.obj)] {
+                for (i, palette) in palettes.iter().enumerate() {
+                    write!(f, "{:<5}", format!("{}{}", name, i))?;
+                    for swatch in palette {
+                        write!(f, " {:04X}", swatch.raw)?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        } else {
+            let names = ["BGP", "OBP0", "OBP1"];
+            let palettes = self.bg.iter().chain(self.obj.iter());
+            for ((name, value), palette) in names.iter().zip(&self.dmg_registers).zip(palettes) {
+                write!(f, "{:<5} {:02X} ", name, value)?;
+                for swatch in palette {
+                    write!(f, " {}", swatch.raw)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a sprite takes part in a given line, as the OAM scan sees it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OamVisibility {
+    /// Doesn't cover the line.
+    Hidden,
+    /// Among the first 10 covering the line.
+    Selected,
+    /// Covers the line but lost out to the 10 sprite limit.
+    Dropped,
+}
+
+/// An OAM entry decoded for display.
+#[derive(Debug, Copy, Clone)]
+pub struct OamEntryInfo {
+    /// The raw entry.
+    pub sprite: Sprite,
+    /// Screen position of the left edge, negative when partly off screen.
+    pub screen_x: i16,
+    /// Screen position of the top edge, negative when partly off screen.
+    pub screen_y: i16,
+    /// With bit 0 masked off for 8x16 sprites.
+    pub tile: u8,
+    /// How the OAM scan treats it on the summary's line.
+    pub visibility: OamVisibility,
+}
+
+/// All 40 OAM entries, annotated with their visibility on one line.
+pub struct OamSummary {
+    /// The line the visibility is for.
+    pub line: u8,
+    /// Every entry, in OAM order.
+    pub entries: Vec<OamEntryInfo>,
+}
+
+impl fmt::Display for OamSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, " #   Y   X  scr Y scr X  tile  flags  bank  pal  line {}", self.line)?;
+        for entry in &self.entries {
+            let sprite = &entry.sprite;
+            let flags = format!("{}{}{}{}",
+                if sprite.behind_background() { 'P' } else { '-' },
+                if sprite.y_flip() { 'Y' } else { '-' },
+                if sprite.x_flip() { 'X' } else { '-' },
+                if sprite.uses_obp1() { '1' } else { '0' });
+            let visibility = match entry.visibility {
+                OamVisibility::Hidden => "",
+                OamVisibility::Selected => "visible",
+                OamVisibility::Dropped => "dropped",
+            };
+            let row = format!("{:2}  {:02X}  {:02X}  {:5} {:5}    {:02X}   {}     {}    {}  {}",
+                sprite.index, sprite.y, sprite.x, entry.screen_y, entry.screen_x, entry.tile,
+                flags, sprite.vram_bank(), sprite.cgb_palette(), visibility);
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl Ppu {
+    /// Decodes all 40 OAM entries, read through `bus` the way the debugger reads memory, and
+    /// marks which ones the OAM scan would select for the current line and which ones the 10
+    /// sprite limit would drop.  The PPU serves as its own bus when there's no machine around
+    /// it.
+    pub fn oam_summary(&self, bus: &dyn Peek) -> OamSummary {
+        let mut oam = [0; 0xA0];
+        for (address, byte) in (0xFE00..).zip(oam.iter_mut()) {
+            *byte = bus.peek(address);
+        }
+        let line = self.ly();
+        let tall = self.sprite_height() == 16;
+        let mut covering = 0;
+        let entries = (0..40).map(|index| {
+            let sprite = Sprite::from_oam(&oam, index);
+            let visibility = if !self.sprite_on_line(&sprite, line) {
+                OamVisibility::Hidden
+            } else {
+                covering += 1;
+                if covering <= MAX_SPRITES_PER_LINE { OamVisibility::Selected } else { OamVisibility::Dropped }
+            };
+            OamEntryInfo {
+                sprite,
+                screen_x: sprite.x as i16 - 8,
+                screen_y: sprite.y as i16 - 16,
+                tile: if tall { sprite.tile & 0xFE } else { sprite.tile },
+                visibility,
+            }
+        }).collect();
+
+        OamSummary { line, entries }
+    }
+
+    /// Decodes all 384 tiles of a VRAM bank (0x8000-0x97FF) into a tile sheet.  VRAM is read
+    /// directly, so this works regardless of what the PPU is doing.
+    pub fn dump_tiles(&self, bank: u8) -> TileSheet {
+        let width = SHEET_COLUMNS * 8;
+        let height = TILES_PER_BANK / SHEET_COLUMNS * 8;
+        let mut pixels = vec![0; width * height];
+
+        let bank = if self.model.is_cgb() { bank & 0x1 } else { 0 };
+        for tile in 0..TILES_PER_BANK {
+            let left = (tile % SHEET_COLUMNS) * 8;
+            let top = (tile / SHEET_COLUMNS) * 8;
+            for row in 0..8 {
+                for col in 0..8 {
+                    let color = self.tile_pixel(bank, tile * 16, col as u8, row as u8);
+                    pixels[(top + row) * width + left + col] = color;
+                }
+            }
+        }
+
+        TileSheet { width, height, pixels }
+    }
+
+    /// Renders a whole background tile map using the current tile data addressing mode and
+    /// palettes.  On CGB the attribute map is applied as well.
+    pub fn dump_tilemap(&self, which: TileMapSelect) -> TileMapImage {
+        let map_base = match which {
+            TileMapSelect::Map9800 => 0x1800,
+            TileMapSelect::Map9C00 => 0x1C00,
+            TileMapSelect::Background => if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 },
+            TileMapSelect::Window => if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 },
+        };
+
+        let (width, height) = (256, 256);
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.map_pixel(map_base, x as u8, y as u8);
+                let [r, g, b] = if self.model.is_cgb() {
+                    rgb555_to_rgb(self.bg_palettes.color(pixel.palette, pixel.color))
+                } else {
+                    self.display_palette.color(self.bg_shade(pixel.color))
+                };
+                rgba.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+
+        let viewport = MapRect {
+            x: self.scx as usize,
+            y: self.scy as usize,
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+        };
+
+        // The window covers the screen from (WX-7, WY) to the bottom right corner
+        let window_left = (self.wx as usize).saturating_sub(7);
+        let window_shown = self.lcdc & 0x20 != 0
+            && window_left < SCREEN_WIDTH
+            && (self.wy as usize) < SCREEN_HEIGHT;
+        let window = if window_shown {
+            Some(MapRect {
+                x: viewport.x + window_left,
+                y: viewport.y + self.wy as usize,
+                width: SCREEN_WIDTH - window_left,
+                height: SCREEN_HEIGHT - self.wy as usize,
+            })
+        } else {
+            None
+        };
+
+        TileMapImage { width, height, rgba, viewport, window }
+    }
+
+    /// Decodes the current palettes.  Palette RAM is read directly, so this doesn't disturb
+    /// the BCPS/OCPS index.
+    pub fn dump_palettes(&self) -> PaletteSwatches {
+        let dmg_registers = [self.bgp, self.obp0, self.obp1];
+        if self.model.is_cgb() {
+            let decode = |ram: &PaletteRam| -> Vec<[Swatch; 4]> {
+                (0..8).map(|palette| {
+                    let mut colors = [Swatch::default(); 4];
+                    for (color, swatch) in colors.iter_mut().enumerate() {
+                        let raw = ram.color(palette, color as u8);
+                        *swatch = Swatch { raw, rgb: rgb555_to_rgb(raw) };
+                    }
+                    colors
+                }).collect()
+            };
+            PaletteSwatches {
+                cgb: true,
+                dmg_registers,
+                bg: decode(&self.bg_palettes),
+                obj: decode(&self.obj_palettes),
+            }
+        } else {
+            let decode = |register: u8| -> [Swatch; 4] {
+                let mut colors = [Swatch::default(); 4];
+                for (color, swatch) in colors.iter_mut().enumerate() {
+                    let shade = (register >> (color * 2)) & 0x3;
+                    *swatch = Swatch { raw: shade as u16, rgb: self.display_palette.color(shade) };
+                }
+                colors
+            };
+            PaletteSwatches {
+                cgb: false,
+                dmg_registers,
+                bg: vec![decode(self.bgp)],
+                obj: vec![decode(self.obp0), decode(self.obp1)],
+            }
+        }
+    }
+}
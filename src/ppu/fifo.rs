@@ -0,0 +1,300 @@
+//! Pixel FIFO renderer
+//!
+//! Instead of drawing a whole line at once, the background fetcher and the pixel FIFOs are
+//! stepped one dot at a time during mode 3, the way the hardware does it.  Register writes
+//! made mid-scanline (SCX, palettes, LCDC) affect the pixels pushed after them, and mode 3
+//! takes longer when sprites have to be fetched or pixels discarded.
+
+use std::collections::VecDeque;
+
+use super::{BgPixel, ObjPixel, Ppu, MAX_SPRITES_PER_LINE, SCREEN_WIDTH};
+
+
+// The first tile fetch of every line is thrown away, delaying the first real fetch.
+const STARTUP_DOTS: u8 = 6;
+
+// Fetching a sprite stalls the background fetcher and pixel output.
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FetchStep {
+    Tile,     // 2 dots, read the tile number from the map
+    DataLow,  // 2 dots, read the low bitplane
+    DataHigh, // 2 dots, read the high bitplane
+    Push,     // Waits until the background FIFO is empty, then pushes 8 pixels
+}
+
+#[derive(Debug, Copy, Clone)]
+struct FifoObject {
+    pixel: ObjPixel,
+    index: u8, // OAM index, for CGB priority when merging
+}
+
+pub struct FifoState {
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<Option<FifoObject>>,
+
+    step: FetchStep,
+    step_dots: u8,
+    tile_x: u8, // Fetcher's tile column, relative to the start of the line or window
+    tile: u8,
+    attributes: u8,
+
+    lx: u8,      // Pixels output so far on this line
+    discard: u8, // Pixels still to be dropped from the front of the background FIFO
+    startup: u8,
+    in_window: bool,
+    window_drawn: bool,
+
+    sprite_fetched: [bool; MAX_SPRITES_PER_LINE],
+    sprite_stall: u8,
+    pending_sprite: Option<usize>,
+}
+
+impl FifoState {
+    pub fn new() -> Self {
+        FifoState {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            step: FetchStep::Tile,
+            step_dots: 0,
+            tile_x: 0,
+            tile: 0,
+            attributes: 0,
+            lx: 0,
+            discard: 0,
+            startup: 0,
+            in_window: false,
+            window_drawn: false,
+            sprite_fetched: [false; MAX_SPRITES_PER_LINE],
+            sprite_stall: 0,
+            pending_sprite: None,
+        }
+    }
+
+    pub fn line_done(&self) -> bool {
+        self.lx as usize >= SCREEN_WIDTH
+    }
+}
+
+impl Ppu {
+    pub(super) fn start_fifo_line(&mut self) {
+        let fifo = &mut self.fifo;
+        fifo.bg.clear();
+        fifo.obj.clear();
+        fifo.step = FetchStep::Tile;
+        fifo.step_dots = 0;
+        fifo.tile_x = 0;
+        fifo.lx = 0;
+        fifo.startup = STARTUP_DOTS;
+        fifo.in_window = false;
+        fifo.window_drawn = false;
+        fifo.sprite_fetched = [false; MAX_SPRITES_PER_LINE];
+        fifo.sprite_stall = 0;
+        fifo.pending_sprite = None;
+
+        // Fine scroll, latched at the start of the line
+        fifo.discard = self.scx % 8;
+    }
+
+    pub(super) fn end_fifo_line(&mut self) {
+        if self.fifo.window_drawn {
+            self.window_line += 1;
+        }
+    }
+
+    /// Runs the fetcher and pushes at most one pixel to the LCD.
+    pub(super) fn fifo_dot(&mut self) {
+        if self.fifo.line_done() {
+            return;
+        }
+
+        if self.fifo.startup > 0 {
+            self.fifo.startup -= 1;
+            return;
+        }
+
+        if self.fifo.sprite_stall > 0 {
+            self.fifo.sprite_stall -= 1;
+            if self.fifo.sprite_stall == 0 {
+                if let Some(slot) = self.fifo.pending_sprite.take() {
+                    self.merge_sprite(slot);
+                }
+            }
+            return;
+        }
+
+        self.check_window_start();
+
+        // Pixel output halts while a sprite is due.  The background fetcher keeps going until
+        // it has pixels in the FIFO, then the sprite fetch takes over.
+        if let Some(slot) = self.due_sprite() {
+            if self.fifo.bg.is_empty() {
+                self.fetcher_dot();
+            } else {
+                self.fifo.sprite_fetched[slot] = true;
+                self.fifo.sprite_stall = SPRITE_FETCH_DOTS;
+                self.fifo.pending_sprite = Some(slot);
+            }
+            return;
+        }
+
+        // A pixel pushed this dot leaves the FIFO on the next one
+        self.shift_pixel();
+        self.fetcher_dot();
+    }
+
+    // Sprites are fetched once the output reaches their left edge.  When several are due at
+    // once (only possible at the left edge of the screen), the lowest X goes first.
+    fn due_sprite(&self) -> Option<usize> {
+        if self.lcdc & 0x02 == 0 {
+            return None;
+        }
+
+        let lx = self.fifo.lx as u16;
+        (0..self.line_sprite_count)
+            .filter(|&slot| !self.fifo.sprite_fetched[slot])
+            .filter(|&slot| {
+                let x = self.line_sprites[slot].x as u16;
+                x <= lx + 8 && x < SCREEN_WIDTH as u16 + 8
+            })
+            .min_by_key(|&slot| (self.line_sprites[slot].x, slot))
+    }
+
+    fn merge_sprite(&mut self, slot: usize) {
+        let sprite = self.line_sprites[slot];
+        let height = self.sprite_height();
+        let cgb = self.model.is_cgb();
+        let x_priority = self.x_priority();
+
+        // Columns already passed (sprites hanging off the left edge) are skipped
+        let skip = (self.fifo.lx as u16 + 8).saturating_sub(sprite.x as u16) as u8;
+        while self.fifo.obj.len() < 8 {
+            self.fifo.obj.push_back(None);
+        }
+
+        for col in skip..8 {
+            let color = self.sprite_pixel(&sprite, height, col);
+            if color == 0 {
+                continue;
+            }
+            let incoming = FifoObject {
+                pixel: ObjPixel {
+                    color,
+                    palette: if cgb { sprite.cgb_palette() } else { sprite.uses_obp1() as u8 },
+                    behind_background: sprite.behind_background(),
+                },
+                index: sprite.index,
+            };
+
+            // On DMG whoever got into the FIFO first wins, which is the lower X.  CGB
+            // replaces pixels from later OAM entries, unless OPRI selects the DMG rule.
+            let entry = &mut self.fifo.obj[(col - skip) as usize];
+            let replace = match *entry {
+                None => true,
+                Some(existing) => !x_priority && incoming.index < existing.index,
+            };
+            if replace {
+                *entry = Some(incoming);
+            }
+        }
+    }
+
+    fn check_window_start(&mut self) {
+        if self.fifo.in_window
+            || self.lcdc & 0x20 == 0
+            || !self.window_triggered
+            || self.wx >= 167
+            || (self.fifo.lx as u16 + 7) < self.wx as u16 {
+            return;
+        }
+
+        // The background fetcher restarts on the window map.  A WX below 7 starts the window
+        // partially off screen, which is handled by discarding its first pixels.
+        self.fifo.in_window = true;
+        self.fifo.window_drawn = true;
+        self.fifo.bg.clear();
+        self.fifo.step = FetchStep::Tile;
+        self.fifo.step_dots = 0;
+        self.fifo.tile_x = 0;
+        self.fifo.discard = if self.fifo.lx == 0 { 7u8.saturating_sub(self.wx) } else { 0 };
+    }
+
+    fn fetcher_dot(&mut self) {
+        self.fifo.step_dots += 1;
+        match self.fifo.step {
+            FetchStep::Tile if self.fifo.step_dots == 2 => {
+                let (map_base, x, y) = self.fetch_coordinates();
+                let map_offset = map_base + (y as usize / 8) * 32 + x as usize;
+                self.fifo.tile = self.vram[map_offset];
+                self.fifo.attributes = if self.model.is_cgb() { self.vram[0x2000 + map_offset] } else { 0 };
+                self.fifo.step = FetchStep::DataLow;
+                self.fifo.step_dots = 0;
+            },
+            FetchStep::DataLow if self.fifo.step_dots == 2 => {
+                self.fifo.step = FetchStep::DataHigh;
+                self.fifo.step_dots = 0;
+            },
+            FetchStep::DataHigh if self.fifo.step_dots == 2 => {
+                self.fifo.step = FetchStep::Push;
+                self.fifo.step_dots = 0;
+                self.try_push();
+            },
+            FetchStep::Push => self.try_push(),
+            _ => {},
+        }
+    }
+
+    // The tile map, column and pixel row the fetcher reads from.
+    fn fetch_coordinates(&self) -> (usize, u8, u8) {
+        if self.fifo.in_window {
+            let map_base = if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+            (map_base, self.fifo.tile_x & 0x1F, self.window_line)
+        } else {
+            let map_base = if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+            let x = (self.scx / 8).wrapping_add(self.fifo.tile_x) & 0x1F;
+            (map_base, x, self.ly.wrapping_add(self.scy))
+        }
+    }
+
+    fn try_push(&mut self) {
+        if !self.fifo.bg.is_empty() {
+            return;
+        }
+
+        let (_, _, y) = self.fetch_coordinates();
+        let attributes = self.fifo.attributes;
+        let row = if attributes & 0x40 != 0 { 7 - y % 8 } else { y % 8 };
+        let bank = (attributes >> 3) & 0x1;
+        let tile_address = self.bg_tile_address(self.fifo.tile);
+        for col in 0..8 {
+            let col = if attributes & 0x20 != 0 { 7 - col } else { col };
+            self.fifo.bg.push_back(BgPixel {
+                color: self.tile_pixel(bank, tile_address, col, row),
+                palette: attributes & 0x7,
+                priority: attributes & 0x80 != 0,
+            });
+        }
+
+        self.fifo.tile_x = self.fifo.tile_x.wrapping_add(1);
+        self.fifo.step = FetchStep::Tile;
+        self.fifo.step_dots = 0;
+    }
+
+    fn shift_pixel(&mut self) {
+        let bg = match self.fifo.bg.pop_front() {
+            Some(pixel) => pixel,
+            None => return,
+        };
+
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+
+        let object = self.fifo.obj.pop_front().and_then(|o| o).map(|o| o.pixel);
+        let x = self.fifo.lx as usize;
+        self.output_pixel(x, bg, object);
+        self.fifo.lx += 1;
+    }
+}
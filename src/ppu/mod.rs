@@ -0,0 +1,1114 @@
+//! Pixel processing unit
+
+use interrupt::{Interrupt, InterruptLine};
+use crc;
+use error::FaroreError;
+use io::{IoPeripheral, Peek};
+use model::HardwareModel;
+use palette::DisplayPalette;
+
+pub mod dump;
+mod fifo;
+
+use self::fifo::FifoState;
+
+
+/// The LCD width in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// The LCD height in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// How long a scanline lasts, in dots.
+pub const DOTS_PER_LINE: u32 = 456;
+/// Lines 0-143 are drawn, the rest are VBlank.
+pub const VISIBLE_LINES: u8 = 144;
+/// Lines per frame, VBlank included.
+pub const LINES_PER_FRAME: u8 = 154;
+
+/// How long a frame lasts, in dots.
+pub const DOTS_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32;
+
+const OAM_SCAN_DOTS: u32 = 80;
+
+// When LY changes, the LY=LYC comparator sees nothing for one M-cycle before the new value.
+const LY_COMPARE_DELAY: u32 = 4;
+
+// On line 153 LY only reads 153 for the first M-cycle, then 0 for the rest of the line.
+const LINE_153_LY_DOTS: u32 = 4;
+const DRAWING_DOTS: u32 = 172;
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// The PPU mode, as reported in the lower two bits of STAT.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Mode 0.
+    HBlank,
+    /// Mode 1.
+    VBlank,
+    /// Mode 2, searching OAM for the line's sprites.
+    OamScan,
+    /// Mode 3, sending pixels to the LCD.
+    Drawing,
+}
+
+impl Mode {
+    /// The mode number as STAT shows it.
+    pub fn bits(self) -> u8 {
+        match self {
+            Mode::HBlank => 0,
+            Mode::VBlank => 1,
+            Mode::OamScan => 2,
+            Mode::Drawing => 3,
+        }
+    }
+}
+
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+/// A decoded entry from the sprite attribute table.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Sprite {
+    /// Position within OAM, 0-39.
+    pub index: u8,
+    /// Screen Y + 16.
+    pub y: u8,
+    /// Screen X + 8.
+    pub x: u8,
+    /// The tile number.
+    pub tile: u8,
+    /// Bit 7 BG priority, bit 6 Y flip, bit 5 X flip, bit 4 DMG palette.
+    pub flags: u8,
+}
+
+impl Sprite {
+    fn from_oam(oam: &[u8], index: usize) -> Self {
+        let entry = &oam[index * 4..index * 4 + 4];
+        Sprite {
+            index: index as u8,
+            y: entry[0],
+            x: entry[1],
+            tile: entry[2],
+            flags: entry[3],
+        }
+    }
+
+    /// Whether BG colors 1-3 are drawn over the sprite.
+    pub fn behind_background(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+
+    /// Whether the sprite is flipped vertically.
+    pub fn y_flip(&self) -> bool {
+        self.flags & 0x40 != 0
+    }
+
+    /// Whether the sprite is flipped horizontally.
+    pub fn x_flip(&self) -> bool {
+        self.flags & 0x20 != 0
+    }
+
+    /// Whether the sprite uses OBP1 rather than OBP0, on DMG.
+    pub fn uses_obp1(&self) -> bool {
+        self.flags & 0x10 != 0
+    }
+
+    /// The CGB palette number, bit 2-0.
+    pub fn cgb_palette(&self) -> u8 {
+        self.flags & 0x7
+    }
+
+    /// The VRAM bank the tile comes from on CGB, bit 3.
+    pub fn vram_bank(&self) -> u8 {
+        (self.flags >> 3) & 0x1
+    }
+}
+
+// A background or window pixel before palette lookup
+#[derive(Debug, Copy, Clone, Default)]
+struct BgPixel {
+    color: u8,
+    palette: u8,    // CGB palette number, always 0 on DMG
+    priority: bool, // CGB attribute bit 7, BG wins over sprites
+}
+
+// The winning sprite pixel at a screen column
+#[derive(Debug, Copy, Clone)]
+struct ObjPixel {
+    color: u8,
+    palette: u8, // 0 = OBP0, 1 = OBP1 on DMG, palette number on CGB
+    behind_background: bool,
+}
+
+/// CGB color palette RAM, 8 palettes of 4 RGB555 colors, accessed through an index register
+/// (BCPS/OCPS) and a data register (BCPD/OCPD).
+pub struct PaletteRam {
+    data: [u8; 64],
+    index: u8,
+    auto_increment: bool,
+}
+
+impl PaletteRam {
+    fn new() -> Self {
+        PaletteRam {
+            data: [0; 64],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    fn read_index(&self) -> u8 {
+        let auto = if self.auto_increment { 0x80 } else { 0x00 };
+        auto | 0x40 | self.index
+    }
+
+    fn write_index(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 != 0;
+    }
+
+    fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    fn write_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    /// The RGB555 value of a color, red in the low bits.
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let i = (palette as usize & 0x7) * 8 + (color as usize & 0x3) * 2;
+        (self.data[i] as u16 | (self.data[i + 1] as u16) << 8) & 0x7FFF
+    }
+}
+
+/// Which renderer draws the scanlines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Renderer {
+    /// Draws each line in one go when mode 3 ends.  Fast, fine for most games.
+    Scanline,
+    /// Steps the fetcher and pixel FIFOs every dot, for mid-scanline effects.
+    Fifo,
+}
+
+/// Emulation settings that don't change what the game sees, other than timing accuracy.
+#[derive(Debug, Copy, Clone)]
+pub struct PpuConfig {
+    /// How scanlines are drawn.
+    pub renderer: Renderer,
+}
+
+impl Default for PpuConfig {
+    fn default() -> Self {
+        PpuConfig { renderer: Renderer::Scanline }
+    }
+}
+
+/// A completed frame, handed to the frame callback at the start of VBlank.
+pub struct Frame<'a> {
+    /// Frames delivered so far, starting at 1.
+    pub number: u64,
+    /// Which kind of buffer holds the colors.
+    pub model: HardwareModel,
+    /// Set for the white frames delivered while the LCD is off.
+    pub blank: bool,
+    /// What DMG shades are shown as.
+    pub palette: DisplayPalette,
+    shades: &'a [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    rgb: &'a [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+    indices: &'a [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+}
+
+impl<'a> Frame<'a> {
+    /// Color indices before palette lookup, see `Ppu::color_indices`.
+    pub fn indices(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.indices
+    }
+
+    /// DMG shades (0-3).  Not meaningful on CGB.
+    pub fn shades(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.shades
+    }
+
+    /// CGB RGB555 colors.  Not meaningful on DMG.
+    pub fn rgb555(&self) -> &[u16; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.rgb
+    }
+
+    /// 64 bit FNV-1a over the color index buffer, row by row.  Palettes don't enter into it,
+    /// so the hash stays the same however the frame ends up colored.
+    pub fn hash(&self) -> u64 {
+        self.indices.iter().fold(FNV_OFFSET_BASIS, |hash, &index| {
+            (hash ^ index as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    /// CRC-32 over the same index buffer, for comparing against other tools.
+    pub fn crc32(&self) -> u32 {
+        crc::crc32(self.indices)
+    }
+
+    /// The frame in RGBA, 4 bytes per pixel, whichever the model.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        if self.model.is_cgb() {
+            rgb555_to_rgba(self.rgb)
+        } else {
+            self.palette.shades_to_rgba(self.shades)
+        }
+    }
+
+    /// Writes the frame in RGBA into `out`, for a caller that keeps one buffer rather than
+    /// taking a new one each frame.  Fails with `InvalidArgument` unless `out` holds exactly
+    /// `SCREEN_WIDTH * SCREEN_HEIGHT * 4` bytes.
+    pub fn write_rgba(&self, out: &mut [u8]) -> Result<(), FaroreError> {
+        if out.len() != SCREEN_WIDTH * SCREEN_HEIGHT * 4 {
+            return Err(FaroreError::InvalidArgument(format!(
+                "the buffer is {} bytes, a frame is {}", out.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4)));
+        }
+        for (i, pixel) in out.chunks_exact_mut(4).enumerate() {
+            let [r, g, b] = if self.model.is_cgb() { rgb555_to_rgb(self.rgb[i]) } else { self.palette.color(self.shades[i]) };
+            pixel.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+        Ok(())
+    }
+}
+
+/// Called with each completed frame.
+pub type FrameCallback = Box<dyn FnMut(&Frame)>;
+
+/// Called with the current LY when the LCD is switched off outside VBlank.
+pub type LcdOffWarning = Box<dyn FnMut(u8)>;
+
+/// LCD Registers
+///   FF40        LCDC - LCD Control
+///   FF41        STAT - LCD Status
+///   FF42-FF43   SCY, SCX - Background scroll
+///   FF44        LY - Current scanline (read only)
+///   FF45        LYC - Scanline compare
+///   FF47-FF49   BGP, OBP0, OBP1 - DMG palettes
+///   FF4A-FF4B   WY, WX - Window position
+///   FF4F        VBK - VRAM bank (CGB)
+///   FF68-FF6B   BCPS, BCPD, OCPS, OCPD - Color palettes (CGB)
+///   FF6C        OPRI - Object priority mode (CGB)
+pub struct Ppu {
+    model: HardwareModel,
+    config: PpuConfig,
+
+    lcdc: u8,
+    stat: u8, // Only the interrupt enable bits (3-6) are stored, the rest is live state.
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+
+    // 8000-9FFF, tile data and the two background tile maps.  CGB has a second bank holding
+    // more tile data and the background attribute maps.
+    vram: [u8; 0x4000],
+    vram_bank: u8,
+
+    bg_palettes: PaletteRam,
+    obj_palettes: PaletteRam,
+
+    // Bit 0 set selects DMG style sprite priority by X coordinate, clear the CGB's OAM order.
+    // The boot ROM sets it for DMG games, after which the register is locked.
+    opri: u8,
+    opri_locked: bool,
+
+    // FE00-FE9F, sprite attribute table
+    oam: [u8; 0xA0],
+
+    mode: Mode,
+    dot: u32, // Position within the current line, 0-455
+
+    // SCX as latched when mode 3 started.  The scanline renderer draws the whole line with
+    // it, so writes during mode 3 only show up on the next line.
+    line_scx: u8,
+
+    // Length of mode 3 on the current line.  HBlank gets whatever is left of the 456 dots.
+    drawing_dots: u32,
+
+    // The enabled STAT conditions are ORed into a single line, and the interrupt is only
+    // requested on its rising edge.  A condition that holds the line high therefore blocks
+    // the others from firing ("STAT blocking").
+    lyc_match: bool,
+    stat_line: bool,
+    stat_write_quirk: bool,
+
+    // The window keeps its own line counter, which only advances on lines where the window
+    // was actually drawn.  It is only shown at all once LY has matched WY during the frame.
+    window_line: u8,
+    window_triggered: bool,
+
+    // Sprites selected by the OAM scan for the current line, in OAM order.
+    line_sprites: [Sprite; MAX_SPRITES_PER_LINE],
+    line_sprite_count: usize,
+
+    fifo: FifoState,
+
+    // Palette-resolved shades (0-3), one byte per pixel.  DMG only.
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // Palette-resolved RGB555 colors.  CGB only.
+    rgb_framebuffer: [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // The color index each pixel had before palette lookup.  Bits 0-1 hold the index.
+    // On DMG bits 2-3 hold the palette it goes through: 0 = BGP, 1 = OBP0, 2 = OBP1.
+    // On CGB bits 2-4 hold the palette number and bit 5 is set for object palettes.
+    color_indices: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    display_palette: DisplayPalette,
+
+    frame_count: u64,
+    frame_callback: Option<FrameCallback>,
+
+    // While the LCD is off no frames are produced.  If requested, a white frame is delivered
+    // every 70224 dots instead so frontends keep presenting.
+    blank_frames_when_off: bool,
+    off_dots: u32,
+
+    // The first line after switching the LCD on skips the OAM scan and reports mode 0 instead.
+    first_line: bool,
+
+    // Switching the LCD off outside VBlank can damage a real DMG's screen.
+    lcd_off_warning: Option<LcdOffWarning>,
+}
+
+impl Ppu {
+    /// A PPU with the LCD off and the default settings.
+    pub fn new(model: HardwareModel) -> Self {
+        Ppu::with_config(model, PpuConfig::default())
+    }
+
+    /// A PPU with the LCD off.
+    pub fn with_config(model: HardwareModel, config: PpuConfig) -> Self {
+        Ppu {
+            model,
+            config,
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            vram: [0; 0x4000],
+            vram_bank: 0,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            opri: 0,
+            opri_locked: false,
+            oam: [0; 0xA0],
+            mode: Mode::OamScan,
+            dot: 0,
+            line_scx: 0,
+            drawing_dots: DRAWING_DOTS,
+            lyc_match: true,
+            stat_line: false,
+            stat_write_quirk: false,
+            window_line: 0,
+            window_triggered: false,
+            line_sprites: [Sprite::default(); MAX_SPRITES_PER_LINE],
+            line_sprite_count: 0,
+            fifo: FifoState::new(),
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            rgb_framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            color_indices: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            display_palette: DisplayPalette::default(),
+            frame_count: 0,
+            frame_callback: None,
+            blank_frames_when_off: false,
+            off_dots: 0,
+            first_line: false,
+            lcd_off_warning: None,
+        }
+    }
+
+    /// The current mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The line being drawn, as LY reads.
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    /// Sets up the state the boot ROM leaves behind, for starting without one.  On CGB, carts
+    /// without CGB support (bit 7 of the header's CGB flag clear) get DMG sprite priority.
+    pub fn skip_boot_rom(&mut self, cart_cgb_flag: u8) {
+        if self.model.is_cgb() && cart_cgb_flag & 0x80 == 0 {
+            self.opri = 0x01;
+        }
+        self.lock_opri();
+    }
+
+    /// Locks OPRI, which happens when the boot ROM is unmapped.
+    pub fn lock_opri(&mut self) {
+        self.opri_locked = true;
+    }
+
+    // Whether overlapping sprites are prioritized by X coordinate (DMG) rather than OAM index.
+    fn x_priority(&self) -> bool {
+        !self.model.is_cgb() || self.opri & 0x01 != 0
+    }
+
+    /// The window's internal line counter: how many lines of the window have been drawn this
+    /// frame.  Lines where the window is disabled or pushed off screen don't count.
+    pub fn window_line(&self) -> u8 {
+        self.window_line
+    }
+
+    /// How long mode 3 lasts on the current line.  With the FIFO renderer this is only known
+    /// once the line has been drawn, and holds the previous line's length until then.
+    pub fn drawing_dots(&self) -> u32 {
+        self.drawing_dots
+    }
+
+    /// The last frame's DMG shades (0-3).
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.framebuffer
+    }
+
+    /// Frames completed so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The last completed frame.
+    pub fn frame(&self) -> Frame<'_> {
+        Frame {
+            number: self.frame_count,
+            model: self.model,
+            blank: self.lcdc & 0x80 == 0,
+            palette: self.display_palette,
+            shades: &self.framebuffer,
+            rgb: &self.rgb_framebuffer,
+            indices: &self.color_indices,
+        }
+    }
+
+    /// Sets the colors DMG shades are converted to for RGBA output.
+    pub fn set_display_palette(&mut self, palette: DisplayPalette) {
+        self.display_palette = palette;
+    }
+
+    /// The colors DMG shades are converted to.
+    pub fn display_palette(&self) -> DisplayPalette {
+        self.display_palette
+    }
+
+    /// Registers a callback invoked once per frame at the start of VBlank.
+    pub fn set_frame_callback(&mut self, callback: FrameCallback) {
+        self.frame_callback = Some(callback);
+    }
+
+    /// Opts in to white frames being delivered at the normal rate while the LCD is off.
+    pub fn set_blank_frames_when_off(&mut self, enabled: bool) {
+        self.blank_frames_when_off = enabled;
+    }
+
+    /// Registers a callback invoked when a game switches the LCD off outside VBlank.
+    pub fn set_lcd_off_warning(&mut self, callback: LcdOffWarning) {
+        self.lcd_off_warning = Some(callback);
+    }
+
+    /// The last frame's CGB colors in RGB555.
+    pub fn rgb_framebuffer(&self) -> &[u16; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.rgb_framebuffer
+    }
+
+    /// The CGB background palettes.
+    pub fn bg_palettes(&self) -> &PaletteRam {
+        &self.bg_palettes
+    }
+
+    /// The CGB sprite palettes.
+    pub fn obj_palettes(&self) -> &PaletteRam {
+        &self.obj_palettes
+    }
+
+    /// The unresolved color indices for the last frame, for frontends applying their own
+    /// palettes.  See the `color_indices` field for the encoding.
+    pub fn color_indices(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.color_indices
+    }
+
+    /// Resolves a DMG index buffer through a per-palette shade mapping, one byte of 4 2-bit
+    /// shades per palette in BGP, OBP0, OBP1 order, the same layout as the registers.
+    pub fn resolve_indices(indices: &[u8], palettes: [u8; 3], out: &mut [u8]) {
+        for (shade, &index) in out.iter_mut().zip(indices.iter()) {
+            let palette = palettes[(index >> 2) as usize % 3];
+            *shade = (palette >> ((index & 0x3) * 2)) & 0x3;
+        }
+    }
+
+    /// Reads VRAM (8000-9FFF) in the bank VBK selects.  Other addresses read 0xFF.
+    pub fn read_vram(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0x9FFF => self.vram[self.vram_bank as usize * 0x2000 + (address as usize - 0x8000)],
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes VRAM (8000-9FFF) in the bank VBK selects.  Other addresses are ignored.
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0x9FFF = address {
+            self.vram[self.vram_bank as usize * 0x2000 + (address as usize - 0x8000)] = value;
+        }
+    }
+
+    /// Reads OAM (FE00-FE9F).  Other addresses read 0xFF.
+    pub fn read_oam(&self, address: u16) -> u8 {
+        match address {
+            0xFE00..=0xFE9F => self.oam[address as usize - 0xFE00],
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes OAM (FE00-FE9F).  Other addresses are ignored.
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        if let 0xFE00..=0xFE9F = address {
+            self.oam[address as usize - 0xFE00] = value;
+        }
+    }
+
+    /// Advances the PPU by a number of dots (4 dots per CPU M-cycle at normal speed).
+    pub fn tick(&mut self, dots: u32, irq: &mut InterruptLine) {
+        if self.lcdc & 0x80 == 0 {
+            self.tick_lcd_off(dots);
+            return;
+        }
+        for _ in 0..dots {
+            self.step(irq);
+        }
+    }
+
+    fn tick_lcd_off(&mut self, dots: u32) {
+        self.stat_write_quirk = false;
+        if !self.blank_frames_when_off {
+            return;
+        }
+        self.off_dots += dots;
+        while self.off_dots >= DOTS_PER_FRAME {
+            self.off_dots -= DOTS_PER_FRAME;
+            self.clear_screen();
+            self.deliver_frame();
+        }
+    }
+
+    fn clear_screen(&mut self) {
+        for px in self.framebuffer.iter_mut() {
+            *px = 0;
+        }
+        for px in self.rgb_framebuffer.iter_mut() {
+            *px = 0x7FFF;
+        }
+        for px in self.color_indices.iter_mut() {
+            *px = 0;
+        }
+    }
+
+    fn write_lcdc(&mut self, value: u8) {
+        let was_on = self.lcdc & 0x80 != 0;
+        let on = value & 0x80 != 0;
+        self.lcdc = value;
+
+        if was_on && !on {
+            if self.mode != Mode::VBlank {
+                if let Some(callback) = self.lcd_off_warning.as_mut() {
+                    callback(self.ly);
+                }
+            }
+
+            // The PPU stops dead: LY reads 0, STAT reports mode 0 and nothing raises
+            // interrupts until it is switched back on.
+            self.ly = 0;
+            self.dot = 0;
+            self.mode = Mode::HBlank;
+            self.stat_line = false;
+            self.window_line = 0;
+            self.window_triggered = false;
+            self.off_dots = 0;
+            self.clear_screen();
+        } else if !was_on && on {
+            // Restarts at the top of the frame.  The mode stays 0 until drawing starts.
+            self.ly = 0;
+            self.dot = 0;
+            self.mode = Mode::HBlank;
+            self.first_line = true;
+            self.lyc_match = self.ly == self.lyc;
+        }
+    }
+
+    fn deliver_frame(&mut self) {
+        self.frame_count += 1;
+        if let Some(mut callback) = self.frame_callback.take() {
+            callback(&self.frame());
+            self.frame_callback = Some(callback);
+        }
+    }
+
+    fn step(&mut self, irq: &mut InterruptLine) {
+        if self.mode == Mode::Drawing && self.config.renderer == Renderer::Fifo {
+            self.fifo_dot();
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.first_line = false;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            if self.ly == 0 {
+                self.window_line = 0;
+                self.window_triggered = false;
+            }
+        }
+
+        let mode = if self.ly >= VISIBLE_LINES {
+            Mode::VBlank
+        } else if self.dot < OAM_SCAN_DOTS {
+            if self.first_line { Mode::HBlank } else { Mode::OamScan }
+        } else if self.dot == OAM_SCAN_DOTS || (self.mode == Mode::Drawing && !self.drawing_done()) {
+            Mode::Drawing
+        } else {
+            Mode::HBlank
+        };
+
+        if mode != self.mode {
+            self.enter_mode(mode, irq);
+        }
+
+        self.lyc_match = self.compared_ly() == Some(self.lyc);
+        self.update_stat_line(irq);
+    }
+
+    // The value the LY register reads as.
+    fn read_ly(&self) -> u8 {
+        if self.ly == LINES_PER_FRAME - 1 && self.dot >= LINE_153_LY_DOTS {
+            0
+        } else {
+            self.ly
+        }
+    }
+
+    // The value LYC is compared against at the current dot, if any.  Line 153 gets two
+    // chances: 153 right after the line starts and 0 once LY has dropped early.
+    //   dot    line 1-152    line 153
+    //   0-3    -             -
+    //   4-7    LY            153
+    //   8-11   LY            -
+    //   12-    LY            0
+    fn compared_ly(&self) -> Option<u8> {
+        if self.ly == 0 || self.first_line {
+            return Some(self.ly);
+        }
+        if self.ly == LINES_PER_FRAME - 1 {
+            return match self.dot {
+                0..=3 => None,
+                4..=7 => Some(self.ly),
+                8..=11 => None,
+                _ => Some(0),
+            };
+        }
+        if self.dot < LY_COMPARE_DELAY { None } else { Some(self.ly) }
+    }
+
+    fn drawing_done(&self) -> bool {
+        match self.config.renderer {
+            Renderer::Scanline => self.dot >= OAM_SCAN_DOTS + self.drawing_dots,
+            Renderer::Fifo => self.fifo.line_done(),
+        }
+    }
+
+    fn update_stat_line(&mut self, irq: &mut InterruptLine) {
+        // On DMG, writing STAT briefly acts as if every source were enabled
+        let enables = if self.stat_write_quirk {
+            self.stat_write_quirk = false;
+            0x78
+        } else {
+            self.stat
+        };
+
+        let line = (enables & 0x08 != 0 && self.mode == Mode::HBlank)
+            || (enables & 0x10 != 0 && self.mode == Mode::VBlank)
+            || (enables & 0x20 != 0 && self.mode == Mode::OamScan)
+            || (enables & 0x40 != 0 && self.lyc_match);
+
+        if line && !self.stat_line {
+            irq.request(Interrupt::LcdStat);
+        }
+        self.stat_line = line;
+    }
+
+    fn enter_mode(&mut self, mode: Mode, irq: &mut InterruptLine) {
+        self.mode = mode;
+        match mode {
+            // The scan results are only needed once drawing starts
+            Mode::Drawing => {
+                if self.ly == self.wy {
+                    self.window_triggered = true;
+                }
+                self.scan_oam();
+                self.line_scx = self.scx;
+                match self.config.renderer {
+                    Renderer::Scanline => self.drawing_dots = DRAWING_DOTS + self.drawing_penalty(),
+                    Renderer::Fifo => self.start_fifo_line(),
+                }
+            },
+            Mode::HBlank => match self.config.renderer {
+                Renderer::Scanline => self.render_line(),
+                Renderer::Fifo => {
+                    self.drawing_dots = self.dot - OAM_SCAN_DOTS;
+                    self.end_fifo_line();
+                },
+            },
+            Mode::VBlank => {
+                irq.request(Interrupt::VBlank);
+                self.deliver_frame();
+            },
+            _ => {},
+        }
+    }
+
+    fn render_line(&mut self) {
+        let cgb = self.model.is_cgb();
+        let mut bg = [BgPixel::default(); SCREEN_WIDTH];
+
+        // On DMG, LCDC bit 0 clear blanks both the background and the window to color 0.  On
+        // CGB they are always drawn and the bit only takes away their priority over sprites.
+        let bg_drawn = cgb || self.lcdc & 0x01 != 0;
+        if bg_drawn {
+            self.render_background(&mut bg);
+            self.render_window(&mut bg);
+        }
+
+        let mut objects: [Option<ObjPixel>; SCREEN_WIDTH] = [None; SCREEN_WIDTH];
+        if self.lcdc & 0x02 != 0 {
+            self.render_sprites(&mut objects);
+        }
+
+        // Palettes are read as each line is drawn, so mid-frame writes only affect the lines
+        // that follow.
+        for x in 0..SCREEN_WIDTH {
+            self.output_pixel(x, bg[x], objects[x]);
+        }
+    }
+
+    // Mixes a background and sprite pixel and writes the result to the framebuffers.
+    fn output_pixel(&mut self, x: usize, b: BgPixel, object: Option<ObjPixel>) {
+        let cgb = self.model.is_cgb();
+        let bg_drawn = cgb || self.lcdc & 0x01 != 0;
+        let b = if bg_drawn { b } else { BgPixel::default() };
+
+        let object = object.filter(|o| {
+            let bg_master_priority = !cgb || self.lcdc & 0x01 != 0;
+            !(bg_master_priority && (o.behind_background || b.priority) && b.color != 0)
+        });
+
+        let i = self.ly as usize * SCREEN_WIDTH + x;
+        match (object, cgb) {
+            (Some(o), false) => {
+                let palette = if o.palette == 1 { self.obp1 } else { self.obp0 };
+                self.framebuffer[i] = (palette >> (o.color * 2)) & 0x3;
+                self.color_indices[i] = o.color | ((o.palette + 1) << 2);
+            },
+            (None, false) => {
+                self.framebuffer[i] = if bg_drawn { self.bg_shade(b.color) } else { 0 };
+                self.color_indices[i] = b.color;
+            },
+            (Some(o), true) => {
+                self.rgb_framebuffer[i] = self.obj_palettes.color(o.palette, o.color);
+                self.color_indices[i] = 0x20 | o.color | (o.palette << 2);
+            },
+            (None, true) => {
+                self.rgb_framebuffer[i] = self.bg_palettes.color(b.palette, b.color);
+                self.color_indices[i] = b.color | (b.palette << 2);
+            },
+        }
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & 0x04 != 0 { 16 } else { 8 }
+    }
+
+    // Selects the first 10 sprites in OAM order whose Y range covers the current line.  The
+    // X position is not considered, so off-screen sprites still use up a slot.
+    fn scan_oam(&mut self) {
+        self.line_sprite_count = 0;
+        for index in 0..40 {
+            let sprite = Sprite::from_oam(&self.oam, index);
+            if self.sprite_on_line(&sprite, self.ly) {
+                self.line_sprites[self.line_sprite_count] = sprite;
+                self.line_sprite_count += 1;
+                if self.line_sprite_count == MAX_SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Extra mode 3 dots on top of the base 172, following the usual model of the fetcher:
+    //   - SCX%8 pixels are fetched and discarded at the start of the line
+    //   - starting the window restarts the fetcher, 6 dots
+    //   - every sprite fetch takes 6 dots, plus up to 5 more waiting for the background
+    //     fetch of the tile it starts in.  Only the first sprite in a tile waits.
+    fn drawing_penalty(&self) -> u32 {
+        let scroll = (self.line_scx % 8) as u32;
+        let mut penalty = scroll;
+
+        if self.lcdc & 0x20 != 0 && self.window_triggered && self.wx < 167 {
+            penalty += 6;
+        }
+
+        if self.lcdc & 0x02 != 0 {
+            let mut xs: Vec<u32> = self.line_sprites[..self.line_sprite_count].iter()
+                .map(|sprite| sprite.x as u32)
+                .filter(|&x| x < SCREEN_WIDTH as u32 + 8)
+                .collect();
+            xs.sort_unstable();
+
+            let mut last_tile = None;
+            for x in xs {
+                penalty += 6;
+                let tile = (x + scroll) / 8;
+                if last_tile != Some(tile) {
+                    let offset = (x + scroll) % 8;
+                    penalty += 5 - offset.min(5);
+                    last_tile = Some(tile);
+                }
+            }
+        }
+
+        penalty
+    }
+
+    fn sprite_on_line(&self, sprite: &Sprite, ly: u8) -> bool {
+        let line = ly as u16 + 16;
+        let y = sprite.y as u16;
+        line >= y && line < y + self.sprite_height() as u16
+    }
+
+    // On DMG the sprite with the lowest X wins overlapping pixels, with the earlier OAM entry
+    // breaking ties.  On CGB the OAM order alone decides, unless OPRI selects the DMG rule.
+    // Only the winning sprite's BG priority flag is considered.
+    fn render_sprites(&self, objects: &mut [Option<ObjPixel>; SCREEN_WIDTH]) {
+        let cgb = self.model.is_cgb();
+        let mut sprites = self.line_sprites[..self.line_sprite_count].to_vec();
+        if self.x_priority() {
+            sprites.sort_by_key(|s| (s.x, s.index));
+        }
+
+        let height = self.sprite_height();
+        for (x, object) in objects.iter_mut().enumerate() {
+            let screen_x = x as i16 + 8;
+            for sprite in &sprites {
+                let col = screen_x - sprite.x as i16;
+                if !(0..8).contains(&col) {
+                    continue;
+                }
+
+                // Color 0 is transparent no matter what the palette maps it to, so lower
+                // priority sprites show through.
+                let color = self.sprite_pixel(sprite, height, col as u8);
+                if color == 0 {
+                    continue;
+                }
+
+                *object = Some(ObjPixel {
+                    color,
+                    palette: if cgb { sprite.cgb_palette() } else { sprite.uses_obp1() as u8 },
+                    behind_background: sprite.behind_background(),
+                });
+                break;
+            }
+        }
+    }
+
+    fn sprite_pixel(&self, sprite: &Sprite, height: u8, col: u8) -> u8 {
+        let mut row = (self.ly + 16).wrapping_sub(sprite.y);
+        if sprite.y_flip() {
+            row = height - 1 - row;
+        }
+        let col = if sprite.x_flip() { 7 - col } else { col };
+
+        // In 8x16 mode the low bit of the tile index is ignored
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+        let bank = if self.model.is_cgb() { sprite.vram_bank() } else { 0 };
+        self.tile_pixel(bank, tile as usize * 16, col, row)
+    }
+
+    // SCX%8 shifts the sampling within the first tile, the fine scroll the FIFO renderer gets
+    // by discarding pixels.
+    fn render_background(&self, bg: &mut [BgPixel; SCREEN_WIDTH]) {
+        let map_base = if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+        let y = self.ly.wrapping_add(self.scy);
+        for (x, pixel) in bg.iter_mut().enumerate() {
+            let bx = (x as u8).wrapping_add(self.line_scx);
+            *pixel = self.map_pixel(map_base, bx, y);
+        }
+    }
+
+    // The window is drawn from screen column WX-7 to the right edge.  A WX below 7 clips the
+    // left part of the window, WX=166 leaves a single column and 167 or more hides it for the
+    // line.  Hidden lines don't advance the window's line counter, so a window switched off
+    // for part of the frame picks up where it left off.  Once triggered, later WY writes have
+    // no effect until the next frame.
+    fn render_window(&mut self, bg: &mut [BgPixel; SCREEN_WIDTH]) {
+        if self.lcdc & 0x20 == 0 || !self.window_triggered || self.wx >= 167 {
+            return;
+        }
+
+        let map_base = if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+        let y = self.window_line;
+        let left = self.wx as isize - 7;
+        for (x, pixel) in bg.iter_mut().enumerate() {
+            let wx = x as isize - left;
+            if wx < 0 {
+                continue;
+            }
+            *pixel = self.map_pixel(map_base, wx as u8, y);
+        }
+        self.window_line += 1;
+    }
+
+    // Looks up a pixel of a 256x256 tile map.  On CGB, the attribute byte stored at the same
+    // map offset in VRAM bank 1 supplies the palette, tile bank, flips and BG priority.
+    fn map_pixel(&self, map_base: usize, x: u8, y: u8) -> BgPixel {
+        let map_offset = map_base + (y as usize / 8) * 32 + x as usize / 8;
+        let tile_index = self.vram[map_offset];
+        let attributes = if self.model.is_cgb() { self.vram[0x2000 + map_offset] } else { 0 };
+
+        let col = if attributes & 0x20 != 0 { 7 - x % 8 } else { x % 8 };
+        let row = if attributes & 0x40 != 0 { 7 - y % 8 } else { y % 8 };
+        let bank = (attributes >> 3) & 0x1;
+        BgPixel {
+            color: self.tile_pixel(bank, self.bg_tile_address(tile_index), col, row),
+            palette: attributes & 0x7,
+            priority: attributes & 0x80 != 0,
+        }
+    }
+
+    // LCDC bit 4 selects between unsigned indexing from 0x8000 and signed indexing around
+    // 0x9000 for background and window tiles.
+    fn bg_tile_address(&self, tile_index: u8) -> usize {
+        if self.lcdc & 0x10 != 0 {
+            tile_index as usize * 16
+        } else {
+            (0x1000 + (tile_index as i8 as isize) * 16) as usize
+        }
+    }
+
+    // Reads the 2 bit color index of a pixel within a tile.
+    fn tile_pixel(&self, bank: u8, tile_address: usize, x: u8, y: u8) -> u8 {
+        let row_addr = bank as usize * 0x2000 + tile_address + y as usize * 2;
+        let lo = self.vram[row_addr];
+        let hi = self.vram[row_addr + 1];
+        let bit = 7 - x;
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    fn bg_shade(&self, color: u8) -> u8 {
+        (self.bgp >> (color * 2)) & 0x3
+    }
+
+    fn read_stat(&self) -> u8 {
+        let coincidence = if self.lyc_match { 0x04 } else { 0x00 };
+        0x80 | (self.stat & 0x78) | coincidence | self.mode.bits()
+    }
+}
+
+/// Expands a 5 bit RGB555 color into 8 bit RGB.
+pub fn rgb555_to_rgb(color: u16) -> [u8; 3] {
+    let expand = |c: u16| -> u8 { ((c & 0x1F) << 3 | (c & 0x1F) >> 2) as u8 };
+    [expand(color), expand(color >> 5), expand(color >> 10)]
+}
+
+/// Converts a buffer of RGB555 colors into RGBA, 4 bytes per pixel.
+pub fn rgb555_to_rgba(colors: &[u16]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(colors.len() * 4);
+    for &color in colors {
+        let [r, g, b] = rgb555_to_rgb(color);
+        rgba.extend_from_slice(&[r, g, b, 0xFF]);
+    }
+    rgba
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new(HardwareModel::Dmg)
+    }
+}
+
+impl IoPeripheral for Ppu {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF40 => self.lcdc,
+            0xFF41 => self.read_stat(),
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.read_ly(),
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            0xFF4F if self.model.is_cgb() => 0xFE | self.vram_bank,
+            0xFF68 if self.model.is_cgb() => self.bg_palettes.read_index(),
+            0xFF69 if self.model.is_cgb() => self.bg_palettes.read_data(),
+            0xFF6A if self.model.is_cgb() => self.obj_palettes.read_index(),
+            0xFF6B if self.model.is_cgb() => self.obj_palettes.read_data(),
+            0xFF6C if self.model.is_cgb() => 0xFE | self.opri,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF40 => self.write_lcdc(value),
+            0xFF41 => {
+                self.stat = value & 0x78;
+                self.stat_write_quirk = !self.model.is_cgb();
+            },
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {}, // LY is read only
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            0xFF4F if self.model.is_cgb() => self.vram_bank = value & 0x1,
+            0xFF68 if self.model.is_cgb() => self.bg_palettes.write_index(value),
+            0xFF69 if self.model.is_cgb() => self.bg_palettes.write_data(value),
+            0xFF6A if self.model.is_cgb() => self.obj_palettes.write_index(value),
+            0xFF6B if self.model.is_cgb() => self.obj_palettes.write_data(value),
+            0xFF6C if self.model.is_cgb() && !self.opri_locked => self.opri = value & 0x01,
+            _ => {},
+        }
+    }
+}
+
+// VRAM in the bank VBK selects, and OAM.
+impl Peek for Ppu {
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0xFE00..=0xFE9F => self.read_oam(address),
+            _ => self.read_vram(address),
+        }
+    }
+}
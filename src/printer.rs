@@ -0,0 +1,339 @@
+//! Game Boy Printer
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use error::FaroreError;
+use palette::DisplayPalette;
+use render::write_png_rgba;
+use serial::SerialEndpoint;
+
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const DEVICE_ID: u8 = 0x81;
+
+const PRINT_WIDTH: usize = 160;
+const TILE_COLUMNS: usize = PRINT_WIDTH / 8;
+// The printer's buffer holds 9 DATA packets of 2 tile rows each
+const BUFFER_SIZE: usize = 9 * 2 * TILE_COLUMNS * 16;
+
+// STATUS polls that report busy after a PRINT, before the printout is done
+const PRINTING_POLLS: u8 = 4;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+/// Called with each finished printout.
+pub type PrintCallback = Box<dyn FnMut(&Printout)>;
+
+/// A printed image, 160 pixels wide.  Pixels are shades 0-3 after the PRINT palette, 0 as white.
+pub struct Printout {
+    /// Always 160.
+    pub width: usize,
+    /// A multiple of 8.
+    pub height: usize,
+    /// One shade per pixel, row by row.
+    pub pixels: Vec<u8>,
+    /// Blank paper fed before the image, in units the printer defines.
+    pub margin_before: u8, // Blank paper feeds before and after, in units the printer defines
+    /// Blank paper fed after the image.
+    pub margin_after: u8,
+}
+
+impl Printout {
+    /// The image in RGBA, 4 bytes per pixel.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        DisplayPalette::GRAYSCALE.shades_to_rgba(&self.pixels)
+    }
+
+    /// Saves the image as a PNG.
+    pub fn write_png(&self, path: &Path) -> Result<(), FaroreError> {
+        write_png_rgba(path, self.width as u32, self.height as u32, &self.to_rgba())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    DeviceId, // The printer answers 0x81 to this byte
+    Status,   // and the status to this one
+}
+
+/// Emulates the printer on the other end of the link cable.  Games send it packets:
+///   88 33         Magic
+///   cc            Command: 1 INIT, 2 PRINT, 4 DATA, F STATUS
+///   pp            1 if the payload is RLE compressed
+///   ll ll         Payload length, little endian
+///   ...           Payload
+///   ss ss         Checksum, the 16 bit sum of the command through the payload
+///   00 00         Answered with 81 and the status byte
+///
+/// DATA packets add 640 byte strips (two rows of 20 tiles) to the image, and PRINT hands it to
+/// the callback.  Clones share the same printer, so one can be connected to the serial port
+/// while another sets the callback.
+#[derive(Clone)]
+pub struct GbPrinter {
+    state: Rc<RefCell<PrinterState>>,
+}
+
+struct PrinterState {
+    packet: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    payload: Vec<u8>,
+    checksum: u16,
+    sum: u16,
+
+    image: Vec<u8>, // Tile data accumulated from DATA packets
+    status: u8,
+    printing_polls: u8,
+    callback: Option<PrintCallback>,
+
+    received: u8, // Bits being shifted in, and the count
+    bits: u8,
+    response: u8, // The byte being shifted out
+}
+
+impl GbPrinter {
+    /// A printer with nothing printed yet.
+    pub fn new() -> Self {
+        let state = PrinterState {
+            packet: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            payload: Vec::new(),
+            checksum: 0,
+            sum: 0,
+            image: Vec::new(),
+            status: 0,
+            printing_polls: 0,
+            callback: None,
+            received: 0,
+            bits: 0,
+            response: 0,
+        };
+        GbPrinter { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Sets the callback for finished printouts.
+    pub fn set_callback(&self, callback: PrintCallback) {
+        self.state.borrow_mut().callback = Some(callback);
+    }
+
+    /// The status byte the printer would answer with.
+    pub fn status(&self) -> u8 {
+        self.state.borrow().status
+    }
+
+    /// Feeds one byte to the printer as if it came over the cable, returning its answer.
+    pub fn exchange_byte(&self, byte: u8) -> u8 {
+        let mut state = self.state.borrow_mut();
+        let response = state.response;
+        state.receive(byte);
+        response
+    }
+}
+
+impl Default for GbPrinter {
+    fn default() -> Self {
+        GbPrinter::new()
+    }
+}
+
+impl PrinterState {
+    fn receive(&mut self, byte: u8) {
+        self.response = 0;
+        self.packet = match self.packet {
+            PacketState::Magic(index) => {
+                if byte != MAGIC[index] {
+                    PacketState::Magic(0)
+                } else if index + 1 < MAGIC.len() {
+                    PacketState::Magic(index + 1)
+                } else {
+                    self.sum = 0;
+                    PacketState::Command
+                }
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.add_to_sum(byte);
+                PacketState::Compression
+            },
+            PacketState::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.add_to_sum(byte);
+                PacketState::LengthLow
+            },
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.add_to_sum(byte);
+                PacketState::LengthHigh
+            },
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.add_to_sum(byte);
+                self.payload.clear();
+                if self.length == 0 { PacketState::ChecksumLow } else { PacketState::Data }
+            },
+            PacketState::Data => {
+                self.payload.push(byte);
+                self.add_to_sum(byte);
+                if self.payload.len() < self.length as usize { PacketState::Data } else { PacketState::ChecksumLow }
+            },
+            PacketState::ChecksumLow => {
+                self.checksum = byte as u16;
+                PacketState::ChecksumHigh
+            },
+            PacketState::ChecksumHigh => {
+                self.checksum |= (byte as u16) << 8;
+                self.execute();
+                self.response = DEVICE_ID;
+                PacketState::DeviceId
+            },
+            PacketState::DeviceId => {
+                self.response = self.status;
+                PacketState::Status
+            },
+            PacketState::Status => PacketState::Magic(0),
+        };
+    }
+
+    fn add_to_sum(&mut self, byte: u8) {
+        self.sum = self.sum.wrapping_add(byte as u16);
+    }
+
+    fn execute(&mut self) {
+        self.status &= !(STATUS_CHECKSUM_ERROR | STATUS_PACKET_ERROR);
+        if self.checksum != self.sum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+
+        match self.command {
+            COMMAND_INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.printing_polls = 0;
+            },
+            COMMAND_DATA => {
+                let data = if self.compressed { decompress(&self.payload) } else { self.payload.clone() };
+                let room = BUFFER_SIZE - self.image.len();
+                self.image.extend_from_slice(&data[..data.len().min(room)]);
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.image.len() >= BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            },
+            COMMAND_PRINT => {
+                self.print();
+                self.status = STATUS_PRINTING;
+                self.printing_polls = PRINTING_POLLS;
+            },
+            COMMAND_STATUS => {
+                if self.printing_polls > 0 {
+                    self.printing_polls -= 1;
+                    if self.printing_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+            },
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    // PRINT payload: sheet count, margins (high nibble before, low nibble after), palette and
+    // exposure.  A palette of 0 prints like the usual E4.
+    fn print(&mut self) {
+        let margins = self.payload.get(1).cloned().unwrap_or(0);
+        let palette = match self.payload.get(2).cloned().unwrap_or(0) {
+            0 => 0xE4,
+            palette => palette,
+        };
+
+        let rows = self.image.len() / (TILE_COLUMNS * 16);
+        let height = rows * 8;
+        let mut pixels = vec![0; PRINT_WIDTH * height];
+        for (tile_index, tile) in self.image.chunks(16).take(rows * TILE_COLUMNS).enumerate() {
+            let (column, row) = (tile_index % TILE_COLUMNS, tile_index / TILE_COLUMNS);
+            for y in 0..8 {
+                let (low, high) = (tile[y * 2], tile[y * 2 + 1]);
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                    let shade = (palette >> (color * 2)) & 0x3;
+                    pixels[(row * 8 + y) * PRINT_WIDTH + column * 8 + x] = shade;
+                }
+            }
+        }
+        self.image.clear();
+
+        let printout = Printout {
+            width: PRINT_WIDTH,
+            height,
+            pixels,
+            margin_before: margins >> 4,
+            margin_after: margins & 0x0F,
+        };
+        if let Some(callback) = self.callback.as_mut() {
+            callback(&printout);
+        }
+    }
+}
+
+impl SerialEndpoint for GbPrinter {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        let mut state = self.state.borrow_mut();
+        let incoming = state.response & 0x80 != 0;
+        state.response <<= 1;
+        state.received = (state.received << 1) | outgoing as u8;
+        state.bits += 1;
+        if state.bits == 8 {
+            let byte = state.received;
+            state.bits = 0;
+            state.receive(byte);
+        }
+        incoming
+    }
+}
+
+// DATA compression: a control byte with bit 7 set repeats the next byte (n & 0x7F) + 2 times,
+// otherwise the next n + 1 bytes are copied as is.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let control = data[index];
+        index += 1;
+        if control & 0x80 != 0 {
+            if let Some(&byte) = data.get(index) {
+                output.extend_from_slice(&vec![byte; (control & 0x7F) as usize + 2]);
+            }
+            index += 1;
+        } else {
+            let end = (index + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[index..end]);
+            index = end;
+        }
+    }
+    output
+}
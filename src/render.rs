@@ -0,0 +1,220 @@
+//! Image output
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crc::{adler32, Crc32};
+use error::FaroreError;
+use ppu::{Frame, FrameCallback, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+
+static PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Stored deflate blocks hold at most 65535 bytes each
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Writes a frame as an RGBA PNG.
+pub fn write_png(frame: &Frame, path: &Path) -> Result<(), FaroreError> {
+    write_png_rgba(path, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &frame.to_rgba())
+}
+
+/// Writes 8 bit RGBA pixels to a PNG file.
+pub fn write_png_rgba(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), FaroreError> {
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        encode_png(&mut writer, width, height, rgba)?;
+        writer.flush()
+    };
+    write().map_err(|e| FaroreError::io(path, e))
+}
+
+/// Encodes 8 bit RGBA pixels as a PNG.  The image data is stored uncompressed, which keeps the
+/// encoder tiny at the cost of file size.
+pub fn encode_png(writer: &mut dyn Write, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    let stride = width as usize * 4;
+    if rgba.len() != stride * height as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel buffer does not match image size"));
+    }
+
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[
+        8, // bit depth
+        6, // color type: RGBA
+        0, // compression: deflate
+        0, // filter method
+        0, // no interlacing
+    ]);
+    write_chunk(writer, b"IHDR", &ihdr)?;
+
+    // Each scanline is prefixed with its filter type, 0 = none
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgba.chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(writer, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk(writer: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.finish().to_be_bytes())
+}
+
+// Wraps data in a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len() / MAX_STORED_BLOCK + 1;
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = if chunks.peek().is_none() { 1 } else { 0 };
+        let len = chunk.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Blends each frame with what was shown before it, imitating the slow response of the DMG
+/// LCD.  Games that flicker sprites on alternate frames for transparency rely on this.  It
+/// works on RGBA output only, so index buffers and frame hashes are left alone.
+pub struct Ghosting {
+    factor: f32, // How much of the previous output persists, 0 = off
+    previous: Vec<u8>,
+}
+
+impl Ghosting {
+    /// Keeps `factor` of the previous output in each frame, clamped to 0-1.
+    pub fn new(factor: f32) -> Self {
+        Ghosting { factor: factor.clamp(0.0, 1.0), previous: Vec::new() }
+    }
+
+    /// How much of the previous output persists, 0 for off.
+    pub fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    /// Changes the factor, clamped to 0-1.
+    pub fn set_factor(&mut self, factor: f32) {
+        self.factor = factor.clamp(0.0, 1.0);
+    }
+
+    /// Blends a frame in place.  The result is remembered, so older frames fade out
+    /// geometrically rather than dropping off after one frame.
+    pub fn apply(&mut self, rgba: &mut [u8]) {
+        if self.factor > 0.0 && self.previous.len() == rgba.len() {
+            for (pixel, previous) in rgba.chunks_mut(4).zip(self.previous.chunks(4)) {
+                for i in 0..3 {
+                    let current = pixel[i] as f32;
+                    let blended = current + (previous[i] as f32 - current) * self.factor;
+                    pixel[i] = blended.round() as u8;
+                }
+            }
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(rgba);
+    }
+}
+
+/// Saves frames as PNGs: one chosen frame, every Nth frame into a directory, or both.  The
+/// images are the palette-applied frame before any ghosting.  Clones share the same settings,
+/// so one can be turned into the PPU's frame callback.
+#[derive(Clone, Default)]
+pub struct Screenshots {
+    state: Rc<RefCell<ScreenshotState>>,
+}
+
+#[derive(Default)]
+struct ScreenshotState {
+    at_frame: Option<(u64, PathBuf)>,
+    every: Option<(u64, PathBuf)>,
+    error: Option<FaroreError>, // The first failed write, since the frame callback can't return it
+}
+
+impl Screenshots {
+    /// No screenshots until some are asked for.
+    pub fn new() -> Self {
+        Screenshots::default()
+    }
+
+    /// Saves frame number `frame` to `path`.
+    pub fn at_frame(&self, frame: u64, path: &Path) {
+        self.state.borrow_mut().at_frame = Some((frame, path.to_path_buf()));
+    }
+
+    /// Saves every `interval`th frame into `dir` as 000060.png, 000120.png and so on.  The
+    /// directory is created if needed and checked for writability now, rather than at the
+    /// first screenshot.
+    pub fn every(&self, interval: u64, dir: &Path) -> Result<(), FaroreError> {
+        if interval == 0 {
+            return Err(FaroreError::InvalidArgument("screenshot interval must be at least 1".to_string()));
+        }
+        let probe = dir.join(".farore-write-test");
+        fs::create_dir_all(dir)
+            .and_then(|_| File::create(&probe))
+            .and_then(|_| fs::remove_file(&probe))
+            .map_err(|e| FaroreError::io(dir, e))?;
+        self.state.borrow_mut().every = Some((interval, dir.to_path_buf()));
+        Ok(())
+    }
+
+    /// Saves the frame if it's one that was asked for.  After a failed write, no further
+    /// screenshots are taken.
+    pub fn capture(&self, frame: &Frame) {
+        let mut state = self.state.borrow_mut();
+        if state.error.is_some() {
+            return;
+        }
+        let mut paths = Vec::new();
+        if let Some((number, ref path)) = state.at_frame {
+            if frame.number == number {
+                paths.push(path.clone());
+            }
+        }
+        if let Some((interval, ref dir)) = state.every {
+            if frame.number.is_multiple_of(interval) {
+                paths.push(dir.join(format!("{:06}.png", frame.number)));
+            }
+        }
+        for path in paths {
+            if let Err(e) = write_png(frame, &path) {
+                state.error = Some(e);
+                return;
+            }
+        }
+    }
+
+    /// A callback for the PPU that takes these screenshots.
+    pub fn frame_callback(&self) -> FrameCallback {
+        let screenshots = self.clone();
+        Box::new(move |frame: &Frame| screenshots.capture(frame))
+    }
+
+    /// The write error that stopped the screenshots, if any.
+    pub fn take_error(&self) -> Option<FaroreError> {
+        self.state.borrow_mut().error.take()
+    }
+}
@@ -0,0 +1,113 @@
+//! The debug command's prompt
+//!
+//! Each line typed is turned into the text to print by `Session::respond`, so a session can be
+//! tested as a list of lines and replies.  The loop reading stdin is just glue around it.
+
+use farore::debugger::{DebugCommand, Location, HELP};
+use farore::symbols::SymbolTable;
+
+
+/// What to do after a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Print(String),
+    Quit,
+}
+
+/// A debugging session on one ROM.
+pub struct Session {
+    symbols: Option<SymbolTable>,
+}
+
+impl Session {
+    pub fn new(symbols: Option<SymbolTable>) -> Self {
+        Session { symbols }
+    }
+
+    /// The reply to one line.  Blank lines get an empty reply.
+    pub fn respond(&mut self, line: &str) -> Reply {
+        let name = match line.split_whitespace().next() {
+            Some(name) => name,
+            None => return Reply::Print(String::new()),
+        };
+        let command = match DebugCommand::parse(line) {
+            Ok(command) => command,
+            Err(e) => return Reply::Print(format!("error: {}", e)),
+        };
+        if let Some(label) = location(&command).and_then(|location| self.unknown_label(location)) {
+            return Reply::Print(format!("error: there is no label {}", label));
+        }
+        match command {
+            DebugCommand::Help => Reply::Print(HELP.to_string()),
+            DebugCommand::Quit => Reply::Quit,
+            _ => Reply::Print(format!("error: {} needs a running machine, and there is no CPU yet", name)),
+        }
+    }
+
+    // A label the symbol table doesn't have.
+    fn unknown_label<'a>(&self, location: &'a Location) -> Option<&'a str> {
+        match *location {
+            Location::Label(ref label) if self.symbols.as_ref().and_then(|symbols| symbols.address(label)).is_none() => {
+                Some(label)
+            },
+            _ => None,
+        }
+    }
+}
+
+// The location a command takes, if it takes one.
+fn location(command: &DebugCommand) -> Option<&Location> {
+    match *command {
+        DebugCommand::Break { ref location, .. } | DebugCommand::Watch { ref location, .. } => Some(location),
+        DebugCommand::Examine { ref location, .. } => Some(location),
+        DebugCommand::Disassemble { ref location, .. } => location.as_ref(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn session() -> Session {
+        let mut symbols = SymbolTable::default();
+        symbols.insert(Some(0), 0x0150, "Start");
+        Session::new(Some(symbols))
+    }
+
+    fn error(reply: Reply) -> String {
+        match reply {
+            Reply::Print(text) => text,
+            Reply::Quit => panic!("quit"),
+        }
+    }
+
+    #[test]
+    fn help_and_quit_need_no_machine() {
+        let mut session = session();
+        assert_eq!(session.respond("help"), Reply::Print(HELP.to_string()));
+        assert_eq!(session.respond("  "), Reply::Print(String::new()));
+        assert_eq!(session.respond("q"), Reply::Quit);
+    }
+
+    #[test]
+    fn a_script_gets_a_reply_per_line() {
+        let mut session = session();
+        let replies: Vec<String> = ["b 0x150", "c", "r"].iter().map(|line| error(session.respond(line))).collect();
+        assert_eq!(replies, [
+            "error: b needs a running machine, and there is no CPU yet",
+            "error: c needs a running machine, and there is no CPU yet",
+            "error: r needs a running machine, and there is no CPU yet",
+        ]);
+    }
+
+    #[test]
+    fn bad_lines_and_labels_are_reported() {
+        let mut session = session();
+        assert_eq!(error(session.respond("jump")), "error: unknown command jump, try help");
+        assert_eq!(error(session.respond("b Nowhere")), "error: there is no label Nowhere");
+        assert_eq!(error(session.respond("u Start")), "error: u needs a running machine, and there is no CPU yet");
+        assert_eq!(error(Session::new(None).respond("x Start")), "error: there is no label Start");
+    }
+}
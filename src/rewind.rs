@@ -0,0 +1,165 @@
+//! Rewind history
+//!
+//! Snapshots are kept newest first as a chain of deltas: the newest is stored whole and each
+//! older one as the XOR against the snapshot after it, with the runs of zeros squeezed out.
+//! Consecutive snapshots barely differ, so a delta is a small fraction of a snapshot.  Dropping
+//! the oldest entry never breaks the chain, since nothing is stored relative to it.
+
+use std::collections::VecDeque;
+
+
+/// Snapshots are taken every this many frames unless configured otherwise.
+pub const DEFAULT_INTERVAL: u64 = 6;
+
+/// A bounded history of snapshots to step back through.
+pub struct RewindBuffer {
+    interval: u64,
+    memory_limit: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>, // Oldest at the front
+    memory: usize,           // Bytes held by `newest` and `deltas`
+}
+
+struct Delta {
+    len: usize, // Length of the older snapshot
+    packed: Vec<u8>,
+}
+
+impl RewindBuffer {
+    /// Keeps snapshots taken every `interval` frames, dropping the oldest once they take up
+    /// more than `memory_limit` bytes.  The newest one is always kept.
+    pub fn new(interval: u64, memory_limit: usize) -> Self {
+        RewindBuffer {
+            interval: interval.max(1),
+            memory_limit,
+            newest: None,
+            deltas: VecDeque::new(),
+            memory: 0,
+        }
+    }
+
+    /// Whether a snapshot should be pushed after this frame.
+    pub fn is_due(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    /// Adds a snapshot as the newest, dropping the oldest ones if over the memory limit.
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let delta = Delta { len: previous.len(), packed: pack(&xor(&previous, &snapshot)) };
+            self.memory += delta.packed.len();
+            self.memory -= previous.len();
+            self.deltas.push_back(delta);
+        }
+        self.memory += snapshot.len();
+        self.newest = Some(snapshot);
+
+        while self.memory > self.memory_limit {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.memory -= oldest.packed.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Takes the newest snapshot off the history.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.memory -= newest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.memory -= delta.packed.len();
+            let mut older = unpack(&delta.packed, delta.len.max(newest.len()));
+            for (byte, &newer) in older.iter_mut().zip(&newest) {
+                *byte ^= newer;
+            }
+            older.truncate(delta.len);
+            self.memory += older.len();
+            self.newest = Some(older);
+        }
+        Some(newest)
+    }
+
+    /// How many snapshots are held.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    /// Whether there are no snapshots to step back to.
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes currently held.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Drops every snapshot.
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.memory = 0;
+    }
+}
+
+// XOR of two snapshots, as long as the longer one.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = if a.len() >= b.len() { a.to_vec() } else { b.to_vec() };
+    for (byte, (&x, &y)) in out.iter_mut().zip(a.iter().zip(b)) {
+        *byte = x ^ y;
+    }
+    out
+}
+
+// Alternating zero run and literal run lengths, each a LEB128 varint, the literals following
+// their length.
+fn pack(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += zeros;
+        let literals = data[i..].iter().take_while(|&&byte| byte != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&data[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+fn unpack(packed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < packed.len() {
+        let zeros = read_varint(packed, &mut i);
+        let literals = read_varint(packed, &mut i);
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&packed[i..i + literals]);
+        i += literals;
+    }
+    out.resize(len, 0);
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = data.get(*i) {
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
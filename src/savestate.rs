@@ -0,0 +1,262 @@
+//! Save state files
+//!
+//! A state is a list of sections, each the snapshot of one part of the machine under a four
+//! character tag.  The snapshots are opaque here: this only packs them into a file with what's
+//! needed to refuse a bad load, the format version and the ROM they were taken with.
+//!
+//! File layout, little endian:
+//!   00-03   Magic "FGBS"
+//!   04-05   Major version, 1
+//!   06-07   Minor version, 0
+//!   08-0B   CRC-32 of the ROM
+//!   0C      Model: 0 DMG, 1 CGB
+//!   0D-0F   Reserved
+//!   10-1F   ROM title, padded with zeros
+//!   20-27   Frame it was taken at
+//!   28-2B   Payload length
+//!   2C-     Payload: the sections as a zlib stream
+//!
+//! Each section is its tag, its length as four bytes and then its snapshot.
+//!
+//! Compatibility rules:
+//!   - The major version goes up when a section's layout changes.  A state from another major
+//!     version is refused with `SaveStateVersion`.
+//!   - The minor version goes up when sections are added.  A state from a newer minor version
+//!     still loads: sections this build doesn't know are skipped, wherever they are.
+//!   - A section missing from a state, because it's from an older minor version or its
+//!     component was left out of the build, is defaulted by that component.
+
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use archive;
+use crc::adler32;
+use error::FaroreError;
+use model::HardwareModel;
+
+
+const MAGIC: [u8; 4] = *b"FGBS";
+const HEADER_SIZE: usize = 0x2C;
+const TITLE_SIZE: usize = 16;
+
+// A whole machine is well under this.  It stops a damaged payload from eating all the memory.
+const MAX_SECTIONS_SIZE: usize = 16 * 1024 * 1024;
+
+/// The major version this build writes, and the only one it reads.
+pub const MAJOR_VERSION: u16 = 1;
+/// The minor version this build writes.  States from any minor version can be read.
+pub const MINOR_VERSION: u16 = 0;
+
+/// The CPU registers.
+pub const CPU: [u8; 4] = *b"CPU ";
+/// Work RAM, high RAM and the I/O registers that belong to no component.
+pub const BUS: [u8; 4] = *b"BUS ";
+/// Video RAM, OAM, the palettes and where the PPU is in the frame.
+pub const PPU: [u8; 4] = *b"PPU ";
+/// The sound channels and the frame sequencer.
+pub const APU: [u8; 4] = *b"APU ";
+/// DIV and the timer registers.
+pub const TIMER: [u8; 4] = *b"TIMR";
+/// The mapper's bank registers, cartridge RAM and the clock.
+pub const MAPPER: [u8; 4] = *b"MBC ";
+
+// The sections this build knows, and keeps when loading.
+const KNOWN_SECTIONS: [[u8; 4]; 6] = [CPU, BUS, PPU, APU, TIMER, MAPPER];
+
+/// One component's snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// Which component it is, like `PPU`.
+    pub tag: [u8; 4],
+    /// The component's state.
+    pub data: Vec<u8>,
+}
+
+/// A machine's state and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveState {
+    /// The ROM it was taken with, to catch loading it into another.
+    pub rom_crc32: u32,
+    /// The title from that ROM's header, for telling the user which game a state is for.
+    pub rom_title: String,
+    /// The model it was taken on.
+    pub model: HardwareModel,
+    /// How many frames had run.
+    pub frame: u64,
+    /// The component snapshots, in the order they're saved.
+    pub sections: Vec<Section>,
+}
+
+impl SaveState {
+    /// A state with no sections yet.
+    pub fn new(rom_crc32: u32, rom_title: &str, model: HardwareModel, frame: u64) -> Self {
+        SaveState { rom_crc32, rom_title: rom_title.to_string(), model, frame, sections: Vec::new() }
+    }
+
+    /// The snapshot in the section tagged `tag`, if the state has one.
+    pub fn section(&self, tag: [u8; 4]) -> Option<&[u8]> {
+        self.sections.iter().find(|section| section.tag == tag).map(|section| &section.data[..])
+    }
+
+    /// Sets the snapshot in the section tagged `tag`, adding the section if it's new.
+    pub fn set_section(&mut self, tag: [u8; 4], data: Vec<u8>) {
+        match self.sections.iter_mut().find(|section| section.tag == tag) {
+            Some(section) => section.data = data,
+            None => self.sections.push(Section { tag, data }),
+        }
+    }
+
+    /// Saves the state in the file layout above.  Fails with `InvalidInput` if the title is
+    /// longer than 16 bytes or a section is too large for its length field.
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let too_large = |what: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("the {} is too large for a save state", what));
+        if self.rom_title.len() > TITLE_SIZE {
+            return Err(too_large("rom title"));
+        }
+        let mut sections = Vec::new();
+        for section in &self.sections {
+            let length = u32::try_from(section.data.len()).map_err(|_| too_large("section"))?;
+            sections.extend_from_slice(&section.tag);
+            sections.extend_from_slice(&length.to_le_bytes());
+            sections.extend_from_slice(&section.data);
+        }
+        let payload = zlib(&sections);
+        let payload_length = u32::try_from(payload.len()).map_err(|_| too_large("payload"))?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        header[0x00..0x04].copy_from_slice(&MAGIC);
+        header[0x04..0x06].copy_from_slice(&MAJOR_VERSION.to_le_bytes());
+        header[0x06..0x08].copy_from_slice(&MINOR_VERSION.to_le_bytes());
+        header[0x08..0x0C].copy_from_slice(&self.rom_crc32.to_le_bytes());
+        header[0x0C] = self.model.is_cgb() as u8;
+        header[0x10..0x10 + self.rom_title.len()].copy_from_slice(self.rom_title.as_bytes());
+        header[0x20..0x28].copy_from_slice(&self.frame.to_le_bytes());
+        header[0x28..0x2C].copy_from_slice(&payload_length.to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&payload)
+    }
+
+    /// Reads a state back, keeping only the sections this build knows.  Fails with
+    /// `SaveStateVersion` on a state from another major version, and with `InvalidArgument` on
+    /// anything that isn't a whole save state.
+    pub fn parse(data: &[u8]) -> Result<Self, FaroreError> {
+        if data.len() < 6 || data[0x00..0x04] != MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        let half = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        if half(0x04) != MAJOR_VERSION {
+            return Err(FaroreError::SaveStateVersion { found: half(0x04) as u32, supported: MAJOR_VERSION as u32 });
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(invalid("the header is truncated"));
+        }
+        let word = |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let model = match data[0x0C] {
+            0 => HardwareModel::Dmg,
+            1 => HardwareModel::Cgb,
+            model => return Err(invalid(&format!("unknown model {}", model))),
+        };
+        let title = &data[0x10..0x20];
+        let title = &title[..title.iter().position(|&byte| byte == 0).unwrap_or(TITLE_SIZE)];
+        let rom_title = String::from_utf8(title.to_vec()).map_err(|_| invalid("the rom title isn't UTF-8"))?;
+        let length = word(0x28) as usize;
+        let payload = &data[HEADER_SIZE..];
+        if payload.len() != length {
+            return Err(invalid(&format!("the payload is {} bytes, the header says {}", payload.len(), length)));
+        }
+
+        Ok(SaveState {
+            rom_crc32: word(0x08),
+            rom_title,
+            model,
+            frame: word(0x20) as u64 | (word(0x24) as u64) << 32,
+            sections: parse_sections(&unzlib(payload)?)?,
+        })
+    }
+
+    /// Writes the state to a file.
+    pub fn save(&self, path: &Path) -> Result<(), FaroreError> {
+        let write = || -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(path)?);
+            self.write(&mut writer)?;
+            writer.flush()
+        };
+        write().map_err(|e| FaroreError::io(path, e))
+    }
+
+    /// Reads a state from a file.
+    pub fn load(path: &Path) -> Result<Self, FaroreError> {
+        SaveState::parse(&fs::read(path).map_err(|e| FaroreError::io(path, e))?)
+    }
+
+    /// Checks the state was taken with the ROM with `rom_crc32`.  Loading it into another ROM
+    /// would crash the game at best, so it takes `force` to allow that.
+    pub fn check_rom(&self, rom_crc32: u32, force: bool) -> Result<(), FaroreError> {
+        if self.rom_crc32 != rom_crc32 && !force {
+            return Err(FaroreError::RomMismatch { expected_crc32: self.rom_crc32, found_crc32: rom_crc32 });
+        }
+        Ok(())
+    }
+}
+
+/// How long the state at the start of `data` is, going by its header, for a caller that keeps
+/// states in a larger buffer.  `None` if `data` doesn't start with a whole header.
+pub fn stored_size(data: &[u8]) -> Option<usize> {
+    if data.len() < HEADER_SIZE || data[0x00..0x04] != MAGIC {
+        return None;
+    }
+    Some(HEADER_SIZE + u32::from_le_bytes([data[0x28], data[0x29], data[0x2A], data[0x2B]]) as usize)
+}
+
+/// The file for save state `slot` of `rom`, next to it: slot 1 of game.gb is game.state1.
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+fn invalid(reason: &str) -> FaroreError {
+    FaroreError::InvalidArgument(format!("invalid save state: {}", reason))
+}
+
+// Drops the sections this build doesn't know, which come from newer minor versions.
+fn parse_sections(mut data: &[u8]) -> Result<Vec<Section>, FaroreError> {
+    let mut sections = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 8 {
+            return Err(invalid("a section header is truncated"));
+        }
+        let tag = [data[0], data[1], data[2], data[3]];
+        let length = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let section = match data.get(8..8 + length) {
+            Some(section) => section,
+            None => return Err(invalid(&format!("the {} section is truncated", String::from_utf8_lossy(&tag).trim_end()))),
+        };
+        if KNOWN_SECTIONS.contains(&tag) {
+            sections.push(Section { tag, data: section.to_vec() });
+        }
+        data = &data[8 + length..];
+    }
+    Ok(sections)
+}
+
+// A zlib stream: the header for DEFLATE with a 32K window, the data and its Adler-32.
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    out.extend_from_slice(&archive::deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn unzlib(stream: &[u8]) -> Result<Vec<u8>, FaroreError> {
+    if stream.len() < 6 || stream[0] & 0x0F != 8 || stream[1] & 0x20 != 0
+        || !u16::from_be_bytes([stream[0], stream[1]]).is_multiple_of(31) {
+        return Err(invalid("the payload isn't a zlib stream"));
+    }
+    let (data, used) = archive::inflate(&stream[2..], MAX_SECTIONS_SIZE)
+        .map_err(|e| invalid(&format!("the payload can't be decompressed: {}", e)))?;
+    match stream.get(2 + used..) {
+        Some(checksum) if checksum.len() == 4 && *checksum == adler32(&data).to_be_bytes() => Ok(data),
+        _ => Err(invalid("the payload fails its Adler-32 check")),
+    }
+}
@@ -0,0 +1,197 @@
+//! Automation scripts for `run --script`
+//!
+//! A script is a list of hooks.  Each starts with an `on` line naming when it runs, and its
+//! commands follow on the lines indented below it:
+//!
+//!   on frame 60           # Once, after frame 60
+//!   on every 30           # After every 30th frame
+//!   on break LOC          # Whenever the CPU is about to run LOC
+//!
+//! Hooks run between frames, or between instructions for breakpoints, so a run with a script is
+//! as deterministic as one without.  `#` starts a comment.  Numbers and locations are written
+//! as in the debugger, and errors say which file and line they're on.
+
+use std::fs;
+use std::path::Path;
+
+use debugger::{self, Comparison, Location};
+use error::FaroreError;
+use joypad::{Button, InputState};
+
+
+/// What a script can do when a hook runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptCommand {
+    /// `peek LOC`: print the byte at `LOC`.
+    Peek(Location),
+    /// `poke LOC N`: write a byte.
+    Poke {
+        /// Where to write.
+        location: Location,
+        /// What to write.
+        value: u8,
+    },
+    /// `assert LOC OP N`: fail the run unless the byte at `LOC` compares true against `N`.
+    Assert {
+        /// The byte tested.
+        location: Location,
+        /// How it's compared.
+        comparison: Comparison,
+        /// What it's compared against.
+        value: u8,
+    },
+    /// `press BUTTON...`: hold these buttons for the next frame, and only these.
+    Press(InputState),
+    /// `screenshot PATH`: save the last frame as a PNG.
+    Screenshot(String),
+    /// `stop`: end the run after this hook.
+    Stop,
+}
+
+/// When a hook runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// After this frame.
+    Frame(u32),
+    /// After every this many frames.
+    Every(u32),
+    /// Before the instruction at this location runs.
+    Break(Location),
+}
+
+/// A trigger and the commands it runs, with the line each is on for reporting failed asserts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    /// When it runs.
+    pub trigger: Trigger,
+    /// What it runs, in order, each with its line number.
+    pub commands: Vec<(usize, ScriptCommand)>,
+}
+
+/// A parsed script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    /// Where it came from, for messages.
+    pub name: String,
+    /// Its hooks, in the order they're written, which is the order they run in.
+    pub hooks: Vec<Hook>,
+}
+
+impl Script {
+    /// Parses a script called `name`.  Fails with `InvalidArgument`, the message starting with
+    /// the name and line number, on anything that doesn't parse.
+    pub fn parse(source: &str, name: &str) -> Result<Self, FaroreError> {
+        let mut hooks: Vec<Hook> = Vec::new();
+        for (index, raw) in source.lines().enumerate() {
+            let number = index + 1;
+            let at_line = |e: FaroreError| FaroreError::InvalidArgument(format!("{}:{}: {}", name, number, e));
+            let line = raw.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            if !line.starts_with(char::is_whitespace) {
+                hooks.push(Hook { trigger: trigger(&words).map_err(at_line)?, commands: Vec::new() });
+                continue;
+            }
+            let command = command(&words).map_err(at_line)?;
+            match hooks.last_mut() {
+                Some(hook) => hook.commands.push((number, command)),
+                None => return Err(at_line(invalid("commands go indented under an on line".to_string()))),
+            }
+        }
+        Ok(Script { name: name.to_string(), hooks })
+    }
+
+    /// Reads and parses a script file.
+    pub fn load(path: &Path) -> Result<Self, FaroreError> {
+        let source = fs::read_to_string(path).map_err(|e| FaroreError::io(path, e))?;
+        Script::parse(&source, &path.display().to_string())
+    }
+
+    /// The hooks to run after `frame`, counting from 1.
+    pub fn frame_hooks(&self, frame: u32) -> impl Iterator<Item = &Hook> {
+        self.hooks.iter().filter(move |hook| match hook.trigger {
+            Trigger::Frame(at) => at == frame,
+            Trigger::Every(interval) => frame.is_multiple_of(interval),
+            Trigger::Break(_) => false,
+        })
+    }
+
+    /// The locations breakpoint hooks stop at, to set as breakpoints before the run.
+    pub fn breakpoints(&self) -> Vec<&Location> {
+        self.hooks.iter()
+            .filter_map(|hook| match hook.trigger {
+                Trigger::Break(ref location) => Some(location),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn invalid(reason: String) -> FaroreError {
+    FaroreError::InvalidArgument(reason)
+}
+
+// Checks a line has exactly `count` words after its name.
+fn arity(words: &[&str], count: usize) -> Result<(), FaroreError> {
+    if words.len() != count + 1 {
+        let plural = if count == 1 { "" } else { "s" };
+        return Err(invalid(format!("{} takes {} argument{}, not {}", words[0], count, plural, words.len() - 1)));
+    }
+    Ok(())
+}
+
+fn trigger(words: &[&str]) -> Result<Trigger, FaroreError> {
+    if words[0] != "on" || words.len() != 3 {
+        return Err(invalid("expected on frame N, on every N or on break LOC".to_string()));
+    }
+    match words[1] {
+        "frame" => Ok(Trigger::Frame(debugger::number(words[2], 1, u32::MAX as usize)? as u32)),
+        "every" => Ok(Trigger::Every(debugger::number(words[2], 1, u32::MAX as usize)? as u32)),
+        "break" => Ok(Trigger::Break(debugger::location(words[2])?)),
+        other => Err(invalid(format!("{} isn't frame, every or break", other))),
+    }
+}
+
+fn command(words: &[&str]) -> Result<ScriptCommand, FaroreError> {
+    let byte = |s: &str| debugger::number(s, 0, 0xFF).map(|n| n as u8);
+    Ok(match words[0] {
+        "peek" => {
+            arity(words, 1)?;
+            ScriptCommand::Peek(debugger::location(words[1])?)
+        },
+        "poke" => {
+            arity(words, 2)?;
+            ScriptCommand::Poke { location: debugger::location(words[1])?, value: byte(words[2])? }
+        },
+        "assert" => {
+            arity(words, 3)?;
+            ScriptCommand::Assert {
+                location: debugger::location(words[1])?,
+                comparison: debugger::comparison(words[2])?,
+                value: byte(words[3])?,
+            }
+        },
+        "press" => {
+            let mut state = InputState::new();
+            for name in &words[1..] {
+                state.set(button(name)?, true);
+            }
+            ScriptCommand::Press(state)
+        },
+        "screenshot" => {
+            arity(words, 1)?;
+            ScriptCommand::Screenshot(words[1].to_string())
+        },
+        "stop" => {
+            arity(words, 0)?;
+            ScriptCommand::Stop
+        },
+        other => return Err(invalid(format!("unknown command {}", other))),
+    })
+}
+
+fn button(name: &str) -> Result<Button, FaroreError> {
+    Button::named(name).ok_or_else(|| invalid(format!("{} isn't a button", name)))
+}
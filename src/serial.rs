@@ -0,0 +1,332 @@
+//! Serial port
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::rc::Rc;
+use std::str;
+
+use interrupt::{Interrupt, InterruptLine};
+use io::IoPeripheral;
+use model::HardwareModel;
+
+
+// T-cycles per bit with the internal clock: 8192Hz, or 262144Hz with the CGB fast bit
+const NORMAL_BIT_CYCLES: u32 = 512;
+const FAST_BIT_CYCLES: u32 = 16;
+
+// Captured text stops growing past this many bytes
+const DEFAULT_CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// Called with each piece of text as it's captured.
+pub type TextCallback = Box<dyn FnMut(&str)>;
+
+/// Whatever is plugged into the link port.  Both ends shift at the same time, so every bit
+/// sent comes with one received.
+pub trait SerialEndpoint {
+    /// Exchanges a bit clocked by this side.  Nothing connected reads as 1.
+    fn exchange_bit(&mut self, _outgoing: bool) -> bool {
+        true
+    }
+
+    /// Polled on every tick while waiting on an external clock.  Returns the incoming bit
+    /// when the other side has driven a clock pulse, in which case `outgoing` is shifted out.
+    fn poll_clock_and_bit(&mut self, _outgoing: bool) -> Option<bool> {
+        None
+    }
+}
+
+/// Registers
+///   FF01   SB - Transfer data
+///   FF02   SC - Bit 7 transfer start/in progress, bit 1 fast clock (CGB), bit 0 internal clock
+///
+/// A transfer shifts SB out from the highest bit while the received bits come in at the lowest.
+/// After 8 bits SC bit 7 clears and the Serial interrupt is requested.  With the external clock
+/// nothing happens until the endpoint clocks it, forever if nothing is connected.
+pub struct Serial {
+    model: HardwareModel,
+    sb: u8,
+    sc: u8,
+
+    endpoint: Option<Box<dyn SerialEndpoint>>,
+    cycles: u32, // T-cycles towards the next internally clocked bit
+    bits: u8,    // Bits shifted in the current transfer
+}
+
+impl Serial {
+    /// A port with nothing plugged in.
+    pub fn new(model: HardwareModel) -> Self {
+        Serial {
+            model,
+            sb: 0,
+            sc: 0,
+            endpoint: None,
+            cycles: 0,
+            bits: 0,
+        }
+    }
+
+    /// Plugs something into the port, replacing whatever was there.
+    pub fn connect(&mut self, endpoint: Box<dyn SerialEndpoint>) {
+        self.endpoint = Some(endpoint);
+    }
+
+    /// Unplugs whatever is in the port and hands it back.
+    pub fn disconnect(&mut self) -> Option<Box<dyn SerialEndpoint>> {
+        self.endpoint.take()
+    }
+
+    /// Whether SC bit 7 is set.
+    pub fn is_transferring(&self) -> bool {
+        self.sc & 0x80 != 0
+    }
+
+    /// Advances by a number of T-cycles, requesting the Serial interrupt when a transfer ends.
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptLine) {
+        if !self.is_transferring() {
+            return;
+        }
+
+        if self.sc & 0x01 == 0 {
+            let outgoing = self.sb & 0x80 != 0;
+            let incoming = self.endpoint.as_mut().and_then(|endpoint| endpoint.poll_clock_and_bit(outgoing));
+            if let Some(incoming) = incoming {
+                self.shift(incoming, irq);
+            }
+            return;
+        }
+
+        self.cycles += cycles;
+        let period = self.bit_cycles();
+        while self.is_transferring() && self.cycles >= period {
+            self.cycles -= period;
+            let outgoing = self.sb & 0x80 != 0;
+            let incoming = match self.endpoint.as_mut() {
+                Some(endpoint) => endpoint.exchange_bit(outgoing),
+                None => true,
+            };
+            self.shift(incoming, irq);
+        }
+    }
+
+    fn bit_cycles(&self) -> u32 {
+        if self.model.is_cgb() && self.sc & 0x02 != 0 {
+            FAST_BIT_CYCLES
+        } else {
+            NORMAL_BIT_CYCLES
+        }
+    }
+
+    fn shift(&mut self, incoming: bool, irq: &mut InterruptLine) {
+        self.sb = (self.sb << 1) | incoming as u8;
+        self.bits += 1;
+        if self.bits == 8 {
+            self.sc &= 0x7F;
+            irq.request(Interrupt::Serial);
+        }
+    }
+}
+
+impl IoPeripheral for Serial {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.sb,
+            0xFF02 if self.model.is_cgb() => 0x7C | self.sc,
+            0xFF02 => 0x7E | self.sc,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.sb = value,
+            0xFF02 => {
+                self.sc = value & if self.model.is_cgb() { 0x83 } else { 0x81 };
+                if self.is_transferring() {
+                    self.cycles = 0;
+                    self.bits = 0;
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Collects the bytes a game sends with the internal clock as text, which is how blargg's test
+/// ROMs and a lot of homebrew print.  Clones share the same capture, so one can be connected to
+/// the serial port while another reads the text.  Bytes that aren't valid UTF-8 are kept as
+/// "\xNN" escapes.
+#[derive(Clone)]
+pub struct SerialTextCapture {
+    state: Rc<RefCell<CaptureState>>,
+}
+
+struct CaptureState {
+    text: String,
+    limit: usize,
+    callback: Option<TextCallback>,
+    shift: u8,
+    bits: u8,
+    pending: Vec<u8>, // The start of a UTF-8 sequence still waiting on bytes
+}
+
+impl SerialTextCapture {
+    /// A capture that keeps up to 64KiB of text.
+    pub fn new() -> Self {
+        SerialTextCapture::with_limit(DEFAULT_CAPTURE_LIMIT)
+    }
+
+    /// A capture that stops growing once the text reaches `limit` bytes.
+    pub fn with_limit(limit: usize) -> Self {
+        let state = CaptureState {
+            text: String::new(),
+            limit,
+            callback: None,
+            shift: 0,
+            bits: 0,
+            pending: Vec::new(),
+        };
+        SerialTextCapture { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// The text captured so far.
+    pub fn text(&self) -> String {
+        self.state.borrow().text.clone()
+    }
+
+    /// Forgets the text captured so far.
+    pub fn clear(&self) {
+        self.state.borrow_mut().text.clear();
+    }
+
+    /// Sets a callback for new text, which is called even after the limit is reached.
+    pub fn set_callback(&self, callback: TextCallback) {
+        self.state.borrow_mut().callback = Some(callback);
+    }
+
+    /// Echoes new text to `writer` as it arrives, e.g. stdout.
+    pub fn echo_to(&self, mut writer: Box<dyn Write>) {
+        self.set_callback(Box::new(move |text| {
+            let _ = writer.write_all(text.as_bytes()).and_then(|_| writer.flush());
+        }));
+    }
+}
+
+impl Default for SerialTextCapture {
+    fn default() -> Self {
+        SerialTextCapture::new()
+    }
+}
+
+impl CaptureState {
+    fn push_byte(&mut self, byte: u8) {
+        self.pending.push(byte);
+        let mut text = String::new();
+        loop {
+            match str::from_utf8(&self.pending) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    self.pending.clear();
+                    break;
+                },
+                Err(error) => {
+                    let valid = error.valid_up_to();
+                    text.push_str(str::from_utf8(&self.pending[..valid]).unwrap_or_default());
+                    match error.error_len() {
+                        Some(len) => {
+                            for byte in &self.pending[valid..valid + len] {
+                                text.push_str(&format!("\\x{:02X}", byte));
+                            }
+                            self.pending.drain(..valid + len);
+                        },
+                        None => {
+                            self.pending.drain(..valid);
+                            break;
+                        },
+                    }
+                },
+            }
+        }
+
+        if text.is_empty() {
+            return;
+        }
+        if self.text.len() < self.limit {
+            self.text.push_str(&text);
+        }
+        if let Some(callback) = self.callback.as_mut() {
+            callback(&text);
+        }
+    }
+}
+
+impl SerialEndpoint for SerialTextCapture {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        let mut state = self.state.borrow_mut();
+        state.shift = (state.shift << 1) | outgoing as u8;
+        state.bits += 1;
+        if state.bits == 8 {
+            let byte = state.shift;
+            state.bits = 0;
+            state.push_byte(byte);
+        }
+        true
+    }
+}
+
+/// Links two serial ports in the same process.  When one side shifts with its internal clock,
+/// the other side gets a clock pulse and the two bits are swapped.  A side only receives a
+/// pulse if it has polled for one since the last, so both need to be stepped in small slices
+/// of a few M-cycles each to stay in sync.
+///
+/// Like the real cable, two internally clocked sides each read 1s, and two externally clocked
+/// sides wait forever.
+pub struct LinkCable;
+
+// One direction of the cable, for the end receiving clock pulses
+#[derive(Default)]
+struct LinkLine {
+    listening: bool, // Waiting on an external clock, as of its last poll
+    ready: bool,     // The bit it will shift out on the next pulse
+    pulses: VecDeque<bool>,
+}
+
+struct LinkEnd {
+    lines: Rc<RefCell<[LinkLine; 2]>>,
+    side: usize,
+}
+
+impl LinkCable {
+    /// Plugs two ports into each other, replacing what they had.
+    pub fn connect(a: &mut Serial, b: &mut Serial) {
+        let lines = Rc::new(RefCell::new([LinkLine::default(), LinkLine::default()]));
+        a.connect(Box::new(LinkEnd { lines: lines.clone(), side: 0 }));
+        b.connect(Box::new(LinkEnd { lines, side: 1 }));
+    }
+}
+
+impl SerialEndpoint for LinkEnd {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        let mut lines = self.lines.borrow_mut();
+        let other = &mut lines[1 - self.side];
+        if !other.listening {
+            return true;
+        }
+        other.listening = false;
+        other.pulses.push_back(outgoing);
+        other.ready
+    }
+
+    fn poll_clock_and_bit(&mut self, outgoing: bool) -> Option<bool> {
+        let mut lines = self.lines.borrow_mut();
+        let line = &mut lines[self.side];
+        match line.pulses.pop_front() {
+            Some(incoming) => Some(incoming),
+            None => {
+                line.listening = true;
+                line.ready = outgoing;
+                None
+            },
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Super Game Boy command packets
+//!
+//! SGB games talk to the SNES side by toggling the joypad select lines (P1 bits 5-4):
+//!   00   Reset pulse, starting a packet
+//!   20   A 0 bit (P14 low)
+//!   10   A 1 bit (P15 low)
+//!   30   Between pulses
+//! Each packet is 128 bits, least significant bit of each byte first, followed by a 0 stop bit.
+//! The first byte of the first packet holds the command in bits 7-3 and the number of packets
+//! in bits 2-0.
+
+use std::fmt;
+
+
+const PACKET_SIZE: usize = 16;
+
+/// The multiplayer request command, which changes what JOYP reads.
+pub const MLT_REQ: u8 = 0x11;
+
+const COMMAND_NAMES: [&str; 0x1A] = [
+    "PAL01", "PAL23", "PAL03", "PAL12", "ATTR_BLK", "ATTR_LIN", "ATTR_DIV", "ATTR_CHR",
+    "SOUND", "SOU_TRN", "PAL_SET", "PAL_TRN", "ATRC_EN", "TEST_EN", "ICON_EN", "DATA_SND",
+    "DATA_TRN", "MLT_REQ", "JUMP", "CHR_TRN", "PCT_TRN", "ATTR_TRN", "ATTR_SET", "MASK_EN",
+    "OBJ_TRN", "PAL_PRI",
+];
+
+/// Called with each complete command.
+pub type SgbCallback = Box<dyn FnMut(&SgbCommand)>;
+
+/// A command and the data of all its packets, the first byte included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SgbCommand {
+    /// The command code, the top 5 bits of the first byte.
+    pub command: u8,
+    /// Every packet's 16 bytes, back to back.
+    pub data: Vec<u8>,
+}
+
+impl SgbCommand {
+    /// The command's name from the SGB manual, like "PAL01".
+    pub fn name(&self) -> &'static str {
+        COMMAND_NAMES.get(self.command as usize).cloned().unwrap_or("unknown")
+    }
+}
+
+impl fmt::Display for SgbCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SGB {:02X} {:<8}", self.command, self.name())?;
+        for byte in &self.data {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rebuilds packets from the sequence of values written to the select lines.
+pub struct SgbDecoder {
+    previous: u8,
+    receiving: bool,
+    bits: usize,
+    packet: [u8; PACKET_SIZE],
+    data: Vec<u8>, // Packets received so far for the current command
+}
+
+impl SgbDecoder {
+    /// A decoder waiting for a reset pulse.
+    pub fn new() -> Self {
+        SgbDecoder {
+            previous: 0x30,
+            receiving: false,
+            bits: 0,
+            packet: [0; PACKET_SIZE],
+            data: Vec::new(),
+        }
+    }
+
+    /// Takes bits 5-4 of a P1 write, returning a command once all its packets have arrived.
+    pub fn write(&mut self, select: u8) -> Option<SgbCommand> {
+        let previous = self.previous;
+        self.previous = select;
+        if select == 0x00 {
+            self.receiving = true;
+            self.bits = 0;
+            self.packet = [0; PACKET_SIZE];
+            return None;
+        }
+        if !self.receiving || previous != 0x30 {
+            return None;
+        }
+        let bit = match select {
+            0x20 => false,
+            0x10 => true,
+            _ => return None,
+        };
+
+        if self.bits < PACKET_SIZE * 8 {
+            if bit {
+                self.packet[self.bits / 8] |= 1 << (self.bits % 8);
+            }
+            self.bits += 1;
+            return None;
+        }
+
+        // The stop bit
+        self.receiving = false;
+        if bit {
+            self.data.clear();
+            return None;
+        }
+        self.data.extend_from_slice(&self.packet);
+        let packets = (self.data[0] & 0x07).max(1) as usize;
+        if self.data.len() < packets * PACKET_SIZE {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data);
+        Some(SgbCommand { command: data[0] >> 3, data })
+    }
+}
+
+impl Default for SgbDecoder {
+    fn default() -> Self {
+        SgbDecoder::new()
+    }
+}
@@ -0,0 +1,157 @@
+//! SM83 single step test cases
+//!
+//! The SingleStepTests sm83 suite has a JSON file per opcode, each an array of cases:
+//!   name      The opcode and case number
+//!   initial   Registers, "ime", "ie" and the RAM as [address, value] pairs
+//!   final     The same after running one instruction
+//!   cycles    Per M-cycle [address, value, "r-m"], where r is a read and w in the middle a
+//!             write, or null when the bus is idle
+
+use error::FaroreError;
+use json::Json;
+use trace::Registers;
+
+
+/// The CPU and memory before or after a case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    /// The registers.
+    pub registers: Registers,
+    /// The interrupt master enable.
+    pub ime: bool,
+    /// IE, which older files leave out.
+    pub ie: Option<u8>,
+    /// Every byte the case sets or checks, in file order.
+    pub ram: Vec<(u16, u8)>,
+}
+
+/// What the bus does on one M-cycle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BusAccess {
+    /// Reads `value` from `address`.
+    Read,
+    /// Writes `value` to `address`.
+    Write,
+    /// Neither, with the address and value left on the bus.
+    Idle,
+}
+
+/// One M-cycle of bus activity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BusCycle {
+    /// The address on the bus.
+    pub address: u16,
+    /// The data on the bus, if the file gives it.
+    pub value: Option<u8>,
+    /// What was done with it.
+    pub access: BusAccess,
+}
+
+/// One case: run a single instruction from `initial` and compare against `expected`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    /// Such as "3e 0017".
+    pub name: String,
+    /// Where the CPU starts.
+    pub initial: CpuState,
+    /// Where it should end up.
+    pub expected: CpuState,
+    /// The bus activity of each M-cycle, None for an idle bus with nothing recorded.
+    pub cycles: Vec<Option<BusCycle>>,
+}
+
+impl TestCase {
+    /// Reads every case in one of the suite's files.  Fails with `InvalidArgument` naming the
+    /// case and field that doesn't fit the format.
+    pub fn parse_file(text: &str) -> Result<Vec<TestCase>, FaroreError> {
+        let json = Json::parse(text)?;
+        let cases = json.as_array().ok_or_else(|| invalid("the file", "isn't an array of cases"))?;
+        cases.iter().enumerate().map(|(i, case)| TestCase::from_json(case, i)).collect()
+    }
+
+    fn from_json(case: &Json, index: usize) -> Result<Self, FaroreError> {
+        let name = match case.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_string(),
+            None => return Err(invalid(&format!("case {}", index), "has no name")),
+        };
+        let cycles = field(case, &name, "cycles")?.as_array()
+            .ok_or_else(|| invalid(&name, "cycles isn't an array"))?
+            .iter()
+            .map(|cycle| bus_cycle(cycle, &name))
+            .collect::<Result<_, _>>()?;
+        Ok(TestCase {
+            initial: cpu_state(field(case, &name, "initial")?, &name)?,
+            expected: cpu_state(field(case, &name, "final")?, &name)?,
+            cycles,
+            name,
+        })
+    }
+}
+
+fn invalid(case: &str, reason: &str) -> FaroreError {
+    FaroreError::InvalidArgument(format!("invalid test case: {} {}", case, reason))
+}
+
+fn field<'a>(json: &'a Json, case: &str, key: &str) -> Result<&'a Json, FaroreError> {
+    json.get(key).ok_or_else(|| invalid(case, &format!("has no {}", key)))
+}
+
+// A number from 0 to `max`.
+fn number(json: &Json, case: &str, what: &str, max: i64) -> Result<i64, FaroreError> {
+    match json.as_i64() {
+        Some(value) if (0..=max).contains(&value) => Ok(value),
+        _ => Err(invalid(case, &format!("{} isn't a number from 0 to {}", what, max))),
+    }
+}
+
+fn cpu_state(json: &Json, case: &str) -> Result<CpuState, FaroreError> {
+    let byte = |key: &str| -> Result<u8, FaroreError> { Ok(number(field(json, case, key)?, case, key, 0xFF)? as u8) };
+    let word = |key: &str| -> Result<u16, FaroreError> { Ok(number(field(json, case, key)?, case, key, 0xFFFF)? as u16) };
+    let registers = Registers {
+        a: byte("a")?,
+        f: byte("f")?,
+        b: byte("b")?,
+        c: byte("c")?,
+        d: byte("d")?,
+        e: byte("e")?,
+        h: byte("h")?,
+        l: byte("l")?,
+        sp: word("sp")?,
+        pc: word("pc")?,
+    };
+    let ime = match json.get("ime") {
+        Some(ime) => number(ime, case, "ime", 1)? == 1,
+        None => false,
+    };
+    let ie = json.get("ie").map(|ie| number(ie, case, "ie", 0xFF).map(|ie| ie as u8)).transpose()?;
+    let ram = field(json, case, "ram")?.as_array()
+        .ok_or_else(|| invalid(case, "ram isn't an array"))?
+        .iter()
+        .map(|pair| match pair.as_array() {
+            Some([address, value]) => {
+                Ok((number(address, case, "a ram address", 0xFFFF)? as u16, number(value, case, "a ram value", 0xFF)? as u8))
+            },
+            _ => Err(invalid(case, "ram holds something other than [address, value] pairs")),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(CpuState { registers, ime, ie, ram })
+}
+
+fn bus_cycle(json: &Json, case: &str) -> Result<Option<BusCycle>, FaroreError> {
+    let (address, value, kind) = match json {
+        Json::Null => return Ok(None),
+        Json::Array(values) if values.len() == 3 => (&values[0], &values[1], &values[2]),
+        _ => return Err(invalid(case, "has a cycle other than [address, value, kind] or null")),
+    };
+    let access = match kind.as_str().map(str::as_bytes) {
+        Some([b'r', _, _]) => BusAccess::Read,
+        Some([_, b'w', _]) => BusAccess::Write,
+        Some([_, _, _]) => BusAccess::Idle,
+        _ => return Err(invalid(case, "has a cycle kind other than 3 characters like r-m")),
+    };
+    let value = match *value {
+        Json::Null => None,
+        ref value => Some(number(value, case, "a cycle value", 0xFF)? as u8),
+    };
+    Ok(Some(BusCycle { address: number(address, case, "a cycle address", 0xFFFF)? as u16, value, access }))
+}
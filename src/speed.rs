@@ -0,0 +1,100 @@
+//! CGB double speed
+//!
+//! KEY1 arms a speed switch, which the CPU carries out on its next STOP.  In double speed the
+//! CPU runs twice as fast, and so does the divider with everything clocked off it: the timer,
+//! the serial port and the APU frame sequencer.  The PPU and the APU channels keep their normal
+//! rate.  Time is handed out in CPU M-cycles, so each peripheral converts it to its own clock
+//! here.
+
+use io::IoPeripheral;
+use model::HardwareModel;
+
+
+const KEY1: u16 = 0xFF4D;
+
+/// The CPU speed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Speed {
+    #[default]
+    /// 4194304Hz, the only speed on DMG.
+    Normal,
+    /// 8388608Hz, CGB only.
+    Double,
+}
+
+impl Speed {
+    /// Whether it's double speed.
+    pub fn is_double(self) -> bool {
+        self == Speed::Double
+    }
+
+    /// The PPU dots, which are also APU channel T-cycles, that pass in `m_cycles` CPU
+    /// M-cycles: 4 each in normal speed and 2 in double speed.
+    pub fn dots(self, m_cycles: u32) -> u32 {
+        match self {
+            Speed::Normal => m_cycles * 4,
+            Speed::Double => m_cycles * 2,
+        }
+    }
+
+    /// The T-cycles the divider counts in `m_cycles` CPU M-cycles.  The divider follows the
+    /// CPU clock, so it's 4 each whatever the speed.
+    pub fn divider_cycles(self, m_cycles: u32) -> u32 {
+        m_cycles * 4
+    }
+}
+
+/// Registers
+///   FF4D   KEY1 - Bit 7 current speed (read only), bit 0 switch armed.  CGB only.
+#[derive(Debug)]
+pub struct SpeedSwitch {
+    model: HardwareModel,
+    speed: Speed,
+    armed: bool,
+}
+
+impl SpeedSwitch {
+    /// Normal speed with no switch armed, as after reset.
+    pub fn new(model: HardwareModel) -> Self {
+        SpeedSwitch { model, speed: Speed::Normal, armed: false }
+    }
+
+    /// The current speed.
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Whether the next STOP switches speed.
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// What STOP does with KEY1: switches speed and disarms the switch if it was armed.
+    /// Returns whether it switched, as STOP only stops the CPU when it didn't.
+    pub fn stop(&mut self) -> bool {
+        if !self.armed {
+            return false;
+        }
+        self.armed = false;
+        self.speed = match self.speed {
+            Speed::Normal => Speed::Double,
+            Speed::Double => Speed::Normal,
+        };
+        true
+    }
+}
+
+impl IoPeripheral for SpeedSwitch {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            KEY1 if self.model.is_cgb() => (self.speed.is_double() as u8) << 7 | 0x7E | self.armed as u8,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address == KEY1 && self.model.is_cgb() {
+            self.armed = value & 0x01 != 0;
+        }
+    }
+}
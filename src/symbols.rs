@@ -0,0 +1,95 @@
+//! Symbol files, as written by rgblink
+//!
+//! Each line is `BB:AAAA Name`, a hex bank and address, or `AAAA Name` for a label that's the
+//! same in every bank.  Anything after a `;` is a comment.  Banks are ROM banks for 0000-7FFF
+//! and RAM banks elsewhere, the same as rgblink numbers them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use error::FaroreError;
+
+
+/// Labels by address, and addresses by label.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: BTreeMap<(Option<usize>, u16), String>,
+    addresses: BTreeMap<String, (Option<usize>, u16)>,
+}
+
+impl SymbolTable {
+    /// Parses a symbol file, failing on the first line that isn't a symbol or a comment.
+    pub fn parse(text: &str) -> Result<Self, FaroreError> {
+        let mut table = SymbolTable::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (bank, address, name) = parse_line(line).ok_or_else(|| {
+                FaroreError::InvalidArgument(format!("line {} isn't a symbol: {}", number + 1, line))
+            })?;
+            table.insert(bank, address, name);
+        }
+        Ok(table)
+    }
+
+    /// Reads and parses a symbol file.
+    pub fn load(path: &Path) -> Result<Self, FaroreError> {
+        let text = fs::read_to_string(path).map_err(|e| FaroreError::io(path, e))?;
+        SymbolTable::parse(&text)
+    }
+
+    /// Adds a label.  When two share an address, the first one added is the one shown.
+    pub fn insert(&mut self, bank: Option<usize>, address: u16, name: &str) {
+        self.names.entry((bank, address)).or_insert_with(|| name.to_string());
+        self.addresses.entry(name.to_string()).or_insert((bank, address));
+    }
+
+    /// The label at `address` in `bank`, or one that isn't tied to a bank.
+    pub fn name(&self, bank: usize, address: u16) -> Option<&str> {
+        self.names.get(&(Some(bank), address))
+            .or_else(|| self.names.get(&(None, address)))
+            .map(String::as_str)
+    }
+
+    /// Where `name` is, with its bank if it has one.
+    pub fn address(&self, name: &str) -> Option<(Option<usize>, u16)> {
+        self.addresses.get(name).cloned()
+    }
+
+    /// How many labels there are.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Option<usize>, u16, &str)> {
+    let mut fields = line.split_whitespace();
+    let (location, name) = (fields.next()?, fields.next()?);
+    if fields.next().is_some() {
+        return None;
+    }
+    let (bank, address) = match location.split_once(':') {
+        Some((bank, address)) => (Some(hex(bank)?), address),
+        None => (None, location),
+    };
+    if address.len() != 4 {
+        return None;
+    }
+    Some((bank, hex(address)? as u16, name))
+}
+
+// Unlike from_str_radix, turns down a leading +.
+fn hex(digits: &str) -> Option<usize> {
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    usize::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,178 @@
+//! Timer and divider
+
+use interrupt::{Interrupt, InterruptLine};
+use io::IoPeripheral;
+
+
+/// Anything else clocked off the internal divider, namely the APU frame sequencer.  Listeners
+/// see every M-cycle step and should do their own falling-edge detection, so that DIV resets
+/// produce the same extra clocks they do on hardware.
+pub trait DividerListener {
+    /// Called with the new value of the 16 bit divider.
+    fn divider_changed(&mut self, divider: u16);
+}
+
+impl DividerListener for () {
+    fn divider_changed(&mut self, _divider: u16) {}
+}
+
+/// Registers
+///   FF04   DIV - Upper byte of the 16 bit internal divider, any write resets it
+///   FF05   TIMA - Timer counter
+///   FF06   TMA - Timer reload value
+///   FF07   TAC - Bit 2 enable, bit 1-0 clock select
+///
+/// TIMA counts the falling edges of a divider bit ANDed with the enable bit.  Anything that
+/// drops that signal counts, including resetting the divider or disabling the timer while the
+/// bit is set.
+///
+/// An overflow leaves TIMA at 0 for one M-cycle before it reloads from TMA and the interrupt
+/// is requested.  Writing TIMA during that delay cancels the reload, while during the reload
+/// cycle itself TIMA writes are ignored and TMA writes go straight through to TIMA.
+pub struct Timer {
+    divider: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+
+    signal: bool, // The selected divider bit ANDed with the enable bit, as last seen
+    reload: Reload,
+
+    // T-cycles left over from the last tick, short of a full M-cycle
+    cycle_remainder: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Reload {
+    Idle,
+    Delay,     // TIMA overflowed and reads 0, the reload happens next M-cycle
+    Reloading, // TIMA was just reloaded from TMA
+}
+
+impl Timer {
+    /// A stopped timer with the divider at 0.
+    pub fn new() -> Self {
+        Timer {
+            divider: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            signal: false,
+            reload: Reload::Idle,
+            cycle_remainder: 0,
+        }
+    }
+
+    /// The full 16 bit internal divider, which the APU frame sequencer also runs off.
+    pub fn divider(&self) -> u16 {
+        self.divider
+    }
+
+    /// Advances the divider by a number of T-cycles, in M-cycle steps.
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptLine) {
+        self.tick_with(cycles, irq, &mut ());
+    }
+
+    /// Like `tick`, passing every divider step on to a listener.
+    pub fn tick_with(&mut self, cycles: u32, irq: &mut InterruptLine, listener: &mut dyn DividerListener) {
+        let mut cycles = cycles + self.cycle_remainder;
+        while cycles >= 4 {
+            cycles -= 4;
+            self.reload = match self.reload {
+                Reload::Delay => {
+                    self.tima = self.tma;
+                    irq.request(Interrupt::Timer);
+                    Reload::Reloading
+                },
+                _ => Reload::Idle,
+            };
+            self.divider = self.divider.wrapping_add(4);
+            self.update_signal();
+            listener.divider_changed(self.divider);
+        }
+        self.cycle_remainder = cycles;
+    }
+
+    /// Like `write`, passing a DIV reset on to a listener so it sees the same falling edge
+    /// TIMA does.
+    pub fn write_with(&mut self, address: u16, value: u8, listener: &mut dyn DividerListener) {
+        match address {
+            0xFF04 => {
+                self.divider = 0;
+                self.cycle_remainder = 0;
+                self.update_signal();
+                listener.divider_changed(self.divider);
+            },
+            _ => self.write(address, value),
+        }
+    }
+
+    // The divider bit TAC selects: 4096Hz, 262144Hz, 65536Hz or 16384Hz.
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0x3 {
+            0 => 1 << 9,
+            1 => 1 << 3,
+            2 => 1 << 5,
+            _ => 1 << 7,
+        }
+    }
+
+    fn update_signal(&mut self) {
+        let signal = self.tac & 0x04 != 0 && self.divider & self.selected_bit() != 0;
+        if self.signal && !signal {
+            self.increment();
+        }
+        self.signal = signal;
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload = Reload::Delay;
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+impl IoPeripheral for Timer {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF04 => (self.divider >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => 0xF8 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF04 => self.write_with(address, value, &mut ()),
+            0xFF05 => match self.reload {
+                Reload::Delay => {
+                    self.reload = Reload::Idle;
+                    self.tima = value;
+                },
+                Reload::Reloading => {},
+                Reload::Idle => self.tima = value,
+            },
+            0xFF06 => {
+                self.tma = value;
+                if self.reload == Reload::Reloading {
+                    self.tima = value;
+                }
+            },
+            0xFF07 => {
+                self.tac = value & 0x07;
+                self.update_signal();
+            },
+            _ => {},
+        }
+    }
+}
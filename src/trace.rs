@@ -0,0 +1,132 @@
+//! What a CPU step did
+//!
+//! Stepping hands back a `StepInfo`, and its Display is the trace line, so the trace logger
+//! and the debugger's step command print instructions the same way.
+
+use std::fmt;
+
+use disasm;
+use interrupt::Interrupt;
+
+
+/// The CPU registers.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Registers {
+    /// The accumulator.
+    pub a: u8,
+    /// The flags, Z N H C in bits 7-4.
+    pub f: u8,
+    /// B.
+    pub b: u8,
+    /// C.
+    pub c: u8,
+    /// D.
+    pub d: u8,
+    /// E.
+    pub e: u8,
+    /// H.
+    pub h: u8,
+    /// L.
+    pub l: u8,
+    /// The stack pointer.
+    pub sp: u16,
+    /// The program counter.
+    pub pc: u16,
+}
+
+impl Registers {
+    // Every register but PC by name, which changes with every step anyway.
+    fn named(&self) -> [(&'static str, u16); 9] {
+        [
+            ("a", self.a as u16),
+            ("f", self.f as u16),
+            ("b", self.b as u16),
+            ("c", self.c as u16),
+            ("d", self.d as u16),
+            ("e", self.e as u16),
+            ("h", self.h as u16),
+            ("l", self.l as u16),
+            ("sp", self.sp),
+        ]
+    }
+}
+
+/// What one step ran: an instruction, or the dispatch of an interrupt in its place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    /// PC before the step.
+    pub pc: u16,
+    /// The opcode and operands, empty for an interrupt dispatch.
+    pub bytes: Vec<u8>,
+    /// The instruction in RGBDS syntax, or the interrupt's name.
+    pub text: String,
+    /// T-cycles the step took.
+    pub cycles: u32,
+    /// Whether a conditional jump, call or return went its way.
+    pub branch_taken: bool,
+    /// The interrupt dispatched instead of running an instruction.
+    pub interrupt: Option<Interrupt>,
+    /// The registers before the step.
+    pub before: Registers,
+    /// The registers after it.
+    pub after: Registers,
+}
+
+impl StepInfo {
+    /// An instruction that ran from `before.pc`.  `bytes` needs at least the whole
+    /// instruction, and anything after it is dropped.
+    pub fn instruction(bytes: &[u8], cycles: u32, branch_taken: bool, before: Registers, after: Registers) -> Self {
+        let instruction = disasm::decode(bytes, before.pc);
+        StepInfo {
+            pc: before.pc,
+            bytes: instruction.bytes,
+            text: instruction.text,
+            cycles,
+            branch_taken,
+            interrupt: None,
+            before,
+            after,
+        }
+    }
+
+    /// An interrupt dispatched when the CPU was about to run the instruction at `before.pc`.
+    pub fn interrupt(interrupt: Interrupt, cycles: u32, before: Registers, after: Registers) -> Self {
+        StepInfo {
+            pc: before.pc,
+            bytes: Vec::new(),
+            text: format!("int ${:02x} {:?}", interrupt.vector(), interrupt),
+            cycles,
+            branch_taken: false,
+            interrupt: Some(interrupt),
+            before,
+            after,
+        }
+    }
+
+    /// The registers other than PC that the step changed, as name, old value and new value.
+    pub fn changes(&self) -> Vec<(&'static str, u16, u16)> {
+        self.before.named().iter().zip(self.after.named().iter())
+            .filter(|(old, new)| old.1 != new.1)
+            .map(|(old, new)| (old.0, old.1, new.1))
+            .collect()
+    }
+}
+
+// The address, bytes and text the way disasm lists them, then the cycles and what changed.
+impl fmt::Display for StepInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex: Vec<String> = self.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        write!(f, "{:04x}  {:<8}  {:<20}  {:>2}", self.pc, hex.join(" "), self.text, self.cycles)?;
+        if self.branch_taken {
+            write!(f, " taken")?;
+        }
+        for (name, old, new) in self.changes() {
+            if name == "sp" {
+                write!(f, "  {} {:04x}->{:04x}", name, old, new)?;
+            } else {
+                write!(f, "  {} {:02x}->{:02x}", name, old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
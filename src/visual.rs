@@ -0,0 +1,86 @@
+//! Checking visual test ROMs by their frames
+//!
+//! ROMs like dmg-acid2 and cgb-acid2 draw a single image and then leave the screen alone, so
+//! there's no result to read back.  A run waits for the frame hash to stop changing and
+//! compares the hash it settled on against the one of the reference image.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use error::FaroreError;
+use ppu::Frame;
+use render::write_png;
+
+
+/// Watches the hashes of consecutive frames for the picture to settle.
+#[derive(Debug, Clone)]
+pub struct StableFrameDetector {
+    repeats: u32,
+    last: Option<u64>,
+    run: u32, // How many frames in a row had `last`
+}
+
+impl StableFrameDetector {
+    /// Calls the picture stable once the same hash has come `repeats` frames in a row.  Fewer
+    /// than 2 is taken as 2, as a single frame can't repeat.
+    pub fn new(repeats: u32) -> Self {
+        StableFrameDetector { repeats: repeats.max(2), last: None, run: 0 }
+    }
+
+    /// Takes the next frame's hash, from `Frame::hash`.  Returns whether the picture is
+    /// stable now.
+    pub fn push(&mut self, hash: u64) -> bool {
+        if self.last == Some(hash) {
+            self.run += 1;
+        } else {
+            self.last = Some(hash);
+            self.run = 1;
+        }
+        self.is_stable()
+    }
+
+    /// Whether the last `repeats` hashes were the same.
+    pub fn is_stable(&self) -> bool {
+        self.run >= self.repeats
+    }
+
+    /// The hash the picture settled on, once it's stable.
+    pub fn stable_hash(&self) -> Option<u64> {
+        if self.is_stable() { self.last } else { None }
+    }
+}
+
+/// A frame that didn't match its reference.
+#[derive(Debug)]
+pub struct FrameMismatch {
+    /// The reference hash.
+    pub expected: u64,
+    /// The frame's hash.
+    pub found: u64,
+    /// Where the frame was saved for eyeballing, or why it couldn't be.
+    pub dump: Result<PathBuf, FaroreError>,
+}
+
+impl fmt::Display for FrameMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame hash {:016x} doesn't match the reference {:016x}", self.found, self.expected)?;
+        match self.dump {
+            Ok(ref path) => write!(f, ", the frame is saved as {}", path.display()),
+            Err(ref e) => write!(f, ", and the frame couldn't be saved: {}", e),
+        }
+    }
+}
+
+impl Error for FrameMismatch {}
+
+/// Compares a frame with the hash of a reference image.  On a mismatch the frame is written
+/// to `dump` as a PNG, so the difference can be looked at.
+pub fn compare_frame(frame: &Frame, expected: u64, dump: &Path) -> Result<(), FrameMismatch> {
+    let found = frame.hash();
+    if found == expected {
+        return Ok(());
+    }
+    let dump = write_png(frame, dump).map(|_| dump.to_path_buf());
+    Err(FrameMismatch { expected, found, dump })
+}
@@ -0,0 +1,126 @@
+//! WAV output for captured audio
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use apu::mixer::SampleCallback;
+use error::FaroreError;
+
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const FRAME_SIZE: u32 = 4;
+/// The most sample data a WAV file can hold, in bytes.  The RIFF size field counts everything
+/// after itself, so the data can only grow this far.
+pub const MAX_DATA_SIZE: u32 = (u32::MAX - (HEADER_SIZE - 8)) / FRAME_SIZE * FRAME_SIZE;
+
+/// Streams 16 bit stereo PCM to a WAV file.  The sizes in the header are written as zero up
+/// front and patched in when the writer is finished or dropped, so nothing is buffered beyond
+/// the file writer itself.
+pub struct WavWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    data_size: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    /// Creates the file and writes a header for 16 bit stereo at `rate` Hz.
+    pub fn create(path: &Path, rate: u32) -> Result<Self, FaroreError> {
+        let file = File::create(path).map_err(|e| FaroreError::io(path, e))?;
+        let mut wav = WavWriter { path: path.to_path_buf(), writer: BufWriter::new(file), data_size: 0, finished: false };
+        wav.write_header(rate).map_err(|e| FaroreError::io(path, e))?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self, rate: u32) -> io::Result<()> {
+        let writer = &mut self.writer;
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?; // Patched on finish
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&(rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes()) // Patched on finish
+    }
+
+    /// Appends stereo frames, clamping each sample to -1.0 to 1.0.  A WAV file can't hold
+    /// more than 4GiB, so once it's full the frames that don't fit are dropped with an error.
+    pub fn write_samples(&mut self, samples: &[(f32, f32)]) -> Result<(), FaroreError> {
+        let room = ((MAX_DATA_SIZE - self.data_size) / FRAME_SIZE) as usize;
+        let (fits, dropped) = samples.split_at(samples.len().min(room));
+        for &(left, right) in fits {
+            let result = self.writer.write_all(&to_pcm(left).to_le_bytes())
+                .and_then(|_| self.writer.write_all(&to_pcm(right).to_le_bytes()));
+            result.map_err(|e| FaroreError::io(&self.path, e))?;
+            self.data_size += FRAME_SIZE;
+        }
+        if !dropped.is_empty() {
+            let full = io::Error::new(io::ErrorKind::FileTooLarge, "a WAV file can't hold more than 4GiB of samples");
+            return Err(FaroreError::io(&self.path, full));
+        }
+        Ok(())
+    }
+
+    /// Counts `bytes` of samples as written without writing them, for testing the size limit
+    /// without a 4GiB file.
+    #[doc(hidden)]
+    pub fn skip_samples(&mut self, bytes: u32) {
+        self.data_size += bytes;
+    }
+
+    /// Fills in the header sizes and flushes the file.
+    pub fn finish(mut self) -> Result<(), FaroreError> {
+        self.finalize().map_err(|e| FaroreError::io(&self.path, e))
+    }
+
+    /// Wraps the writer in an APU sample callback.  The file is finished when the callback
+    /// is dropped.  Write errors are reported once and stop the capture.
+    pub fn into_callback(self) -> SampleCallback {
+        let mut writer = Some(self);
+        Box::new(move |samples| {
+            let failed = match writer.as_mut() {
+                Some(wav) => wav.write_samples(samples).is_err(),
+                None => false,
+            };
+            if failed {
+                error!("Failed writing WAV samples, stopping the capture.");
+                writer = None;
+            }
+        })
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
+
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
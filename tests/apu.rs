@@ -0,0 +1,635 @@
+//! The APU through its registers: channels, the frame sequencer, power and the output stage.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::apu::mixer::{self, Channel, ChannelMask, NATIVE_RATE};
+use farore::apu::log::WriteLogFormat;
+use farore::apu::{Apu, AudioConfig};
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+
+
+const NR52: u16 = 0xFF26;
+
+fn powered(model: HardwareModel) -> Apu {
+    let mut apu = Apu::new(model);
+    apu.write(NR52, 0x80);
+    apu
+}
+
+// One falling edge of DIV bit 12, one frame sequencer step.
+fn step(apu: &mut Apu) {
+    apu.update_divider(0x1000);
+    apu.update_divider(0x0000);
+}
+
+fn steps(apu: &mut Apu, count: usize) {
+    for _ in 0..count {
+        step(apu);
+    }
+}
+
+// The NR52 channel status bits.
+fn status(apu: &Apu) -> u8 {
+    apu.read(NR52) & 0x0F
+}
+
+// The DAC input a channel (0-3) is producing, recovered from the mixer with only that channel
+// panned in at full volume.  Only meaningful while the channel's DAC is on.
+fn dac_input(apu: &mut Apu, channel: u8) -> u8 {
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF25, 0x11 << channel);
+    let (left, _) = apu.mix();
+    ((1.0 - left * 4.0) * 7.5).round() as u8
+}
+
+// Pulse 2 at 75% duty and the highest frequency, stepped onto the high part of the wave so its
+// output is the envelope volume.
+fn play_pulse2(apu: &mut Apu, nr22: u8) {
+    apu.write(0xFF16, 0xC0);
+    apu.write(0xFF17, nr22);
+    apu.write(0xFF18, 0xFF);
+    apu.write(0xFF19, 0x87);
+    apu.tick(4);
+}
+
+// Samples 0-15 twice over, two to a byte.
+fn load_ramp(apu: &mut Apu) {
+    for i in 0..16u16 {
+        let high = (i as u8 * 2) & 0xF;
+        apu.write(0xFF30 + i, high << 4 | (high + 1));
+    }
+}
+
+// The wave channel at full volume, one sample every 512 T-cycles.
+fn play_wave(apu: &mut Apu) {
+    apu.write(0xFF1A, 0x80);
+    apu.write(0xFF1C, 0x20);
+    apu.write(0xFF1D, 0x00);
+    apu.write(0xFF1E, 0x87);
+}
+
+#[test]
+fn registers_read_back_through_their_masks() {
+    let masks: [(u16, u8); 23] = [
+        (0xFF10, 0x80), (0xFF11, 0x3F), (0xFF12, 0x00), (0xFF13, 0xFF), (0xFF14, 0xBF),
+        (0xFF15, 0xFF), (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+        (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF),
+        (0xFF1F, 0xFF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+        (0xFF24, 0x00), (0xFF25, 0x00), (0xFF27, 0xFF),
+    ];
+    for &model in &[HardwareModel::Dmg, HardwareModel::Cgb] {
+        let mut apu = powered(model);
+        for &(address, mask) in &masks {
+            apu.write(address, 0x00);
+            assert_eq!(apu.read(address), mask, "{:04X} after writing 00", address);
+            apu.write(address, 0xFF);
+            assert_eq!(apu.read(address), 0xFF, "{:04X} after writing FF", address);
+        }
+        for address in 0xFF27..0xFF30 {
+            assert_eq!(apu.read(address), 0xFF, "{:04X}", address);
+        }
+    }
+}
+
+#[test]
+fn nr52_shows_power_and_channel_status() {
+    let mut apu = Apu::default();
+    assert_eq!(apu.read(NR52), 0x70);
+    apu.write(NR52, 0xFF);
+    assert_eq!(apu.read(NR52), 0xF0);
+
+    play_pulse2(&mut apu, 0xF0);
+    assert_eq!(apu.read(NR52), 0xF2);
+    apu.write(0xFF12, 0xF0);
+    apu.write(0xFF14, 0x80);
+    assert_eq!(apu.read(NR52), 0xF3);
+
+    apu.write(NR52, 0x00);
+    assert_eq!(apu.read(NR52), 0x70);
+}
+
+#[test]
+fn power_off_clears_registers_and_ignores_writes() {
+    for &model in &[HardwareModel::Dmg, HardwareModel::Cgb] {
+        let mut apu = powered(model);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0xF3);
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF1C, 0x40);
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF25), 0x00);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(0xFF1C), 0x9F);
+
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF11, 0xC0);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(0xFF11), 0x3F);
+
+        apu.write(NR52, 0x80);
+        apu.write(0xFF24, 0x77);
+        assert_eq!(apu.read(0xFF24), 0x77);
+    }
+}
+
+#[test]
+fn wave_ram_survives_power_off() {
+    let mut apu = powered(HardwareModel::Dmg);
+    load_ramp(&mut apu);
+    apu.write(NR52, 0x00);
+    assert_eq!(apu.read(0xFF30), 0x01);
+    apu.write(0xFF3F, 0x5A);
+    apu.write(NR52, 0x80);
+    assert_eq!(apu.read(0xFF3F), 0x5A);
+}
+
+// Pulse 2 triggered with length enabled and whatever is left in its length counter.  A single
+// length clock ends it if the count of 1 survived power-off; a cleared counter reloads to 64.
+fn length_survives_power_off(model: HardwareModel, load_while_off: bool) -> bool {
+    let mut apu = powered(model);
+    if !load_while_off {
+        apu.write(0xFF16, 0x3F);
+    }
+    apu.write(NR52, 0x00);
+    if load_while_off {
+        apu.write(0xFF16, 0x3F);
+    }
+    apu.write(NR52, 0x80);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF19, 0xC0);
+    assert_eq!(status(&apu), 0x02);
+    step(&mut apu);
+    status(&apu) == 0x00
+}
+
+#[test]
+fn length_counters_survive_power_off_on_dmg_only() {
+    assert!(length_survives_power_off(HardwareModel::Dmg, false));
+    assert!(!length_survives_power_off(HardwareModel::Cgb, false));
+}
+
+#[test]
+fn length_writes_while_off_go_through_on_dmg_only() {
+    assert!(length_survives_power_off(HardwareModel::Dmg, true));
+    assert!(!length_survives_power_off(HardwareModel::Cgb, true));
+}
+
+#[test]
+fn length_expiry_clears_the_status_bit() {
+    let mut apu = powered(HardwareModel::Dmg);
+    apu.write(0xFF16, 0x3C);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF19, 0xC0);
+
+    // 4 left, clocked on every other step
+    steps(&mut apu, 6);
+    assert_eq!(status(&apu), 0x02);
+    steps(&mut apu, 1);
+    assert_eq!(status(&apu), 0x00);
+}
+
+#[test]
+fn enabling_length_in_the_first_half_clocks_it_once_extra() {
+    let mut apu = powered(HardwareModel::Dmg);
+    apu.write(0xFF16, 0x3F);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF19, 0x80);
+    apu.write(0xFF19, 0x40);
+    assert_eq!(status(&apu), 0x02);
+
+    let mut apu = powered(HardwareModel::Dmg);
+    apu.write(0xFF16, 0x3F);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF19, 0x80);
+    step(&mut apu);
+    apu.write(0xFF19, 0x40);
+    assert_eq!(status(&apu), 0x00);
+}
+
+#[test]
+fn dac_off_disables_the_channel() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse2(&mut apu, 0xF0);
+    assert_eq!(status(&apu), 0x02);
+    apu.write(0xFF17, 0x07);
+    assert_eq!(status(&apu), 0x00);
+
+    apu.write(0xFF19, 0x80);
+    assert_eq!(status(&apu), 0x00);
+}
+
+// Pulse 1 triggered at a frequency, with NR10 already set.
+fn play_pulse1(apu: &mut Apu, nr10: u8, frequency: u16) {
+    apu.write(0xFF10, nr10);
+    apu.write(0xFF11, 0x80);
+    apu.write(0xFF12, 0xF0);
+    apu.write(0xFF13, frequency as u8);
+    apu.write(0xFF14, 0x80 | (frequency >> 8) as u8);
+}
+
+#[test]
+fn sweep_overflow_on_trigger_disables_the_channel() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse1(&mut apu, 0x11, 0x7FF);
+    assert_eq!(status(&apu), 0x00);
+
+    // A shift of 0 skips the check on trigger
+    play_pulse1(&mut apu, 0x10, 0x7FF);
+    assert_eq!(status(&apu), 0x01);
+}
+
+#[test]
+fn sweep_overflow_after_a_clock_disables_the_channel() {
+    let mut apu = powered(HardwareModel::Dmg);
+
+    // 0x500 + 0x280 fits, but the check after storing it, 0x780 + 0x3C0, doesn't
+    play_pulse1(&mut apu, 0x11, 0x500);
+    steps(&mut apu, 2);
+    assert_eq!(status(&apu), 0x01);
+    step(&mut apu);
+    assert_eq!(status(&apu), 0x00);
+}
+
+#[test]
+fn leaving_negate_mode_after_a_negated_calculation_disables_the_channel() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse1(&mut apu, 0x19, 0x400);
+    assert_eq!(status(&apu), 0x01);
+    apu.write(0xFF10, 0x11);
+    assert_eq!(status(&apu), 0x00);
+
+    // Without a calculation since the trigger it's fine
+    play_pulse1(&mut apu, 0x18, 0x400);
+    apu.write(0xFF10, 0x10);
+    assert_eq!(status(&apu), 0x01);
+}
+
+#[test]
+fn pulse2_duty_cycles_are_12_25_50_and_75_percent() {
+    for &(duty, high) in &[(0x00, 1), (0x40, 2), (0x80, 4), (0xC0, 6)] {
+        let mut apu = powered(HardwareModel::Dmg);
+        play_pulse2(&mut apu, 0xF0);
+        apu.write(0xFF16, duty);
+
+        // One duty step every 4 T-cycles at this frequency
+        let mut steps_high = 0;
+        for _ in 0..8 {
+            if dac_input(&mut apu, 1) == 15 {
+                steps_high += 1;
+            }
+            apu.tick(4);
+        }
+        assert_eq!(steps_high, high, "NR21 {:02X}", duty);
+    }
+}
+
+#[test]
+fn envelope_steps_at_64hz() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse2(&mut apu, 0xF1);
+    assert_eq!(dac_input(&mut apu, 1), 15);
+    steps(&mut apu, 7);
+    assert_eq!(dac_input(&mut apu, 1), 15);
+    step(&mut apu);
+    assert_eq!(dac_input(&mut apu, 1), 14);
+    steps(&mut apu, 8 * 13);
+    assert_eq!(dac_input(&mut apu, 1), 1);
+    steps(&mut apu, 8 * 5);
+    assert_eq!(dac_input(&mut apu, 1), 0);
+
+    play_pulse2(&mut apu, 0x3A);
+    assert_eq!(dac_input(&mut apu, 1), 3);
+    steps(&mut apu, 8 * 2);
+    assert_eq!(dac_input(&mut apu, 1), 4);
+}
+
+#[test]
+fn zombie_mode_nudges_the_volume_on_dmg() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse2(&mut apu, 0x80);
+    assert_eq!(dac_input(&mut apu, 1), 8);
+
+    // Period 0, same direction: add 1
+    apu.write(0xFF17, 0x80);
+    assert_eq!(dac_input(&mut apu, 1), 9);
+    apu.write(0xFF17, 0x80);
+    assert_eq!(dac_input(&mut apu, 1), 10);
+
+    // Direction change: 16 - volume
+    apu.write(0xFF17, 0x88);
+    assert_eq!(dac_input(&mut apu, 1), 6);
+
+    // A running period with the direction unchanged leaves it be
+    apu.write(0xFF17, 0x89);
+    assert_eq!(dac_input(&mut apu, 1), 7);
+    apu.write(0xFF17, 0x89);
+    assert_eq!(dac_input(&mut apu, 1), 7);
+}
+
+#[test]
+fn zombie_mode_leaves_the_volume_alone_on_cgb() {
+    let mut apu = powered(HardwareModel::Cgb);
+    play_pulse2(&mut apu, 0x80);
+    apu.write(0xFF17, 0x80);
+    apu.write(0xFF17, 0x88);
+    assert_eq!(dac_input(&mut apu, 1), 8);
+}
+
+#[test]
+fn wave_plays_wave_ram_through_the_volume_shift() {
+    let mut apu = powered(HardwareModel::Dmg);
+    load_ramp(&mut apu);
+    play_wave(&mut apu);
+
+    // The sample buffer isn't refilled on trigger, playback starts at sample 1
+    for sample in 1..32u8 {
+        apu.tick(512);
+        assert_eq!(dac_input(&mut apu, 2), sample & 0xF, "sample {}", sample);
+    }
+
+    apu.tick(512 * 6);
+    assert_eq!(dac_input(&mut apu, 2), 5);
+    for &(nr32, shift) in &[(0x40, 1), (0x60, 2)] {
+        apu.write(0xFF1C, nr32);
+        assert_eq!(dac_input(&mut apu, 2), 5 >> shift);
+    }
+    apu.write(0xFF1C, 0x00);
+    assert_eq!(dac_input(&mut apu, 2), 0);
+}
+
+#[test]
+fn dmg_wave_ram_is_only_reachable_while_the_channel_reads_it() {
+    let mut apu = powered(HardwareModel::Dmg);
+    load_ramp(&mut apu);
+    play_wave(&mut apu);
+    apu.tick(512);
+
+    // In the window, any address reaches the byte being played
+    assert_eq!(apu.read(0xFF3A), 0x01);
+    apu.write(0xFF3A, 0x9F);
+    apu.tick(4);
+    assert_eq!(apu.read(0xFF30), 0xFF);
+    apu.write(0xFF31, 0x77);
+
+    apu.write(0xFF1A, 0x00);
+    assert_eq!(apu.read(0xFF30), 0x9F);
+    assert_eq!(apu.read(0xFF31), 0x23);
+    assert_eq!(apu.read(0xFF3A), 0x45);
+}
+
+#[test]
+fn cgb_wave_ram_always_reaches_the_byte_being_played() {
+    let mut apu = powered(HardwareModel::Cgb);
+    load_ramp(&mut apu);
+    play_wave(&mut apu);
+    apu.tick(512 * 2);
+    apu.tick(100);
+    assert_eq!(apu.read(0xFF3A), 0x23);
+    apu.write(0xFF3F, 0xAB);
+
+    apu.write(0xFF1A, 0x00);
+    assert_eq!(apu.read(0xFF31), 0xAB);
+    assert_eq!(apu.read(0xFF3F), 0xEF);
+}
+
+// The documented LFSR, one output level per clock.
+fn reference_noise(seven_bit: bool, clocks: usize) -> Vec<u8> {
+    let mut lfsr: u16 = 0x7FFF;
+    let mut levels = Vec::new();
+    for _ in 0..clocks {
+        let feedback = (lfsr ^ (lfsr >> 1)) & 0x1;
+        lfsr = (lfsr >> 1) | (feedback << 14);
+        if seven_bit {
+            lfsr = (lfsr & !0x40) | (feedback << 6);
+        }
+        levels.push(if lfsr & 0x1 == 0 { 15 } else { 0 });
+    }
+    levels
+}
+
+// Noise at full volume, sampled once every `period` T-cycles.
+fn noise_levels(nr43: u8, period: u32, samples: usize) -> Vec<u8> {
+    let mut apu = powered(HardwareModel::Dmg);
+    apu.write(0xFF21, 0xF0);
+    apu.write(0xFF22, nr43);
+    apu.write(0xFF23, 0x80);
+    (0..samples).map(|_| {
+        apu.tick(period);
+        dac_input(&mut apu, 3)
+    }).collect()
+}
+
+#[test]
+fn noise_follows_the_15_bit_lfsr() {
+    let levels = noise_levels(0x00, 8, 400);
+    assert_eq!(levels, reference_noise(false, 400));
+    assert!((20..147).any(|i| levels[i] != levels[i + 127]));
+}
+
+#[test]
+fn noise_follows_the_7_bit_lfsr() {
+    let levels = noise_levels(0x08, 8, 400);
+    assert_eq!(levels, reference_noise(true, 400));
+    assert!((20..273).all(|i| levels[i] == levels[i + 127]));
+}
+
+#[test]
+fn noise_divisor_code_0_counts_as_half() {
+    // Code 0 clocks every 8 T-cycles, code 1 every 16, shift 2 every 64
+    assert_eq!(noise_levels(0x01, 16, 200), reference_noise(false, 200));
+    assert_eq!(noise_levels(0x21, 64, 100), reference_noise(false, 100));
+
+    // Sampled every 8 T-cycles, code 1 holds each level twice as long
+    let levels = noise_levels(0x01, 8, 200);
+    let reference = reference_noise(false, 100);
+    assert_eq!(levels[0], 0);
+    for k in 0..99 {
+        assert_eq!((levels[2 * k + 1], levels[2 * k + 2]), (reference[k], reference[k]));
+    }
+}
+
+#[test]
+fn dac_levels_span_plus_to_minus_one() {
+    assert_eq!(mixer::dac_output(true, 0), 1.0);
+    assert_eq!(mixer::dac_output(true, 15), -1.0);
+    assert_eq!(mixer::dac_output(false, 15), 0.0);
+}
+
+#[test]
+fn mix_pans_and_scales_each_side() {
+    let levels = [1.0, -1.0, 0.5, 0.25];
+    assert_eq!(mixer::mix(levels, 0x77, 0x00), (0.0, 0.0));
+    assert_eq!(mixer::mix(levels, 0x77, 0x10), (0.25, 0.0));
+    assert_eq!(mixer::mix(levels, 0x77, 0x0C), (0.0, 0.1875));
+    assert_eq!(mixer::mix(levels, 0x70, 0x11), (0.25, 0.03125));
+    assert_eq!(mixer::mix(levels, 0x37, 0xF0), (0.09375, 0.0));
+
+    // Vin bits mix in as silence
+    assert_eq!(mixer::mix(levels, 0xFF, 0x11), mixer::mix(levels, 0x77, 0x11));
+}
+
+#[test]
+fn muting_only_affects_the_mixer() {
+    let mut apu = powered(HardwareModel::Dmg);
+    play_pulse2(&mut apu, 0xF0);
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF25, 0x22);
+    assert!(apu.mix().0 < 0.0);
+
+    apu.set_channel_mask(ChannelMask::parse_channels("1,2").unwrap().inverted());
+    assert_eq!(apu.mix(), (0.0, 0.0));
+    assert_eq!(status(&apu), 0x02);
+
+    apu.solo(Channel::Pulse2);
+    assert!(apu.mix().0 < 0.0);
+    apu.solo(Channel::Wave);
+    assert_eq!(apu.mix(), (0.0, 0.0));
+
+    assert!(ChannelMask::parse_channels("1,5").is_err());
+}
+
+// Collects everything the sample callback is handed.
+fn capture(apu: &mut Apu, rate: u32) -> Rc<RefCell<Vec<(f32, f32)>>> {
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    let sink = samples.clone();
+    apu.set_sample_callback(rate, Box::new(move |chunk: &[(f32, f32)]| {
+        sink.borrow_mut().extend_from_slice(chunk);
+    }));
+    samples
+}
+
+#[test]
+fn resampler_keeps_the_square_wave_period_and_panning() {
+    let mut apu = Apu::with_config(HardwareModel::Dmg, AudioConfig { high_pass: false });
+    let samples = capture(&mut apu, 48_000);
+    apu.write(NR52, 0x80);
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF25, 0x20);
+
+    // 2048 - 0x780 = 128, times 4 T-cycles per step and 8 steps: 1024Hz
+    apu.write(0xFF16, 0x80);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF18, 0x80);
+    apu.write(0xFF19, 0x87);
+    apu.tick(4_194_304 / 4);
+    apu.flush_samples();
+
+    let samples = samples.borrow();
+    assert_eq!(samples.len(), 12_000);
+    assert!(samples.iter().all(|&(_, right)| right == 0.0));
+    let rising = samples.windows(2).filter(|pair| pair[0].0 >= 0.0 && pair[1].0 < 0.0).count();
+    assert!((255..=257).contains(&rising), "{} periods", rising);
+}
+
+#[test]
+fn samples_arrive_in_whole_chunks_until_flushed() {
+    let mut apu = Apu::default();
+    let chunks = Rc::new(RefCell::new(Vec::new()));
+    let sink = chunks.clone();
+    apu.set_sample_callback(NATIVE_RATE, Box::new(move |chunk: &[(f32, f32)]| {
+        sink.borrow_mut().push(chunk.len());
+    }));
+    apu.tick(4 * (mixer::CHUNK_FRAMES as u32 * 2 + 10));
+    assert_eq!(*chunks.borrow(), [mixer::CHUNK_FRAMES, mixer::CHUNK_FRAMES]);
+    apu.flush_samples();
+    assert_eq!(*chunks.borrow(), [mixer::CHUNK_FRAMES, mixer::CHUNK_FRAMES, 10]);
+}
+
+// Pulse 1's DAC switched on but the channel never triggered: a constant level of 1.0 panned
+// left, a quarter of the mix.
+fn dc_step(config: AudioConfig) -> Vec<(f32, f32)> {
+    let mut apu = Apu::with_config(HardwareModel::Dmg, config);
+    let samples = capture(&mut apu, 48_000);
+    apu.write(NR52, 0x80);
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF25, 0x10);
+    apu.write(0xFF12, 0xF0);
+    apu.tick(4_194_304 / 10);
+    apu.flush_samples();
+    let samples = samples.borrow().clone();
+    samples
+}
+
+#[test]
+fn high_pass_decays_a_step_with_the_dmg_time_constant() {
+    let samples = dc_step(AudioConfig::default());
+    let factor = 0.999958f64.powf(4_194_304.0 / 48_000.0);
+    assert!((samples[0].0 - 0.25).abs() < 1e-6);
+    for pair in samples.windows(2).take(1000) {
+        let ratio = pair[1].0 as f64 / pair[0].0 as f64;
+        assert!((ratio - factor).abs() < 1e-4, "ratio {}", ratio);
+    }
+    let last = samples.last().unwrap().0 as f64;
+    assert!((last - 0.25 * factor.powi(samples.len() as i32 - 1)).abs() < 1e-3);
+    assert!(samples.iter().all(|&(_, right)| right == 0.0));
+}
+
+#[test]
+fn high_pass_off_keeps_the_raw_level() {
+    let samples = dc_step(AudioConfig { high_pass: false });
+    assert!(!samples.is_empty());
+    assert!(samples.iter().all(|&frame| frame == (0.25, 0.0)));
+}
+
+fn csv(apu: &Apu) -> String {
+    let mut out = Vec::new();
+    apu.export_write_log(WriteLogFormat::Csv, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn write_log_records_applied_writes_with_timestamps() {
+    let mut apu = powered(HardwareModel::Dmg);
+    apu.tick(100);
+    apu.enable_write_log();
+    apu.tick(20);
+    apu.write(0xFF24, 0x77);
+    apu.tick(8);
+    apu.write(0xFF30, 0x12);
+
+    let log = apu.take_write_log().unwrap();
+    assert_eq!(log.writes.len(), 1 + 0x2F + 2);
+    assert_eq!((log.writes[0].address, log.writes[0].value), (0xFF26, 0x80));
+    assert!(log.writes[..0x30].iter().all(|write| write.cycle == 100));
+    let tail: Vec<(u64, u16, u8)> = log.writes[0x30..].iter().map(|write| (write.cycle, write.address, write.value)).collect();
+    assert_eq!(tail, [(120, 0xFF24, 0x77), (128, 0xFF30, 0x12)]);
+}
+
+#[test]
+fn write_log_skips_writes_the_power_gate_drops() {
+    let mut apu = Apu::default();
+    apu.enable_write_log();
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF12, 0xF0);
+    apu.write(0xFF11, 0xBF);
+    apu.write(0xFF3F, 0x5A);
+    apu.write(0xFF27, 0x01);
+
+    let rows = csv(&apu);
+    let writes: Vec<&str> = rows.lines().skip(1 + 0x30).collect();
+    assert_eq!(writes, ["0,FF11,BF", "0,FF3F,5A"]);
+}
+
+#[test]
+fn write_log_starts_from_a_cleared_state_after_power_off() {
+    let mut apu = powered(HardwareModel::Dmg);
+    load_ramp(&mut apu);
+    apu.write(0xFF24, 0x77);
+    apu.write(0xFF12, 0xF3);
+    apu.write(NR52, 0x00);
+    apu.enable_write_log();
+
+    let log = apu.take_write_log().unwrap();
+    let value = |address: u16| log.writes.iter().find(|write| write.address == address).unwrap().value;
+    assert_eq!(value(0xFF26), 0x00);
+    assert_eq!(value(0xFF24), 0x00);
+    assert_eq!(value(0xFF12), 0x00);
+    assert_eq!(value(0xFF30), 0x01);
+}
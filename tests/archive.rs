@@ -0,0 +1,293 @@
+//! Unpacking ROMs from gzip and zip files, built here byte by byte or taken from the corpus.
+
+extern crate farore;
+
+use std::fs;
+use std::path::Path;
+
+use farore::archive::{self, ArchiveKind, MAX_UNPACKED_SIZE};
+use farore::crc::crc32;
+use farore::error::FaroreError;
+
+
+fn corpus(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join("archive").join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e))
+}
+
+// Something ROM sized that doesn't repeat too obviously.
+fn rom(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31) ^ (i >> 8) as u8 ^ seed).collect()
+}
+
+// DEFLATE data of stored blocks only.
+fn stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(chunks.peek().is_none() as u8);
+        out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+// Writes Huffman codes most significant bit first into DEFLATE's least significant first stream.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        for i in 0..n {
+            if self.count.is_multiple_of(8) {
+                self.out.push(0);
+            }
+            *self.out.last_mut().unwrap() |= ((value >> i & 1) as u8) << (self.count % 8);
+            self.count += 1;
+        }
+    }
+
+    fn code(&mut self, code: u32, n: u32) {
+        self.bits(code.reverse_bits() >> (32 - n), n);
+    }
+}
+
+// A fixed Huffman block of `byte` repeated `1 + 258 * runs` times: a literal, then copies of
+// the longest length from one byte back.
+fn fixed_run(byte: u8, runs: usize) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+    match byte {
+        0..=143 => writer.code(0x30 + byte as u32, 8),
+        _ => writer.code(0x190 + byte as u32 - 144, 9),
+    }
+    for _ in 0..runs {
+        writer.code(0xC0 + 285 - 280, 8);
+        writer.code(0, 5);
+    }
+    writer.code(0, 7);
+    writer.out
+}
+
+fn gzip(name: Option<&str>, contents: &[u8], deflated: &[u8]) -> Vec<u8> {
+    let mut file = vec![0x1F, 0x8B, 0x08, if name.is_some() { 0x08 } else { 0x00 }, 0, 0, 0, 0, 0x00, 0xFF];
+    if let Some(name) = name {
+        file.extend_from_slice(name.as_bytes());
+        file.push(0);
+    }
+    file.extend_from_slice(deflated);
+    file.extend_from_slice(&crc32(contents).to_le_bytes());
+    file.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    file
+}
+
+// A zip of (name, contents, method) entries, method 8 being stored blocks of DEFLATE.
+fn zip(entries: &[(&str, &[u8], u16)]) -> Vec<u8> {
+    let mut file = Vec::new();
+    let mut directory = Vec::new();
+    for &(name, contents, method) in entries {
+        let data = if method == 8 { stored(contents) } else { contents.to_vec() };
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&[0; 4]);
+        common.extend_from_slice(&crc32(contents).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        directory.extend_from_slice(&0x0201_4B50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&common);
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&(file.len() as u32).to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+
+        file.extend_from_slice(&0x0403_4B50u32.to_le_bytes());
+        file.extend_from_slice(&common);
+        file.extend_from_slice(name.as_bytes());
+        file.extend_from_slice(&data);
+    }
+    let directory_offset = file.len() as u32;
+    file.extend_from_slice(&directory);
+    file.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    file.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    file.extend_from_slice(&directory_offset.to_le_bytes());
+    file.extend_from_slice(&[0; 2]);
+    file
+}
+
+// The archive's own complaint, under the FaroreError wrapping it.
+fn reason(result: Result<archive::Unpacked, FaroreError>) -> String {
+    match result {
+        Err(FaroreError::Archive(error)) => error.to_string(),
+        Err(other) => panic!("{:?}", other),
+        Ok(_) => panic!("unpacked"),
+    }
+}
+
+#[test]
+fn sniffs_by_magic() {
+    assert_eq!(archive::sniff(&corpus("gzip")), Some(ArchiveKind::Gzip));
+    assert_eq!(archive::sniff(&corpus("zip-deflated")), Some(ArchiveKind::Zip));
+    assert_eq!(archive::sniff(&rom(0, 0x8000)), None);
+    assert_eq!(archive::sniff(&[0x1F]), None);
+}
+
+#[test]
+fn inflates_stored_blocks() {
+    for &len in &[0, 1, 0xFFFF, 0x10000, 0x8000 * 4 + 7] {
+        let contents = rom(1, len);
+        let deflated = stored(&contents);
+        let (inflated, used) = archive::inflate(&deflated, MAX_UNPACKED_SIZE).unwrap();
+        assert_eq!(inflated, contents, "{} bytes", len);
+        assert_eq!(used, deflated.len());
+    }
+}
+
+#[test]
+fn inflates_fixed_huffman_runs() {
+    for &byte in &[0x00, 0x8F, 0x90, 0xFF] {
+        let (inflated, _) = archive::inflate(&fixed_run(byte, 100), MAX_UNPACKED_SIZE).unwrap();
+        assert_eq!(inflated, vec![byte; 1 + 258 * 100]);
+    }
+}
+
+#[test]
+fn inflating_stops_at_the_limit() {
+    let bomb = fixed_run(0, 1000);
+    assert!(bomb.len() * 100 < 258 * 1000);
+    assert_eq!(archive::inflate(&bomb, 258 * 1000 + 1).unwrap().0.len(), 258 * 1000 + 1);
+    assert!(archive::inflate(&bomb, 258 * 1000).is_err());
+    assert!(archive::inflate(&stored(&rom(0, 1000)), 999).is_err());
+}
+
+#[test]
+fn deflate_round_trips() {
+    let mut mixed = rom(2, 40_000);
+    mixed.extend(vec![0; 5000]);
+    mixed.extend(rom(2, 40_000)); // Repeats from further back than the window reaches
+    mixed.extend((0..=255).collect::<Vec<u8>>());
+    for contents in &[Vec::new(), vec![0x42], vec![0; 100_000], rom(3, 70_000), mixed] {
+        let deflated = archive::deflate(contents);
+        let (inflated, used) = archive::inflate(&deflated, MAX_UNPACKED_SIZE).unwrap();
+        assert_eq!(&inflated, contents, "{} bytes", contents.len());
+        assert_eq!(used, deflated.len());
+    }
+    assert!(archive::deflate(&[0; 100_000]).len() < 1000);
+}
+
+#[test]
+fn gzip_round_trips() {
+    let contents = rom(2, 0x8000);
+    let unpacked = archive::unpack(&gzip(Some("game.gb"), &contents, &stored(&contents)), None).unwrap();
+    assert_eq!(unpacked.name.as_deref(), Some("game.gb"));
+    assert_eq!(unpacked.data, contents);
+
+    let file = gzip(None, &contents, &stored(&contents));
+    let unpacked = archive::unpack(&file, None).unwrap();
+    assert_eq!(unpacked.name, None);
+    assert_eq!(unpacked.data, contents);
+    let entries = archive::list(&file).unwrap();
+    assert_eq!((entries[0].name.as_str(), entries[0].size), ("", 0x8000));
+
+    let runs = vec![0xFF; 1 + 258 * 4];
+    assert_eq!(archive::unpack(&gzip(None, &runs, &fixed_run(0xFF, 4)), None).unwrap().data, runs);
+}
+
+#[test]
+fn gzip_checks_its_crc() {
+    let contents = rom(3, 0x100);
+    let mut file = gzip(None, &contents, &stored(&contents));
+    let crc = file.len() - 8;
+    file[crc] ^= 0x01;
+    assert_eq!(reason(archive::unpack(&file, None)), "the gzip file fails its CRC check");
+}
+
+#[test]
+fn reads_the_corpus_gzip() {
+    let unpacked = archive::unpack(&corpus("gzip"), None).unwrap();
+    assert_eq!(unpacked.data.len(), 0x8000);
+    assert_eq!(crc32(&unpacked.data), 0x9EFD_A772);
+    assert_eq!(&unpacked.data[0x134..0x138], b"TEST");
+}
+
+#[test]
+fn zip_round_trips() {
+    let (first, second) = (rom(4, 0x8000), rom(5, 0x10000));
+    let file = zip(&[("first.gb", &first, 0), ("second.gbc", &second, 8), ("readme.txt", b"hello", 0)]);
+    let names: Vec<(String, usize, bool)> = archive::list(&file).unwrap().into_iter()
+        .map(|entry| (entry.name.clone(), entry.size, entry.is_rom()))
+        .collect();
+    assert_eq!(names, [("first.gb".to_string(), 0x8000, true), ("second.gbc".to_string(), 0x10000, true),
+                       ("readme.txt".to_string(), 5, false)]);
+
+    assert_eq!(archive::unpack(&file, Some("first.gb")).unwrap().data, first);
+    let unpacked = archive::unpack(&file, Some("second.gbc")).unwrap();
+    assert_eq!(unpacked.name.as_deref(), Some("second.gbc"));
+    assert_eq!(unpacked.data, second);
+    assert_eq!(archive::unpack(&file, Some("readme.txt")).unwrap().data, b"hello");
+}
+
+#[test]
+fn zip_picks_the_only_rom() {
+    let contents = rom(6, 0x8000);
+    let file = zip(&[("notes.txt", b"hi", 0), ("Game.GB", &contents, 8)]);
+    assert_eq!(archive::unpack(&file, None).unwrap().data, contents);
+
+    // A lone file is taken whatever its name
+    let file = zip(&[("game.bin", &contents, 0)]);
+    assert_eq!(archive::unpack(&file, None).unwrap().data, contents);
+}
+
+#[test]
+fn zip_asks_when_it_cannot_choose() {
+    let contents = rom(7, 0x100);
+    let two_roms = zip(&[("a.gb", &contents, 0), ("b.gbc", &contents, 0)]);
+    assert_eq!(reason(archive::unpack(&two_roms, None)), "the archive has several roms, pick one with --entry: a.gb, b.gbc");
+    assert_eq!(reason(archive::unpack(&two_roms, Some("c.gb"))), "no entry named c.gb in the archive");
+
+    let no_roms = zip(&[("a.txt", &contents, 0), ("b.txt", &contents, 0)]);
+    assert!(reason(archive::unpack(&no_roms, None)).contains("no .gb or .gbc files"));
+}
+
+#[test]
+fn zip_checks_entry_crcs() {
+    let contents = rom(8, 0x100);
+    let mut file = zip(&[("game.gb", &contents, 0)]);
+    file[30 + "game.gb".len() + 0x10] ^= 0xFF;
+    assert_eq!(reason(archive::unpack(&file, None)), "game.gb fails its CRC check");
+}
+
+#[test]
+fn reads_the_corpus_zips() {
+    let file = corpus("zip-two-roms");
+    let names: Vec<String> = archive::list(&file).unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, ["first.gb", "second.gbc", "readme.txt"]);
+    assert_eq!(crc32(&archive::unpack(&file, Some("first.gb")).unwrap().data), 0x9EFD_A772);
+    assert_eq!(crc32(&archive::unpack(&file, Some("second.gbc")).unwrap().data), 0x0161_0A65);
+
+    let unpacked = archive::unpack(&corpus("zip-deflated"), None).unwrap();
+    assert_eq!(unpacked.name.as_deref(), Some("game.gb"));
+    assert_eq!(crc32(&unpacked.data), 0x82C6_C5B4);
+}
+
+#[test]
+fn the_corpus_zip_bomb_is_refused() {
+    let bomb = corpus("zip-bomb");
+    assert_eq!(archive::list(&bomb).unwrap()[0].size, 20 * 1024 * 1024);
+    assert_eq!(reason(archive::unpack(&bomb, None)), format!("the rom unpacks to more than {} bytes", MAX_UNPACKED_SIZE));
+}
@@ -0,0 +1,102 @@
+//! Benchmark reports timed against a made up clock.
+
+extern crate farore;
+
+use std::time::Duration;
+
+use farore::bench::{BenchReport, Benchmark, DEFAULT_WARMUP_FRAMES};
+use farore::pacing::FRAME_TIME;
+
+
+const CYCLES_PER_FRAME: u64 = 70224;
+
+// Runs `frames` frames taking `frame_time` each, 10ms after the start.
+fn run(benchmark: &mut Benchmark, frames: u64, frame_time: Duration) {
+    for frame in 1..=frames {
+        benchmark.frame(Duration::from_millis(10) + frame_time * frame as u32, frame * CYCLES_PER_FRAME);
+    }
+}
+
+#[test]
+fn the_warm_up_is_left_out() {
+    let mut benchmark = Benchmark::new(DEFAULT_WARMUP_FRAMES, Duration::from_millis(10));
+    run(&mut benchmark, 59, Duration::from_millis(8));
+    assert!(benchmark.is_warming_up());
+    assert_eq!(benchmark.report().frames, 0);
+    assert_eq!(benchmark.report().speed(), None);
+
+    benchmark.frame(Duration::from_millis(10 + 60 * 8), 60 * CYCLES_PER_FRAME);
+    assert!(!benchmark.is_warming_up());
+    for frame in 61..=660 {
+        benchmark.frame(Duration::from_millis(10 + 60 * 8 + (frame - 60) * 2), frame * CYCLES_PER_FRAME);
+    }
+
+    let report = benchmark.report();
+    assert_eq!((report.frames, report.warmup_frames), (600, 60));
+    assert_eq!(report.cycles, 600 * CYCLES_PER_FRAME);
+    assert_eq!(report.wall_time, Duration::from_millis(1200));
+    assert_eq!(report.emulated_time(), FRAME_TIME * 600);
+    assert_eq!(report.cycles_per_second(), Some(35_112_000));
+    let speed = report.speed().unwrap();
+    assert!((speed - 16.742706 / 2.0).abs() < 1e-6, "{}", speed);
+}
+
+#[test]
+fn no_warm_up_measures_from_the_start() {
+    let mut benchmark = Benchmark::new(0, Duration::from_millis(10));
+    assert!(!benchmark.is_warming_up());
+    run(&mut benchmark, 120, FRAME_TIME);
+    let report = benchmark.report();
+    assert_eq!((report.frames, report.cycles), (120, 120 * CYCLES_PER_FRAME));
+
+    // Running at the hardware's own pace
+    assert_eq!(report.wall_time, report.emulated_time());
+    assert_eq!(report.speed(), Some(1.0));
+    assert_eq!(report.cycles_per_second(), Some(4_194_304));
+}
+
+#[test]
+fn component_time_adds_up_after_the_warm_up() {
+    let mut benchmark = Benchmark::new(1, Duration::ZERO);
+    benchmark.add_component_time("cpu", Duration::from_secs(5));
+    run(&mut benchmark, 1, Duration::from_millis(5));
+    for _ in 0..3 {
+        benchmark.add_component_time("cpu", Duration::from_millis(30));
+        benchmark.add_component_time("ppu", Duration::from_millis(10));
+    }
+    assert_eq!(benchmark.report().components, [("cpu", Duration::from_millis(90)), ("ppu", Duration::from_millis(30))]);
+}
+
+#[test]
+fn the_report_prints_as_text_and_json() {
+    let report = BenchReport {
+        frames: 600,
+        warmup_frames: 60,
+        cycles: 600 * CYCLES_PER_FRAME,
+        wall_time: Duration::from_millis(1250),
+        components: vec![("cpu", Duration::from_millis(750)), ("ppu", Duration::from_millis(250))],
+    };
+    assert_eq!(report.to_string(), "\
+frames:  600 after 60 to warm up, 10.05s of emulated time
+time:    1.25s, 8.04x the hardware
+cycles:  42134400, 33.7M per second
+cpu      0.75s  75%
+ppu      0.25s  25%
+");
+    assert_eq!(report.to_json().to_string(), "{\"frames\":600,\"warmup_frames\":60,\"cycles\":42134400,\
+\"wall_time_us\":1250000,\"emulated_time_us\":10045623,\"speed_percent\":804,\"cycles_per_second\":33707520,\
+\"components\":[{\"name\":\"cpu\",\"time_us\":750000},{\"name\":\"ppu\",\"time_us\":250000}]}");
+}
+
+#[test]
+fn an_empty_report_has_no_speed() {
+    let report = Benchmark::new(10, Duration::from_secs(3)).report();
+    assert_eq!((report.frames, report.cycles, report.wall_time), (0, 0, Duration::ZERO));
+    assert_eq!(report.to_string(), "\
+frames:  0 after 10 to warm up, 0.00s of emulated time
+time:    0.00s
+cycles:  0
+");
+    let json = report.to_json().to_string();
+    assert!(json.contains("\"speed_percent\":null,\"cycles_per_second\":null,\"components\":[]"), "{}", json);
+}
@@ -0,0 +1,58 @@
+//! Reading blargg's test results out of cartridge RAM.
+
+extern crate farore;
+
+use farore::blargg::{read_status, BlarggStatus, SoundTestResult};
+
+
+// Cartridge RAM from A000, with reads past what's given returning 0xFF like open bus.
+fn status(ram: &[u8]) -> BlarggStatus {
+    read_status(&|address: u16| ram.get((address - 0xA000) as usize).cloned().unwrap_or(0xFF))
+}
+
+// The status byte, the signature and the text.
+fn ram(code: u8, text: &[u8]) -> Vec<u8> {
+    [&[code, 0xDE, 0xB0, 0x61][..], text].concat()
+}
+
+#[test]
+fn a_missing_signature_is_no_result() {
+    assert_eq!(status(&[]), BlarggStatus::NoSignature);
+    assert_eq!(status(&[0x00, 0xDE, 0xB0, 0x60, 0x00]), BlarggStatus::NoSignature);
+}
+
+#[test]
+fn status_0x80_means_still_running() {
+    assert_eq!(status(&ram(0x80, b"01:ok\0")), BlarggStatus::Running);
+}
+
+#[test]
+fn finished_results_list_their_subtests() {
+    let result = match status(&ram(0x01, b"01:ok 02:01\n\nFailed\0garbage")) {
+        BlarggStatus::Finished(result) => result,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(result, SoundTestResult {
+        code: 1,
+        text: "01:ok 02:01\n\nFailed".to_string(),
+        subtests: vec![(1, true), (2, false)],
+    });
+    assert!(!result.passed());
+
+    match status(&ram(0x00, b"Passed\0")) {
+        BlarggStatus::Finished(result) => {
+            assert!(result.passed());
+            assert!(result.subtests.is_empty());
+        },
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unterminated_text_stops_at_4k() {
+    let text = vec![b'x'; 0x2000];
+    match status(&ram(0x00, &text)) {
+        BlarggStatus::Finished(result) => assert_eq!(result.text.len(), 0x1000),
+        other => panic!("{:?}", other),
+    }
+}
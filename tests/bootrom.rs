@@ -0,0 +1,117 @@
+//! Loading and picking boot ROMs, with fake ones standing in for the real dumps.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use farore::bootrom::{self, BootRom, BootRomKind, BootRomPaths};
+use farore::model::HardwareModel;
+
+
+fn fake(size: usize, seed: u8) -> Vec<u8> {
+    (0..size).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+fn digest(data: &[u8]) -> String {
+    BootRom::with_digests(data.to_vec(), if data.len() == 0x100 { HardwareModel::Dmg } else { HardwareModel::Cgb }, &[])
+        .unwrap()
+        .sha1
+}
+
+// A directory of its own under the temp dir, emptied first.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("farore-bootrom-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn recognizes_known_digests() {
+    let dmg = fake(0x100, 1);
+    let known = digest(&dmg);
+    let digests = [(BootRomKind::Mgb, known.as_str())];
+    let rom = BootRom::with_digests(dmg, HardwareModel::Dmg, &digests).unwrap();
+    assert_eq!(rom.kind, Some(BootRomKind::Mgb));
+}
+
+#[test]
+fn keeps_unknown_boot_roms() {
+    let patched = fake(0x100, 2);
+    let rom = BootRom::new(patched.clone(), HardwareModel::Dmg).unwrap();
+    assert_eq!(rom.kind, None);
+    assert_eq!(rom.data, patched);
+}
+
+#[test]
+fn rejects_a_boot_rom_for_the_other_model() {
+    assert!(BootRom::new(fake(0x900, 3), HardwareModel::Dmg).is_err());
+    assert!(BootRom::new(fake(0x100, 3), HardwareModel::Cgb).is_err());
+
+    // A known CGB dump is refused by digest, whatever its size
+    let cgb = fake(0x100, 4);
+    let known = digest(&cgb);
+    assert!(BootRom::with_digests(cgb, HardwareModel::Dmg, &[(BootRomKind::Cgb, known.as_str())]).is_err());
+}
+
+#[test]
+fn rejects_odd_sizes() {
+    assert!(BootRom::new(fake(0x200, 5), HardwareModel::Dmg).is_err());
+}
+
+#[test]
+fn falls_back_to_skipping_the_boot_rom() {
+    assert!(bootrom::select(&BootRomPaths::default(), HardwareModel::Cgb).unwrap().is_none());
+
+    let empty = scratch_dir("empty");
+    let paths = BootRomPaths { dir: Some(empty.clone()), ..BootRomPaths::default() };
+    assert!(bootrom::select(&paths, HardwareModel::Dmg).unwrap().is_none());
+    fs::remove_dir_all(empty).unwrap();
+}
+
+#[test]
+fn picks_the_model_file_from_a_directory() {
+    let dir = scratch_dir("pick");
+    fs::write(dir.join("dmg_boot.bin"), fake(0x100, 6)).unwrap();
+    fs::write(dir.join("cgb_boot.bin"), fake(0x900, 7)).unwrap();
+    let paths = BootRomPaths { dir: Some(dir.clone()), ..BootRomPaths::default() };
+
+    assert_eq!(bootrom::select(&paths, HardwareModel::Dmg).unwrap().unwrap().data, fake(0x100, 6));
+    assert_eq!(bootrom::select(&paths, HardwareModel::Cgb).unwrap().unwrap().data, fake(0x900, 7));
+
+    // An explicit file wins, and still has to fit the model
+    let explicit = BootRomPaths { explicit: Some(dir.join("cgb_boot.bin")), ..paths };
+    assert!(bootrom::select(&explicit, HardwareModel::Dmg).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dmg_flags_follow_the_header_checksum() {
+    let registers = bootrom::post_boot_registers(HardwareModel::Dmg, 0x66);
+    assert_eq!((registers.a, registers.f, registers.pc, registers.sp), (0x01, 0xB0, 0x0100, 0xFFFE));
+    assert_eq!([registers.b, registers.c, registers.d, registers.e, registers.h, registers.l],
+               [0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]);
+    assert_eq!(bootrom::post_boot_registers(HardwareModel::Dmg, 0x00).f, 0x80);
+
+    // The CGB boot ROM leaves the same flags either way
+    for &checksum in &[0x00, 0x66] {
+        let registers = bootrom::post_boot_registers(HardwareModel::Cgb, checksum);
+        assert_eq!((registers.a, registers.f, registers.pc), (0x11, 0x80, 0x0100), "{:02X}", checksum);
+    }
+}
+
+#[test]
+fn post_boot_io_lists_each_register_once() {
+    for &model in &[HardwareModel::Dmg, HardwareModel::Cgb] {
+        let io = bootrom::post_boot_io(model);
+        assert!(io.windows(2).all(|pair| pair[0].0 < pair[1].0), "{:?}", model);
+        assert!(io.iter().all(|&(address, _)| address >= 0xFF00), "{:?}", model);
+        let read = |address: u16| io.iter().find(|register| register.0 == address).map(|register| register.1);
+        assert_eq!(read(0xFF40), Some(0x91), "{:?}", model);
+        assert_eq!(read(0xFF26), Some(0xF1), "{:?}", model);
+        assert_eq!(read(0xFF50), Some(0xFF), "{:?}", model);
+        assert_eq!(read(0xFF4D).is_some(), model.is_cgb());
+    }
+}
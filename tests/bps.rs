@@ -0,0 +1,118 @@
+//! Applying hand-built BPS patches.
+
+extern crate farore;
+
+use farore::bps::{self, BpsPatch};
+use farore::crc::crc32;
+
+
+fn number(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(low | 0x80);
+            return;
+        }
+        out.push(low);
+        value -= 1;
+    }
+}
+
+fn signed(out: &mut Vec<u8>, step: isize) {
+    number(out, (step.unsigned_abs() << 1) | (step < 0) as usize);
+}
+
+fn action(out: &mut Vec<u8>, kind: usize, length: usize) {
+    number(out, ((length - 1) << 2) | kind);
+}
+
+// Turns the 16 byte source into the 16 byte target with one of each action.
+fn build(source: &[u8], target: &[u8], target_crc32: u32) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, target.len());
+    let metadata = b"<patch author=\"test\"/>";
+    number(&mut patch, metadata.len());
+    patch.extend_from_slice(metadata);
+
+    action(&mut patch, 0, 4); // SourceRead 0-3
+    action(&mut patch, 1, 3); // TargetRead
+    patch.extend_from_slice(b"XYZ");
+    action(&mut patch, 2, 4); // SourceCopy from 12
+    signed(&mut patch, 12);
+    action(&mut patch, 2, 2); // SourceCopy from 2, back from 16
+    signed(&mut patch, -14);
+    action(&mut patch, 3, 3); // TargetCopy from 4, reading XYZ
+    signed(&mut patch, 4);
+
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&target_crc32.to_le_bytes());
+    let patch_crc32 = crc32(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    patch
+}
+
+fn source() -> Vec<u8> {
+    (0..16).collect()
+}
+
+fn target() -> Vec<u8> {
+    let mut target = vec![0, 1, 2, 3];
+    target.extend_from_slice(b"XYZ");
+    target.extend_from_slice(&[12, 13, 14, 15, 2, 3]);
+    target.extend_from_slice(b"XYZ");
+    target
+}
+
+#[test]
+fn applies_every_action() {
+    let patch = build(&source(), &target(), crc32(&target()));
+    assert_eq!(bps::apply(&source(), &patch).unwrap(), target());
+
+    let parsed = BpsPatch::parse(&patch).unwrap();
+    assert_eq!(parsed.metadata, "<patch author=\"test\"/>");
+    assert_eq!((parsed.source_size, parsed.target_size), (16, 16));
+}
+
+#[test]
+fn target_copy_can_overlap_itself() {
+    let source = [7u8];
+    let target = [7u8; 6];
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, 1);
+    number(&mut patch, 6);
+    number(&mut patch, 0);
+    action(&mut patch, 0, 1);
+    action(&mut patch, 3, 5);
+    signed(&mut patch, 0);
+    patch.extend_from_slice(&crc32(&source).to_le_bytes());
+    patch.extend_from_slice(&crc32(&target).to_le_bytes());
+    let patch_crc32 = crc32(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    assert_eq!(bps::apply(&source, &patch).unwrap(), target);
+}
+
+#[test]
+fn rejects_the_wrong_source_unless_forced() {
+    let patch = build(&source(), &target(), crc32(&target()));
+    let mut other = source();
+    other[8] = 0xFF; // A byte the patch never reads
+    let error = bps::apply(&other, &patch).unwrap_err();
+    assert!(error.to_string().contains("the patch is for"), "{}", error);
+    assert_eq!(BpsPatch::parse(&patch).unwrap().apply(&other, true).unwrap(), target());
+}
+
+#[test]
+fn rejects_a_result_with_the_wrong_crc() {
+    let patch = build(&source(), &target(), crc32(&target()) ^ 1);
+    let error = bps::apply(&source(), &patch).unwrap_err();
+    assert!(error.to_string().contains("patched rom's CRC32"), "{}", error);
+}
+
+#[test]
+fn rejects_a_corrupt_patch() {
+    let mut patch = build(&source(), &target(), crc32(&target()));
+    patch[10] ^= 0xFF;
+    assert!(bps::apply(&source(), &patch).is_err());
+}
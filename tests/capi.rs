@@ -0,0 +1,81 @@
+//! The C API called through its extern "C" signatures, as C would.
+
+#![cfg(feature = "capi")]
+
+extern crate farore;
+
+use std::ffi::CStr;
+use std::fs;
+use std::ptr;
+
+use farore::capi::{self, FaroreOptions};
+use farore::cart::{self, Repairs};
+
+
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x0138].copy_from_slice(b"CAPI");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+// Creates a machine, expecting it to fail, and returns why.
+fn failure(rom: *const u8, len: usize, options: *const FaroreOptions) -> String {
+    unsafe {
+        assert!(capi::farore_machine_new(rom, len, options).is_null());
+        let error = capi::farore_last_error(ptr::null());
+        assert!(!error.is_null());
+        CStr::from_ptr(error).to_str().unwrap().to_string()
+    }
+}
+
+#[test]
+fn null_and_bad_arguments_are_reported() {
+    let rom = rom();
+    assert_eq!(failure(ptr::null(), 0x8000, ptr::null()), "the rom is null");
+    assert!(failure(rom.as_ptr(), 0x100, ptr::null()).starts_with("the rom is 256 bytes, too short"));
+    let options = FaroreOptions { model: 3, palette: 0 };
+    assert_eq!(failure(rom.as_ptr(), rom.len(), &options), "3 isn't a FARORE_MODEL_");
+    let options = FaroreOptions { model: capi::FARORE_MODEL_CGB, palette: 7 };
+    assert_eq!(failure(rom.as_ptr(), rom.len(), &options), "7 isn't a FARORE_PALETTE_");
+
+    let mut unknown = rom.clone();
+    unknown[0x0147] = 0xEE;
+    cart::repair(&mut unknown, Repairs::ALL).unwrap();
+    assert_eq!(failure(unknown.as_ptr(), unknown.len(), ptr::null()), "cartridge type 0xEE isn't supported");
+}
+
+#[test]
+fn a_good_rom_gets_as_far_as_the_cpu() {
+    let rom = rom();
+    let options = FaroreOptions { model: capi::FARORE_MODEL_DMG, palette: capi::FARORE_PALETTE_POCKET };
+    assert_eq!(failure(rom.as_ptr(), rom.len(), &options), "running roms isn't supported yet, there is no CPU");
+}
+
+#[test]
+fn errors_are_kept_per_thread() {
+    let rom = rom();
+    assert_eq!(failure(ptr::null(), 0, ptr::null()), "the rom is null");
+    std::thread::spawn(|| assert!(unsafe { capi::farore_last_error(ptr::null()) }.is_null())).join().unwrap();
+    assert_eq!(failure(rom.as_ptr(), 0x100, ptr::null()).split(',').next(), Some("the rom is 256 bytes"));
+}
+
+#[test]
+fn freeing_null_is_harmless() {
+    unsafe { capi::farore_machine_free(ptr::null_mut()) };
+}
+
+#[test]
+fn the_header_declares_every_entry_point() {
+    let header = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/include/farore.h")).unwrap();
+    for declaration in &[
+        "FaroreMachine *farore_machine_new(const uint8_t *rom, size_t rom_len, const FaroreOptions *options);",
+        "void farore_machine_free(FaroreMachine *machine);",
+        "const char *farore_last_error(const FaroreMachine *machine);",
+        "#define FARORE_MODEL_CGB 2",
+        "#define FARORE_PALETTE_POCKET 2",
+        "uint32_t palette;",
+    ] {
+        assert!(header.contains(declaration), "{}", declaration);
+    }
+}
@@ -0,0 +1,165 @@
+//! The header fields decoded from their bytes: cartridge types and ROM sizes.
+
+extern crate farore;
+
+use farore::cart::{self, CartridgeType, GameboyProgramMeta, MbcKind, Repairs, SizeCheck};
+
+
+// A 32KiB ROM with the header fixed up after `edit`.
+fn rom<F: FnOnce(&mut Vec<u8>)>(edit: F) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x0138].copy_from_slice(b"CART");
+    edit(&mut rom);
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+fn debug_lines(rom: &[u8]) -> String {
+    let mut out = Vec::new();
+    GameboyProgramMeta::new(rom).unwrap().print_debug(&mut out);
+    String::from_utf8(out).unwrap()
+}
+
+// A type's byte, name, whether it has RAM, a battery, a clock and rumble, and its mapper.
+type TypeRow = (u8, CartridgeType, &'static str, bool, bool, bool, bool, MbcKind);
+
+const TYPES: [TypeRow; 28] = [
+    (0x00, CartridgeType::RomOnly, "ROM ONLY", false, false, false, false, MbcKind::None),
+    (0x01, CartridgeType::Mbc1, "MBC1", false, false, false, false, MbcKind::Mbc1),
+    (0x02, CartridgeType::Mbc1Ram, "MBC1+RAM", true, false, false, false, MbcKind::Mbc1),
+    (0x03, CartridgeType::Mbc1RamBattery, "MBC1+RAM+BATTERY", true, true, false, false, MbcKind::Mbc1),
+    (0x05, CartridgeType::Mbc2, "MBC2", true, false, false, false, MbcKind::Mbc2),
+    (0x06, CartridgeType::Mbc2Battery, "MBC2+BATTERY", true, true, false, false, MbcKind::Mbc2),
+    (0x08, CartridgeType::RomRam, "ROM+RAM", true, false, false, false, MbcKind::None),
+    (0x09, CartridgeType::RomRamBattery, "ROM+RAM+BATTERY", true, true, false, false, MbcKind::None),
+    (0x0B, CartridgeType::Mmm01, "MMM01", false, false, false, false, MbcKind::Mmm01),
+    (0x0C, CartridgeType::Mmm01Ram, "MMM01+RAM", true, false, false, false, MbcKind::Mmm01),
+    (0x0D, CartridgeType::Mmm01RamBattery, "MMM01+RAM+BATTERY", true, true, false, false, MbcKind::Mmm01),
+    (0x0F, CartridgeType::Mbc3TimerBattery, "MBC3+TIMER+BATTERY", false, true, true, false, MbcKind::Mbc3),
+    (0x10, CartridgeType::Mbc3TimerRamBattery, "MBC3+TIMER+RAM+BATTERY", true, true, true, false, MbcKind::Mbc3),
+    (0x11, CartridgeType::Mbc3, "MBC3", false, false, false, false, MbcKind::Mbc3),
+    (0x12, CartridgeType::Mbc3Ram, "MBC3+RAM", true, false, false, false, MbcKind::Mbc3),
+    (0x13, CartridgeType::Mbc3RamBattery, "MBC3+RAM+BATTERY", true, true, false, false, MbcKind::Mbc3),
+    (0x19, CartridgeType::Mbc5, "MBC5", false, false, false, false, MbcKind::Mbc5),
+    (0x1A, CartridgeType::Mbc5Ram, "MBC5+RAM", true, false, false, false, MbcKind::Mbc5),
+    (0x1B, CartridgeType::Mbc5RamBattery, "MBC5+RAM+BATTERY", true, true, false, false, MbcKind::Mbc5),
+    (0x1C, CartridgeType::Mbc5Rumble, "MBC5+RUMBLE", false, false, false, true, MbcKind::Mbc5),
+    (0x1D, CartridgeType::Mbc5RumbleRam, "MBC5+RUMBLE+RAM", true, false, false, true, MbcKind::Mbc5),
+    (0x1E, CartridgeType::Mbc5RumbleRamBattery, "MBC5+RUMBLE+RAM+BATTERY", true, true, false, true, MbcKind::Mbc5),
+    (0x20, CartridgeType::Mbc6, "MBC6", false, false, false, false, MbcKind::Mbc6),
+    (0x22, CartridgeType::Mbc7SensorRumbleRamBattery, "MBC7+SENSOR+RUMBLE+RAM+BATTERY", true, true, false, true, MbcKind::Mbc7),
+    (0xFC, CartridgeType::PocketCamera, "POCKET CAMERA", true, true, false, false, MbcKind::PocketCamera),
+    (0xFD, CartridgeType::BandaiTama5, "BANDAI TAMA5", false, true, true, false, MbcKind::Tama5),
+    (0xFE, CartridgeType::HuC3, "HuC3", true, true, true, false, MbcKind::HuC3),
+    (0xFF, CartridgeType::HuC1RamBattery, "HuC1+RAM+BATTERY", true, true, false, false, MbcKind::HuC1),
+];
+
+#[test]
+fn every_cartridge_type_decodes() {
+    for &(byte, cart_type, name, ram, battery, rtc, rumble, mbc) in &TYPES {
+        assert_eq!(CartridgeType::new(byte), cart_type, "0x{:02X}", byte);
+        assert_eq!(cart_type.byte(), byte, "{:?}", cart_type);
+        assert_eq!(cart_type.to_string(), name);
+        assert_eq!(
+            (cart_type.has_ram(), cart_type.has_battery(), cart_type.has_rtc(), cart_type.has_rumble()),
+            (ram, battery, rtc, rumble),
+            "{}", name);
+        assert_eq!(cart_type.mbc_kind(), Some(mbc), "{}", name);
+    }
+}
+
+#[test]
+fn other_bytes_are_invalid() {
+    for byte in 0..=0xFFu8 {
+        let cart_type = CartridgeType::new(byte);
+        assert_eq!(cart_type.byte(), byte);
+        if TYPES.iter().any(|&(known, ..)| known == byte) {
+            continue;
+        }
+        assert_eq!(cart_type, CartridgeType::Invalid(byte));
+        assert_eq!(cart_type.to_string(), format!("unknown (0x{:02X})", byte));
+        assert_eq!(cart_type.mbc_kind(), None);
+        assert!(!cart_type.has_ram() && !cart_type.has_battery() && !cart_type.has_rtc() && !cart_type.has_rumble());
+    }
+}
+
+#[test]
+fn the_header_shows_the_decoded_type() {
+    let rumble = rom(|rom| rom[0x0147] = 0x1E);
+    assert_eq!(GameboyProgramMeta::new(&rumble).unwrap().cart_type(), CartridgeType::Mbc5RumbleRamBattery);
+    assert!(debug_lines(&rumble).contains("\ncart type: MBC5+RUMBLE+RAM+BATTERY\n"));
+
+    let unknown = rom(|rom| rom[0x0147] = 0x04);
+    assert!(debug_lines(&unknown).contains("\ncart type: unknown (0x04)\n"));
+}
+
+// Each ROM size byte the table has, with the size and banks it declares.
+const SIZES: [(u8, usize, usize, &str); 12] = [
+    (0x00, 32 * 1024, 2, "32 KiB"),
+    (0x01, 64 * 1024, 4, "64 KiB"),
+    (0x02, 128 * 1024, 8, "128 KiB"),
+    (0x03, 256 * 1024, 16, "256 KiB"),
+    (0x04, 512 * 1024, 32, "512 KiB"),
+    (0x05, 1024 * 1024, 64, "1 MiB"),
+    (0x06, 2 * 1024 * 1024, 128, "2 MiB"),
+    (0x07, 4 * 1024 * 1024, 256, "4 MiB"),
+    (0x08, 8 * 1024 * 1024, 512, "8 MiB"),
+    (0x52, 1152 * 1024, 72, "1152 KiB"),
+    (0x53, 1280 * 1024, 80, "1280 KiB"),
+    (0x54, 1536 * 1024, 96, "1536 KiB"),
+];
+
+// A 32KiB ROM declaring `rom_size`, its checksums left alone.
+fn declaring(rom_size: u8) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0148] = rom_size;
+    rom
+}
+
+#[test]
+fn every_rom_size_decodes() {
+    for &(byte, size, banks, human) in &SIZES {
+        let rom = declaring(byte);
+        let meta = GameboyProgramMeta::new(&rom).unwrap();
+        assert_eq!(meta.declared_size(), Some(size), "0x{:02X}", byte);
+        assert_eq!(meta.declared_rom_banks(), Some(banks), "0x{:02X}", byte);
+        let line = format!("\nROM size: 0x{:02X}, {} ({} banks)\n", byte, human, banks);
+        assert!(debug_lines(&rom).contains(&line), "{}", line);
+    }
+}
+
+#[test]
+fn other_rom_sizes_are_unknown() {
+    for byte in 0..=0xFFu8 {
+        if SIZES.iter().any(|&(known, ..)| known == byte) {
+            continue;
+        }
+        let rom = declaring(byte);
+        let meta = GameboyProgramMeta::new(&rom).unwrap();
+        assert_eq!((meta.declared_size(), meta.declared_rom_banks()), (None, None), "0x{:02X}", byte);
+        assert_eq!(meta.size_check(), SizeCheck::UnknownIndicator(byte));
+        assert!(debug_lines(&rom).contains(&format!("\nROM size: 0x{:02X}, unknown\n", byte)));
+    }
+}
+
+#[test]
+fn the_length_is_checked_against_the_declared_size() {
+    for &(rom_size, length, check) in &[
+        (0x00, 0x8000, SizeCheck::Matches),
+        (0x00, 0x8001, SizeCheck::Overdump(1)),
+        (0x00, 0x10000, SizeCheck::Overdump(0x8000)),
+        (0x01, 0x8000, SizeCheck::Truncated(0x8000)),
+        (0x52, 0x120000, SizeCheck::Matches),
+        (0x52, 0x100000, SizeCheck::Truncated(0x20000)),
+        (0x09, 0x8000, SizeCheck::UnknownIndicator(0x09)),
+    ] {
+        let mut rom = declaring(rom_size);
+        rom.resize(length, 0xFF);
+        let meta = GameboyProgramMeta::new(&rom).unwrap();
+        assert_eq!(meta.size_check(), check, "0x{:02X} with {} bytes", rom_size, length);
+        assert_eq!(meta.is_valid_size(), check == SizeCheck::Matches);
+        assert!(debug_lines(&rom).contains(&format!("\nsize test: {}\n", check)));
+    }
+    assert_eq!(SizeCheck::Overdump(1).to_string(), "overdump, 1 bytes past the declared size");
+    assert_eq!(SizeCheck::Truncated(0x8000).to_string(), "truncated, 32768 bytes short of the declared size");
+}
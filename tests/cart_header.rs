@@ -0,0 +1,108 @@
+//! Parsing the header from a borrowed or an owned ROM.
+
+extern crate farore;
+
+use farore::cart::{self, GameboyProgramMeta, Repairs};
+use farore::json::Json;
+
+
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x013B].copy_from_slice(b"HEADERS");
+    rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x0149] = 0x02; // 8KiB of RAM
+    rom[0x014B] = 0x33;
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+#[test]
+fn borrowed_and_owned_headers_agree() {
+    let mut broken = rom();
+    broken[0x0110] ^= 0x01; // One bit of the logo
+    broken[0x014D] ^= 0xFF;
+
+    for rom in [rom(), broken].iter() {
+        let borrowed = GameboyProgramMeta::new(rom).unwrap();
+        let owned = GameboyProgramMeta::from_vec(rom.clone()).unwrap();
+        assert_eq!(borrowed.to_json().to_string(), owned.to_json().to_string());
+        assert_eq!(borrowed.is_runable(), owned.is_runable());
+        assert_eq!(owned.name, "HEADERS");
+    }
+}
+
+#[test]
+fn the_logo_has_to_match_byte_for_byte() {
+    let rom = rom();
+    assert!(GameboyProgramMeta::new(&rom).unwrap().is_valid_logo());
+    for offset in [0x0104, 0x011A, 0x0133].iter() {
+        let mut broken = rom.clone();
+        broken[*offset] ^= 0x80;
+        let meta = GameboyProgramMeta::from_vec(broken).unwrap();
+        assert!(!meta.is_valid_logo(), "{:04x}", offset);
+        assert!(!meta.is_runable());
+    }
+}
+
+#[test]
+fn short_roms_are_an_error() {
+    for size in [0, 37, 0x014F].iter() {
+        let error = GameboyProgramMeta::new(&rom()[..*size]).err().unwrap();
+        assert_eq!(error.to_string(), format!("the rom is {} bytes, too short to hold a header, which needs at least 336", size));
+    }
+}
+
+#[test]
+fn a_rom_of_just_the_header_parses() {
+    let rom = rom();
+    let meta = GameboyProgramMeta::new(&rom[..0x0150]).unwrap();
+    assert_eq!(meta.name, "HEADERS");
+    assert!(meta.is_runable());
+    assert_eq!(meta.program_size, 0x0150);
+}
+
+// An object's keys in order, and the value of each.
+fn fields(json: &Json) -> Vec<(&str, &Json)> {
+    match *json {
+        Json::Object(ref fields) => fields.iter().map(|(key, value)| (key.as_str(), value)).collect(),
+        ref other => panic!("not an object: {}", other),
+    }
+}
+
+#[test]
+fn the_json_schema_stays_put() {
+    let text = GameboyProgramMeta::new(&rom()).unwrap().to_json().to_string();
+    let json = Json::parse(&text).unwrap();
+    let keys: Vec<&str> = fields(&json).iter().map(|&(key, _)| key).collect();
+    assert_eq!(keys, [
+        "name", "size", "manufacturer_code", "licensee_code", "licensee", "color_flag", "super_flag",
+        "cart_type", "cart_type_name", "rom_size", "declared_size", "ram_size", "declared_ram_size",
+        "region_code", "version", "header_checksum", "global_checksum", "validation",
+    ]);
+
+    let field = |key: &str| json.get(key).unwrap();
+    assert_eq!(field("name").as_str(), Some("HEADERS"));
+    assert_eq!(field("size").as_i64(), Some(0x8000));
+    assert_eq!(field("manufacturer_code").as_array().map(|bytes| bytes.len()), Some(4));
+    assert_eq!(field("cart_type").as_i64(), Some(0x03));
+    assert_eq!(field("cart_type_name").as_str(), Some("MBC1+RAM+BATTERY"));
+    assert_eq!(field("declared_size").as_i64(), Some(0x8000));
+    assert_eq!(field("declared_ram_size").as_i64(), Some(0x2000));
+    let checksum: Vec<(&str, Option<i64>)> = fields(field("header_checksum")).iter()
+        .map(|&(key, value)| (key, value.as_i64()))
+        .collect();
+    let header = rom()[0x014D] as i64;
+    assert_eq!(checksum, [("declared", Some(header)), ("calculated", Some(header))]);
+
+    let checks: Vec<(&str, &Json)> = fields(field("validation"));
+    assert_eq!(checks, [
+        ("logo", &Json::Bool(true)),
+        ("header_checksum", &Json::Bool(true)),
+        ("global_checksum", &Json::Bool(true)),
+        ("size", &Json::Bool(true)),
+        ("ram_size", &Json::Bool(true)),
+        ("runnable", &Json::Bool(true)),
+    ]);
+    assert_eq!(GameboyProgramMeta::new(&rom()).unwrap().validation_json(), *field("validation"));
+}
@@ -0,0 +1,137 @@
+//! Decoding Game Genie and GameShark codes, and what a list of them patches.
+
+extern crate farore;
+
+use std::fs;
+use std::path::Path;
+
+use farore::cheat::{Cheat, CheatList, Patch};
+use farore::error::FaroreError;
+
+
+fn corpus(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join("cheat").join(name);
+    let bytes = fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e));
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn patch(code: &str) -> Patch {
+    Cheat::parse(code).unwrap_or_else(|e| panic!("{}: {}", code, e)).patch()
+}
+
+// The reason a code was rejected.
+fn rejection(code: &str) -> String {
+    match Cheat::parse(code) {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{} gave {:?}", code, other),
+    }
+}
+
+#[test]
+fn decodes_game_genie_codes() {
+    assert_eq!(patch(&corpus("game-genie-9")), Patch::Rom { address: 0x4A17, value: 0x00, compare: Some(0xC8) });
+    assert_eq!(patch(&corpus("game-genie-6")), Patch::Rom { address: 0x4A17, value: 0x01, compare: None });
+    assert_eq!(patch("00A-17B-C49"), patch("00A17BC49"));
+    assert_eq!(patch("3E0-55F-E62"), Patch::Rom { address: 0x0055, value: 0x3E, compare: Some(0x02) });
+}
+
+// Encodes a Game Genie code, with 0 for the unused digit.
+fn game_genie(address: u16, value: u8, compare: u8) -> String {
+    let scrambled = (compare ^ 0xBA).rotate_left(2);
+    format!("{:02X}{:X}-{:03X}-{:X}0{:X}", value, address >> 8 & 0xF, (address & 0xFF) << 4 | (address >> 12 ^ 0xF),
+            scrambled >> 4, scrambled & 0xF)
+}
+
+#[test]
+fn game_genie_decoding_undoes_the_scrambling() {
+    assert_eq!(game_genie(0x4A17, 0x00, 0xC8), "00A-17B-C09");
+    for address in (0..0x8000).step_by(0x123) {
+        for compare in 0..=255u8 {
+            let value = compare.wrapping_mul(7);
+            assert_eq!(patch(&game_genie(address, value, compare)), Patch::Rom { address, value, compare: Some(compare) });
+        }
+    }
+}
+
+#[test]
+fn game_genie_addresses_stay_in_rom() {
+    assert_eq!(patch("000-00F"), Patch::Rom { address: 0x0000, value: 0x00, compare: None });
+    assert_eq!(patch("FFF-FF8"), Patch::Rom { address: 0x7FFF, value: 0xFF, compare: None });
+    assert!(rejection("000-007").contains("digit 6"));
+    assert!(Cheat::parse("000-000").is_err());
+}
+
+#[test]
+fn decodes_game_shark_codes() {
+    assert_eq!(patch(&corpus("game-shark")), Patch::Ram { bank: None, address: 0xCD38, value: 0x02 });
+    assert_eq!(patch(&corpus("game-shark-bank")), Patch::Ram { bank: Some(1), address: 0xDFD0, value: 0x02 });
+    assert_eq!(patch("8F10FFBF"), Patch::Ram { bank: Some(0xF), address: 0xBFFF, value: 0x10 });
+    assert_eq!(patch("01ff00a0"), Patch::Ram { bank: None, address: 0xA000, value: 0xFF });
+}
+
+#[test]
+fn game_shark_codes_are_checked() {
+    assert!(rejection("020238CD").contains("digits 1-2, 02"));
+    assert!(rejection("90FF00C0").contains("digits 1-2, 90"));
+    assert!(rejection("01FF0080").contains("8000"));
+    assert!(rejection("01FF00E0").contains("E000"));
+    assert!(rejection("0102-38CD").contains("dashes"));
+}
+
+#[test]
+fn bad_lengths_are_rejected() {
+    for &(code, count) in &[("", 0), ("00A-17", 5), ("00A-17B-C", 7), ("0102380", 7), ("00A-17B-C49-1", 10)] {
+        assert!(rejection(code).contains(&format!("not {}", count)), "{}", code);
+    }
+    assert!(Cheat::parse(&corpus("empty")).is_err());
+    assert!(Cheat::parse(&corpus("dashes-only")).is_err());
+}
+
+#[test]
+fn bad_hex_names_the_digit() {
+    assert_eq!(rejection(&corpus("bad-digit")), "invalid cheat 00A-17Z-C49: digit 6 ('Z') isn't hex");
+    assert!(rejection("G1023ACD").contains("digit 1 ('G')"));
+    assert!(rejection(&corpus("non-ascii")).contains("digit 1"));
+}
+
+#[test]
+fn codes_keep_their_spelling() {
+    let cheat = Cheat::parse("  00a-17b-c49 ").unwrap();
+    assert_eq!(cheat.code(), "00A-17B-C49");
+    assert_eq!(cheat.to_string(), "00A-17B-C49");
+}
+
+#[test]
+fn rom_reads_are_patched_when_the_compare_matches() {
+    let mut cheats = CheatList::new();
+    cheats.add(Cheat::parse("00A-17B-C49").unwrap());
+    assert_eq!(cheats.patch_rom_read(0x4A17, 0xC8), 0x00);
+    assert_eq!(cheats.patch_rom_read(0x4A17, 0xC9), 0xC9);
+    assert_eq!(cheats.patch_rom_read(0x4A18, 0xC8), 0xC8);
+
+    // Without a compare any byte is replaced
+    cheats.add(Cheat::parse("01A-17B").unwrap());
+    assert_eq!(cheats.patch_rom_read(0x4A17, 0xC9), 0x01);
+    assert_eq!(cheats.patch_rom_read(0x4A17, 0xC8), 0x00);
+}
+
+#[test]
+fn cheats_can_be_toggled_and_removed() {
+    let mut cheats = CheatList::new();
+    let genie = cheats.add(Cheat::parse("01A-17B").unwrap());
+    let shark = cheats.add(Cheat::parse("010238CD").unwrap());
+    cheats.add(Cheat::parse("8102D0DF").unwrap());
+    assert_eq!(cheats.ram_writes().collect::<Vec<_>>(), [(None, 0xCD38, 0x02), (Some(1), 0xDFD0, 0x02)]);
+
+    assert!(cheats.set_enabled(genie, false));
+    assert!(cheats.set_enabled(shark, false));
+    assert!(!cheats.set_enabled(3, false));
+    assert_eq!(cheats.patch_rom_read(0x4A17, 0x55), 0x55);
+    assert_eq!(cheats.ram_writes().collect::<Vec<_>>(), [(Some(1), 0xDFD0, 0x02)]);
+    let enabled: Vec<(&str, bool)> = cheats.list().map(|(cheat, enabled)| (cheat.code(), enabled)).collect();
+    assert_eq!(enabled, [("01A-17B", false), ("010238CD", false), ("8102D0DF", true)]);
+
+    assert_eq!(cheats.remove(genie).map(|cheat| cheat.code().to_string()), Some("01A-17B".to_string()));
+    assert_eq!(cheats.remove(5), None);
+    assert_eq!(cheats.list().count(), 2);
+}
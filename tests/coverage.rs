@@ -0,0 +1,77 @@
+//! Recording which ROM bytes ran, reporting it, and disassembling with the report.
+
+extern crate farore;
+
+use farore::coverage::Coverage;
+use farore::disasm::{BankView, Listing};
+
+
+// Bank 0 jumps from the entry point to a loop calling a bank 1 routine, with data in between.
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0xFF; 0x8000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // nop, jp $0150
+    rom[0x0104..0x0108].copy_from_slice(&[0xCE, 0xED, 0x66, 0x66]); // The logo, never run
+    rom[0x0150..0x0157].copy_from_slice(&[0x3E, 0x12, 0xCD, 0x00, 0x40, 0x18, 0xF9]);
+    rom[0x4000..0x4002].copy_from_slice(&[0xAF, 0xC9]); // xor a, ret
+    rom
+}
+
+// What a CPU would record running the loop once: (bank, address, length) per instruction.
+fn run(coverage: &mut Coverage) {
+    let executed = [
+        (1, 0x0100, 1), (1, 0x0101, 3),
+        (1, 0x0150, 2), (1, 0x0152, 3), (1, 0x4000, 1), (1, 0x4001, 1), (1, 0x0155, 2),
+    ];
+    for &(bank, address, length) in executed.iter() {
+        coverage.record(bank, address, length);
+    }
+}
+
+#[test]
+fn marks_exactly_what_ran() {
+    let mut coverage = Coverage::new(rom().len());
+    run(&mut coverage);
+    assert_eq!(coverage.ranges(0), [0x0100..=0x0103, 0x0150..=0x0156]);
+    assert_eq!(coverage.ranges(1), [0x4000..=0x4001]);
+    assert!(!coverage.contains(1, 0x0104));
+    // Bank 2 isn't in a two bank rom
+    coverage.record(2, 0x4000, 1);
+    assert!(!coverage.contains(2, 0x4000));
+    assert_eq!(coverage.covered_bytes(0) + coverage.covered_bytes(1), 13);
+}
+
+#[test]
+fn the_report_reads_back_in() {
+    let mut coverage = Coverage::new(rom().len());
+    run(&mut coverage);
+    let report = coverage.to_string();
+    assert_eq!(report, "\
+; 0.0% of 32768 bytes ran as code
+bank 00  0.1%
+  0100-0103
+  0150-0156
+bank 01  0.0%
+  4000-4001
+");
+    assert_eq!(Coverage::parse(&report).unwrap(), coverage);
+
+    for bad in ["0100-0103", "bank 00\n  4000-4001", "bank 01\n  0100-0101", "bank 00\n  0103-0100", "bank 999\n"].iter() {
+        assert!(Coverage::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn the_disassembler_shows_what_never_ran_as_data() {
+    let rom = rom();
+    let mut coverage = Coverage::new(rom.len());
+    run(&mut coverage);
+    let listing = Listing::covered(&BankView::new(&rom, 1), 0x0100..0x0106, &coverage);
+    let expected = [
+        "    0100  00        nop",
+        "    0101  c3 50 01  jp $0150",
+        "",
+        "    0104  ce        db $ce",
+        "    0105  ed        db $ed",
+    ];
+    assert_eq!(listing.to_string(), expected.join("\n") + "\n");
+}
@@ -0,0 +1,180 @@
+//! Debugger command lines parsed the way a script of them would be, and fed to `farore debug`.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+
+use farore::cart::{self, Repairs};
+use farore::debugger::{Access, Comparison, Condition, DebugCommand, Location, Register, HELP};
+use farore::error::FaroreError;
+use farore::ppu::dump::TileMapSelect;
+
+
+fn parse(line: &str) -> DebugCommand {
+    DebugCommand::parse(line).unwrap_or_else(|e| panic!("{}: {}", line, e))
+}
+
+fn rejection(line: &str) -> String {
+    match DebugCommand::parse(line) {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{}: {:?}", line, other),
+    }
+}
+
+#[test]
+fn a_script_of_commands_parses() {
+    let script = ["b 0x150", "c", "r", "s", "s 10", "n", "x $C000 16", "u", "bt", "d 0", "q"];
+    let commands: Vec<DebugCommand> = script.iter().map(|line| parse(line)).collect();
+    assert_eq!(commands, [
+        DebugCommand::Break { location: Location::Address(0x150), condition: None },
+        DebugCommand::Continue,
+        DebugCommand::Registers,
+        DebugCommand::Step(1),
+        DebugCommand::Step(10),
+        DebugCommand::StepOver,
+        DebugCommand::Examine { location: Location::Address(0xC000), length: 16 },
+        DebugCommand::Disassemble { location: None, count: 10 },
+        DebugCommand::Backtrace,
+        DebugCommand::Delete(Some(0)),
+        DebugCommand::Quit,
+    ]);
+}
+
+#[test]
+fn long_names_and_short_names_agree() {
+    let pairs = [
+        ("s", "step"), ("n", "next"), ("c", "continue"), ("b 1", "break 1"), ("w 1", "watch 1"),
+        ("d", "delete"), ("r", "regs"), ("bt", "backtrace"), ("pal", "palettes"), ("h", "help"), ("q", "quit"),
+    ];
+    for &(short, long) in &pairs {
+        assert_eq!(parse(short), parse(long), "{}", long);
+    }
+    assert_eq!(parse("  x   0x8000  "), DebugCommand::Examine { location: Location::Address(0x8000), length: 64 });
+    assert_eq!(parse("oam"), DebugCommand::Oam);
+    assert_eq!(parse("tiles"), DebugCommand::Tiles(None));
+    assert_eq!(parse("tiles out.png"), DebugCommand::Tiles(Some("out.png".to_string())));
+    assert_eq!(parse("banks"), DebugCommand::Banks);
+    assert_eq!(parse("map"), DebugCommand::TileMap { which: TileMapSelect::Background, path: "map.png".to_string() });
+    assert_eq!(parse("map win"), DebugCommand::TileMap { which: TileMapSelect::Window, path: "map.png".to_string() });
+    assert_eq!(parse("map 9c00 a.png"), DebugCommand::TileMap { which: TileMapSelect::Map9C00, path: "a.png".to_string() });
+    assert_eq!(parse("map a.png"), DebugCommand::TileMap { which: TileMapSelect::Background, path: "a.png".to_string() });
+    assert_eq!(parse("save slot.state"), DebugCommand::SaveState("slot.state".to_string()));
+    assert_eq!(parse("load slot.state"), DebugCommand::LoadState("slot.state".to_string()));
+}
+
+#[test]
+fn addresses_are_hex_decimal_or_labels() {
+    let at = |line: &str| match parse(line) {
+        DebugCommand::Break { location, .. } => location,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(at("b 0x150"), Location::Address(0x150));
+    assert_eq!(at("b 0X150"), Location::Address(0x150));
+    assert_eq!(at("b $150"), Location::Address(0x150));
+    assert_eq!(at("b 336"), Location::Address(0x150));
+    assert_eq!(at("b Main.loop"), Location::Label("Main.loop".to_string()));
+    assert_eq!(parse("u VBlank 3"), DebugCommand::Disassemble { location: Some(Location::Label("VBlank".to_string())), count: 3 });
+
+    assert_eq!(rejection("b 0x10000"), "0x10000 isn't from 0 to 65535");
+    assert_eq!(rejection("b 12ab"), "12ab isn't a number");
+    assert_eq!(rejection("x 0 0"), "0 isn't from 1 to 65535");
+    assert_eq!(rejection("s 0"), "0 isn't from 1 to 4294967295");
+}
+
+#[test]
+fn breakpoints_take_conditions() {
+    assert_eq!(parse("b 0x150 if a == 0x03"), DebugCommand::Break {
+        location: Location::Address(0x150),
+        condition: Some(Condition { register: Register::A, comparison: Comparison::Equal, value: 3 }),
+    });
+    assert_eq!(parse("b Loop if HL >= $C000"), DebugCommand::Break {
+        location: Location::Label("Loop".to_string()),
+        condition: Some(Condition { register: Register::HL, comparison: Comparison::GreaterOrEqual, value: 0xC000 }),
+    });
+
+    // 8-bit registers only compare against bytes
+    assert_eq!(rejection("b 0x150 if a == 0x100"), "0x100 isn't from 0 to 255");
+    assert!(DebugCommand::parse("b 0x150 if sp == 0x100").is_ok());
+    assert_eq!(rejection("b 0x150 if x == 1"), "x isn't a register");
+    assert_eq!(rejection("b 0x150 if a = 1"), "= isn't one of == != < <= > >=");
+    assert_eq!(rejection("b 0x150 if a =="), "expected b LOC [if REG OP N]");
+    assert_eq!(rejection("b 0x150 a == 1"), "expected b LOC [if REG OP N]");
+}
+
+#[test]
+fn comparisons_hold_as_written() {
+    let cases = [
+        (Comparison::Equal, [false, true, false]),
+        (Comparison::NotEqual, [true, false, true]),
+        (Comparison::Less, [true, false, false]),
+        (Comparison::LessOrEqual, [true, true, false]),
+        (Comparison::Greater, [false, false, true]),
+        (Comparison::GreaterOrEqual, [false, true, true]),
+    ];
+    for &(comparison, expected) in &cases {
+        assert_eq!([comparison.holds(1, 2), comparison.holds(2, 2), comparison.holds(3, 2)], expected, "{:?}", comparison);
+    }
+    assert!(Register::PC.is_pair() && !Register::F.is_pair());
+}
+
+#[test]
+fn watchpoints_default_to_writes() {
+    let watch = |access| DebugCommand::Watch { location: Location::Address(0xFF40), access };
+    assert_eq!(parse("w 0xFF40"), watch(Access::Write));
+    assert_eq!(parse("w 0xFF40 w"), watch(Access::Write));
+    assert_eq!(parse("w 0xFF40 r"), watch(Access::Read));
+    assert_eq!(parse("w 0xFF40 rw"), watch(Access::ReadWrite));
+    assert_eq!(rejection("w 0xFF40 x"), "x isn't r, w or rw");
+}
+
+#[test]
+fn bad_lines_say_what_is_wrong() {
+    assert_eq!(rejection(""), "no command given");
+    assert_eq!(rejection("   "), "no command given");
+    assert_eq!(rejection("frobnicate"), "unknown command frobnicate, try help");
+    assert_eq!(rejection("c 5"), "c takes 0 arguments, not 1");
+    assert_eq!(rejection("x"), "x takes 1 to 2 arguments, not 0");
+    assert_eq!(rejection("save"), "save takes 1 argument, not 0");
+    assert_eq!(rejection("d one"), "one isn't a number");
+    assert_eq!(rejection("map 9900 a.png"), "9900 isn't bg, win, 9800 or 9c00");
+}
+
+#[test]
+fn help_covers_every_command() {
+    for name in &["step", "next", "continue", "break", "watch", "delete", "regs", "x LOC", "u LOC", "backtrace",
+                  "oam", "tiles", "map", "palettes", "banks", "save", "load", "help", "quit"] {
+        assert!(HELP.contains(name), "{}", name);
+    }
+}
+
+#[test]
+fn debug_answers_each_line_until_quit() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x0139].copy_from_slice(b"DEBUG");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    let scratch = |name: &str| env::temp_dir().join(format!("farore-debug-{}-{}", process::id(), name));
+    let (rom_path, sym_path) = (scratch("game.gb"), scratch("game.sym"));
+    fs::write(&rom_path, &rom).unwrap();
+    fs::write(&sym_path, "00:0150 Start\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_farore"))
+        .args(["debug", rom_path.to_str().unwrap(), "--sym", sym_path.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"help\nb Start\n\nb Nowhere\nq\nr\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_file(&rom_path);
+    let _ = fs::remove_file(&sym_path);
+
+    assert!(output.status.success());
+    let expected = format!("{}\n{}\n{}\n", HELP,
+                           "error: b needs a running machine, and there is no CPU yet",
+                           "error: there is no label Nowhere");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
@@ -0,0 +1,131 @@
+//! The listings of `farore disasm` over a hand-assembled routine, pinned byte for byte.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use farore::cart::{self, Repairs};
+
+
+const LINEAR: &str = "    0150  31 fe ff  ld sp, $fffe
+    0153  af        xor a
+    0154  21 00 c0  ld hl, $c000
+    0157  06 10     ld b, $10
+.l_0159:
+    0159  22        ld [hl+], a
+    015a  05        dec b
+    015b  20 fc     jr nz, .l_0159
+    015d  cd 00 40  call $4000
+.l_0160:
+    0160  18 fe     jr .l_0160
+
+    0162  ff        rst $38
+";
+
+const BANKED: &str = "    4000  3e 91     ld a, $91
+    4002  e0 40     ldh [$ff40], a
+    4004  cb 7f     bit 7, a
+    4006  28 02     jr z, .l_400a
+    4008  c9        ret
+
+    4009  dd        db $dd
+.l_400a:
+    400a  fa 00 c0  ld a, [$c000]
+    400d  c9        ret
+";
+
+const FOLLOWED: &str = "    0100  00        nop
+    0101  c3 50 01  jp .l_0150
+
+.l_0150:
+    0150  31 fe ff  ld sp, $fffe
+    0153  af        xor a
+    0154  21 00 c0  ld hl, $c000
+    0157  06 10     ld b, $10
+.l_0159:
+    0159  22        ld [hl+], a
+    015a  05        dec b
+    015b  20 fc     jr nz, .l_0159
+    015d  cd 00 40  call .l_4000
+.l_0160:
+    0160  18 fe     jr .l_0160
+
+.l_4000:
+    4000  3e 91     ld a, $91
+    4002  e0 40     ldh [$ff40], a
+    4004  cb 7f     bit 7, a
+    4006  28 02     jr z, .l_400a
+    4008  c9        ret
+
+.l_400a:
+    400a  fa 00 c0  ld a, [$c000]
+    400d  c9        ret
+";
+
+// A 64KiB MBC1 ROM whose entry point clears some work RAM and calls into bank 3, where a
+// routine turns the LCD on.  An invalid opcode sits after its first RET.
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0x00; 0x10000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0134..0x013A].copy_from_slice(b"DISASM");
+    rom[0x0147] = 0x01;
+    rom[0x0148] = 0x01;
+    rom[0x0150..0x0163].copy_from_slice(&[
+        0x31, 0xFE, 0xFF, // ld sp, $fffe
+        0xAF,             // xor a
+        0x21, 0x00, 0xC0, // ld hl, $c000
+        0x06, 0x10,       // ld b, $10
+        0x22,             // ld [hl+], a
+        0x05,             // dec b
+        0x20, 0xFC,       // jr nz, -4
+        0xCD, 0x00, 0x40, // call $4000
+        0x18, 0xFE,       // jr -2
+        0xFF,             // rst $38
+    ]);
+    rom[0xC000..0xC00E].copy_from_slice(&[
+        0x3E, 0x91,       // ld a, $91
+        0xE0, 0x40,       // ldh [$ff40], a
+        0xCB, 0x7F,       // bit 7, a
+        0x28, 0x02,       // jr z, +2
+        0xC9,             // ret
+        0xDD,             // Not an instruction
+        0xFA, 0x00, 0xC0, // ld a, [$c000]
+        0xC9,             // ret
+    ]);
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+fn disasm(name: &str, flags: &[&str]) -> String {
+    let path = env::temp_dir().join(format!("farore-disasm-{}-{}.gb", name, process::id()));
+    fs::write(&path, rom()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_farore")).args(["disasm", path.to_str().unwrap()]).args(flags).output().unwrap();
+    let _ = fs::remove_file(&path);
+
+    assert!(output.status.success(), "{}: {}", name, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn linear_sweep_labels_local_targets() {
+    assert_eq!(disasm("linear", &["--range", "0x0150..0x0163"]), LINEAR);
+}
+
+#[test]
+fn the_bank_is_mapped_at_4000() {
+    assert_eq!(disasm("banked", &["--bank", "3", "--range", "0x4000..0x400E"]), BANKED);
+    assert_ne!(disasm("bank-1", &["--range", "0x4000..0x400E"]), BANKED);
+}
+
+#[test]
+fn follow_traces_from_the_entry_point() {
+    assert_eq!(disasm("follow", &["--bank", "3", "--follow"]), FOLLOWED);
+}
+
+#[test]
+fn follow_stops_at_the_budget() {
+    let listing = disasm("budget", &["--bank", "3", "--follow", "--budget", "4"]);
+    assert_eq!(listing.lines().filter(|line| line.starts_with("    ")).count(), 4);
+}
@@ -0,0 +1,244 @@
+//! Truncated and garbage ROMs, archives and patches through the public API: errors, never
+//! panics, and error chains that lead back to the cause.
+
+extern crate farore;
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+
+use farore::archive;
+use farore::bps;
+use farore::cart::{self, GameboyProgramMeta, Repairs, HEADER_END};
+use farore::crc::crc32;
+use farore::error::FaroreError;
+use farore::ips;
+
+
+fn corpus(target: &str, name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join(target).join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e))
+}
+
+// Runs `f` on every input, collecting the ones it panicked on.
+fn panics<F: Fn(&[u8])>(inputs: &[Vec<u8>], f: F) -> Vec<usize> {
+    let f = panic::AssertUnwindSafe(f);
+    inputs.iter().enumerate()
+        .filter(|&(_, input)| panic::catch_unwind(|| f(input)).is_err())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Every proper prefix of `data`.
+fn truncations(data: &[u8]) -> Vec<Vec<u8>> {
+    (0..data.len()).map(|len| data[..len].to_vec()).collect()
+}
+
+// `data` with each byte in turn replaced by its complement.
+fn corruptions(data: &[u8]) -> Vec<Vec<u8>> {
+    (0..data.len()).map(|i| {
+        let mut corrupt = data.to_vec();
+        corrupt[i] = !corrupt[i];
+        corrupt
+    }).collect()
+}
+
+fn garbage(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(2_654_435_761) | 1;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }).collect()
+}
+
+// The message and every cause under it.
+fn chain(error: &dyn Error) -> Vec<String> {
+    let mut messages = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(cause) = source {
+        messages.push(cause.to_string());
+        source = cause.source();
+    }
+    messages
+}
+
+#[test]
+fn short_roms_are_too_short_for_a_header() {
+    for len in 0..HEADER_END {
+        for &fill in &[0x00, 0xFF] {
+            match GameboyProgramMeta::new(&vec![fill; len]) {
+                Err(FaroreError::RomTooShort(size)) => assert_eq!(size, len),
+                other => panic!("{} bytes: {:?}", len, other.map(|_| ())),
+            }
+        }
+    }
+}
+
+#[test]
+fn garbage_roms_never_panic() {
+    let mut roms: Vec<Vec<u8>> = (0..64).map(|seed| garbage(HEADER_END + seed as usize * 37, seed)).collect();
+    roms.extend((HEADER_END..HEADER_END + 4).map(|len| vec![0xFF; len]));
+    roms.push(vec![0x00; 0x8000]);
+
+    let panicked = panics(&roms, |rom| {
+        if let Ok(meta) = GameboyProgramMeta::new(rom) {
+            meta.fields();
+            meta.to_json();
+            meta.print_debug(&mut io::sink());
+            meta.size_check();
+            meta.declared_ram_size();
+            meta.licensee_name();
+        }
+        let _ = cart::repair(&mut rom.to_vec(), Repairs::ALL);
+        let _ = cart::trim(&mut rom.to_vec(), false);
+        let _ = cart::pad(&mut rom.to_vec(), 0x8000, 0xFF);
+    });
+    assert!(panicked.is_empty(), "panicked on roms {:?}", panicked);
+}
+
+#[test]
+fn damaged_archives_are_errors() {
+    for &name in &["gzip", "zip-deflated"] {
+        let archive = corpus("archive", name);
+        assert!(archive::unpack(&archive, None).is_ok(), "{}", name);
+
+        let truncated = truncations(&archive);
+        assert!(panics(&truncated, |data| {
+            let _ = archive::list(data);
+            let _ = archive::unpack(data, None);
+        }).is_empty(), "{} truncated", name);
+        for data in truncated.iter().filter(|data| archive::sniff(data).is_some()) {
+            assert!(archive::unpack(data, None).is_err(), "{} cut to {} bytes", name, data.len());
+        }
+
+        assert!(panics(&corruptions(&archive), |data| {
+            let _ = archive::list(data);
+            let _ = archive::unpack(data, None);
+        }).is_empty(), "{} corrupted", name);
+    }
+}
+
+#[test]
+fn archive_errors_chain_to_the_cause() {
+    let error = archive::unpack(&corpus("archive", "gzip-truncated"), None).err().unwrap();
+    match error {
+        FaroreError::Archive(_) => {},
+        ref other => panic!("{:?}", other),
+    }
+    let messages = chain(&error);
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+    assert_eq!(messages[0], "the archive can't be unpacked");
+}
+
+fn ips_patch() -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x03, 0xAA, 0xBB, 0xCC]);
+    patch.extend_from_slice(&[0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x10, 0x5A]);
+    patch.extend_from_slice(b"EOF");
+    patch
+}
+
+#[test]
+fn truncated_ips_patches_are_errors() {
+    let truncated = truncations(&ips_patch());
+    assert!(panics(&truncated, |patch| {
+        let _ = ips::apply(&mut vec![0; 0x400], patch);
+    }).is_empty());
+    for patch in &truncated {
+        let mut rom = vec![0; 0x400];
+        assert!(ips::apply(&mut rom, patch).is_err(), "cut to {} bytes", patch.len());
+    }
+
+    assert!(panics(&corruptions(&ips_patch()), |patch| {
+        let _ = ips::apply(&mut vec![0; 0x400], patch);
+    }).is_empty());
+}
+
+fn number(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(low | 0x80);
+            return;
+        }
+        out.push(low);
+        value -= 1;
+    }
+}
+
+// The header and action stream of a patch turning 8 zero bytes into 8 bytes read from the patch,
+// without the trailing checksums.
+fn bps_body() -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, 8);
+    number(&mut patch, 8);
+    number(&mut patch, 0);
+    number(&mut patch, (8 - 1) << 2 | 1);
+    patch.extend_from_slice(b"ABCDEFGH");
+    patch
+}
+
+// Closes a patch body with the source, target and patch CRCs, so damage to the body gets past
+// the patch checksum.
+fn bps_finish(body: &[u8]) -> Vec<u8> {
+    let mut patch = body.to_vec();
+    patch.extend_from_slice(&crc32(&[0; 8]).to_le_bytes());
+    patch.extend_from_slice(&crc32(b"ABCDEFGH").to_le_bytes());
+    let patch_crc32 = crc32(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    patch
+}
+
+#[test]
+fn damaged_bps_patches_are_errors() {
+    let patch = bps_finish(&bps_body());
+    assert_eq!(bps::apply(&[0; 8], &patch).unwrap(), b"ABCDEFGH");
+
+    let truncated = truncations(&patch);
+    let resealed: Vec<Vec<u8>> = truncations(&bps_body()).iter().map(|body| bps_finish(body)).collect();
+    for patches in &[&truncated, &resealed, &corruptions(&patch)] {
+        assert!(panics(patches, |patch| {
+            let _ = bps::apply(&[0; 8], patch);
+            let _ = bps::BpsPatch::parse(patch);
+        }).is_empty());
+    }
+    for patch in truncated.iter().chain(&resealed) {
+        assert!(bps::apply(&[0; 8], patch).is_err(), "cut to {} bytes", patch.len());
+    }
+}
+
+#[test]
+fn patch_errors_chain_to_the_cause() {
+    let cause = ips::apply(&mut vec![0; 16], b"PATCH\x00").err().unwrap();
+    let error = FaroreError::from(cause.clone());
+    let messages = chain(&error);
+    assert_eq!(messages, ["the patch can't be applied".to_string(), cause.to_string()]);
+}
+
+#[test]
+fn io_errors_chain_to_the_cause() {
+    let error = FaroreError::io(Path::new("missing.gb"), io::Error::new(io::ErrorKind::NotFound, "not found"));
+    assert_eq!(chain(&error), ["unable to access missing.gb", "not found"]);
+}
+
+#[test]
+fn leaf_errors_have_no_cause() {
+    let errors = [
+        FaroreError::RomTooShort(3),
+        FaroreError::UnsupportedMapper(0xEE),
+        FaroreError::RamSizeMismatch { expected: 0x2000, found: 0x1000 },
+        FaroreError::SaveStateVersion { found: 9, supported: 1 },
+        FaroreError::InvalidArgument("bad".to_string()),
+    ];
+    for error in &errors {
+        assert!(error.source().is_none(), "{:?}", error);
+    }
+    assert_eq!(errors[1].to_string(), "cartridge type 0xEE isn't supported");
+    assert_eq!(errors[2].to_string(), "the save is 4096 bytes, the cartridge has 8192 bytes of RAM");
+    assert_eq!(errors[3].to_string(), "the save state is version 9, only version 1 can be loaded");
+}
@@ -0,0 +1,121 @@
+//! The fix command on ROMs broken in each of the ways it repairs.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+use farore::cart::{self, GameboyProgramMeta, Repairs};
+
+
+// A 64KiB ROM with correct checksums, its second half filled with code.
+fn rom() -> Vec<u8> {
+    let mut rom: Vec<u8> = (0..0x10000).map(|i| (i % 0xF1) as u8).collect();
+    rom[0x0134..0x0144].copy_from_slice(b"FIXME\0\0\0\0\0\0\0\0\0\0\0");
+    rom[0x0147] = 0x01;
+    rom[0x0148] = 0x01;
+    rom[0x0149] = 0x00;
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("farore-fix-{}-{}.gb", name, process::id()))
+}
+
+// Writes `rom`, runs `farore fix` on it with `flags` and returns the repaired copy.
+fn fix(name: &str, rom: &[u8], flags: &[&str]) -> Vec<u8> {
+    let (input, output) = (scratch(name), scratch(&format!("{}-out", name)));
+    fs::write(&input, rom).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_farore"))
+        .args(["-q", "fix", input.to_str().unwrap(), "-o", output.to_str().unwrap()])
+        .args(flags)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "{}: {:?}", name, status);
+    let fixed = fs::read(&output).unwrap();
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+    fixed
+}
+
+// The offsets where `a` and `b` differ, for the bytes they both have.
+fn changed(a: &[u8], b: &[u8]) -> Vec<usize> {
+    a.iter().zip(b).enumerate().filter(|&(_, (x, y))| x != y).map(|(i, _)| i).collect()
+}
+
+#[test]
+fn logo_only_touches_the_logo() {
+    let mut broken = rom();
+    for byte in &mut broken[0x0104..0x010C] {
+        *byte ^= 0xFF;
+    }
+    let fixed = fix("logo", &broken, &["--logo"]);
+    assert!(GameboyProgramMeta::new(&fixed).unwrap().is_valid_logo());
+    assert_eq!(changed(&broken, &fixed), (0x0104..0x010C).collect::<Vec<usize>>());
+    assert_eq!(fixed, rom());
+}
+
+#[test]
+fn checksums_only_touch_the_checksums() {
+    let mut broken = rom();
+    broken[0x0134] = b'B';
+    broken[0x4000] ^= 0xFF;
+    let fixed = fix("checksums", &broken, &["--checksums"]);
+    let meta = GameboyProgramMeta::new(&fixed).unwrap();
+    assert!(meta.is_valid_header() && meta.is_valid_program());
+    assert!(changed(&broken, &fixed).iter().all(|&i| (0x014D..0x0150).contains(&i)));
+    assert_eq!(fixed.len(), broken.len());
+}
+
+#[test]
+fn pad_fills_up_to_the_declared_size_with_ff() {
+    let short = &rom()[..0x8000];
+    let fixed = fix("pad", short, &["--pad"]);
+    assert_eq!(fixed.len(), 0x10000);
+    assert_eq!(&fixed[..0x8000], short);
+    assert!(fixed[0x8000..].iter().all(|&byte| byte == 0xFF));
+    assert!(GameboyProgramMeta::new(&fixed).unwrap().is_valid_size());
+}
+
+#[test]
+fn no_flags_applies_every_repair() {
+    let mut broken = rom()[..0x8000].to_vec();
+    broken[0x0120] ^= 0x01;
+    broken[0x014D] ^= 0xFF;
+    let fixed = fix("all", &broken, &[]);
+    let meta = GameboyProgramMeta::new(&fixed).unwrap();
+    assert!(meta.is_runable() && meta.is_valid_program() && meta.is_valid_size());
+    let untouched = |i: &usize| !(0x0104..0x0134).contains(i) && !(0x014D..0x0150).contains(i);
+    assert_eq!(changed(&broken, &fixed).into_iter().filter(untouched).count(), 0);
+}
+
+#[test]
+fn the_input_is_only_overwritten_in_place() {
+    let path = scratch("same");
+    let mut broken = rom();
+    broken[0x014D] ^= 0xFF;
+    fs::write(&path, &broken).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_farore"))
+        .args(["fix", path.to_str().unwrap(), "-o", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let unchanged = fs::read(&path).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_farore"))
+        .args(["-q", "fix", path.to_str().unwrap(), "--in-place"])
+        .output()
+        .unwrap()
+        .status;
+    let fixed = fs::read(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is the input rom, use --in-place to overwrite it"));
+    assert_eq!(unchanged, broken);
+    assert!(status.success());
+    assert_eq!(fixed, rom());
+}
@@ -0,0 +1,85 @@
+//! Replays the fuzzing corpus in fuzz/corpus through the fuzz targets, for those who don't run
+//! the fuzzer.  Each target here does what the one of the same name in fuzz/fuzz_targets does.
+//! Inputs that once crashed a target go in its corpus directory too, so they stay fixed.
+
+extern crate farore;
+
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+use std::str;
+
+use farore::archive;
+use farore::cart::{self, GameboyProgramMeta, Repairs};
+use farore::cheat::Cheat;
+use farore::disasm::{BankView, Listing};
+use farore::movie::InputMovie;
+
+
+type Target = fn(&[u8]);
+
+const TARGETS: [(&str, Target); 5] = [
+    ("cart_header", cart_header),
+    ("archive", archive),
+    ("cheat", cheat),
+    ("movie", movie),
+    ("disasm", disasm),
+];
+
+fn cart_header(data: &[u8]) {
+    if let Ok(meta) = GameboyProgramMeta::new(data) {
+        meta.fields();
+        meta.to_json();
+        meta.print_debug(&mut io::sink());
+    }
+    let _ = cart::repair(&mut data.to_vec(), Repairs::ALL);
+}
+
+fn archive(data: &[u8]) {
+    archive::sniff(data);
+    let _ = archive::list(data);
+    let _ = archive::unpack(data, None);
+    let _ = archive::unpack(data, Some("game.gb"));
+}
+
+fn cheat(data: &[u8]) {
+    if let Ok(code) = str::from_utf8(data) {
+        let _ = Cheat::parse(code);
+    }
+}
+
+fn movie(data: &[u8]) {
+    let _ = InputMovie::read(&mut &data[..]);
+}
+
+fn disasm(data: &[u8]) {
+    if let Some((&bank, rom)) = data.split_first() {
+        let view = BankView::new(rom, bank as usize);
+        Listing::linear(&view, 0x0000..0x8000).to_string();
+        Listing::follow(&view, 0x0100, 1000).to_string();
+    }
+}
+
+#[test]
+fn corpus_never_panics() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus");
+    let mut panicked = Vec::new();
+    let mut replayed = 0;
+    for &(name, target) in TARGETS.iter() {
+        let mut inputs: Vec<_> = fs::read_dir(corpus.join(name))
+            .unwrap_or_else(|e| panic!("no corpus for {}: {}", name, e))
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        inputs.sort();
+        for input in inputs {
+            let data = fs::read(&input).unwrap();
+            if panic::catch_unwind(|| target(&data)).is_err() {
+                panicked.push(input.display().to_string());
+            }
+            replayed += 1;
+        }
+    }
+    assert!(replayed > 0, "the corpus is empty");
+    assert!(panicked.is_empty(), "panicked on {}", panicked.join(", "));
+}
@@ -0,0 +1,277 @@
+//! Animated GIFs taken apart again: header, frames, delays, pixels and trailer.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use farore::gif::{GifEncoder, GifRecorder};
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::ppu::{Ppu, DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+
+// Collects what's written where the test can still see it.
+#[derive(Clone, Default)]
+struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct GifFrame {
+    delay: u16,
+    rgb: Vec<[u8; 3]>,
+}
+
+struct Gif {
+    width: u16,
+    height: u16,
+    loops: bool,
+    frames: Vec<GifFrame>,
+}
+
+// Reads a GIF as the encoder writes it, panicking on anything else, including a missing trailer.
+fn parse(file: &[u8]) -> Gif {
+    assert_eq!(&file[..6], b"GIF89a");
+    let u16_at = |i: usize| u16::from_le_bytes([file[i], file[i + 1]]);
+    let (width, height) = (u16_at(6), u16_at(8));
+    assert_eq!(file[10] & 0x80, 0, "no global color table");
+    let mut i = 13;
+    let (mut loops, mut delay, mut frames) = (false, 0, Vec::new());
+    loop {
+        match (file[i], file.get(i + 1)) {
+            (0x21, Some(0xFF)) => {
+                loops |= &file[i + 3..i + 14] == b"NETSCAPE2.0";
+                i = skip_blocks(file, i + 3 + file[i + 2] as usize);
+            },
+            (0x21, Some(0xF9)) => {
+                delay = u16_at(i + 4);
+                i = skip_blocks(file, i + 3 + file[i + 2] as usize);
+            },
+            (0x2C, _) => {
+                assert_eq!((u16_at(i + 1), u16_at(i + 3)), (0, 0));
+                assert_eq!((u16_at(i + 5), u16_at(i + 7)), (width, height));
+                let flags = file[i + 9];
+                assert_ne!(flags & 0x80, 0, "local color table");
+                let colors = 2 << (flags & 0x07);
+                let table: Vec<[u8; 3]> = file[i + 10..i + 10 + colors * 3].chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+                i += 10 + colors * 3;
+                let min_code_size = file[i];
+                let mut data = Vec::new();
+                i += 1;
+                while file[i] != 0 {
+                    data.extend_from_slice(&file[i + 1..i + 1 + file[i] as usize]);
+                    i += 1 + file[i] as usize;
+                }
+                i += 1;
+                let indices = lzw_decode(&data, min_code_size);
+                assert_eq!(indices.len(), width as usize * height as usize);
+                frames.push(GifFrame { delay, rgb: indices.iter().map(|&index| table[index as usize]).collect() });
+            },
+            (0x3B, None) => break,
+            (byte, _) => panic!("unexpected {:02X} at {}", byte, i),
+        }
+    }
+    Gif { width, height, loops, frames }
+}
+
+fn skip_blocks(file: &[u8], mut i: usize) -> usize {
+    while file[i] != 0 {
+        i += 1 + file[i] as usize;
+    }
+    i + 1
+}
+
+fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_code_size;
+    let reset = || -> Vec<Vec<u8>> { (0..clear + 2).map(|i| vec![i as u8]).collect() };
+    let (mut table, mut width, mut previous): (_, u32, Option<Vec<u8>>) = (reset(), min_code_size as u32 + 1, None);
+    let (mut position, mut out) = (0, Vec::new());
+    loop {
+        let code = (0..width).fold(0, |code, bit| {
+            let at = position + bit as usize;
+            code | ((data[at / 8] >> (at % 8) & 1) as usize) << bit
+        });
+        position += width as usize;
+        if code == clear {
+            table = reset();
+            width = min_code_size as u32 + 1;
+            previous = None;
+            continue;
+        }
+        if code == clear + 1 {
+            return out;
+        }
+        let entry = match (table.get(code), &previous) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(previous)) if code == table.len() => [&previous[..], &previous[..1]].concat(),
+            _ => panic!("bad code {} with {} entries", code, table.len()),
+        };
+        out.extend_from_slice(&entry);
+        if let Some(previous) = previous {
+            if table.len() < 4096 {
+                table.push([&previous[..], &entry[..1]].concat());
+            }
+        }
+        if table.len() == 1 << width && width < 12 {
+            width += 1;
+        }
+        previous = Some(entry);
+    }
+}
+
+fn rgba(pixels: &[[u8; 3]]) -> Vec<u8> {
+    pixels.iter().flat_map(|&[r, g, b]| vec![r, g, b, 0xFF]).collect()
+}
+
+// `count` pixels cycling through `colors` different colors in a noisy order.
+fn noise(count: usize, colors: usize) -> Vec<[u8; 3]> {
+    (0..count).map(|i| {
+        let color = (i * 7919 + i / 13) % colors;
+        [color as u8, (color >> 8) as u8 * 40, 255 - color as u8]
+    }).collect()
+}
+
+#[test]
+fn encodes_frames_with_delays_and_a_trailer() {
+    let file = SharedWriter::default();
+    let pixels = [[0, 0, 0], [255, 255, 255], [10, 20, 30], [255, 255, 255], [0, 0, 0], [0, 0, 0]];
+    let mut encoder = GifEncoder::new(file.clone(), 3, 2).unwrap();
+    encoder.add_frame(&rgba(&pixels), 2).unwrap();
+    encoder.add_frame(&rgba(&[[7, 7, 7]; 6]), 300).unwrap();
+    assert_eq!(encoder.frames(), 2);
+    encoder.finish().unwrap();
+
+    let gif = parse(&file.0.borrow());
+    assert_eq!((gif.width, gif.height, gif.loops), (3, 2, true));
+    assert_eq!(gif.frames.len(), 2);
+    assert_eq!((gif.frames[0].delay, gif.frames[1].delay), (2, 300));
+    assert_eq!(gif.frames[0].rgb, pixels);
+    assert_eq!(gif.frames[1].rgb, [[7, 7, 7]; 6]);
+}
+
+#[test]
+fn a_screen_of_256_colors_round_trips() {
+    let file = SharedWriter::default();
+    let pixels = noise(SCREEN_WIDTH * SCREEN_HEIGHT, 256);
+    let mut encoder = GifEncoder::new(file.clone(), SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16).unwrap();
+    for _ in 0..3 {
+        encoder.add_frame(&rgba(&pixels), 2).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let gif = parse(&file.0.borrow());
+    assert_eq!(gif.frames.len(), 3);
+    assert!(gif.frames.iter().all(|frame| frame.rgb == pixels));
+}
+
+#[test]
+fn more_than_256_colors_fall_back_to_the_cube() {
+    let file = SharedWriter::default();
+    let pixels = noise(64 * 64, 1000);
+    let mut encoder = GifEncoder::new(file.clone(), 64, 64).unwrap();
+    encoder.add_frame(&rgba(&pixels), 2).unwrap();
+    encoder.finish().unwrap();
+
+    let gif = parse(&file.0.borrow());
+    for (decoded, original) in gif.frames[0].rgb.iter().zip(&pixels) {
+        for channel in 0..3 {
+            let error = (decoded[channel] as i32 - original[channel] as i32).abs();
+            assert!(error <= 26, "{:?} came out {:?}", original, decoded);
+        }
+    }
+}
+
+#[test]
+fn bad_frames_are_refused() {
+    let file = SharedWriter::default();
+    let mut encoder = GifEncoder::new(file.clone(), 2, 2).unwrap();
+    assert_eq!(encoder.add_frame(&[0; 12], 2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    encoder.finish().unwrap();
+    assert!(encoder.add_frame(&[0; 16], 2).is_err());
+
+    // Finishing again doesn't write a second trailer
+    encoder.finish().unwrap();
+    drop(encoder);
+    assert_eq!(parse(&file.0.borrow()).frames.len(), 0);
+}
+
+#[test]
+fn dropping_the_encoder_writes_the_trailer() {
+    let file = SharedWriter::default();
+    {
+        let mut encoder = GifEncoder::new(file.clone(), 1, 1).unwrap();
+        encoder.add_frame(&[1, 2, 3, 255], 5).unwrap();
+    }
+    assert_eq!(file.0.borrow().last(), Some(&0x3B));
+    assert_eq!(parse(&file.0.borrow()).frames[0].rgb, [[1, 2, 3]]);
+}
+
+// Runs a DMG PPU with a scrolling background for `frames` frames, feeding `recorder`.
+fn record(recorder: &GifRecorder, frames: u32) {
+    let mut ppu = Ppu::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    for row in 0..8 {
+        ppu.write_vram(0x8010 + row * 2, 0x0F << (row % 4));
+    }
+    for i in 0..0x400 {
+        ppu.write_vram(0x9800 + i, (i % 2) as u8);
+    }
+    ppu.write(0xFF47, 0xE4);
+    ppu.write(0xFF40, 0x91);
+    ppu.set_frame_callback(recorder.frame_callback());
+    for frame in 0..frames {
+        ppu.write(0xFF43, frame as u8);
+        ppu.tick(DOTS_PER_FRAME, &mut irq);
+    }
+}
+
+#[test]
+fn the_recorder_keeps_every_nth_frame_up_to_the_limit() {
+    let file = SharedWriter::default();
+    let recorder = GifRecorder::new(Box::new(file.clone()), 2, Some(5)).unwrap();
+    record(&recorder, 30);
+    assert_eq!(recorder.frames(), 5);
+
+    // The limit finished the file without being asked
+    let gif = parse(&file.0.borrow());
+    assert_eq!((gif.width as usize, gif.height as usize), (SCREEN_WIDTH, SCREEN_HEIGHT));
+    assert_eq!(gif.frames.len(), 5);
+    assert!(gif.frames[0].rgb != gif.frames[1].rgb, "the background scrolls");
+    recorder.finish().unwrap();
+    assert_eq!(parse(&file.0.borrow()).frames.len(), 5);
+}
+
+#[test]
+fn recorded_delays_keep_to_real_time() {
+    let file = SharedWriter::default();
+    let recorder = GifRecorder::new(Box::new(file.clone()), 1, None).unwrap();
+    record(&recorder, 60);
+    recorder.finish().unwrap();
+
+    // A frame is 1.67 centiseconds, but anything under 2 plays slower in browsers
+    let gif = parse(&file.0.borrow());
+    assert_eq!(gif.frames.len(), 60);
+    let delays: Vec<u16> = gif.frames.iter().map(|frame| frame.delay).collect();
+    assert!(delays.iter().all(|&delay| delay == 2), "{:?}", delays);
+
+    let file = SharedWriter::default();
+    let recorder = GifRecorder::new(Box::new(file.clone()), 3, None).unwrap();
+    record(&recorder, 60);
+    recorder.finish().unwrap();
+    let gif = parse(&file.0.borrow());
+    assert_eq!(gif.frames.len(), 20);
+
+    // Every third frame covers 5.02 centiseconds, the rounding never building up
+    assert!(gif.frames.iter().all(|frame| frame.delay == 5));
+}
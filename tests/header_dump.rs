@@ -0,0 +1,60 @@
+//! The annotated header hexdump of `farore header`, pinned byte for byte.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use farore::cart::{self, Repairs};
+
+
+const GOLDEN: &str = "\
+0100  00 C3 50 01                                      entry point
+0104  CE ED 66 66 CC 0D 00 0B 03 73 00 83 00 0C 00 0D  logo
+0114  00 08 11 1F 88 89 00 0E DC CC 6E E6 DD DD D9 99
+0124  BB BB 67 63 6E 0E EC CC DD DC 99 9F BB B9 33 3E
+0134  47 4F 4C 44 45 4E 00 00 00 00 00                 title
+013F  41 47 44 45                                      manufacturer code
+0143  80                                               CGB flag
+0144  30 31                                            new licensee code
+0146  03                                               SGB flag
+0147  03                                               cart type
+0148  00                                               ROM size
+0149  02                                               RAM size
+014A  01                                               region
+014B  33                                               old licensee code
+014C  02                                               version
+014D  FE                                               header checksum
+014E  BE EF                                            global checksum  FAILED
+";
+
+// A CGB-compatible MBC1+RAM+BATTERY ROM whose global checksum is wrong.
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0134..0x013A].copy_from_slice(b"GOLDEN");
+    rom[0x013F..0x0143].copy_from_slice(b"AGDE");
+    rom[0x0143] = 0x80;
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    rom[0x0146] = 0x03;
+    rom[0x0147] = 0x03;
+    rom[0x0149] = 0x02;
+    rom[0x014A] = 0x01;
+    rom[0x014B] = 0x33;
+    rom[0x014C] = 0x02;
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom[0x014E..0x0150].copy_from_slice(&[0xBE, 0xEF]);
+    rom
+}
+
+#[test]
+fn the_hexdump_labels_every_field() {
+    let path = env::temp_dir().join(format!("farore-header-{}.gbc", process::id()));
+    fs::write(&path, rom()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_farore")).args(["header", path.to_str().unwrap()]).output().unwrap();
+    let _ = fs::remove_file(&path);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), GOLDEN);
+}
@@ -0,0 +1,139 @@
+//! The CGB infrared port with scripted, looped back and linked light sources.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::infrared::{Infrared, InfraredLink, InfraredSource, Loopback};
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+
+
+const RP: u16 = 0xFF56;
+
+// Light during the listed ranges of T-cycles, recording the LED changes it's told about.
+struct Script {
+    blips: Vec<(u64, u64)>,
+    led: Rc<RefCell<Vec<(bool, u64)>>>,
+}
+
+impl InfraredSource for Script {
+    fn light_seen(&mut self, cycle: u64) -> bool {
+        self.blips.iter().any(|&(start, end)| cycle >= start && cycle < end)
+    }
+
+    fn led_changed(&mut self, on: bool, cycle: u64) {
+        self.led.borrow_mut().push((on, cycle));
+    }
+}
+
+fn sensor(port: &Infrared) -> u8 {
+    port.read(RP) & 0x02
+}
+
+#[test]
+fn nothing_in_front_reads_no_light() {
+    let mut port = Infrared::new(HardwareModel::Cgb);
+    assert_eq!(port.read(RP), 0x3E);
+    port.write(RP, 0xC0);
+    port.tick(1000);
+    assert_eq!(port.read(RP), 0xFE);
+}
+
+#[test]
+fn only_the_enable_led_and_sensor_bits_are_writable() {
+    let mut port = Infrared::new(HardwareModel::Cgb);
+    port.write(RP, 0xFF);
+    assert_eq!(port.read(RP), 0xFF);
+    port.write(RP, 0x3E);
+    assert_eq!(port.read(RP), 0x3E);
+    assert!(!port.led_on());
+}
+
+#[test]
+fn light_is_only_seen_with_both_enable_bits() {
+    for &(enable, expected) in &[(0x00, 0x02), (0x40, 0x02), (0x80, 0x02), (0xC0, 0x00)] {
+        let mut port = Infrared::new(HardwareModel::Cgb);
+        port.set_source(Box::new(Script { blips: vec![(0, 1000)], led: Rc::default() }));
+        port.write(RP, enable);
+        port.tick(4);
+        assert_eq!(sensor(&port), expected, "RP {:02X}", enable);
+    }
+}
+
+#[test]
+fn a_scripted_source_gives_the_expected_transitions() {
+    let mut port = Infrared::new(HardwareModel::Cgb);
+    port.set_source(Box::new(Script { blips: vec![(100, 200), (400, 404)], led: Rc::default() }));
+    port.write(RP, 0xC0);
+
+    let mut readings = Vec::new();
+    for _ in 0..120 {
+        port.tick(4);
+        readings.push(sensor(&port));
+    }
+    let changes: Vec<(usize, u8)> = readings.windows(2).enumerate()
+        .filter(|&(_, pair)| pair[0] != pair[1])
+        .map(|(i, pair)| ((i + 2) * 4, pair[1]))
+        .collect();
+    assert_eq!(changes, [(100, 0x00), (200, 0x02), (400, 0x00), (404, 0x02)]);
+}
+
+#[test]
+fn the_source_hears_about_the_led() {
+    let led = Rc::new(RefCell::new(Vec::new()));
+    let mut port = Infrared::new(HardwareModel::Cgb);
+    port.write(RP, 0x01);
+    port.set_source(Box::new(Script { blips: Vec::new(), led: led.clone() }));
+    port.tick(40);
+    port.write(RP, 0x01);
+    port.write(RP, 0xC0);
+    port.tick(8);
+    port.write(RP, 0xC1);
+
+    // The current state when connected, then only real changes
+    assert_eq!(*led.borrow(), [(true, 0), (false, 40), (true, 48)]);
+}
+
+#[test]
+fn loopback_sees_its_own_led() {
+    let mut port = Infrared::new(HardwareModel::Cgb);
+    port.set_source(Box::new(Loopback::default()));
+    port.write(RP, 0xC1);
+    port.tick(4);
+    assert_eq!(sensor(&port), 0x00);
+    port.write(RP, 0xC0);
+    port.tick(4);
+    assert_eq!(sensor(&port), 0x02);
+}
+
+#[test]
+fn linked_ports_see_each_other() {
+    let mut a = Infrared::new(HardwareModel::Cgb);
+    let mut b = Infrared::new(HardwareModel::Cgb);
+    InfraredLink::connect(&mut a, &mut b);
+    a.write(RP, 0xC0);
+    b.write(RP, 0xC0);
+
+    a.write(RP, 0xC1);
+    a.tick(4);
+    b.tick(4);
+    assert_eq!((sensor(&a), sensor(&b)), (0x02, 0x00));
+
+    a.write(RP, 0xC0);
+    b.write(RP, 0xC1);
+    a.tick(4);
+    b.tick(4);
+    assert_eq!((sensor(&a), sensor(&b)), (0x00, 0x02));
+}
+
+#[test]
+fn dmg_has_no_port() {
+    let mut port = Infrared::new(HardwareModel::Dmg);
+    port.set_source(Box::new(Loopback::default()));
+    port.write(RP, 0xC1);
+    port.tick(4);
+    assert_eq!(port.read(RP), 0xFF);
+    assert!(!port.led_on());
+}
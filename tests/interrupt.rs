@@ -0,0 +1,179 @@
+//! IF and IE, the priority order requests are serviced in, and the request statistics.
+
+extern crate farore;
+
+use farore::interrupt::{CycleStats, Interrupt, InterruptController, InterruptLine, InterruptStats, IrqStats};
+use farore::io::IoPeripheral;
+
+
+const IF: u16 = 0xFF0F;
+const IE: u16 = 0xFFFF;
+
+#[test]
+fn sources_have_their_bits_and_vectors() {
+    let expected = [(0x01, 0x40), (0x02, 0x48), (0x04, 0x50), (0x08, 0x58), (0x10, 0x60)];
+    for (interrupt, &(bit, vector)) in Interrupt::ALL.iter().zip(&expected) {
+        assert_eq!((interrupt.bit(), interrupt.vector()), (bit, vector), "{:?}", interrupt);
+    }
+}
+
+#[test]
+fn requests_are_serviced_highest_priority_first() {
+    let mut controller = InterruptController::new();
+    controller.write(IE, 0x1F);
+    for &interrupt in Interrupt::ALL.iter().rev() {
+        controller.line_mut().request(interrupt);
+    }
+
+    let mut order = Vec::new();
+    while let Some(interrupt) = controller.highest_pending() {
+        controller.acknowledge(interrupt);
+        order.push(interrupt);
+    }
+    assert_eq!(order, Interrupt::ALL);
+    assert_eq!(controller.read(IF), 0xE0);
+}
+
+#[test]
+fn each_pair_resolves_to_the_higher_priority() {
+    for (i, &high) in Interrupt::ALL.iter().enumerate() {
+        for &low in &Interrupt::ALL[i + 1..] {
+            let mut controller = InterruptController::new();
+            controller.write(IE, 0xFF);
+            controller.line_mut().request(low);
+            controller.line_mut().request(high);
+            assert_eq!(controller.highest_pending(), Some(high), "{:?} and {:?}", high, low);
+
+            controller.acknowledge(high);
+            assert_eq!(controller.highest_pending(), Some(low));
+        }
+    }
+}
+
+#[test]
+fn ie_masks_requests() {
+    let mut controller = InterruptController::new();
+    controller.write(IF, 0x1F);
+    assert_eq!(controller.highest_pending(), None);
+
+    // Only Timer and Joypad enabled, so VBlank and STAT wait
+    controller.write(IE, 0x14);
+    assert_eq!(controller.highest_pending(), Some(Interrupt::Timer));
+    controller.acknowledge(Interrupt::Timer);
+    assert_eq!(controller.highest_pending(), Some(Interrupt::Joypad));
+    controller.acknowledge(Interrupt::Joypad);
+    assert_eq!(controller.highest_pending(), None);
+    assert_eq!(controller.read(IF), 0xEB);
+
+    // Enabling them later lets the waiting requests through
+    controller.write(IE, 0x01);
+    assert_eq!(controller.highest_pending(), Some(Interrupt::VBlank));
+}
+
+#[test]
+fn ie_upper_bits_never_enable_anything() {
+    let mut controller = InterruptController::new();
+    controller.write(IE, 0xE0);
+    controller.write(IF, 0xFF);
+    assert_eq!(controller.highest_pending(), None);
+}
+
+#[test]
+fn if_upper_bits_read_as_one() {
+    let mut line = InterruptLine::new();
+    assert_eq!(line.read(), 0xE0);
+    line.write(0x00);
+    assert_eq!(line.read(), 0xE0);
+    line.write(0xFF);
+    assert_eq!(line.read(), 0xFF);
+    line.write(0x35);
+    assert_eq!(line.read(), 0xF5);
+
+    let mut controller = InterruptController::new();
+    controller.line_mut().request(Interrupt::Serial);
+    assert_eq!(controller.read(IF), 0xE8);
+}
+
+#[test]
+fn ie_reads_back_all_eight_bits() {
+    let mut controller = InterruptController::new();
+    controller.write(IE, 0xA5);
+    assert_eq!(controller.read(IE), 0xA5);
+}
+
+#[test]
+fn clearing_one_request_leaves_the_others() {
+    let mut line = InterruptLine::new();
+    line.request(Interrupt::VBlank);
+    line.request(Interrupt::Timer);
+    line.clear(Interrupt::VBlank);
+    assert!(!line.is_requested(Interrupt::VBlank));
+    assert!(line.is_requested(Interrupt::Timer));
+}
+
+#[test]
+fn stats_count_requests_and_services() {
+    let mut controller = InterruptController::new();
+    controller.write(IE, 0x1F);
+    controller.tick(100);
+    controller.line_mut().request(Interrupt::Timer);
+    controller.tick(50);
+    controller.line_mut().request(Interrupt::Timer);
+    controller.acknowledge(Interrupt::Timer);
+
+    // Writing IF counts bits that weren't already set
+    controller.write(IF, 0x05);
+    controller.write(IF, 0x05);
+
+    assert_eq!(controller.stats(Interrupt::Timer), InterruptStats { raised: 3, serviced: 1, last_raised: Some(150) });
+    assert_eq!(controller.stats(Interrupt::VBlank), InterruptStats { raised: 1, serviced: 0, last_raised: Some(150) });
+    assert_eq!(controller.stats(Interrupt::Joypad), InterruptStats::default());
+}
+
+#[test]
+fn irq_stats_measure_latency_and_handler_time() {
+    let mut stats = IrqStats::new();
+    // A timer interrupt waiting 20 cycles for a 100 cycle handler, then 12 for a 140 one
+    stats.dispatched(Interrupt::Timer, 1000, 1020);
+    stats.returned(1120);
+    stats.dispatched(Interrupt::Timer, 5000, 5012);
+    stats.returned(5152);
+
+    assert_eq!(stats.latency(Interrupt::Timer), CycleStats { count: 2, min: 12, max: 20, total: 32 });
+    assert_eq!(stats.handler_time(Interrupt::Timer), CycleStats { count: 2, min: 100, max: 140, total: 240 });
+    assert_eq!(stats.handler_time(Interrupt::Timer).mean(), Some(120.0));
+    assert_eq!(stats.latency(Interrupt::VBlank), CycleStats::default());
+    assert_eq!(stats.latency(Interrupt::VBlank).mean(), None);
+}
+
+#[test]
+fn nested_handlers_return_innermost_first() {
+    let mut stats = IrqStats::new();
+    stats.dispatched(Interrupt::Joypad, 0, 20);
+    stats.dispatched(Interrupt::VBlank, 40, 60);
+    stats.returned(100);
+    stats.returned(300);
+    // A stray RETI changes nothing
+    stats.returned(400);
+
+    assert_eq!(stats.handler_time(Interrupt::VBlank).total, 40);
+    assert_eq!(stats.handler_time(Interrupt::Joypad).total, 280);
+    assert_eq!(stats.handler_time(Interrupt::Joypad).count, 1);
+}
+
+#[test]
+fn irq_stats_print_a_row_per_source() {
+    let mut stats = IrqStats::new();
+    stats.dispatched(Interrupt::VBlank, 100, 116);
+    stats.returned(216);
+    stats.dispatched(Interrupt::VBlank, 200, 220);
+    stats.returned(340);
+    assert_eq!(stats.to_string(), "\
+source   serviced   latency min/mean/max    handler min/mean/max
+VBlank          2       16     18.0     20       100    110.0    120
+LcdStat         0        -        -      -         -        -      -
+Timer           0        -        -      -         -        -      -
+Serial          0        -        -      -         -        -      -
+Joypad          0        -        -      -         -        -      -
+");
+}
@@ -0,0 +1,75 @@
+//! Applying hand-built IPS patches.
+
+extern crate farore;
+
+use farore::ips::{self, IpsSummary};
+
+
+fn record(offset: u32, data: &[u8]) -> Vec<u8> {
+    let mut record = offset.to_be_bytes()[1..].to_vec();
+    record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    record.extend_from_slice(data);
+    record
+}
+
+fn run(offset: u32, len: u16, value: u8) -> Vec<u8> {
+    let mut record = offset.to_be_bytes()[1..].to_vec();
+    record.extend_from_slice(&[0, 0]);
+    record.extend_from_slice(&len.to_be_bytes());
+    record.push(value);
+    record
+}
+
+fn patch(records: &[Vec<u8>], truncate: Option<u32>) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    for record in records {
+        patch.extend_from_slice(record);
+    }
+    patch.extend_from_slice(b"EOF");
+    if let Some(size) = truncate {
+        patch.extend_from_slice(&size.to_be_bytes()[1..]);
+    }
+    patch
+}
+
+#[test]
+fn applies_copies_runs_and_grows_the_rom() {
+    let mut rom = vec![0x11; 8];
+    let patch = patch(&[
+        record(0x0002, &[0xAA, 0x11, 0xBB]), // The middle byte is unchanged
+        run(0x0005, 2, 0x11), // Already 0x11, changes nothing
+        record(0x0007, &[0xCC, 0xDD, 0xEE]), // Grows the ROM by two
+        run(0x000C, 2, 0x77), // Leaves a zero filled gap at 0x0A-0x0B
+    ], None);
+
+    let summary = ips::apply(&mut rom, &patch).unwrap();
+    assert_eq!(rom, [
+        0x11, 0x11, 0xAA, 0x11, 0xBB, 0x11, 0x11, 0xCC,
+        0xDD, 0xEE, 0x00, 0x00, 0x77, 0x77,
+    ]);
+    assert_eq!(summary, IpsSummary { records: 4, bytes_changed: 2 + 3 + 4, truncated_to: None });
+}
+
+#[test]
+fn truncates_after_the_eof_marker() {
+    let mut rom = vec![0x11; 8];
+    let summary = ips::apply(&mut rom, &patch(&[run(0, 2, 0x22)], Some(4))).unwrap();
+    assert_eq!(rom, [0x22, 0x22, 0x11, 0x11]);
+    assert_eq!(summary.truncated_to, Some(4));
+}
+
+#[test]
+fn rejects_bad_patches_without_touching_the_rom() {
+    let good = patch(&[record(0, &[0xAA; 4])], None);
+    let bad = [
+        b"PACTH".to_vec(),
+        b"PATCH".to_vec(), // No EOF marker
+        good[..good.len() - 5].to_vec(), // Cut off inside the record
+        [&good[..], &[1, 2]].concat(), // Junk after EOF
+    ];
+    for patch in bad.iter() {
+        let mut rom = vec![0x11; 8];
+        assert!(ips::apply(&mut rom, patch).is_err(), "{:?}", patch);
+        assert_eq!(rom, [0x11; 8]);
+    }
+}
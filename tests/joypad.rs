@@ -0,0 +1,171 @@
+//! P1 reads for every select combination, and the Joypad interrupt.
+
+extern crate farore;
+
+use std::thread;
+
+use farore::interrupt::{Interrupt, InterruptLine};
+use farore::io::IoPeripheral;
+use farore::joypad::{Button, InputState, Joypad};
+
+
+const P1: u16 = 0xFF00;
+
+// Select values for P1: buttons, d-pad, both, neither.
+const BUTTONS: u8 = 0x10;
+const DPAD: u8 = 0x20;
+const BOTH: u8 = 0x00;
+const NEITHER: u8 = 0x30;
+
+fn pressed(buttons: &[Button]) -> InputState {
+    let mut state = InputState::new();
+    for &button in buttons {
+        state.set(button, true);
+    }
+    state
+}
+
+// The P1 value read with `select` written and `buttons` held.
+fn read(select: u8, buttons: &[Button]) -> u8 {
+    let mut joypad = Joypad::new();
+    joypad.set_inputs(pressed(buttons));
+    joypad.write(P1, select);
+    joypad.read(P1)
+}
+
+#[test]
+fn each_select_reads_its_own_group() {
+    use farore::joypad::Button::*;
+
+    let cases: [(&[Button], [u8; 4]); 6] = [
+        // What's held, then P1 with the buttons, the d-pad, both and neither selected
+        (&[],                 [0xDF, 0xEF, 0xCF, 0xFF]),
+        (&[A],                [0xDE, 0xEF, 0xCE, 0xFF]),
+        (&[Right],            [0xDF, 0xEE, 0xCE, 0xFF]),
+        (&[Start, Down],      [0xD7, 0xE7, 0xC7, 0xFF]),
+        (&[B, Select, Left],  [0xD9, 0xED, 0xC9, 0xFF]),
+        (&[A, B, Select, Start, Right, Left, Up, Down], [0xD0, 0xE0, 0xC0, 0xFF]),
+    ];
+    for &(held, expected) in &cases {
+        for (&select, &value) in [BUTTONS, DPAD, BOTH, NEITHER].iter().zip(&expected) {
+            assert_eq!(read(select, held), value, "{:?} with P1 {:02X}", held, select);
+        }
+    }
+}
+
+#[test]
+fn only_the_select_bits_are_writable() {
+    let mut joypad = Joypad::new();
+    joypad.write(P1, 0x0F);
+    assert_eq!(joypad.read(P1), 0xCF);
+    joypad.write(P1, 0xFF);
+    assert_eq!(joypad.read(P1), 0xFF);
+}
+
+#[test]
+fn pressing_a_selected_input_interrupts() {
+    let mut joypad = Joypad::new();
+    let mut irq = InterruptLine::new();
+    joypad.write(P1, BUTTONS);
+    joypad.tick(&mut irq);
+
+    joypad.set_button(Button::Start, true);
+    joypad.tick(&mut irq);
+    assert!(irq.is_requested(Interrupt::Joypad));
+
+    // Holding it, and letting go, don't
+    irq.clear(Interrupt::Joypad);
+    joypad.tick(&mut irq);
+    joypad.set_button(Button::Start, false);
+    joypad.tick(&mut irq);
+    assert!(!irq.is_requested(Interrupt::Joypad));
+}
+
+#[test]
+fn pressing_an_unselected_input_does_not_interrupt() {
+    let mut joypad = Joypad::new();
+    let mut irq = InterruptLine::new();
+    joypad.write(P1, DPAD);
+    joypad.tick(&mut irq);
+    joypad.set_button(Button::A, true);
+    joypad.tick(&mut irq);
+    assert!(!irq.is_requested(Interrupt::Joypad));
+
+    joypad.write(P1, NEITHER);
+    joypad.set_button(Button::Up, true);
+    joypad.tick(&mut irq);
+    assert!(!irq.is_requested(Interrupt::Joypad));
+}
+
+#[test]
+fn selecting_a_group_with_an_input_held_interrupts() {
+    let mut joypad = Joypad::new();
+    let mut irq = InterruptLine::new();
+    joypad.set_button(Button::B, true);
+    joypad.tick(&mut irq);
+    assert!(!irq.is_requested(Interrupt::Joypad));
+
+    joypad.write(P1, BUTTONS);
+    joypad.tick(&mut irq);
+    assert!(irq.is_requested(Interrupt::Joypad));
+}
+
+#[test]
+fn a_second_line_falling_interrupts_again() {
+    let mut joypad = Joypad::new();
+    let mut irq = InterruptLine::new();
+    joypad.write(P1, DPAD);
+    joypad.set_button(Button::Left, true);
+    joypad.tick(&mut irq);
+    irq.clear(Interrupt::Joypad);
+
+    joypad.set_button(Button::Up, true);
+    joypad.tick(&mut irq);
+    assert!(irq.is_requested(Interrupt::Joypad));
+}
+
+#[test]
+fn opposing_directions_can_be_filtered() {
+    let held = pressed(&[Button::Left, Button::Right, Button::Up]);
+    let mut joypad = Joypad::new();
+    joypad.set_inputs(held);
+    joypad.write(P1, DPAD);
+    assert_eq!(joypad.read(P1) & 0x0F, 0x08);
+
+    joypad.set_opposing_filter(true);
+    assert_eq!(joypad.read(P1) & 0x0F, 0x0B);
+}
+
+#[test]
+fn inputs_can_be_set_from_another_thread() {
+    let mut joypad = Joypad::new();
+    let input = joypad.input();
+    thread::spawn(move || {
+        input.set_button(Button::A, true);
+        input.set_button(Button::Down, true);
+        input.set_button(Button::Down, false);
+    }).join().unwrap();
+
+    assert_eq!(joypad.input().inputs(), pressed(&[Button::A]));
+    joypad.write(P1, BUTTONS);
+    assert_eq!(joypad.read(P1), 0xDE);
+}
+
+#[test]
+fn unrelated_addresses_are_unmapped() {
+    let mut joypad = Joypad::new();
+    joypad.write(0xFF01, 0x00);
+    assert_eq!(joypad.read(0xFF01), 0xFF);
+    assert_eq!(joypad.read(P1), 0xFF);
+}
+
+#[test]
+fn buttons_are_looked_up_by_name() {
+    for &button in &Button::ALL {
+        assert_eq!(Button::named(button.name()), Some(button));
+        assert_eq!(Button::named(&button.name().to_uppercase()), Some(button));
+    }
+    let bits: Vec<InputState> = Button::ALL.iter().map(|&button| pressed(&[button])).collect();
+    assert_eq!(bits, (0..8).map(|bit| InputState::from_bits(1 << bit)).collect::<Vec<InputState>>());
+    assert_eq!(Button::named("turbo"), None);
+}
@@ -0,0 +1,52 @@
+//! Parsing JSON and writing it back out.
+
+extern crate farore;
+
+use farore::json::Json;
+
+
+#[test]
+fn documents_parse_into_values() {
+    let json = Json::parse(r#" {"a": [1, -2, true, false, null], "b": {"c": "d\"\\\/\n\u00e9\ud83d\ude00"}, "e": []} "#).unwrap();
+    assert_eq!(json.get("a").and_then(Json::as_array), Some(&[
+        Json::Number(1),
+        Json::Number(-2),
+        Json::Bool(true),
+        Json::Bool(false),
+        Json::Null,
+    ][..]));
+    assert_eq!(json.get("b").and_then(|b| b.get("c")).and_then(Json::as_str), Some("d\"\\/\n\u{e9}\u{1F600}"));
+    assert_eq!(json.get("e"), Some(&Json::Array(Vec::new())));
+    assert_eq!(json.get("f"), None);
+    assert_eq!(Json::Number(1).get("a"), None);
+}
+
+#[test]
+fn output_parses_back() {
+    let json = Json::object(vec![
+        ("title", Json::string("TETRIS \"DX\"\t")),
+        ("banks", Json::Number(64)),
+        ("ok", Json::Bool(true)),
+        ("errors", Json::Array(vec![Json::Null, Json::string("\u{1}")])),
+    ]);
+    assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+}
+
+#[test]
+fn broken_documents_say_where() {
+    let cases = [
+        ("", "invalid JSON at character 0: the text ended early"),
+        ("[1, 2", "invalid JSON at character 5: expected , or ]"),
+        ("{\"a\" 1}", "invalid JSON at character 5: expected :"),
+        ("{1: 2}", "invalid JSON at character 1: expected a key"),
+        ("1.5", "invalid JSON at character 1: only whole numbers are supported"),
+        ("\"abc", "invalid JSON at character 4: unterminated string"),
+        ("\"\\ud800\"", "invalid JSON at character 7: expected \\u"),
+        ("nul", "invalid JSON at character 3: expected null"),
+        ("[] []", "invalid JSON at character 3: expected the end of the text"),
+        ("99999999999999999999", "invalid JSON at character 20: expected a number that fits 64 bits"),
+    ];
+    for &(text, message) in &cases {
+        assert_eq!(Json::parse(text).unwrap_err().to_string(), message, "{:?}", text);
+    }
+}
@@ -0,0 +1,149 @@
+//! The library the way a crate depending on farore for header parsing and patching uses it.
+
+extern crate farore;
+
+use farore::bps;
+use farore::cart::{self, CartridgeType, GameboyProgramMeta, MbcKind, RamSize, Repairs};
+use farore::crc::crc32;
+use farore::ips;
+
+
+// A 64KiB MBC5+RAM+BATTERY ROM with 32KiB of RAM and correct checksums.
+fn rom(title: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0xFF; 0x10000];
+    rom[0x0134..0x0144].copy_from_slice(&[0; 16]);
+    rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+    rom[0x0146] = 0x00;
+    rom[0x0147] = 0x1B;
+    rom[0x0148] = 0x01;
+    rom[0x0149] = 0x03;
+    rom[0x014A] = 0x01;
+    rom[0x014B] = 0x33;
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    rom
+}
+
+fn number(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(low | 0x80);
+            return;
+        }
+        out.push(low);
+        value -= 1;
+    }
+}
+
+// A BPS patch keeping everything outside the header from the source and taking the header
+// and checksums from the target.
+fn header_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    number(&mut patch, source.len());
+    number(&mut patch, target.len());
+    number(&mut patch, 0);
+    number(&mut patch, (0x0134 - 1) << 2); // SourceRead
+    number(&mut patch, (cart::HEADER_END - 0x0134 - 1) << 2 | 1); // TargetRead
+    patch.extend_from_slice(&target[0x0134..cart::HEADER_END]);
+    number(&mut patch, (target.len() - cart::HEADER_END - 1) << 2); // SourceRead
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc32 = crc32(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    patch
+}
+
+#[test]
+fn parses_a_header() {
+    let rom = rom(b"LIBRARY");
+    let meta = GameboyProgramMeta::new(&rom).unwrap();
+    assert_eq!(meta.name, "LIBRARY");
+    assert_eq!(meta.program_size, 0x10000);
+    assert_eq!(meta.cart_type(), CartridgeType::Mbc5RamBattery);
+    assert_eq!(meta.cart_type().mbc_kind(), Some(MbcKind::Mbc5));
+    assert!(meta.cart_type().has_battery());
+    assert_eq!(meta.declared_size(), Some(0x10000));
+    assert!(meta.is_runable());
+    assert!(meta.is_valid_size());
+    assert!(!meta.supports_cgb());
+}
+
+#[test]
+fn reads_the_declared_ram_size() {
+    let rom = rom(b"LIBRARY");
+    let ram = GameboyProgramMeta::new(&rom).unwrap().declared_ram_size();
+    assert_eq!(ram, RamSize::Banked { bytes: 32 * 1024, banks: 4 });
+    assert_eq!((ram.bytes(), ram.banks()), (32 * 1024, 4));
+    assert_eq!(ram.to_string(), "32 KiB (4 banks of 8 KiB)");
+
+    // MBC2 carts declare none but have their own
+    let mut mbc2 = rom.clone();
+    mbc2[0x0147] = 0x06;
+    mbc2[0x0149] = 0x00;
+    assert_eq!(GameboyProgramMeta::new(&mbc2).unwrap().declared_ram_size(), RamSize::Mbc2);
+
+    for &byte in &[0x01, 0x42] {
+        let mut unknown = rom.clone();
+        unknown[0x0149] = byte;
+        let meta = GameboyProgramMeta::new(&unknown).unwrap();
+        assert_eq!(meta.declared_ram_size(), RamSize::Invalid(byte));
+        assert!(!meta.is_valid_ram_size());
+        assert!(meta.validation_json().to_string().contains("\"ram_size\":false"), "{}", meta.validation_json());
+    }
+    assert!(GameboyProgramMeta::new(&rom).unwrap().validation_json().to_string().contains("\"ram_size\":true"));
+}
+
+#[test]
+fn an_owned_header_outlives_its_rom() {
+    let meta = GameboyProgramMeta::from_vec(rom(b"OWNED")).unwrap();
+    assert_eq!(meta.name, "OWNED");
+    assert_eq!(meta.to_json().to_string(), GameboyProgramMeta::new(&rom(b"OWNED")).unwrap().to_json().to_string());
+}
+
+#[test]
+fn ips_patches_round_trip_through_the_header() {
+    let original = rom(b"LIBRARY");
+    let target = rom(b"PATCHED");
+
+    // One record per run of differing bytes
+    let mut patch = b"PATCH".to_vec();
+    let mut offset = 0;
+    while offset < original.len() {
+        if original[offset] == target[offset] {
+            offset += 1;
+            continue;
+        }
+        let end = (offset..original.len()).find(|&i| original[i] == target[i]).unwrap_or(original.len());
+        patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - offset) as u16).to_be_bytes());
+        patch.extend_from_slice(&target[offset..end]);
+        offset = end;
+    }
+    patch.extend_from_slice(b"EOF");
+
+    let mut patched = original.clone();
+    let summary = ips::apply(&mut patched, &patch).unwrap();
+    assert_eq!(patched, target);
+    assert!(summary.records > 0);
+    let meta = GameboyProgramMeta::new(&patched).unwrap();
+    assert_eq!(meta.name, "PATCHED");
+    assert!(meta.is_valid_header() && meta.is_valid_program());
+}
+
+#[test]
+fn bps_patches_round_trip_through_the_header() {
+    let original = rom(b"LIBRARY");
+    let target = rom(b"PATCHED");
+    let patch = header_bps(&original, &target);
+
+    let parsed = bps::BpsPatch::parse(&patch).unwrap();
+    assert_eq!((parsed.source_size, parsed.target_size), (original.len(), target.len()));
+    let patched = bps::apply(&original, &patch).unwrap();
+    assert_eq!(patched, target);
+    assert_eq!(GameboyProgramMeta::new(&patched).unwrap().name, "PATCHED");
+
+    // It only applies to the ROM it was made for
+    assert!(bps::apply(&target, &patch).is_err());
+}
@@ -0,0 +1,96 @@
+//! Publisher names looked up from the old and new licensee codes.
+
+extern crate farore;
+
+use farore::cart::GameboyProgramMeta;
+use farore::licensee::{Licensee, LicenseeCode};
+
+
+// A ROM with the old code `old` at 0x014B and the characters `new` at 0x0144-0x0145.
+fn rom(old: u8, new: &[u8; 2]) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0144..0x0146].copy_from_slice(new);
+    rom[0x014B] = old;
+    rom
+}
+
+#[test]
+fn old_codes_name_their_publishers() {
+    for &(code, name) in &[
+        (0x00, "None"),
+        (0x01, "Nintendo"),
+        (0x08, "Capcom"),
+        (0x13, "EA (Electronic Arts)"),
+        (0x31, "Nintendo"),
+        (0x34, "Konami"),
+        (0x52, "Activision"),
+        (0xA4, "Konami"),
+        (0xB4, "Square Enix"),
+        (0xC3, "Square"),
+        (0xFF, "LJN"),
+    ] {
+        assert_eq!(LicenseeCode::Old(code).lookup(), Licensee::Known(name), "0x{:02X}", code);
+    }
+}
+
+#[test]
+fn new_codes_name_their_publishers() {
+    for &(code, name) in &[
+        (b"00", "None"),
+        (b"01", "Nintendo"),
+        (b"08", "Capcom"),
+        (b"33", "Ocean Software/Acclaim Entertainment"),
+        (b"54", "Konami"),
+        (b"9H", "Bottom Up"),
+        (b"A4", "Konami (Yu-Gi-Oh!)"),
+        (b"BL", "MTO"),
+        (b"DK", "Kodansha"),
+    ] {
+        assert_eq!(LicenseeCode::New(*code).lookup(), Licensee::Known(name), "{:?}", code);
+    }
+}
+
+#[test]
+fn codes_not_in_the_tables_are_unknown() {
+    for &(code, shown) in &[
+        (LicenseeCode::Old(0x02), "unknown (0x02)"),
+        (LicenseeCode::Old(0xFE), "unknown (0xFE)"),
+        (LicenseeCode::New(*b"02"), "unknown (\"02\")"),
+        (LicenseeCode::New(*b"a4"), "unknown (\"a4\")"),
+        (LicenseeCode::New([0x00, 0xFF]), "unknown (\"\\x00\\xff\")"),
+    ] {
+        assert_eq!(code.lookup(), Licensee::Unknown(code));
+        assert_eq!(code.lookup().to_string(), shown);
+    }
+}
+
+#[test]
+fn the_header_picks_the_code_in_use() {
+    for &(old, new, code, name) in &[
+        (0x33, b"01", LicenseeCode::New(*b"01"), "Nintendo"),
+        (0x33, b"8\0", LicenseeCode::New(*b"8\0"), "unknown (\"8\\x00\")"),
+        (0x01, b"08", LicenseeCode::Old(0x01), "Nintendo"),
+        (0x08, b"\0\0", LicenseeCode::Old(0x08), "Capcom"),
+        (0x02, b"01", LicenseeCode::Old(0x02), "unknown (0x02)"),
+    ] {
+        let rom = rom(old, new);
+        assert_eq!(LicenseeCode::from_header(&rom), code);
+        let meta = GameboyProgramMeta::new(&rom).unwrap();
+        assert_eq!(meta.licensee_name(), code.lookup());
+        assert_eq!(meta.licensee_name().to_string(), name);
+    }
+}
+
+#[test]
+fn the_debug_output_shows_the_name_and_code() {
+    for &(old, new, line) in &[
+        (0x33, b"01", "licensee: Nintendo (\"01\")"),
+        (0xC3, b"\0\0", "licensee: Square (0xC3)"),
+        (0x33, b"ZZ", "licensee: unknown (\"ZZ\")"),
+    ] {
+        let mut out = Vec::new();
+        GameboyProgramMeta::new(&rom(old, new)).unwrap().print_debug(&mut out);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().any(|printed| printed == line), "{}", out);
+    }
+}
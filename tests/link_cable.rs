@@ -0,0 +1,105 @@
+//! Two serial ports linked in-process, stepped in lockstep.
+
+extern crate farore;
+
+use farore::interrupt::{Interrupt, InterruptLine};
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::serial::{LinkCable, Serial};
+
+
+const SB: u16 = 0xFF01;
+const SC: u16 = 0xFF02;
+
+struct Side {
+    serial: Serial,
+    irq: InterruptLine,
+}
+
+fn linked() -> (Side, Side) {
+    let mut a = Serial::new(HardwareModel::Dmg);
+    let mut b = Serial::new(HardwareModel::Dmg);
+    LinkCable::connect(&mut a, &mut b);
+    (Side { serial: a, irq: InterruptLine::new() }, Side { serial: b, irq: InterruptLine::new() })
+}
+
+fn start(side: &mut Side, sb: u8, sc: u8) {
+    side.serial.write(SB, sb);
+    side.serial.write(SC, sc);
+}
+
+// Steps both sides an M-cycle at a time, for `cycles` T-cycles.
+fn run(a: &mut Side, b: &mut Side, cycles: u32) {
+    for _ in 0..cycles / 4 {
+        a.serial.tick(4, &mut a.irq);
+        b.serial.tick(4, &mut b.irq);
+    }
+}
+
+#[test]
+fn a_counter_goes_both_ways_in_order() {
+    let (mut a, mut b) = linked();
+    let mut received = (Vec::new(), Vec::new());
+    for count in 0..8u8 {
+        // The externally clocked side gets ready first, like a game waiting to receive
+        start(&mut b, 0x80 | count, 0x80);
+        run(&mut a, &mut b, 64);
+        start(&mut a, count, 0x81);
+        run(&mut a, &mut b, 8 * 512 + 8);
+
+        assert!(!a.serial.is_transferring() && !b.serial.is_transferring(), "byte {}", count);
+        assert!(a.irq.is_requested(Interrupt::Serial) && b.irq.is_requested(Interrupt::Serial));
+        a.irq.clear(Interrupt::Serial);
+        b.irq.clear(Interrupt::Serial);
+        received.0.push(a.serial.read(SB));
+        received.1.push(b.serial.read(SB));
+    }
+    assert_eq!(received.0, (0..8).map(|count| 0x80 | count).collect::<Vec<u8>>());
+    assert_eq!(received.1, (0..8).collect::<Vec<u8>>());
+}
+
+#[test]
+fn either_side_can_drive_the_clock() {
+    let (mut a, mut b) = linked();
+    start(&mut a, 0x12, 0x80);
+    run(&mut a, &mut b, 16);
+    start(&mut b, 0x34, 0x81);
+    run(&mut a, &mut b, 8 * 512 + 8);
+    assert_eq!((a.serial.read(SB), b.serial.read(SB)), (0x34, 0x12));
+}
+
+#[test]
+fn a_side_not_listening_misses_the_byte() {
+    let (mut a, mut b) = linked();
+    start(&mut a, 0x55, 0x81);
+    run(&mut a, &mut b, 8 * 512 + 8);
+    assert_eq!(a.serial.read(SB), 0xFF);
+    assert!(a.irq.is_requested(Interrupt::Serial));
+
+    // Starting to listen afterwards gets nothing stale
+    start(&mut b, 0x66, 0x80);
+    run(&mut a, &mut b, 8 * 512);
+    assert!(b.serial.is_transferring());
+    assert_eq!(b.serial.read(SB), 0x66);
+}
+
+#[test]
+fn both_internal_read_ones() {
+    let (mut a, mut b) = linked();
+    start(&mut a, 0x12, 0x81);
+    start(&mut b, 0x34, 0x81);
+    run(&mut a, &mut b, 8 * 512 + 8);
+    assert_eq!((a.serial.read(SB), b.serial.read(SB)), (0xFF, 0xFF));
+    assert!(a.irq.is_requested(Interrupt::Serial) && b.irq.is_requested(Interrupt::Serial));
+}
+
+#[test]
+fn both_external_wait_forever() {
+    let (mut a, mut b) = linked();
+    start(&mut a, 0x12, 0x80);
+    start(&mut b, 0x34, 0x80);
+    run(&mut a, &mut b, 64 * 512);
+    assert!(a.serial.is_transferring() && b.serial.is_transferring());
+    assert_eq!((a.serial.read(SB), b.serial.read(SB)), (0x12, 0x34));
+    assert!(!a.irq.is_requested(Interrupt::Serial) && !b.irq.is_requested(Interrupt::Serial));
+}
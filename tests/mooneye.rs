@@ -0,0 +1,42 @@
+//! Reading mooneye-gb results from the registers at the magic breakpoint.
+
+extern crate farore;
+
+use farore::mooneye::MooneyeResult;
+
+
+#[test]
+fn fibonacci_numbers_pass() {
+    let result = MooneyeResult::from_registers([3, 5, 8, 13, 21, 34]);
+    assert_eq!(result, Some(MooneyeResult::Pass));
+    assert!(result.unwrap().passed());
+}
+
+#[test]
+fn all_0x42_fails() {
+    let result = MooneyeResult::from_registers([0x42; 6]);
+    assert_eq!(result, Some(MooneyeResult::Fail));
+    assert!(!result.unwrap().passed());
+}
+
+#[test]
+fn anything_else_is_no_result() {
+    let cases = [
+        [0; 6],
+        [3, 5, 8, 13, 21, 35],
+        [34, 21, 13, 8, 5, 3],
+        [0x42, 0x42, 0x42, 0x42, 0x42, 0x00],
+    ];
+    for registers in &cases {
+        assert_eq!(MooneyeResult::from_registers(*registers), None, "{:?}", registers);
+    }
+}
+
+#[test]
+fn results_print_for_the_table() {
+    let printed: Vec<String> = [MooneyeResult::Pass, MooneyeResult::Fail, MooneyeResult::Timeout]
+        .iter()
+        .map(|result| result.to_string())
+        .collect();
+    assert_eq!(printed, ["pass", "fail", "timeout"]);
+}
@@ -0,0 +1,193 @@
+//! Recording input movies and playing them back to the same frames.
+
+extern crate farore;
+
+use std::fs;
+use std::path::Path;
+
+use farore::error::FaroreError;
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::joypad::{Button, InputState, Joypad};
+use farore::model::HardwareModel;
+use farore::movie::{InputMovie, MoviePlayer, MovieRecorder};
+use farore::ppu::{Ppu, DOTS_PER_FRAME, DOTS_PER_LINE};
+
+
+const ROM_CRC32: u32 = 0x1234_ABCD;
+
+fn corpus(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join("movie").join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e))
+}
+
+fn held(buttons: &[Button]) -> InputState {
+    let mut state = InputState::new();
+    for &button in buttons {
+        state.set(button, true);
+    }
+    state
+}
+
+// The scripted inputs: a few frames of each.
+fn script() -> Vec<InputState> {
+    let mut script = Vec::new();
+    for &buttons in &[&[][..], &[Button::Right], &[Button::Right, Button::Down], &[Button::A], &[Button::Left]] {
+        script.extend(vec![held(buttons); 3]);
+    }
+    script
+}
+
+// A stand-in for a game, with the PPU showing a striped background.  Halfway down every frame
+// it reads the joypad: the d-pad scrolls and A toggles the background.
+struct Game {
+    ppu: Ppu,
+    joypad: Joypad,
+    irq: InterruptLine,
+}
+
+impl Game {
+    fn new() -> Self {
+        let mut ppu = Ppu::new(HardwareModel::Dmg);
+        for row in 0..8 {
+            ppu.write_vram(0x8010 + row * 2, 0xF0);
+            ppu.write_vram(0x8020 + row * 2 + 1, 0x3C);
+        }
+        for i in 0..0x400 {
+            ppu.write_vram(0x9800 + i, (i % 3) as u8);
+        }
+        ppu.write(0xFF40, 0x91);
+        Game { ppu, joypad: Joypad::new(), irq: InterruptLine::new() }
+    }
+
+    // Runs a frame, returning its hash.
+    fn frame(&mut self) -> u64 {
+        self.ppu.tick(72 * DOTS_PER_LINE, &mut self.irq);
+        self.joypad.write(0xFF00, 0x20);
+        let dpad = !self.joypad.read(0xFF00) & 0x0F;
+        self.joypad.write(0xFF00, 0x10);
+        let buttons = !self.joypad.read(0xFF00) & 0x0F;
+
+        let (scx, scy) = (self.ppu.read(0xFF43), self.ppu.read(0xFF42));
+        let (right, left) = ((dpad & 0x01 != 0) as u8 * 3, (dpad & 0x02 != 0) as u8 * 5);
+        self.ppu.write(0xFF43, scx.wrapping_add(right).wrapping_sub(left));
+        self.ppu.write(0xFF42, scy.wrapping_add((dpad & 0x08 != 0) as u8 * 2));
+        if buttons & 0x01 != 0 {
+            let lcdc = self.ppu.read(0xFF40);
+            self.ppu.write(0xFF40, lcdc ^ 0x01);
+        }
+
+        self.ppu.tick(DOTS_PER_FRAME - 72 * DOTS_PER_LINE, &mut self.irq);
+        self.ppu.frame().hash()
+    }
+}
+
+// Plays the script by hand, setting each frame's buttons as it starts, and records it.
+fn record() -> (InputMovie, Vec<u64>) {
+    let mut game = Game::new();
+    let recorder = MovieRecorder::new(InputMovie::new(ROM_CRC32, HardwareModel::Dmg, true), game.joypad.input());
+    game.ppu.set_frame_callback(recorder.frame_callback());
+
+    let input = game.joypad.input();
+    let hashes = script().into_iter().map(|buttons| {
+        input.set_inputs(buttons);
+        game.frame()
+    }).collect();
+    (recorder.movie(), hashes)
+}
+
+#[test]
+fn playback_reproduces_every_frame() {
+    let (movie, recorded) = record();
+    assert_eq!(movie.frames, script());
+
+    let mut game = Game::new();
+    let player = MoviePlayer::new(movie, game.joypad.input(), ROM_CRC32).unwrap();
+    game.ppu.set_frame_callback(player.frame_callback());
+    let replayed: Vec<u64> = (0..recorded.len()).map(|_| game.frame()).collect();
+    assert_eq!(replayed, recorded);
+    assert!(player.is_finished());
+
+    // The inputs made a difference, or this proves nothing
+    let mut idle = Game::new();
+    let untouched: Vec<u64> = (0..recorded.len()).map(|_| idle.frame()).collect();
+    assert_ne!(untouched, recorded);
+}
+
+#[test]
+fn a_movie_survives_saving_and_loading() {
+    let (movie, _) = record();
+    let mut file = Vec::new();
+    movie.write(&mut file).unwrap();
+    assert_eq!(file.len(), 0x10 + script().len());
+    assert_eq!(&file[..4], b"FGBM");
+    assert_eq!(InputMovie::read(&mut &file[..]).unwrap(), movie);
+}
+
+#[test]
+fn reads_the_corpus_movie() {
+    let movie = InputMovie::read(&mut &corpus("valid")[..]).unwrap();
+    assert_eq!(movie.rom_crc32, ROM_CRC32);
+    assert_eq!(movie.model, HardwareModel::Dmg);
+    assert!(movie.from_reset);
+    assert_eq!(movie.frames, [InputState::from_bits(0x00), InputState::from_bits(0x01), InputState::from_bits(0x80)]);
+}
+
+#[test]
+fn damaged_movies_are_rejected() {
+    let rejection = |data: &[u8]| match InputMovie::read(&mut &data[..]) {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(rejection(&corpus("bad-model")), "invalid input movie: unknown model 7");
+    assert_eq!(rejection(&corpus("huge-frame-count")), "invalid input movie: the movie has 0 frames, the header says 4294967295");
+    assert_eq!(rejection(&corpus("short-header")), "invalid input movie: the header is truncated");
+
+    let mut file = corpus("valid");
+    file[4] = 2;
+    assert_eq!(rejection(&file), "invalid input movie: unsupported movie version 2");
+    let mut file = corpus("valid");
+    file[0] = b'X';
+    assert_eq!(rejection(&file), "invalid input movie: not an input movie");
+    let file = corpus("valid");
+    assert_eq!(rejection(&file[..file.len() - 1]), "invalid input movie: the movie has 2 frames, the header says 3");
+}
+
+#[test]
+fn movies_load_from_files() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join("movie");
+    assert_eq!(InputMovie::load(&path.join("valid")).unwrap().frames.len(), 3);
+    match InputMovie::load(&path.join("missing")) {
+        Err(FaroreError::Io { .. }) => {},
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn playing_on_another_rom_is_refused() {
+    let (movie, _) = record();
+    let joypad = Joypad::new();
+    match MoviePlayer::new(movie, joypad.input(), 0xDEAD_BEEF) {
+        Err(FaroreError::RomMismatch { expected_crc32, found_crc32 }) => {
+            assert_eq!((expected_crc32, found_crc32), (ROM_CRC32, 0xDEAD_BEEF));
+        },
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn inputs_are_left_alone_after_the_last_frame() {
+    let mut movie = InputMovie::new(ROM_CRC32, HardwareModel::Dmg, true);
+    movie.frames = vec![held(&[Button::A]), held(&[Button::B])];
+    let joypad = Joypad::new();
+    let player = MoviePlayer::new(movie, joypad.input(), ROM_CRC32).unwrap();
+    assert_eq!(joypad.input().inputs(), held(&[Button::A]));
+    player.advance_frame();
+    assert_eq!(joypad.input().inputs(), held(&[Button::B]));
+
+    player.advance_frame();
+    assert!(player.is_finished());
+    joypad.set_button(Button::Start, true);
+    player.advance_frame();
+    assert_eq!(joypad.input().inputs(), held(&[Button::B, Button::Start]));
+}
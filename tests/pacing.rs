@@ -0,0 +1,140 @@
+//! The frame pacer driven by made up clocks.
+
+extern crate farore;
+
+use std::time::Duration;
+
+use farore::pacing::{FramePacer, Pacing, FRAME_TIME};
+
+
+// Runs a host loop that sleeps exactly as long as asked, plus `jitter` on every wakeup, until
+// `until`.  Returns the frames emulated and the number of wakeups.
+fn run(pacer: &mut FramePacer, until: Duration, jitter: Duration) -> (u64, u64) {
+    let (mut now, mut frames, mut wakeups) = (Duration::ZERO, 0, 0);
+    while now < until {
+        let pacing = pacer.pace(now);
+        frames += pacing.frames as u64;
+        wakeups += 1;
+        now += pacing.sleep + jitter;
+    }
+    (frames, wakeups)
+}
+
+// How many frames fall due in `time` at `speed`, counting the one at time 0.
+fn frames_in(time: Duration, speed: f64) -> u64 {
+    (time.as_nanos() as f64 * speed / FRAME_TIME.as_nanos() as f64).ceil() as u64
+}
+
+#[test]
+fn real_time_is_about_60_frames_a_second() {
+    assert_eq!(FRAME_TIME, Duration::from_nanos(16_742_706));
+    let mut pacer = FramePacer::new();
+    let (frames, wakeups) = run(&mut pacer, Duration::from_secs(10), Duration::ZERO);
+    assert_eq!(frames, frames_in(Duration::from_secs(10), 1.0));
+    assert_eq!(frames, 598);
+    assert_eq!(wakeups, frames);
+}
+
+#[test]
+fn the_first_frame_is_due_right_away() {
+    let mut pacer = FramePacer::new();
+    let start = Duration::from_secs(1000);
+    assert_eq!(pacer.pace(start), Pacing { frames: 1, sleep: FRAME_TIME });
+    assert_eq!(pacer.pace(start + FRAME_TIME / 2), Pacing { frames: 0, sleep: FRAME_TIME / 2 });
+    assert_eq!(pacer.pace(start + FRAME_TIME), Pacing { frames: 1, sleep: FRAME_TIME });
+}
+
+#[test]
+fn turbo_runs_four_frames_per_host_frame() {
+    let mut pacer = FramePacer::new();
+    pacer.set_speed(Some(4.0));
+    let (frames, _) = run(&mut pacer, Duration::from_secs(5), Duration::ZERO);
+    assert_eq!(frames, frames_in(Duration::from_secs(5), 4.0));
+
+    // A host that only wakes at its own 60Hz gets its frames in batches
+    let mut pacer = FramePacer::new();
+    pacer.set_speed(Some(4.0));
+    let batches: Vec<u32> = (0..10).map(|i| pacer.pace(FRAME_TIME * i).frames).collect();
+    assert_eq!(batches, [1, 4, 4, 4, 4, 4, 4, 4, 4, 4]);
+}
+
+#[test]
+fn slow_motion_runs_every_other_host_frame() {
+    let mut pacer = FramePacer::new();
+    pacer.set_speed(Some(0.5));
+    let (frames, _) = run(&mut pacer, Duration::from_secs(10), Duration::ZERO);
+    assert_eq!(frames, frames_in(Duration::from_secs(10), 0.5));
+
+    let mut pacer = FramePacer::new();
+    pacer.set_speed(Some(0.5));
+    let batches: Vec<u32> = (0..8).map(|i| pacer.pace(FRAME_TIME * i).frames).collect();
+    assert_eq!(batches, [1, 0, 1, 0, 1, 0, 1, 0]);
+    assert_eq!(pacer.pace(FRAME_TIME * 8 + FRAME_TIME / 2).sleep, FRAME_TIME + FRAME_TIME / 2);
+}
+
+#[test]
+fn late_wakeups_do_not_drift() {
+    // Waking 3ms late every time still averages out to real time
+    let mut pacer = FramePacer::new();
+    let (frames, wakeups) = run(&mut pacer, Duration::from_secs(10), Duration::from_millis(3));
+    let expected = frames_in(Duration::from_secs(10), 1.0);
+    assert!(frames >= expected - 1 && frames <= expected, "{} frames, expected {}", frames, expected);
+    assert!(wakeups <= frames);
+}
+
+#[test]
+fn a_long_stall_drops_the_missed_frames() {
+    let mut pacer = FramePacer::new();
+    pacer.pace(Duration::ZERO);
+    let pacing = pacer.pace(Duration::from_secs(2));
+    assert_eq!(pacing, Pacing { frames: 1, sleep: FRAME_TIME });
+
+    // A short one is caught up on
+    let mut pacer = FramePacer::new();
+    pacer.pace(Duration::ZERO);
+    assert_eq!(pacer.pace(FRAME_TIME * 4).frames, 4);
+}
+
+#[test]
+fn pause_keeps_asking_to_be_called_back() {
+    let mut pacer = FramePacer::new();
+    pacer.pace(Duration::ZERO);
+    pacer.set_paused(true);
+    assert!(pacer.is_paused());
+    for i in 1..100 {
+        assert_eq!(pacer.pace(FRAME_TIME * i), Pacing { frames: 0, sleep: FRAME_TIME });
+    }
+
+    // Unpausing picks up from now rather than catching up the paused time
+    pacer.set_paused(false);
+    assert_eq!(pacer.pace(FRAME_TIME * 100), Pacing { frames: 1, sleep: FRAME_TIME });
+    assert_eq!(pacer.pace(FRAME_TIME * 101).frames, 1);
+}
+
+#[test]
+fn uncapped_never_sleeps() {
+    let mut pacer = FramePacer::new();
+    pacer.set_speed(None);
+    for i in 0..10 {
+        assert_eq!(pacer.pace(Duration::from_micros(i)), Pacing { frames: 1, sleep: Duration::ZERO });
+    }
+
+    // And nonsense speeds mean uncapped
+    for &speed in &[0.0, -1.0] {
+        pacer.set_speed(Some(speed));
+        assert_eq!(pacer.speed(), None);
+    }
+}
+
+#[test]
+fn changing_speed_restarts_the_schedule() {
+    let mut pacer = FramePacer::new();
+    for i in 0..10 {
+        pacer.pace(FRAME_TIME * i);
+    }
+    pacer.set_speed(Some(4.0));
+    let now = FRAME_TIME * 10 + Duration::from_millis(1);
+    let pacing = pacer.pace(now);
+    assert_eq!(pacing.frames, 1);
+    assert!((pacing.sleep.as_nanos() as i128 - (FRAME_TIME / 4).as_nanos() as i128).abs() <= 1, "{:?}", pacing);
+}
@@ -0,0 +1,1028 @@
+//! PPU timing dot by dot, STAT and its interrupt, what ends up in the frame, and the debug views.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::interrupt::{Interrupt, InterruptLine};
+use farore::io::{IoPeripheral, Peek};
+use farore::model::HardwareModel;
+use farore::palette::DisplayPalette;
+use farore::ppu::dump::{MapRect, OamVisibility, Swatch, TileMapSelect};
+use farore::ppu::{rgb555_to_rgb, Mode, Ppu, PpuConfig, Renderer, DOTS_PER_FRAME, DOTS_PER_LINE, SCREEN_WIDTH};
+
+
+const LCDC: u16 = 0xFF40;
+const STAT: u16 = 0xFF41;
+const SCY: u16 = 0xFF42;
+const SCX: u16 = 0xFF43;
+const LY: u16 = 0xFF44;
+const LYC: u16 = 0xFF45;
+const BGP: u16 = 0xFF47;
+const OBP0: u16 = 0xFF48;
+const WY: u16 = 0xFF4A;
+const WX: u16 = 0xFF4B;
+const VBK: u16 = 0xFF4F;
+const BCPS: u16 = 0xFF68;
+const OCPS: u16 = 0xFF6A;
+const OPRI: u16 = 0xFF6C;
+
+const LINE: u32 = DOTS_PER_LINE;
+
+// A PPU that just had the LCD switched on, and a clock counting dots from that moment.
+struct Lcd {
+    ppu: Ppu,
+    irq: InterruptLine,
+    dot: u32,
+}
+
+impl Lcd {
+    fn on(model: HardwareModel, renderer: Renderer, lcdc: u8) -> Self {
+        let mut ppu = Ppu::with_config(model, PpuConfig { renderer });
+        ppu.write(LCDC, lcdc);
+        Lcd { ppu, irq: InterruptLine::new(), dot: 0 }
+    }
+
+    fn dmg(lcdc: u8) -> Self {
+        Lcd::on(HardwareModel::Dmg, Renderer::Scanline, lcdc)
+    }
+
+    // Ticks until `dot` dots have passed since the LCD went on.
+    fn run_to(&mut self, dot: u32) {
+        assert!(dot >= self.dot, "already at dot {}", self.dot);
+        self.ppu.tick(dot - self.dot, &mut self.irq);
+        self.dot = dot;
+    }
+
+    fn mode_bits(&self) -> u8 {
+        self.ppu.read(STAT) & 0x03
+    }
+
+    fn coincidence(&self) -> bool {
+        self.ppu.read(STAT) & 0x04 != 0
+    }
+
+    fn stat_requested(&self) -> bool {
+        self.irq.is_requested(Interrupt::LcdStat)
+    }
+
+    fn vblank_requested(&self) -> bool {
+        self.irq.is_requested(Interrupt::VBlank)
+    }
+
+    // The color index at a screen position in the last frame.
+    fn index(&self, x: usize, y: usize) -> u8 {
+        self.ppu.color_indices()[y * SCREEN_WIDTH + x]
+    }
+
+    fn row(&self, y: usize) -> Vec<u8> {
+        self.ppu.color_indices()[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].to_vec()
+    }
+}
+
+// Fills a tile with one color index.
+fn solid_tile(ppu: &mut Ppu, tile: u16, color: u8) {
+    let (low, high) = (if color & 1 != 0 { 0xFF } else { 0x00 }, if color & 2 != 0 { 0xFF } else { 0x00 });
+    for row in 0..8 {
+        ppu.write_vram(0x8000 + tile * 16 + row * 2, low);
+        ppu.write_vram(0x8000 + tile * 16 + row * 2 + 1, high);
+    }
+}
+
+// Fills a 32x32 tile map at 9800 or 9C00 with the tile `f` picks for each column and row.
+fn fill_map<F: Fn(u16, u16) -> u8>(ppu: &mut Ppu, base: u16, f: F) {
+    for row in 0..32 {
+        for col in 0..32 {
+            ppu.write_vram(base + row * 32 + col, f(col, row));
+        }
+    }
+}
+
+// Tiles 1-3 in colors 1-3, and a background of alternating tiles 1 and 2 with tile 3 once at
+// the top left.  Tile 1 has a column of color 0 so horizontal scrolling shows.
+fn checkerboard(ppu: &mut Ppu) {
+    for color in 1..4 {
+        solid_tile(ppu, color as u16, color);
+    }
+    for row in 0..8 {
+        ppu.write_vram(0x8010 + row * 2, 0x7F);
+    }
+    fill_map(ppu, 0x9800, |col, row| if col == 0 && row == 0 { 3 } else { 1 + ((col + row) % 2) as u8 });
+    ppu.write(BGP, 0xE4);
+}
+
+// An OAM entry.
+fn sprite(ppu: &mut Ppu, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+    for (offset, &value) in [y, x, tile, flags].iter().enumerate() {
+        ppu.write_oam(0xFE00 + index * 4 + offset as u16, value);
+    }
+}
+
+// Writes RGB555 colors through BCPS/BCPD or OCPS/OCPD, from color `first` counting 4 per
+// palette, letting the index auto-increment.
+fn write_palette(ppu: &mut Ppu, select: u16, first: u8, colors: &[u16]) {
+    ppu.write(select, 0x80 | (first * 2));
+    for &color in colors {
+        ppu.write(select + 1, color as u8);
+        ppu.write(select + 1, (color >> 8) as u8);
+    }
+}
+
+#[test]
+fn modes_follow_the_line_timing() {
+    let mut lcd = Lcd::dmg(0x91);
+
+    // The first line after switching on skips the OAM scan
+    for &(dot, mode) in &[(1, 0), (79, 0), (80, 3), (251, 3), (252, 0), (455, 0)] {
+        lcd.run_to(dot);
+        assert_eq!((lcd.mode_bits(), lcd.ppu.read(LY)), (mode, 0), "dot {}", dot);
+    }
+
+    for line in 1..4 {
+        let start = line * LINE;
+        for &(dot, mode) in &[(0, 2), (79, 2), (80, 3), (251, 3), (252, 0), (455, 0)] {
+            lcd.run_to(start + dot);
+            assert_eq!((lcd.mode_bits(), lcd.ppu.read(LY)), (mode, line as u8), "line {} dot {}", line, dot);
+        }
+    }
+    assert_eq!(lcd.ppu.mode(), Mode::HBlank);
+    assert_eq!(lcd.ppu.read(STAT) & 0x80, 0x80);
+}
+
+#[test]
+fn vblank_starts_on_line_144_with_its_interrupt() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.run_to(144 * LINE - 1);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (143, 0));
+    assert!(!lcd.vblank_requested());
+
+    lcd.run_to(144 * LINE);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (144, 1));
+    assert!(lcd.vblank_requested());
+    assert_eq!(lcd.irq.read() & 0x01, 0x01);
+
+    lcd.irq.clear(Interrupt::VBlank);
+    for line in 145..154 {
+        lcd.run_to(line * LINE + 200);
+        assert_eq!(lcd.mode_bits(), 1, "line {}", line);
+    }
+    assert!(!lcd.vblank_requested());
+}
+
+#[test]
+fn ly_wraps_at_154() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.run_to(153 * LINE - 1);
+    assert_eq!(lcd.ppu.read(LY), 152);
+    lcd.run_to(154 * LINE - 1);
+    assert_eq!(lcd.ppu.ly(), 153);
+
+    lcd.run_to(154 * LINE);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (0, 2));
+    lcd.run_to(154 * LINE + 80);
+    assert_eq!(lcd.mode_bits(), 3);
+
+    // And the next frame's VBlank comes a full frame later
+    lcd.irq.clear(Interrupt::VBlank);
+    lcd.run_to(DOTS_PER_FRAME + 144 * LINE - 1);
+    assert!(!lcd.vblank_requested());
+    lcd.run_to(DOTS_PER_FRAME + 144 * LINE);
+    assert!(lcd.vblank_requested());
+}
+
+#[test]
+fn line_153_reads_as_0_after_its_first_m_cycle() {
+    let mut lcd = Lcd::dmg(0x91);
+    for &(dot, ly) in &[(0, 153), (3, 153), (4, 0), (200, 0), (455, 0)] {
+        lcd.run_to(153 * LINE + dot);
+        assert_eq!(lcd.ppu.read(LY), ly, "dot {}", dot);
+        assert_eq!(lcd.ppu.ly(), 153);
+        assert_eq!(lcd.mode_bits(), 1);
+    }
+}
+
+#[test]
+fn ly_writes_are_ignored() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.run_to(5 * LINE + 10);
+    lcd.ppu.write(LY, 0x42);
+    assert_eq!(lcd.ppu.read(LY), 5);
+}
+
+#[test]
+fn lyc_compares_an_m_cycle_after_ly_changes() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(LYC, 5);
+    for &(dot, coincidence) in &[(4 * LINE + 455, false), (5 * LINE, false), (5 * LINE + 3, false),
+                                 (5 * LINE + 4, true), (5 * LINE + 455, true), (6 * LINE + 3, false),
+                                 (6 * LINE + 4, false)] {
+        lcd.run_to(dot);
+        assert_eq!(lcd.coincidence(), coincidence, "line {} dot {}", dot / LINE, dot % LINE);
+    }
+}
+
+#[test]
+fn lyc_matches_twice_on_line_153() {
+    // LYC=153 only matches for the second M-cycle of the line
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(LYC, 153);
+    lcd.ppu.write(STAT, 0x40);
+    lcd.run_to(153 * LINE + 3);
+    assert!(!lcd.coincidence());
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.run_to(153 * LINE + 4);
+    assert!(lcd.coincidence());
+    assert!(lcd.stat_requested());
+    lcd.run_to(153 * LINE + 7);
+    assert!(lcd.coincidence());
+    lcd.run_to(153 * LINE + 8);
+    assert!(!lcd.coincidence());
+
+    // LYC=0 matches from the early 0 and holds through line 0, raising a single interrupt
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(LYC, 0);
+    lcd.ppu.write(STAT, 0x40);
+    lcd.run_to(153 * LINE + 11);
+    assert!(!lcd.coincidence());
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.run_to(153 * LINE + 12);
+    assert!(lcd.coincidence());
+    assert!(lcd.stat_requested());
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.run_to(155 * LINE - 1);
+    assert!(lcd.coincidence());
+    assert!(!lcd.stat_requested());
+    lcd.run_to(155 * LINE);
+    assert!(!lcd.coincidence());
+}
+
+#[test]
+fn stat_interrupts_on_the_rising_edge_only() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(STAT, 0x08);
+    lcd.run_to(LINE);
+    lcd.irq.clear(Interrupt::LcdStat);
+
+    lcd.run_to(LINE + 251);
+    assert!(!lcd.stat_requested());
+    lcd.run_to(LINE + 252);
+    assert!(lcd.stat_requested());
+
+    // Staying in HBlank doesn't raise it again
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.run_to(2 * LINE - 1);
+    assert!(!lcd.stat_requested());
+}
+
+#[test]
+fn lyc_holding_the_line_blocks_the_hblank_interrupt() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(LYC, 1);
+    lcd.ppu.write(STAT, 0x48);
+    lcd.run_to(LINE);
+    lcd.irq.clear(Interrupt::LcdStat);
+
+    lcd.run_to(LINE + 3);
+    assert!(!lcd.stat_requested());
+    lcd.run_to(LINE + 4);
+    assert!(lcd.stat_requested());
+
+    // HBlank on line 1 finds the line already high
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.run_to(LINE + 252);
+    assert_eq!(lcd.mode_bits(), 0);
+    assert!(!lcd.stat_requested());
+
+    // On line 2 LYC no longer matches, so HBlank gets its edge
+    lcd.run_to(2 * LINE + 251);
+    assert!(!lcd.stat_requested());
+    lcd.run_to(2 * LINE + 252);
+    assert!(lcd.stat_requested());
+}
+
+#[test]
+fn writing_stat_on_dmg_briefly_enables_every_source() {
+    for &(model, fires) in &[(HardwareModel::Dmg, true), (HardwareModel::Cgb, false)] {
+        let mut lcd = Lcd::on(model, Renderer::Scanline, 0x91);
+        lcd.ppu.write(LYC, 0xFF);
+        lcd.run_to(145 * LINE);
+        lcd.irq.clear(Interrupt::LcdStat);
+
+        lcd.ppu.write(STAT, 0x00);
+        lcd.run_to(145 * LINE + 1);
+        assert_eq!(lcd.stat_requested(), fires, "{:?}", model);
+
+        // Only the one dot
+        lcd.irq.clear(Interrupt::LcdStat);
+        lcd.run_to(146 * LINE);
+        assert!(!lcd.stat_requested(), "{:?}", model);
+    }
+}
+
+#[test]
+fn the_stat_write_quirk_needs_a_condition_to_hold() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(LYC, 0xFF);
+    lcd.run_to(10 * LINE + 100);
+    assert_eq!(lcd.mode_bits(), 3);
+    lcd.irq.clear(Interrupt::LcdStat);
+    lcd.ppu.write(STAT, 0x00);
+    lcd.run_to(10 * LINE + 101);
+    assert!(!lcd.stat_requested());
+}
+
+// Renders a frame of the checkerboard at a scroll position.
+fn scrolled(scx: u8, scy: u8) -> Lcd {
+    let mut lcd = Lcd::dmg(0x91);
+    checkerboard(&mut lcd.ppu);
+    lcd.ppu.write(SCX, scx);
+    lcd.ppu.write(SCY, scy);
+    lcd.run_to(144 * LINE);
+    lcd
+}
+
+#[test]
+fn background_scrolls_and_wraps() {
+    let tile_1 = [0, 1, 1, 1, 1, 1, 1, 1];
+    let cases: [(u8, u8, usize, Vec<u8>); 4] = [
+        // Tile 3 at the top left, then alternating tiles 2 and 1
+        (0, 0, 0, [[3; 8], [2; 8], tile_1].concat()),
+        (3, 0, 0, [&[3; 5][..], &[2; 8], &tile_1, &[2; 3]].concat()),
+        // Line 3 at SCY 5 is the second row of tiles
+        (0, 5, 3, [[2; 8], tile_1, [2; 8]].concat()),
+        // Both wrap around, back to the top left tile after the last column's 3 pixels
+        (0xFD, 0xFB, 5, [&[2; 3][..], &[3; 8], &[2; 8], &tile_1[..5]].concat()),
+    ];
+    for (scx, scy, y, expected) in cases.iter() {
+        let lcd = scrolled(*scx, *scy);
+        assert_eq!(lcd.row(*y)[..24], expected[..], "SCX {} SCY {}", scx, scy);
+    }
+}
+
+#[test]
+fn tile_data_at_8800_is_indexed_signed() {
+    for &(lcdc, color) in &[(0x91, 1), (0x81, 2)] {
+        let mut lcd = Lcd::dmg(lcdc);
+        solid_tile(&mut lcd.ppu, 0x01, 1);
+        solid_tile(&mut lcd.ppu, 0x101, 2);
+        fill_map(&mut lcd.ppu, 0x9800, |_, _| 0x01);
+        lcd.run_to(144 * LINE);
+        assert_eq!(lcd.row(0), vec![color; SCREEN_WIDTH], "LCDC {:02X}", lcdc);
+    }
+
+    // Tiles 0x80-0xFF come from 8800-8FFF either way
+    let mut lcd = Lcd::dmg(0x81);
+    solid_tile(&mut lcd.ppu, 0x80, 3);
+    fill_map(&mut lcd.ppu, 0x9800, |_, _| 0x80);
+    lcd.run_to(144 * LINE);
+    assert_eq!(lcd.row(0), vec![3; SCREEN_WIDTH]);
+}
+
+#[test]
+fn lcdc_bit_0_blanks_the_background_on_dmg() {
+    let mut lcd = Lcd::dmg(0x90);
+    checkerboard(&mut lcd.ppu);
+    lcd.run_to(144 * LINE);
+    assert!(lcd.ppu.color_indices().iter().all(|&index| index == 0));
+}
+
+#[test]
+fn fine_scroll_shifts_each_line_by_its_own_scx() {
+    let mut lcd = Lcd::dmg(0x91);
+    // Every tile is colors 0, 1, 2, 3, 3, 2, 1, 0, so each SCX%8 starts on its own pair
+    for row in 0..8 {
+        lcd.ppu.write_vram(0x8000 + row * 2, 0x5A);
+        lcd.ppu.write_vram(0x8001 + row * 2, 0x3C);
+    }
+    // Each write lands in the middle of mode 3 and is picked up by the next line
+    for y in 0..144 {
+        lcd.run_to(y * LINE + 100);
+        lcd.ppu.write(SCX, (y as u8 + 1) % 8 + y as u8 / 8 * 8);
+    }
+    lcd.run_to(144 * LINE);
+
+    let stripes = [0, 1, 2, 3, 3, 2, 1, 0];
+    for y in 0..144 {
+        let fine = y % 8;
+        assert_eq!(lcd.row(y)[..2], [stripes[fine], stripes[(fine + 1) % 8]], "line {}", y);
+    }
+}
+
+#[test]
+fn bgp_changes_only_reach_the_lines_drawn_after_them() {
+    let mut lcd = Lcd::dmg(0x91);
+    checkerboard(&mut lcd.ppu);
+    lcd.run_to(72 * LINE);
+    lcd.ppu.write(BGP, 0x1B);
+    lcd.run_to(144 * LINE);
+
+    // The indices don't care about BGP, the shades are inverted from line 72 on
+    let indices = lcd.ppu.color_indices();
+    let shades = lcd.ppu.framebuffer();
+    for (i, (&index, &shade)) in indices.iter().zip(shades.iter()).enumerate() {
+        let expected = if i / SCREEN_WIDTH < 72 { index } else { 3 - index };
+        assert_eq!(shade, expected, "line {} column {}", i / SCREEN_WIDTH, i % SCREEN_WIDTH);
+    }
+    assert_ne!(lcd.row(0), lcd.row(72).iter().map(|&index| 3 - index).collect::<Vec<u8>>());
+
+    // A frontend with its own palette can resolve the indices itself
+    let mut resolved = vec![0; indices.len()];
+    Ppu::resolve_indices(&indices[..], [0x1B, 0xFF, 0xFF], &mut resolved);
+    assert_eq!(resolved[72 * SCREEN_WIDTH..], shades[72 * SCREEN_WIDTH..]);
+}
+
+#[test]
+fn lcd_off_stops_the_ppu() {
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.write(STAT, 0x78);
+    lcd.run_to(50 * LINE + 100);
+    lcd.ppu.write(LCDC, 0x11);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (0, 0));
+
+    lcd.irq.write(0x00);
+    lcd.run_to(50 * LINE + 100 + 2 * DOTS_PER_FRAME);
+    assert_eq!(lcd.irq.read() & 0x1F, 0);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (0, 0));
+
+    // Back on, it restarts at the top of the frame
+    lcd.ppu.write(LCDC, 0x91);
+    let on = lcd.dot;
+    lcd.run_to(on + 80);
+    assert_eq!((lcd.ppu.read(LY), lcd.mode_bits()), (0, 3));
+    lcd.run_to(on + 144 * LINE);
+    assert!(lcd.vblank_requested());
+}
+
+#[test]
+fn sprites_and_fine_scroll_lengthen_mode_3() {
+    // SCX%8 pixels are discarded
+    let mut lcd = Lcd::dmg(0x93);
+    lcd.ppu.write(SCX, 3);
+    lcd.run_to(LINE + 254);
+    assert_eq!(lcd.mode_bits(), 3);
+    lcd.run_to(LINE + 255);
+    assert_eq!(lcd.mode_bits(), 0);
+    assert_eq!(lcd.ppu.drawing_dots(), 175);
+
+    // A sprite at the start of a tile costs 6 dots plus 5 waiting on the background fetch
+    let mut lcd = Lcd::dmg(0x93);
+    sprite(&mut lcd.ppu, 0, 17, 8, 0, 0);
+    lcd.run_to(LINE + 262);
+    assert_eq!(lcd.mode_bits(), 3);
+    lcd.run_to(LINE + 263);
+    assert_eq!(lcd.mode_bits(), 0);
+    assert_eq!(lcd.ppu.drawing_dots(), 183);
+
+    // With sprites switched off they cost nothing
+    let mut lcd = Lcd::dmg(0x91);
+    sprite(&mut lcd.ppu, 0, 17, 8, 0, 0);
+    lcd.run_to(LINE + 252);
+    assert_eq!(lcd.mode_bits(), 0);
+}
+
+#[test]
+fn window_line_only_counts_lines_the_window_was_drawn_on() {
+    let mut lcd = Lcd::dmg(0xF1);
+    solid_tile(&mut lcd.ppu, 1, 1);
+    fill_map(&mut lcd.ppu, 0x9C00, |_, _| 1);
+    lcd.ppu.write(WY, 64);
+    lcd.ppu.write(WX, 7);
+
+    lcd.run_to(64 * LINE + 251);
+    assert_eq!(lcd.ppu.window_line(), 0);
+    lcd.run_to(64 * LINE + 258);
+    assert_eq!(lcd.ppu.window_line(), 1);
+
+    // Hidden for lines 80-89, by LCDC and then by WX
+    lcd.run_to(80 * LINE);
+    lcd.ppu.write(LCDC, 0xD1);
+    lcd.run_to(85 * LINE);
+    lcd.ppu.write(LCDC, 0xF1);
+    lcd.ppu.write(WX, 167);
+    lcd.run_to(90 * LINE);
+    assert_eq!(lcd.ppu.window_line(), 16);
+    lcd.ppu.write(WX, 7);
+
+    // Moving WY once the window has started changes nothing
+    lcd.ppu.write(WY, 120);
+    lcd.run_to(144 * LINE);
+    assert_eq!(lcd.ppu.window_line(), 70);
+
+    assert_eq!(lcd.row(63), vec![0; SCREEN_WIDTH]);
+    assert_eq!(lcd.row(64), vec![1; SCREEN_WIDTH]);
+    assert_eq!(lcd.row(80), vec![0; SCREEN_WIDTH]);
+    assert_eq!(lcd.row(89), vec![0; SCREEN_WIDTH]);
+    assert_eq!(lcd.row(90), vec![1; SCREEN_WIDTH]);
+
+    // The counter starts over with the next frame, where WY=120 applies
+    lcd.run_to(DOTS_PER_FRAME + 120 * LINE);
+    assert_eq!(lcd.ppu.window_line(), 0);
+    lcd.run_to(DOTS_PER_FRAME + 144 * LINE);
+    assert_eq!(lcd.ppu.window_line(), 24);
+}
+
+#[test]
+fn window_never_reached_leaves_the_counter_alone() {
+    let mut lcd = Lcd::dmg(0xF1);
+    lcd.ppu.write(WY, 200);
+    lcd.ppu.write(WX, 7);
+    lcd.run_to(144 * LINE);
+    assert_eq!(lcd.ppu.window_line(), 0);
+}
+
+#[test]
+fn window_wx_clips_at_the_edges() {
+    for &(wx, first) in &[(0, 0), (6, 0), (7, 0), (8, 1), (166, 159)] {
+        let mut lcd = Lcd::dmg(0xF1);
+        solid_tile(&mut lcd.ppu, 1, 1);
+        fill_map(&mut lcd.ppu, 0x9C00, |_, _| 1);
+        lcd.ppu.write(WX, wx);
+        lcd.run_to(144 * LINE);
+        let row = lcd.row(0);
+        assert!(row[..first].iter().all(|&index| index == 0), "WX {}", wx);
+        assert!(row[first..].iter().all(|&index| index == 1), "WX {}", wx);
+    }
+}
+
+#[test]
+fn only_the_first_10_sprites_on_a_line_are_drawn() {
+    let mut lcd = Lcd::dmg(0x93);
+    solid_tile(&mut lcd.ppu, 1, 1);
+    // Entries 0-9 side by side from column 8, and entry 10 at the far left
+    for index in 0..10 {
+        sprite(&mut lcd.ppu, index, 16, 16 + index as u8 * 8, 1, 0);
+    }
+    sprite(&mut lcd.ppu, 10, 16, 8, 1, 0);
+    // Off screen at X 0, but still taking a slot on lines 8-15
+    sprite(&mut lcd.ppu, 11, 24, 0, 1, 0);
+    for index in 12..22 {
+        sprite(&mut lcd.ppu, index, 24, 88 + (index - 12) as u8 * 8, 1, 0);
+    }
+    lcd.run_to(144 * LINE);
+
+    // The lowest X doesn't save entry 10, the scan goes by OAM order
+    let expected: Vec<u8> = (0..SCREEN_WIDTH).map(|x| if (8..88).contains(&x) { 0x05 } else { 0 }).collect();
+    assert_eq!(lcd.row(0), expected);
+    let expected: Vec<u8> = (0..SCREEN_WIDTH).map(|x| if (80..152).contains(&x) { 0x05 } else { 0 }).collect();
+    assert_eq!(lcd.row(8), expected);
+}
+
+#[test]
+fn tall_sprites_ignore_the_low_tile_bit() {
+    let mut lcd = Lcd::dmg(0x97);
+    solid_tile(&mut lcd.ppu, 2, 1);
+    solid_tile(&mut lcd.ppu, 3, 2);
+    sprite(&mut lcd.ppu, 0, 16, 8, 3, 0);
+    sprite(&mut lcd.ppu, 1, 16, 16, 2, 0x40);
+    lcd.run_to(144 * LINE);
+
+    for y in 0..16 {
+        let (top, bottom) = if y < 8 { (0x05, 0x06) } else { (0x06, 0x05) };
+        assert_eq!([lcd.index(0, y), lcd.index(8, y)], [top, bottom], "line {}", y);
+    }
+    assert_eq!(lcd.row(16), vec![0; SCREEN_WIDTH]);
+
+    // The same sprites are half as tall in 8x8 mode
+    let mut lcd = Lcd::dmg(0x93);
+    solid_tile(&mut lcd.ppu, 3, 2);
+    sprite(&mut lcd.ppu, 0, 16, 8, 3, 0);
+    lcd.run_to(144 * LINE);
+    assert_eq!([lcd.index(0, 7), lcd.index(0, 8)], [0x06, 0]);
+}
+
+#[test]
+fn sprites_flip_on_either_axis() {
+    let mut lcd = Lcd::dmg(0x93);
+    // Tile 1 has a single pixel of color 1, at its top left
+    lcd.ppu.write_vram(0x8010, 0x80);
+    for (index, &flags) in [0x00, 0x20, 0x40, 0x60].iter().enumerate() {
+        sprite(&mut lcd.ppu, index as u16, 16, 8 + index as u8 * 16, 1, flags);
+    }
+    lcd.run_to(144 * LINE);
+
+    let drawn: Vec<(usize, usize)> = (0..8)
+        .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| lcd.index(x, y) != 0)
+        .collect();
+    assert_eq!(drawn, [(0, 0), (23, 0), (32, 7), (55, 7)]);
+}
+
+#[test]
+fn background_priority_only_hides_sprites_over_nonzero_colors() {
+    let mut lcd = Lcd::dmg(0x93);
+    checkerboard(&mut lcd.ppu);
+    fill_map(&mut lcd.ppu, 0x9800, |_, _| 1);
+    solid_tile(&mut lcd.ppu, 4, 2);
+    sprite(&mut lcd.ppu, 0, 16, 8, 4, 0x80);
+    sprite(&mut lcd.ppu, 1, 16, 16, 4, 0x00);
+    // Color 0 is transparent however OBP0 maps it
+    sprite(&mut lcd.ppu, 2, 16, 24, 0, 0x00);
+    lcd.ppu.write(OBP0, 0xFF);
+    lcd.run_to(144 * LINE);
+
+    // Tile 1's first column is color 0, so only that pixel shows the sprite behind it
+    assert_eq!(lcd.row(0)[..24], [&[0x06][..], &[1; 7], &[0x06; 8], &[0], &[1; 7]].concat()[..]);
+}
+
+// Two sprites overlapping at columns 4-7 of line 0: OAM entry 0 further right in color 1, entry
+// 1 further left in color 2.  Returns the color indices of columns 0, 4 and 8.
+fn overlapping_sprites(opri: Option<u8>, cart_cgb_flag: Option<u8>) -> [u8; 3] {
+    let mut lcd = Lcd::on(HardwareModel::Cgb, Renderer::Scanline, 0x93);
+    solid_tile(&mut lcd.ppu, 1, 1);
+    solid_tile(&mut lcd.ppu, 2, 2);
+    sprite(&mut lcd.ppu, 0, 16, 12, 1, 0);
+    sprite(&mut lcd.ppu, 1, 16, 8, 2, 0);
+    if let Some(opri) = opri {
+        lcd.ppu.write(OPRI, opri);
+    }
+    if let Some(flag) = cart_cgb_flag {
+        lcd.ppu.skip_boot_rom(flag);
+    }
+    lcd.run_to(144 * LINE);
+    [lcd.index(0, 0), lcd.index(4, 0), lcd.index(8, 0)]
+}
+
+#[test]
+fn opri_picks_the_sprite_priority_on_cgb() {
+    // OAM order by default, lower X with bit 0 set
+    assert_eq!(overlapping_sprites(None, None), [0x22, 0x21, 0x21]);
+    assert_eq!(overlapping_sprites(Some(0x01), None), [0x22, 0x22, 0x21]);
+
+    // Skipping the boot ROM picks it from the cart's CGB flag and locks it
+    assert_eq!(overlapping_sprites(None, Some(0x00)), [0x22, 0x22, 0x21]);
+    assert_eq!(overlapping_sprites(None, Some(0x80)), [0x22, 0x21, 0x21]);
+}
+
+#[test]
+fn opri_locks_and_is_cgb_only() {
+    let mut ppu = Ppu::new(HardwareModel::Cgb);
+    assert_eq!(ppu.read(OPRI), 0xFE);
+    ppu.write(OPRI, 0xFF);
+    assert_eq!(ppu.read(OPRI), 0xFF);
+    ppu.lock_opri();
+    ppu.write(OPRI, 0x00);
+    assert_eq!(ppu.read(OPRI), 0xFF);
+
+    let mut ppu = Ppu::new(HardwareModel::Dmg);
+    ppu.write(OPRI, 0x00);
+    assert_eq!(ppu.read(OPRI), 0xFF);
+}
+
+#[test]
+fn dmg_sprites_always_use_x_priority() {
+    let mut lcd = Lcd::dmg(0x93);
+    solid_tile(&mut lcd.ppu, 1, 1);
+    solid_tile(&mut lcd.ppu, 2, 2);
+    sprite(&mut lcd.ppu, 0, 16, 12, 1, 0);
+    sprite(&mut lcd.ppu, 1, 16, 8, 2, 0x10);
+    lcd.run_to(144 * LINE);
+
+    // OBP0 is index 1 in bits 2-3, OBP1 index 2
+    assert_eq!([lcd.index(0, 0), lcd.index(4, 0), lcd.index(8, 0)], [0x0A, 0x0A, 0x05]);
+}
+
+#[test]
+fn cgb_attributes_pick_the_bank_palette_and_flips() {
+    const WHITE: u16 = 0x7FFF;
+    const RED: u16 = 0x001F;
+    const GREEN: u16 = 0x03E0;
+    const BLUE: u16 = 0x7C00;
+    const BLACK: u16 = 0x0000;
+    const MAGENTA: u16 = 0x7C1F;
+    const YELLOW: u16 = 0x03FF;
+    const CYAN: u16 = 0x7FE0;
+
+    for &lcdc in &[0x93, 0x92] {
+        let mut lcd = Lcd::on(HardwareModel::Cgb, Renderer::Scanline, lcdc);
+        // Bank 0 has tile 1 in color 3 and tile 2 in color 1, and a map of tile 1
+        solid_tile(&mut lcd.ppu, 1, 3);
+        solid_tile(&mut lcd.ppu, 2, 1);
+        fill_map(&mut lcd.ppu, 0x9800, |_, _| 1);
+        // Bank 1's tile 1 has color 1 across the left half of its top row and color 2 along
+        // its bottom row.  The attributes repeat bank 1 in palette 0, X flipped in palette 1,
+        // Y flipped in palette 1, and bank 0
+        lcd.ppu.write(VBK, 1);
+        lcd.ppu.write_vram(0x8010, 0xF0);
+        lcd.ppu.write_vram(0x801F, 0xFF);
+        fill_map(&mut lcd.ppu, 0x9800, |col, _| [0x08, 0x29, 0x49, 0x00][col as usize % 4]);
+        lcd.ppu.write(VBK, 0);
+        write_palette(&mut lcd.ppu, BCPS, 0, &[WHITE, RED, GREEN, BLUE, BLACK, MAGENTA, YELLOW, CYAN]);
+
+        // A sprite from tile 2 in OBJ palette 2, over the background's lines 16-23
+        sprite(&mut lcd.ppu, 0, 32, 8, 2, 0x02);
+        write_palette(&mut lcd.ppu, OCPS, 9, &[0x1234]);
+        lcd.run_to(144 * LINE);
+
+        let rgb = lcd.ppu.rgb_framebuffer();
+        let row = |y: usize| rgb[y * SCREEN_WIDTH..y * SCREEN_WIDTH + 32].to_vec();
+        let top = [[RED; 4], [WHITE; 4], [BLACK; 4], [MAGENTA; 4], [YELLOW; 4], [YELLOW; 4], [BLUE; 4], [BLUE; 4]];
+        assert_eq!(row(0), top.concat(), "LCDC {:02X}", lcdc);
+        let bottom = [[GREEN; 4], [GREEN; 4], [YELLOW; 4], [YELLOW; 4], [MAGENTA; 4], [BLACK; 4], [BLUE; 4], [BLUE; 4]];
+        assert_eq!(row(7), bottom.concat(), "LCDC {:02X}", lcdc);
+        assert_eq!(row(16)[..9], [&[0x1234; 8][..], &[BLACK]].concat()[..], "LCDC {:02X}", lcdc);
+        assert_eq!(lcd.index(0, 16), 0x29);
+
+        assert_eq!(rgb555_to_rgb(RED), [0xFF, 0x00, 0x00]);
+        assert_eq!(lcd.ppu.frame().to_rgba()[..8], [0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF]);
+    }
+}
+
+// Renders one frame of the checkerboard scene with a couple of sprites.
+fn scene(renderer: Renderer, setup: &dyn Fn(&mut Ppu)) -> Lcd {
+    let mut lcd = Lcd::on(HardwareModel::Dmg, renderer, 0x93);
+    checkerboard(&mut lcd.ppu);
+    sprite(&mut lcd.ppu, 0, 40, 30, 1, 0);
+    sprite(&mut lcd.ppu, 1, 44, 34, 2, 0x30);
+    setup(&mut lcd.ppu);
+    lcd.run_to(144 * LINE);
+    lcd
+}
+
+#[test]
+fn both_renderers_agree_on_easy_scenes() {
+    let setups: [&dyn Fn(&mut Ppu); 4] = [
+        &|_| {},
+        &|ppu| ppu.write(SCX, 3),
+        &|ppu| {
+            ppu.write(SCX, 0xFD);
+            ppu.write(SCY, 0x11);
+        },
+        &|ppu| {
+            ppu.write(BGP, 0x1B);
+            ppu.write(0xFF48, 0x4E);
+        },
+    ];
+    for (i, setup) in setups.iter().enumerate() {
+        let scanline = scene(Renderer::Scanline, *setup);
+        let fifo = scene(Renderer::Fifo, *setup);
+        assert_eq!(scanline.ppu.color_indices()[..], fifo.ppu.color_indices()[..], "scene {}", i);
+        assert_eq!(scanline.ppu.framebuffer()[..], fifo.ppu.framebuffer()[..], "scene {}", i);
+    }
+}
+
+#[test]
+fn fifo_mode_3_grows_with_the_fine_scroll() {
+    for scx in 0..8 {
+        let mut lcd = Lcd::on(HardwareModel::Dmg, Renderer::Fifo, 0x91);
+        lcd.ppu.write(SCX, scx);
+        lcd.run_to(2 * LINE);
+        assert_eq!(lcd.ppu.drawing_dots(), 172 + scx as u32, "SCX {}", scx);
+    }
+}
+
+#[test]
+fn fifo_sees_scx_changes_mid_line() {
+    let mid_line = |lcd: &mut Lcd| {
+        lcd.run_to(10 * LINE + 80 + 90);
+        lcd.ppu.write(SCX, 8);
+        lcd.run_to(144 * LINE);
+    };
+    let mut scanline = scene(Renderer::Scanline, &|_| {});
+    let mut fifo = scene(Renderer::Fifo, &|_| {});
+    for lcd in [&mut scanline, &mut fifo].iter_mut() {
+        let start = lcd.dot + DOTS_PER_FRAME - 144 * LINE;
+        lcd.run_to(start);
+        lcd.dot = 0;
+        mid_line(lcd);
+    }
+
+    for y in 0..144 {
+        if y == 10 {
+            assert_ne!(scanline.row(y), fifo.row(y));
+            assert_eq!(scanline.row(y)[..60], fifo.row(y)[..60]);
+        } else {
+            assert_eq!(scanline.row(y), fifo.row(y), "line {}", y);
+        }
+    }
+}
+
+#[test]
+fn frame_hash_ignores_the_palette() {
+    let plain = scene(Renderer::Scanline, &|_| {});
+    let recolored = scene(Renderer::Scanline, &|ppu| {
+        ppu.write(BGP, 0x1B);
+        ppu.write(0xFF48, 0xFF);
+    });
+    assert_ne!(plain.ppu.framebuffer()[..], recolored.ppu.framebuffer()[..]);
+    assert_eq!(plain.ppu.frame().hash(), recolored.ppu.frame().hash());
+    assert_eq!(plain.ppu.frame().crc32(), recolored.ppu.frame().crc32());
+}
+
+#[test]
+fn frame_hash_changes_with_a_single_pixel() {
+    let plain = scene(Renderer::Scanline, &|_| {});
+    let changed = scene(Renderer::Scanline, &|ppu| ppu.write_vram(0x8030, 0x7F));
+    let differing = plain.ppu.color_indices().iter().zip(changed.ppu.color_indices().iter())
+        .filter(|&(a, b)| a != b)
+        .count();
+    assert_eq!(differing, 1);
+    assert_ne!(plain.ppu.frame().hash(), changed.ppu.frame().hash());
+    assert_ne!(plain.ppu.frame().crc32(), changed.ppu.frame().crc32());
+}
+
+#[test]
+fn frame_hash_is_deterministic_across_runs() {
+    let hashes = || {
+        let hashes = Rc::new(RefCell::new(Vec::new()));
+        let sink = hashes.clone();
+        let mut lcd = Lcd::dmg(0x93);
+        checkerboard(&mut lcd.ppu);
+        lcd.ppu.set_frame_callback(Box::new(move |frame| sink.borrow_mut().push((frame.number, frame.hash()))));
+        for frame in 0..3u8 {
+            lcd.ppu.write(SCX, frame * 3);
+            lcd.run_to((frame as u32 + 1) * DOTS_PER_FRAME);
+        }
+        let hashes = hashes.borrow().clone();
+        hashes
+    };
+    let first = hashes();
+    assert_eq!(first.iter().map(|&(number, _)| number).collect::<Vec<u64>>(), [1, 2, 3]);
+    assert_ne!(first[0].1, first[1].1);
+    assert_eq!(first, hashes());
+}
+
+#[test]
+fn frames_are_delivered_at_the_start_of_vblank() {
+    let delivered = Rc::new(RefCell::new(0));
+    let sink = delivered.clone();
+    let mut lcd = Lcd::dmg(0x91);
+    lcd.ppu.set_frame_callback(Box::new(move |_| *sink.borrow_mut() += 1));
+
+    lcd.run_to(144 * LINE - 1);
+    assert_eq!(*delivered.borrow(), 0);
+    lcd.run_to(144 * LINE);
+    assert_eq!(*delivered.borrow(), 1);
+    lcd.run_to(2 * DOTS_PER_FRAME + 144 * LINE);
+    assert_eq!(*delivered.borrow(), 3);
+    assert_eq!(lcd.ppu.frame_count(), 3);
+}
+
+#[test]
+fn vram_and_oam_accessors_ignore_other_addresses() {
+    let mut ppu = Ppu::new(HardwareModel::Cgb);
+    ppu.write_vram(0x8000, 0x12);
+    ppu.write_vram(0x9FFF, 0x34);
+    ppu.write_oam(0xFE00, 0x56);
+    ppu.write_oam(0xFE9F, 0x78);
+
+    for &address in &[0x0000, 0x7FFF, 0xA000, 0xFDFF, 0xFEA0, 0xFFFF] {
+        ppu.write_vram(address, 0xAA);
+        ppu.write_oam(address, 0xAA);
+        assert_eq!(ppu.read_vram(address), 0xFF, "{:04X}", address);
+        assert_eq!(ppu.read_oam(address), 0xFF, "{:04X}", address);
+    }
+    assert_eq!([ppu.read_vram(0x8000), ppu.read_vram(0x9FFF)], [0x12, 0x34]);
+    assert_eq!([ppu.read_oam(0xFE00), ppu.read_oam(0xFE9F)], [0x56, 0x78]);
+}
+
+#[test]
+fn tile_sheet_lays_out_16_tiles_a_row() {
+    let mut lcd = Lcd::on(HardwareModel::Cgb, Renderer::Scanline, 0x91);
+    // Tile 0 is a diagonal of color 3 and tile 17 is solid color 2, and in bank 1 tile 383
+    // has a top row of color 1
+    for row in 0..8 {
+        lcd.ppu.write_vram(0x8000 + row * 2, 0x80 >> row);
+        lcd.ppu.write_vram(0x8001 + row * 2, 0x80 >> row);
+    }
+    solid_tile(&mut lcd.ppu, 17, 2);
+    lcd.ppu.write(VBK, 1);
+    lcd.ppu.write_vram(0x97F0, 0xFF);
+    lcd.ppu.write(VBK, 0);
+
+    // The dump reads VRAM directly, even while the PPU is drawing
+    lcd.run_to(10 * LINE + 100);
+    assert_eq!(lcd.mode_bits(), 3);
+    let sheet = lcd.ppu.dump_tiles(0);
+    assert_eq!((sheet.width, sheet.height, sheet.pixels.len()), (128, 192, 128 * 192));
+    let pixel = |pixels: &[u8], x: usize, y: usize| pixels[y * 128 + x];
+    for y in 0..8 {
+        for x in 0..8 {
+            assert_eq!(pixel(&sheet.pixels, x, y), if x == y { 3 } else { 0 }, "tile 0 at {},{}", x, y);
+            assert_eq!(pixel(&sheet.pixels, 8 + x, 8 + y), 2, "tile 17 at {},{}", x, y);
+        }
+    }
+    assert_eq!(pixel(&sheet.pixels, 16, 8), 0);
+    assert!(sheet.pixels[184 * 128..].iter().all(|&color| color == 0));
+
+    let bank_1 = lcd.ppu.dump_tiles(1);
+    assert_eq!(bank_1.pixels[184 * 128 + 120..185 * 128], [1; 8]);
+    assert_eq!(bank_1.pixels[185 * 128 + 120..186 * 128], [0; 8]);
+    assert_eq!(bank_1.to_rgba()[(184 * 128 + 120) * 4..][..3], DisplayPalette::GRAYSCALE.color(1));
+}
+
+#[test]
+fn tile_map_dump_shows_the_viewport_and_window() {
+    let mut lcd = Lcd::dmg(0xF1);
+    checkerboard(&mut lcd.ppu);
+    lcd.ppu.write(SCX, 100);
+    lcd.ppu.write(SCY, 200);
+    lcd.ppu.write(WX, 87);
+    lcd.ppu.write(WY, 44);
+
+    let mut image = lcd.ppu.dump_tilemap(TileMapSelect::Background);
+    assert_eq!((image.width, image.height, image.rgba.len()), (256, 256, 256 * 256 * 4));
+    assert_eq!(image.viewport, MapRect { x: 100, y: 200, width: 160, height: 144 });
+    assert_eq!(image.window, Some(MapRect { x: 180, y: 244, width: 80, height: 100 }));
+
+    // The whole map goes through BGP and the display palette, wherever the screen is
+    let palette = lcd.ppu.display_palette();
+    let pixel = |rgba: &[u8], x: usize, y: usize| rgba[(y * 256 + x) * 4..][..3].to_vec();
+    for &(x, y, shade) in &[(0, 0, 3), (8, 0, 2), (16, 0, 0), (17, 0, 1), (0, 8, 2), (255, 255, 1)] {
+        assert_eq!(pixel(&image.rgba, x, y), palette.color(shade), "{},{}", x, y);
+    }
+
+    // The outlines wrap around the map's edges
+    image.draw_overlays();
+    let red = vec![0xFF, 0x00, 0x00];
+    let blue = vec![0x00, 0x00, 0xFF];
+    for &(x, y) in &[(100, 200), (255, 200), (0, 200), (2, 200), (100, 87), (100, 255)] {
+        assert_eq!(pixel(&image.rgba, x, y), red, "{},{}", x, y);
+    }
+    for &(x, y) in &[(180, 244), (180, 87), (3, 244), (3, 30)] {
+        assert_eq!(pixel(&image.rgba, x, y), blue, "{},{}", x, y);
+    }
+    assert_eq!(pixel(&image.rgba, 150, 250), palette.color(2));
+
+    // The window map is 9C00 here, and still all tile 0
+    let window = lcd.ppu.dump_tilemap(TileMapSelect::Window);
+    assert!(window.rgba.chunks(4).all(|rgba| rgba[..3] == palette.color(0)));
+    assert_eq!(lcd.ppu.dump_tilemap(TileMapSelect::Map9800).rgba, lcd.ppu.dump_tilemap(TileMapSelect::Background).rgba);
+}
+
+#[test]
+fn palette_dump_decodes_what_bcpd_wrote() {
+    let mut ppu = Ppu::new(HardwareModel::Cgb);
+    write_palette(&mut ppu, BCPS, 4, &[0x7FFF, 0x001F, 0x03E0, 0x7C00]);
+    write_palette(&mut ppu, OCPS, 28, &[0x0000, 0x0421, 0x5294, 0x7FFF]);
+    // The index moved on past the last color written
+    assert_eq!(ppu.read(BCPS) & 0xBF, 0x80 | 16);
+
+    let swatches = ppu.dump_palettes();
+    assert!(swatches.cgb);
+    assert_eq!((swatches.bg.len(), swatches.obj.len()), (8, 8));
+    let raw = |palette: &[Swatch; 4]| palette.iter().map(|swatch| swatch.raw).collect::<Vec<u16>>();
+    assert_eq!(raw(&swatches.bg[1]), [0x7FFF, 0x001F, 0x03E0, 0x7C00]);
+    assert_eq!(raw(&swatches.obj[7]), [0x0000, 0x0421, 0x5294, 0x7FFF]);
+    assert_eq!(swatches.bg[1][1].rgb, [0xFF, 0x00, 0x00]);
+    assert_eq!(swatches.obj[7][1].rgb, [0x08, 0x08, 0x08]);
+
+    let text = swatches.to_string();
+    assert!(text.contains("BG1   7FFF 001F 03E0 7C00\n"), "{}", text);
+    assert!(text.contains("OBJ7  0000 0421 5294 7FFF\n"), "{}", text);
+
+    // 16 pixel cells, BG on the left and OBJ after an 8 pixel gap
+    let (width, height, rgba) = swatches.to_rgba();
+    assert_eq!((width, height), (136, 128));
+    let cell = |x: usize, y: usize| rgba[(y * width + x) * 4..][..4].to_vec();
+    assert_eq!(cell(16, 16), [0xFF, 0x00, 0x00, 0xFF]);
+    assert_eq!(cell(72 + 48, 7 * 16 + 15), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+    // DMG shows the registers' shades instead
+    let mut ppu = Ppu::new(HardwareModel::Dmg);
+    ppu.write(BGP, 0xE4);
+    ppu.write(OBP0, 0x1B);
+    let swatches = ppu.dump_palettes();
+    assert!(!swatches.cgb);
+    assert_eq!(raw(&swatches.obj[0]), [3, 2, 1, 0]);
+    assert!(swatches.to_string().starts_with("BGP   E4  0 1 2 3\nOBP0  1B  3 2 1 0\n"), "{}", swatches);
+}
+
+#[test]
+fn oam_summary_marks_what_the_scan_selects_on_the_current_line() {
+    let mut lcd = Lcd::dmg(0x97);
+    // Eleven 8x16 sprites over lines 14-29, the first one off the left edge, then one over
+    // lines 0-15 with every flag set
+    for index in 0..11 {
+        sprite(&mut lcd.ppu, index, 30, index as u8 * 8, 0x13, 0);
+    }
+    sprite(&mut lcd.ppu, 11, 16, 0xA8, 0x21, 0xF0);
+    lcd.run_to(20 * LINE + 10);
+
+    let summary = lcd.ppu.oam_summary(&lcd.ppu);
+    assert_eq!(summary.line, 20);
+    let visibility: Vec<OamVisibility> = summary.entries.iter().map(|entry| entry.visibility).collect();
+    let expected = [[OamVisibility::Selected; 10].to_vec(), vec![OamVisibility::Dropped], vec![OamVisibility::Hidden; 29]];
+    assert_eq!(visibility, expected.concat());
+    let entry = &summary.entries[1];
+    assert_eq!((entry.sprite.index, entry.screen_x, entry.screen_y, entry.tile), (1, 0, 14, 0x12));
+
+    let table = summary.to_string();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], " #   Y   X  scr Y scr X  tile  flags  bank  pal  line 20");
+    assert_eq!(lines[1], " 0  1E  00     14    -8    12   ---0     0    0  visible");
+    assert_eq!(lines[11], "10  1E  50     14    72    12   ---0     0    0  dropped");
+    assert_eq!(lines[12], "11  10  A8      0   160    20   PYX1     0    0");
+    assert_eq!(lines.len(), 41);
+
+    // The bus is what gets read, not the PPU's own OAM
+    struct Oam;
+    impl Peek for Oam {
+        fn peek(&self, address: u16) -> u8 {
+            if address == 0xFE00 { 0x24 } else { 0x00 }
+        }
+    }
+    let summary = lcd.ppu.oam_summary(&Oam);
+    assert_eq!(summary.entries[0].visibility, OamVisibility::Selected);
+    assert!(summary.entries[1..].iter().all(|entry| entry.visibility == OamVisibility::Hidden));
+}
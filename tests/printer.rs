@@ -0,0 +1,214 @@
+//! The Game Boy Printer fed hand-built packet sequences.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::printer::{GbPrinter, Printout};
+use farore::serial::Serial;
+
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+// A packet with its checksum and the two bytes the printer answers on.
+fn packet(command: u8, compressed: bool, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![command, compressed as u8, payload.len() as u8, (payload.len() >> 8) as u8];
+    body.extend_from_slice(payload);
+    let checksum = body.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    let mut packet = vec![0x88, 0x33];
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet
+}
+
+// Sends a packet, returning the device ID and status it answers with.
+fn send(printer: &GbPrinter, packet: &[u8]) -> (u8, u8) {
+    let answers: Vec<u8> = packet.iter().map(|&byte| printer.exchange_byte(byte)).collect();
+    assert!(answers[..answers.len() - 2].iter().all(|&answer| answer == 0), "{:02X?}", answers);
+    (answers[answers.len() - 2], answers[answers.len() - 1])
+}
+
+// Two rows of 20 tiles, each tile a solid color: the tile's index mod 4.
+fn strip() -> Vec<u8> {
+    let mut strip = Vec::new();
+    for tile in 0..40 {
+        let color = tile % 4;
+        for _ in 0..8 {
+            strip.push(if color & 1 != 0 { 0xFF } else { 0x00 });
+            strip.push(if color & 2 != 0 { 0xFF } else { 0x00 });
+        }
+    }
+    strip
+}
+
+// The strip packed with the printer's RLE: a tile of one repeated byte becomes a single run,
+// any other tile is copied as is.
+fn compressed_strip() -> Vec<u8> {
+    let mut compressed = Vec::new();
+    for tile in strip().chunks(16) {
+        if tile.iter().all(|&byte| byte == tile[0]) {
+            compressed.extend_from_slice(&[0x80 | (16 - 2), tile[0]]);
+        } else {
+            compressed.push(15);
+            compressed.extend_from_slice(tile);
+        }
+    }
+    compressed
+}
+
+fn printer_with_sink() -> (GbPrinter, Rc<RefCell<Vec<Printout>>>) {
+    let printer = GbPrinter::new();
+    let printouts = Rc::new(RefCell::new(Vec::new()));
+    let sink = printouts.clone();
+    printer.set_callback(Box::new(move |printout| sink.borrow_mut().push(Printout {
+        width: printout.width,
+        height: printout.height,
+        pixels: printout.pixels.clone(),
+        margin_before: printout.margin_before,
+        margin_after: printout.margin_after,
+    })));
+    (printer, printouts)
+}
+
+// The shade the strip's pixel at (x, y) prints as with `palette`.
+fn expected_shade(x: usize, y: usize, palette: u8) -> u8 {
+    let color = ((y / 8) * 20 + x / 8) % 4;
+    (palette >> (color * 2)) & 0x3
+}
+
+#[test]
+fn prints_a_strip() {
+    let (printer, printouts) = printer_with_sink();
+    assert_eq!(send(&printer, &packet(INIT, false, &[])), (0x81, 0x00));
+    assert_eq!(send(&printer, &packet(DATA, false, &strip())), (0x81, 0x08));
+    assert_eq!(send(&printer, &packet(DATA, false, &[])), (0x81, 0x08));
+    assert_eq!(send(&printer, &packet(PRINT, false, &[0x01, 0x13, 0xE4, 0x40])), (0x81, 0x02));
+
+    let printouts = printouts.borrow();
+    assert_eq!(printouts.len(), 1);
+    let printout = &printouts[0];
+    assert_eq!((printout.width, printout.height), (160, 16));
+    assert_eq!((printout.margin_before, printout.margin_after), (1, 3));
+    for y in 0..16 {
+        for x in 0..160 {
+            assert_eq!(printout.pixels[y * 160 + x], expected_shade(x, y, 0xE4), "({}, {})", x, y);
+        }
+    }
+    assert_eq!(printout.to_rgba().len(), 160 * 16 * 4);
+}
+
+#[test]
+fn status_reports_busy_while_printing() {
+    let (printer, _) = printer_with_sink();
+    send(&printer, &packet(INIT, false, &[]));
+    send(&printer, &packet(DATA, false, &strip()));
+    send(&printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+    let polls: Vec<u8> = (0..5).map(|_| send(&printer, &packet(STATUS, false, &[])).1).collect();
+    assert_eq!(polls, [0x02, 0x02, 0x02, 0x00, 0x00]);
+}
+
+#[test]
+fn compressed_data_prints_the_same() {
+    let (printer, printouts) = printer_with_sink();
+    let compressed = compressed_strip();
+    assert!(compressed.len() < strip().len());
+    send(&printer, &packet(INIT, false, &[]));
+    send(&printer, &packet(DATA, true, &compressed));
+    send(&printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+
+    send(&printer, &packet(INIT, false, &[]));
+    send(&printer, &packet(DATA, false, &strip()));
+    send(&printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+
+    let printouts = printouts.borrow();
+    assert_eq!(printouts.len(), 2);
+    assert_eq!(printouts[0].pixels, printouts[1].pixels);
+}
+
+#[test]
+fn the_print_palette_maps_the_shades() {
+    let (printer, printouts) = printer_with_sink();
+    for &palette in &[0x1B, 0x00] {
+        send(&printer, &packet(INIT, false, &[]));
+        send(&printer, &packet(DATA, false, &strip()));
+        send(&printer, &packet(PRINT, false, &[0x01, 0x00, palette, 0x40]));
+    }
+
+    // A palette of 0 prints like E4
+    let printouts = printouts.borrow();
+    assert_eq!(printouts[0].pixels[..32], (0..32).map(|x| expected_shade(x, 0, 0x1B)).collect::<Vec<u8>>()[..]);
+    assert_eq!(printouts[1].pixels[..32], (0..32).map(|x| expected_shade(x, 0, 0xE4)).collect::<Vec<u8>>()[..]);
+}
+
+#[test]
+fn a_bad_checksum_is_reported_and_ignored() {
+    let (printer, printouts) = printer_with_sink();
+    send(&printer, &packet(INIT, false, &[]));
+    let mut corrupt = packet(DATA, false, &strip());
+    corrupt[10] ^= 0x01;
+    assert_eq!(send(&printer, &corrupt), (0x81, 0x01));
+
+    // The next good packet clears the error, and the corrupt data never made it in
+    assert_eq!(send(&printer, &packet(STATUS, false, &[])), (0x81, 0x00));
+    send(&printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+    assert_eq!(printouts.borrow()[0].height, 0);
+}
+
+#[test]
+fn an_unknown_command_is_a_packet_error() {
+    let printer = GbPrinter::new();
+    assert_eq!(send(&printer, &packet(0x07, false, &[])), (0x81, 0x10));
+    assert_eq!(printer.status(), 0x10);
+}
+
+#[test]
+fn the_buffer_fills_after_nine_strips() {
+    let (printer, printouts) = printer_with_sink();
+    send(&printer, &packet(INIT, false, &[]));
+    for strip_number in 1..=9 {
+        let (_, status) = send(&printer, &packet(DATA, false, &strip()));
+        assert_eq!(status & 0x04 != 0, strip_number == 9, "strip {}", strip_number);
+    }
+
+    // A tenth doesn't fit
+    send(&printer, &packet(DATA, false, &strip()));
+    send(&printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+    assert_eq!(printouts.borrow()[0].height, 144);
+}
+
+#[test]
+fn bytes_before_the_magic_are_ignored() {
+    let printer = GbPrinter::new();
+    let mut bytes = vec![0x00, 0x88, 0x12, 0x33];
+    bytes.extend_from_slice(&packet(INIT, false, &[]));
+    assert_eq!(send(&printer, &bytes), (0x81, 0x00));
+}
+
+#[test]
+fn prints_over_the_serial_port() {
+    let (printer, printouts) = printer_with_sink();
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    serial.connect(Box::new(printer.clone()));
+    let mut irq = InterruptLine::new();
+
+    let mut answers = Vec::new();
+    for packet in &[packet(INIT, false, &[]), packet(DATA, false, &strip()), packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40])] {
+        for &byte in packet {
+            serial.write(0xFF01, byte);
+            serial.write(0xFF02, 0x81);
+            serial.tick(8 * 512, &mut irq);
+            answers.push(serial.read(0xFF01));
+        }
+    }
+    assert_eq!(answers[answers.len() - 2..], [0x81, 0x02]);
+    assert_eq!(printouts.borrow().len(), 1);
+    assert_eq!(printouts.borrow()[0].height, 16);
+}
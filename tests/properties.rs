@@ -0,0 +1,101 @@
+//! Invariants of the checksums, bank switching and save RAM, checked against random inputs.
+//! The case count is kept low so `cargo test` stays fast; raise it locally to dig deeper.
+
+extern crate farore;
+extern crate proptest;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use farore::cart::{self, Repairs};
+use farore::mbc::{MemoryBankController, Ram, Ram2kb, MBC1};
+
+
+const ROM_SIZE: usize = 0x8000;
+
+fn with_checksums(mut rom: Vec<u8>) -> Vec<u8> {
+    cart::repair(&mut rom, Repairs { logo: false, checksums: true, pad: false }).unwrap();
+    rom
+}
+
+fn header_checksum(rom: Vec<u8>) -> u8 {
+    with_checksums(rom)[0x14D]
+}
+
+fn global_checksum(rom: Vec<u8>) -> [u8; 2] {
+    let rom = with_checksums(rom);
+    [rom[0x14E], rom[0x14F]]
+}
+
+// Addresses the CPU writes to reach a mapper's registers and RAM.
+fn mapper_address() -> impl Strategy<Value = u16> {
+    prop_oneof![0x0000..=0x7FFFu16, 0xA000..=0xBFFFu16]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn header_checksum_covers_0134_to_014c(
+        rom in vec(any::<u8>(), ROM_SIZE),
+        offset in 0x134..=0x14Cusize,
+        delta in 1..=255u8,
+    ) {
+        let mut changed = rom.clone();
+        changed[offset] = changed[offset].wrapping_add(delta);
+        prop_assert_ne!(header_checksum(rom), header_checksum(changed));
+    }
+
+    #[test]
+    fn header_checksum_ignores_everything_else(
+        rom in vec(any::<u8>(), ROM_SIZE),
+        offset in prop_oneof![0..0x134usize, 0x14D..ROM_SIZE],
+        value in any::<u8>(),
+    ) {
+        let mut changed = rom.clone();
+        changed[offset] = value;
+        prop_assert_eq!(header_checksum(rom), header_checksum(changed));
+    }
+
+    #[test]
+    fn global_checksum_ignores_itself(
+        rom in vec(any::<u8>(), ROM_SIZE),
+        stored in any::<[u8; 2]>(),
+    ) {
+        let mut changed = rom.clone();
+        changed[0x14E..0x150].copy_from_slice(&stored);
+        prop_assert_eq!(global_checksum(rom), global_checksum(changed));
+    }
+
+    #[test]
+    fn mbc1_stays_within_the_rom(
+        banks in prop_oneof![Just(1usize), Just(2), Just(4), Just(8), Just(32), Just(64), Just(128), 1..128usize],
+        writes in vec((mapper_address(), any::<u8>()), 0..64),
+        reads in vec(any::<u16>(), 16),
+    ) {
+        let mut mbc = MBC1::new(vec![0; banks * 0x4000], Box::new(Ram2kb::new()));
+        for (address, value) in writes {
+            mbc.write(address, value);
+            prop_assert!(mbc.current_rom_bank() < banks, "bank {} of {}", mbc.current_rom_bank(), banks);
+        }
+        for address in reads {
+            mbc.read(address);
+        }
+    }
+
+    #[test]
+    fn ram_survives_a_save(contents in vec(any::<u8>(), 0x800)) {
+        let saved = Ram2kb::load(&contents).serialize();
+        prop_assert_eq!(&saved, &contents);
+        prop_assert_eq!(Ram2kb::load(&saved).serialize(), saved);
+    }
+
+    #[test]
+    fn ram_loads_saves_of_any_length(contents in vec(any::<u8>(), 0..0x1000)) {
+        let saved = Ram2kb::load(&contents).serialize();
+        prop_assert_eq!(saved.len(), 0x800);
+        let kept = contents.len().min(0x800);
+        prop_assert_eq!(&saved[..kept], &contents[..kept]);
+        prop_assert!(saved[kept..].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,184 @@
+//! Stepping back through the rewind history.
+
+extern crate farore;
+
+use std::convert::TryInto;
+
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::ppu::{Ppu, DOTS_PER_FRAME};
+use farore::rewind::{RewindBuffer, DEFAULT_INTERVAL};
+
+
+// A snapshot of `len` bytes, mostly zero with a few bytes marking which one it is.
+fn snapshot(id: u8, len: usize) -> Vec<u8> {
+    let mut snapshot = vec![0; len];
+    for i in (0..len).step_by(97) {
+        snapshot[i] = id.wrapping_add(i as u8);
+    }
+    snapshot
+}
+
+#[test]
+fn pops_newest_first() {
+    let mut rewind = RewindBuffer::new(1, usize::MAX);
+    assert!(rewind.is_empty());
+    for id in 0..10 {
+        rewind.push(snapshot(id, 1000));
+    }
+    assert_eq!(rewind.len(), 10);
+    for id in (0..10).rev() {
+        assert_eq!(rewind.pop(), Some(snapshot(id, 1000)));
+    }
+    assert_eq!(rewind.pop(), None);
+    assert!(rewind.is_empty());
+    assert_eq!(rewind.memory(), 0);
+}
+
+#[test]
+fn deltas_are_smaller_than_snapshots() {
+    let mut rewind = RewindBuffer::new(1, usize::MAX);
+    for id in 0..10 {
+        rewind.push(snapshot(id, 0x2000));
+    }
+    assert!(rewind.memory() < 0x2000 * 2, "{} bytes", rewind.memory());
+}
+
+#[test]
+fn the_memory_limit_drops_the_oldest() {
+    let mut rewind = RewindBuffer::new(1, 3000);
+    for id in 0..200 {
+        rewind.push(snapshot(id, 1000));
+        assert!(rewind.memory() <= 3000, "{} bytes after {}", rewind.memory(), id);
+    }
+    let kept = rewind.len();
+    assert!(kept > 2 && kept < 200, "{} kept", kept);
+
+    // What's left is the newest ones, still in order
+    for id in (200 - kept..200).rev() {
+        assert_eq!(rewind.pop(), Some(snapshot(id as u8, 1000)));
+    }
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn the_newest_is_kept_even_over_the_limit() {
+    let mut rewind = RewindBuffer::new(1, 10);
+    rewind.push(snapshot(1, 1000));
+    rewind.push(snapshot(2, 1000));
+    assert_eq!(rewind.len(), 1);
+    assert_eq!(rewind.pop(), Some(snapshot(2, 1000)));
+}
+
+#[test]
+fn snapshots_can_change_length() {
+    let lengths = [100, 300, 50, 0, 200, 200, 1];
+    let mut rewind = RewindBuffer::new(1, usize::MAX);
+    for (id, &len) in lengths.iter().enumerate() {
+        rewind.push(snapshot(id as u8, len));
+    }
+    for (id, &len) in lengths.iter().enumerate().rev() {
+        assert_eq!(rewind.pop(), Some(snapshot(id as u8, len)));
+    }
+}
+
+#[test]
+fn due_every_interval() {
+    let rewind = RewindBuffer::new(DEFAULT_INTERVAL, usize::MAX);
+    let due: Vec<u64> = (0..20).filter(|&frame| rewind.is_due(frame)).collect();
+    assert_eq!(due, [0, 6, 12, 18]);
+
+    // An interval of 0 means every frame
+    let rewind = RewindBuffer::new(0, usize::MAX);
+    assert!((0..10).all(|frame| rewind.is_due(frame)));
+}
+
+#[test]
+fn clear_forgets_everything() {
+    let mut rewind = RewindBuffer::new(1, usize::MAX);
+    for id in 0..5 {
+        rewind.push(snapshot(id, 100));
+    }
+    rewind.clear();
+    assert!(rewind.is_empty());
+    assert_eq!(rewind.memory(), 0);
+    assert_eq!(rewind.pop(), None);
+
+    rewind.push(snapshot(9, 100));
+    assert_eq!(rewind.pop(), Some(snapshot(9, 100)));
+}
+
+// A stand-in for a game whose whole state is VRAM and the scroll registers: every frame it
+// scrolls and draws another tile.  Its snapshot is that state plus the frame number.
+struct Game {
+    ppu: Ppu,
+    irq: InterruptLine,
+    frame: u64,
+}
+
+impl Game {
+    fn new() -> Self {
+        let mut ppu = Ppu::new(HardwareModel::Dmg);
+        ppu.write(0xFF40, 0x91);
+        Game { ppu, irq: InterruptLine::new(), frame: 0 }
+    }
+
+    fn restore(snapshot: &[u8]) -> Self {
+        let mut game = Game::new();
+        let (vram, rest) = snapshot.split_at(0x2000);
+        for (address, &value) in (0x8000..).zip(vram) {
+            game.ppu.write_vram(address, value);
+        }
+        game.ppu.write(0xFF43, rest[0]);
+        game.ppu.write(0xFF42, rest[1]);
+        game.frame = u64::from_le_bytes(rest[2..10].try_into().unwrap());
+        game
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        let mut snapshot: Vec<u8> = (0x8000..0xA000).map(|address| self.ppu.read_vram(address)).collect();
+        snapshot.push(self.ppu.read(0xFF43));
+        snapshot.push(self.ppu.read(0xFF42));
+        snapshot.extend_from_slice(&self.frame.to_le_bytes());
+        snapshot
+    }
+
+    // Runs a frame, returning its hash.
+    fn run_frame(&mut self) -> u64 {
+        let n = self.frame;
+        self.ppu.write_vram(0x8010 + (n as u16 * 7) % 0x7F0, (n * 37) as u8 | 0x01);
+        self.ppu.write_vram(0x9800 + (n as u16 * 13) % 0x400, 1 + (n % 0x7E) as u8);
+        self.ppu.write(0xFF43, (n * 3) as u8);
+        self.ppu.write(0xFF42, (n / 2) as u8);
+        self.ppu.tick(DOTS_PER_FRAME, &mut self.irq);
+        self.frame += 1;
+        self.ppu.frame().hash()
+    }
+}
+
+#[test]
+fn restoring_a_snapshot_replays_the_same_frames() {
+    let mut game = Game::new();
+    let mut rewind = RewindBuffer::new(DEFAULT_INTERVAL, 0x2000 + 512);
+    let mut hashes = Vec::new();
+    for _ in 0..120 {
+        if rewind.is_due(game.frame) {
+            rewind.push(game.snapshot());
+        }
+        hashes.push(game.run_frame());
+    }
+    assert!(rewind.len() > 4 && rewind.len() < 20, "{} kept", rewind.len());
+
+    // Step back past three snapshots to frame 96, and play forward again
+    for _ in 0..3 {
+        rewind.pop().unwrap();
+    }
+    let snapshot = rewind.pop().unwrap();
+    let mut restored = Game::restore(&snapshot);
+    assert_eq!(restored.frame, 120 - 4 * DEFAULT_INTERVAL);
+    assert_eq!(restored.snapshot(), snapshot);
+    for frame in restored.frame..120 {
+        assert_eq!(restored.run_frame(), hashes[frame as usize], "frame {}", frame);
+    }
+}
@@ -0,0 +1,70 @@
+//! Padding, trimming and splitting ROMs.
+
+extern crate farore;
+
+use farore::cart::{self, BANK_SIZE};
+
+
+// A ROM of `banks` banks declaring `declared_banks`, with its code filling `used` bytes and
+// 0xFF after that.
+fn rom(banks: usize, declared_banks: usize, used: usize) -> Vec<u8> {
+    let mut rom = vec![0xFF; banks * BANK_SIZE];
+    for (i, byte) in rom[..used].iter_mut().enumerate() {
+        *byte = (i % 0xF0) as u8;
+    }
+    rom[0x0134..0x0144].copy_from_slice(b"SURGERY\0\0\0\0\0\0\0\0\0");
+    rom[0x0148] = (declared_banks / 2).trailing_zeros() as u8;
+    rom
+}
+
+#[test]
+fn pads_to_a_size() {
+    let mut padded = rom(2, 4, 0x5000);
+    cart::pad(&mut padded, 4 * BANK_SIZE, 0x00).unwrap();
+    assert_eq!(padded.len(), 4 * BANK_SIZE);
+    assert!(padded[2 * BANK_SIZE..].iter().all(|&byte| byte == 0x00));
+    assert_eq!(padded[..2 * BANK_SIZE], rom(2, 4, 0x5000)[..]);
+}
+
+#[test]
+fn refuses_to_pad_smaller() {
+    let mut rom = rom(4, 4, 0x5000);
+    assert!(cart::pad(&mut rom, 2 * BANK_SIZE, 0xFF).is_err());
+    assert_eq!(rom.len(), 4 * BANK_SIZE);
+}
+
+#[test]
+fn trims_up_to_a_bank_boundary() {
+    // Code ends one byte into bank 4, so five banks stay
+    let mut trimmed = rom(8, 4, 4 * BANK_SIZE + 1);
+    assert_eq!(cart::trim(&mut trimmed, false).unwrap(), 5 * BANK_SIZE);
+    assert_eq!(trimmed.len(), 5 * BANK_SIZE);
+
+    // Exactly four banks of code stay four banks
+    let mut trimmed = rom(8, 4, 4 * BANK_SIZE);
+    assert_eq!(cart::trim(&mut trimmed, false).unwrap(), 4 * BANK_SIZE);
+}
+
+#[test]
+fn never_trims_below_two_banks() {
+    let mut trimmed = rom(4, 2, 0x200);
+    assert_eq!(cart::trim(&mut trimmed, false).unwrap(), 2 * BANK_SIZE);
+}
+
+#[test]
+fn refuses_to_trim_below_the_declared_size_unless_forced() {
+    let mut trimmed = rom(8, 8, 3 * BANK_SIZE);
+    assert!(cart::trim(&mut trimmed, false).is_err());
+    assert_eq!(trimmed.len(), 8 * BANK_SIZE);
+
+    assert_eq!(cart::trim(&mut trimmed, true).unwrap(), 3 * BANK_SIZE);
+}
+
+#[test]
+fn splits_into_groups_of_banks() {
+    let rom = rom(5, 8, 5 * BANK_SIZE);
+    let pieces: Vec<&[u8]> = cart::split(&rom, 2).collect();
+    assert_eq!(pieces.len(), 3);
+    assert_eq!(pieces.iter().map(|piece| piece.len()).collect::<Vec<_>>(), [2 * BANK_SIZE, 2 * BANK_SIZE, BANK_SIZE]);
+    assert_eq!(pieces.concat(), rom);
+}
@@ -0,0 +1,221 @@
+//! Save state files written, read back and checked against the ROM.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+use farore::cart::{self, Repairs};
+use farore::archive;
+use farore::crc::{adler32, crc32};
+use farore::error::FaroreError;
+use farore::model::HardwareModel;
+use farore::savestate::{self, SaveState, Section, MAJOR_VERSION};
+
+
+fn state() -> SaveState {
+    let mut state = SaveState::new(0x9EFDA772, "POKEMON RED", HardwareModel::Cgb, 0x1_0000_0064);
+    state.set_section(savestate::CPU, (0..12).collect());
+    state.set_section(savestate::PPU, (0..0x4000).map(|i| (i / 64) as u8).collect());
+    state.set_section(savestate::TIMER, Vec::new());
+    state
+}
+
+fn bytes(state: &SaveState) -> Vec<u8> {
+    let mut file = Vec::new();
+    state.write(&mut file).unwrap();
+    file
+}
+
+// Rebuilds a file's payload from raw section bytes, for sections this build wouldn't write.
+fn with_sections(file: &[u8], sections: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    stream.extend(archive::deflate(sections));
+    stream.extend_from_slice(&adler32(sections).to_be_bytes());
+    let mut out = file[..0x2C].to_vec();
+    out[0x28..0x2C].copy_from_slice(&(stream.len() as u32).to_le_bytes());
+    out.extend(stream);
+    out
+}
+
+fn rejection(data: &[u8]) -> String {
+    match SaveState::parse(data) {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn states_round_trip() {
+    let file = bytes(&state());
+    assert_eq!(&file[..4], b"FGBS");
+    assert_eq!(&file[0x10..0x20], b"POKEMON RED\0\0\0\0\0");
+    assert!(file.len() < 0x4000 / 10, "the payload is compressed, {} bytes", file.len());
+    let loaded = SaveState::parse(&file).unwrap();
+    assert_eq!(loaded, state());
+    assert_eq!(loaded.section(savestate::CPU), Some(&(0..12).collect::<Vec<u8>>()[..]));
+    assert_eq!(loaded.section(savestate::TIMER), Some(&[][..]));
+    assert_eq!(loaded.section(savestate::APU), None);
+
+    let empty = SaveState::new(0, "", HardwareModel::Dmg, 0);
+    assert_eq!(SaveState::parse(&bytes(&empty)).unwrap(), empty);
+}
+
+#[test]
+fn sections_are_replaced_in_place() {
+    let mut state = state();
+    state.set_section(savestate::CPU, vec![1, 2, 3]);
+    let tags: Vec<[u8; 4]> = state.sections.iter().map(|section| section.tag).collect();
+    assert_eq!(tags, [savestate::CPU, savestate::PPU, savestate::TIMER]);
+    assert_eq!(state.section(savestate::CPU), Some(&[1, 2, 3][..]));
+}
+
+#[test]
+fn other_major_versions_are_refused() {
+    let mut file = bytes(&state());
+    file[4] = 2;
+    match SaveState::parse(&file) {
+        Err(FaroreError::SaveStateVersion { found: 2, supported }) => assert_eq!(supported, MAJOR_VERSION as u32),
+        other => panic!("{:?}", other),
+    }
+
+    // Even when the rest of the layout changed too
+    match SaveState::parse(b"FGBS\x07\x00") {
+        Err(FaroreError::SaveStateVersion { found: 7, .. }) => {},
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn newer_minor_versions_load_without_their_new_sections() {
+    let mut file = bytes(&state());
+    file[6] = 3;
+    assert_eq!(SaveState::parse(&file).unwrap(), state());
+
+    let mut sections = Vec::new();
+    for &(tag, data) in &[(b"CPU ", &[7, 8][..]), (b"RTC2", &[1, 2, 3, 4, 5][..]), (b"APU ", &[9][..]), (b"LINK", &[][..])] {
+        sections.extend_from_slice(tag);
+        sections.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        sections.extend_from_slice(data);
+    }
+    let loaded = SaveState::parse(&with_sections(&file, &sections)).unwrap();
+    assert_eq!(loaded.sections, [
+        Section { tag: savestate::CPU, data: vec![7, 8] },
+        Section { tag: savestate::APU, data: vec![9] },
+    ]);
+}
+
+#[test]
+fn damaged_files_are_refused() {
+    let file = bytes(&state());
+    assert_eq!(rejection(b"FGBM\x01\x00\x00\x00"), "invalid save state: not a save state");
+    assert_eq!(rejection(b"FG"), "invalid save state: not a save state");
+    assert_eq!(rejection(&file[..0x20]), "invalid save state: the header is truncated");
+    let length = file.len() - 0x2C;
+    assert_eq!(rejection(&file[..file.len() - 1]),
+               format!("invalid save state: the payload is {} bytes, the header says {}", length - 1, length));
+
+    let mut model = file.clone();
+    model[0x0C] = 2;
+    assert_eq!(rejection(&model), "invalid save state: unknown model 2");
+    let mut title = file.clone();
+    title[0x10] = 0xFF;
+    assert_eq!(rejection(&title), "invalid save state: the rom title isn't UTF-8");
+    let mut checksum = file.clone();
+    let last = checksum.len() - 1;
+    checksum[last] ^= 1;
+    assert_eq!(rejection(&checksum), "invalid save state: the payload fails its Adler-32 check");
+    let mut stream = file.clone();
+    stream[0x2C] = 0x79;
+    assert_eq!(rejection(&stream), "invalid save state: the payload isn't a zlib stream");
+    assert_eq!(rejection(&with_sections(&file, b"PPU \x10\x00\x00\x00abc")),
+               "invalid save state: the PPU section is truncated");
+    assert_eq!(rejection(&with_sections(&file, b"PPU ")), "invalid save state: a section header is truncated");
+}
+
+#[test]
+fn long_titles_are_not_written() {
+    let state = SaveState::new(0, "SEVENTEEN LETTERS", HardwareModel::Dmg, 0);
+    let error = state.write(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn another_rom_takes_force() {
+    let state = state();
+    assert!(state.check_rom(0x9EFDA772, false).is_ok());
+    match state.check_rom(0x01610A65, false) {
+        Err(FaroreError::RomMismatch { expected_crc32: 0x9EFDA772, found_crc32: 0x01610A65 }) => {},
+        other => panic!("{:?}", other),
+    }
+    assert!(state.check_rom(0x01610A65, true).is_ok());
+}
+
+#[test]
+fn slots_sit_next_to_the_rom() {
+    assert_eq!(savestate::slot_path(Path::new("roms/game.gb"), 1), Path::new("roms/game.state1"));
+    assert_eq!(savestate::slot_path(Path::new("game.gbc"), 4), Path::new("game.state4"));
+    assert_eq!(savestate::slot_path(Path::new("game"), 2), Path::new("game.state2"));
+}
+
+#[test]
+fn states_save_to_files() {
+    let path = env::temp_dir().join(format!("farore-savestate-{}.state1", process::id()));
+    state().save(&path).unwrap();
+    let loaded = SaveState::load(&path);
+    let _ = fs::remove_file(&path);
+    assert_eq!(loaded.unwrap(), state());
+
+    match SaveState::load(&env::temp_dir().join("farore-no-such-dir").join("game.state1")) {
+        Err(FaroreError::Io { .. }) => {},
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn run_refuses_a_state_of_another_rom() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x0139].copy_from_slice(b"STATE");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    let scratch = |name: &str| env::temp_dir().join(format!("farore-savestate-run-{}-{}", process::id(), name));
+    let (rom_path, state_path) = (scratch("game.gb"), scratch("game.state1"));
+    fs::write(&rom_path, &rom).unwrap();
+    state().save(&state_path).unwrap();
+
+    let run = |force: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_farore"))
+            .args(["run", rom_path.to_str().unwrap(), "--skip-boot", "--load-state", state_path.to_str().unwrap()])
+            .args(force)
+            .output()
+            .unwrap()
+    };
+    let refused = run(&[]);
+    let forced = run(&["--force"]);
+    let mut ours = state();
+    ours.rom_crc32 = crc32(&rom);
+    ours.save(&state_path).unwrap();
+    let matching = run(&[]);
+    let _ = fs::remove_file(&rom_path);
+    let _ = fs::remove_file(&state_path);
+
+    let stderr = String::from_utf8(refused.stderr).unwrap();
+    assert!(stderr.contains("the save state is for \"POKEMON RED\", --force loads it anyway"), "{}", stderr);
+    // Past the check, the others only stop for want of a CPU
+    for output in &[forced, matching] {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("there is no CPU"), "{}", stderr);
+    }
+}
+
+#[test]
+fn states_are_found_in_padded_buffers() {
+    let mut buffer = bytes(&state());
+    let size = buffer.len();
+    buffer.resize(size + 100, 0);
+    assert_eq!(savestate::stored_size(&buffer), Some(size));
+    assert_eq!(SaveState::parse(&buffer[..size]).unwrap(), state());
+    assert_eq!(savestate::stored_size(&buffer[..0x20]), None);
+    assert_eq!(savestate::stored_size(&[0; 0x100]), None);
+}
@@ -0,0 +1,242 @@
+//! Screenshots saved as PNGs during a run, decoded again and compared with the frames.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use farore::archive;
+use farore::crc::crc32;
+use farore::error::FaroreError;
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::ppu::{Frame, Ppu, DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use farore::render::{self, Ghosting, Screenshots};
+
+
+// A directory of its own under the temp dir, emptied first.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("farore-screenshots-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Decodes a PNG as the encoder writes it, checking every chunk's CRC.  Returns the size and the
+// RGBA pixels.
+fn decode_png(file: &[u8]) -> (u32, u32, Vec<u8>) {
+    assert_eq!(&file[..8], b"\x89PNG\r\n\x1A\n");
+    let be32 = |data: &[u8]| u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    let (mut i, mut size, mut idat, mut ended) = (8, (0, 0), Vec::new(), false);
+    while i < file.len() {
+        let len = be32(&file[i..]) as usize;
+        let (kind, data) = (&file[i + 4..i + 8], &file[i + 8..i + 8 + len]);
+        assert_eq!(be32(&file[i + 8 + len..]), crc32(&file[i + 4..i + 8 + len]), "{} CRC", String::from_utf8_lossy(kind));
+        match kind {
+            b"IHDR" => {
+                size = (be32(data), be32(&data[4..]));
+                assert_eq!(&data[8..], [8, 6, 0, 0, 0]);
+            },
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => ended = true,
+            other => panic!("unexpected chunk {:?}", other),
+        }
+        i += 12 + len;
+    }
+    assert!(ended);
+
+    let (raw, _) = archive::inflate(&idat[2..], usize::MAX).unwrap();
+    let stride = size.0 as usize * 4;
+    assert_eq!(raw.len(), (stride + 1) * size.1 as usize);
+    let mut rgba = Vec::new();
+    for row in raw.chunks(stride + 1) {
+        assert_eq!(row[0], 0, "filter type");
+        rgba.extend_from_slice(&row[1..]);
+    }
+    (size.0, size.1, rgba)
+}
+
+fn decode_png_file(path: &Path) -> (u32, u32, Vec<u8>) {
+    decode_png(&fs::read(path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e)))
+}
+
+// Runs a DMG PPU with a scrolling background for `frames` frames, taking screenshots along the
+// way.  Returns each frame's RGBA and hash.
+fn run(screenshots: &Screenshots, frames: u64) -> Vec<(Vec<u8>, u64)> {
+    let mut ppu = Ppu::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    for row in 0..8 {
+        ppu.write_vram(0x8010 + row * 2, 0x3C);
+        ppu.write_vram(0x8010 + row * 2 + 1, 0xF0 >> (row % 4));
+    }
+    for i in 0..0x400 {
+        ppu.write_vram(0x9800 + i, (i % 3 == 0) as u8);
+    }
+    ppu.write(0xFF47, 0xE4);
+    ppu.write(0xFF40, 0x91);
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let (sink, screenshots) = (seen.clone(), screenshots.clone());
+    ppu.set_frame_callback(Box::new(move |frame: &Frame| {
+        screenshots.capture(frame);
+        sink.borrow_mut().push((frame.to_rgba(), frame.hash()));
+    }));
+    for frame in 0..frames {
+        ppu.write(0xFF43, frame as u8 * 3);
+        ppu.tick(DOTS_PER_FRAME, &mut irq);
+    }
+    let seen = seen.borrow().clone();
+    seen
+}
+
+#[test]
+fn frames_are_written_into_a_kept_buffer() {
+    for &model in &[HardwareModel::Dmg, HardwareModel::Cgb] {
+        let mut ppu = Ppu::new(model);
+        let mut irq = InterruptLine::new();
+        for row in 0..16 {
+            ppu.write_vram(0x8010 + row, 0xA5 ^ row as u8);
+        }
+        for i in 0..0x400 {
+            ppu.write_vram(0x9800 + i, (i % 2) as u8);
+        }
+        ppu.write(0xFF68, 0x80); // BCPS, auto-increment
+        for &byte in &[0xFF, 0x7F, 0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C] {
+            ppu.write(0xFF69, byte);
+        }
+        ppu.write(0xFF47, 0xE4);
+        ppu.write(0xFF40, 0x91);
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = seen.clone();
+        ppu.set_frame_callback(Box::new(move |frame: &Frame| {
+            let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+            frame.write_rgba(&mut buffer).unwrap();
+            let short = frame.write_rgba(&mut [0; 4]);
+            sink.borrow_mut().push((buffer, frame.to_rgba(), short));
+        }));
+        ppu.tick(DOTS_PER_FRAME * 2, &mut irq);
+
+        let seen = seen.borrow();
+        let (buffer, rgba, short) = seen.last().unwrap();
+        assert_eq!(buffer, rgba, "{:?}", model);
+        assert!(rgba.chunks(4).any(|pixel| pixel != [0xFF; 4]), "{:?} drew nothing", model);
+        match short {
+            Err(FaroreError::InvalidArgument(reason)) => assert_eq!(reason, "the buffer is 4 bytes, a frame is 92160"),
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn png_encoding_round_trips() {
+    let rgba: Vec<u8> = (0..7 * 5 * 4).map(|i| (i * 37) as u8).collect();
+    let mut png = Vec::new();
+    render::encode_png(&mut png, 7, 5, &rgba).unwrap();
+    assert_eq!(decode_png(&png), (7, 5, rgba));
+
+    // Big enough to need several stored blocks
+    let rgba: Vec<u8> = (0..300 * 300 * 4).map(|i| (i % 251) as u8).collect();
+    let mut png = Vec::new();
+    render::encode_png(&mut png, 300, 300, &rgba).unwrap();
+    assert_eq!(decode_png(&png), (300, 300, rgba));
+
+    let mut png = Vec::new();
+    let error = render::encode_png(&mut png, 2, 2, &[0; 15]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn captures_the_asked_for_frame() {
+    let dir = scratch_dir("at-frame");
+    let path = dir.join("shot.png");
+    let screenshots = Screenshots::new();
+    screenshots.at_frame(30, &path);
+    let frames = run(&screenshots, 40);
+    assert!(screenshots.take_error().is_none());
+
+    // Frame numbers start at 1
+    let (width, height, rgba) = decode_png_file(&path);
+    assert_eq!((width as usize, height as usize), (SCREEN_WIDTH, SCREEN_HEIGHT));
+    assert!(rgba == frames[29].0, "the screenshot isn't frame 30");
+    assert!(frames.iter().all(|frame| (frame.1 == frames[29].1) == (frame.0 == rgba)), "pixels and hashes disagree");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn captures_every_nth_frame_into_a_directory() {
+    let dir = scratch_dir("every").join("shots");
+    let screenshots = Screenshots::new();
+    screenshots.every(4, &dir).unwrap();
+    let frames = run(&screenshots, 13);
+
+    let mut names: Vec<String> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, ["000004.png", "000008.png", "000012.png"]);
+    for &number in &[4, 8, 12] {
+        let (_, _, rgba) = decode_png_file(&dir.join(format!("{:06}.png", number)));
+        assert!(rgba == frames[number - 1].0, "frame {}", number);
+    }
+    let _ = fs::remove_dir_all(dir.parent().unwrap());
+}
+
+#[test]
+fn a_bad_directory_is_reported_up_front() {
+    let dir = scratch_dir("bad-dir");
+    let file = dir.join("not-a-dir");
+    fs::write(&file, b"").unwrap();
+    let screenshots = Screenshots::new();
+    match screenshots.every(10, &file.join("shots")) {
+        Err(FaroreError::Io { .. }) => {},
+        other => panic!("{:?}", other),
+    }
+    match screenshots.every(0, &dir) {
+        Err(FaroreError::InvalidArgument(_)) => {},
+        other => panic!("{:?}", other),
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_failed_write_stops_the_screenshots() {
+    let dir = scratch_dir("failed");
+    let screenshots = Screenshots::new();
+    screenshots.at_frame(2, &dir.join("missing").join("shot.png"));
+    screenshots.every(1, &dir).unwrap();
+    run(&screenshots, 5);
+
+    match screenshots.take_error() {
+        Some(FaroreError::Io { .. }) => {},
+        other => panic!("{:?}", other),
+    }
+    assert!(screenshots.take_error().is_none());
+    assert!(dir.join("000001.png").exists());
+    assert!(!dir.join("000003.png").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ghosting_blends_with_the_previous_output() {
+    let mut ghosting = Ghosting::new(0.5);
+    let mut first = vec![200, 100, 0, 255];
+    ghosting.apply(&mut first);
+    assert_eq!(first, [200, 100, 0, 255]);
+    let mut second = vec![0, 100, 200, 255];
+    ghosting.apply(&mut second);
+    assert_eq!(second, [100, 100, 100, 255]);
+
+    // Off leaves frames alone, and the factor is clamped
+    let mut off = Ghosting::new(-1.0);
+    assert_eq!(off.factor(), 0.0);
+    let mut frame = vec![9, 9, 9, 255];
+    off.apply(&mut frame);
+    off.apply(&mut frame);
+    assert_eq!(frame, [9, 9, 9, 255]);
+    off.set_factor(2.0);
+    assert_eq!(off.factor(), 1.0);
+}
@@ -0,0 +1,114 @@
+//! Automation scripts parsed into hooks, and their errors pointing at the line.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use farore::cart::{self, Repairs};
+use farore::debugger::{Comparison, Location};
+use farore::error::FaroreError;
+use farore::joypad::{Button, InputState};
+use farore::script::{Hook, Script, ScriptCommand, Trigger};
+
+
+const SCRIPT: &str = "\
+# Check the title screen comes up, then start the game
+on frame 60
+    poke $C000 0x42
+    assert $C000 == 0x42   # The poke lands
+    press start a
+
+on every 30
+    peek wCounter
+
+on break 0x0150
+    screenshot entry.png
+    stop
+";
+
+fn parse(source: &str) -> Script {
+    Script::parse(source, "test.farore").unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn rejection(source: &str) -> String {
+    match Script::parse(source, "test.farore") {
+        Err(FaroreError::InvalidArgument(reason)) => reason,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn hooks_collect_the_commands_under_them() {
+    let mut start = InputState::new();
+    start.set(Button::Start, true);
+    start.set(Button::A, true);
+    assert_eq!(parse(SCRIPT).hooks, [
+        Hook {
+            trigger: Trigger::Frame(60),
+            commands: vec![
+                (3, ScriptCommand::Poke { location: Location::Address(0xC000), value: 0x42 }),
+                (4, ScriptCommand::Assert { location: Location::Address(0xC000), comparison: Comparison::Equal, value: 0x42 }),
+                (5, ScriptCommand::Press(start)),
+            ],
+        },
+        Hook { trigger: Trigger::Every(30), commands: vec![(8, ScriptCommand::Peek(Location::Label("wCounter".to_string())))] },
+        Hook {
+            trigger: Trigger::Break(Location::Address(0x0150)),
+            commands: vec![(11, ScriptCommand::Screenshot("entry.png".to_string())), (12, ScriptCommand::Stop)],
+        },
+    ]);
+    assert_eq!(parse("on frame 1\n    press\n").hooks[0].commands, [(2, ScriptCommand::Press(InputState::new()))]);
+}
+
+#[test]
+fn hooks_are_found_by_frame_and_breakpoint() {
+    let script = parse(SCRIPT);
+    let triggers = |frame| script.frame_hooks(frame).map(|hook| hook.trigger.clone()).collect::<Vec<Trigger>>();
+    assert_eq!(triggers(30), [Trigger::Every(30)]);
+    assert_eq!(triggers(59), []);
+    assert_eq!(triggers(60), [Trigger::Frame(60), Trigger::Every(30)]);
+    assert_eq!(triggers(120), [Trigger::Every(30)]);
+    assert_eq!(script.breakpoints(), [&Location::Address(0x0150)]);
+}
+
+#[test]
+fn errors_name_the_file_and_line() {
+    for &(source, expected) in &[
+        ("on frame 1\n    jump 3\n", "test.farore:2: unknown command jump"),
+        ("\n\n    stop\n", "test.farore:3: commands go indented under an on line"),
+        ("on frame\n", "test.farore:1: expected on frame N, on every N or on break LOC"),
+        ("on reset 1\n", "test.farore:1: reset isn't frame, every or break"),
+        ("on every 0\n", "test.farore:1: 0 isn't from 1 to 4294967295"),
+        ("on frame 1\n    poke $C000 0x100\n", "test.farore:2: 0x100 isn't from 0 to 255"),
+        ("on frame 1\n    assert $C000 = 1\n", "test.farore:2: = isn't one of == != < <= > >="),
+        ("on frame 1\n    press start x\n", "test.farore:2: x isn't a button"),
+        ("on frame 1\n    stop now\n", "test.farore:2: stop takes 0 arguments, not 1"),
+        ("on frame 1\n    peek\n", "test.farore:2: peek takes 1 argument, not 0"),
+    ] {
+        assert_eq!(rejection(source), expected);
+    }
+}
+
+#[test]
+fn run_reports_script_errors() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0134..0x013A].copy_from_slice(b"SCRIPT");
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    let scratch = |name: &str| env::temp_dir().join(format!("farore-script-{}-{}", process::id(), name));
+    let (rom_path, script_path) = (scratch("game.gb"), scratch("test.farore"));
+    fs::write(&rom_path, &rom).unwrap();
+    fs::write(&script_path, "on frame 1\n    poke wram 1 2\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_farore"))
+        .args(["run", rom_path.to_str().unwrap(), "--skip-boot", "--script", script_path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&rom_path);
+    let _ = fs::remove_file(&script_path);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let expected = format!("{}:2: poke takes 2 arguments, not 3", script_path.display());
+    assert!(stderr.contains(&expected), "{}", stderr);
+    assert!(!output.status.success());
+}
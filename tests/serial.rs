@@ -0,0 +1,197 @@
+//! Serial transfers bit by bit with either clock, and the completion interrupt.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::interrupt::{Interrupt, InterruptLine};
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::serial::{Serial, SerialEndpoint};
+
+
+const SB: u16 = 0xFF01;
+const SC: u16 = 0xFF02;
+
+// The other side of an internally clocked transfer: sends `incoming` and records what it gets.
+struct Partner {
+    incoming: u8,
+    received: Rc<RefCell<Vec<bool>>>,
+}
+
+impl SerialEndpoint for Partner {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        self.received.borrow_mut().push(outgoing);
+        let bit = self.incoming & 0x80 != 0;
+        self.incoming <<= 1;
+        bit
+    }
+}
+
+// Drives the clock itself, pulsing on the polls listed in `schedule`.
+struct ExternalClock {
+    polls: u32,
+    schedule: Vec<u32>,
+    incoming: u8,
+    received: Rc<RefCell<Vec<bool>>>,
+}
+
+impl SerialEndpoint for ExternalClock {
+    fn poll_clock_and_bit(&mut self, outgoing: bool) -> Option<bool> {
+        self.polls += 1;
+        if !self.schedule.contains(&self.polls) {
+            return None;
+        }
+        self.received.borrow_mut().push(outgoing);
+        let bit = self.incoming & 0x80 != 0;
+        self.incoming <<= 1;
+        Some(bit)
+    }
+}
+
+fn start(serial: &mut Serial, sb: u8, sc: u8) {
+    serial.write(SB, sb);
+    serial.write(SC, sc);
+}
+
+// A byte's bits in the order they're shifted out, highest first.
+fn bits(byte: u8) -> Vec<bool> {
+    (0..8).rev().map(|bit| byte & (1 << bit) != 0).collect()
+}
+
+#[test]
+fn internal_clock_shifts_a_bit_every_512_cycles() {
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x00, 0x81);
+
+    // Nothing connected shifts in 1s
+    for bit in 1..=8u32 {
+        serial.tick(511, &mut irq);
+        assert_eq!(serial.read(SB) as u32, (1 << (bit - 1)) - 1, "before bit {}", bit);
+        serial.tick(1, &mut irq);
+        assert_eq!(serial.read(SB) as u32, (1 << bit) - 1, "after bit {}", bit);
+    }
+    assert!(!serial.is_transferring());
+}
+
+#[test]
+fn the_interrupt_comes_after_the_eighth_bit() {
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x5A, 0x81);
+    serial.tick(8 * 512 - 1, &mut irq);
+    assert!(!irq.is_requested(Interrupt::Serial));
+    assert_eq!(serial.read(SC), 0xFF);
+
+    serial.tick(1, &mut irq);
+    assert!(irq.is_requested(Interrupt::Serial));
+    assert_eq!(serial.read(SC), 0x7F);
+
+    // And the port stays idle afterwards
+    irq.clear(Interrupt::Serial);
+    serial.tick(8 * 512, &mut irq);
+    assert!(!irq.is_requested(Interrupt::Serial));
+    assert_eq!(serial.read(SB), 0xFF);
+}
+
+#[test]
+fn the_fast_clock_is_cgb_only() {
+    let mut serial = Serial::new(HardwareModel::Cgb);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x00, 0x83);
+    assert_eq!(serial.read(SC), 0xFF);
+    serial.tick(8 * 16 - 1, &mut irq);
+    assert!(serial.is_transferring());
+    serial.tick(1, &mut irq);
+    assert!(irq.is_requested(Interrupt::Serial));
+
+    // DMG doesn't have the bit, so it transfers at the normal speed
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x00, 0x83);
+    assert_eq!(serial.read(SC), 0xFF);
+    serial.tick(8 * 16, &mut irq);
+    assert!(serial.is_transferring());
+    serial.tick(8 * 512 - 8 * 16, &mut irq);
+    assert!(irq.is_requested(Interrupt::Serial));
+}
+
+#[test]
+fn sc_unused_bits_read_as_one() {
+    let serial = Serial::new(HardwareModel::Dmg);
+    assert_eq!(serial.read(SC), 0x7E);
+    let mut serial = Serial::new(HardwareModel::Cgb);
+    assert_eq!(serial.read(SC), 0x7C);
+    serial.write(SC, 0x02);
+    assert_eq!(serial.read(SC), 0x7E);
+}
+
+#[test]
+fn bytes_are_swapped_with_the_partner() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    serial.connect(Box::new(Partner { incoming: 0xC3, received: received.clone() }));
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0xA5, 0x81);
+    serial.tick(8 * 512, &mut irq);
+
+    assert_eq!(serial.read(SB), 0xC3);
+    assert_eq!(*received.borrow(), bits(0xA5));
+}
+
+#[test]
+fn external_clock_waits_for_pulses() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let schedule = vec![3, 4, 10, 11, 12, 40, 41, 100];
+    serial.connect(Box::new(ExternalClock { polls: 0, schedule, incoming: 0x81, received: received.clone() }));
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x3C, 0x80);
+
+    for poll in 1..100 {
+        serial.tick(4, &mut irq);
+        assert!(serial.is_transferring(), "poll {}", poll);
+    }
+    assert_eq!(received.borrow().len(), 7);
+    assert!(!irq.is_requested(Interrupt::Serial));
+
+    serial.tick(4, &mut irq);
+    assert!(irq.is_requested(Interrupt::Serial));
+    assert_eq!(serial.read(SB), 0x81);
+    assert_eq!(*received.borrow(), bits(0x3C));
+}
+
+#[test]
+fn external_clock_with_nothing_connected_never_finishes() {
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x42, 0x80);
+    for _ in 0..1000 {
+        serial.tick(4096, &mut irq);
+    }
+    assert!(serial.is_transferring());
+    assert_eq!(serial.read(SB), 0x42);
+    assert!(!irq.is_requested(Interrupt::Serial));
+
+    // Which the game can get out of by switching to the internal clock
+    serial.write(SC, 0x81);
+    serial.tick(8 * 512, &mut irq);
+    assert!(irq.is_requested(Interrupt::Serial));
+}
+
+#[test]
+fn restarting_a_transfer_starts_over() {
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    let mut irq = InterruptLine::new();
+    start(&mut serial, 0x00, 0x81);
+    serial.tick(3 * 512 + 100, &mut irq);
+    serial.write(SB, 0x00);
+    serial.write(SC, 0x81);
+    serial.tick(8 * 512 - 1, &mut irq);
+    assert!(serial.is_transferring());
+    serial.tick(1, &mut irq);
+    assert!(!serial.is_transferring());
+    assert_eq!(serial.read(SB), 0xFF);
+}
@@ -0,0 +1,116 @@
+//! Capturing what a game prints over the serial port.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::serial::{Serial, SerialTextCapture};
+
+
+// Stands in for stdout, keeping what was written where the test can see it.
+#[derive(Clone, Default)]
+struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A serial port with `capture` plugged in.
+fn port(capture: &SerialTextCapture) -> (Serial, InterruptLine) {
+    let mut serial = Serial::new(HardwareModel::Dmg);
+    serial.connect(Box::new(capture.clone()));
+    (serial, InterruptLine::new())
+}
+
+// Sends bytes the way a test ROM prints: write SB, start an internally clocked transfer and
+// wait for it to finish.
+fn send(serial: &mut Serial, irq: &mut InterruptLine, bytes: &[u8]) {
+    for &byte in bytes {
+        serial.write(0xFF01, byte);
+        serial.write(0xFF02, 0x81);
+        serial.tick(8 * 512, irq);
+        assert!(!serial.is_transferring());
+    }
+}
+
+#[test]
+fn captures_printed_text() {
+    let capture = SerialTextCapture::new();
+    let (mut serial, mut irq) = port(&capture);
+    send(&mut serial, &mut irq, b"HI\n");
+    assert_eq!(capture.text(), "HI\n");
+
+    send(&mut serial, &mut irq, b"Passed");
+    assert_eq!(capture.text(), "HI\nPassed");
+    capture.clear();
+    assert_eq!(capture.text(), "");
+}
+
+#[test]
+fn echoes_as_the_bytes_arrive() {
+    let capture = SerialTextCapture::new();
+    let stdout = SharedWriter::default();
+    capture.echo_to(Box::new(stdout.clone()));
+    let (mut serial, mut irq) = port(&capture);
+
+    send(&mut serial, &mut irq, b"H");
+    assert_eq!(*stdout.0.borrow(), b"H");
+    send(&mut serial, &mut irq, b"I\n");
+    assert_eq!(*stdout.0.borrow(), b"HI\n");
+}
+
+#[test]
+fn externally_clocked_bytes_are_not_captured() {
+    let capture = SerialTextCapture::new();
+    let (mut serial, mut irq) = port(&capture);
+    serial.write(0xFF01, b'X');
+    serial.write(0xFF02, 0x80);
+    serial.tick(8 * 512, &mut irq);
+    assert_eq!(capture.text(), "");
+}
+
+#[test]
+fn split_utf8_is_put_back_together() {
+    let capture = SerialTextCapture::new();
+    let pieces = Rc::new(RefCell::new(Vec::new()));
+    let sink = pieces.clone();
+    capture.set_callback(Box::new(move |text| sink.borrow_mut().push(text.to_string())));
+    let (mut serial, mut irq) = port(&capture);
+
+    send(&mut serial, &mut irq, "é€".as_bytes());
+    assert_eq!(capture.text(), "é€");
+    assert_eq!(*pieces.borrow(), ["é", "€"]);
+}
+
+#[test]
+fn invalid_utf8_is_escaped() {
+    let capture = SerialTextCapture::new();
+    let (mut serial, mut irq) = port(&capture);
+    send(&mut serial, &mut irq, b"a\xFFb\xC3(");
+    assert_eq!(capture.text(), "a\\xFFb\\xC3(");
+}
+
+#[test]
+fn the_text_stops_growing_at_the_limit() {
+    let capture = SerialTextCapture::with_limit(4);
+    let echoed = Rc::new(RefCell::new(String::new()));
+    let sink = echoed.clone();
+    capture.set_callback(Box::new(move |text| sink.borrow_mut().push_str(text)));
+    let (mut serial, mut irq) = port(&capture);
+
+    send(&mut serial, &mut irq, b"abcdefgh");
+    assert_eq!(capture.text(), "abcd");
+    assert_eq!(*echoed.borrow(), "abcdefgh");
+}
@@ -0,0 +1,176 @@
+//! Super Game Boy packets bit-banged through the joypad select lines.
+
+extern crate farore;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use farore::io::IoPeripheral;
+use farore::joypad::{Button, Joypad};
+use farore::sgb::{SgbCommand, SgbDecoder};
+
+
+const P1: u16 = 0xFF00;
+
+// The P1 writes sending `packet`: a reset pulse, each bit low bit first as a pulse on P14 (0)
+// or P15 (1) with both lines high in between, then the stop bit.
+fn writes(packet: &[u8; 16], stop_bit: bool) -> Vec<u8> {
+    let mut writes = vec![0x00, 0x30];
+    let bits = packet.iter().flat_map(|&byte| (0..8).map(move |bit| byte & (1 << bit) != 0));
+    for bit in bits.chain(Some(stop_bit)) {
+        writes.push(if bit { 0x10 } else { 0x20 });
+        writes.push(0x30);
+    }
+    writes
+}
+
+fn packet(first: u8, rest: &[u8]) -> [u8; 16] {
+    let mut packet = [0; 16];
+    packet[0] = first;
+    packet[1..1 + rest.len()].copy_from_slice(rest);
+    packet
+}
+
+// PAL01 setting the shared color 0 and palettes 0 and 1 to a gray ramp.
+fn pal01() -> [u8; 16] {
+    packet(0x01, &[0xFF, 0x7F, 0xB5, 0x56, 0x4A, 0x29, 0x00, 0x00, 0xB5, 0x56, 0x4A, 0x29, 0x00, 0x00])
+}
+
+fn decode(writes: &[u8]) -> Vec<SgbCommand> {
+    let mut decoder = SgbDecoder::new();
+    writes.iter().filter_map(|&select| decoder.write(select)).collect()
+}
+
+// A joypad decoding SGB packets, and the commands it has seen.
+fn sgb_joypad(answer_mlt_req: bool) -> (Joypad, Rc<RefCell<Vec<SgbCommand>>>) {
+    let commands = Rc::new(RefCell::new(Vec::new()));
+    let sink = commands.clone();
+    let mut joypad = Joypad::new();
+    joypad.enable_sgb(Box::new(move |command| sink.borrow_mut().push(command.clone())), answer_mlt_req);
+    (joypad, commands)
+}
+
+fn send(joypad: &mut Joypad, writes: &[u8]) {
+    for &value in writes {
+        joypad.write(P1, value);
+    }
+}
+
+#[test]
+fn decodes_a_pal01_packet() {
+    let commands = decode(&writes(&pal01(), false));
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].command, 0x00);
+    assert_eq!(commands[0].name(), "PAL01");
+    assert_eq!(commands[0].data, pal01().to_vec());
+    assert!(commands[0].to_string().starts_with("SGB 00 PAL01    01 FF 7F B5"));
+}
+
+#[test]
+fn multi_packet_commands_wait_for_every_packet() {
+    let first = packet(0x04 << 3 | 2, &[0x02, 0x07, 0x05]);
+    let second = packet(0x00, &[0x12, 0x34]);
+    let mut decoder = SgbDecoder::new();
+    let mut commands = Vec::new();
+    for &select in &writes(&first, false) {
+        commands.extend(decoder.write(select));
+    }
+    assert!(commands.is_empty());
+    for &select in &writes(&second, false) {
+        commands.extend(decoder.write(select));
+    }
+
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].name(), "ATTR_BLK");
+    assert_eq!(commands[0].data.len(), 32);
+    assert_eq!(commands[0].data[..16], first);
+    assert_eq!(commands[0].data[16..], second);
+}
+
+#[test]
+fn a_bad_stop_bit_drops_the_packet() {
+    assert!(decode(&writes(&pal01(), true)).is_empty());
+
+    // And the next packet starts clean
+    let mut sequence = writes(&pal01(), true);
+    sequence.extend(writes(&pal01(), false));
+    assert_eq!(decode(&sequence).len(), 1);
+}
+
+#[test]
+fn a_reset_pulse_restarts_the_packet() {
+    let mut sequence = writes(&packet(0xFF, &[0xFF; 15]), false);
+    sequence.truncate(40);
+    sequence.extend(writes(&pal01(), false));
+    let commands = decode(&sequence);
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].data, pal01().to_vec());
+}
+
+#[test]
+fn ordinary_joypad_polling_is_not_a_packet() {
+    let mut sequence = Vec::new();
+    for _ in 0..100 {
+        sequence.extend_from_slice(&[0x20, 0x20, 0x10, 0x10, 0x30]);
+    }
+    assert!(decode(&sequence).is_empty());
+}
+
+#[test]
+fn unknown_commands_are_named_so() {
+    let commands = decode(&writes(&packet(0x1F << 3 | 1, &[]), false));
+    assert_eq!(commands[0].command, 0x1F);
+    assert_eq!(commands[0].name(), "unknown");
+}
+
+#[test]
+fn the_joypad_reports_packets_when_enabled() {
+    let (mut joypad, commands) = sgb_joypad(false);
+    send(&mut joypad, &writes(&pal01(), false));
+    assert_eq!(commands.borrow().len(), 1);
+    assert_eq!(commands.borrow()[0].name(), "PAL01");
+
+    // Without decoding on, nothing is reported and nothing changes
+    let mut plain = Joypad::new();
+    send(&mut plain, &writes(&pal01(), false));
+    assert_eq!(plain.read(P1), 0xFF);
+}
+
+// MLT_REQ asking for two players.
+fn mlt_req() -> Vec<u8> {
+    writes(&packet(0x11 << 3 | 1, &[0x01]), false)
+}
+
+#[test]
+fn mlt_req_cycles_the_controller_id_when_answered() {
+    let (mut joypad, commands) = sgb_joypad(true);
+    joypad.set_button(Button::A, true);
+    send(&mut joypad, &mlt_req());
+    assert_eq!(commands.borrow()[0].name(), "MLT_REQ");
+
+    // Controller 1 reads F, and each rising P15 moves to the next
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        ids.push(joypad.read(P1) & 0x0F);
+        send(&mut joypad, &[0x10, 0x30]);
+    }
+    assert_eq!(ids, [0x0F, 0x0E, 0x0F, 0x0E]);
+
+    // Only controller 1 has the inputs
+    joypad.write(P1, 0x10);
+    assert_eq!(joypad.read(P1) & 0x0F, 0x0E);
+    joypad.write(P1, 0x30);
+    joypad.write(P1, 0x10);
+    assert_eq!(joypad.read(P1) & 0x0F, 0x0F);
+}
+
+#[test]
+fn mlt_req_is_only_logged_unless_answered() {
+    let (mut joypad, commands) = sgb_joypad(false);
+    send(&mut joypad, &mlt_req());
+    assert_eq!(commands.borrow().len(), 1);
+    for _ in 0..3 {
+        send(&mut joypad, &[0x10, 0x30]);
+        assert_eq!(joypad.read(P1) & 0x0F, 0x0F);
+    }
+}
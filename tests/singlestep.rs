@@ -0,0 +1,88 @@
+//! Reading SingleStepTests sm83 cases.
+
+extern crate farore;
+
+use farore::singlestep::{BusAccess, BusCycle, TestCase};
+use farore::trace::Registers;
+
+
+// Two cases cut down from the suite's 3e.json and 77.json, in the same layout.
+const SAMPLE: &str = r#"[
+  {
+    "name": "3e 0000",
+    "initial": {
+      "a": 203, "b": 7, "c": 209, "d": 12, "e": 253, "f": 176, "h": 99, "l": 241,
+      "pc": 33093, "sp": 4325, "ime": 0, "ie": 1,
+      "ram": [[33093, 62], [33094, 66]]
+    },
+    "final": {
+      "a": 66, "b": 7, "c": 209, "d": 12, "e": 253, "f": 176, "h": 99, "l": 241,
+      "pc": 33095, "sp": 4325, "ime": 0, "ie": 1,
+      "ram": [[33093, 62], [33094, 66]]
+    },
+    "cycles": [[33093, 62, "r-m"], [33094, 66, "r-m"]]
+  },
+  {
+    "name": "77 0000",
+    "initial": {
+      "a": 18, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 192, "l": 0,
+      "pc": 256, "sp": 65534,
+      "ram": [[256, 119], [49152, 0]]
+    },
+    "final": {
+      "a": 18, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 192, "l": 0,
+      "pc": 257, "sp": 65534,
+      "ram": [[256, 119], [49152, 18]]
+    },
+    "cycles": [[256, 119, "r-m"], [49152, 18, "-wm"], null]
+  }
+]"#;
+
+#[test]
+fn the_sample_cases_parse() {
+    let cases = TestCase::parse_file(SAMPLE).unwrap();
+    assert_eq!(cases.len(), 2);
+
+    let load = &cases[0];
+    assert_eq!(load.name, "3e 0000");
+    assert_eq!(load.initial.registers, Registers {
+        a: 203, f: 176, b: 7, c: 209, d: 12, e: 253, h: 99, l: 241, sp: 4325, pc: 33093,
+    });
+    assert_eq!((load.initial.ime, load.initial.ie), (false, Some(1)));
+    assert_eq!(load.initial.ram, [(0x8145, 0x3E), (0x8146, 0x42)]);
+    assert_eq!((load.expected.registers.a, load.expected.registers.pc), (0x42, 0x8147));
+    assert_eq!(load.cycles, [
+        Some(BusCycle { address: 0x8145, value: Some(0x3E), access: BusAccess::Read }),
+        Some(BusCycle { address: 0x8146, value: Some(0x42), access: BusAccess::Read }),
+    ]);
+
+    let store = &cases[1];
+    assert_eq!((store.initial.ime, store.initial.ie), (false, None));
+    assert_eq!(store.expected.ram[1], (0xC000, 0x12));
+    assert_eq!(store.cycles[1], Some(BusCycle { address: 0xC000, value: Some(0x12), access: BusAccess::Write }));
+    assert_eq!(store.cycles[2], None);
+}
+
+#[test]
+fn malformed_cases_name_the_problem() {
+    let cases = [
+        ("{}", "invalid test case: the file isn't an array of cases"),
+        ("[{}]", "invalid test case: case 0 has no name"),
+        (r#"[{"name": "00 0001", "cycles": []}]"#, "invalid test case: 00 0001 has no initial"),
+        (&SAMPLE.replacen("\"a\": 203", "\"a\": 256", 1), "invalid test case: 3e 0000 a isn't a number from 0 to 255"),
+        (&SAMPLE.replacen("[33094, 66, \"r-m\"]", "[33094, 66]", 1),
+         "invalid test case: 3e 0000 has a cycle other than [address, value, kind] or null"),
+        (&SAMPLE.replacen("[33093, 62], [33094, 66]", "[33093]", 1),
+         "invalid test case: 3e 0000 ram holds something other than [address, value] pairs"),
+    ];
+    for &(text, message) in &cases {
+        let error = TestCase::parse_file(text).unwrap_err();
+        assert_eq!(error.to_string(), message);
+    }
+}
+
+#[test]
+fn broken_json_is_refused() {
+    let error = TestCase::parse_file("[{\"name\": \"00 0000\",").unwrap_err();
+    assert!(error.to_string().starts_with("invalid JSON at character"), "{}", error);
+}
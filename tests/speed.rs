@@ -0,0 +1,68 @@
+//! KEY1 and how the two CPU speeds drive the PPU and the timer.
+
+extern crate farore;
+
+use farore::interrupt::InterruptLine;
+use farore::io::IoPeripheral;
+use farore::model::HardwareModel;
+use farore::ppu::Ppu;
+use farore::speed::{Speed, SpeedSwitch};
+use farore::timer::Timer;
+
+
+const KEY1: u16 = 0xFF4D;
+
+// Ticks a PPU and a timer through 1000 runs of instructions taking 1 to 6 M-cycles, and
+// returns LY and TIMA at the end.
+fn run_instructions(speed: Speed) -> (u8, u8) {
+    let mut irq = InterruptLine::new();
+    let mut ppu = Ppu::new(HardwareModel::Cgb);
+    ppu.write(0xFF40, 0x91);
+    let mut timer = Timer::new();
+    timer.write(0xFF07, 0x04);
+
+    for _ in 0..1000 {
+        for m_cycles in 1..7 {
+            ppu.tick(speed.dots(m_cycles), &mut irq);
+            timer.tick(speed.divider_cycles(m_cycles), &mut irq);
+        }
+    }
+    (ppu.read(0xFF44), timer.read(0xFF05))
+}
+
+#[test]
+fn double_speed_halves_the_ppu_time_per_instruction() {
+    // 21000 M-cycles.  In normal speed that's 84000 dots, a whole frame of 70224 and then 30
+    // lines of 456.  In double speed it's 42000 dots, 92 lines.  The timer counts every 1024
+    // T-cycles of the CPU clock either way, 82 times.
+    assert_eq!(run_instructions(Speed::Normal), (30, 82));
+    assert_eq!(run_instructions(Speed::Double), (92, 82));
+}
+
+#[test]
+fn stop_switches_speed_once_armed() {
+    let mut key1 = SpeedSwitch::new(HardwareModel::Cgb);
+    assert_eq!(key1.read(KEY1), 0x7E);
+    assert!(!key1.stop());
+    assert_eq!(key1.speed(), Speed::Normal);
+
+    key1.write(KEY1, 0xFF);
+    assert_eq!(key1.read(KEY1), 0x7F);
+    assert!(key1.is_armed());
+    assert!(key1.stop());
+    assert_eq!((key1.speed(), key1.read(KEY1)), (Speed::Double, 0xFE));
+
+    // And back
+    key1.write(KEY1, 0x01);
+    assert!(key1.stop());
+    assert_eq!((key1.speed(), key1.read(KEY1)), (Speed::Normal, 0x7E));
+}
+
+#[test]
+fn dmg_has_no_key1() {
+    let mut key1 = SpeedSwitch::new(HardwareModel::Dmg);
+    key1.write(KEY1, 0x01);
+    assert_eq!(key1.read(KEY1), 0xFF);
+    assert!(!key1.stop());
+    assert_eq!(key1.speed(), Speed::Normal);
+}
@@ -0,0 +1,56 @@
+//! Reading rgblink symbol files and labeling the disassembly with them.
+
+extern crate farore;
+
+use farore::disasm::{BankView, Listing};
+use farore::symbols::SymbolTable;
+
+
+const SYMBOLS: &str = "\
+; File generated by rgblink
+00:0150 Main_Loop
+00:0150 Main_Alias ; A second name for the same place
+01:4000 Helper
+02:4000 OtherHelper
+
+0038 Reset_38
+";
+
+#[test]
+fn reads_banked_and_unbanked_entries() {
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    assert_eq!(symbols.name(0, 0x0150), Some("Main_Loop"));
+    assert_eq!(symbols.name(1, 0x4000), Some("Helper"));
+    assert_eq!(symbols.name(2, 0x4000), Some("OtherHelper"));
+    assert_eq!(symbols.name(3, 0x4000), None);
+    // Unbanked labels match whatever is mapped
+    assert_eq!(symbols.name(5, 0x0038), Some("Reset_38"));
+
+    assert_eq!(symbols.address("Helper"), Some((Some(1), 0x4000)));
+    assert_eq!(symbols.address("Main_Alias"), Some((Some(0), 0x0150)));
+    assert_eq!(symbols.address("Reset_38"), Some((None, 0x0038)));
+    assert_eq!(symbols.address("Missing"), None);
+}
+
+#[test]
+fn rejects_malformed_lines() {
+    for line in ["zz", "00:150 Short", "0g:0150 Bad_Bank", "00:0150", "00:0150 Two Names", "+0:0150 Plus"].iter() {
+        let error = SymbolTable::parse(&format!("00:0100 Entry\n{}\n", line)).unwrap_err();
+        assert!(error.to_string().starts_with("line 2 "), "{}: {}", line, error);
+    }
+}
+
+#[test]
+fn labels_the_disassembly() {
+    let mut rom = vec![0xFF; 0x8000];
+    rom[0x0150..0x0157].copy_from_slice(&[0x3E, 0x12, 0xCD, 0x00, 0x40, 0x18, 0xF9]);
+    let view = BankView::new(&rom, 1);
+    let mut listing = Listing::linear(&view, 0x0150..0x0157);
+    listing.name_from(&SymbolTable::parse(SYMBOLS).unwrap(), &view);
+    assert_eq!(listing.to_string(), "\
+Main_Loop:
+    0150  3e 12     ld a, $12
+    0152  cd 00 40  call Helper
+    0155  18 f9     jr Main_Loop
+");
+}
@@ -0,0 +1,227 @@
+//! The timer's falling-edge model and the TIMA reload, cycle by cycle.
+
+extern crate farore;
+
+use farore::apu::Apu;
+use farore::interrupt::{Interrupt, InterruptLine};
+use farore::io::IoPeripheral;
+use farore::timer::Timer;
+
+
+const DIV: u16 = 0xFF04;
+const TIMA: u16 = 0xFF05;
+const TMA: u16 = 0xFF06;
+const TAC: u16 = 0xFF07;
+
+// A powered APU playing pulse 2 with one length clock left, so the next frame sequencer step
+// (step 0, a length step) switches it off.
+fn apu_one_length_clock_from_silence() -> Apu {
+    let mut apu = Apu::default();
+    apu.write(0xFF26, 0x80);
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF16, 0x3F);
+    apu.write(0xFF19, 0xC0);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+    apu
+}
+
+// A timer at 262144Hz, counting every 16 T-cycles, about to overflow into a reload from TMA.
+fn overflowing() -> (Timer, InterruptLine) {
+    let mut timer = Timer::new();
+    timer.write(TAC, 0x05);
+    timer.write(TMA, 0xAB);
+    timer.write(TIMA, 0xFF);
+    (timer, InterruptLine::new())
+}
+
+#[test]
+fn each_tac_frequency_counts_at_its_rate() {
+    for &(tac, period) in &[(0x04, 1024), (0x05, 16), (0x06, 64), (0x07, 256)] {
+        let mut timer = Timer::new();
+        let mut irq = InterruptLine::new();
+        timer.write(TAC, tac);
+
+        timer.tick(period - 4, &mut irq);
+        assert_eq!(timer.read(TIMA), 0, "TAC {:02X}", tac);
+        timer.tick(4, &mut irq);
+        assert_eq!(timer.read(TIMA), 1, "TAC {:02X}", tac);
+        timer.tick(period * 9, &mut irq);
+        assert_eq!(timer.read(TIMA), 10, "TAC {:02X}", tac);
+    }
+}
+
+#[test]
+fn disabled_timer_does_not_count() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.write(TAC, 0x01);
+    timer.tick(4096, &mut irq);
+    assert_eq!(timer.read(TIMA), 0);
+}
+
+#[test]
+fn cycles_short_of_an_m_cycle_carry_over() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.write(TAC, 0x05);
+    for _ in 0..16 {
+        timer.tick(1, &mut irq);
+    }
+    assert_eq!(timer.divider(), 16);
+    assert_eq!(timer.read(TIMA), 1);
+
+    timer.tick(3, &mut irq);
+    timer.tick(3, &mut irq);
+    assert_eq!(timer.divider(), 20);
+}
+
+#[test]
+fn div_reads_the_upper_byte() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.tick(0x2FC, &mut irq);
+    assert_eq!(timer.divider(), 0x2FC);
+    assert_eq!(timer.read(DIV), 0x02);
+
+    timer.write(DIV, 0x5A);
+    assert_eq!(timer.divider(), 0);
+    assert_eq!(timer.read(DIV), 0);
+}
+
+#[test]
+fn div_write_with_the_selected_bit_set_increments() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.write(TAC, 0x05);
+    timer.tick(8, &mut irq);
+    assert_eq!(timer.read(TIMA), 0);
+
+    timer.write(DIV, 0);
+    assert_eq!(timer.read(TIMA), 1);
+
+    // The count restarts from the reset divider
+    timer.tick(12, &mut irq);
+    assert_eq!(timer.read(TIMA), 1);
+    timer.tick(4, &mut irq);
+    assert_eq!(timer.read(TIMA), 2);
+}
+
+#[test]
+fn div_write_with_the_selected_bit_clear_does_not_increment() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.write(TAC, 0x05);
+    timer.tick(4, &mut irq);
+    timer.write(DIV, 0);
+    assert_eq!(timer.read(TIMA), 0);
+}
+
+#[test]
+fn disabling_with_the_selected_bit_set_increments() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    timer.write(TAC, 0x05);
+    timer.tick(8, &mut irq);
+    timer.write(TAC, 0x01);
+    assert_eq!(timer.read(TIMA), 1);
+}
+
+#[test]
+fn tac_unused_bits_read_as_one() {
+    let mut timer = Timer::new();
+    timer.write(TAC, 0xFF);
+    assert_eq!(timer.read(TAC), 0xFF);
+    timer.write(TAC, 0x02);
+    assert_eq!(timer.read(TAC), 0xFA);
+}
+
+#[test]
+fn overflow_reads_zero_for_an_m_cycle_before_reloading() {
+    let (mut timer, mut irq) = overflowing();
+    timer.tick(16, &mut irq);
+    assert_eq!(timer.read(TIMA), 0);
+    assert!(!irq.is_requested(Interrupt::Timer));
+
+    timer.tick(4, &mut irq);
+    assert_eq!(timer.read(TIMA), 0xAB);
+    assert!(irq.is_requested(Interrupt::Timer));
+}
+
+#[test]
+fn tima_write_during_the_delay_cancels_the_reload() {
+    let (mut timer, mut irq) = overflowing();
+    timer.tick(16, &mut irq);
+    timer.write(TIMA, 0x42);
+    timer.tick(4, &mut irq);
+    assert_eq!(timer.read(TIMA), 0x42);
+    assert!(!irq.is_requested(Interrupt::Timer));
+}
+
+#[test]
+fn tima_write_on_the_reload_cycle_is_ignored() {
+    let (mut timer, mut irq) = overflowing();
+    timer.tick(20, &mut irq);
+    timer.write(TIMA, 0x42);
+    assert_eq!(timer.read(TIMA), 0xAB);
+
+    // One M-cycle later writes go through again
+    timer.tick(4, &mut irq);
+    timer.write(TIMA, 0x42);
+    assert_eq!(timer.read(TIMA), 0x42);
+}
+
+#[test]
+fn tma_write_on_the_reload_cycle_goes_through_to_tima() {
+    let (mut timer, mut irq) = overflowing();
+    timer.tick(20, &mut irq);
+    timer.write(TMA, 0x37);
+    assert_eq!(timer.read(TIMA), 0x37);
+
+    timer.tick(4, &mut irq);
+    timer.write(TMA, 0x99);
+    assert_eq!(timer.read(TIMA), 0x37);
+}
+
+#[test]
+fn each_overflow_requests_one_interrupt() {
+    let (mut timer, mut irq) = overflowing();
+    timer.write(TMA, 0xFE);
+    timer.tick(20, &mut irq);
+    assert!(irq.is_requested(Interrupt::Timer));
+    irq.clear(Interrupt::Timer);
+
+    // 0xFE, 0xFF, overflow at the second edge, reload an M-cycle later
+    timer.tick(12 + 16, &mut irq);
+    assert!(!irq.is_requested(Interrupt::Timer));
+    timer.tick(4, &mut irq);
+    assert!(irq.is_requested(Interrupt::Timer));
+    assert_eq!(timer.read(TIMA), 0xFE);
+}
+
+#[test]
+fn div_write_with_bit_12_set_steps_the_frame_sequencer() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    let mut apu = apu_one_length_clock_from_silence();
+    timer.tick_with(0x1000, &mut irq, &mut apu);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+
+    timer.write_with(DIV, 0, &mut apu);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+}
+
+#[test]
+fn div_write_with_bit_12_clear_leaves_the_frame_sequencer() {
+    let mut timer = Timer::new();
+    let mut irq = InterruptLine::new();
+    let mut apu = apu_one_length_clock_from_silence();
+    timer.tick_with(0x0800, &mut irq, &mut apu);
+    timer.write_with(DIV, 0, &mut apu);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+
+    // The next fall of bit 12 is a full 0x2000 T-cycles away
+    timer.tick_with(0x1FFC, &mut irq, &mut apu);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x02);
+    timer.tick_with(4, &mut irq, &mut apu);
+    assert_eq!(apu.read(0xFF26) & 0x02, 0x00);
+}
@@ -0,0 +1,50 @@
+//! StepInfo and the trace line printed from it.
+
+extern crate farore;
+
+use farore::interrupt::Interrupt;
+use farore::trace::{Registers, StepInfo};
+
+
+fn at(pc: u16) -> Registers {
+    Registers { a: 0x01, f: 0xB0, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, sp: 0xFFFE, pc }
+}
+
+#[test]
+fn a_load_lists_the_register_it_changed() {
+    let after = Registers { a: 0x42, ..at(0x0152) };
+    let step = StepInfo::instruction(&[0x3E, 0x42, 0xFF], 8, false, at(0x0150), after);
+    assert_eq!(step.bytes, [0x3E, 0x42]);
+    assert_eq!(step.changes(), [("a", 0x01, 0x42)]);
+    assert_eq!(step.to_string(), "0150  3e 42     ld a, $42              8  a 01->42");
+}
+
+#[test]
+fn taken_branches_are_marked() {
+    let step = StepInfo::instruction(&[0x20, 0xFE], 12, true, at(0x0200), at(0x0200));
+    assert!(step.changes().is_empty());
+    assert_eq!(step.to_string(), "0200  20 fe     jr nz, $0200          12 taken");
+
+    let step = StepInfo::instruction(&[0x20, 0xFE], 8, false, at(0x0200), at(0x0202));
+    assert_eq!(step.to_string(), "0200  20 fe     jr nz, $0200           8");
+}
+
+#[test]
+fn flags_and_the_stack_pointer_show_their_width() {
+    let after = Registers { a: 0x00, f: 0x80, ..at(0x0301) };
+    let step = StepInfo::instruction(&[0xAF], 4, false, at(0x0300), after);
+    assert_eq!(step.to_string(), "0300  af        xor a                  4  a 01->00  f b0->80");
+
+    let after = Registers { sp: 0xFFFC, ..at(0x0302) };
+    let step = StepInfo::instruction(&[0xC5], 16, false, at(0x0301), after);
+    assert_eq!(step.to_string(), "0301  c5        push bc               16  sp fffe->fffc");
+}
+
+#[test]
+fn interrupt_dispatches_show_the_vector() {
+    let after = Registers { sp: 0xFFFC, ..at(0x0050) };
+    let step = StepInfo::interrupt(Interrupt::Timer, 20, at(0x0400), after);
+    assert_eq!(step.interrupt, Some(Interrupt::Timer));
+    assert!(step.bytes.is_empty());
+    assert_eq!(step.to_string(), "0400            int $50 Timer         20  sp fffe->fffc");
+}
@@ -0,0 +1,47 @@
+//! What the farore binary writes to stderr at each verbosity level, with stdout left alone.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use farore::cart::{self, Repairs};
+
+
+// Runs farore with `args`, returning stdout and stderr.
+fn farore(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_farore")).args(args).output().unwrap();
+    assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn each_level_adds_its_messages_to_stderr() {
+    // A CGB-only ROM with a .gb extension, which is worth a warning
+    let path = env::temp_dir().join(format!("farore-verbosity-{}.gb", process::id()));
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x0138].copy_from_slice(b"LOUD");
+    rom[0x0143] = 0xC0;
+    cart::repair(&mut rom, Repairs::ALL).unwrap();
+    fs::write(&path, rom).unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    let runs: Vec<(String, String)> = [&["-q"][..], &[], &["-v"], &["-vv"]].iter()
+        .map(|flags| farore(&[flags, &["info", &path][..]].concat()))
+        .collect();
+    let _ = fs::remove_file(&path);
+
+    let opening = format!("Opening rom {}\n", path);
+    let read = "debug: Read 32768 bytes\n";
+    let warning = format!("warning: {} has a .gb extension, but the header says it only runs on CGB\n", path);
+    let stderr: Vec<&str> = runs.iter().map(|(_, stderr)| stderr.as_str()).collect();
+    assert_eq!(stderr, [
+        String::new(),
+        warning.clone(),
+        format!("{}{}", opening, warning),
+        format!("{}{}{}", opening, read, warning),
+    ]);
+    assert!(runs[0].0.starts_with("name: LOUD\n"), "{}", runs[0].0);
+    assert!(runs.iter().all(|(stdout, _)| *stdout == runs[0].0));
+}
@@ -0,0 +1,52 @@
+//! Settling on a frame and comparing it with a reference hash.
+
+extern crate farore;
+
+use std::env;
+use std::fs;
+
+use farore::model::HardwareModel;
+use farore::ppu::Ppu;
+use farore::visual::{compare_frame, StableFrameDetector};
+
+
+#[test]
+fn stable_once_the_hash_repeats_enough() {
+    let mut detector = StableFrameDetector::new(3);
+    let stable: Vec<bool> = [1, 2, 2, 3, 3, 2, 2, 2, 2].iter().map(|&hash| detector.push(hash)).collect();
+    assert_eq!(stable, [false, false, false, false, false, false, false, true, true]);
+    assert_eq!(detector.stable_hash(), Some(2));
+
+    // A change starts the count over
+    assert!(!detector.push(4));
+    assert_eq!(detector.stable_hash(), None);
+
+    // A single frame can't repeat, so 0 and 1 need two in a row
+    let mut detector = StableFrameDetector::new(0);
+    assert!(!detector.push(7));
+    assert!(detector.push(7));
+}
+
+#[test]
+fn mismatched_frames_are_saved_as_png() {
+    let ppu = Ppu::new(HardwareModel::Dmg);
+    let frame = ppu.frame();
+    let path = env::temp_dir().join(format!("farore-visual-{}.png", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    assert!(compare_frame(&frame, frame.hash(), &path).is_ok());
+    assert!(!path.exists());
+
+    let mismatch = compare_frame(&frame, 0x0123456789ABCDEF, &path).unwrap_err();
+    assert_eq!((mismatch.expected, mismatch.found), (0x0123456789ABCDEF, frame.hash()));
+    assert_eq!(mismatch.dump.as_ref().ok(), Some(&path));
+    assert_eq!(mismatch.to_string(), format!("frame hash {:016x} doesn't match the reference 0123456789abcdef, \
+                                              the frame is saved as {}", frame.hash(), path.display()));
+    assert_eq!(&fs::read(&path).unwrap()[..8], b"\x89PNG\r\n\x1A\n");
+    fs::remove_file(&path).unwrap();
+
+    let nowhere = env::temp_dir().join("farore-no-such-dir").join("frame.png");
+    let mismatch = compare_frame(&frame, 0, &nowhere).unwrap_err();
+    assert!(mismatch.dump.is_err());
+    assert!(mismatch.to_string().contains("and the frame couldn't be saved: unable to access"), "{}", mismatch);
+}
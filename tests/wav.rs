@@ -0,0 +1,132 @@
+//! Captured audio written out as WAV files and read back.
+
+extern crate byteorder;
+extern crate farore;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use byteorder::{ByteOrder, LittleEndian};
+use farore::error::FaroreError;
+use farore::wav::{WavWriter, MAX_DATA_SIZE};
+
+
+// A file of its own under the temp dir.
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("farore-wav-{}-{}.wav", name, std::process::id()))
+}
+
+fn read(path: &PathBuf) -> Vec<u8> {
+    let file = fs::read(path).unwrap();
+    let _ = fs::remove_file(path);
+    file
+}
+
+// The header fields, checking the fixed ones along the way.  Returns the RIFF size, rate and
+// data size.
+fn header(file: &[u8]) -> (u32, u32, u32) {
+    assert_eq!(&file[0..4], b"RIFF");
+    assert_eq!(&file[8..16], b"WAVEfmt ");
+    assert_eq!(LittleEndian::read_u32(&file[16..]), 16);
+    assert_eq!(LittleEndian::read_u16(&file[20..]), 1, "PCM");
+    assert_eq!(LittleEndian::read_u16(&file[22..]), 2, "channels");
+    let rate = LittleEndian::read_u32(&file[24..]);
+    assert_eq!(LittleEndian::read_u32(&file[28..]), rate * 4, "byte rate");
+    assert_eq!(LittleEndian::read_u16(&file[32..]), 4, "block align");
+    assert_eq!(LittleEndian::read_u16(&file[34..]), 16, "bits per sample");
+    assert_eq!(&file[36..40], b"data");
+    (LittleEndian::read_u32(&file[4..]), rate, LittleEndian::read_u32(&file[40..]))
+}
+
+fn samples(file: &[u8]) -> Vec<(i16, i16)> {
+    file[44..].chunks(4).map(|frame| (LittleEndian::read_i16(frame), LittleEndian::read_i16(&frame[2..]))).collect()
+}
+
+#[test]
+fn sizes_are_patched_on_finish() {
+    let path = scratch("finish");
+    let mut wav = WavWriter::create(&path, 48000).unwrap();
+    wav.write_samples(&[(0.0, 0.0); 100]).unwrap();
+    wav.write_samples(&[(0.25, -0.25); 50]).unwrap();
+    wav.finish().unwrap();
+
+    let file = read(&path);
+    assert_eq!(file.len(), 44 + 150 * 4);
+    assert_eq!(header(&file), (36 + 150 * 4, 48000, 150 * 4));
+}
+
+#[test]
+fn samples_are_clamped_16_bit_pcm() {
+    let path = scratch("samples");
+    let mut wav = WavWriter::create(&path, 44100).unwrap();
+    wav.write_samples(&[(1.0, -1.0), (0.5, -0.5), (2.0, -3.0), (0.0, f32::NAN)]).unwrap();
+    wav.finish().unwrap();
+
+    let file = read(&path);
+    assert_eq!(header(&file).1, 44100);
+    assert_eq!(samples(&file), [(32767, -32767), (16383, -16383), (32767, -32767), (0, 0)]);
+}
+
+#[test]
+fn no_samples_is_an_empty_wav() {
+    let path = scratch("empty");
+    WavWriter::create(&path, 32768).unwrap().finish().unwrap();
+    let file = read(&path);
+    assert_eq!(file.len(), 44);
+    assert_eq!(header(&file), (36, 32768, 0));
+}
+
+#[test]
+fn dropping_the_writer_finishes_the_file() {
+    let path = scratch("drop");
+    {
+        let mut wav = WavWriter::create(&path, 48000).unwrap();
+        wav.write_samples(&[(0.1, 0.1); 10]).unwrap();
+    }
+    assert_eq!(header(&read(&path)), (36 + 40, 48000, 40));
+}
+
+#[test]
+fn the_sample_callback_streams_to_the_file() {
+    let path = scratch("callback");
+    let mut callback = WavWriter::create(&path, 48000).unwrap().into_callback();
+    for i in 0..8 {
+        let chunk: Vec<(f32, f32)> = (0..100).map(|j| (i as f32 / 8.0, -(j as f32) / 100.0)).collect();
+        callback(&chunk);
+    }
+    drop(callback);
+
+    let file = read(&path);
+    assert_eq!(header(&file), (36 + 800 * 4, 48000, 800 * 4));
+    let samples = samples(&file);
+    assert_eq!(samples[0], (0, 0));
+    assert_eq!(samples[799], ((7.0 / 8.0 * 32767.0) as i16, (-0.99 * 32767.0) as i16));
+}
+
+#[test]
+fn an_unwritable_path_is_an_error() {
+    let path = env::temp_dir().join("farore-no-such-dir").join("out.wav");
+    assert!(WavWriter::create(&path, 48000).is_err());
+}
+
+#[test]
+fn stops_at_the_riff_size_limit() {
+    let path = scratch("limit");
+    let mut wav = WavWriter::create(&path, 48000).unwrap();
+    // Pretend almost 4GiB has gone out already
+    wav.skip_samples(MAX_DATA_SIZE - 2 * 4);
+
+    assert!(wav.write_samples(&[(0.5, -0.5); 2]).is_ok());
+    match wav.write_samples(&[(0.5, -0.5)]) {
+        Err(FaroreError::Io { ref source, .. }) => assert_eq!(source.kind(), io::ErrorKind::FileTooLarge),
+        other => panic!("{:?}", other),
+    }
+    wav.finish().unwrap();
+
+    let file = read(&path);
+    assert_eq!(LittleEndian::read_u32(&file[4..]), 36 + MAX_DATA_SIZE);
+    assert_eq!(LittleEndian::read_u32(&file[40..]), MAX_DATA_SIZE);
+    assert_eq!(file.len(), 44 + 2 * 4);
+}